@@ -5,7 +5,10 @@ use tokio::io::{self, AsyncWriteExt};
 use tokio_stream::StreamExt;
 use vtysh::exec_client::ExecClient;
 use vtysh::show_client::ShowClient;
-use vtysh::{CommandPath, ExecCode, ExecReply, ExecRequest, ExecType, ShowRequest};
+use vtysh::state_client::StateClient;
+use vtysh::{
+    CommandPath, ExecCode, ExecReply, ExecRequest, ExecType, GetStateRequest, ShowRequest,
+};
 
 pub mod vtysh {
     tonic::include_proto!("vtysh");
@@ -35,6 +38,12 @@ struct Cli {
     #[arg(short, long, help = "Show output in JSON format")]
     json: bool,
 
+    #[arg(short, long, help = "Get operational state of the YANG path")]
+    get: Option<String>,
+
+    #[arg(long, help = "Print the latest commands in the history")]
+    history: Option<usize>,
+
     #[arg(
         short,
         long,
@@ -110,6 +119,30 @@ async fn show(cli: Cli, port: Option<u32>, paths: Vec<CommandPath>) -> Result<()
     Ok(())
 }
 
+async fn get_state(cli: &Cli, path: &str) -> Result<()> {
+    let mut client = StateClient::connect(format!("{}:{}", cli.base, cli.port)).await?;
+
+    let request = tonic::Request::new(GetStateRequest {
+        path: path.to_string(),
+    });
+    match client.get_state(request).await {
+        Ok(reply) => println!("{}", reply.into_inner().json),
+        Err(status) => println!("% {}", status.message()),
+    }
+    Ok(())
+}
+
+async fn history(cli: &Cli, last: usize) -> Result<()> {
+    let mut client = ExecClient::connect(format!("{}:{}", cli.base, cli.port)).await?;
+
+    let commands = vec![last.to_string()];
+    let request = tonic::Request::new(exec_request(ExecType::History as i32, &cli.mode, &commands));
+    let reply = client.do_exec(request).await?.into_inner();
+    print!("{}", reply.lines);
+
+    Ok(())
+}
+
 async fn completion(cli: Cli) -> Result<()> {
     let mut client = ExecClient::connect(format!("{}:{}", cli.base, cli.port)).await?;
 
@@ -165,7 +198,11 @@ async fn exec(cli: Cli) -> Result<()> {
 }
 
 async fn run(cli: Cli) -> Result<()> {
-    if cli.show {
+    if let Some(path) = cli.get.as_ref() {
+        get_state(&cli, path).await?;
+    } else if let Some(last) = cli.history {
+        history(&cli, last).await?;
+    } else if cli.show {
         show(cli, None, Vec::new()).await?;
     } else if cli.completion || cli.trailing || cli.first {
         completion(cli).await?;
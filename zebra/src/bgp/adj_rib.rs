@@ -0,0 +1,885 @@
+use super::handler::Bgp;
+use super::packet::{
+    As4PathAttr, Attribute, AttributeType, Attrs, CommunityValue, LocalPrefAttr, NextHopAttr,
+    OtcAttr, UpdatePacket, BGP_PACKET_LEN,
+};
+use super::peer::{peer_adj_rib_out_update, Peer, PeerType};
+use super::role::{otc_egress, BgpRole, OtcAction};
+use super::route::{Route, RouteFrom};
+use super::transform::{AsPathPrepend, AsPathRewrite, NextHopSelf, RemovePrivateAs};
+use super::vpn::{peer_vpn_out_update, VpnRibOut};
+use bytes::BytesMut;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+// Adj-RIB-Out of a peer, RFC 4271 3.2. The selected routes of the Loc-RIB
+// pass the export processing of the peer and are kept here as advertised.
+// An entry refers to the attribute set of the Loc-RIB route and holds only
+// the modifications made for the peer, so that a route advertised to many
+// peers shares one attribute set. Changes made while the MRAI timer of the
+// peer is running are pending until the timer expires.
+
+// MinRouteAdvertisementIntervalTimer, RFC 4271 10.
+pub const MRAI_EBGP: u64 = 30;
+pub const MRAI_IBGP: u64 = 5;
+
+pub const LOCAL_PREF_DEFAULT: u32 = 100;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AdjOutMods {
+    // AS numbers prepended to the AS path.
+    pub prepend: Vec<u32>,
+    pub nexthop: Option<Ipv4Addr>,
+    pub local_pref: Option<u32>,
+    // Type code of the attributes which are not sent.
+    pub strip: Vec<u8>,
+    pub otc: Option<u32>,
+    // Rewrite of the AS path applied before the prepend.
+    pub rewrite: Option<AsPathRewrite>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdjOut {
+    pub attrs: Arc<Attrs>,
+    pub mods: AdjOutMods,
+}
+
+impl AdjOut {
+    // Attributes as sent to the peer.
+    pub fn attrs(&self) -> Attrs {
+        let mods = &self.mods;
+        let mut attrs: Attrs = self
+            .attrs
+            .iter()
+            .filter(|attr| {
+                !matches!(
+                    attr,
+                    Attribute::MpReachNlri(_) | Attribute::MpUnreachNlri(_)
+                ) && !mods.strip.contains(&attr.type_code())
+            })
+            .cloned()
+            .collect();
+        let mut as_path = false;
+        let mut nexthop = false;
+        let mut local_pref = false;
+        for attr in attrs.iter_mut() {
+            match attr {
+                Attribute::As4Path(v) => {
+                    if let Some(rewrite) = &mods.rewrite {
+                        rewrite.apply(v);
+                    }
+                    v.prepend(&mods.prepend);
+                    as_path = true;
+                }
+                Attribute::NextHop(v) => {
+                    if let Some(addr) = mods.nexthop {
+                        v.next_hop = addr.octets();
+                    }
+                    nexthop = true;
+                }
+                Attribute::LocalPref(v) => {
+                    if let Some(value) = mods.local_pref {
+                        v.local_pref = value;
+                    }
+                    local_pref = true;
+                }
+                _ => {}
+            }
+        }
+        if !as_path {
+            let mut v = As4PathAttr {
+                segments: Vec::new(),
+            };
+            if let Some(rewrite) = &mods.rewrite {
+                rewrite.apply(&mut v);
+            }
+            v.prepend(&mods.prepend);
+            attrs.push(Attribute::As4Path(v));
+        }
+        if let Some(addr) = mods.nexthop.filter(|_| !nexthop) {
+            attrs.push(Attribute::NextHop(NextHopAttr {
+                next_hop: addr.octets(),
+            }));
+        }
+        if let Some(value) = mods.local_pref.filter(|_| !local_pref) {
+            attrs.push(Attribute::LocalPref(LocalPrefAttr { local_pref: value }));
+        }
+        if let Some(asn) = mods.otc {
+            attrs.push(Attribute::Otc(OtcAttr { asn }));
+        }
+        attrs
+    }
+}
+
+fn attrs_encode(attrs: &Attrs) -> BytesMut {
+    let mut buf = BytesMut::new();
+    for attr in attrs.iter() {
+        attr.encode(&mut buf, true);
+    }
+    buf
+}
+
+// Locally originated routes are rebuilt when the Loc-RIB is re-evaluated, so
+// the attribute sets are compared by value when they are not shared.
+fn attrs_same(a: &Arc<Attrs>, b: &Arc<Attrs>) -> bool {
+    Arc::ptr_eq(a, b) || attrs_encode(a) == attrs_encode(b)
+}
+
+#[derive(Debug, Default)]
+pub struct AdjRibOut {
+    pub routes: PrefixMap<Ipv4Net, AdjOut>,
+    // Updated and not yet sent.
+    pub pending: BTreeSet<Ipv4Net>,
+    // Withdrawn and not yet sent.
+    pub withdraw: BTreeSet<Ipv4Net>,
+    pub vpn: VpnRibOut,
+    // Export parameters of the last sync with the whole Loc-RIB.
+    pub export: Option<ExportPeer>,
+}
+
+impl AdjRibOut {
+    // Replace the entry of the prefix with the exported route, or remove it
+    // when nothing is exported. Returns true when the entry has been changed.
+    pub fn update(&mut self, prefix: Ipv4Net, export: Option<(Arc<Attrs>, AdjOutMods)>) -> bool {
+        match (self.routes.get_mut(&prefix), export) {
+            (Some(out), Some((attrs, mods))) => {
+                if out.mods == mods && attrs_same(&out.attrs, &attrs) {
+                    return false;
+                }
+                out.attrs = attrs;
+                out.mods = mods;
+                self.pending.insert(prefix);
+                true
+            }
+            (None, Some((attrs, mods))) => {
+                self.withdraw.remove(&prefix);
+                self.pending.insert(prefix);
+                self.routes.insert(prefix, AdjOut { attrs, mods });
+                true
+            }
+            (Some(_), None) => {
+                self.routes.remove(&prefix);
+                self.pending.remove(&prefix);
+                self.withdraw.insert(prefix);
+                true
+            }
+            (None, None) => false,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.withdraw.is_empty() || !self.pending.is_empty() || self.vpn.is_pending()
+    }
+
+    // Mark all of the routes to be sent again for route refresh.
+    pub fn refresh(&mut self) {
+        self.pending = self.routes.iter().map(|(prefix, _)| *prefix).collect();
+    }
+
+    // Take the pending withdrawals and routes with the attributes to be sent.
+    pub fn flush(&mut self) -> (Vec<Ipv4Net>, Vec<(Ipv4Net, Attrs)>) {
+        let withdraw = std::mem::take(&mut self.withdraw).into_iter().collect();
+        let updates = std::mem::take(&mut self.pending)
+            .into_iter()
+            .filter_map(|prefix| Some((prefix, self.routes.get(&prefix)?.attrs())))
+            .collect();
+        (withdraw, updates)
+    }
+}
+
+// Octets of the prefix in the NLRI or the withdrawn routes.
+fn prefix_len(prefix: &Ipv4Net) -> usize {
+    1 + (prefix.prefix_len() as usize + 7) / 8
+}
+
+// Pack the flushed changes into UPDATE messages. The withdrawn routes are
+// packed together, and the routes with the identical attributes as sent to
+// the peer share one UPDATE, each message being within `max` octets. The
+// messages of the attributes are in the order of their first route.
+pub fn update_pack(
+    withdraw: Vec<Ipv4Net>,
+    updates: Vec<(Ipv4Net, Attrs)>,
+    as4: bool,
+    max: usize,
+) -> Vec<UpdatePacket> {
+    let mut packets = Vec::new();
+
+    let empty = UpdatePacket::new().encode(as4).len();
+    let mut packet = UpdatePacket::new();
+    let mut len = empty;
+    for prefix in withdraw.into_iter() {
+        if !packet.ipv4_withdraw.is_empty() && len + prefix_len(&prefix) > max {
+            packets.push(std::mem::take(&mut packet));
+            len = empty;
+        }
+        len += prefix_len(&prefix);
+        packet.ipv4_withdraw.push(prefix);
+    }
+    if !packet.ipv4_withdraw.is_empty() {
+        packets.push(packet);
+    }
+
+    // Routes grouped by the encoded attributes.
+    let mut groups: Vec<(usize, Vec<UpdatePacket>)> = Vec::new();
+    let mut index: HashMap<BytesMut, usize> = HashMap::new();
+    for (prefix, attrs) in updates.into_iter() {
+        let mut packet = UpdatePacket::new();
+        packet.attrs = attrs;
+        let encoded = packet.encode(as4);
+        let i = match index.get(&encoded) {
+            Some(i) => *i,
+            None => {
+                index.insert(encoded.clone(), groups.len());
+                groups.push((encoded.len(), vec![packet]));
+                groups.len() - 1
+            }
+        };
+        let (empty, group) = &mut groups[i];
+        let last = group.last_mut().unwrap();
+        let len = *empty + last.ipv4_update.iter().map(prefix_len).sum::<usize>();
+        if !last.ipv4_update.is_empty() && len + prefix_len(&prefix) > max {
+            let mut next = UpdatePacket::new();
+            next.attrs = last.attrs.clone();
+            group.push(next);
+        }
+        group.last_mut().unwrap().ipv4_update.push(prefix);
+    }
+    for (_, group) in groups.into_iter() {
+        packets.extend(group);
+    }
+    packets
+}
+
+// Parameters of the peer used by the export processing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPeer {
+    pub address: Ipv4Addr,
+    pub local_as: u32,
+    pub peer_as: u32,
+    pub ibgp: bool,
+    pub local_addr: Ipv4Addr,
+    pub role: Option<BgpRole>,
+    pub next_hop_self: Option<NextHopSelf>,
+    pub remove_private_as: Option<RemovePrivateAs>,
+    pub as_override: bool,
+    pub as_path_prepend: AsPathPrepend,
+    // Advertise the best external route to the internal peer.
+    pub best_external: bool,
+}
+
+impl ExportPeer {
+    pub fn new(peer: &Peer) -> Self {
+        Self {
+            address: peer.address,
+            local_as: peer.local_as,
+            peer_as: peer.peer_as,
+            ibgp: matches!(peer.peer_type, PeerType::Internal),
+            local_addr: peer.local_addr.unwrap_or(peer.router_id),
+            role: peer.config.role,
+            next_hop_self: peer.config.next_hop_self,
+            remove_private_as: peer.config.remove_private_as,
+            as_override: peer.config.as_override,
+            as_path_prepend: peer.config.as_path_prepend.clone(),
+            best_external: false,
+        }
+    }
+}
+
+fn route_community(route: &Route, value: CommunityValue) -> bool {
+    route.attrs.iter().any(|attr| match attr {
+        Attribute::Community(v) => v.contains(&value.0),
+        _ => false,
+    })
+}
+
+fn route_aspath_contains(route: &Route, asn: u32) -> bool {
+    route.attrs.iter().any(|attr| match attr {
+        Attribute::As4Path(v) => v.contains(asn),
+        _ => false,
+    })
+}
+
+// Export processing of the selected route for the peer. Returns the
+// modifications of the attributes, or None when the route is not advertised.
+pub fn adj_out_export(peer: &ExportPeer, route: &Route) -> Option<AdjOutMods> {
+    if route.suppressed || route.from == peer.address {
+        return None;
+    }
+    // Routes learned from an internal peer are not advertised to internal
+    // peers, RFC 4271 9.2.1.
+    if peer.ibgp && route.ibgp {
+        return None;
+    }
+    // Well-known communities, RFC 1997.
+    if route_community(route, CommunityValue::NoAdvertise) {
+        return None;
+    }
+    if !peer.ibgp
+        && (route_community(route, CommunityValue::NoExport)
+            || route_community(route, CommunityValue::NoExportSubconfed))
+    {
+        return None;
+    }
+    let mut mods = AdjOutMods::default();
+    if peer.ibgp {
+        let local = route.kind != RouteFrom::Peer;
+        // Routes learned from internal peers reach an internal peer only by
+        // route reflection, where the nexthop is rewritten only by force.
+        let nexthop_self = peer
+            .next_hop_self
+            .is_some_and(|nhs| nhs.applies(route.ibgp));
+        if local
+            || nexthop_self
+            || !route
+                .attrs
+                .iter()
+                .any(|a| matches!(a, Attribute::NextHop(_)))
+        {
+            mods.nexthop = Some(peer.local_addr);
+        }
+        if !route
+            .attrs
+            .iter()
+            .any(|a| matches!(a, Attribute::LocalPref(_)))
+        {
+            mods.local_pref = Some(LOCAL_PREF_DEFAULT);
+        }
+    } else {
+        // The peer would discard the route by AS loop detection, unless its
+        // AS is overridden.
+        if !peer.as_override && route_aspath_contains(route, peer.peer_as) {
+            return None;
+        }
+        if let Some(role) = peer.role {
+            match otc_egress(role, peer.local_as, &route.attrs) {
+                OtcAction::Reject => return None,
+                OtcAction::Set(asn) => mods.otc = Some(asn),
+                OtcAction::Keep => {}
+            }
+        }
+        if peer.remove_private_as.is_some() || peer.as_override || !peer.as_path_prepend.is_empty()
+        {
+            mods.rewrite = Some(AsPathRewrite {
+                local_as: peer.local_as,
+                peer_as: peer.peer_as,
+                remove_private_as: peer.remove_private_as,
+                as_override: peer.as_override,
+                prepend: peer.as_path_prepend.clone(),
+            });
+        }
+        mods.prepend = vec![peer.local_as];
+        mods.nexthop = Some(peer.local_addr);
+        mods.strip.push(AttributeType::LocalPref.0);
+        // MED received from a neighboring AS is not propagated to other
+        // ASes, RFC 4271 5.1.4.
+        if route.kind == RouteFrom::Peer && !route.ibgp {
+            mods.strip.push(AttributeType::Med.0);
+        }
+    }
+    Some(mods)
+}
+
+// Route of the prefix to be exported to the peer. The best external route is
+// advertised to an internal peer in place of the selected route learned from
+// an internal peer, which is not advertised to it.
+fn route_export<'a>(peer: &ExportPeer, routes: &'a [Route]) -> Option<&'a Route> {
+    let selected = routes.iter().find(|route| route.selected)?;
+    if peer.ibgp && selected.ibgp && peer.best_external {
+        return routes.iter().find(|route| route.best_external);
+    }
+    Some(selected)
+}
+
+fn adj_rib_out_prefix(peer: &ExportPeer, routes: &[Route]) -> Option<(Arc<Attrs>, AdjOutMods)> {
+    route_export(peer, routes)
+        .and_then(|route| adj_out_export(peer, route).map(|mods| (route.attrs.clone(), mods)))
+}
+
+// Bring the Adj-RIB-Out in sync with the selected routes of the Loc-RIB.
+// Returns the number of changed entries.
+pub fn adj_rib_out_sync(
+    adj_out: &mut AdjRibOut,
+    peer: &ExportPeer,
+    ptree: &PrefixMap<Ipv4Net, Vec<Route>>,
+) -> usize {
+    adj_out.export = Some(peer.clone());
+    let mut changed = 0;
+    for (prefix, routes) in ptree.iter() {
+        if adj_out.update(*prefix, adj_rib_out_prefix(peer, routes)) {
+            changed += 1;
+        }
+    }
+    let removed: Vec<Ipv4Net> = adj_out
+        .routes
+        .iter()
+        .filter(|(prefix, _)| !ptree.contains_key(prefix))
+        .map(|(prefix, _)| *prefix)
+        .collect();
+    for prefix in removed.into_iter() {
+        adj_out.update(prefix, None);
+        changed += 1;
+    }
+    changed
+}
+
+// Export the changed prefixes of the Loc-RIB. The whole Loc-RIB is exported
+// only when the export parameters differ from the last sync, that is when
+// the session comes up or the outbound config of the peer has been changed.
+// Returns the number of changed entries.
+pub fn adj_rib_out_update(
+    adj_out: &mut AdjRibOut,
+    peer: &ExportPeer,
+    ptree: &PrefixMap<Ipv4Net, Vec<Route>>,
+    prefixes: &BTreeSet<Ipv4Net>,
+) -> usize {
+    if adj_out.export.as_ref() != Some(peer) {
+        return adj_rib_out_sync(adj_out, peer, ptree);
+    }
+    let mut changed = 0;
+    for prefix in prefixes.iter() {
+        let export = ptree
+            .get(prefix)
+            .and_then(|routes| adj_rib_out_prefix(peer, routes));
+        if adj_out.update(*prefix, export) {
+            changed += 1;
+        }
+    }
+    changed
+}
+
+impl Bgp {
+    // Advertise the changes of the Loc-RIB to the established peers.
+    pub fn adj_rib_update(&mut self) {
+        let changed = std::mem::take(&mut self.nexthops.changed);
+        let vpn_changed = std::mem::take(&mut self.vpn_changed);
+        for peer in self.peers.values_mut() {
+            peer_vpn_out_update(peer, &self.vpn, &vpn_changed);
+            peer_adj_rib_out_update(peer, &self.ptree, &changed, self.advertise_best_external);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{
+        peek_bgp_length, As4Segment, CommunityAttr, MedAttr, OriginAttr, AS_SEQUENCE,
+        BGP_EXTENDED_PACKET_LEN, ORIGIN_IGP,
+    };
+    use crate::bgp::route::route_aspath;
+
+    fn peer(address: &str, peer_as: u32) -> ExportPeer {
+        ExportPeer {
+            address: address.parse().unwrap(),
+            local_as: 65000,
+            peer_as,
+            ibgp: peer_as == 65000,
+            local_addr: "192.0.2.1".parse().unwrap(),
+            role: None,
+            next_hop_self: None,
+            remove_private_as: None,
+            as_override: false,
+            as_path_prepend: AsPathPrepend::default(),
+            best_external: false,
+        }
+    }
+
+    fn route(from: &str, ibgp: bool, asn: &[u32], extra: Vec<Attribute>) -> Route {
+        let mut attrs = vec![
+            Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: asn.to_vec(),
+                }],
+            }),
+            Attribute::NextHop(NextHopAttr {
+                next_hop: [10, 0, 0, 1],
+            }),
+        ];
+        attrs.extend(extra);
+        Route {
+            from: from.parse().unwrap(),
+            router_id: from.parse().unwrap(),
+            kind: RouteFrom::Peer,
+            aspath: route_aspath(&attrs),
+            attrs: Arc::new(attrs),
+            nexthop: "10.0.0.1".parse().ok(),
+            ibgp,
+            selected: true,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        }
+    }
+
+    fn sent(peer: &ExportPeer, route: &Route) -> Option<Attrs> {
+        let mods = adj_out_export(peer, route)?;
+        let out = AdjOut {
+            attrs: route.attrs.clone(),
+            mods,
+        };
+        Some(out.attrs())
+    }
+
+    fn nexthop(attrs: &Attrs) -> Option<Ipv4Addr> {
+        attrs.iter().find_map(|attr| match attr {
+            Attribute::NextHop(v) => Some(Ipv4Addr::from(v.next_hop)),
+            _ => None,
+        })
+    }
+
+    fn local_pref(attrs: &Attrs) -> Option<u32> {
+        attrs.iter().find_map(|attr| match attr {
+            Attribute::LocalPref(v) => Some(v.local_pref),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn export() {
+        let ebgp = peer("10.1.0.2", 65002);
+        let ibgp = peer("10.2.0.2", 65000);
+        let local_pref = Attribute::LocalPref(LocalPrefAttr { local_pref: 200 });
+        let med = Attribute::Med(MedAttr { med: 10 });
+
+        // eBGP learned route to eBGP peer.
+        let r = route("10.1.0.1", false, &[65001], vec![med.clone()]);
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65001");
+        assert_eq!(nexthop(&attrs), "192.0.2.1".parse().ok());
+        assert!(!attrs.iter().any(|a| matches!(a, Attribute::Med(_))));
+        // Loc-RIB attributes are not changed.
+        assert_eq!(&*route_aspath(&r.attrs), "65001");
+
+        // Not back to the peer, nor to the AS in the path.
+        assert!(adj_out_export(&peer("10.1.0.1", 65001), &r).is_none());
+        assert!(adj_out_export(&peer("10.3.0.1", 65001), &r).is_none());
+
+        // eBGP learned route to iBGP peer keeps the nexthop and gets the
+        // default LOCAL_PREF.
+        let attrs = sent(&ibgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65001");
+        assert_eq!(nexthop(&attrs), "10.0.0.1".parse().ok());
+        assert_eq!(local_pref(&attrs), Some(LOCAL_PREF_DEFAULT));
+        assert!(attrs.iter().any(|a| matches!(a, Attribute::Med(_))));
+
+        // iBGP learned route is not sent to iBGP peer. LOCAL_PREF is
+        // stripped and MED is kept towards eBGP peer.
+        let r = route("10.2.0.3", true, &[65003], vec![local_pref, med]);
+        assert!(adj_out_export(&ibgp, &r).is_none());
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(local_pref(&attrs), None);
+        assert!(attrs.iter().any(|a| matches!(a, Attribute::Med(_))));
+
+        // Well-known communities.
+        let no_export = Attribute::Community(CommunityAttr(vec![CommunityValue::NoExport.0]));
+        let r = route("10.1.0.1", false, &[65001], vec![no_export]);
+        assert!(adj_out_export(&ebgp, &r).is_none());
+        assert!(adj_out_export(&ibgp, &r).is_some());
+        let no_advertise = Attribute::Community(CommunityAttr(vec![CommunityValue::NoAdvertise.0]));
+        let r = route("10.1.0.1", false, &[65001], vec![no_advertise]);
+        assert!(adj_out_export(&ibgp, &r).is_none());
+
+        // Locally originated route.
+        let mut r = route("0.0.0.0", false, &[], vec![]);
+        r.kind = RouteFrom::Static;
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000");
+        let attrs = sent(&ibgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "");
+        assert_eq!(nexthop(&attrs), "192.0.2.1".parse().ok());
+
+        // More specific route of summary-only aggregate.
+        r.suppressed = true;
+        assert!(adj_out_export(&ebgp, &r).is_none());
+    }
+
+    #[test]
+    fn otc() {
+        let mut customer = peer("10.1.0.2", 65002);
+        customer.role = Some(BgpRole::Provider);
+        let mut provider = peer("10.3.0.2", 65003);
+        provider.role = Some(BgpRole::Customer);
+        let otc = |attrs: &Attrs| {
+            attrs.iter().find_map(|a| match a {
+                Attribute::Otc(v) => Some(v.asn),
+                _ => None,
+            })
+        };
+
+        // Marked with the local AS towards the customer.
+        let r = route("10.4.0.1", false, &[65004], vec![]);
+        let attrs = sent(&customer, &r).unwrap();
+        assert_eq!(otc(&attrs), Some(65000));
+        // Not marked towards the provider nor towards the peer without role.
+        let attrs = sent(&provider, &r).unwrap();
+        assert_eq!(otc(&attrs), None);
+        let attrs = sent(&peer("10.5.0.2", 65005), &r).unwrap();
+        assert_eq!(otc(&attrs), None);
+
+        // Route marked by the peer is not leaked to the provider.
+        let marked = Attribute::Otc(OtcAttr { asn: 65004 });
+        let r = route("10.4.0.1", false, &[65004], vec![marked]);
+        assert!(adj_out_export(&provider, &r).is_none());
+        let attrs = sent(&customer, &r).unwrap();
+        assert_eq!(otc(&attrs), Some(65004));
+    }
+
+    #[test]
+    fn transforms() {
+        let mut ibgp = peer("10.2.0.2", 65000);
+        let r = route("10.1.0.1", false, &[65001], vec![]);
+        assert_eq!(nexthop(&sent(&ibgp, &r).unwrap()), "10.0.0.1".parse().ok());
+        ibgp.next_hop_self = Some(NextHopSelf::Enabled);
+        assert_eq!(nexthop(&sent(&ibgp, &r).unwrap()), "192.0.2.1".parse().ok());
+
+        // Private AS numbers of the customer behind the local AS.
+        let mut ebgp = peer("10.1.0.2", 65002);
+        let r = route("10.3.0.1", false, &[64512, 4200000001], vec![]);
+        ebgp.remove_private_as = Some(RemovePrivateAs::Enabled);
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000");
+        ebgp.remove_private_as = Some(RemovePrivateAs::ReplaceAs);
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65000 65000");
+        assert_eq!(&*route_aspath(&r.attrs), "64512 4200000001");
+
+        // Sites of the customer sharing one AS.
+        let site = peer("10.4.0.2", 65100);
+        let r = route("10.4.0.1", false, &[65100], vec![]);
+        assert!(adj_out_export(&site, &r).is_none());
+        let mut site = peer("10.5.0.2", 65100);
+        site.as_override = true;
+        let attrs = sent(&site, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65000");
+
+        // Prepended behind the local AS.
+        let mut ebgp = peer("10.1.0.2", 65002);
+        let r = route("10.3.0.1", false, &[65003], vec![]);
+        ebgp.as_path_prepend = AsPathPrepend {
+            asns: vec![65000, 65000],
+            last_as: 0,
+        };
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65000 65000 65003");
+        ebgp.as_path_prepend = AsPathPrepend {
+            asns: Vec::new(),
+            last_as: 2,
+        };
+        let attrs = sent(&ebgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65003 65003 65003");
+
+        // Route without AS path repeats the local AS.
+        let mut local = route("10.3.0.1", false, &[], vec![]);
+        local.attrs = Arc::new(
+            local
+                .attrs
+                .iter()
+                .filter(|attr| !matches!(attr, Attribute::As4Path(_)))
+                .cloned()
+                .collect(),
+        );
+        let attrs = sent(&ebgp, &local).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65000 65000 65000");
+
+        // Not prepended towards an internal peer.
+        ibgp.as_path_prepend = AsPathPrepend {
+            asns: vec![65000],
+            last_as: 1,
+        };
+        let attrs = sent(&ibgp, &r).unwrap();
+        assert_eq!(&*route_aspath(&attrs), "65003");
+    }
+
+    #[test]
+    fn best_external() {
+        let mut ibgp = peer("10.2.0.2", 65000);
+        let ebgp = peer("10.1.0.2", 65002);
+        let prefix: Ipv4Net = "10.10.0.0/16".parse().unwrap();
+        let internal = route("10.2.0.3", true, &[65003], vec![]);
+        let mut external = route("10.1.0.1", false, &[65001], vec![]);
+        external.selected = false;
+        external.best_external = true;
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        ptree.insert(prefix, vec![internal, external]);
+
+        // Selected route is learned from an internal peer.
+        let mut adj_out = AdjRibOut::default();
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ibgp, &ptree), 0);
+
+        ibgp.best_external = true;
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ibgp, &ptree), 1);
+        let (_, updates) = adj_out.flush();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(&*route_aspath(&updates[0].1), "65001");
+        assert_eq!(nexthop(&updates[0].1), "10.0.0.1".parse().ok());
+
+        // External peer is sent the selected route.
+        let mut ebgp_out = AdjRibOut::default();
+        adj_rib_out_sync(&mut ebgp_out, &ebgp, &ptree);
+        let (_, updates) = ebgp_out.flush();
+        assert_eq!(&*route_aspath(&updates[0].1), "65000 65003");
+
+        // External route is gone.
+        ptree.get_mut(&prefix).unwrap().truncate(1);
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ibgp, &ptree), 1);
+        assert!(adj_out.withdraw.contains(&prefix));
+    }
+
+    #[test]
+    fn pending() {
+        let ebgp = peer("10.1.0.2", 65002);
+        let mut adj_out = AdjRibOut::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let p1: Ipv4Net = "10.10.0.0/16".parse().unwrap();
+        let p2: Ipv4Net = "10.20.0.0/16".parse().unwrap();
+        ptree.insert(p1, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        ptree.insert(p2, vec![route("10.1.0.1", false, &[65001], vec![])]);
+
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ebgp, &ptree), 2);
+        assert!(adj_out.is_pending());
+        let (withdraw, updates) = adj_out.flush();
+        assert!(withdraw.is_empty());
+        assert_eq!(updates.len(), 2);
+        assert!(!adj_out.is_pending());
+
+        // Nothing has been changed.
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ebgp, &ptree), 0);
+
+        // Same attributes rebuilt by value are not a change.
+        let r = route("10.1.0.1", false, &[65001], vec![]);
+        ptree.get_mut(&p1).unwrap()[0] = r;
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ebgp, &ptree), 0);
+
+        // Path change and withdrawal are held until flushed.
+        ptree.get_mut(&p1).unwrap()[0] = route("10.1.0.1", false, &[65001, 65010], vec![]);
+        ptree.remove(&p2);
+        assert_eq!(adj_rib_out_sync(&mut adj_out, &ebgp, &ptree), 2);
+        assert_eq!(adj_out.pending, BTreeSet::from([p1]));
+        assert!(adj_out.routes.get(&p2).is_none());
+        assert!(adj_out.withdraw.contains(&p2));
+
+        // Re-advertised before the flush.
+        ptree.insert(p2, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        adj_rib_out_sync(&mut adj_out, &ebgp, &ptree);
+        assert!(adj_out.withdraw.is_empty());
+
+        let (withdraw, updates) = adj_out.flush();
+        assert!(withdraw.is_empty());
+        assert_eq!(updates.len(), 2);
+        assert_eq!(&*route_aspath(&updates[0].1), "65000 65001 65010");
+    }
+
+    #[test]
+    fn changed_prefixes() {
+        let mut ebgp = peer("10.1.0.2", 65002);
+        let mut adj_out = AdjRibOut::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let p1: Ipv4Net = "10.10.0.0/16".parse().unwrap();
+        let p2: Ipv4Net = "10.20.0.0/16".parse().unwrap();
+        ptree.insert(p1, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        ptree.insert(p2, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        let none = BTreeSet::new();
+
+        // Whole Loc-RIB at the first sync of the session.
+        assert_eq!(adj_rib_out_update(&mut adj_out, &ebgp, &ptree, &none), 2);
+        assert_eq!(adj_out.export.as_ref(), Some(&ebgp));
+        assert_eq!(adj_rib_out_update(&mut adj_out, &ebgp, &ptree, &none), 0);
+
+        // Only the changed prefixes are exported.
+        ptree.get_mut(&p1).unwrap()[0] = route("10.1.0.1", false, &[65001, 65010], vec![]);
+        ptree.remove(&p2);
+        assert_eq!(adj_rib_out_update(&mut adj_out, &ebgp, &ptree, &none), 0);
+        let changed = BTreeSet::from([p2]);
+        assert_eq!(adj_rib_out_update(&mut adj_out, &ebgp, &ptree, &changed), 1);
+        assert!(adj_out.withdraw.contains(&p2));
+        let aspath = |adj_out: &AdjRibOut| route_aspath(&adj_out.routes.get(&p1).unwrap().attrs());
+        assert_eq!(&*aspath(&adj_out), "65000 65001");
+
+        // Change of the outbound config exports the whole Loc-RIB.
+        ebgp.as_path_prepend.asns = vec![65000];
+        assert_eq!(adj_rib_out_update(&mut adj_out, &ebgp, &ptree, &none), 1);
+        assert_eq!(&*aspath(&adj_out), "65000 65000 65001 65010");
+    }
+
+    #[test]
+    fn packing() {
+        let ebgp = peer("10.1.0.2", 65002);
+        let mut adj_out = AdjRibOut::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        for i in 0..2000u32 {
+            let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 + (i << 8)), 24).unwrap();
+            ptree.insert(prefix, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        }
+        let other: Ipv4Net = "192.168.0.0/16".parse().unwrap();
+        ptree.insert(other, vec![route("10.1.0.1", false, &[65003], vec![])]);
+        adj_rib_out_sync(&mut adj_out, &ebgp, &ptree);
+        let (withdraw, updates) = adj_out.flush();
+
+        // Routes of the same attributes share one UPDATE.
+        let head: Vec<_> = updates.iter().take(3).cloned().collect();
+        let packets = update_pack(Vec::new(), head, true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].ipv4_update.len(), 3);
+
+        // Size limit forces the next UPDATE. 2000 prefixes of 4 octets do
+        // not fit in one message.
+        let packets = update_pack(withdraw, updates, true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 3);
+        assert!(packets[0].ipv4_update.len() > 1000);
+        assert_eq!(
+            packets[0].ipv4_update.len() + packets[1].ipv4_update.len(),
+            2000
+        );
+        assert_eq!(packets[2].ipv4_update, vec![other]);
+        for packet in packets.iter() {
+            assert!(packet.encode(true).len() <= BGP_PACKET_LEN);
+        }
+        let full = packets[0].encode(true).len();
+        assert!(full + 4 > BGP_PACKET_LEN);
+
+        // Withdrawals are packed as well.
+        let prefixes: Vec<Ipv4Net> = ptree.iter().map(|(prefix, _)| *prefix).collect();
+        let packets = update_pack(prefixes, Vec::new(), true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(
+            packets[0].ipv4_withdraw.len() + packets[1].ipv4_withdraw.len(),
+            2001
+        );
+        assert!(packets.iter().all(|p| p.attrs.is_empty()));
+        assert!(packets[0].encode(true).len() <= BGP_PACKET_LEN);
+    }
+
+    #[test]
+    fn extended_message() {
+        let ebgp = peer("10.1.0.2", 65002);
+        let mut adj_out = AdjRibOut::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        for i in 0..1013u32 {
+            let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 + (i << 8)), 24).unwrap();
+            ptree.insert(prefix, vec![route("10.1.0.1", false, &[65001], vec![])]);
+        }
+        adj_rib_out_sync(&mut adj_out, &ebgp, &ptree);
+        let (_, updates) = adj_out.flush();
+
+        // Just over 4096 octets in one UPDATE when negotiated.
+        let packets = update_pack(Vec::new(), updates.clone(), true, BGP_EXTENDED_PACKET_LEN);
+        assert_eq!(packets.len(), 1);
+        let len = packets[0].encode(true).len();
+        assert!(len > BGP_PACKET_LEN && len < BGP_PACKET_LEN + 8);
+        assert_eq!(peek_bgp_length(&packets[0].encode(true)), len);
+
+        // Split otherwise, without losing a prefix.
+        let packets = update_pack(Vec::new(), updates, true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].ipv4_update.len(), 1);
+        assert_eq!(&*route_aspath(&packets[1].attrs), "65000 65001");
+        assert!(packets[0].encode(true).len() <= BGP_PACKET_LEN);
+    }
+}
@@ -0,0 +1,161 @@
+// TCP session authentication for BGP peers.
+//
+// TCP MD5 (RFC 2385) keys are installed per remote address with the
+// TCP_MD5SIG socket option. The active side sets the key on the socket before
+// connect(), the passive side sets it on the listening socket so the kernel
+// can validate the SYN before accept() returns the connection.
+//
+// Setting TCP_MD5SIG does not need any capability but the kernel must be built
+// with CONFIG_TCP_MD5SIG. Binding the BGP port 179 for passive sessions still
+// requires CAP_NET_BIND_SERVICE.
+//
+// TCP-AO (RFC 5925) is configured through TCP_AO_ADD_KEY on Linux 6.7 or
+// later. It will be plugged in as another `TcpAuth` variant so that both the
+// connect and listen paths go through `tcp_auth_set()`.
+
+use std::io;
+use std::net::IpAddr;
+use std::os::unix::io::RawFd;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TcpAuth {
+    Md5(String),
+}
+
+pub fn tcp_auth_set(fd: RawFd, addr: IpAddr, auth: &TcpAuth) -> io::Result<()> {
+    match auth {
+        TcpAuth::Md5(password) => tcp_md5sig_set(fd, addr, password.as_bytes()),
+    }
+}
+
+pub fn tcp_auth_clear(fd: RawFd, addr: IpAddr) -> io::Result<()> {
+    tcp_md5sig_set(fd, addr, &[])
+}
+
+#[cfg(target_os = "linux")]
+const TCP_MD5SIG_MAXKEYLEN: usize = 80;
+
+// struct tcp_md5sig in <linux/tcp.h>.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct TcpMd5Sig {
+    addr: libc::sockaddr_storage,
+    flags: u8,
+    prefixlen: u8,
+    keylen: u16,
+    ifindex: libc::c_int,
+    key: [u8; TCP_MD5SIG_MAXKEYLEN],
+}
+
+#[cfg(target_os = "linux")]
+fn sockaddr_storage(addr: IpAddr) -> libc::sockaddr_storage {
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    match addr {
+        IpAddr::V4(v4) => {
+            let sin = &mut storage as *mut _ as *mut libc::sockaddr_in;
+            unsafe {
+                (*sin).sin_family = libc::AF_INET as libc::sa_family_t;
+                (*sin).sin_addr.s_addr = u32::from(v4).to_be();
+            }
+        }
+        IpAddr::V6(v6) => {
+            let sin6 = &mut storage as *mut _ as *mut libc::sockaddr_in6;
+            unsafe {
+                (*sin6).sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                (*sin6).sin6_addr.s6_addr = v6.octets();
+            }
+        }
+    }
+    storage
+}
+
+// Install the MD5 key for `addr` on the socket. An empty key removes it.
+#[cfg(target_os = "linux")]
+pub fn tcp_md5sig_set(fd: RawFd, addr: IpAddr, key: &[u8]) -> io::Result<()> {
+    if key.len() > TCP_MD5SIG_MAXKEYLEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TCP MD5 password is too long",
+        ));
+    }
+    let mut sig = TcpMd5Sig {
+        addr: sockaddr_storage(addr),
+        flags: 0,
+        prefixlen: 0,
+        keylen: key.len() as u16,
+        ifindex: 0,
+        key: [0u8; TCP_MD5SIG_MAXKEYLEN],
+    };
+    sig.key[..key.len()].copy_from_slice(key);
+
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_MD5SIG,
+            &sig as *const _ as *const libc::c_void,
+            std::mem::size_of::<TcpMd5Sig>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        // Removing a key which was never installed is not an error.
+        if key.is_empty() && err.raw_os_error() == Some(libc::ENOENT) {
+            return Ok(());
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn tcp_md5sig_set(_fd: RawFd, _addr: IpAddr, key: &[u8]) -> io::Result<()> {
+    if key.is_empty() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP MD5 signature is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn md5sig_set() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = listener.as_raw_fd();
+        let addr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+        tcp_auth_set(fd, addr, &TcpAuth::Md5(String::from("secret"))).unwrap();
+        tcp_auth_clear(fd, addr).unwrap();
+
+        // Clearing again must succeed even though no key is installed.
+        tcp_auth_clear(fd, addr).unwrap();
+    }
+
+    #[test]
+    fn md5sig_set_v6() {
+        let Ok(listener) = TcpListener::bind("[::1]:0") else {
+            return;
+        };
+        let fd = listener.as_raw_fd();
+        let addr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        tcp_md5sig_set(fd, addr, b"secret").unwrap();
+    }
+
+    #[test]
+    fn md5sig_key_too_long() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = listener.as_raw_fd();
+        let addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let key = [b'x'; TCP_MD5SIG_MAXKEYLEN + 1];
+        let err = tcp_md5sig_set(fd, addr, &key).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,246 @@
+use super::adj_rib::{adj_rib_out_sync, ExportPeer};
+use super::handler::{Bgp, ClearCallback};
+use super::packet::RouteRefreshSubtype;
+use super::peer::{fsm, peer_send_adj_rib_out, peer_send_route_refresh, Event, Peer, State};
+use super::route::Route;
+use super::{Afi, Safi};
+use crate::config::Args;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::net::Ipv4Addr;
+
+// Operational clear of the neighbors. A hard clear resets the session with a
+// Cease notification. The soft clears keep the session up: inbound asks the
+// neighbor to send its routes again with route refresh, and outbound exports
+// the Loc-RIB to the Adj-RIB-Out again and re-advertises all of the routes.
+// "clear bgp neighbor shutdown" shuts the session down until the next hard
+// clear without changing the config.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearType {
+    Hard,
+    SoftIn,
+    SoftOut,
+    Soft,
+}
+
+impl Bgp {
+    fn clear_add(&mut self, path: &str, cb: ClearCallback) {
+        self.clear_cb.insert(path.to_string(), cb);
+    }
+
+    pub fn clear_build(&mut self) {
+        self.clear_add("/clear/bgp/neighbor", clear_bgp_neighbor);
+        self.clear_add("/clear/bgp/neighbor/shutdown", clear_bgp_neighbor_shutdown);
+        self.clear_add("/clear/bgp/ipv4/unicast/in", clear_bgp_ipv4_in);
+        self.clear_add("/clear/bgp/ipv4/unicast/out", clear_bgp_ipv4_out);
+        self.clear_add("/clear/bgp/ipv4/unicast/soft", clear_bgp_ipv4_soft);
+    }
+
+    pub fn peer_clear(&mut self, addr: Ipv4Addr, clear: ClearType) -> Result<(), String> {
+        if !self.peers.contains_key(&addr) {
+            return Err(format!("% No such neighbor {}", addr));
+        }
+        if clear == ClearType::Hard {
+            // Resume the neighbor shut down by the operator.
+            if self.peers[&addr].shutdown.exec {
+                self.peer_shutdown_update(addr, |shutdown| shutdown.exec = false);
+            } else {
+                fsm(self, addr, Event::AdminReset);
+            }
+            return Ok(());
+        }
+        let peer = self.peers.get_mut(&addr).unwrap();
+        if peer.state != State::Established {
+            return Err(format!("% Neighbor {} is not established", addr));
+        }
+        if matches!(clear, ClearType::SoftIn | ClearType::Soft) {
+            if !peer.route_refresh {
+                return Err(format!(
+                    "% Neighbor {} does not support route refresh",
+                    addr
+                ));
+            }
+            peer_send_route_refresh(peer, Afi::IP, Safi::Unicast, RouteRefreshSubtype::Normal);
+        }
+        if matches!(clear, ClearType::SoftOut | ClearType::Soft) {
+            peer_soft_out(peer, &self.ptree, self.advertise_best_external);
+        }
+        Ok(())
+    }
+}
+
+// Export runs again with the current outbound config of the neighbor, and
+// every route of the Adj-RIB-Out is sent regardless of the MRAI timer.
+fn peer_soft_out(peer: &mut Peer, ptree: &PrefixMap<Ipv4Net, Vec<Route>>, best_external: bool) {
+    let mut export = ExportPeer::new(peer);
+    export.best_external = best_external;
+    adj_rib_out_sync(&mut peer.adj_out, &export, ptree);
+    peer.adj_out.refresh();
+    peer.adj_out.vpn.refresh();
+    peer_send_adj_rib_out(peer);
+}
+
+fn clear_output(result: Result<(), String>) -> String {
+    match result {
+        Ok(()) => String::new(),
+        Err(err) => format!("{}\n", err),
+    }
+}
+
+fn clear_bgp(bgp: &mut Bgp, mut args: Args, clear: ClearType) -> String {
+    let Some(addr) = args.v4addr() else {
+        return String::from("% Invalid neighbor address\n");
+    };
+    clear_output(bgp.peer_clear(addr, clear))
+}
+
+fn clear_bgp_neighbor(bgp: &mut Bgp, args: Args) -> String {
+    clear_bgp(bgp, args, ClearType::Hard)
+}
+
+fn clear_bgp_neighbor_shutdown(bgp: &mut Bgp, mut args: Args) -> String {
+    let Some(addr) = args.v4addr() else {
+        return String::from("% Invalid neighbor address\n");
+    };
+    if !bgp.peers.contains_key(&addr) {
+        return format!("% No such neighbor {}\n", addr);
+    }
+    bgp.peer_shutdown_update(addr, |shutdown| shutdown.exec = true);
+    String::new()
+}
+
+fn clear_bgp_ipv4_in(bgp: &mut Bgp, args: Args) -> String {
+    clear_bgp(bgp, args, ClearType::SoftIn)
+}
+
+fn clear_bgp_ipv4_out(bgp: &mut Bgp, args: Args) -> String {
+    clear_bgp(bgp, args, ClearType::SoftOut)
+}
+
+fn clear_bgp_ipv4_soft(bgp: &mut Bgp, args: Args) -> String {
+    clear_bgp(bgp, args, ClearType::Soft)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{
+        As4PathAttr, As4Segment, Attribute, BgpType, NextHopAttr, OriginAttr, AS_SEQUENCE,
+        ORIGIN_IGP,
+    };
+    use crate::bgp::route::{route_aspath, RouteFrom};
+    use crate::bgp::transform::RemovePrivateAs;
+    use bytes::BytesMut;
+    use std::sync::Arc;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    fn route(from: &str, asn: &[u32]) -> Route {
+        let attrs = vec![
+            Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: asn.to_vec(),
+                }],
+            }),
+            Attribute::NextHop(NextHopAttr {
+                next_hop: [10, 1, 0, 1],
+            }),
+        ];
+        Route {
+            from: from.parse().unwrap(),
+            router_id: from.parse().unwrap(),
+            kind: RouteFrom::Peer,
+            aspath: route_aspath(&attrs),
+            attrs: Arc::new(attrs),
+            nexthop: "10.1.0.1".parse().ok(),
+            ibgp: false,
+            selected: true,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        }
+    }
+
+    // Established neighbor whose packets are kept in the channel.
+    fn established(bgp: &mut Bgp, addr: Ipv4Addr, peer_as: u32) -> UnboundedReceiver<BytesMut> {
+        let mut peer = Peer::new(addr, bgp.asn, bgp.router_id, peer_as, addr, bgp.tx.clone());
+        let (tx, rx) = mpsc::unbounded_channel();
+        peer.packet_tx = Some(tx);
+        peer.state = State::Established;
+        peer.local_addr = "192.0.2.1".parse().ok();
+        bgp.peers.insert(addr, peer);
+        rx
+    }
+
+    fn sent(rx: &mut UnboundedReceiver<BytesMut>) -> usize {
+        let mut count = 0;
+        while rx.try_recv().is_ok() {
+            count += 1;
+        }
+        count
+    }
+
+    #[tokio::test]
+    async fn clear() {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.asn = 65000;
+        bgp.router_id = "192.0.2.1".parse().unwrap();
+        let addr: Ipv4Addr = "10.2.0.2".parse().unwrap();
+        let mut rx = established(&mut bgp, addr, 65002);
+        let prefix: Ipv4Net = "10.10.0.0/16".parse().unwrap();
+        bgp.ptree
+            .insert(prefix, vec![route("10.1.0.1", &[65001, 65010])]);
+
+        bgp.adj_rib_update();
+        assert_eq!(sent(&mut rx), 1);
+        bgp.adj_rib_update();
+        assert_eq!(sent(&mut rx), 0);
+        let aspath = |bgp: &Bgp| {
+            let out = bgp.peers[&addr].adj_out.routes.get(&prefix).unwrap();
+            route_aspath(&out.attrs()).to_string()
+        };
+        assert_eq!(aspath(&bgp), "65000 65001 65010");
+
+        // Outbound policy is applied again and the route is re-advertised.
+        let peer = bgp.peers.get_mut(&addr).unwrap();
+        peer.config.remove_private_as = Some(RemovePrivateAs::All);
+        assert_eq!(bgp.peer_clear(addr, ClearType::SoftOut), Ok(()));
+        assert_eq!(sent(&mut rx), 1);
+        assert_eq!(aspath(&bgp), "65000");
+
+        // Re-advertised even when nothing has been changed.
+        assert_eq!(bgp.peer_clear(addr, ClearType::SoftOut), Ok(()));
+        assert_eq!(sent(&mut rx), 1);
+        assert_eq!(bgp.peers[&addr].counter[BgpType::Update as usize].sent, 3);
+
+        // Inbound needs the route refresh capability of the neighbor.
+        assert!(bgp.peer_clear(addr, ClearType::SoftIn).is_err());
+        bgp.peers.get_mut(&addr).unwrap().route_refresh = true;
+        assert_eq!(bgp.peer_clear(addr, ClearType::SoftIn), Ok(()));
+        assert_eq!(sent(&mut rx), 1);
+        assert_eq!(
+            bgp.peers[&addr].counter[BgpType::RouteRefresh as usize].sent,
+            1
+        );
+        assert_eq!(bgp.peer_clear(addr, ClearType::Soft), Ok(()));
+        assert_eq!(sent(&mut rx), 2);
+        assert_eq!(bgp.peers[&addr].state, State::Established);
+
+        let unknown: Ipv4Addr = "10.9.0.9".parse().unwrap();
+        assert!(bgp.peer_clear(unknown, ClearType::Hard).is_err());
+
+        // Hard clear resets the session with a notification.
+        assert_eq!(bgp.peer_clear(addr, ClearType::Hard), Ok(()));
+        assert_eq!(sent(&mut rx), 1);
+        let peer = &bgp.peers[&addr];
+        assert_eq!(peer.counter[BgpType::Notification as usize].sent, 1);
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.adj_out.routes.is_empty());
+        assert!(bgp.peer_clear(addr, ClearType::SoftOut).is_err());
+    }
+}
@@ -1,13 +1,21 @@
 use super::{
     handler::Callback,
-    peer::{fsm_init, Peer, PeerType},
-    AfiSafi, Bgp,
+    network::{aggregate_suppress, local_route_update, Network},
+    peer::Peer,
+    peer_group::MAX_DYNAMIC_PEERS,
+    route::{route_nexthop_update, RouteFrom},
+    rpki::RpkiCache,
+    rtr::RTR_PORT,
+    transform::prepend_parse,
+    ttl::TtlConfig,
+    AfiSafi, Bgp, BGP_PORT,
 };
 use crate::{
-    config::{Args, ConfigOp},
-    policy::CommunityMember,
+    config::{Args, ConfigManager, ConfigOp},
+    policy::{aspath_regex, CommunityMember, MaskLengthRange, PrefixSet},
+    rib::api::RibTx,
 };
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 
 fn config_global_asn(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
     if op == ConfigOp::Set && !args.is_empty() {
@@ -17,48 +25,318 @@ fn config_global_asn(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()>
     Some(())
 }
 fn config_global_identifier(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
-    if op == ConfigOp::Set {
-        let router_id = args.v4addr()?;
-        bgp.router_id = router_id;
-    }
+    bgp.identifier = if op == ConfigOp::Set {
+        Some(args.v4addr()?)
+    } else {
+        None
+    };
+    bgp.router_id_update();
     Some(())
 }
 
 fn config_peer(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
     if op == ConfigOp::Set {
         let addr: Ipv4Addr = args.v4addr()?;
-        let peer = Peer::new(addr, bgp.asn, bgp.router_id, 0u32, addr, bgp.tx.clone());
+        let mut peer = Peer::new(addr, bgp.asn, bgp.router_id, 0u32, addr, bgp.tx.clone());
+        peer.shutdown.global = bgp.shutdown;
         bgp.peers.insert(addr, peer);
+        bgp.listen_ttl_update();
     }
     Some(())
 }
 
-fn config_peer_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+fn config_peer_group(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
     if op == ConfigOp::Set {
-        let addr: Ipv4Addr = args.v4addr()?;
-        let asn: u32 = args.u32()?;
-        if let Some(peer) = bgp.peers.get_mut(&addr) {
-            peer.peer_as = asn;
-            peer.peer_type = if peer.peer_as == bgp.asn {
-                PeerType::Internal
-            } else {
-                PeerType::External
-            };
-            peer.update();
-        }
+        bgp.peer_groups.entry(name).or_default();
+    } else {
+        bgp.peer_groups.remove(&name);
+        bgp.peer_group_apply(&name);
+        bgp.listen_ttl_update();
     }
     Some(())
 }
 
-fn config_afi_safi(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+fn config_peer_group_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.peer_as = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_hold_time(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.hold_time = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_keepalive(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.keepalive = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_connect_retry(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.connect_retry = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_passive(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.passive = if op == ConfigOp::Set {
+        Some(args.boolean()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_afi_safi(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let afi_safi: AfiSafi = args.afi_safi()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.afi_safi.0.retain(|x| *x != afi_safi);
+    if op == ConfigOp::Set && args.boolean()? {
+        group.template.afi_safi.push(afi_safi);
+    }
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_next_hop_self(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.next_hop_self = if op == ConfigOp::Set {
+        Some(args.string()?.parse().ok()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_remove_private_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.remove_private_as = if op == ConfigOp::Set {
+        Some(args.string()?.parse().ok()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_as_override(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.as_override = if op == ConfigOp::Set {
+        Some(args.boolean()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_as_path_prepend(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.as_path_prepend = if op == ConfigOp::Set {
+        Some(prepend_parse(&args.string()?)?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_as_path_prepend_last_as(
+    bgp: &mut Bgp,
+    mut args: Args,
+    op: ConfigOp,
+) -> Option<()> {
+    let name = args.string()?;
+    let group = bgp.peer_groups.entry(name.clone()).or_default();
+    group.template.as_path_prepend_last_as = if op == ConfigOp::Set {
+        Some(args.u8()?)
+    } else {
+        None
+    };
+    bgp.peer_group_apply(&name);
+    Some(())
+}
+
+fn config_peer_group_range(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let range = args.v4net()?;
     if op == ConfigOp::Set {
-        let addr: Ipv4Addr = args.v4addr()?;
-        let afi_safi: AfiSafi = args.afi_safi()?;
-        if let Some(peer) = bgp.peers.get_mut(&addr) {
-            if !peer.config.afi_safi.has(&afi_safi) {
-                peer.config.afi_safi.push(afi_safi);
-            }
-        }
+        let group = bgp.peer_groups.entry(name).or_default();
+        group.ranges.insert(range);
+    } else if let Some(group) = bgp.peer_groups.get_mut(&name) {
+        group.ranges.remove(&range);
+    }
+    bgp.listen_ttl_update();
+    Some(())
+}
+
+fn config_max_dynamic_peers(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    bgp.max_dynamic_peers = if op == ConfigOp::Set {
+        args.u32()? as usize
+    } else {
+        MAX_DYNAMIC_PEERS
+    };
+    Some(())
+}
+
+fn config_advertise_best_external(bgp: &mut Bgp, _args: Args, op: ConfigOp) -> Option<()> {
+    bgp.advertise_best_external = op == ConfigOp::Set;
+    Some(())
+}
+
+fn config_multipath_ebgp(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    bgp.nexthops.multipath.ebgp = if op == ConfigOp::Set { args.u32()? } else { 1 };
+    route_nexthop_update(&mut bgp.ptree, &mut bgp.nexthops, None);
+    Some(())
+}
+
+fn config_multipath_ibgp(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    bgp.nexthops.multipath.ibgp = if op == ConfigOp::Set { args.u32()? } else { 1 };
+    route_nexthop_update(&mut bgp.ptree, &mut bgp.nexthops, None);
+    Some(())
+}
+
+fn config_multipath_allow_multiple_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    bgp.nexthops.multipath.allow_multiple_as = op == ConfigOp::Set && args.boolean()?;
+    route_nexthop_update(&mut bgp.ptree, &mut bgp.nexthops, None);
+    Some(())
+}
+
+fn config_listen_address(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: IpAddr = args.string()?.parse().ok()?;
+    if op == ConfigOp::Set {
+        bgp.listen_config.entry(addr).or_insert(BGP_PORT);
+    } else {
+        bgp.listen_config.remove(&addr);
+    }
+    bgp.listen_update();
+    Some(())
+}
+
+fn config_listen_port(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: IpAddr = args.string()?.parse().ok()?;
+    let port = if op == ConfigOp::Set {
+        args.u16()?
+    } else {
+        BGP_PORT
+    };
+    // The listener is restarted on the new port.
+    if let Some(v) = bgp.listen_config.get_mut(&addr) {
+        *v = port;
+    }
+    bgp.listen_update();
+    Some(())
+}
+
+fn config_rpki_cache(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: IpAddr = args.string()?.parse().ok()?;
+    if op == ConfigOp::Set {
+        bgp.rpki
+            .caches
+            .entry(addr)
+            .or_insert_with(|| RpkiCache::new(RTR_PORT));
+    } else {
+        bgp.rpki.caches.remove(&addr);
+    }
+    bgp.rpki_update();
+    Some(())
+}
+
+fn config_rpki_cache_port(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: IpAddr = args.string()?.parse().ok()?;
+    let port = if op == ConfigOp::Set {
+        args.u16()?
+    } else {
+        RTR_PORT
+    };
+    // The session is established again with the new port.
+    let cache = bgp.rpki.caches.get_mut(&addr)?;
+    if cache.port != port {
+        *cache = RpkiCache::new(port);
+    }
+    bgp.rpki_update();
+    Some(())
+}
+
+fn config_peer_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.peer_as = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_peer_peer_group(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.peer_group = if op == ConfigOp::Set {
+        Some(args.string()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_afi_safi(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let afi_safi: AfiSafi = args.afi_safi()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.afi_safi.0.retain(|x| *x != afi_safi);
+    if op == ConfigOp::Set && args.boolean()? {
+        peer.template.afi_safi.push(afi_safi);
+    }
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_afi_safi_extended_nexthop(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let afi_safi: AfiSafi = args.afi_safi()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.extended_nexthop.0.retain(|x| *x != afi_safi);
+    if op == ConfigOp::Set && args.boolean()? {
+        peer.config.extended_nexthop.push(afi_safi);
     }
     Some(())
 }
@@ -76,26 +354,397 @@ fn config_local_identifier(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Optio
 }
 
 fn config_transport_passive(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
-    let addr = args.v4addr()?;
-    let passive = args.boolean()?;
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.passive = if op == ConfigOp::Set {
+        Some(args.boolean()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
 
+fn config_shutdown(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    bgp.peers.get(&addr)?;
+    bgp.peer_shutdown_update(addr, |shutdown| shutdown.config = op == ConfigOp::Set);
+    Some(())
+}
+
+fn config_shutdown_message(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.shutdown.message = if op == ConfigOp::Set {
+        Some(args.string()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_description(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.description = if op == ConfigOp::Set {
+        Some(args.string()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_global_shutdown(bgp: &mut Bgp, _args: Args, op: ConfigOp) -> Option<()> {
+    bgp.shutdown_update(op == ConfigOp::Set);
+    Some(())
+}
+
+fn config_transport_password(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
     if op == ConfigOp::Set {
-        if let Some(peer) = bgp.peers.get_mut(&addr) {
-            peer.config.transport.passive = passive;
-            peer.timer.idle_hold_timer = None;
-            peer.state = fsm_init(peer);
-        }
+        peer.config.transport.password = Some(args.string()?);
+    } else {
+        peer.config.transport.password = None;
+    }
+    bgp.listen_auth_update(&addr);
+    Some(())
+}
+
+// TTL change takes effect when the session is established next time. The
+// listening sockets are updated right away.
+fn config_ttl_security(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    let hops = if op == ConfigOp::Set {
+        Some(args.u8()?)
+    } else {
+        None
+    };
+    if let Err(err) = peer.config.transport.ttl.set_ttl_security(hops) {
+        println!("neighbor {}: {}", addr, err);
+        return None;
+    }
+    bgp.listen_ttl_update();
+    Some(())
+}
+
+fn config_ebgp_multihop(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    let ttl = if op == ConfigOp::Set {
+        Some(args.u8()?)
+    } else {
+        None
+    };
+    if let Err(err) = peer.config.transport.ttl.set_ebgp_multihop(ttl) {
+        println!("neighbor {}: {}", addr, err);
+        return None;
     }
     Some(())
 }
 
 fn config_hold_time(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.hold_time = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_keepalive(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.keepalive = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_connect_retry(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.connect_retry = if op == ConfigOp::Set {
+        Some(args.u16()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+// Rate limit change takes effect when the session is established next time.
+fn config_rate_limit_inbound(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.rate_limit.inbound = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_rate_limit_outbound(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.rate_limit.outbound = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_rate_limit_burst(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.rate_limit.burst = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_debug_packets(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.trace.config.packets = op == ConfigOp::Set && args.boolean()?;
+    peer.trace.update();
+    Some(())
+}
+
+fn config_debug_events(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.trace.config.events = op == ConfigOp::Set && args.boolean()?;
+    peer.trace.update();
+    Some(())
+}
+
+fn config_debug_trace_size(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.trace.config.size = if op == ConfigOp::Set {
+        Some(args.u32()? as usize)
+    } else {
+        None
+    };
+    peer.trace.update();
+    Some(())
+}
+
+// Role change takes effect when the session is established next time.
+fn config_role(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.config.role = if op == ConfigOp::Set {
+        Some(args.string()?.parse().ok()?)
+    } else {
+        None
+    };
+    Some(())
+}
+
+fn config_next_hop_self(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.next_hop_self = if op == ConfigOp::Set {
+        Some(args.string()?.parse().ok()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_remove_private_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.remove_private_as = if op == ConfigOp::Set {
+        Some(args.string()?.parse().ok()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_as_override(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.as_override = if op == ConfigOp::Set {
+        Some(args.boolean()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_as_path_prepend(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.as_path_prepend = if op == ConfigOp::Set {
+        Some(prepend_parse(&args.string()?)?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_as_path_prepend_last_as(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: Ipv4Addr = args.v4addr()?;
+    let peer = bgp.peers.get_mut(&addr)?;
+    peer.template.as_path_prepend_last_as = if op == ConfigOp::Set {
+        Some(args.u8()?)
+    } else {
+        None
+    };
+    bgp.peer_config_apply(addr);
+    Some(())
+}
+
+fn config_resolve_via_default(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    bgp.nexthops.resolve_via_default = op == ConfigOp::Set && args.boolean()?;
+    route_nexthop_update(&mut bgp.ptree, &mut bgp.nexthops, None);
+    Some(())
+}
+
+fn config_network(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix = args.v4net()?.trunc();
     if op == ConfigOp::Set {
-        let addr: Ipv4Addr = args.v4addr()?;
-        let hold_time: u16 = args.u16()?;
-        if let Some(peer) = bgp.peers.get_mut(&addr) {
-            peer.config.hold_time = Some(hold_time);
+        if !bgp.networks.contains_key(&prefix) {
+            bgp.networks.insert(prefix, Network::default());
+            bgp.rib_pending.push(RibTx::PrefixRegister(prefix));
+        }
+    } else if bgp.networks.remove(&prefix).is_some() {
+        bgp.rib_pending.push(RibTx::PrefixUnregister(prefix));
+        bgp.network_update(prefix);
+    }
+    Some(())
+}
+
+fn config_network_match(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix = args.v4net()?.trunc();
+    let network = bgp.networks.get_mut(&prefix)?;
+    network.mode = if op == ConfigOp::Set {
+        args.string()?.parse().ok()?
+    } else {
+        Default::default()
+    };
+    bgp.network_update(prefix);
+    Some(())
+}
+
+fn config_aggregate(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix = args.v4net()?.trunc();
+    if op == ConfigOp::Set {
+        bgp.aggregates.entry(prefix).or_default();
+        // Evaluated at the next aggregate update.
+        bgp.nexthops.changed.insert(prefix);
+    } else if bgp.aggregates.remove(&prefix).is_some() {
+        local_route_update(
+            &mut bgp.ptree,
+            &mut bgp.nexthops,
+            prefix,
+            RouteFrom::Aggregate,
+            None,
+        );
+        // Release the routes suppressed by the aggregate.
+        aggregate_suppress(&mut bgp.ptree, &mut bgp.nexthops, &bgp.aggregates, &prefix);
+    }
+    Some(())
+}
+
+fn config_aggregate_summary_only(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix = args.v4net()?.trunc();
+    let aggregate = bgp.aggregates.get_mut(&prefix)?;
+    aggregate.summary_only = op == ConfigOp::Set && args.boolean()?;
+    bgp.nexthops.changed.insert(prefix);
+    Some(())
+}
+
+fn config_aggregate_as_set(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix = args.v4net()?.trunc();
+    let aggregate = bgp.aggregates.get_mut(&prefix)?;
+    aggregate.as_set = op == ConfigOp::Set && args.boolean()?;
+    bgp.nexthops.changed.insert(prefix);
+    Some(())
+}
+
+fn config_aspath_set(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    if op == ConfigOp::Set {
+        bgp.aspath_sets.entry(name).or_default();
+    } else {
+        bgp.aspath_sets.remove(&name);
+    }
+    Some(())
+}
+
+fn config_aspath_set_member(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let pattern = args.string()?;
+    if op == ConfigOp::Set {
+        // Pattern has been validated at commit.
+        let set = bgp.aspath_sets.entry(name).or_default();
+        set.insert(&pattern).ok()?;
+    } else if let Some(set) = bgp.aspath_sets.get_mut(&name) {
+        set.remove(&pattern);
+    }
+    Some(())
+}
+
+fn config_prefix_set(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    if op == ConfigOp::Set {
+        bgp.prefix_sets.entry(name).or_default();
+    } else {
+        bgp.prefix_sets.remove(&name);
+    }
+    Some(())
+}
+
+fn config_prefix_set_prefix(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let prefix = args.v4net()?;
+    if op == ConfigOp::Set {
+        bgp.prefix_sets.entry(name).or_default().insert(prefix);
+    } else if let Some(set) = bgp.prefix_sets.get_mut(&name) {
+        set.remove(prefix);
+    }
+    Some(())
+}
+
+fn config_prefix_set_range(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let prefix = args.v4net()?;
+    let range = args.string()?;
+    let range: MaskLengthRange = match range.parse() {
+        Ok(range) => range,
+        Err(err) => {
+            println!("prefix-set {}: {}", name, err);
+            return None;
+        }
+    };
+    if op == ConfigOp::Set {
+        let set = bgp.prefix_sets.entry(name.clone()).or_default();
+        if let Err(err) = set.range_insert(prefix, range) {
+            println!("prefix-set {}: masklength-range {}", name, err);
+            return None;
         }
+    } else if let Some(set) = bgp.prefix_sets.get_mut(&name) {
+        set.range_remove(prefix, range);
     }
     Some(())
 }
@@ -105,20 +754,210 @@ fn config_clist(bgp: &mut Bgp, mut args: Args, op: ConfigOp) -> Option<()> {
     Some(())
 }
 
+const PEER_TTL_SECURITY: &str = "/routing/bgp/neighbors/neighbor/transport/ttl-security/hops";
+const PEER_EBGP_MULTIHOP: &str =
+    "/routing/bgp/neighbors/neighbor/transport/ebgp-multihop/multihop-ttl";
+
+// Checks at commit of the errors which the callbacks above fail with, so that
+// the commit is rejected instead of the change being dropped by BGP.
+
+fn validate_aspath_set_member(mut args: Args, _candidate: &[(String, Args)]) -> Result<(), String> {
+    let _name = args.string();
+    let pattern = args.string().unwrap_or_default();
+    match aspath_regex(&pattern) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("invalid pattern {}: {}", pattern, err)),
+    }
+}
+
+fn validate_prefix_set_range(mut args: Args, _candidate: &[(String, Args)]) -> Result<(), String> {
+    let _name = args.string();
+    let prefix = args.v4net().ok_or("invalid prefix")?;
+    let range: MaskLengthRange = args.string().unwrap_or_default().parse()?;
+    PrefixSet::default()
+        .range_insert(prefix, range)
+        .map_err(|err| format!("masklength-range {}", err))
+}
+
+// Policy of the network statement needs the set actions of the routing
+// policy, which are not implemented yet.
+fn validate_network_policy(_args: Args, _candidate: &[(String, Args)]) -> Result<(), String> {
+    Err("network policy is not supported yet".to_string())
+}
+
+// Value of the leaf of the neighbor in the candidate config.
+fn candidate_peer_u8(candidate: &[(String, Args)], path: &str, addr: Ipv4Addr) -> Option<u8> {
+    candidate
+        .iter()
+        .filter(|(p, _)| p == path)
+        .find_map(|(_, args)| {
+            let mut args = args.clone();
+            if args.v4addr()? == addr {
+                args.u8()
+            } else {
+                None
+            }
+        })
+}
+
+fn validate_ttl_security(mut args: Args, candidate: &[(String, Args)]) -> Result<(), String> {
+    let addr = args.v4addr().ok_or("invalid neighbor")?;
+    let mut ttl = TtlConfig {
+        ebgp_multihop: candidate_peer_u8(candidate, PEER_EBGP_MULTIHOP, addr),
+        ..Default::default()
+    };
+    ttl.set_ttl_security(args.u8())
+}
+
+fn validate_ebgp_multihop(mut args: Args, candidate: &[(String, Args)]) -> Result<(), String> {
+    let addr = args.v4addr().ok_or("invalid neighbor")?;
+    let mut ttl = TtlConfig {
+        ttl_security: candidate_peer_u8(candidate, PEER_TTL_SECURITY, addr),
+        ..Default::default()
+    };
+    ttl.set_ebgp_multihop(args.u8())
+}
+
+pub fn validator_build(config: &mut ConfigManager) {
+    config.validator_add("/as-path-set/member", validate_aspath_set_member);
+    config.validator_add(
+        "/prefix-set/prefix/masklength-range",
+        validate_prefix_set_range,
+    );
+    config.validator_add(PEER_TTL_SECURITY, validate_ttl_security);
+    config.validator_add(PEER_EBGP_MULTIHOP, validate_ebgp_multihop);
+    config.validator_add(
+        "/routing/bgp/global/network/policy",
+        validate_network_policy,
+    );
+}
+
 impl Bgp {
     fn callback_peer(&mut self, path: &str, cb: Callback) {
         let neighbor_prefix = String::from("/routing/bgp/neighbors/neighbor");
         self.callbacks.insert(neighbor_prefix + path, cb);
     }
 
+    fn callback_group(&mut self, path: &str, cb: Callback) {
+        let group_prefix = String::from("/routing/bgp/peer-groups/peer-group");
+        self.callbacks.insert(group_prefix + path, cb);
+    }
+
     pub fn callback_build(&mut self) {
         self.callback_add("/routing/bgp/global/as", config_global_asn);
         self.callback_add("/routing/bgp/global/identifier", config_global_identifier);
+        self.callback_add(
+            "/routing/bgp/global/nexthop-tracking/resolve-via-default",
+            config_resolve_via_default,
+        );
+        self.callback_add("/routing/bgp/global/network", config_network);
+        self.callback_add("/routing/bgp/global/network/match", config_network_match);
+        self.callback_add("/routing/bgp/global/aggregate-address", config_aggregate);
+        self.callback_add(
+            "/routing/bgp/global/aggregate-address/summary-only",
+            config_aggregate_summary_only,
+        );
+        self.callback_add(
+            "/routing/bgp/global/aggregate-address/as-set",
+            config_aggregate_as_set,
+        );
+        self.callback_add(
+            "/routing/bgp/global/max-dynamic-peers",
+            config_max_dynamic_peers,
+        );
+        self.callback_add(
+            "/routing/bgp/global/advertise/best-external",
+            config_advertise_best_external,
+        );
+        self.callback_add(
+            "/routing/bgp/global/use-multiple-paths/ebgp/maximum-paths",
+            config_multipath_ebgp,
+        );
+        self.callback_add(
+            "/routing/bgp/global/use-multiple-paths/ibgp/maximum-paths",
+            config_multipath_ibgp,
+        );
+        self.callback_add(
+            "/routing/bgp/global/use-multiple-paths/ebgp/allow-multiple-as",
+            config_multipath_allow_multiple_as,
+        );
+        self.callback_add("/routing/bgp/global/listen/address", config_listen_address);
+        self.callback_add(
+            "/routing/bgp/global/listen/address/port",
+            config_listen_port,
+        );
+        self.callback_add("/routing/bgp/global/rpki/cache", config_rpki_cache);
+        self.callback_add(
+            "/routing/bgp/global/rpki/cache/port",
+            config_rpki_cache_port,
+        );
+        self.callback_add("/routing/bgp/global/shutdown", config_global_shutdown);
+        self.callback_add("/routing/bgp/peer-groups/peer-group", config_peer_group);
+        self.callback_group("/peer-as", config_peer_group_as);
+        self.callback_group("/timers/hold-time", config_peer_group_hold_time);
+        self.callback_group("/timers/keepalive", config_peer_group_keepalive);
+        self.callback_group(
+            "/timers/connect-retry-interval",
+            config_peer_group_connect_retry,
+        );
+        self.callback_group("/transport/passive-mode", config_peer_group_passive);
+        self.callback_group("/afi-safis/afi-safi/enabled", config_peer_group_afi_safi);
+        self.callback_group("/next-hop-self", config_peer_group_next_hop_self);
+        self.callback_group("/remove-private-as", config_peer_group_remove_private_as);
+        self.callback_group("/as-override", config_peer_group_as_override);
+        self.callback_group(
+            "/as-path-prepend/as-path",
+            config_peer_group_as_path_prepend,
+        );
+        self.callback_group(
+            "/as-path-prepend/last-as",
+            config_peer_group_as_path_prepend_last_as,
+        );
+        self.callback_add(
+            "/routing/bgp/peer-groups/peer-group/dynamic-peers/dynamic-peer-list",
+            config_peer_group_range,
+        );
+        self.callback_add("/as-path-set", config_aspath_set);
+        self.callback_add("/as-path-set/member", config_aspath_set_member);
+        self.callback_add("/prefix-set", config_prefix_set);
+        self.callback_add("/prefix-set/prefix", config_prefix_set_prefix);
+        self.callback_add(
+            "/prefix-set/prefix/masklength-range",
+            config_prefix_set_range,
+        );
         self.callback_peer("", config_peer);
         self.callback_peer("/peer-as", config_peer_as);
+        self.callback_peer("/peer-group", config_peer_peer_group);
         self.callback_peer("/local-identifier", config_local_identifier);
         self.callback_peer("/transport/passive-mode", config_transport_passive);
+        self.callback_peer("/transport/password", config_transport_password);
+        self.callback_peer("/transport/ttl-security/hops", config_ttl_security);
+        self.callback_peer(
+            "/transport/ebgp-multihop/multihop-ttl",
+            config_ebgp_multihop,
+        );
         self.callback_peer("/afi-safis/afi-safi/enabled", config_afi_safi);
+        self.callback_peer(
+            "/afi-safis/afi-safi/extended-nexthop",
+            config_afi_safi_extended_nexthop,
+        );
         self.callback_peer("/timers/hold-time", config_hold_time);
+        self.callback_peer("/timers/keepalive", config_keepalive);
+        self.callback_peer("/timers/connect-retry-interval", config_connect_retry);
+        self.callback_peer("/update-rate-limit/inbound", config_rate_limit_inbound);
+        self.callback_peer("/update-rate-limit/outbound", config_rate_limit_outbound);
+        self.callback_peer("/update-rate-limit/burst", config_rate_limit_burst);
+        self.callback_peer("/debug/packets", config_debug_packets);
+        self.callback_peer("/debug/events", config_debug_events);
+        self.callback_peer("/debug/trace-size", config_debug_trace_size);
+        self.callback_peer("/role", config_role);
+        self.callback_peer("/next-hop-self", config_next_hop_self);
+        self.callback_peer("/remove-private-as", config_remove_private_as);
+        self.callback_peer("/as-override", config_as_override);
+        self.callback_peer("/as-path-prepend/as-path", config_as_path_prepend);
+        self.callback_peer("/as-path-prepend/last-as", config_as_path_prepend_last_as);
+        self.callback_peer("/shutdown", config_shutdown);
+        self.callback_peer("/shutdown-message", config_shutdown_message);
+        self.callback_peer("/description", config_description);
     }
 }
@@ -1,3 +1,4 @@
 pub const BGP_VERSION: u8 = 4;
 pub const BGP_PORT: u16 = 179;
 pub const BGP_HOLD_TIME: u16 = 90;
+pub const BGP_CONNECT_RETRY: u16 = 5;
@@ -1,43 +1,89 @@
+use super::listen::{listen_bind, ListenBind, ListenEvent, Listener, LISTEN_RETRY_MIN};
+use super::network::{Aggregates, Networks};
+use super::nexthop::NexthopCache;
 use super::peer::{fsm, Event, Peer};
-use super::route::Route;
+use super::peer_group::{PeerGroups, MAX_DYNAMIC_PEERS};
+use super::route::{route_nexthop_update, Route};
+use super::rpki::Rpki;
+use super::rtr::RtrEvent;
+use super::vpn::{VpnOutKey, VpnTable};
 use crate::bgp::peer::accept;
-use crate::bgp::task::Task;
 use crate::config::{
-    path_from_command, Args, ConfigChannel, ConfigOp, ConfigRequest, DisplayRequest, ShowChannel,
+    path_from_command, show_path, Args, ConfigChannel, ConfigOp, ConfigRequest, DisplayRequest,
+    ShowChannel, StateChannel, StateProviders, StateRequest,
 };
-use crate::rib::api::{RibRxChannel, RibTx};
+use crate::exit::ExitRx;
+use crate::policy::{AsPathSet, PrefixSet};
+use crate::rib::api::{RibRx, RibRxChannel, RibTx};
 use ipnet::Ipv4Net;
 use prefix_trie::PrefixMap;
-use std::collections::{BTreeMap, HashMap};
-use std::net::{Ipv4Addr, SocketAddr};
-use tokio::net::{TcpListener, TcpStream};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender};
 
 #[derive(Debug)]
 pub enum Message {
     Event(Ipv4Addr, Event),
-    Accept(TcpStream, SocketAddr),
+    // Accepted connection, its remote address and the listener.
+    Accept(TcpStream, SocketAddr, SocketAddr),
+    Listen(SocketAddr, ListenEvent),
+    // Event of the RPKI cache session.
+    Rtr(IpAddr, RtrEvent),
     Show(Sender<String>),
 }
 
 pub type Callback = fn(&mut Bgp, Args, ConfigOp) -> Option<()>;
-pub type ShowCallback = fn(&Bgp, Args) -> String;
+pub type ShowCallback = fn(&Bgp, Args, bool) -> String;
+pub type ClearCallback = fn(&mut Bgp, Args) -> String;
 
 pub struct Bgp {
     pub asn: u32,
     pub router_id: Ipv4Addr,
+    // Configured identifier and the router ID selected by the RIB.
+    pub identifier: Option<Ipv4Addr>,
+    pub rib_router_id: Option<Ipv4Addr>,
     pub peers: BTreeMap<Ipv4Addr, Peer>,
+    pub peer_groups: PeerGroups,
+    pub max_dynamic_peers: usize,
+    pub advertise_best_external: bool,
     pub tx: UnboundedSender<Message>,
     pub rx: UnboundedReceiver<Message>,
     pub cm: ConfigChannel,
     pub show: ShowChannel,
     pub show_cb: HashMap<String, ShowCallback>,
+    pub clear_cb: HashMap<String, ClearCallback>,
+    pub state: StateChannel,
+    pub state_cb: StateProviders<Bgp>,
     pub rib: Sender<RibTx>,
     pub redist: RibRxChannel,
     pub callbacks: HashMap<String, Callback>,
     pub ptree: PrefixMap<Ipv4Net, Vec<Route>>,
-    pub listen_task: Option<Task<()>>,
-    pub listen_err: Option<anyhow::Error>,
+    // VPN-IPv4 routes received from the peers and exported by the RIB.
+    pub vpn: VpnTable,
+    // Routes exported by the RIB changed since the last export to the peers.
+    pub vpn_changed: BTreeSet<VpnOutKey>,
+    pub aspath_sets: BTreeMap<String, AsPathSet>,
+    pub prefix_sets: BTreeMap<String, PrefixSet>,
+    pub nexthops: NexthopCache,
+    pub networks: Networks,
+    pub aggregates: Aggregates,
+    // VPN routes and prefix registrations waiting to be sent to the RIB.
+    pub rib_pending: Vec<RibTx>,
+    // Messages to the RIB which did not fit in the channel, sent by the event
+    // loop as the RIB receives them.
+    pub rib_queue: VecDeque<RibTx>,
+    // Configured listen addresses and their ports.
+    pub listen_config: BTreeMap<IpAddr, u16>,
+    pub listeners: BTreeMap<SocketAddr, Listener>,
+    pub listen_enabled: bool,
+    pub listen_bind: ListenBind,
+    pub listen_retry: Duration,
+    pub rpki: Rpki,
+    // Global shutdown of all of the neighbors.
+    pub shutdown: bool,
 }
 
 impl Bgp {
@@ -46,21 +92,45 @@ impl Bgp {
         let mut bgp = Self {
             asn: 0,
             router_id: Ipv4Addr::UNSPECIFIED,
+            identifier: None,
+            rib_router_id: None,
             peers: BTreeMap::new(),
+            peer_groups: PeerGroups::new(),
+            max_dynamic_peers: MAX_DYNAMIC_PEERS,
+            advertise_best_external: false,
             tx,
             rx,
             ptree: PrefixMap::<Ipv4Net, Vec<Route>>::new(),
+            vpn: VpnTable::new(),
+            vpn_changed: BTreeSet::new(),
+            aspath_sets: BTreeMap::new(),
+            prefix_sets: BTreeMap::new(),
+            nexthops: NexthopCache::default(),
+            networks: Networks::new(),
+            aggregates: Aggregates::new(),
+            rib_pending: Vec::new(),
+            rib_queue: VecDeque::new(),
             rib,
             cm: ConfigChannel::new(),
             show: ShowChannel::new(),
             show_cb: HashMap::new(),
+            clear_cb: HashMap::new(),
+            state: StateChannel::new(),
+            state_cb: StateProviders::default(),
             redist: RibRxChannel::new(),
             callbacks: HashMap::new(),
-            listen_task: None,
-            listen_err: None,
+            listen_config: BTreeMap::new(),
+            listeners: BTreeMap::new(),
+            listen_enabled: false,
+            listen_bind,
+            listen_retry: LISTEN_RETRY_MIN,
+            rpki: Rpki::default(),
+            shutdown: false,
         };
         bgp.callback_build();
         bgp.show_build();
+        bgp.clear_build();
+        bgp.state_build();
         bgp
     }
 
@@ -74,16 +144,69 @@ impl Bgp {
                 println!("Message::Event: {:?}", event);
                 fsm(self, peer, event);
             }
-            Message::Accept(socket, sockaddr) => {
-                println!("Accept: {:?}", sockaddr);
+            Message::Accept(socket, sockaddr, listen) => {
+                println!("Accept: {:?} on {}", sockaddr, listen);
+                self.listen_accepted(&listen);
                 accept(self, socket, sockaddr);
             }
+            Message::Listen(addr, event) => {
+                self.listen_event(addr, event);
+            }
+            Message::Rtr(addr, event) => {
+                self.rpki_event(addr, event);
+            }
             Message::Show(tx) => {
                 self.tx.send(Message::Show(tx)).unwrap();
             }
         }
     }
 
+    fn process_rib_msg(&mut self, msg: RibRx) {
+        match msg {
+            RibRx::Nexthop(update) => {
+                let addr = update.addr;
+                if self.nexthops.update(update) {
+                    route_nexthop_update(&mut self.ptree, &mut self.nexthops, Some(addr));
+                }
+            }
+            RibRx::Prefix(update) => {
+                self.prefix_update(update);
+            }
+            RibRx::RouterId(router_id) => {
+                self.router_id_rib_update(router_id);
+            }
+            RibRx::VpnRouteAdd(route) => {
+                self.vpn_rib_update(route, true);
+            }
+            RibRx::VpnRouteDel(route) => {
+                self.vpn_rib_update(route, false);
+            }
+            _ => {}
+        }
+    }
+
+    // Send nexthop and prefix register/unregister and VPN routes requested
+    // while processing messages. The send does not wait for the RIB, so that
+    // BGP keeps receiving the notifications of the RIB, which may be
+    // processing the earlier messages. The rest is queued in order.
+    fn rib_flush(&mut self) {
+        self.rib_queue.extend(self.nexthops.pending.drain(..));
+        self.rib_queue.extend(self.rib_pending.drain(..));
+        while let Some(msg) = self.rib_queue.pop_front() {
+            match self.rib.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(msg)) => {
+                    self.rib_queue.push_front(msg);
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    self.rib_queue.clear();
+                    break;
+                }
+            }
+        }
+    }
+
     pub fn process_cm_msg(&mut self, msg: ConfigRequest) {
         let (path, args) = path_from_command(&msg.paths);
         if let Some(f) = self.callbacks.get(&path) {
@@ -91,50 +214,131 @@ impl Bgp {
         }
     }
 
-    async fn process_show_msg(&self, msg: DisplayRequest) {
+    // Clear commands are redirected to the protocol the same way as the show
+    // commands.
+    async fn process_show_msg(&mut self, msg: DisplayRequest) {
         let (path, args) = path_from_command(&msg.paths);
-        if let Some(f) = self.show_cb.get(&path) {
+        if let Some(f) = self.clear_cb.get(&path).copied() {
             let output = f(self, args);
             msg.resp.send(output).await.unwrap();
+            return;
+        }
+        let (path, json) = show_path(path);
+        if let Some(f) = self.show_cb.get(&path) {
+            let output = f(self, args, json);
+            msg.resp.send(output).await.unwrap();
         }
     }
 
-    pub async fn listen(&mut self) -> anyhow::Result<()> {
-        let listener = TcpListener::bind("0.0.0.0:179").await?;
-        let tx = self.tx.clone();
-
-        let listen_task = Task::spawn(async move {
-            loop {
-                let (socket, sockaddr) = listener.accept().await.unwrap();
-                tx.send(Message::Accept(socket, sockaddr)).unwrap();
-            }
-        });
-        self.listen_task = Some(listen_task);
-        Ok(())
+    fn process_state_msg(&self, msg: StateRequest) {
+        let _ = msg.resp.send(self.state_cb.get(self, &msg.path));
     }
 
-    pub async fn event_loop(&mut self) {
-        if let Err(err) = self.listen().await {
-            self.listen_err = Some(err);
-        }
+    pub async fn event_loop(&mut self, mut exit: ExitRx) {
+        self.listen_start();
         loop {
             tokio::select! {
                 Some(msg) = self.rx.recv() => {
                     self.process_msg(msg);
+                    self.aggregate_update();
+                    self.adj_rib_update();
+                    self.rib_flush();
                 }
                 Some(msg) = self.cm.rx.recv() => {
                     self.process_cm_msg(msg);
+                    self.aggregate_update();
+                    self.adj_rib_update();
+                    self.rib_flush();
+                }
+                Some(msg) = self.redist.rx.recv() => {
+                    self.process_rib_msg(msg);
+                    self.aggregate_update();
+                    self.adj_rib_update();
+                    self.rib_flush();
                 }
                 Some(msg) = self.show.rx.recv() => {
-            self.process_show_msg(msg).await;
+                    self.process_show_msg(msg).await;
+                    self.aggregate_update();
+                    self.adj_rib_update();
+                    self.rib_flush();
+                }
+                Ok(permit) = self.rib.reserve(), if !self.rib_queue.is_empty() => {
+                    if let Some(msg) = self.rib_queue.pop_front() {
+                        permit.send(msg);
+                    }
+                    self.rib_flush();
+                }
+                Some(msg) = self.state.rx.recv() => {
+                    self.process_state_msg(msg);
+                }
+                Some(done) = exit.recv() => {
+                    self.exit();
+                    let _ = done.send(());
+                    return;
                 }
             }
         }
     }
 }
 
-pub fn serve(mut bgp: Bgp) {
+pub fn serve(mut bgp: Bgp, exit: ExitRx) {
     tokio::spawn(async move {
-        bgp.event_loop().await;
+        bgp.event_loop(exit).await;
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::api::{rib_notify, NexthopUpdate, RibTxChannel};
+
+    #[tokio::test]
+    async fn rib_register_burst() {
+        let rib = RibTxChannel::new();
+        let mut bgp = Bgp::new(rib.tx.clone());
+        let count = 20;
+
+        // RIB answers each registration right away as the nexthop tracking
+        // does, while BGP does not receive the notifications.
+        let redists = vec![bgp.redist.tx.clone()];
+        let mut rx = rib.rx;
+        let task = tokio::spawn(async move {
+            let mut registered = 0;
+            while registered < count {
+                let Some(RibTx::NexthopRegister(addr)) = rx.recv().await else {
+                    break;
+                };
+                let update = NexthopUpdate {
+                    addr,
+                    resolved: None,
+                    metric: 0,
+                };
+                rib_notify(&redists, RibRx::Nexthop(update));
+                registered += 1;
+            }
+            registered
+        });
+
+        for i in 0..count {
+            let addr = Ipv4Addr::from(0x0a000001 + i);
+            bgp.nexthops.pending.push(RibTx::NexthopRegister(addr));
+        }
+        bgp.rib_flush();
+        assert!(!bgp.rib_queue.is_empty());
+
+        // Rest of the registrations are sent as the channel has room.
+        while !bgp.rib_queue.is_empty() {
+            let permit = bgp.rib.reserve().await.unwrap();
+            permit.send(bgp.rib_queue.pop_front().unwrap());
+            bgp.rib_flush();
+        }
+        assert_eq!(task.await.unwrap(), count);
+
+        let mut notified = Vec::new();
+        while let Ok(RibRx::Nexthop(update)) = bgp.redist.rx.try_recv() {
+            notified.push(update.addr);
+        }
+        let sent: Vec<Ipv4Addr> = (0..count).map(|i| Ipv4Addr::from(0x0a000001 + i)).collect();
+        assert_eq!(notified, sent);
+    }
+}
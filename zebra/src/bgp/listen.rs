@@ -0,0 +1,437 @@
+use super::auth::{tcp_auth_clear, tcp_auth_set};
+use super::handler::{Bgp, Message};
+use super::task::Task;
+use super::ttl::{SocketFd, TtlSocket};
+use super::BGP_PORT;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedSender;
+
+// Listening sockets. A listener task is spawned for each configured address
+// and port, and the accepted connections are sent to the event loop together
+// with the address of the listener. Without configuration, connections are
+// accepted on the wildcard addresses of IPv4 and IPv6. A listener which fails
+// to bind, e.g. because the address is not assigned to an interface yet,
+// retries with backoff, so that the address is picked up once it appears.
+
+pub const LISTEN_RETRY_MIN: Duration = Duration::from_secs(1);
+pub const LISTEN_RETRY_MAX: Duration = Duration::from_secs(60);
+
+pub type ListenBind = fn(SocketAddr) -> io::Result<TcpListener>;
+
+#[derive(Debug)]
+pub enum ListenEvent {
+    // Bound socket and its local address.
+    Bound(RawFd, SocketAddr),
+    // Bind error and the time until the next attempt.
+    Failed(String, Duration),
+}
+
+#[derive(Debug, Default)]
+pub struct Listener {
+    pub task: Option<Task<()>>,
+    pub fd: Option<RawFd>,
+    pub accepted: u64,
+    pub bind_failures: u64,
+    pub error: Option<String>,
+}
+
+pub fn listen_bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // IPv4 connections are accepted by the IPv4 listener.
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+fn listen_task(
+    addr: SocketAddr,
+    tx: UnboundedSender<Message>,
+    bind: ListenBind,
+    retry: Duration,
+) -> Task<()> {
+    Task::spawn(async move {
+        let mut backoff = retry;
+        let listener = loop {
+            match bind(addr) {
+                Ok(listener) => break listener,
+                Err(err) => {
+                    let event = ListenEvent::Failed(err.to_string(), backoff);
+                    let _ = tx.send(Message::Listen(addr, event));
+                    tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, LISTEN_RETRY_MAX);
+                }
+            }
+        };
+        let Ok(local) = listener.local_addr() else {
+            return;
+        };
+        let event = ListenEvent::Bound(listener.as_raw_fd(), local);
+        let _ = tx.send(Message::Listen(addr, event));
+        loop {
+            match listener.accept().await {
+                Ok((socket, sockaddr)) => {
+                    let _ = tx.send(Message::Accept(socket, sockaddr, addr));
+                }
+                Err(err) => {
+                    println!("BGP accept on {}: {}", addr, err);
+                }
+            }
+        }
+    })
+}
+
+// Address and port of the listeners.
+pub fn listen_addrs(config: &BTreeMap<IpAddr, u16>) -> Vec<SocketAddr> {
+    if config.is_empty() {
+        return vec![
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), BGP_PORT),
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), BGP_PORT),
+        ];
+    }
+    config
+        .iter()
+        .map(|(addr, port)| SocketAddr::new(*addr, *port))
+        .collect()
+}
+
+impl Bgp {
+    pub fn listen_start(&mut self) {
+        self.listen_enabled = true;
+        self.listen_update();
+    }
+
+    // Start the listeners which have been added to the config and stop the
+    // ones which have been removed. Stopping a listener closes the socket but
+    // does not affect the sessions accepted on it.
+    pub fn listen_update(&mut self) {
+        if !self.listen_enabled {
+            return;
+        }
+        let addrs = listen_addrs(&self.listen_config);
+        self.listeners.retain(|addr, _| addrs.contains(addr));
+        for addr in addrs.into_iter() {
+            if self.listeners.contains_key(&addr) {
+                continue;
+            }
+            let task = listen_task(addr, self.tx.clone(), self.listen_bind, self.listen_retry);
+            let listener = Listener {
+                task: Some(task),
+                ..Default::default()
+            };
+            self.listeners.insert(addr, listener);
+        }
+    }
+
+    pub fn listen_event(&mut self, addr: SocketAddr, event: ListenEvent) {
+        let Some(listener) = self.listeners.get_mut(&addr) else {
+            return;
+        };
+        match event {
+            ListenEvent::Bound(fd, _) => {
+                listener.fd = Some(fd);
+                listener.error = None;
+                let peers: Vec<Ipv4Addr> = self.peers.keys().cloned().collect();
+                for peer in peers.iter() {
+                    self.listen_auth_update(peer);
+                }
+                self.listen_ttl_update();
+            }
+            ListenEvent::Failed(err, retry) => {
+                println!("BGP listen on {}: {}, retry in {:?}", addr, err, retry);
+                listener.fd = None;
+                listener.bind_failures += 1;
+                listener.error = Some(err);
+            }
+        }
+    }
+
+    pub fn listen_accepted(&mut self, addr: &SocketAddr) {
+        if let Some(listener) = self.listeners.get_mut(addr) {
+            listener.accepted += 1;
+        }
+    }
+
+    // Install or remove the peer's TCP authentication key on the IPv4
+    // listening sockets so that passive connections from the peer are signed
+    // as well.
+    pub fn listen_auth_update(&self, addr: &Ipv4Addr) {
+        let Some(peer) = self.peers.get(addr) else {
+            return;
+        };
+        let auth = peer.auth();
+        for (listen, listener) in self.listeners.iter() {
+            let Some(fd) = listener.fd.filter(|_| listen.is_ipv4()) else {
+                continue;
+            };
+            let result = match auth.as_ref() {
+                Some(auth) => tcp_auth_set(fd, IpAddr::V4(*addr), auth),
+                None => tcp_auth_clear(fd, IpAddr::V4(*addr)),
+            };
+            if let Err(err) = result {
+                println!("TCP auth for {} on listen socket {}: {}", addr, listen, err);
+            }
+        }
+    }
+
+    // Minimum TTL of the listening sockets, RFC 5082. A listener is shared by
+    // all of the neighbors, so the least strict minimum TTL of them is used,
+    // and none when a neighbor without ttl-security or a dynamic neighbor may
+    // connect. In that case the minimum TTL set on the accepted socket is
+    // checked from the packets after the handshake.
+    pub fn listen_min_ttl(&self) -> Option<u8> {
+        if self
+            .peer_groups
+            .values()
+            .any(|group| !group.ranges.is_empty())
+        {
+            return None;
+        }
+        let mut min_ttl: Option<u8> = None;
+        for peer in self.peers.values().filter(|peer| peer.dynamic.is_none()) {
+            let ttl = peer.ttl_mode().min_ttl()?;
+            min_ttl = Some(min_ttl.map_or(ttl, |min| min.min(ttl)));
+        }
+        min_ttl
+    }
+
+    // Apply the minimum TTL to the IPv4 listening sockets so that the SYN
+    // from beyond the hop limit is dropped by the kernel.
+    pub fn listen_ttl_update(&self) {
+        let min_ttl = self.listen_min_ttl().unwrap_or(0);
+        for (listen, listener) in self.listeners.iter() {
+            let Some(fd) = listener.fd.filter(|_| listen.is_ipv4()) else {
+                continue;
+            };
+            let fd = SocketFd { fd, ipv6: false };
+            if let Err(err) = fd.set_min_ttl(min_ttl) {
+                // Nothing to clear where the option is not supported.
+                if min_ttl > 0 {
+                    println!("Minimum TTL on listen socket {}: {}", listen, err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::peer::Peer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+
+    fn bgp() -> Bgp {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.listen_retry = Duration::from_millis(1);
+        bgp
+    }
+
+    // Every listener is bound to an ephemeral port of the loopback address.
+    fn loopback(_addr: SocketAddr) -> io::Result<TcpListener> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        TcpListener::from_std(listener)
+    }
+
+    #[test]
+    fn addrs() {
+        let mut config = BTreeMap::new();
+        let addrs = listen_addrs(&config);
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[0], "0.0.0.0:179".parse().unwrap());
+        assert_eq!(addrs[1], "[::]:179".parse().unwrap());
+
+        config.insert("10.0.0.1".parse().unwrap(), 179);
+        config.insert("2001:db8::1".parse().unwrap(), 1179);
+        let addrs = listen_addrs(&config);
+        assert_eq!(
+            addrs,
+            vec![
+                "10.0.0.1:179".parse().unwrap(),
+                "[2001:db8::1]:1179".parse().unwrap()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch() {
+        let mut bgp = bgp();
+        bgp.listen_bind = loopback;
+        let first: SocketAddr = "10.0.0.1:179".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:179".parse().unwrap();
+        bgp.listen_config.insert(first.ip(), first.port());
+        bgp.listen_config.insert(second.ip(), second.port());
+        bgp.listen_start();
+        assert_eq!(bgp.listeners.len(), 2);
+
+        let mut locals = Vec::new();
+        while locals.len() < 2 {
+            let msg = bgp.rx.recv().await.unwrap();
+            if let Message::Listen(_, ListenEvent::Bound(_, local)) = &msg {
+                locals.push(*local);
+            }
+            bgp.process_msg(msg);
+        }
+
+        // Connections on both listeners are fed to the same event loop.
+        let mut streams = Vec::new();
+        for local in locals.iter() {
+            streams.push(TcpStream::connect(local).await.unwrap());
+        }
+        let mut from = Vec::new();
+        while from.len() < 2 {
+            let msg = bgp.rx.recv().await.unwrap();
+            if let Message::Accept(_, _, listen) = &msg {
+                from.push(*listen);
+            }
+            bgp.process_msg(msg);
+        }
+        from.sort();
+        assert_eq!(from, vec![first, second]);
+        assert_eq!(bgp.listeners[&first].accepted, 1);
+        assert_eq!(bgp.listeners[&second].accepted, 1);
+
+        // Removed listener is stopped.
+        bgp.listen_config.remove(&second.ip());
+        bgp.listen_update();
+        assert!(bgp.listeners.contains_key(&first));
+        assert!(!bgp.listeners.contains_key(&second));
+    }
+
+    #[tokio::test]
+    async fn retry() {
+        static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+        // Address is not available for the first two attempts.
+        fn unavailable(addr: SocketAddr) -> io::Result<TcpListener> {
+            if ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+                return Err(io::Error::from(io::ErrorKind::AddrNotAvailable));
+            }
+            loopback(addr)
+        }
+
+        let mut bgp = bgp();
+        bgp.listen_bind = unavailable;
+        let addr: SocketAddr = "10.0.0.1:179".parse().unwrap();
+        bgp.listen_config.insert(addr.ip(), addr.port());
+        bgp.listen_start();
+
+        let mut retries = Vec::new();
+        loop {
+            let msg = bgp.rx.recv().await.unwrap();
+            let bound = matches!(msg, Message::Listen(_, ListenEvent::Bound(..)));
+            if let Message::Listen(_, ListenEvent::Failed(_, retry)) = &msg {
+                retries.push(*retry);
+            }
+            bgp.process_msg(msg);
+            if bound {
+                break;
+            }
+            assert!(bgp.listeners[&addr].fd.is_none());
+        }
+        // Backoff is doubled on each failure.
+        assert_eq!(
+            retries,
+            vec![Duration::from_millis(1), Duration::from_millis(2)]
+        );
+        let listener = &bgp.listeners[&addr];
+        assert!(listener.fd.is_some());
+        assert_eq!(listener.bind_failures, 2);
+        assert_eq!(listener.error, None);
+    }
+
+    #[test]
+    fn min_ttl() {
+        let mut bgp = bgp();
+        assert_eq!(bgp.listen_min_ttl(), None);
+        let mut peer = |addr: &str, hops: Option<u8>| {
+            let addr: Ipv4Addr = addr.parse().unwrap();
+            let mut peer = Peer::new(addr, 65000, addr, 65001, addr, bgp.tx.clone());
+            peer.config.transport.ttl.set_ttl_security(hops).unwrap();
+            bgp.peers.insert(addr, peer);
+        };
+        peer("10.0.0.1", Some(1));
+        peer("10.0.0.2", Some(3));
+        assert_eq!(bgp.listen_min_ttl(), Some(253));
+
+        // Neighbor without ttl-security.
+        let other: Ipv4Addr = "10.0.0.2".parse().unwrap();
+        let ttl = &mut bgp.peers.get_mut(&other).unwrap().config.transport.ttl;
+        ttl.set_ttl_security(None).unwrap();
+        assert_eq!(bgp.listen_min_ttl(), None);
+        bgp.peers.remove(&other);
+        assert_eq!(bgp.listen_min_ttl(), Some(255));
+
+        // Dynamic neighbor may connect from the range.
+        let group = bgp.peer_groups.entry(String::from("dynamic")).or_default();
+        group.ranges.insert("192.168.0.0/16".parse().unwrap());
+        assert_eq!(bgp.listen_min_ttl(), None);
+    }
+
+    // Connection from beyond the hop limit is dropped by the kernel before it
+    // is accepted by the listener task.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn min_ttl_accept() {
+        let mut bgp = bgp();
+        bgp.listen_bind = loopback;
+        let addr: Ipv4Addr = "127.0.0.1".parse().unwrap();
+        let mut peer = Peer::new(addr, 65000, addr, 65001, addr, bgp.tx.clone());
+        peer.config.transport.ttl.set_ttl_security(Some(3)).unwrap();
+        bgp.peers.insert(addr, peer);
+        bgp.listen_config.insert(IpAddr::V4(addr), BGP_PORT);
+        bgp.listen_start();
+
+        let local = loop {
+            let msg = bgp.rx.recv().await.unwrap();
+            let bound = match &msg {
+                Message::Listen(_, ListenEvent::Bound(_, local)) => Some(*local),
+                _ => None,
+            };
+            bgp.process_msg(msg);
+            if let Some(local) = bound {
+                break local;
+            }
+        };
+
+        // TTL is not decremented over the loopback, the peer is 256 - TTL
+        // hops away.
+        let connect = |ttl: u32| {
+            tokio::task::spawn_blocking(move || {
+                let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+                socket.set_ttl(ttl).unwrap();
+                socket
+                    .connect_timeout(&local.into(), Duration::from_millis(500))
+                    .map(|_| socket)
+            })
+        };
+        assert!(connect(252).await.unwrap().is_err());
+        let _socket = connect(253).await.unwrap().unwrap();
+        loop {
+            let msg = bgp.rx.recv().await.unwrap();
+            if let Message::Accept(_, sockaddr, _) = &msg {
+                assert_eq!(sockaddr.ip(), IpAddr::V4(addr));
+                break;
+            }
+        }
+
+        // Neighbor without ttl-security lifts the limit.
+        let ttl = &mut bgp.peers.get_mut(&addr).unwrap().config.transport.ttl;
+        ttl.set_ttl_security(None).unwrap();
+        bgp.listen_ttl_update();
+        assert!(connect(252).await.unwrap().is_ok());
+    }
+}
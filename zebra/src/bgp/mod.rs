@@ -7,11 +7,29 @@ pub use afi::*;
 pub mod constant;
 pub use constant::*;
 
+pub mod adj_rib;
+pub mod auth;
+pub mod clear;
 pub mod config;
+pub mod listen;
+pub mod multipath;
+pub mod network;
+pub mod nexthop;
 pub mod packet;
 pub mod peer;
+pub mod peer_group;
+pub mod ratelimit;
+pub mod role;
 pub mod route;
+pub mod router_id;
+pub mod rpki;
+pub mod rtr;
 pub mod show;
+pub mod shutdown;
 pub mod task;
+pub mod trace;
+pub mod transform;
+pub mod ttl;
+pub mod vpn;
 
 pub mod mrt;
@@ -0,0 +1,344 @@
+use super::adj_rib::LOCAL_PREF_DEFAULT;
+use super::nexthop::NexthopCache;
+use super::packet::{Attribute, Attrs, AS_SEQUENCE};
+use super::route::{route_bandwidth, Route, RouteFrom};
+use crate::rib::api::RibTx;
+use crate::rib::entry::{RibEntry, RibType};
+use crate::rib::nexthop::Nexthop;
+use ipnet::Ipv4Net;
+use std::net::IpAddr;
+
+// BGP multipath. The routes equal to the selected one in the LOCAL_PREF of
+// iBGP, the AS path length, the origin and the MED from the same neighboring
+// AS are installed to the RIB together with it as an ECMP route, up to the
+// maximum paths of eBGP or iBGP. With allow-multiple-as the eBGP routes from the different
+// neighboring AS are also used, and their MED is not compared. Only the
+// selected route is advertised to the peers.
+
+pub const EBGP_DISTANCE: u32 = 20;
+pub const IBGP_DISTANCE: u32 = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Multipath {
+    pub ebgp: u32,
+    pub ibgp: u32,
+    pub allow_multiple_as: bool,
+}
+
+impl Default for Multipath {
+    fn default() -> Self {
+        Self {
+            ebgp: 1,
+            ibgp: 1,
+            allow_multiple_as: false,
+        }
+    }
+}
+
+impl Multipath {
+    pub fn maximum_paths(&self, ibgp: bool) -> usize {
+        let paths = if ibgp { self.ibgp } else { self.ebgp };
+        paths.max(1) as usize
+    }
+}
+
+// Attributes compared for the route selection and the multipath.
+#[derive(Debug, Default, PartialEq)]
+pub struct PathKey {
+    pub local_pref: u32,
+    pub length: usize,
+    pub origin: u8,
+    pub neighbor_as: u32,
+    pub med: u32,
+}
+
+pub fn path_key(attrs: &Attrs) -> PathKey {
+    let mut key = PathKey {
+        local_pref: LOCAL_PREF_DEFAULT,
+        ..Default::default()
+    };
+    for attr in attrs.iter() {
+        match attr {
+            Attribute::LocalPref(local_pref) => key.local_pref = local_pref.local_pref,
+            Attribute::As4Path(as_path) => {
+                key.length = as_path.length();
+                key.neighbor_as = as_path
+                    .segments
+                    .first()
+                    .filter(|seg| seg.typ == AS_SEQUENCE)
+                    .and_then(|seg| seg.asn.first().cloned())
+                    .unwrap_or(0);
+            }
+            Attribute::Origin(origin) => key.origin = origin.origin,
+            Attribute::Med(med) => key.med = med.med,
+            _ => {}
+        }
+    }
+    key
+}
+
+pub fn multipath_eligible(best: &Route, route: &Route, allow_multiple_as: bool) -> bool {
+    if route.kind != RouteFrom::Peer || route.ibgp != best.ibgp {
+        return false;
+    }
+    let (a, b) = (path_key(&best.attrs), path_key(&route.attrs));
+    if best.ibgp && a.local_pref != b.local_pref {
+        return false;
+    }
+    if a.length != b.length || a.origin != b.origin {
+        return false;
+    }
+    if a.neighbor_as != b.neighbor_as {
+        return allow_multiple_as && !best.ibgp;
+    }
+    a.med == b.med
+}
+
+// Mark the routes used together with the selected one. Routes of the same
+// nexthop are counted once. Returns true when the multipath has changed.
+pub fn multipath_select(routes: &mut [Route], nexthops: &NexthopCache) -> bool {
+    let mut paths: Vec<usize> = Vec::new();
+    let best = routes
+        .iter()
+        .position(|route| route.selected && route.kind == RouteFrom::Peer);
+    if let Some(best) = best.filter(|i| routes[*i].nexthop.is_some()) {
+        let config = &nexthops.multipath;
+        let max = config.maximum_paths(routes[best].ibgp);
+        let mut addrs = vec![routes[best].nexthop];
+        for (i, route) in routes.iter().enumerate() {
+            if paths.len() + 1 >= max {
+                break;
+            }
+            if i == best
+                || route.nexthop.is_none()
+                || addrs.contains(&route.nexthop)
+                || !route.valid(nexthops)
+            {
+                continue;
+            }
+            if multipath_eligible(&routes[best], route, config.allow_multiple_as) {
+                paths.push(i);
+                addrs.push(route.nexthop);
+            }
+        }
+    }
+    let mut changed = false;
+    for (i, route) in routes.iter_mut().enumerate() {
+        let multipath = paths.contains(&i);
+        changed |= route.multipath != multipath;
+        route.multipath = multipath;
+    }
+    changed
+}
+
+// RIB entry of the selected route with the nexthops of the multipath. None
+// when the selected route is not installed, e.g. the local routes.
+pub fn multipath_entry(routes: &[Route]) -> Option<RibEntry> {
+    let best = routes
+        .iter()
+        .find(|route| route.selected && route.kind == RouteFrom::Peer)?;
+    let mut e = RibEntry::new(RibType::BGP);
+    e.distance = if best.ibgp {
+        IBGP_DISTANCE
+    } else {
+        EBGP_DISTANCE
+    };
+    e.metric = path_key(&best.attrs).med;
+    e.gateway = IpAddr::V4(best.nexthop?);
+    let multipath = routes.iter().filter(|route| route.multipath);
+    for route in std::iter::once(best).chain(multipath) {
+        let mut nhop = Nexthop::new(route.nexthop?);
+        nhop.bandwidth = route_bandwidth(&route.attrs);
+        e.nexthops.push(nhop);
+    }
+    Some(e)
+}
+
+// RIB update of the prefix. The entry replaces the installed one in place.
+pub fn multipath_install(prefix: Ipv4Net, routes: &[Route]) -> RibTx {
+    match multipath_entry(routes) {
+        Some(e) => RibTx::RouteAdd(prefix, e),
+        None => RibTx::RouteDel(prefix, RibEntry::new(RibType::BGP)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{
+        As4PathAttr, As4Segment, MedAttr, NextHopAttr, OriginAttr, ORIGIN_IGP,
+    };
+    use crate::bgp::route::{route_aspath, route_nexthop, route_select};
+    use crate::rib::api::NexthopUpdate;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+
+    fn route(from: &str, nexthop: &str, asn: &[u32], med: u32) -> Route {
+        let attrs = vec![
+            Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: asn.to_vec(),
+                }],
+            }),
+            Attribute::NextHop(NextHopAttr {
+                next_hop: nexthop.parse::<Ipv4Addr>().unwrap().octets(),
+            }),
+            Attribute::Med(MedAttr { med }),
+        ];
+        Route {
+            from: from.parse().unwrap(),
+            router_id: from.parse().unwrap(),
+            kind: RouteFrom::Peer,
+            aspath: route_aspath(&attrs),
+            nexthop: route_nexthop(&attrs),
+            attrs: Arc::new(attrs),
+            ibgp: false,
+            selected: false,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        }
+    }
+
+    fn nexthops(addrs: &[&str]) -> NexthopCache {
+        let mut nexthops = NexthopCache::default();
+        for addr in addrs.iter() {
+            let addr: Ipv4Addr = addr.parse().unwrap();
+            nexthops.lock(addr);
+            nexthops.update(NexthopUpdate {
+                addr,
+                resolved: "10.0.0.0/8".parse().ok(),
+                metric: 0,
+            });
+        }
+        nexthops.pending.clear();
+        nexthops
+    }
+
+    fn installed(routes: &[Route]) -> Vec<String> {
+        multipath_entry(routes)
+            .map(|e| e.nexthops.iter().map(|n| n.nexthop.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn eligibility() {
+        let best = route("1.1.1.1", "10.0.0.1", &[65001, 65100], 10);
+        assert!(multipath_eligible(
+            &best,
+            &route("2.2.2.2", "10.0.0.2", &[65001, 65100], 10),
+            false
+        ));
+        // Different MED from the same neighboring AS.
+        let med = route("2.2.2.2", "10.0.0.2", &[65001, 65100], 20);
+        assert!(!multipath_eligible(&best, &med, false));
+        assert!(!multipath_eligible(&best, &med, true));
+        // Different AS path length.
+        let longer = route("2.2.2.2", "10.0.0.2", &[65001, 65001, 65100], 10);
+        assert!(!multipath_eligible(&best, &longer, true));
+        // Different origin.
+        let mut origin = route("2.2.2.2", "10.0.0.2", &[65001, 65100], 10);
+        let mut attrs = (*origin.attrs).clone();
+        attrs[0] = Attribute::Origin(OriginAttr { origin: 2 });
+        origin.attrs = Arc::new(attrs);
+        assert!(!multipath_eligible(&best, &origin, true));
+        // iBGP route is not mixed with the eBGP one.
+        let mut ibgp = route("2.2.2.2", "10.0.0.2", &[65001, 65100], 10);
+        ibgp.ibgp = true;
+        assert!(!multipath_eligible(&best, &ibgp, true));
+    }
+
+    #[test]
+    fn allow_multiple_as() {
+        let best = route("1.1.1.1", "10.0.0.1", &[65001, 65100], 10);
+        // Different neighboring AS, MED is not compared.
+        let other = route("2.2.2.2", "10.0.0.2", &[65002, 65100], 20);
+        assert!(!multipath_eligible(&best, &other, false));
+        assert!(multipath_eligible(&best, &other, true));
+
+        // Not for iBGP.
+        let mut best = best;
+        let mut other = other;
+        best.ibgp = true;
+        other.ibgp = true;
+        assert!(!multipath_eligible(&best, &other, true));
+    }
+
+    #[test]
+    fn maximum_paths() {
+        let mut nexthops = nexthops(&["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4"]);
+        let mut routes = vec![
+            route("1.1.1.1", "10.0.0.1", &[65001], 0),
+            route("2.2.2.2", "10.0.0.2", &[65001], 0),
+            route("3.3.3.3", "10.0.0.2", &[65001], 0),
+            route("4.4.4.4", "10.0.0.3", &[65001], 0),
+            route("5.5.5.5", "10.0.0.4", &[65002], 0),
+        ];
+        assert!(route_select(&mut routes, &nexthops));
+        assert_eq!(installed(&routes), vec!["10.0.0.1"]);
+
+        // Same nexthop is counted once.
+        nexthops.multipath.ebgp = 4;
+        assert!(route_select(&mut routes, &nexthops));
+        assert_eq!(installed(&routes), vec!["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+        assert!(!routes[2].multipath);
+        assert!(!route_select(&mut routes, &nexthops));
+
+        nexthops.multipath.allow_multiple_as = true;
+        assert!(route_select(&mut routes, &nexthops));
+        assert_eq!(
+            installed(&routes),
+            vec!["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4"]
+        );
+
+        nexthops.multipath.ebgp = 2;
+        assert!(route_select(&mut routes, &nexthops));
+        assert_eq!(installed(&routes), vec!["10.0.0.1", "10.0.0.2"]);
+
+        // Only the selected route is advertised.
+        assert_eq!(routes.iter().filter(|route| route.selected).count(), 1);
+    }
+
+    #[test]
+    fn install_in_place() {
+        let mut nexthops = nexthops(&["10.0.0.1", "10.0.0.2"]);
+        nexthops.multipath.ebgp = 2;
+        let prefix: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+        let mut routes = vec![
+            route("1.1.1.1", "10.0.0.1", &[65001], 0),
+            route("2.2.2.2", "10.0.0.2", &[65001], 0),
+        ];
+        route_select(&mut routes, &nexthops);
+        let RibTx::RouteAdd(_, e) = multipath_install(prefix, &routes) else {
+            panic!("not installed");
+        };
+        assert_eq!(e.distance, EBGP_DISTANCE);
+        assert_eq!(e.nexthops.len(), 2);
+
+        // Second path goes away, the entry is replaced.
+        nexthops.update(NexthopUpdate {
+            addr: "10.0.0.2".parse().unwrap(),
+            resolved: None,
+            metric: 0,
+        });
+        assert!(route_select(&mut routes, &nexthops));
+        let RibTx::RouteAdd(_, e) = multipath_install(prefix, &routes) else {
+            panic!("not installed");
+        };
+        assert_eq!(e.nexthops.len(), 1);
+
+        // Nothing is selected.
+        for route in routes.iter_mut() {
+            route.nexthop = Some("10.0.0.9".parse().unwrap());
+        }
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(matches!(
+            multipath_install(prefix, &routes),
+            RibTx::RouteDel(_, _)
+        ));
+    }
+}
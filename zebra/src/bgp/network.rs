@@ -0,0 +1,492 @@
+use super::handler::Bgp;
+use super::nexthop::NexthopCache;
+use super::packet::{
+    aspath_aggregate, Aggregator4Attr, As4PathAttr, AtomicAggregateAttr, Attribute, Attrs,
+    OriginAttr, ORIGIN_IGP,
+};
+use super::route::{route_aspath, route_update, Route, RouteFrom};
+use crate::rib::api::PrefixUpdate;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Locally originated routes. A network statement originates the prefix while
+// a matching route exists in the RIB. An aggregate is originated while the
+// Loc-RIB has selected routes more specific than the aggregate. Local routes
+// are placed before the received ones so that they are preferred.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkMatch {
+    // The RIB has the route of the prefix.
+    #[default]
+    Exact,
+    // The RIB has the route of the prefix or a less specific one other than
+    // the default route.
+    Covering,
+}
+
+impl FromStr for NetworkMatch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "covering" => Ok(Self::Covering),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Network {
+    pub mode: NetworkMatch,
+    // Longest RIB route covering the prefix, notified by the RIB.
+    pub covering: Option<Ipv4Net>,
+}
+
+impl Network {
+    pub fn active(&self, prefix: &Ipv4Net) -> bool {
+        match self.covering {
+            None => false,
+            Some(covering) if covering == *prefix => true,
+            Some(covering) => self.mode == NetworkMatch::Covering && covering.prefix_len() > 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Aggregate {
+    // Routes under the aggregate are not advertised.
+    pub summary_only: bool,
+    pub as_set: bool,
+}
+
+pub type Networks = BTreeMap<Ipv4Net, Network>;
+pub type Aggregates = BTreeMap<Ipv4Net, Aggregate>;
+
+fn network_attrs() -> Attrs {
+    vec![
+        Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+        Attribute::As4Path(As4PathAttr {
+            segments: Vec::new(),
+        }),
+    ]
+}
+
+// Replace the local route of the kind with a route of the attributes, or
+// remove it when `attrs` is None.
+pub fn local_route_update(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    prefix: Ipv4Net,
+    kind: RouteFrom,
+    attrs: Option<Attrs>,
+) {
+    if let Some(routes) = ptree.get_mut(&prefix) {
+        routes.retain(|route| route.kind != kind);
+    }
+    if let Some(attrs) = attrs {
+        let route = Route {
+            from: Ipv4Addr::UNSPECIFIED,
+            router_id: Ipv4Addr::UNSPECIFIED,
+            kind,
+            aspath: route_aspath(&attrs),
+            attrs: Arc::new(attrs),
+            nexthop: None,
+            ibgp: false,
+            selected: false,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        };
+        let routes = ptree.entry(prefix).or_default();
+        let pos = routes
+            .iter()
+            .position(|route| route.kind == RouteFrom::Peer)
+            .unwrap_or(routes.len());
+        routes.insert(pos, route);
+    }
+    match ptree.get_mut(&prefix) {
+        Some(routes) if routes.is_empty() => {
+            ptree.remove(&prefix);
+            nexthops.changed.insert(prefix);
+        }
+        Some(routes) => {
+            route_update(prefix, routes, nexthops);
+        }
+        None => {}
+    }
+}
+
+// Attributes of the aggregate built from the selected routes under it, RFC
+// 4271 9.2.2.2. None when there is no contributor.
+fn aggregate_attrs(
+    ptree: &PrefixMap<Ipv4Net, Vec<Route>>,
+    prefix: &Ipv4Net,
+    aggregate: &Aggregate,
+    asn: u32,
+    router_id: Ipv4Addr,
+) -> Option<Attrs> {
+    let mut origin = ORIGIN_IGP;
+    let mut atomic = false;
+    let mut paths = Vec::new();
+    for (p, routes) in ptree.children(prefix) {
+        if p.prefix_len() <= prefix.prefix_len() {
+            continue;
+        }
+        let Some(route) = routes.iter().find(|route| route.selected) else {
+            continue;
+        };
+        let mut path = As4PathAttr {
+            segments: Vec::new(),
+        };
+        for attr in route.attrs.iter() {
+            match attr {
+                Attribute::Origin(v) => origin = origin.max(v.origin),
+                Attribute::As4Path(v) => path = v.clone(),
+                Attribute::AtomicAggregate(_) => atomic = true,
+                _ => {}
+            }
+        }
+        paths.push(path);
+    }
+    if paths.is_empty() {
+        return None;
+    }
+    let (as_path, dropped) = aspath_aggregate(&paths, aggregate.as_set);
+    let mut attrs = vec![
+        Attribute::Origin(OriginAttr { origin }),
+        Attribute::As4Path(as_path),
+    ];
+    if atomic || dropped {
+        attrs.push(Attribute::AtomicAggregate(AtomicAggregateAttr {}));
+    }
+    attrs.push(Attribute::Aggregator4(Aggregator4Attr {
+        asn,
+        ip: u32::from(router_id),
+    }));
+    Some(attrs)
+}
+
+// Whether the aggregate or a prefix under it has been changed since the last
+// export. The changed prefixes are ordered by the address, so only the range
+// of the aggregate is looked at.
+fn aggregate_changed(changed: &BTreeSet<Ipv4Net>, prefix: &Ipv4Net) -> bool {
+    let first = Ipv4Net::new(prefix.network(), 0).unwrap();
+    let last = Ipv4Net::new(prefix.broadcast(), 32).unwrap();
+    changed.range(first..=last).any(|p| prefix.contains(p))
+}
+
+fn aggregate_active(ptree: &PrefixMap<Ipv4Net, Vec<Route>>, prefix: &Ipv4Net) -> bool {
+    ptree.get(prefix).is_some_and(|routes| {
+        routes
+            .iter()
+            .any(|route| route.kind == RouteFrom::Aggregate)
+    })
+}
+
+// Mark the routes under the prefix suppressed while an active summary-only
+// aggregate covers them.
+pub fn aggregate_suppress(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    aggregates: &Aggregates,
+    prefix: &Ipv4Net,
+) {
+    let summary: Vec<Ipv4Net> = aggregates
+        .iter()
+        .filter(|(agg, aggregate)| {
+            aggregate.summary_only && (agg.contains(prefix) || prefix.contains(*agg))
+        })
+        .map(|(agg, _)| *agg)
+        .filter(|agg| aggregate_active(ptree, agg))
+        .collect();
+    for (p, routes) in ptree.children_mut(prefix) {
+        let suppressed = summary
+            .iter()
+            .any(|agg| agg.prefix_len() < p.prefix_len() && agg.contains(p));
+        for route in routes.iter_mut() {
+            if route.suppressed != suppressed {
+                nexthops.changed.insert(*p);
+            }
+            route.suppressed = suppressed;
+        }
+    }
+}
+
+// Originate or withdraw the aggregates which have changed contributors, and
+// update the suppression of the routes under them. More specific aggregates
+// are evaluated first as they contribute to the less specific ones. The
+// aggregate covering a more specific one is changed whenever the more
+// specific one is, as its contributors are under both of them.
+pub fn aggregate_update(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    aggregates: &Aggregates,
+    asn: u32,
+    router_id: Ipv4Addr,
+) {
+    let mut prefixes: Vec<&Ipv4Net> = aggregates
+        .keys()
+        .filter(|prefix| aggregate_changed(&nexthops.changed, prefix))
+        .collect();
+    prefixes.sort_by_key(|prefix| Reverse(prefix.prefix_len()));
+
+    for prefix in prefixes.iter() {
+        let aggregate = &aggregates[*prefix];
+        let attrs = aggregate_attrs(ptree, prefix, aggregate, asn, router_id);
+        local_route_update(ptree, nexthops, **prefix, RouteFrom::Aggregate, attrs);
+    }
+    for prefix in prefixes.iter() {
+        aggregate_suppress(ptree, nexthops, aggregates, prefix);
+    }
+}
+
+impl Bgp {
+    pub fn network_update(&mut self, prefix: Ipv4Net) {
+        let attrs = self
+            .networks
+            .get(&prefix)
+            .filter(|network| network.active(&prefix))
+            .map(|_| network_attrs());
+        local_route_update(
+            &mut self.ptree,
+            &mut self.nexthops,
+            prefix,
+            RouteFrom::Static,
+            attrs,
+        );
+    }
+
+    pub fn prefix_update(&mut self, update: PrefixUpdate) {
+        if let Some(network) = self.networks.get_mut(&update.prefix) {
+            network.covering = update.covering;
+            self.network_update(update.prefix);
+        }
+    }
+
+    // Re-evaluate the aggregates after the Loc-RIB has been changed.
+    pub fn aggregate_update(&mut self) {
+        if self.aggregates.is_empty() {
+            return;
+        }
+        aggregate_update(
+            &mut self.ptree,
+            &mut self.nexthops,
+            &self.aggregates,
+            self.asn,
+            self.router_id,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{As4Segment, AS_SEQUENCE};
+
+    fn route(from: &str, origin: u8, asn: &[u32]) -> Route {
+        let attrs = vec![
+            Attribute::Origin(OriginAttr { origin }),
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: asn.to_vec(),
+                }],
+            }),
+        ];
+        Route {
+            from: from.parse().unwrap(),
+            router_id: from.parse().unwrap(),
+            kind: RouteFrom::Peer,
+            aspath: route_aspath(&attrs),
+            attrs: Arc::new(attrs),
+            nexthop: None,
+            ibgp: false,
+            selected: true,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        }
+    }
+
+    fn aggregate_route<'a>(
+        ptree: &'a PrefixMap<Ipv4Net, Vec<Route>>,
+        prefix: &Ipv4Net,
+    ) -> Option<&'a Route> {
+        ptree
+            .get(prefix)?
+            .iter()
+            .find(|route| route.kind == RouteFrom::Aggregate)
+    }
+
+    // Aggregate update after the prefixes have been changed.
+    fn update(
+        ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+        nexthops: &mut NexthopCache,
+        aggregates: &Aggregates,
+        changed: &[Ipv4Net],
+    ) {
+        nexthops.changed.clear();
+        nexthops.changed.extend(changed.iter().cloned());
+        let router_id: Ipv4Addr = "1.1.1.1".parse().unwrap();
+        aggregate_update(ptree, nexthops, aggregates, 65000, router_id);
+    }
+
+    #[test]
+    fn aggregate_lifecycle() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let mut aggregates = Aggregates::new();
+        let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+        let c1: Ipv4Net = "10.0.1.0/24".parse().unwrap();
+        let c2: Ipv4Net = "10.0.2.0/24".parse().unwrap();
+        let router_id: Ipv4Addr = "1.1.1.1".parse().unwrap();
+        aggregates.insert(
+            prefix,
+            Aggregate {
+                summary_only: true,
+                as_set: true,
+            },
+        );
+
+        // No contributor.
+        update(&mut ptree, &mut nexthops, &aggregates, &[prefix]);
+        assert!(ptree.get(&prefix).is_none());
+
+        ptree.insert(c1, vec![route("2.2.2.2", 0, &[100, 200])]);
+        update(&mut ptree, &mut nexthops, &aggregates, &[c1]);
+        let route = aggregate_route(&ptree, &prefix).unwrap();
+        assert!(route.selected);
+        assert_eq!(&*route.aspath, "100 200");
+        assert!(!route
+            .attrs
+            .iter()
+            .any(|attr| matches!(attr, Attribute::AtomicAggregate(_))));
+        assert!(route.attrs.iter().any(|attr| matches!(
+            attr,
+            Attribute::Aggregator4(v) if v.asn == 65000 && v.ip == u32::from(router_id)
+        )));
+        assert!(ptree.get(&c1).unwrap()[0].suppressed);
+        assert!(!route.suppressed);
+
+        // Second contributor with INCOMPLETE origin.
+        ptree.insert(c2, vec![route("3.3.3.3", 2, &[100, 300])]);
+        update(&mut ptree, &mut nexthops, &aggregates, &[c2]);
+        let route = aggregate_route(&ptree, &prefix).unwrap();
+        assert_eq!(&*route.aspath, "100 {200,300}");
+        assert!(route
+            .attrs
+            .iter()
+            .any(|attr| matches!(attr, Attribute::Origin(v) if v.origin == 2)));
+        assert!(ptree.get(&c2).unwrap()[0].suppressed);
+
+        // Without as-set the path is truncated with ATOMIC_AGGREGATE.
+        aggregates.get_mut(&prefix).unwrap().as_set = false;
+        aggregates.get_mut(&prefix).unwrap().summary_only = false;
+        update(&mut ptree, &mut nexthops, &aggregates, &[prefix]);
+        let route = aggregate_route(&ptree, &prefix).unwrap();
+        assert_eq!(&*route.aspath, "100");
+        assert!(route
+            .attrs
+            .iter()
+            .any(|attr| matches!(attr, Attribute::AtomicAggregate(_))));
+        assert!(!ptree.get(&c1).unwrap()[0].suppressed);
+
+        // Contributors disappear.
+        ptree.remove(&c1);
+        update(&mut ptree, &mut nexthops, &aggregates, &[c1]);
+        assert!(aggregate_route(&ptree, &prefix).is_some());
+        ptree.remove(&c2);
+        update(&mut ptree, &mut nexthops, &aggregates, &[c2]);
+        assert!(ptree.get(&prefix).is_none());
+    }
+
+    #[test]
+    fn aggregate_preferred() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let mut aggregates = Aggregates::new();
+        let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+        aggregates.insert(prefix, Aggregate::default());
+
+        // The same prefix received from a peer is not a contributor.
+        ptree.insert(prefix, vec![route("2.2.2.2", 0, &[100])]);
+        update(&mut ptree, &mut nexthops, &aggregates, &[prefix]);
+        assert_eq!(ptree.get(&prefix).unwrap().len(), 1);
+
+        let contributor: Ipv4Net = "10.0.1.0/24".parse().unwrap();
+        ptree.insert(contributor, vec![route("2.2.2.2", 0, &[100])]);
+        update(&mut ptree, &mut nexthops, &aggregates, &[contributor]);
+        let routes = ptree.get(&prefix).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].kind, RouteFrom::Aggregate);
+        assert!(routes[0].selected && !routes[1].selected);
+    }
+
+    #[test]
+    fn aggregate_changed_only() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let mut aggregates = Aggregates::new();
+        let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+        let other: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let c1: Ipv4Net = "10.0.1.0/24".parse().unwrap();
+        let c2: Ipv4Net = "10.1.1.0/24".parse().unwrap();
+        aggregates.insert(prefix, Aggregate::default());
+        aggregates.insert(
+            other,
+            Aggregate {
+                summary_only: true,
+                as_set: false,
+            },
+        );
+
+        // A change outside of the aggregate does not evaluate it.
+        ptree.insert(c1, vec![route("2.2.2.2", 0, &[100])]);
+        ptree.insert(c2, vec![route("2.2.2.2", 0, &[100])]);
+        update(&mut ptree, &mut nexthops, &aggregates, &[c2]);
+        assert!(aggregate_route(&ptree, &prefix).is_none());
+        assert!(aggregate_route(&ptree, &other).is_some());
+        assert!(ptree.get(&c2).unwrap()[0].suppressed);
+        assert!(!ptree.get(&c1).unwrap()[0].suppressed);
+
+        update(&mut ptree, &mut nexthops, &aggregates, &[c1]);
+        assert!(aggregate_route(&ptree, &prefix).is_some());
+        assert!(nexthops.changed.contains(&prefix));
+        assert!(!nexthops.changed.contains(&other));
+
+        // Removed aggregate releases the routes under it.
+        aggregates.remove(&other);
+        local_route_update(&mut ptree, &mut nexthops, other, RouteFrom::Aggregate, None);
+        aggregate_suppress(&mut ptree, &mut nexthops, &aggregates, &other);
+        assert!(!ptree.get(&c2).unwrap()[0].suppressed);
+    }
+
+    #[test]
+    fn network_match() {
+        let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+        let mut network = Network::default();
+        assert!(!network.active(&prefix));
+
+        network.covering = Some(prefix);
+        assert!(network.active(&prefix));
+
+        network.covering = "10.0.0.0/8".parse().ok();
+        assert!(!network.active(&prefix));
+        network.mode = NetworkMatch::Covering;
+        assert!(network.active(&prefix));
+
+        // Default route does not cover the network.
+        network.covering = "0.0.0.0/0".parse().ok();
+        assert!(!network.active(&prefix));
+    }
+}
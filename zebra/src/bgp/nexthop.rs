@@ -0,0 +1,94 @@
+use super::multipath::Multipath;
+use crate::rib::api::{NexthopUpdate, RibTx};
+use ipnet::Ipv4Net;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+
+// BGP nexthop tracking.
+//
+// Each distinct nexthop of the received routes is registered to the RIB.
+// The RIB notifies resolution changes of the nexthop and routes using a
+// nexthop which became unreachable are excluded from the route selection.
+// The selected routes are installed to the RIB with their nexthops.
+
+#[derive(Debug, Default)]
+pub struct BgpNexthop {
+    pub refcnt: usize,
+    // None until the first resolution result is received from the RIB.
+    pub update: Option<NexthopUpdate>,
+}
+
+impl BgpNexthop {
+    pub fn resolved(&self) -> Option<Ipv4Net> {
+        self.update.as_ref().and_then(|update| update.resolved)
+    }
+
+    pub fn valid(&self, resolve_via_default: bool) -> bool {
+        match self.resolved() {
+            Some(prefix) => prefix.prefix_len() > 0 || resolve_via_default,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NexthopCache {
+    pub map: BTreeMap<Ipv4Addr, BgpNexthop>,
+    pub resolve_via_default: bool,
+    pub multipath: Multipath,
+    // Register/unregister messages and the routes waiting to be sent to the
+    // RIB.
+    pub pending: Vec<RibTx>,
+    // Prefixes of the Loc-RIB changed since the last export to the peers.
+    pub changed: BTreeSet<Ipv4Net>,
+}
+
+impl NexthopCache {
+    pub fn lock(&mut self, addr: Ipv4Addr) {
+        let nexthop = self.map.entry(addr).or_default();
+        nexthop.refcnt += 1;
+        if nexthop.refcnt == 1 {
+            self.pending.push(RibTx::NexthopRegister(addr));
+        }
+    }
+
+    pub fn unlock(&mut self, addr: Ipv4Addr) {
+        let Some(nexthop) = self.map.get_mut(&addr) else {
+            return;
+        };
+        nexthop.refcnt -= 1;
+        if nexthop.refcnt == 0 {
+            self.map.remove(&addr);
+            self.pending.push(RibTx::NexthopUnregister(addr));
+        }
+    }
+
+    pub fn valid(&self, addr: &Ipv4Addr) -> bool {
+        self.map
+            .get(addr)
+            .is_some_and(|nexthop| nexthop.valid(self.resolve_via_default))
+    }
+
+    // IGP metric to the nexthop, compared in the route selection.
+    pub fn metric(&self, addr: &Ipv4Addr) -> u32 {
+        self.map
+            .get(addr)
+            .and_then(|nexthop| nexthop.update.as_ref())
+            .map(|update| update.metric)
+            .unwrap_or(0)
+    }
+
+    // Apply resolution result from the RIB. Returns true when the validity
+    // or the metric of the nexthop has been changed.
+    pub fn update(&mut self, update: NexthopUpdate) -> bool {
+        let resolve_via_default = self.resolve_via_default;
+        let Some(nexthop) = self.map.get_mut(&update.addr) else {
+            return false;
+        };
+        let prev = nexthop.valid(resolve_via_default);
+        let metric = nexthop.update.as_ref().map(|update| update.metric);
+        let changed = metric.is_some_and(|metric| metric != update.metric);
+        nexthop.update = Some(update);
+        changed || prev != nexthop.valid(resolve_via_default)
+    }
+}
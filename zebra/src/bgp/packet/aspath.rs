@@ -1,37 +1,498 @@
 #![allow(dead_code)]
+use bytes::{BufMut, BytesMut};
 use nom_derive::*;
+use std::collections::BTreeSet;
+use std::fmt;
 
 pub const AS_SET: u8 = 1;
 pub const AS_SEQUENCE: u8 = 2;
 pub const AS_CONFED_SEQUENCE: u8 = 3;
 pub const AS_CONFED_SET: u8 = 4;
 
+// RFC 6793 reserved two-octet AS number used in place of non-mappable
+// four-octet AS numbers.
+pub const AS_TRANS: u16 = 23456;
+
 #[derive(Debug, NomBE)]
 pub struct AsSegmentHeader {
     pub typ: u8,
     pub length: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AsSegment {
     pub typ: u8,
     pub asn: Vec<u16>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AsPathAttr {
     pub segments: Vec<AsSegment>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct As4Segment {
     pub typ: u8,
     pub asn: Vec<u32>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct As4PathAttr {
     pub segments: Vec<As4Segment>,
 }
 
-// let output: Vec<u8> = input.iter().flat_map(|val| val.to_be_bytes()).collect();
+pub fn is_confed(typ: u8) -> bool {
+    typ == AS_CONFED_SEQUENCE || typ == AS_CONFED_SET
+}
+
+impl As4Segment {
+    // Number of AS counted for path length, RFC 4271 9.1.2.2 and RFC 5065.
+    pub fn length(&self) -> usize {
+        match self.typ {
+            AS_SEQUENCE => self.asn.len(),
+            AS_SET => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl From<&AsSegment> for As4Segment {
+    fn from(seg: &AsSegment) -> Self {
+        Self {
+            typ: seg.typ,
+            asn: seg.asn.iter().map(|asn| *asn as u32).collect(),
+        }
+    }
+}
+
+impl From<&AsPathAttr> for As4PathAttr {
+    fn from(as_path: &AsPathAttr) -> Self {
+        Self {
+            segments: as_path.segments.iter().map(As4Segment::from).collect(),
+        }
+    }
+}
+
+impl As4PathAttr {
+    pub fn length(&self) -> usize {
+        self.segments.iter().map(|seg| seg.length()).sum()
+    }
+
+    // Prepend the AS numbers to the leading AS_SEQUENCE, or to a new one when
+    // the path does not start with AS_SEQUENCE.
+    pub fn prepend(&mut self, asns: &[u32]) {
+        if asns.is_empty() {
+            return;
+        }
+        match self.segments.first_mut() {
+            Some(seg) if seg.typ == AS_SEQUENCE => {
+                seg.asn.splice(0..0, asns.iter().cloned());
+            }
+            _ => {
+                self.segments.insert(
+                    0,
+                    As4Segment {
+                        typ: AS_SEQUENCE,
+                        asn: asns.to_vec(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn contains(&self, asn: u32) -> bool {
+        self.segments.iter().any(|seg| seg.asn.contains(&asn))
+    }
+
+    // Split four-octet AS path into AS_PATH and AS4_PATH for a peer which
+    // does not support four-octet AS number. Non-mappable AS numbers are
+    // replaced by AS_TRANS in AS_PATH. AS4_PATH is returned only when the
+    // path contains non-mappable AS numbers and never carries confederation
+    // segments.
+    pub fn to_as2(&self) -> (AsPathAttr, Option<As4PathAttr>) {
+        let mut mappable = true;
+        let segments = self
+            .segments
+            .iter()
+            .map(|seg| AsSegment {
+                typ: seg.typ,
+                asn: seg
+                    .asn
+                    .iter()
+                    .map(|asn| {
+                        if *asn > u16::MAX as u32 {
+                            mappable = false;
+                            AS_TRANS
+                        } else {
+                            *asn as u16
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        let as_path = AsPathAttr { segments };
+        if mappable {
+            return (as_path, None);
+        }
+        let as4_path = As4PathAttr {
+            segments: self
+                .segments
+                .iter()
+                .filter(|seg| !is_confed(seg.typ))
+                .cloned()
+                .collect(),
+        };
+        (as_path, Some(as4_path))
+    }
+}
+
+// Reconstruct AS path from AS_PATH and AS4_PATH received from a peer which
+// does not support four-octet AS number, RFC 6793 4.2.3. When AS4_PATH is
+// not present or it is longer than AS_PATH, AS_PATH is used as is and
+// AS_TRANS is left in the path.
+pub fn aspath_merge(as_path: &AsPathAttr, as4_path: Option<&As4PathAttr>) -> As4PathAttr {
+    let as_path = As4PathAttr::from(as_path);
+    let Some(as4_path) = as4_path else {
+        return as_path;
+    };
+    let length = as_path.length();
+    let length4 = as4_path.length();
+    if length < length4 {
+        return as_path;
+    }
+
+    // Take leading AS numbers from AS_PATH. Confederation segments are
+    // prepended when they lead or are adjacent to a prepended segment.
+    let mut need = length - length4;
+    let mut segments = Vec::new();
+    for seg in as_path.segments.iter() {
+        if is_confed(seg.typ) {
+            segments.push(seg.clone());
+            continue;
+        }
+        if need == 0 {
+            break;
+        }
+        match seg.typ {
+            AS_SEQUENCE => {
+                let take = need.min(seg.asn.len());
+                segments.push(As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: seg.asn[..take].to_vec(),
+                });
+                need -= take;
+            }
+            _ => {
+                segments.push(seg.clone());
+                need -= 1;
+            }
+        }
+    }
+    segments.extend(as4_path.segments.iter().cloned());
+    As4PathAttr { segments }
+}
+
+// AS numbers of the leading AS_SEQUENCE segments.
+fn aspath_lead(path: &As4PathAttr) -> Vec<u32> {
+    let mut lead = Vec::new();
+    for seg in path.segments.iter() {
+        if seg.typ != AS_SEQUENCE {
+            break;
+        }
+        lead.extend(seg.asn.iter());
+    }
+    lead
+}
+
+// AS path of an aggregate route, RFC 4271 9.2.2.2. The leading AS_SEQUENCE
+// common to all of the contributors is kept. The rest of the AS numbers are
+// put into an AS_SET when `as_set` is true, otherwise they are dropped and
+// true is returned so that ATOMIC_AGGREGATE is attached. Confederation
+// segments are not carried to the aggregate.
+pub fn aspath_aggregate(paths: &[As4PathAttr], as_set: bool) -> (As4PathAttr, bool) {
+    let mut common: Option<Vec<u32>> = None;
+    for path in paths.iter() {
+        let lead = aspath_lead(path);
+        common = Some(match common {
+            None => lead,
+            Some(common) => common
+                .iter()
+                .zip(lead.iter())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| *a)
+                .collect(),
+        });
+    }
+    let common = common.unwrap_or_default();
+
+    let mut rest = BTreeSet::new();
+    for path in paths.iter() {
+        let mut skip = common.len();
+        for seg in path.segments.iter().filter(|seg| !is_confed(seg.typ)) {
+            for asn in seg.asn.iter() {
+                if seg.typ == AS_SEQUENCE && skip > 0 {
+                    skip -= 1;
+                } else {
+                    rest.insert(*asn);
+                }
+            }
+        }
+    }
+
+    let mut segments = Vec::new();
+    if !common.is_empty() {
+        segments.push(As4Segment {
+            typ: AS_SEQUENCE,
+            asn: common,
+        });
+    }
+    if as_set && !rest.is_empty() {
+        segments.push(As4Segment {
+            typ: AS_SET,
+            asn: rest.iter().cloned().collect(),
+        });
+    }
+    (As4PathAttr { segments }, !as_set && !rest.is_empty())
+}
+
+// Segments longer than 255 AS numbers are split into consecutive segments of
+// the same type.
+const AS_SEGMENT_MAX: usize = 255;
+
+impl AsPathAttr {
+    pub fn encode(&self, buf: &mut BytesMut) {
+        for seg in self.segments.iter() {
+            for chunk in seg.asn.chunks(AS_SEGMENT_MAX) {
+                buf.put_u8(seg.typ);
+                buf.put_u8(chunk.len() as u8);
+                for asn in chunk.iter() {
+                    buf.put_u16(*asn);
+                }
+            }
+        }
+    }
+}
+
+impl As4PathAttr {
+    pub fn encode(&self, buf: &mut BytesMut) {
+        for seg in self.segments.iter() {
+            for chunk in seg.asn.chunks(AS_SEGMENT_MAX) {
+                buf.put_u8(seg.typ);
+                buf.put_u8(chunk.len() as u8);
+                for asn in chunk.iter() {
+                    buf.put_u32(*asn);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for As4Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (open, close, sep) = match self.typ {
+            AS_SET => ("{", "}", ","),
+            AS_CONFED_SEQUENCE => ("(", ")", " "),
+            AS_CONFED_SET => ("[", "]", ","),
+            _ => ("", "", " "),
+        };
+        let asn: Vec<String> = self.asn.iter().map(|asn| asn.to_string()).collect();
+        write!(f, "{}{}{}", open, asn.join(sep), close)
+    }
+}
+
+impl fmt::Display for As4PathAttr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segs: Vec<String> = self.segments.iter().map(|seg| seg.to_string()).collect();
+        write!(f, "{}", segs.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn seg2(typ: u8, asn: &[u16]) -> AsSegment {
+        AsSegment {
+            typ,
+            asn: asn.to_vec(),
+        }
+    }
+
+    fn seg4(typ: u8, asn: &[u32]) -> As4Segment {
+        As4Segment {
+            typ,
+            asn: asn.to_vec(),
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let as_path = AsPathAttr {
+            segments: vec![seg2(AS_SEQUENCE, &[100, 200, AS_TRANS, 300])],
+        };
+        let as4_path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[65536, 300])],
+        };
+        let path = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(path.to_string(), "100 200 65536 300");
+        assert_eq!(path.length(), 4);
+    }
+
+    #[test]
+    fn merge_without_as4_path() {
+        let as_path = AsPathAttr {
+            segments: vec![seg2(AS_SEQUENCE, &[100, AS_TRANS, 300])],
+        };
+        let path = aspath_merge(&as_path, None);
+        assert_eq!(path.to_string(), "100 23456 300");
+    }
+
+    #[test]
+    fn merge_as4_path_longer() {
+        let as_path = AsPathAttr {
+            segments: vec![seg2(AS_SEQUENCE, &[AS_TRANS, 300])],
+        };
+        let as4_path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[65536, 65537, 300])],
+        };
+        let path = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(path.to_string(), "23456 300");
+    }
+
+    #[test]
+    fn merge_set() {
+        let as_path = AsPathAttr {
+            segments: vec![
+                seg2(AS_SEQUENCE, &[100]),
+                seg2(AS_SET, &[200, 201]),
+                seg2(AS_SEQUENCE, &[AS_TRANS]),
+            ],
+        };
+        let as4_path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[70000])],
+        };
+        let path = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(path.to_string(), "100 {200,201} 70000");
+    }
+
+    #[test]
+    fn merge_confed() {
+        // Leading confederation segment is prepended even though it does not
+        // count for the path length.
+        let as_path = AsPathAttr {
+            segments: vec![
+                seg2(AS_CONFED_SEQUENCE, &[64512, 64513]),
+                seg2(AS_SEQUENCE, &[AS_TRANS, 300]),
+            ],
+        };
+        let as4_path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[65536, 300])],
+        };
+        let path = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(path.to_string(), "(64512 64513) 65536 300");
+
+        // Confederation segment adjacent to a prepended segment.
+        let as_path = AsPathAttr {
+            segments: vec![
+                seg2(AS_SEQUENCE, &[100]),
+                seg2(AS_CONFED_SET, &[64512, 64513]),
+                seg2(AS_SEQUENCE, &[AS_TRANS]),
+            ],
+        };
+        let as4_path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[65536])],
+        };
+        let path = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(path.to_string(), "100 [64512,64513] 65536");
+    }
+
+    #[test]
+    fn to_as2() {
+        let path = As4PathAttr {
+            segments: vec![
+                seg4(AS_CONFED_SEQUENCE, &[64512]),
+                seg4(AS_SEQUENCE, &[100, 65536, 300]),
+            ],
+        };
+        let (as_path, as4_path) = path.to_as2();
+        assert_eq!(
+            as_path.segments,
+            vec![
+                seg2(AS_CONFED_SEQUENCE, &[64512]),
+                seg2(AS_SEQUENCE, &[100, AS_TRANS, 300])
+            ]
+        );
+        let as4_path = as4_path.unwrap();
+        assert_eq!(as4_path.to_string(), "100 65536 300");
+
+        // Round trip.
+        let merged = aspath_merge(&as_path, Some(&as4_path));
+        assert_eq!(merged, path);
+
+        // No AS4_PATH when all AS numbers are mappable.
+        let path = As4PathAttr {
+            segments: vec![seg4(AS_SEQUENCE, &[100, 200])],
+        };
+        let (_, as4_path) = path.to_as2();
+        assert!(as4_path.is_none());
+    }
+
+    fn path4(segments: Vec<As4Segment>) -> As4PathAttr {
+        As4PathAttr { segments }
+    }
+
+    #[test]
+    fn aggregate() {
+        let paths = vec![
+            path4(vec![seg4(AS_SEQUENCE, &[100, 200, 300])]),
+            path4(vec![seg4(AS_SEQUENCE, &[100, 200, 400])]),
+            path4(vec![
+                seg4(AS_SEQUENCE, &[100, 200]),
+                seg4(AS_SET, &[500, 300]),
+            ]),
+        ];
+        let (path, atomic) = aspath_aggregate(&paths, true);
+        assert_eq!(path.to_string(), "100 200 {300,400,500}");
+        assert!(!atomic);
+
+        // Without as-set the differing part is dropped.
+        let (path, atomic) = aspath_aggregate(&paths, false);
+        assert_eq!(path.to_string(), "100 200");
+        assert!(atomic);
+
+        // Identical paths are kept as is.
+        let paths = vec![
+            path4(vec![seg4(AS_SEQUENCE, &[100, 200])]),
+            path4(vec![seg4(AS_SEQUENCE, &[100]), seg4(AS_SEQUENCE, &[200])]),
+        ];
+        let (path, atomic) = aspath_aggregate(&paths, false);
+        assert_eq!(path.to_string(), "100 200");
+        assert!(!atomic);
+    }
+
+    #[test]
+    fn aggregate_no_common() {
+        let paths = vec![
+            path4(vec![seg4(AS_SEQUENCE, &[100, 300])]),
+            path4(vec![seg4(AS_SEQUENCE, &[200, 300])]),
+            // Locally originated contributor.
+            path4(vec![]),
+        ];
+        let (path, atomic) = aspath_aggregate(&paths, true);
+        assert_eq!(path.to_string(), "{100,200,300}");
+        assert_eq!(path.length(), 1);
+        assert!(!atomic);
+
+        let (path, atomic) = aspath_aggregate(&paths, false);
+        assert!(path.segments.is_empty());
+        assert!(atomic);
+
+        // Confederation segments are removed.
+        let paths = vec![path4(vec![
+            seg4(AS_CONFED_SEQUENCE, &[64512]),
+            seg4(AS_SEQUENCE, &[100]),
+        ])];
+        let (path, _) = aspath_aggregate(&paths, true);
+        assert_eq!(path.to_string(), "{100}");
+    }
+}
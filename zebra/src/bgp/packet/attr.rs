@@ -1,10 +1,13 @@
 #![allow(dead_code)]
-use super::{As4PathAttr, AsPathAttr, CommunityAttr, ExtendedComAttr, LargeComAttr};
+use super::{
+    As4PathAttr, AsPathAttr, CommunityAttr, ExtendedComAttr, LargeComAttr, Vpnv4Nlri, AS_TRANS,
+};
 use crate::bgp::{Afi, Safi};
-use ipnet::Ipv6Net;
+use bytes::{BufMut, BytesMut};
+use ipnet::{Ipv4Net, Ipv6Net};
 use nom_derive::*;
 use rusticata_macros::newtype_enum;
-use std::net::Ipv6Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 pub const BGP_ATTR_FLAG_OPTIONAL: u8 = 0x80;
 pub const BGP_ATTR_FLAG_TRNANSITIVE: u8 = 0x40;
@@ -27,7 +30,10 @@ newtype_enum! {
         MpReachNlri = 14,
         MpUnreachNlri = 15,
         ExtendedCom = 16,
+        As4Path = 17,
+        As4Aggregator = 18,
         LargeCom = 32,
+        Otc = 35,
     }
 }
 
@@ -47,10 +53,256 @@ pub enum Attribute {
     MpUnreachNlri(MpNlriAttr),
     ExtendedCom(ExtendedComAttr),
     LargeCom(LargeComAttr),
+    Otc(OtcAttr),
 }
 
 pub type Attrs = Vec<Attribute>;
 
+fn attr_put(buf: &mut BytesMut, flags: u8, typ: AttributeType, value: &[u8]) {
+    if value.len() > u8::MAX as usize {
+        buf.put_u8(flags | BGP_ATTR_FLAG_EXTENDED_LENGTH);
+        buf.put_u8(typ.0);
+        buf.put_u16(value.len() as u16);
+    } else {
+        buf.put_u8(flags);
+        buf.put_u8(typ.0);
+        buf.put_u8(value.len() as u8);
+    }
+    buf.put(value);
+}
+
+const FLAG_OPTIONAL_TRANSITIVE: u8 = BGP_ATTR_FLAG_OPTIONAL | BGP_ATTR_FLAG_TRNANSITIVE;
+
+impl Attribute {
+    pub fn type_code(&self) -> u8 {
+        let typ = match self {
+            Self::Origin(_) => AttributeType::Origin,
+            Self::AsPath(_) | Self::As4Path(_) => AttributeType::AsPath,
+            Self::NextHop(_) => AttributeType::NextHop,
+            Self::Med(_) => AttributeType::Med,
+            Self::LocalPref(_) => AttributeType::LocalPref,
+            Self::AtomicAggregate(_) => AttributeType::AtomicAggregate,
+            Self::Aggregator(_) | Self::Aggregator4(_) => AttributeType::Aggregator,
+            Self::Community(_) => AttributeType::Community,
+            Self::MpReachNlri(_) => AttributeType::MpReachNlri,
+            Self::MpUnreachNlri(_) => AttributeType::MpUnreachNlri,
+            Self::ExtendedCom(_) => AttributeType::ExtendedCom,
+            Self::LargeCom(_) => AttributeType::LargeCom,
+            Self::Otc(_) => AttributeType::Otc,
+        };
+        typ.0
+    }
+
+    // Encode the attribute for a peer. Four-octet AS path and aggregator are
+    // split into AS_PATH and AS4_PATH, AGGREGATOR and AS4_AGGREGATOR when the
+    // peer does not support four-octet AS number, RFC 6793 4.2.2. Only
+    // VPN-IPv4 NLRI of MP_REACH and MP_UNREACH is encoded, the other address
+    // families are not advertised.
+    pub fn encode(&self, buf: &mut BytesMut, as4: bool) {
+        let mut value = BytesMut::new();
+        match self {
+            Self::Origin(v) => {
+                value.put_u8(v.origin);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::Origin,
+                    &value,
+                );
+            }
+            Self::AsPath(v) => {
+                v.encode(&mut value);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::AsPath,
+                    &value,
+                );
+            }
+            Self::As4Path(v) if as4 => {
+                v.encode(&mut value);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::AsPath,
+                    &value,
+                );
+            }
+            Self::As4Path(v) => {
+                let (as_path, as4_path) = v.to_as2();
+                as_path.encode(&mut value);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::AsPath,
+                    &value,
+                );
+                if let Some(as4_path) = as4_path {
+                    value.clear();
+                    as4_path.encode(&mut value);
+                    attr_put(
+                        buf,
+                        FLAG_OPTIONAL_TRANSITIVE,
+                        AttributeType::As4Path,
+                        &value,
+                    );
+                }
+            }
+            Self::NextHop(v) => {
+                value.put(&v.next_hop[..]);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::NextHop,
+                    &value,
+                );
+            }
+            Self::Med(v) => {
+                value.put_u32(v.med);
+                attr_put(buf, BGP_ATTR_FLAG_OPTIONAL, AttributeType::Med, &value);
+            }
+            Self::LocalPref(v) => {
+                value.put_u32(v.local_pref);
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::LocalPref,
+                    &value,
+                );
+            }
+            Self::AtomicAggregate(_) => {
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_TRNANSITIVE,
+                    AttributeType::AtomicAggregate,
+                    &value,
+                );
+            }
+            Self::Aggregator(v) => {
+                value.put_u16(v.asn);
+                value.put_u32(v.ip);
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::Aggregator,
+                    &value,
+                );
+            }
+            Self::Aggregator4(v) if as4 => {
+                value.put_u32(v.asn);
+                value.put_u32(v.ip);
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::Aggregator,
+                    &value,
+                );
+            }
+            Self::Aggregator4(v) => {
+                let mappable = v.asn <= u16::MAX as u32;
+                value.put_u16(if mappable { v.asn as u16 } else { AS_TRANS });
+                value.put_u32(v.ip);
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::Aggregator,
+                    &value,
+                );
+                if !mappable {
+                    value.clear();
+                    value.put_u32(v.asn);
+                    value.put_u32(v.ip);
+                    attr_put(
+                        buf,
+                        FLAG_OPTIONAL_TRANSITIVE,
+                        AttributeType::As4Aggregator,
+                        &value,
+                    );
+                }
+            }
+            Self::Community(v) => {
+                for com in v.0.iter() {
+                    value.put_u32(*com);
+                }
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::Community,
+                    &value,
+                );
+            }
+            Self::ExtendedCom(v) => {
+                for ecom in v.0.iter() {
+                    value.put_u8(ecom.high_type);
+                    value.put_u8(ecom.low_type);
+                    value.put(&ecom.val[..]);
+                }
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::ExtendedCom,
+                    &value,
+                );
+            }
+            Self::LargeCom(v) => {
+                for lcom in v.0.iter() {
+                    value.put_u32(lcom.global);
+                    value.put_u32(lcom.local1);
+                    value.put_u32(lcom.local2);
+                }
+                attr_put(
+                    buf,
+                    FLAG_OPTIONAL_TRANSITIVE,
+                    AttributeType::LargeCom,
+                    &value,
+                );
+            }
+            Self::Otc(v) => {
+                value.put_u32(v.asn);
+                attr_put(buf, FLAG_OPTIONAL_TRANSITIVE, AttributeType::Otc, &value);
+            }
+            Self::MpReachNlri(v) => {
+                let Some(nexthop) = v.vpnv4_next_hop else {
+                    return;
+                };
+                value.put_u16(Afi::IP.0);
+                value.put_u8(Safi::MplsVpn.0);
+                // VPN-IPv4 address of the next hop with zero RD.
+                value.put_u8(12);
+                value.put_u64(0);
+                value.put(&nexthop.octets()[..]);
+                // Reserved.
+                value.put_u8(0);
+                for nlri in v.vpnv4_prefix.iter() {
+                    nlri.encode(&mut value, false);
+                }
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_OPTIONAL,
+                    AttributeType::MpReachNlri,
+                    &value,
+                );
+            }
+            Self::MpUnreachNlri(v) => {
+                if v.vpnv4_prefix.is_empty() {
+                    return;
+                }
+                value.put_u16(Afi::IP.0);
+                value.put_u8(Safi::MplsVpn.0);
+                for nlri in v.vpnv4_prefix.iter() {
+                    nlri.encode(&mut value, true);
+                }
+                attr_put(
+                    buf,
+                    BGP_ATTR_FLAG_OPTIONAL,
+                    AttributeType::MpUnreachNlri,
+                    &value,
+                );
+            }
+        }
+    }
+}
+
 #[derive(Debug, NomBE)]
 pub struct AttributeHeader {
     pub flags: u8,
@@ -63,6 +315,11 @@ impl AttributeHeader {
     }
 }
 
+// ORIGIN of locally originated routes. EGP (1) and INCOMPLETE (2) are less
+// preferred, RFC 4271 5.1.1.
+pub const ORIGIN_IGP: u8 = 0;
+pub const ORIGIN_INCOMPLETE: u8 = 2;
+
 #[derive(Clone, Debug, NomBE)]
 pub struct OriginAttr {
     pub origin: u8,
@@ -98,6 +355,12 @@ pub struct Aggregator4Attr {
     pub ip: u32,
 }
 
+// Only to Customer, RFC 9234.
+#[derive(Clone, Debug, NomBE)]
+pub struct OtcAttr {
+    pub asn: u32,
+}
+
 #[derive(Clone, Debug, NomBE)]
 pub struct MpNlriReachHeader {
     pub afi: Afi,
@@ -115,4 +378,9 @@ pub struct MpNlriUnreachHeader {
 pub struct MpNlriAttr {
     pub next_hop: Option<Ipv6Addr>,
     pub prefix: Vec<Ipv6Net>,
+    pub ipv4_prefix: Vec<Ipv4Net>,
+    // VPN-IPv4, RFC 4364. The next hop is carried as VPN-IPv4 address with
+    // zero RD.
+    pub vpnv4_next_hop: Option<Ipv4Addr>,
+    pub vpnv4_prefix: Vec<Vpnv4Nlri>,
 }
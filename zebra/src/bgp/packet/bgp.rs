@@ -1,8 +1,11 @@
 #![allow(dead_code)]
-use super::{NotificationPacket, OpenPacket, UpdatePacket};
+use super::{NotificationPacket, OpenPacket, RouteRefreshPacket, UpdatePacket};
 use nom_derive::*;
 
 pub const BGP_PACKET_LEN: usize = 4096;
+// Maximum message size when both sides advertise the Extended Message
+// capability, RFC 8654.
+pub const BGP_EXTENDED_PACKET_LEN: usize = 65535;
 pub const BGP_HEADER_LEN: u16 = 19;
 
 #[repr(u8)]
@@ -40,4 +43,5 @@ pub enum BgpPacket {
     Keepalive(BgpHeader),
     Notification(NotificationPacket),
     Update(UpdatePacket),
+    RouteRefresh(RouteRefreshPacket),
 }
@@ -1,5 +1,8 @@
-use super::{BgpHeader, NotificationPacket, OpenPacket};
+use super::{
+    Attribute, BgpHeader, BgpType, NotificationPacket, OpenPacket, RouteRefreshPacket, UpdatePacket,
+};
 use bytes::{BufMut, BytesMut};
+use ipnet::Ipv4Net;
 
 impl From<BgpHeader> for BytesMut {
     fn from(header: BgpHeader) -> Self {
@@ -53,3 +56,62 @@ impl From<NotificationPacket> for BytesMut {
         buf
     }
 }
+
+impl From<RouteRefreshPacket> for BytesMut {
+    fn from(refresh: RouteRefreshPacket) -> Self {
+        let mut buf = BytesMut::new();
+        let header: BytesMut = refresh.header.into();
+        buf.put(&header[..]);
+        buf.put_u16(refresh.afi.0);
+        buf.put_u8(refresh.subtype.0);
+        buf.put_u8(refresh.safi.0);
+        buf
+    }
+}
+
+fn ipv4_prefix_put(buf: &mut BytesMut, prefix: &Ipv4Net) {
+    let plen = prefix.prefix_len();
+    let psize = (plen as usize + 7) / 8;
+    buf.put_u8(plen);
+    buf.put(&prefix.network().octets()[..psize]);
+}
+
+impl UpdatePacket {
+    // Attributes are encoded in the order of the type code. AS_PATH and
+    // AGGREGATOR are encoded in the two-octet form when the peer does not
+    // support four-octet AS number.
+    pub fn encode(&self, as4: bool) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put(&self.header.marker[..]);
+        buf.put_u16(0);
+        buf.put_u8(BgpType::Update as u8);
+
+        let withdraw_pos = buf.len();
+        buf.put_u16(0);
+        for prefix in self.ipv4_withdraw.iter() {
+            ipv4_prefix_put(&mut buf, prefix);
+        }
+        let length = (buf.len() - withdraw_pos - 2) as u16;
+        buf[withdraw_pos..withdraw_pos + 2].copy_from_slice(&length.to_be_bytes());
+
+        let attr_pos = buf.len();
+        buf.put_u16(0);
+        let mut attrs: Vec<&Attribute> = self.attrs.iter().collect();
+        attrs.sort_by_key(|attr| attr.type_code());
+        for attr in attrs.iter() {
+            attr.encode(&mut buf, as4);
+        }
+        let length = (buf.len() - attr_pos - 2) as u16;
+        buf[attr_pos..attr_pos + 2].copy_from_slice(&length.to_be_bytes());
+
+        for prefix in self.ipv4_update.iter() {
+            ipv4_prefix_put(&mut buf, prefix);
+        }
+
+        const LENGTH_POS: std::ops::Range<usize> = 16..18;
+        let length: u16 = buf.len() as u16;
+        buf[LENGTH_POS].copy_from_slice(&length.to_be_bytes());
+
+        buf
+    }
+}
@@ -1,9 +1,281 @@
-#[derive(Debug)]
+use nom::bytes::complete::take;
+use nom::number::complete::be_u8;
+use nom::IResult;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+// Extended community type, RFC 4360 and RFC 5668.
+pub const EXT_COM_TYPE_AS: u8 = 0x00;
+pub const EXT_COM_TYPE_IP: u8 = 0x01;
+pub const EXT_COM_TYPE_AS4: u8 = 0x02;
+pub const EXT_COM_TYPE_AS_NON_TRANSITIVE: u8 = 0x40;
+
+// Extended community sub-type.
+pub const EXT_COM_SUBTYPE_RT: u8 = 0x02;
+pub const EXT_COM_SUBTYPE_SOO: u8 = 0x03;
+pub const EXT_COM_SUBTYPE_LINK_BW: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ExtendedCom {
-    //
+    pub high_type: u8,
+    pub low_type: u8,
+    pub val: [u8; 6],
+}
+
+impl ExtendedCom {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, high_type) = be_u8(input)?;
+        let (input, low_type) = be_u8(input)?;
+        let (input, val) = take(6usize)(input)?;
+        let mut ecom = ExtendedCom {
+            high_type,
+            low_type,
+            val: [0u8; 6],
+        };
+        ecom.val.copy_from_slice(val);
+        Ok((input, ecom))
+    }
+
+    pub fn route_target(&self) -> Option<RouteTarget> {
+        if self.low_type != EXT_COM_SUBTYPE_RT {
+            return None;
+        }
+        let v = &self.val;
+        match self.high_type {
+            EXT_COM_TYPE_AS => Some(RouteTarget::As(
+                u16::from_be_bytes([v[0], v[1]]),
+                u32::from_be_bytes([v[2], v[3], v[4], v[5]]),
+            )),
+            EXT_COM_TYPE_IP => Some(RouteTarget::Ip(
+                Ipv4Addr::new(v[0], v[1], v[2], v[3]),
+                u16::from_be_bytes([v[4], v[5]]),
+            )),
+            EXT_COM_TYPE_AS4 => Some(RouteTarget::As4(
+                u32::from_be_bytes([v[0], v[1], v[2], v[3]]),
+                u16::from_be_bytes([v[4], v[5]]),
+            )),
+            _ => None,
+        }
+    }
+
+    // Link bandwidth, draft-ietf-idr-link-bandwidth. Two-octet AS specific
+    // with the bandwidth in bytes per second as IEEE floating point. It is
+    // defined as non-transitive, while some implementations send it in the
+    // generic transitive type, so both of them are accepted.
+    pub fn link_bandwidth(&self) -> Option<LinkBandwidth> {
+        if self.low_type != EXT_COM_SUBTYPE_LINK_BW {
+            return None;
+        }
+        if self.high_type != EXT_COM_TYPE_AS && self.high_type != EXT_COM_TYPE_AS_NON_TRANSITIVE {
+            return None;
+        }
+        let v = &self.val;
+        let bandwidth = f32::from_be_bytes([v[2], v[3], v[4], v[5]]);
+        if !bandwidth.is_finite() || bandwidth < 0.0 {
+            return None;
+        }
+        Some(LinkBandwidth {
+            asn: u16::from_be_bytes([v[0], v[1]]),
+            bandwidth: bandwidth as u64,
+        })
+    }
+}
+
+impl fmt::Display for ExtendedCom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(rt) = self.route_target() {
+            write!(f, "RT:{}", rt)
+        } else if let Some(lb) = self.link_bandwidth() {
+            write!(f, "LB:{}", lb)
+        } else {
+            write!(f, "0x{:02x}{:02x}", self.high_type, self.low_type)?;
+            for v in self.val.iter() {
+                write!(f, "{:02x}", v)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ExtendedComAttr(pub Vec<ExtendedCom>);
+
+impl ExtendedComAttr {
+    pub fn route_targets(&self) -> impl Iterator<Item = RouteTarget> + '_ {
+        self.0.iter().filter_map(|ecom| ecom.route_target())
+    }
+
+    pub fn link_bandwidth(&self) -> Option<LinkBandwidth> {
+        self.0.iter().find_map(|ecom| ecom.link_bandwidth())
+    }
+}
+
+impl fmt::Display for ExtendedComAttr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v: Vec<String> = self.0.iter().map(|ecom| ecom.to_string()).collect();
+        write!(f, "{}", v.join(" "))
+    }
+}
+
+// Link bandwidth extended community. The bandwidth is in bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkBandwidth {
+    pub asn: u16,
+    pub bandwidth: u64,
 }
 
-#[derive(Clone, Debug)]
-pub struct ExtendedComAttr {
-    //
+impl From<LinkBandwidth> for ExtendedCom {
+    fn from(lb: LinkBandwidth) -> Self {
+        let mut val = [0u8; 6];
+        val[..2].copy_from_slice(&lb.asn.to_be_bytes());
+        val[2..].copy_from_slice(&(lb.bandwidth as f32).to_be_bytes());
+        ExtendedCom {
+            high_type: EXT_COM_TYPE_AS_NON_TRANSITIVE,
+            low_type: EXT_COM_SUBTYPE_LINK_BW,
+            val,
+        }
+    }
+}
+
+// Shown in Mbps as the other implementations do.
+impl fmt::Display for LinkBandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}Mbps", self.asn, self.bandwidth * 8 / 1_000_000)
+    }
+}
+
+// Route target extended community.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RouteTarget {
+    As(u16, u32),
+    Ip(Ipv4Addr, u16),
+    As4(u32, u16),
+}
+
+impl From<RouteTarget> for ExtendedCom {
+    fn from(rt: RouteTarget) -> Self {
+        let mut val = [0u8; 6];
+        let high_type = match rt {
+            RouteTarget::As(asn, local) => {
+                val[..2].copy_from_slice(&asn.to_be_bytes());
+                val[2..].copy_from_slice(&local.to_be_bytes());
+                EXT_COM_TYPE_AS
+            }
+            RouteTarget::Ip(addr, local) => {
+                val[..4].copy_from_slice(&addr.octets());
+                val[4..].copy_from_slice(&local.to_be_bytes());
+                EXT_COM_TYPE_IP
+            }
+            RouteTarget::As4(asn, local) => {
+                val[..4].copy_from_slice(&asn.to_be_bytes());
+                val[4..].copy_from_slice(&local.to_be_bytes());
+                EXT_COM_TYPE_AS4
+            }
+        };
+        ExtendedCom {
+            high_type,
+            low_type: EXT_COM_SUBTYPE_RT,
+            val,
+        }
+    }
+}
+
+impl fmt::Display for RouteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::As(asn, local) => write!(f, "{}:{}", asn, local),
+            Self::Ip(addr, local) => write!(f, "{}:{}", addr, local),
+            Self::As4(asn, local) => write!(f, "{}:{}", asn, local),
+        }
+    }
+}
+
+// "ASN:NN" or "A.B.C.D:NN". Two-octet AS number is used when the AS number
+// fits in it.
+impl FromStr for RouteTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (global, local) = s.rsplit_once(':').ok_or(())?;
+        if let Ok(addr) = global.parse::<Ipv4Addr>() {
+            let local: u16 = local.parse().map_err(|_| ())?;
+            return Ok(Self::Ip(addr, local));
+        }
+        let asn: u32 = global.parse().map_err(|_| ())?;
+        if asn <= u16::MAX as u32 {
+            let local: u32 = local.parse().map_err(|_| ())?;
+            Ok(Self::As(asn as u16, local))
+        } else {
+            let local: u16 = local.parse().map_err(|_| ())?;
+            Ok(Self::As4(asn, local))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::many0;
+
+    #[test]
+    fn route_target() {
+        let rt: RouteTarget = "100:1".parse().unwrap();
+        assert_eq!(rt, RouteTarget::As(100, 1));
+        let rt: RouteTarget = "10.0.0.1:5".parse().unwrap();
+        assert_eq!(rt, RouteTarget::Ip(Ipv4Addr::new(10, 0, 0, 1), 5));
+        let rt: RouteTarget = "4200000000:7".parse().unwrap();
+        assert_eq!(rt, RouteTarget::As4(4200000000, 7));
+        assert!("4200000000:70000".parse::<RouteTarget>().is_err());
+        assert!("100".parse::<RouteTarget>().is_err());
+        assert_eq!(rt.to_string(), "4200000000:7");
+    }
+
+    #[test]
+    fn parse() {
+        let input: &[u8] = &[
+            0x00, 0x02, 0x00, 0x64, 0x00, 0x00, 0x00, 0x01, // RT:100:1
+            0x01, 0x02, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x05, // RT:10.0.0.1:5
+            0x00, 0x03, 0x00, 0x64, 0x00, 0x00, 0x00, 0x01, // SoO
+        ];
+        let (_, ecoms) = many0(ExtendedCom::parse)(input).unwrap();
+        let attr = ExtendedComAttr(ecoms);
+        let rts: Vec<RouteTarget> = attr.route_targets().collect();
+        assert_eq!(
+            rts,
+            vec![
+                RouteTarget::As(100, 1),
+                RouteTarget::Ip(Ipv4Addr::new(10, 0, 0, 1), 5)
+            ]
+        );
+        assert_eq!(
+            attr.to_string(),
+            "RT:100:1 RT:10.0.0.1:5 0x0003006400000001"
+        );
+        assert_eq!(ExtendedCom::from(RouteTarget::As(100, 1)), attr.0[0]);
+    }
+
+    #[test]
+    fn link_bandwidth() {
+        // 1 Gbps = 125000000 bytes/s = 0x4cee6b28.
+        let input: &[u8] = &[
+            0x40, 0x04, 0xfd, 0xe8, 0x4c, 0xee, 0x6b, 0x28, // non-transitive
+            0x00, 0x04, 0xfd, 0xe8, 0x4d, 0x6e, 0x6b, 0x28, // transitive, 2 Gbps
+            0x01, 0x04, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x05, // IP specific
+            0x40, 0x04, 0xfd, 0xe8, 0x7f, 0xc0, 0x00, 0x00, // NaN
+        ];
+        let (_, ecoms) = many0(ExtendedCom::parse)(input).unwrap();
+        let lbs: Vec<Option<LinkBandwidth>> = ecoms.iter().map(|e| e.link_bandwidth()).collect();
+        let lb = LinkBandwidth {
+            asn: 65000,
+            bandwidth: 125_000_000,
+        };
+        assert_eq!(lbs[0], Some(lb));
+        assert_eq!(lbs[1].unwrap().bandwidth, 250_000_000);
+        assert_eq!(lbs[2], None);
+        assert_eq!(lbs[3], None);
+        assert_eq!(ExtendedCom::from(lb), ecoms[0]);
+        assert_eq!(ecoms[0].to_string(), "LB:65000:1000Mbps");
+        assert_eq!(ExtendedComAttr(ecoms).link_bandwidth(), Some(lb));
+    }
 }
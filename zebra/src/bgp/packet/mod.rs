@@ -8,6 +8,8 @@ pub mod large;
 pub mod notification;
 pub mod open;
 pub mod parser;
+pub mod rd;
+pub mod refresh;
 pub mod update;
 
 pub mod many;
@@ -22,4 +24,6 @@ pub use large::*;
 pub use notification::*;
 pub use open::*;
 pub use parser::*;
+pub use rd::*;
+pub use refresh::*;
 pub use update::*;
@@ -29,7 +29,7 @@ newtype_enum! {
 }
 
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum MessageError {
     ConnectionNotSynced = 1,
     BadMessageLength = 2,
@@ -1,6 +1,6 @@
 use super::BgpHeader;
 use crate::bgp::BGP_VERSION;
-use crate::bgp::{Afi, Safi};
+use crate::bgp::{Afi, AfiSafi, Safi};
 use bytes::BufMut;
 use bytes::BytesMut;
 use nom_derive::*;
@@ -28,7 +28,9 @@ newtype_enum! {
     impl display CapabilityType {
         MultiProtocol = 1,
         RouteRefresh = 2,
+        ExtendedNextHop = 5,
     ExtendedMessage = 6,
+        Role = 9,
         GracefulRestart = 64,
         As4 = 65,
         DynamicCapability = 67,
@@ -46,6 +48,7 @@ newtype_enum! {
 pub enum CapabilityPacket {
     MultiProtocol(CapabilityMultiProtocol),
     RouteRefresh(CapabilityRouteRefresh),
+    ExtendedNextHop(CapabilityExtendedNextHop),
     ExtendedMessage(CapabilityExtendedMessage),
     As4(CapabilityAs4),
     DynamicCapability(CapabilityDynamicCapability),
@@ -56,6 +59,7 @@ pub enum CapabilityPacket {
     FQDN(CapabilityFQDN),
     SoftwareVersion(CapabilitySoftwareVersion),
     PathLimit(CapabilityPathLimit),
+    Role(CapabilityRole),
     Unknown(CapabilityUnknown),
 }
 
@@ -75,6 +79,16 @@ impl CapabilityPacket {
                 buf.put_u8(m.typ.0);
                 buf.put_u8(m.length);
             }
+            Self::ExtendedNextHop(m) => {
+                m.header.encode(buf);
+                buf.put_u8(m.typ.0);
+                buf.put_u8(m.length);
+                for value in m.values.iter() {
+                    buf.put_u16(value.afi.0);
+                    buf.put_u16(value.safi);
+                    buf.put_u16(value.nhop_afi.0);
+                }
+            }
             Self::ExtendedMessage(m) => {
                 m.header.encode(buf);
                 buf.put_u8(m.typ.0);
@@ -132,6 +146,12 @@ impl CapabilityPacket {
                 buf.put_u8(m.safi.0);
                 buf.put_u16(m.path_limit);
             }
+            Self::Role(m) => {
+                m.header.encode(buf);
+                buf.put_u8(m.typ.0);
+                buf.put_u8(m.length);
+                buf.put_u8(m.role);
+            }
             Self::Unknown(m) => {
                 m.header.encode(buf);
                 buf.put_u8(m.typ.0);
@@ -190,6 +210,10 @@ impl CapabilityMultiProtocol {
             safi: safi.clone(),
         }
     }
+
+    pub fn afi_safi(&self) -> AfiSafi {
+        AfiSafi::new(self.afi.clone(), self.safi.clone())
+    }
 }
 
 #[derive(Debug, PartialEq, NomBE, Clone)]
@@ -209,6 +233,45 @@ impl CapabilityRouteRefresh {
     }
 }
 
+#[derive(Debug, PartialEq, NomBE, Clone)]
+pub struct ExtendedNextHopValue {
+    pub afi: Afi,
+    pub safi: u16,
+    pub nhop_afi: Afi,
+}
+
+impl ExtendedNextHopValue {
+    pub fn new(afi: &Afi, safi: &Safi, nhop_afi: &Afi) -> Self {
+        Self {
+            afi: afi.clone(),
+            safi: safi.0 as u16,
+            nhop_afi: nhop_afi.clone(),
+        }
+    }
+}
+
+// RFC 8950 Extended Next Hop Encoding capability.
+#[derive(Debug, PartialEq, NomBE, Clone)]
+pub struct CapabilityExtendedNextHop {
+    header: CapabilityHeader,
+    typ: CapabilityType,
+    pub length: u8,
+    #[nom(Ignore)]
+    pub values: Vec<ExtendedNextHopValue>,
+}
+
+impl CapabilityExtendedNextHop {
+    pub fn new(values: Vec<ExtendedNextHopValue>) -> Self {
+        let length = (values.len() * 6) as u8;
+        Self {
+            header: CapabilityHeader::new(length + 2),
+            typ: CapabilityType::ExtendedNextHop,
+            length,
+            values,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, NomBE, Clone)]
 pub struct CapabilityAs4 {
     header: CapabilityHeader,
@@ -410,6 +473,26 @@ impl CapabilityPathLimit {
     }
 }
 
+// BGP Role capability, RFC 9234.
+#[derive(Debug, PartialEq, NomBE, Clone)]
+pub struct CapabilityRole {
+    header: CapabilityHeader,
+    typ: CapabilityType,
+    length: u8,
+    pub role: u8,
+}
+
+impl CapabilityRole {
+    pub fn new(role: u8) -> Self {
+        Self {
+            header: CapabilityHeader::new(3),
+            typ: CapabilityType::Role,
+            length: 1,
+            role,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, NomBE, Clone)]
 pub struct CapabilityUnknown {
     header: CapabilityHeader,
@@ -438,3 +521,38 @@ impl OpenPacket {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{parse_bgp_packet, BgpPacket, BgpType, BGP_HEADER_LEN};
+
+    #[test]
+    fn extended_nexthop_round_trip() {
+        let cap = CapabilityExtendedNextHop::new(vec![
+            ExtendedNextHopValue::new(&Afi::IP, &Safi::Unicast, &Afi::IP6),
+            ExtendedNextHopValue::new(&Afi::IP, &Safi::MplsLabel, &Afi::IP6),
+        ]);
+        let caps = vec![
+            CapabilityPacket::MultiProtocol(CapabilityMultiProtocol::new(&Afi::IP, &Safi::Unicast)),
+            CapabilityPacket::ExtendedNextHop(cap),
+            CapabilityPacket::Role(CapabilityRole::new(3)),
+        ];
+        let header = BgpHeader::new(BgpType::Open, BGP_HEADER_LEN + 10);
+        let open = OpenPacket::new(
+            header,
+            65001,
+            180,
+            &Ipv4Addr::new(10, 0, 0, 1),
+            caps.clone(),
+        );
+        let bytes: BytesMut = open.into();
+
+        let (remain, packet) = parse_bgp_packet(&bytes[..], true).unwrap();
+        assert!(remain.is_empty());
+        let BgpPacket::Open(open) = packet else {
+            panic!("not an open packet");
+        };
+        assert_eq!(open.caps, caps);
+    }
+}
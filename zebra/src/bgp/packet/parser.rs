@@ -22,6 +22,13 @@ fn parse_bgp_capability_packet(input: &[u8]) -> IResult<&[u8], CapabilityPacket>
             CapabilityRouteRefresh::parse,
             CapabilityPacket::RouteRefresh,
         )(input),
+        CapabilityType::ExtendedNextHop => {
+            let (input, mut cap) = CapabilityExtendedNextHop::parse(input)?;
+            let (input, value) = take(cap.length)(input)?;
+            let (_, values) = many0(ExtendedNextHopValue::parse)(value)?;
+            cap.values = values;
+            Ok((input, CapabilityPacket::ExtendedNextHop(cap)))
+        }
         CapabilityType::ExtendedMessage => map(
             CapabilityExtendedMessage::parse,
             CapabilityPacket::ExtendedMessage,
@@ -63,6 +70,7 @@ fn parse_bgp_capability_packet(input: &[u8]) -> IResult<&[u8], CapabilityPacket>
             cap.version = version.to_vec();
             Ok((input, CapabilityPacket::SoftwareVersion(cap)))
         }
+        CapabilityType::Role => map(CapabilityRole::parse, CapabilityPacket::Role)(input),
         CapabilityType::PathLimit => {
             map(CapabilityPathLimit::parse, CapabilityPacket::PathLimit)(input)
         }
@@ -117,6 +125,45 @@ fn parse_bgp_attr_as4_path(input: &[u8], length: u16) -> IResult<&[u8], Attribut
     Ok((input, Attribute::As4Path(as_path)))
 }
 
+// AS4_PATH received from a peer which does not support four-octet AS number.
+// Malformed AS4_PATH is discarded instead of resetting the session and
+// confederation segments are removed, RFC 6793 6.
+fn parse_bgp_attr_as4_path_trans(input: &[u8], length: u16) -> IResult<&[u8], Option<Attribute>> {
+    if input.len() < length as usize {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Eof)));
+    }
+    let (attr, input) = input.split_at(length as usize);
+    if length % 2 != 0 || length < 6 {
+        return Ok((input, None));
+    }
+    let Ok((_, segments)) = many0(parse_bgp_attr_as4_segment)(attr) else {
+        return Ok((input, None));
+    };
+    if segments
+        .iter()
+        .any(|seg| seg.asn.is_empty() || !(AS_SET..=AS_CONFED_SET).contains(&seg.typ))
+    {
+        return Ok((input, None));
+    }
+    let segments = segments
+        .into_iter()
+        .filter(|seg| seg.typ == AS_SET || seg.typ == AS_SEQUENCE)
+        .collect();
+    Ok((input, Some(Attribute::As4Path(As4PathAttr { segments }))))
+}
+
+fn parse_bgp_attr_as4_aggregator(input: &[u8], length: u16) -> IResult<&[u8], Option<Attribute>> {
+    if input.len() < length as usize {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Eof)));
+    }
+    let (attr, input) = input.split_at(length as usize);
+    if length != 8 {
+        return Ok((input, None));
+    }
+    let (_, aggregator) = Aggregator4Attr::parse(attr)?;
+    Ok((input, Some(Attribute::Aggregator4(aggregator))))
+}
+
 fn parse_bgp_attr_community(input: &[u8], length: u16) -> IResult<&[u8], Attribute> {
     let (attr, input) = input.split_at(length as usize);
     let (_, community) = CommunityAttr::parse(attr)?;
@@ -129,6 +176,12 @@ fn parse_bgp_attr_mp_reach(input: &[u8], length: u16) -> IResult<&[u8], Attribut
     }
     let (attr, input) = input.split_at(length as usize);
     let (attr, header) = MpNlriReachHeader::parse(attr)?;
+    if header.afi == Afi::IP && header.safi == Safi::Unicast {
+        return parse_bgp_attr_mp_reach_ipv4(input, attr, header.nhop_len);
+    }
+    if header.afi == Afi::IP && header.safi == Safi::MplsVpn {
+        return parse_bgp_attr_mp_reach_vpnv4(input, attr, header.nhop_len);
+    }
     if header.afi != Afi::IP6 || header.safi != Safi::Unicast {
         return Err(nom::Err::Error(make_error(input, ErrorKind::Tag)));
     }
@@ -142,6 +195,58 @@ fn parse_bgp_attr_mp_reach(input: &[u8], length: u16) -> IResult<&[u8], Attribut
     let mp_nlri = MpNlriAttr {
         next_hop: Some(nhop),
         prefix: updates,
+        ipv4_prefix: Vec::new(),
+        vpnv4_next_hop: None,
+        vpnv4_prefix: Vec::new(),
+    };
+    Ok((input, Attribute::MpReachNlri(mp_nlri)))
+}
+
+// IPv4 unicast NLRI with IPv6 next hop, RFC 8950. The next hop is either
+// global address or global and link-local address. Whether the encoding has
+// been negotiated is checked by the peer.
+fn parse_bgp_attr_mp_reach_ipv4<'a>(
+    input: &'a [u8],
+    attr: &'a [u8],
+    nhop_len: u8,
+) -> IResult<&'a [u8], Attribute> {
+    if nhop_len != 16 && nhop_len != 32 {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Tag)));
+    }
+    let (attr, nhop) = be_u128(attr)?;
+    let nhop: Ipv6Addr = Ipv6Addr::from(nhop);
+    let (attr, _) = take(nhop_len as usize - 16)(attr)?;
+    let (attr, _snpa) = be_u8(attr)?;
+    let (_, updates) = many0(parse_ipv4_prefix)(attr)?;
+    let mp_nlri = MpNlriAttr {
+        next_hop: Some(nhop),
+        prefix: Vec::new(),
+        ipv4_prefix: updates,
+        vpnv4_next_hop: None,
+        vpnv4_prefix: Vec::new(),
+    };
+    Ok((input, Attribute::MpReachNlri(mp_nlri)))
+}
+
+// VPN-IPv4 NLRI. The next hop is VPN-IPv4 address which RD is zero.
+fn parse_bgp_attr_mp_reach_vpnv4<'a>(
+    input: &'a [u8],
+    attr: &'a [u8],
+    nhop_len: u8,
+) -> IResult<&'a [u8], Attribute> {
+    if nhop_len != 12 {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Tag)));
+    }
+    let (attr, _rd) = take(8usize)(attr)?;
+    let (attr, nhop) = be_u32(attr)?;
+    let (attr, _snpa) = be_u8(attr)?;
+    let (_, updates) = many0(parse_vpnv4_prefix)(attr)?;
+    let mp_nlri = MpNlriAttr {
+        next_hop: None,
+        prefix: Vec::new(),
+        ipv4_prefix: Vec::new(),
+        vpnv4_next_hop: Some(Ipv4Addr::from(nhop)),
+        vpnv4_prefix: updates,
     };
     Ok((input, Attribute::MpReachNlri(mp_nlri)))
 }
@@ -152,6 +257,30 @@ fn parse_bgp_attr_mp_unreach(input: &[u8], length: u16) -> IResult<&[u8], Attrib
     }
     let (attr, input) = input.split_at(length as usize);
     let (attr, header) = MpNlriUnreachHeader::parse(attr)?;
+    if header.afi == Afi::IP && header.safi == Safi::MplsVpn {
+        let (_, withdrawal) = many0(parse_vpnv4_prefix)(attr)?;
+        let mp_nlri = MpNlriAttr {
+            next_hop: None,
+            prefix: Vec::new(),
+            ipv4_prefix: Vec::new(),
+            vpnv4_next_hop: None,
+            vpnv4_prefix: withdrawal,
+        };
+        return Ok((input, Attribute::MpUnreachNlri(mp_nlri)));
+    }
+    // IPv4 unicast withdrawal of the routes advertised with IPv6 next hop,
+    // RFC 8950.
+    if header.afi == Afi::IP && header.safi == Safi::Unicast {
+        let (_, withdrawal) = many0(parse_ipv4_prefix)(attr)?;
+        let mp_nlri = MpNlriAttr {
+            next_hop: None,
+            prefix: Vec::new(),
+            ipv4_prefix: withdrawal,
+            vpnv4_next_hop: None,
+            vpnv4_prefix: Vec::new(),
+        };
+        return Ok((input, Attribute::MpUnreachNlri(mp_nlri)));
+    }
     if header.afi != Afi::IP6 || header.safi != Safi::Unicast {
         return Err(nom::Err::Error(make_error(input, ErrorKind::Tag)));
     }
@@ -159,17 +288,29 @@ fn parse_bgp_attr_mp_unreach(input: &[u8], length: u16) -> IResult<&[u8], Attrib
     let mp_nlri = MpNlriAttr {
         next_hop: None,
         prefix: withdrawal,
+        ipv4_prefix: Vec::new(),
+        vpnv4_next_hop: None,
+        vpnv4_prefix: Vec::new(),
     };
     Ok((input, Attribute::MpReachNlri(mp_nlri)))
 }
 
+fn parse_bgp_attr_extended_com(input: &[u8], length: u16) -> IResult<&[u8], Attribute> {
+    if input.len() < length as usize {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Eof)));
+    }
+    let (attr, input) = input.split_at(length as usize);
+    let (_, ecoms) = many0(ExtendedCom::parse)(attr)?;
+    Ok((input, Attribute::ExtendedCom(ExtendedComAttr(ecoms))))
+}
+
 fn parse_bgp_attr_large_com(input: &[u8], length: u16) -> IResult<&[u8], Attribute> {
     let (attr, input) = input.split_at(length as usize);
     let (_, lcom) = LargeComAttr::parse(attr)?;
     Ok((input, Attribute::LargeCom(lcom)))
 }
 
-fn parse_bgp_attribute(input: &[u8], as4: bool) -> IResult<&[u8], Attribute> {
+fn parse_bgp_attribute(input: &[u8], as4: bool) -> IResult<&[u8], Option<Attribute>> {
     let (input, header) = AttributeHeader::parse(input)?;
     let ext_len: usize = if header.is_extended() { 2 } else { 1 };
     let (input, exts) = take(ext_len)(input)?;
@@ -179,6 +320,27 @@ fn parse_bgp_attribute(input: &[u8], as4: bool) -> IResult<&[u8], Attribute> {
         ((exts[0] as u16) << 8) + exts[1] as u16
     };
     match AttributeType(header.type_code) {
+        // AS4_PATH and AS4_AGGREGATOR must not be carried between four-octet
+        // AS speakers. Discard them and continue processing the update.
+        AttributeType::As4Path | AttributeType::As4Aggregator if as4 => {
+            let (input, _) = take(attr_len)(input)?;
+            Ok((input, None))
+        }
+        AttributeType::As4Path => parse_bgp_attr_as4_path_trans(input, attr_len),
+        AttributeType::As4Aggregator => parse_bgp_attr_as4_aggregator(input, attr_len),
+        _ => map(
+            parse_bgp_attribute_value(header.type_code, attr_len, as4),
+            Some,
+        )(input),
+    }
+}
+
+fn parse_bgp_attribute_value(
+    type_code: u8,
+    attr_len: u16,
+    as4: bool,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Attribute> {
+    move |input: &[u8]| match AttributeType(type_code) {
         AttributeType::Origin => map(OriginAttr::parse, Attribute::Origin)(input),
         AttributeType::AsPath => {
             if as4 {
@@ -203,15 +365,63 @@ fn parse_bgp_attribute(input: &[u8], as4: bool) -> IResult<&[u8], Attribute> {
         AttributeType::Community => parse_bgp_attr_community(input, attr_len),
         AttributeType::MpReachNlri => parse_bgp_attr_mp_reach(input, attr_len),
         AttributeType::MpUnreachNlri => parse_bgp_attr_mp_unreach(input, attr_len),
+        AttributeType::ExtendedCom => parse_bgp_attr_extended_com(input, attr_len),
         AttributeType::LargeCom => parse_bgp_attr_large_com(input, attr_len),
+        AttributeType::Otc => map(OtcAttr::parse, Attribute::Otc)(input),
         _ => Err(nom::Err::Error(make_error(input, ErrorKind::Tag))),
     }
 }
 
-pub fn parse_bgp_attribute_as(as4: bool) -> impl Fn(&[u8]) -> IResult<&[u8], attr::Attribute> {
+pub fn parse_bgp_attribute_as(
+    as4: bool,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Option<attr::Attribute>> {
     move |i: &[u8]| parse_bgp_attribute(i, as4)
 }
 
+// Reconstruct four-octet AS_PATH and AGGREGATOR from the attributes received
+// from a peer which does not support four-octet AS number, RFC 6793 4.2.3.
+// After this the update carries only As4Path and Aggregator4 regardless of
+// the peer's capability.
+fn attrs_as4_merge(attrs: Vec<Attribute>) -> Vec<Attribute> {
+    let mut as_path: Option<AsPathAttr> = None;
+    let mut as4_path: Option<As4PathAttr> = None;
+    let mut aggregator: Option<AggregatorAttr> = None;
+    let mut aggregator4: Option<Aggregator4Attr> = None;
+    let mut merged = Vec::new();
+    for attr in attrs.into_iter() {
+        match attr {
+            Attribute::AsPath(v) => as_path = Some(v),
+            Attribute::As4Path(v) => as4_path = Some(v),
+            Attribute::Aggregator(v) => aggregator = Some(v),
+            Attribute::Aggregator4(v) => aggregator4 = Some(v),
+            _ => merged.push(attr),
+        }
+    }
+    // When AGGREGATOR does not carry AS_TRANS, AS4_AGGREGATOR and AS4_PATH
+    // are ignored.
+    if let Some(aggregator) = aggregator {
+        match aggregator4 {
+            Some(aggregator4) if aggregator.asn == AS_TRANS => {
+                merged.push(Attribute::Aggregator4(aggregator4));
+            }
+            _ => {
+                as4_path = None;
+                merged.push(Attribute::Aggregator4(Aggregator4Attr {
+                    asn: aggregator.asn as u32,
+                    ip: aggregator.ip,
+                }));
+            }
+        }
+    }
+    if let Some(as_path) = as_path {
+        merged.push(Attribute::As4Path(aspath_merge(
+            &as_path,
+            as4_path.as_ref(),
+        )));
+    }
+    merged
+}
+
 fn parse_bgp_update_attribute(
     input: &[u8],
     length: u16,
@@ -219,7 +429,12 @@ fn parse_bgp_update_attribute(
 ) -> IResult<&[u8], Vec<Attribute>> {
     let (attr, input) = input.split_at(length as usize);
     let (_, attrs) = many0(parse_bgp_attribute_as(as4))(attr)?;
-    Ok((input, attrs))
+    let attrs: Vec<Attribute> = attrs.into_iter().flatten().collect();
+    if as4 {
+        Ok((input, attrs))
+    } else {
+        Ok((input, attrs_as4_merge(attrs)))
+    }
 }
 
 fn plen2size(plen: u8) -> usize {
@@ -288,6 +503,23 @@ pub fn peek_bgp_length(input: &[u8]) -> usize {
     }
 }
 
+// Validate the marker and the length of the message header, RFC 4271 6.1.
+// Returns the length of the message, or the sub-code and the data of the
+// Message Header Error notification.
+pub fn bgp_header_check(input: &[u8], max: usize) -> Result<usize, (MessageError, Vec<u8>)> {
+    if input.len() < BGP_HEADER_LEN as usize {
+        return Ok(0);
+    }
+    if input[..16].iter().any(|b| *b != 0xff) {
+        return Err((MessageError::ConnectionNotSynced, Vec::new()));
+    }
+    let length = peek_bgp_length(input);
+    if length < BGP_HEADER_LEN as usize || length > max {
+        return Err((MessageError::BadMessageLength, input[16..18].to_vec()));
+    }
+    Ok(length)
+}
+
 pub fn parse_bgp_packet(input: &[u8], as4: bool) -> IResult<&[u8], BgpPacket> {
     let (_, header) = peek(BgpHeader::parse)(input)?;
     match header.typ {
@@ -298,6 +530,60 @@ pub fn parse_bgp_packet(input: &[u8], as4: bool) -> IResult<&[u8], BgpPacket> {
         }
         BgpType::Notification => map(parse_bgp_notification_packet, BgpPacket::Notification)(input),
         BgpType::Keepalive => map(BgpHeader::parse, BgpPacket::Keepalive)(input),
+        BgpType::RouteRefresh => map(RouteRefreshPacket::parse, BgpPacket::RouteRefresh)(input),
         _ => Err(nom::Err::Error(make_error(input, ErrorKind::Eof))),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::BytesMut;
+
+    fn header(length: u16) -> BytesMut {
+        BgpHeader::new(BgpType::Update, length).into()
+    }
+
+    #[test]
+    fn header_check() {
+        assert_eq!(bgp_header_check(&header(23), BGP_PACKET_LEN), Ok(23));
+        // Incomplete header is waited for.
+        assert_eq!(bgp_header_check(&header(23)[..10], BGP_PACKET_LEN), Ok(0));
+
+        let long = header(5000);
+        assert_eq!(
+            bgp_header_check(&long, BGP_PACKET_LEN),
+            Err((MessageError::BadMessageLength, vec![0x13, 0x88]))
+        );
+        assert_eq!(bgp_header_check(&long, BGP_EXTENDED_PACKET_LEN), Ok(5000));
+        assert_eq!(
+            bgp_header_check(&header(65535), BGP_EXTENDED_PACKET_LEN),
+            Ok(65535)
+        );
+        assert!(bgp_header_check(&header(18), BGP_EXTENDED_PACKET_LEN).is_err());
+
+        let mut marker = header(23);
+        marker[3] = 0;
+        assert_eq!(
+            bgp_header_check(&marker, BGP_EXTENDED_PACKET_LEN),
+            Err((MessageError::ConnectionNotSynced, Vec::new()))
+        );
+    }
+
+    #[test]
+    fn mp_unreach_ipv4() {
+        // AFI 1, SAFI 1, 10.1.0.0/16 and 192.168.0.0/24.
+        let attr = [0, 1, 1, 16, 10, 1, 24, 192, 168, 0];
+        let (rest, attr) = parse_bgp_attr_mp_unreach(&attr, attr.len() as u16).unwrap();
+        assert!(rest.is_empty());
+        let Attribute::MpUnreachNlri(mp_nlri) = attr else {
+            panic!("not MP_UNREACH_NLRI");
+        };
+        let prefixes: Vec<Ipv4Net> = vec![
+            "10.1.0.0/16".parse().unwrap(),
+            "192.168.0.0/24".parse().unwrap(),
+        ];
+        assert_eq!(mp_nlri.ipv4_prefix, prefixes);
+        assert!(mp_nlri.prefix.is_empty() && mp_nlri.vpnv4_prefix.is_empty());
+    }
+}
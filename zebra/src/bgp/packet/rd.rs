@@ -0,0 +1,210 @@
+use bytes::{BufMut, BytesMut};
+use ipnet::Ipv4Net;
+use nom::bytes::complete::take;
+use nom::error::{make_error, ErrorKind};
+use nom::number::complete::{be_u16, be_u24, be_u8};
+use nom::IResult;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+// Route Distinguisher, RFC 4364 4.2.
+pub const RD_TYPE_AS: u16 = 0;
+pub const RD_TYPE_IP: u16 = 1;
+pub const RD_TYPE_AS4: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RouteDistinguisher {
+    As(u16, u32),
+    Ip(Ipv4Addr, u16),
+    As4(u32, u16),
+}
+
+impl RouteDistinguisher {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, typ) = be_u16(input)?;
+        let (input, v) = take(6usize)(input)?;
+        let rd = match typ {
+            RD_TYPE_AS => Self::As(
+                u16::from_be_bytes([v[0], v[1]]),
+                u32::from_be_bytes([v[2], v[3], v[4], v[5]]),
+            ),
+            RD_TYPE_IP => Self::Ip(
+                Ipv4Addr::new(v[0], v[1], v[2], v[3]),
+                u16::from_be_bytes([v[4], v[5]]),
+            ),
+            RD_TYPE_AS4 => Self::As4(
+                u32::from_be_bytes([v[0], v[1], v[2], v[3]]),
+                u16::from_be_bytes([v[4], v[5]]),
+            ),
+            _ => return Err(nom::Err::Error(make_error(input, ErrorKind::Tag))),
+        };
+        Ok((input, rd))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut buf = [0u8; 8];
+        match self {
+            Self::As(asn, local) => {
+                buf[..2].copy_from_slice(&RD_TYPE_AS.to_be_bytes());
+                buf[2..4].copy_from_slice(&asn.to_be_bytes());
+                buf[4..].copy_from_slice(&local.to_be_bytes());
+            }
+            Self::Ip(addr, local) => {
+                buf[..2].copy_from_slice(&RD_TYPE_IP.to_be_bytes());
+                buf[2..6].copy_from_slice(&addr.octets());
+                buf[6..].copy_from_slice(&local.to_be_bytes());
+            }
+            Self::As4(asn, local) => {
+                buf[..2].copy_from_slice(&RD_TYPE_AS4.to_be_bytes());
+                buf[2..6].copy_from_slice(&asn.to_be_bytes());
+                buf[6..].copy_from_slice(&local.to_be_bytes());
+            }
+        }
+        buf
+    }
+}
+
+impl fmt::Display for RouteDistinguisher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::As(asn, local) => write!(f, "{}:{}", asn, local),
+            Self::Ip(addr, local) => write!(f, "{}:{}", addr, local),
+            Self::As4(asn, local) => write!(f, "{}:{}", asn, local),
+        }
+    }
+}
+
+impl FromStr for RouteDistinguisher {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (global, local) = s.rsplit_once(':').ok_or(())?;
+        if let Ok(addr) = global.parse::<Ipv4Addr>() {
+            let local: u16 = local.parse().map_err(|_| ())?;
+            return Ok(Self::Ip(addr, local));
+        }
+        let asn: u32 = global.parse().map_err(|_| ())?;
+        if asn <= u16::MAX as u32 {
+            let local: u32 = local.parse().map_err(|_| ())?;
+            Ok(Self::As(asn as u16, local))
+        } else {
+            let local: u16 = local.parse().map_err(|_| ())?;
+            Ok(Self::As4(asn, local))
+        }
+    }
+}
+
+// VPN-IPv4 NLRI, RFC 4364 and RFC 8277. The prefix length covers the label,
+// the RD and the IPv4 prefix. Only single label is supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vpnv4Nlri {
+    pub label: u32,
+    pub rd: RouteDistinguisher,
+    pub prefix: Ipv4Net,
+}
+
+const VPN_LABEL_BITS: u8 = 24;
+const VPN_RD_BITS: u8 = 64;
+
+// Label of the withdrawn routes, RFC 8277 2.4.
+const VPN_LABEL_WITHDRAW: u32 = 0x800000;
+
+impl Vpnv4Nlri {
+    // Octets of the NLRI.
+    pub fn encoded_len(&self) -> usize {
+        1 + 3 + 8 + self.prefix.prefix_len().div_ceil(8) as usize
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut, withdraw: bool) {
+        let plen = self.prefix.prefix_len();
+        buf.put_u8(VPN_LABEL_BITS + VPN_RD_BITS + plen);
+        // Bottom of stack.
+        let label = if withdraw {
+            VPN_LABEL_WITHDRAW
+        } else {
+            (self.label << 4) | 1
+        };
+        buf.put(&label.to_be_bytes()[1..]);
+        buf.put(&self.rd.to_bytes()[..]);
+        buf.put(&self.prefix.network().octets()[..plen.div_ceil(8) as usize]);
+    }
+}
+
+pub fn parse_vpnv4_prefix(input: &[u8]) -> IResult<&[u8], Vpnv4Nlri> {
+    let (input, plen) = be_u8(input)?;
+    let Some(plen) = plen.checked_sub(VPN_LABEL_BITS + VPN_RD_BITS) else {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::LengthValue)));
+    };
+    if plen > 32 {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::LengthValue)));
+    }
+    let (input, label) = be_u24(input)?;
+    let (input, rd) = RouteDistinguisher::parse(input)?;
+    let psize = plen.div_ceil(8) as usize;
+    let (input, addr) = take(psize)(input)?;
+    let mut paddr = [0u8; 4];
+    paddr[..psize].copy_from_slice(addr);
+    let prefix = Ipv4Net::new(Ipv4Addr::from(paddr), plen).expect("Ipv4Net create error");
+    let nlri = Vpnv4Nlri {
+        label: label >> 4,
+        rd,
+        prefix,
+    };
+    Ok((input, nlri))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for s in ["100:1", "10.0.0.1:5", "4200000000:7"] {
+            let rd: RouteDistinguisher = s.parse().unwrap();
+            let bytes = rd.to_bytes();
+            let (_, parsed) = RouteDistinguisher::parse(&bytes).unwrap();
+            assert_eq!(parsed, rd);
+            assert_eq!(parsed.to_string(), s);
+        }
+        let bytes = [0u8, 3, 0, 0, 0, 0, 0, 0];
+        assert!(RouteDistinguisher::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn vpnv4_prefix() {
+        let input: &[u8] = &[
+            0x70, // 24 + 64 + 24 bits
+            0x00, 0x3e, 0x81, // label 1000, bottom of stack
+            0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x01, // RD 100:1
+            0x0a, 0x01, 0x02, // 10.1.2.0/24
+        ];
+        let (rest, nlri) = parse_vpnv4_prefix(input).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(nlri.label, 1000);
+        assert_eq!(nlri.rd, RouteDistinguisher::As(100, 1));
+        assert_eq!(nlri.prefix, "10.1.2.0/24".parse().unwrap());
+
+        // Shorter than label and RD.
+        assert!(parse_vpnv4_prefix(&[0x20, 0x0a, 0x01, 0x02, 0x03]).is_err());
+    }
+
+    #[test]
+    fn vpnv4_prefix_encode() {
+        let nlri = Vpnv4Nlri {
+            label: 1000,
+            rd: RouteDistinguisher::As(100, 1),
+            prefix: "10.1.2.0/24".parse().unwrap(),
+        };
+        let mut buf = BytesMut::new();
+        nlri.encode(&mut buf, false);
+        assert_eq!(buf.len(), nlri.encoded_len());
+        let (rest, parsed) = parse_vpnv4_prefix(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, nlri);
+
+        let mut buf = BytesMut::new();
+        nlri.encode(&mut buf, true);
+        assert_eq!(&buf[1..4], &[0x80, 0x00, 0x00]);
+    }
+}
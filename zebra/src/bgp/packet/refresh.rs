@@ -0,0 +1,61 @@
+use super::{BgpHeader, BgpType, BGP_HEADER_LEN};
+use crate::bgp::{Afi, Safi};
+use nom_derive::*;
+use rusticata_macros::newtype_enum;
+
+// ROUTE-REFRESH message length, RFC 2918.
+pub const ROUTE_REFRESH_LEN: u16 = BGP_HEADER_LEN + 4;
+
+// Message subtype of enhanced route refresh, RFC 7313.
+#[derive(Debug, Eq, PartialEq, NomBE, Clone, Copy)]
+pub struct RouteRefreshSubtype(pub u8);
+
+newtype_enum! {
+    impl display RouteRefreshSubtype {
+        Normal = 0,
+        BoRR = 1,
+        EoRR = 2,
+    }
+}
+
+#[derive(Debug, NomBE)]
+pub struct RouteRefreshPacket {
+    pub header: BgpHeader,
+    pub afi: Afi,
+    pub subtype: RouteRefreshSubtype,
+    pub safi: Safi,
+}
+
+impl RouteRefreshPacket {
+    pub fn new(afi: Afi, safi: Safi, subtype: RouteRefreshSubtype) -> Self {
+        Self {
+            header: BgpHeader::new(BgpType::RouteRefresh, ROUTE_REFRESH_LEN),
+            afi,
+            subtype,
+            safi,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{parse_bgp_packet, BgpPacket};
+    use bytes::BytesMut;
+
+    #[test]
+    fn encode_parse() {
+        let packet = RouteRefreshPacket::new(Afi::IP, Safi::Unicast, RouteRefreshSubtype::EoRR);
+        let bytes: BytesMut = packet.into();
+        assert_eq!(bytes.len(), ROUTE_REFRESH_LEN as usize);
+        assert_eq!(&bytes[19..], &[0x00, 0x01, 0x02, 0x01]);
+
+        let (_, packet) = parse_bgp_packet(&bytes, true).unwrap();
+        let BgpPacket::RouteRefresh(packet) = packet else {
+            panic!("not a route refresh");
+        };
+        assert_eq!(packet.afi, Afi::IP);
+        assert_eq!(packet.safi, Safi::Unicast);
+        assert_eq!(packet.subtype, RouteRefreshSubtype::EoRR);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Attribute, BgpHeader};
+use super::{Attribute, BgpHeader, BgpType, BGP_HEADER_LEN};
 use ipnet::Ipv4Net;
 use nom_derive::*;
 
@@ -12,3 +12,119 @@ pub struct UpdatePacket {
     #[nom(Ignore)]
     pub ipv4_withdraw: Vec<Ipv4Net>,
 }
+
+impl UpdatePacket {
+    pub fn new() -> Self {
+        Self {
+            header: BgpHeader::new(BgpType::Update, BGP_HEADER_LEN),
+            attrs: Vec::new(),
+            ipv4_update: Vec::new(),
+            ipv4_withdraw: Vec::new(),
+        }
+    }
+}
+
+impl Default for UpdatePacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::*;
+
+    fn update() -> UpdatePacket {
+        let mut packet = UpdatePacket::new();
+        packet.attrs = vec![
+            Attribute::LocalPref(LocalPrefAttr { local_pref: 200 }),
+            Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: vec![65001, 4200000000],
+                }],
+            }),
+            Attribute::NextHop(NextHopAttr {
+                next_hop: [10, 0, 0, 1],
+            }),
+            Attribute::Aggregator4(Aggregator4Attr {
+                asn: 4200000000,
+                ip: 0x01010101,
+            }),
+            Attribute::Community(CommunityAttr(vec![0xFFFFFF01, 0x00640001])),
+        ];
+        packet.ipv4_update = vec![
+            "10.1.0.0/16".parse().unwrap(),
+            "10.2.3.0/24".parse().unwrap(),
+        ];
+        packet.ipv4_withdraw = vec!["0.0.0.0/0".parse().unwrap()];
+        packet
+    }
+
+    fn parse(bytes: &[u8], as4: bool) -> UpdatePacket {
+        let (rest, packet) = parse_bgp_packet(bytes, as4).unwrap();
+        assert!(rest.is_empty());
+        let BgpPacket::Update(packet) = packet else {
+            panic!("not an update");
+        };
+        packet
+    }
+
+    #[test]
+    fn encode_parse() {
+        let sent = update();
+        for as4 in [true, false] {
+            let bytes = sent.encode(as4);
+            assert_eq!(peek_bgp_length(&bytes), bytes.len());
+            let recv = parse(&bytes, as4);
+            assert_eq!(recv.ipv4_update, sent.ipv4_update);
+            assert_eq!(recv.ipv4_withdraw, sent.ipv4_withdraw);
+            let codes: Vec<u8> = recv.attrs.iter().map(|attr| attr.type_code()).collect();
+            assert_eq!(codes.len(), 6);
+            assert!(recv.attrs.iter().any(|attr| matches!(
+                attr,
+                Attribute::As4Path(v) if v.to_string() == "65001 4200000000"
+            )));
+            assert!(recv.attrs.iter().any(|attr| matches!(
+                attr,
+                Attribute::Aggregator4(v) if v.asn == 4200000000 && v.ip == 0x01010101
+            )));
+            assert!(recv.attrs.iter().any(|attr| matches!(
+                attr,
+                Attribute::LocalPref(v) if v.local_pref == 200
+            )));
+            assert!(recv.attrs.iter().any(|attr| matches!(
+                attr,
+                Attribute::Community(v) if v.0 == vec![0xFFFFFF01, 0x00640001]
+            )));
+        }
+
+        // Attributes are sorted by type code.
+        let bytes = sent.encode(true);
+        let withdraw_len = u16::from_be_bytes([bytes[19], bytes[20]]) as usize;
+        let attr_pos = 21 + withdraw_len;
+        let attr_len = u16::from_be_bytes([bytes[attr_pos], bytes[attr_pos + 1]]) as usize;
+        let mut pos = attr_pos + 2;
+        let mut codes = Vec::new();
+        while pos < attr_pos + 2 + attr_len {
+            codes.push(bytes[pos + 1]);
+            let len = bytes[pos + 2] as usize;
+            pos += 3 + len;
+        }
+        assert_eq!(codes, vec![1, 2, 3, 5, 7, 8]);
+    }
+
+    #[test]
+    fn encode_withdraw() {
+        let mut packet = UpdatePacket::new();
+        packet.ipv4_withdraw = vec!["192.168.0.0/24".parse().unwrap()];
+        let bytes = packet.encode(true);
+        assert_eq!(bytes.len(), 19 + 2 + 4 + 2);
+        let recv = parse(&bytes, true);
+        assert_eq!(recv.ipv4_withdraw, packet.ipv4_withdraw);
+        assert!(recv.attrs.is_empty());
+        assert!(recv.ipv4_update.is_empty());
+    }
+}
@@ -1,22 +1,38 @@
 #![allow(dead_code)]
+use super::adj_rib::{
+    adj_rib_out_update, update_pack, AdjRibOut, ExportPeer, MRAI_EBGP, MRAI_IBGP,
+};
+use super::auth::{tcp_auth_set, TcpAuth};
 use super::handler::Message;
+use super::nexthop::NexthopCache;
 use super::packet::*;
-use super::route::route_from_peer;
+use super::peer_group::PeerTemplate;
+use super::ratelimit::{RateLimitConfig, RateLimitStatRef, TokenBucket};
+use super::role::{role_match, BgpRole};
 use super::route::Route;
+use super::route::{route_clear, route_from_peer, route_refresh_begin, route_refresh_end};
 use super::task::*;
+use super::trace::{PeerTrace, TraceDir, TraceEvent};
+use super::transform::{AsPathPrepend, NextHopSelf, RemovePrivateAs};
+use super::ttl::{ttl_apply, SocketFd, TtlConfig, TtlMode};
+use super::vpn::{vpn_clear, vpn_update_pack, VpnTable};
 use super::BGP_PORT;
-use super::{Afi, AfiSafi, AfiSafis, Bgp, Safi, BGP_HOLD_TIME};
+use super::{Afi, AfiSafi, AfiSafis, Bgp, Safi, BGP_CONNECT_RETRY, BGP_HOLD_TIME};
+use crate::rib::api::RibTx;
 use bytes::BytesMut;
 use ipnet::Ipv4Net;
 use nom::AsBytes;
 use prefix_trie::PrefixMap;
 use serde::Serialize;
 use std::cmp::min;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::collections::{BTreeSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::Ordering;
 use std::time::Instant;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::net::TcpStream;
+use tokio::net::{TcpSocket, TcpStream};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -57,6 +73,13 @@ pub enum Event {
     NotifMsg(NotificationPacket), // 25
     KeepAliveMsg,                 // 26
     UpdateMsg(UpdatePacket),      // 27
+    RouteRefreshMsg(RouteRefreshPacket),
+    MinRouteAdvTimerExpires,
+    AdminReset,
+    AdminShutdown,
+    // Message Header Error detected by the reader with its sub-code and
+    // data.
+    HeaderError(u8, Vec<u8>),
 }
 
 #[derive(Debug, Default)]
@@ -85,17 +108,65 @@ pub struct PeerCounter {
 #[derive(Debug, Default, Clone)]
 pub struct PeerTransportConfig {
     pub passive: bool,
+    pub password: Option<String>,
+    pub ttl: TtlConfig,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct PeerConfig {
     pub transport: PeerTransportConfig,
     pub afi_safi: AfiSafis,
+    pub extended_nexthop: AfiSafis,
     pub four_octet: bool,
     pub route_refresh: bool,
+    pub extended_message: bool,
     pub graceful_restart: Option<u32>,
     pub received: Vec<CapabilityPacket>,
     pub hold_time: Option<u16>,
+    pub keepalive: Option<u16>,
+    pub connect_retry: Option<u16>,
+    pub rate_limit: RateLimitConfig,
+    pub role: Option<BgpRole>,
+    pub next_hop_self: Option<NextHopSelf>,
+    pub remove_private_as: Option<RemovePrivateAs>,
+    pub as_override: bool,
+    pub as_path_prepend: AsPathPrepend,
+    // Free text shown in the neighbor output.
+    pub description: Option<String>,
+}
+
+// Administrative shutdown of the neighbor by its config, by the operator
+// with "clear bgp neighbor shutdown" or by the global shutdown. The neighbor
+// stays in Idle without connecting or accepting connections while any of
+// them is set. The config and the counters are kept.
+#[derive(Debug, Default, Clone)]
+pub struct PeerShutdown {
+    pub config: bool,
+    pub exec: bool,
+    pub global: bool,
+    // Shutdown communication sent with the notification, RFC 9003.
+    pub message: Option<String>,
+}
+
+impl PeerShutdown {
+    pub fn is_down(&self) -> bool {
+        self.config || self.exec || self.global
+    }
+}
+
+// Data of the Administrative Shutdown notification, the length and the
+// UTF-8 message truncated to 255 octets at a character boundary.
+pub fn shutdown_data(message: Option<&str>) -> Vec<u8> {
+    let Some(message) = message.filter(|message| !message.is_empty()) else {
+        return Vec::new();
+    };
+    let mut len = message.len().min(255);
+    while !message.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut data = vec![len as u8];
+    data.extend_from_slice(&message.as_bytes()[..len]);
+    data
 }
 
 #[derive(Debug)]
@@ -135,6 +206,15 @@ pub struct Peer {
     pub timer: PeerTimer,
     pub counter: [PeerCounter; BgpType::Max as usize],
     pub as4: bool,
+    // Address families advertised by both sides.
+    pub afi_safi: AfiSafis,
+    pub extended_nexthop: AfiSafis,
+    pub route_refresh: bool,
+    pub enhanced_refresh: bool,
+    // Both sides advertised the Extended Message capability.
+    pub extended_message: bool,
+    pub rate_stat: RateLimitStatRef,
+    pub trace: PeerTrace,
     pub param: PeerParam,
     pub param_tx: PeerParam,
     pub param_rx: PeerParam,
@@ -142,6 +222,16 @@ pub struct Peer {
     pub tx: UnboundedSender<Message>,
     pub config: PeerConfig,
     pub instant: Option<Instant>,
+    // Local address of the connection, used as the nexthop of the routes
+    // advertised to the peer.
+    pub local_addr: Option<Ipv4Addr>,
+    pub adj_out: AdjRibOut,
+    // Peer group of the dynamic neighbor.
+    pub dynamic: Option<String>,
+    pub peer_group: Option<String>,
+    // Settings configured on the neighbor itself.
+    pub template: PeerTemplate,
+    pub shutdown: PeerShutdown,
 }
 
 impl Peer {
@@ -171,16 +261,30 @@ impl Peer {
             local_identifier: None,
             config: PeerConfig::default(),
             as4: true,
+            afi_safi: AfiSafis::default(),
+            extended_nexthop: AfiSafis::default(),
+            route_refresh: false,
+            enhanced_refresh: false,
+            extended_message: false,
+            rate_stat: RateLimitStatRef::default(),
+            trace: PeerTrace::default(),
             param: PeerParam::default(),
             param_tx: PeerParam::default(),
             param_rx: PeerParam::default(),
             instant: None,
+            local_addr: None,
+            adj_out: AdjRibOut::default(),
+            dynamic: None,
+            peer_group: None,
+            template: PeerTemplate::default(),
+            shutdown: PeerShutdown::default(),
         };
         peer.config
             .afi_safi
             .push(AfiSafi::new(Afi::IP, Safi::Unicast));
         peer.config.four_octet = true;
         peer.config.route_refresh = true;
+        peer.config.extended_message = true;
         // peer.config.graceful_restart = Some(65535);
         peer
     }
@@ -189,6 +293,15 @@ impl Peer {
         let _ = self.tx.clone().send(Message::Event(ident, event));
     }
 
+    // Maximum size of the messages sent to the peer.
+    pub fn max_packet_len(&self) -> usize {
+        if self.extended_message {
+            BGP_EXTENDED_PACKET_LEN
+        } else {
+            BGP_PACKET_LEN
+        }
+    }
+
     pub fn is_passive(&self) -> bool {
         self.config.transport.passive
     }
@@ -200,10 +313,48 @@ impl Peer {
         }
     }
 
+    pub fn auth(&self) -> Option<TcpAuth> {
+        self.config
+            .transport
+            .password
+            .as_ref()
+            .map(|password| TcpAuth::Md5(password.clone()))
+    }
+
+    pub fn ttl_mode(&self) -> TtlMode {
+        let ebgp = matches!(self.peer_type, PeerType::External);
+        self.config.transport.ttl.mode(ebgp)
+    }
+
+    pub fn state_str(&self) -> &str {
+        if self.state == State::Idle && self.shutdown.is_down() {
+            "Idle (Admin)"
+        } else {
+            self.state.to_str()
+        }
+    }
+
     pub fn hold_time(&self) -> u16 {
         self.config.hold_time.unwrap_or(BGP_HOLD_TIME)
     }
 
+    // Keepalive interval sent in the OPEN, one third of the hold time unless
+    // configured.
+    pub fn keepalive(&self) -> u16 {
+        timer_keepalive(self.hold_time(), self.config.keepalive)
+    }
+
+    pub fn connect_retry(&self) -> u64 {
+        self.config.connect_retry.unwrap_or(BGP_CONNECT_RETRY) as u64
+    }
+
+    pub fn min_route_adv(&self) -> u64 {
+        match self.peer_type {
+            PeerType::Internal => MRAI_IBGP,
+            PeerType::External => MRAI_EBGP,
+        }
+    }
+
     pub fn count_clear(&mut self) {
         for count in self.counter.iter_mut() {
             count.sent = 0;
@@ -215,6 +366,9 @@ impl Peer {
 pub struct ConfigRef<'a> {
     pub router_id: &'a Ipv4Addr,
     pub ptree: &'a mut PrefixMap<Ipv4Net, Vec<Route>>,
+    pub vpn: &'a mut VpnTable,
+    pub nexthops: &'a mut NexthopCache,
+    pub rib_pending: &'a mut Vec<RibTx>,
 }
 
 fn update_rib(_bgp: &mut Bgp, id: &Ipv4Addr, _update: &UpdatePacket) {
@@ -225,8 +379,29 @@ pub fn fsm(bgp: &mut Bgp, id: Ipv4Addr, event: Event) {
     let mut bgp_ref = ConfigRef {
         router_id: &bgp.router_id,
         ptree: &mut bgp.ptree,
+        vpn: &mut bgp.vpn,
+        nexthops: &mut bgp.nexthops,
+        rib_pending: &mut bgp.rib_pending,
+    };
+    // Events of the removed dynamic neighbor may be still queued.
+    let Some(peer) = bgp.peers.get_mut(&id) else {
+        return;
     };
-    let peer = bgp.peers.get_mut(&id).unwrap();
+    if let Some(trace) = trace_recv(&event) {
+        peer.trace.record(trace);
+    }
+    // Timers and connections queued before the shutdown.
+    if peer.shutdown.is_down()
+        && matches!(
+            event,
+            Event::Start
+                | Event::ConnRetryTimerExpires
+                | Event::IdleHoldTimerExpires
+                | Event::Connected(_)
+        )
+    {
+        return;
+    }
     let prev_state = peer.state.clone();
     peer.state = match event {
         Event::ConfigUpdate => fsm_config_update(&bgp_ref, peer),
@@ -242,11 +417,56 @@ pub fn fsm(bgp: &mut Bgp, id: Ipv4Addr, event: Event) {
         Event::NotifMsg(packet) => fsm_bgp_notification(peer, packet),
         Event::KeepAliveMsg => fsm_bgp_keepalive(peer),
         Event::UpdateMsg(packet) => fsm_bgp_update(peer, packet, &mut bgp_ref),
+        Event::RouteRefreshMsg(packet) => fsm_bgp_route_refresh(peer, packet, &mut bgp_ref),
+        Event::MinRouteAdvTimerExpires => fsm_min_route_adv_expires(peer),
+        Event::AdminReset => fsm_admin_reset(peer),
+        Event::AdminShutdown => fsm_admin_shutdown(peer),
+        Event::HeaderError(sub_code, data) => fsm_header_error(peer, sub_code, data),
     };
     if prev_state != State::Idle && peer.state == State::Idle {
         peer.state = fsm_stop(peer);
     }
+    if prev_state == State::Established && peer.state != State::Established {
+        peer.adj_out = AdjRibOut::default();
+        peer.timer.min_route_adv = None;
+        // Routes of the peer are removed from the RIB with the session.
+        route_clear(bgp_ref.ptree, bgp_ref.nexthops, peer.address);
+        let msgs = vpn_clear(bgp_ref.vpn, peer.address);
+        bgp_ref.rib_pending.extend(msgs);
+    }
+    if prev_state != peer.state {
+        peer.trace
+            .record(TraceEvent::State(prev_state.clone(), peer.state.clone()));
+    }
     println!("State: {:?} -> {:?}", prev_state, peer.state);
+
+    // Dynamic neighbor is removed when the connection is gone.
+    if peer.dynamic.is_some() && prev_state != State::Active && peer.state == State::Active {
+        bgp.dynamic_peer_delete(id);
+    }
+}
+
+// Summary of the received packet or error event for the peer trace.
+fn trace_recv(event: &Event) -> Option<TraceEvent> {
+    match event {
+        Event::BGPOpen(p) => Some(TraceEvent::Open(TraceDir::Recv, open_asn(p), p.hold_time)),
+        Event::KeepAliveMsg => Some(TraceEvent::Keepalive(TraceDir::Recv)),
+        Event::NotifMsg(p) => Some(TraceEvent::Notification(
+            TraceDir::Recv,
+            p.code.0,
+            p.sub_code,
+        )),
+        Event::UpdateMsg(p) => Some(TraceEvent::Update(
+            TraceDir::Recv,
+            p.ipv4_update.len() as u32,
+            p.ipv4_withdraw.len() as u32,
+        )),
+        Event::RouteRefreshMsg(p) => Some(TraceEvent::RouteRefresh(TraceDir::Recv, p.subtype.0)),
+        Event::HoldTimerExpires => Some(TraceEvent::Error("hold timer expired")),
+        Event::ConnFail => Some(TraceEvent::Error("connection failed")),
+        Event::HeaderError(..) => Some(TraceEvent::Error("message header error")),
+        _ => None,
+    }
 }
 
 fn fsm_config_update(bgp: &ConfigRef, peer: &mut Peer) -> State {
@@ -255,7 +475,10 @@ fn fsm_config_update(bgp: &ConfigRef, peer: &mut Peer) -> State {
 }
 
 pub fn fsm_init(peer: &mut Peer) -> State {
-    if peer.is_passive() {
+    if peer.shutdown.is_down() {
+        peer.timer.idle_hold_timer = None;
+        State::Idle
+    } else if peer.is_passive() {
         peer.timer.idle_hold_timer = None;
         State::Active
     } else {
@@ -270,7 +493,12 @@ pub fn fsm_start(peer: &mut Peer) -> State {
 }
 
 pub fn fsm_stop(peer: &mut Peer) -> State {
-    peer.task.writer = None;
+    // The writer sends the queued messages, e.g. the NOTIFICATION, and ends
+    // when the channel is closed.
+    if let Some(mut writer) = peer.task.writer.take() {
+        writer.detach();
+    }
+    peer.packet_tx = None;
     peer.task.reader = None;
     peer.timer.idle_hold_timer = None;
     peer.timer.connect_retry = None;
@@ -288,6 +516,46 @@ pub fn capability_as4(caps: &Vec<CapabilityPacket>) -> Option<u32> {
     None
 }
 
+// AFI/SAFI which both side advertised IPv6 next hop encoding, RFC 8950.
+// Without the multiprotocol capability only IPv4 unicast is exchanged, RFC
+// 4760 8.
+pub fn capability_afi_safi(config: &PeerConfig, caps: &[CapabilityPacket]) -> AfiSafis {
+    let mut remote = AfiSafis::default();
+    for cap in caps.iter() {
+        if let CapabilityPacket::MultiProtocol(m) = cap {
+            remote.push(m.afi_safi());
+        }
+    }
+    if remote.0.is_empty() {
+        remote.push(AfiSafi::new(Afi::IP, Safi::Unicast));
+    }
+    let mut afi_safis = AfiSafis::default();
+    for afi_safi in config.afi_safi.0.iter() {
+        if remote.has(afi_safi) {
+            afi_safis.push(afi_safi.clone());
+        }
+    }
+    afi_safis
+}
+
+pub fn capability_extended_nexthop(config: &PeerConfig, caps: &[CapabilityPacket]) -> AfiSafis {
+    let mut afi_safis = AfiSafis::default();
+    for afi_safi in config.extended_nexthop.0.iter() {
+        if !config.afi_safi.has(afi_safi) {
+            continue;
+        }
+        let value = ExtendedNextHopValue::new(&afi_safi.afi, &afi_safi.safi, &Afi::IP6);
+        let received = caps.iter().any(|cap| match cap {
+            CapabilityPacket::ExtendedNextHop(m) => m.values.contains(&value),
+            _ => false,
+        });
+        if received {
+            afi_safis.push(afi_safi.clone());
+        }
+    }
+    afi_safis
+}
+
 pub fn open_asn(packet: &OpenPacket) -> u32 {
     let asn = capability_as4(&packet.caps);
     if let Some(asn) = asn {
@@ -323,19 +591,37 @@ pub fn fsm_bgp_open(peer: &mut Peer, packet: OpenPacket) -> State {
         // Send notification.
         return State::Idle;
     }
-    if packet.asn as u32 != peer.peer_as {
-        // Send notification.
-        println!("ASN mismatch");
-        return State::Idle;
-    }
     if packet.bgp_id != peer.address.octets() {
         // Send notification.
         println!("router-id mismatch {:?}", peer.address);
         return State::Idle;
     }
     if packet.hold_time > 0 && packet.hold_time < 3 {
+        peer_send_notification(
+            peer,
+            NotificationCode::OpenMessageError,
+            OpenError::UnacceptableHoldTime as u8,
+            Vec::new(),
+        );
         return State::Idle;
     }
+    // Role mismatch, RFC 9234.
+    if let Some(role) = peer.config.role {
+        let remote = packet.caps.iter().find_map(|cap| match cap {
+            CapabilityPacket::Role(cap) => Some(cap.role),
+            _ => None,
+        });
+        if remote.is_some_and(|remote| !role_match(role, remote)) {
+            println!("role mismatch {:?} {:?}", role, remote);
+            peer_send_notification(
+                peer,
+                NotificationCode::OpenMessageError,
+                OpenError::RoleMismatch as u8,
+                Vec::new(),
+            );
+            return State::Idle;
+        }
+    }
     peer.remote_id = Ipv4Addr::new(
         packet.bgp_id[0],
         packet.bgp_id[1],
@@ -343,18 +629,32 @@ pub fn fsm_bgp_open(peer: &mut Peer, packet: OpenPacket) -> State {
         packet.bgp_id[3],
     );
 
+    // Four-octet AS number is used only when both side advertise it.
+    peer.as4 = peer.config.four_octet && capability_as4(&packet.caps).is_some();
+    peer.afi_safi = capability_afi_safi(&peer.config, &packet.caps);
+    peer.extended_nexthop = capability_extended_nexthop(&peer.config, &packet.caps);
+    peer.route_refresh = peer.config.route_refresh
+        && packet
+            .caps
+            .iter()
+            .any(|cap| matches!(cap, CapabilityPacket::RouteRefresh(_)));
+    peer.enhanced_refresh = peer.config.route_refresh
+        && packet
+            .caps
+            .iter()
+            .any(|cap| matches!(cap, CapabilityPacket::EnhancedRouteRefresh(_)));
+    peer.extended_message = peer.config.extended_message
+        && packet
+            .caps
+            .iter()
+            .any(|cap| matches!(cap, CapabilityPacket::ExtendedMessage(_)));
+
     // Remember received hold time.
     peer.param_rx.hold_time = packet.hold_time;
     peer.param_rx.keepalive = packet.hold_time / 3;
 
     // Hold timer negotiation.
-    if packet.hold_time == 0 {
-        peer.param.hold_time = 0;
-        peer.param.keepalive = 0;
-    } else {
-        peer.param.hold_time = min(packet.hold_time, peer.hold_time());
-        peer.param.keepalive = peer.param.hold_time / 3;
-    }
+    peer.param = timer_negotiate(peer.hold_time(), peer.config.keepalive, packet.hold_time);
     if peer.param.keepalive > 0 {
         peer.timer.keepalive = Some(peer_start_keepalive(peer));
     }
@@ -368,6 +668,25 @@ pub fn fsm_bgp_open(peer: &mut Peer, packet: OpenPacket) -> State {
     State::Established
 }
 
+// Keepalive interval for the hold time. The configured interval is capped at
+// one third of the hold time, and no keepalive is sent without the hold time.
+pub fn timer_keepalive(hold_time: u16, keepalive: Option<u16>) -> u16 {
+    match keepalive {
+        Some(keepalive) => min(keepalive, hold_time / 3),
+        None => hold_time / 3,
+    }
+}
+
+// Hold time is the smaller of the local and the received one, RFC 4271
+// 4.2. Zero on either side disables the hold timer and the keepalives.
+pub fn timer_negotiate(hold_time: u16, keepalive: Option<u16>, remote: u16) -> PeerParam {
+    let hold_time = min(hold_time, remote);
+    PeerParam {
+        hold_time,
+        keepalive: timer_keepalive(hold_time, keepalive),
+    }
+}
+
 pub fn fsm_bgp_notification(peer: &mut Peer, _packet: NotificationPacket) -> State {
     peer.counter[BgpType::Notification as usize].rcvd += 1;
     State::Idle
@@ -379,20 +698,106 @@ pub fn fsm_bgp_keepalive(peer: &mut Peer) -> State {
     State::Established
 }
 
-fn fsm_bgp_update(peer: &mut Peer, packet: UpdatePacket, bgp: &mut ConfigRef) -> State {
+fn fsm_bgp_update(peer: &mut Peer, mut packet: UpdatePacket, bgp: &mut ConfigRef) -> State {
     peer.counter[BgpType::Update as usize].rcvd += 1;
     peer_refresh_holdtimer(peer);
+    peer_update_nexthop_check(&peer.extended_nexthop, &mut packet);
     route_from_peer(peer, packet, bgp);
     State::Established
 }
 
+fn fsm_bgp_route_refresh(
+    peer: &mut Peer,
+    packet: RouteRefreshPacket,
+    bgp: &mut ConfigRef,
+) -> State {
+    peer.counter[BgpType::RouteRefresh as usize].rcvd += 1;
+    if packet.header.length != ROUTE_REFRESH_LEN {
+        peer_send_notification(
+            peer,
+            NotificationCode::RouteRefreshError,
+            RouteRefreshError::InvalidMessageLength as u8,
+            Vec::new(),
+        );
+        return State::Idle;
+    }
+    let ipv4_unicast = packet.afi == Afi::IP && packet.safi == Safi::Unicast;
+    let vpnv4 = packet.afi == Afi::IP && packet.safi == Safi::MplsVpn;
+    match packet.subtype {
+        // Re-advertise the Adj-RIB-Out, between the markers when enhanced
+        // route refresh is negotiated.
+        RouteRefreshSubtype::Normal if ipv4_unicast || vpnv4 => {
+            let (afi, safi) = (packet.afi, packet.safi);
+            if peer.enhanced_refresh {
+                peer_send_route_refresh(peer, afi.clone(), safi.clone(), RouteRefreshSubtype::BoRR);
+            }
+            if vpnv4 {
+                peer.adj_out.vpn.refresh();
+            } else {
+                peer.adj_out.refresh();
+            }
+            peer_send_adj_rib_out(peer);
+            if peer.enhanced_refresh {
+                peer_send_route_refresh(peer, afi, safi, RouteRefreshSubtype::EoRR);
+            }
+        }
+        RouteRefreshSubtype::Normal if peer.enhanced_refresh => {
+            let (afi, safi) = (packet.afi, packet.safi);
+            peer_send_route_refresh(peer, afi.clone(), safi.clone(), RouteRefreshSubtype::BoRR);
+            peer_send_route_refresh(peer, afi, safi, RouteRefreshSubtype::EoRR);
+        }
+        RouteRefreshSubtype::BoRR if peer.enhanced_refresh && ipv4_unicast => {
+            route_refresh_begin(bgp.ptree, peer.address);
+        }
+        RouteRefreshSubtype::EoRR if peer.enhanced_refresh && ipv4_unicast => {
+            route_refresh_end(bgp.ptree, bgp.nexthops, peer.address);
+        }
+        _ => {}
+    }
+    State::Established
+}
+
+// IPv4 NLRI with IPv6 next hop is accepted only when extended next hop
+// encoding is negotiated and the next hop is a unicast address. Otherwise the
+// prefixes are treated as withdrawn, RFC 7606, and the rest of the UPDATE is
+// processed as usual.
+fn peer_update_nexthop_check(extended_nexthop: &AfiSafis, packet: &mut UpdatePacket) {
+    let negotiated = extended_nexthop.has(&AfiSafi::new(Afi::IP, Safi::Unicast));
+    let mut withdraw = Vec::new();
+    for attr in packet.attrs.iter_mut() {
+        let Attribute::MpReachNlri(m) = attr else {
+            continue;
+        };
+        if m.ipv4_prefix.is_empty() {
+            continue;
+        }
+        let valid = m.next_hop.is_some_and(|nhop| {
+            !nhop.is_unspecified() && !nhop.is_loopback() && !nhop.is_multicast()
+        });
+        if negotiated && valid {
+            continue;
+        }
+        if negotiated {
+            println!("Invalid IPv6 next hop {:?} for IPv4 NLRI", m.next_hop);
+        } else {
+            println!("IPv6 next hop for IPv4 NLRI without extended next hop capability");
+        }
+        withdraw.append(&mut m.ipv4_prefix);
+    }
+    packet.ipv4_withdraw.extend(withdraw);
+}
+
 pub fn fsm_connected(peer: &mut Peer, stream: TcpStream) -> State {
     peer.task.connect = None;
+    peer.local_addr = match stream.local_addr() {
+        Ok(SocketAddr::V4(addr)) => Some(*addr.ip()),
+        _ => None,
+    };
     let (packet_tx, packet_rx) = mpsc::unbounded_channel::<BytesMut>();
     peer.packet_tx = Some(packet_tx);
     let (read_half, write_half) = stream.into_split();
     peer.task.reader = Some(peer_start_reader(peer, read_half));
-    peer.task.writer = Some(peer_start_writer(write_half, packet_rx));
+    peer.task.writer = Some(peer_start_writer(peer, write_half, packet_rx));
     peer_send_open(peer);
     peer_send_keepalive(peer);
     State::OpenSent
@@ -403,11 +808,48 @@ pub fn fsm_conn_retry_expires(peer: &mut Peer) -> State {
     State::Connect
 }
 
+pub fn fsm_header_error(peer: &mut Peer, sub_code: u8, data: Vec<u8>) -> State {
+    if peer.packet_tx.is_some() {
+        peer_send_notification(peer, NotificationCode::MessageHeaderError, sub_code, data);
+    }
+    State::Idle
+}
+
 pub fn fsm_holdtimer_expires(peer: &mut Peer) -> State {
     peer_send_notification(peer, NotificationCode::HoldTimerExpired, 0, Vec::new());
     State::Idle
 }
 
+// Hard clear by the operator. The session is started again by the idle hold
+// timer.
+pub fn fsm_admin_reset(peer: &mut Peer) -> State {
+    if peer.packet_tx.is_some() {
+        peer_send_notification(
+            peer,
+            NotificationCode::Cease,
+            NotificationError::AdministrativeReset as u8,
+            Vec::new(),
+        );
+    }
+    State::Idle
+}
+
+// The session is ceased and the neighbor stays in Idle until the shutdown
+// is removed.
+pub fn fsm_admin_shutdown(peer: &mut Peer) -> State {
+    if peer.packet_tx.is_some() {
+        let data = shutdown_data(peer.shutdown.message.as_deref());
+        peer_send_notification(
+            peer,
+            NotificationCode::Cease,
+            NotificationError::AdministrativeShutdown as u8,
+            data,
+        );
+    }
+    peer.task.connect = None;
+    fsm_stop(peer)
+}
+
 pub fn fsm_idle_hold_timer_expires(peer: &mut Peer) -> State {
     peer.timer.idle_hold_timer = None;
     peer.task.connect = Some(peer_start_connection(peer));
@@ -419,6 +861,12 @@ pub fn fsm_keepalive_expires(peer: &mut Peer) -> State {
     State::Established
 }
 
+pub fn fsm_min_route_adv_expires(peer: &mut Peer) -> State {
+    peer.timer.min_route_adv = None;
+    peer_send_adj_rib_out(peer);
+    peer.state.clone()
+}
+
 pub fn fsm_conn_fail(peer: &mut Peer) -> State {
     peer.task.writer = None;
     peer.task.reader = None;
@@ -440,12 +888,16 @@ pub fn peer_start_idle_hold_timer(peer: &Peer) -> Timer {
 pub fn peer_start_connect_retry_timer(peer: &Peer) -> Timer {
     let ident = peer.ident;
     let tx = peer.tx.clone();
-    Timer::new(Timer::second(5), TimerType::Once, move || {
-        let tx = tx.clone();
-        async move {
-            let _ = tx.send(Message::Event(ident, Event::Start));
-        }
-    })
+    Timer::new(
+        Timer::second(peer.connect_retry()),
+        TimerType::Once,
+        move || {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send(Message::Event(ident, Event::Start));
+            }
+        },
+    )
 }
 
 pub fn peer_packet_parse(
@@ -454,7 +906,7 @@ pub fn peer_packet_parse(
     tx: UnboundedSender<Message>,
     config: &mut PeerConfig,
 ) -> Result<(), &'static str> {
-    let as4 = !config.received.is_empty();
+    let as4 = config.four_octet && capability_as4(&config.received).is_some();
 
     if let Ok((_, p)) = parse_bgp_packet(rx, as4) {
         match p {
@@ -471,6 +923,9 @@ pub fn peer_packet_parse(
             BgpPacket::Update(p) => {
                 let _ = tx.send(Message::Event(ident, Event::UpdateMsg(p)));
             }
+            BgpPacket::RouteRefresh(p) => {
+                let _ = tx.send(Message::Event(ident, Event::RouteRefreshMsg(p)));
+            }
         }
         Ok(())
     } else {
@@ -478,13 +933,26 @@ pub fn peer_packet_parse(
     }
 }
 
+fn is_update(packet: &[u8]) -> bool {
+    packet.get(18) == Some(&(BgpType::Update as u8))
+}
+
 pub async fn peer_read(
     ident: Ipv4Addr,
     tx: UnboundedSender<Message>,
     mut read_half: OwnedReadHalf,
     mut config: PeerConfig,
+    stat: RateLimitStatRef,
 ) {
+    let mut bucket = config.rate_limit.inbound_bucket();
     let mut buf = BytesMut::with_capacity(BGP_PACKET_LEN * 2);
+    // Messages over 4096 octets are accepted only when we have advertised
+    // the Extended Message capability, RFC 8654.
+    let max = if config.extended_message {
+        BGP_EXTENDED_PACKET_LEN
+    } else {
+        BGP_PACKET_LEN
+    };
     loop {
         match read_half.read_buf(&mut buf).await {
             Ok(read_len) => {
@@ -492,14 +960,26 @@ pub async fn peer_read(
                     let _ = tx.send(Message::Event(ident, Event::ConnFail));
                     return;
                 }
-                while buf.len() >= BGP_HEADER_LEN as usize
-                    && buf.len() >= peek_bgp_length(buf.as_bytes())
-                {
-                    let length = peek_bgp_length(buf.as_bytes());
+                loop {
+                    let length = match bgp_header_check(buf.as_bytes(), max) {
+                        Ok(length) if length > 0 && buf.len() >= length => length,
+                        Ok(_) => break,
+                        Err((sub_code, data)) => {
+                            let event = Event::HeaderError(sub_code as u8, data);
+                            let _ = tx.send(Message::Event(ident, event));
+                            return;
+                        }
+                    };
 
                     let mut remain = buf.split_off(length);
                     remain.reserve(BGP_PACKET_LEN * 2);
 
+                    if let Some(bucket) = bucket.as_mut() {
+                        if is_update(buf.as_bytes()) && bucket.wait().await {
+                            stat.in_delayed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+
                     match peer_packet_parse(buf.as_bytes(), ident, tx.clone(), &mut config) {
                         Ok(_) => {
                             buf = remain;
@@ -524,30 +1004,90 @@ pub fn peer_start_reader(peer: &Peer, read_half: OwnedReadHalf) -> Task<()> {
     let ident = peer.ident;
     let tx = peer.tx.clone();
     let config = peer.config.clone();
+    let stat = peer.rate_stat.clone();
     Task::spawn(async move {
-        peer_read(ident, tx.clone(), read_half, config).await;
+        peer_read(ident, tx.clone(), read_half, config, stat).await;
     })
 }
 
+// Write the packets to the session. With the outbound rate limit, UPDATEs
+// without a token are queued in order and written as the bucket refills,
+// while the other messages are written right away. KEEPALIVE and
+// NOTIFICATION are not held behind the UPDATEs, so the hold timer of the
+// peer does not expire while the routes are throttled.
+pub async fn peer_write<W: AsyncWrite + Unpin>(
+    write: &mut W,
+    mut rx: UnboundedReceiver<BytesMut>,
+    mut bucket: Option<TokenBucket>,
+    stat: RateLimitStatRef,
+) {
+    let mut updates: VecDeque<BytesMut> = VecDeque::new();
+    loop {
+        let throttled = !updates.is_empty();
+        tokio::select! {
+            biased;
+            msg = rx.recv() => {
+                let Some(msg) = msg else {
+                    return;
+                };
+                if let Some(bucket) = bucket.as_mut().filter(|_| is_update(&msg)) {
+                    if throttled || bucket.acquire(Instant::now()).is_some() {
+                        stat.out_delayed.fetch_add(1, Ordering::Relaxed);
+                        updates.push_back(msg);
+                        continue;
+                    }
+                }
+                let _ = write.write_all(&msg).await;
+            }
+            _ = async { bucket.as_mut().unwrap().wait().await }, if throttled => {
+                if let Some(msg) = updates.pop_front() {
+                    let _ = write.write_all(&msg).await;
+                }
+            }
+        }
+    }
+}
+
 pub fn peer_start_writer(
+    peer: &Peer,
     mut write_half: OwnedWriteHalf,
-    mut rx: UnboundedReceiver<BytesMut>,
+    rx: UnboundedReceiver<BytesMut>,
 ) -> Task<()> {
+    let bucket: Option<TokenBucket> = peer.config.rate_limit.outbound_bucket();
+    let stat = peer.rate_stat.clone();
     Task::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            let _ = write_half.write_all(&msg).await;
-        }
+        peer_write(&mut write_half, rx, bucket, stat).await;
     })
 }
 
+async fn peer_connect(
+    address: Ipv4Addr,
+    auth: Option<TcpAuth>,
+    ttl: TtlMode,
+) -> std::io::Result<TcpStream> {
+    let socket = TcpSocket::new_v4()?;
+    if let Some(auth) = auth {
+        tcp_auth_set(socket.as_raw_fd(), IpAddr::V4(address), &auth)?;
+    }
+    let fd = SocketFd {
+        fd: socket.as_raw_fd(),
+        ipv6: false,
+    };
+    ttl_apply(&fd, ttl)?;
+    socket
+        .connect(SocketAddr::new(IpAddr::V4(address), BGP_PORT))
+        .await
+}
+
 pub fn peer_start_connection(peer: &mut Peer) -> Task<()> {
     let ident = peer.ident;
     let tx = peer.tx.clone();
     let address = peer.address;
+    let auth = peer.auth();
+    let ttl = peer.ttl_mode();
     Task::spawn(async move {
         let tx = tx.clone();
-        let addr = format!("{}:{}", address, BGP_PORT);
-        let result = TcpStream::connect(addr).await;
+        let result = peer_connect(address, auth, ttl).await;
         match result {
             Ok(stream) => {
                 let _ = tx.send(Message::Event(ident, Event::Connected(stream)));
@@ -572,6 +1112,18 @@ pub fn peer_send_open(peer: &mut Peer) {
         let cap = CapabilityMultiProtocol::new(&afi_safi.afi, &afi_safi.safi);
         caps.push(CapabilityPacket::MultiProtocol(cap));
     }
+    let values: Vec<ExtendedNextHopValue> = peer
+        .config
+        .extended_nexthop
+        .0
+        .iter()
+        .filter(|afi_safi| peer.config.afi_safi.has(afi_safi))
+        .map(|afi_safi| ExtendedNextHopValue::new(&afi_safi.afi, &afi_safi.safi, &Afi::IP6))
+        .collect();
+    if !values.is_empty() {
+        let cap = CapabilityExtendedNextHop::new(values);
+        caps.push(CapabilityPacket::ExtendedNextHop(cap));
+    }
     if peer.config.four_octet {
         let cap = CapabilityAs4::new(peer.local_as);
         caps.push(CapabilityPacket::As4(cap));
@@ -581,25 +1133,40 @@ pub fn peer_send_open(peer: &mut Peer) {
         caps.push(CapabilityPacket::RouteRefresh(cap));
         let cap = CapabilityRouteRefresh::new(CapabilityType::RouteRefreshCisco);
         caps.push(CapabilityPacket::RouteRefresh(cap));
+        let cap = CapabilityEnhancedRouteRefresh::new();
+        caps.push(CapabilityPacket::EnhancedRouteRefresh(cap));
+    }
+    if peer.config.extended_message {
+        let cap = CapabilityExtendedMessage::new();
+        caps.push(CapabilityPacket::ExtendedMessage(cap));
     }
     if let Some(restart_time) = peer.config.graceful_restart {
         let cap = CapabilityGracefulRestart::new(restart_time);
         caps.push(CapabilityPacket::GracefulRestart(cap));
     }
+    if let Some(role) = peer.config.role {
+        let cap = CapabilityRole::new(role as u8);
+        caps.push(CapabilityPacket::Role(cap));
+    }
 
     // Remmeber sent hold time.
     peer.param_tx.hold_time = peer.hold_time();
-    peer.param_tx.keepalive = peer.hold_time() / 3;
+    peer.param_tx.keepalive = peer.keepalive();
 
-    let open = OpenPacket::new(
-        header,
-        peer.local_as as u16,
-        peer.hold_time(),
-        &router_id,
-        caps,
-    );
+    // Non-mappable four-octet AS number is sent as AS_TRANS.
+    let asn = if peer.local_as > u16::MAX as u32 {
+        AS_TRANS
+    } else {
+        peer.local_as as u16
+    };
+    let open = OpenPacket::new(header, asn, peer.hold_time(), &router_id, caps);
     let bytes: BytesMut = open.into();
     peer.counter[BgpType::Open as usize].sent += 1;
+    peer.trace.record(TraceEvent::Open(
+        TraceDir::Send,
+        peer.local_as,
+        peer.hold_time(),
+    ));
     let _ = peer.packet_tx.as_ref().unwrap().send(bytes);
 }
 
@@ -609,12 +1176,92 @@ pub fn peer_send_notification(
     sub_code: u8,
     data: Vec<u8>,
 ) {
+    peer.trace
+        .record(TraceEvent::Notification(TraceDir::Send, code.0, sub_code));
     let notification = NotificationPacket::new(code, sub_code, data);
     let bytes: BytesMut = notification.into();
     peer.counter[BgpType::Notification as usize].sent += 1;
     let _ = peer.packet_tx.as_ref().unwrap().send(bytes);
 }
 
+pub fn peer_send_route_refresh(
+    peer: &mut Peer,
+    afi: Afi,
+    safi: Safi,
+    subtype: RouteRefreshSubtype,
+) {
+    peer.trace
+        .record(TraceEvent::RouteRefresh(TraceDir::Send, subtype.0));
+    let refresh = RouteRefreshPacket::new(afi, safi, subtype);
+    let bytes: BytesMut = refresh.into();
+    peer.counter[BgpType::RouteRefresh as usize].sent += 1;
+    let _ = peer.packet_tx.as_ref().unwrap().send(bytes);
+}
+
+pub fn peer_start_min_route_adv_timer(peer: &Peer) -> Timer {
+    let ident = peer.ident;
+    let tx = peer.tx.clone();
+    Timer::new(
+        Timer::second(peer.min_route_adv()),
+        TimerType::Once,
+        move || {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send(Message::Event(ident, Event::MinRouteAdvTimerExpires));
+            }
+        },
+    )
+}
+
+pub fn peer_send_update(peer: &mut Peer, packet: UpdatePacket) {
+    peer.trace.record(TraceEvent::Update(
+        TraceDir::Send,
+        packet.ipv4_update.len() as u32,
+        packet.ipv4_withdraw.len() as u32,
+    ));
+    let bytes = packet.encode(peer.as4);
+    peer.counter[BgpType::Update as usize].sent += 1;
+    let _ = peer.packet_tx.as_ref().unwrap().send(bytes);
+}
+
+// Send the pending changes of the Adj-RIB-Out and start the MRAI timer, RFC
+// 4271 9.2.1.1. The routes of the same attributes are packed into one
+// UPDATE.
+pub fn peer_send_adj_rib_out(peer: &mut Peer) {
+    if peer.state != State::Established || !peer.adj_out.is_pending() {
+        return;
+    }
+    let (withdraw, updates) = peer.adj_out.flush();
+    let max = peer.max_packet_len();
+    for packet in update_pack(withdraw, updates, peer.as4, max) {
+        peer_send_update(peer, packet);
+    }
+    let (withdraw, updates) = peer.adj_out.vpn.flush();
+    for packet in vpn_update_pack(withdraw, updates, peer.as4, max) {
+        peer_send_update(peer, packet);
+    }
+    peer.timer.min_route_adv = Some(peer_start_min_route_adv_timer(peer));
+}
+
+// Export the changed prefixes of the Loc-RIB to the Adj-RIB-Out of the peer.
+// They are sent right away unless the MRAI timer is running.
+pub fn peer_adj_rib_out_update(
+    peer: &mut Peer,
+    ptree: &PrefixMap<Ipv4Net, Vec<Route>>,
+    changed: &BTreeSet<Ipv4Net>,
+    best_external: bool,
+) {
+    if peer.state != State::Established {
+        return;
+    }
+    let mut export = ExportPeer::new(peer);
+    export.best_external = best_external;
+    adj_rib_out_update(&mut peer.adj_out, &export, ptree, changed);
+    if peer.timer.min_route_adv.is_none() {
+        peer_send_adj_rib_out(peer);
+    }
+}
+
 pub fn peer_start_keepalive(peer: &Peer) -> Timer {
     let ident = peer.ident;
     let tx = peer.tx.clone();
@@ -634,6 +1281,7 @@ pub fn peer_send_keepalive(peer: &mut Peer) {
     let header = BgpHeader::new(BgpType::Keepalive, BGP_HEADER_LEN);
     let bytes: BytesMut = header.into();
     peer.counter[BgpType::Keepalive as usize].sent += 1;
+    peer.trace.record(TraceEvent::Keepalive(TraceDir::Send));
     let _ = peer.packet_tx.as_ref().unwrap().send(bytes);
 }
 
@@ -661,8 +1309,34 @@ pub fn peer_refresh_holdtimer(peer: &Peer) {
 pub fn accept(bgp: &mut Bgp, stream: TcpStream, sockaddr: SocketAddr) {
     match sockaddr {
         SocketAddr::V4(addr) => {
-            if let Some(peer) = bgp.peers.get_mut(addr.ip()) {
+            let addr = *addr.ip();
+            if bgp.shutdown {
+                println!("Reject {}: BGP is shut down", addr);
+                return;
+            }
+            // Lookup peer-group for dynamic peer.
+            if !bgp.peers.contains_key(&addr) {
+                if let Err(err) = bgp.dynamic_peer_create(addr) {
+                    println!("Reject {}: {}", addr, err);
+                    return;
+                }
+            }
+            if let Some(peer) = bgp.peers.get_mut(&addr) {
+                if peer.shutdown.is_down() {
+                    println!("Reject {}: neighbor is shut down", addr);
+                    return;
+                }
                 if peer.state == State::Active {
+                    // The minimum TTL is checked from the packets after the
+                    // handshake of the accepted connection.
+                    let fd = SocketFd {
+                        fd: stream.as_raw_fd(),
+                        ipv6: false,
+                    };
+                    if let Err(err) = ttl_apply(&fd, peer.ttl_mode()) {
+                        println!("Reject {}: {}", addr, err);
+                        return;
+                    }
                     peer.state = fsm_connected(peer, stream);
                 }
             }
@@ -671,6 +1345,167 @@ pub fn accept(bgp: &mut Bgp, stream: TcpStream, sockaddr: SocketAddr) {
             println!("IPv6: {:?}", addr);
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn extended_nexthop_cap(afi: Afi, safi: Safi) -> CapabilityPacket {
+        let value = ExtendedNextHopValue::new(&afi, &safi, &Afi::IP6);
+        CapabilityPacket::ExtendedNextHop(CapabilityExtendedNextHop::new(vec![value]))
+    }
+
+    fn packet(typ: BgpType, len: u16) -> BytesMut {
+        let mut bytes: BytesMut = BgpHeader::new(typ, len).into();
+        bytes.resize(len as usize, 0);
+        bytes
+    }
+
+    #[tokio::test]
+    async fn writer_keepalive_not_throttled() {
+        let (mut write, mut read) = tokio::io::duplex(4096);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let bucket = TokenBucket::new(1, 1, Instant::now());
+        let stat = RateLimitStatRef::default();
+        let task = tokio::spawn({
+            let stat = stat.clone();
+            async move { peer_write(&mut write, rx, Some(bucket), stat).await }
+        });
+
+        // One token, the rest of the UPDATEs wait for about a second each.
+        let update_len = BGP_HEADER_LEN + 4;
+        for _ in 0..3 {
+            tx.send(packet(BgpType::Update, update_len)).unwrap();
+        }
+        tx.send(packet(BgpType::Keepalive, BGP_HEADER_LEN)).unwrap();
+
+        let len = (update_len + BGP_HEADER_LEN) as usize;
+        let mut buf = vec![0u8; len];
+        tokio::time::timeout(
+            std::time::Duration::from_millis(500),
+            read.read_exact(&mut buf),
+        )
+        .await
+        .expect("KEEPALIVE is held behind the UPDATEs")
+        .unwrap();
+        assert_eq!(buf[18], BgpType::Update as u8);
+        assert_eq!(buf[update_len as usize + 18], BgpType::Keepalive as u8);
+        assert_eq!(stat.counter().out_delayed, 2);
+
+        drop(tx);
+        task.await.unwrap();
+    }
+
+    #[test]
+    fn extended_nexthop_negotiation() {
+        let ipv4_unicast = AfiSafi::new(Afi::IP, Safi::Unicast);
+        let mut config = PeerConfig::default();
+        config.afi_safi.push(ipv4_unicast.clone());
+
+        // Not configured locally.
+        let caps = vec![extended_nexthop_cap(Afi::IP, Safi::Unicast)];
+        assert!(!capability_extended_nexthop(&config, &caps).has(&ipv4_unicast));
+
+        // Configured but not advertised by the peer.
+        config.extended_nexthop.push(ipv4_unicast.clone());
+        assert!(!capability_extended_nexthop(&config, &[]).has(&ipv4_unicast));
+
+        // Peer advertised for another AFI/SAFI.
+        let caps = vec![extended_nexthop_cap(Afi::IP, Safi::MplsLabel)];
+        assert!(!capability_extended_nexthop(&config, &caps).has(&ipv4_unicast));
+
+        let caps = vec![extended_nexthop_cap(Afi::IP, Safi::Unicast)];
+        assert!(capability_extended_nexthop(&config, &caps).has(&ipv4_unicast));
 
-    // Next, lookup peer-group for dynamic peer.
+        // AFI/SAFI itself is not enabled for the peer.
+        config.afi_safi = AfiSafis::default();
+        assert!(!capability_extended_nexthop(&config, &caps).has(&ipv4_unicast));
+    }
+
+    fn mp_reach_ipv4(nhop: &str, prefix: &str) -> Attribute {
+        Attribute::MpReachNlri(MpNlriAttr {
+            next_hop: Some(nhop.parse().unwrap()),
+            prefix: Vec::new(),
+            ipv4_prefix: vec![prefix.parse().unwrap()],
+            vpnv4_next_hop: None,
+            vpnv4_prefix: Vec::new(),
+        })
+    }
+
+    fn update(attr: Attribute) -> UpdatePacket {
+        let mut packet = UpdatePacket::new();
+        packet.attrs.push(attr);
+        packet.ipv4_update.push("10.1.0.0/16".parse().unwrap());
+        packet
+    }
+
+    fn mp_ipv4_prefix(packet: &UpdatePacket) -> usize {
+        packet
+            .attrs
+            .iter()
+            .map(|attr| match attr {
+                Attribute::MpReachNlri(m) => m.ipv4_prefix.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn extended_nexthop_treat_as_withdraw() {
+        let mut negotiated = AfiSafis::default();
+        negotiated.push(AfiSafi::new(Afi::IP, Safi::Unicast));
+
+        // Accepted when negotiated.
+        let mut packet = update(mp_reach_ipv4("2001:db8::1", "10.2.0.0/16"));
+        peer_update_nexthop_check(&negotiated, &mut packet);
+        assert_eq!(mp_ipv4_prefix(&packet), 1);
+        assert!(packet.ipv4_withdraw.is_empty());
+
+        // Not negotiated, only the MP_REACH prefixes are withdrawn.
+        let mut packet = update(mp_reach_ipv4("2001:db8::1", "10.2.0.0/16"));
+        peer_update_nexthop_check(&AfiSafis::default(), &mut packet);
+        assert_eq!(mp_ipv4_prefix(&packet), 0);
+        let prefix: Ipv4Net = "10.2.0.0/16".parse().unwrap();
+        assert_eq!(packet.ipv4_withdraw, vec![prefix]);
+        assert_eq!(packet.ipv4_update.len(), 1);
+
+        // Invalid next hop even when negotiated.
+        for nhop in ["::", "::1", "ff02::1"] {
+            let mut packet = update(mp_reach_ipv4(nhop, "10.2.0.0/16"));
+            peer_update_nexthop_check(&negotiated, &mut packet);
+            assert_eq!(mp_ipv4_prefix(&packet), 0);
+            assert_eq!(packet.ipv4_withdraw.len(), 1);
+            assert_eq!(packet.ipv4_update.len(), 1);
+        }
+    }
+
+    #[test]
+    fn hold_time_negotiation() {
+        // Smaller hold time of the two wins.
+        let param = timer_negotiate(90, None, 30);
+        assert_eq!((param.hold_time, param.keepalive), (30, 10));
+        let param = timer_negotiate(9, None, 180);
+        assert_eq!((param.hold_time, param.keepalive), (9, 3));
+
+        // Configured keepalive is capped at one third of the hold time.
+        let param = timer_negotiate(90, Some(10), 180);
+        assert_eq!((param.hold_time, param.keepalive), (90, 10));
+        let param = timer_negotiate(90, Some(60), 30);
+        assert_eq!((param.hold_time, param.keepalive), (30, 10));
+
+        // Keepalive of zero sends no keepalive while the hold timer runs.
+        let param = timer_negotiate(90, Some(0), 90);
+        assert_eq!((param.hold_time, param.keepalive), (90, 0));
+    }
+
+    #[test]
+    fn zero_hold_time() {
+        // Zero on either side disables the hold timer and the keepalives.
+        let param = timer_negotiate(90, None, 0);
+        assert_eq!((param.hold_time, param.keepalive), (0, 0));
+        let param = timer_negotiate(0, Some(30), 90);
+        assert_eq!((param.hold_time, param.keepalive), (0, 0));
+        assert_eq!(timer_keepalive(0, Some(30)), 0);
+    }
 }
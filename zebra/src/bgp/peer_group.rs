@@ -0,0 +1,410 @@
+use super::handler::Bgp;
+use super::peer::{fsm_init, peer_adj_rib_out_update, Peer, PeerType, State};
+use super::route::route_clear;
+use super::transform::{AsPathPrepend, NextHopSelf, RemovePrivateAs};
+use super::{Afi, AfiSafi, AfiSafis, Safi};
+use ipnet::Ipv4Net;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::Ipv4Addr;
+
+// Peer groups and dynamic neighbors. A neighbor which is a member of a peer
+// group inherits the settings of the group which are not configured on the
+// neighbor itself, and follows the changes of the group. An inbound
+// connection from an address which is not a configured neighbor is looked up
+// in the listen ranges of the peer groups, and the neighbor is instantiated
+// from the group of the longest matching range. A dynamic neighbor is passive
+// and is removed together with its routes when the session goes down.
+
+pub const MAX_DYNAMIC_PEERS: usize = 100;
+
+// Settings configured on a neighbor or a peer group. Unset ones are inherited
+// from the peer group. Address families of the neighbor and the group are
+// all enabled.
+#[derive(Debug, Default, Clone)]
+pub struct PeerTemplate {
+    pub peer_as: Option<u32>,
+    pub hold_time: Option<u16>,
+    pub keepalive: Option<u16>,
+    pub connect_retry: Option<u16>,
+    pub passive: Option<bool>,
+    pub afi_safi: AfiSafis,
+    pub next_hop_self: Option<NextHopSelf>,
+    pub remove_private_as: Option<RemovePrivateAs>,
+    pub as_override: Option<bool>,
+    pub as_path_prepend: Option<Vec<u32>>,
+    pub as_path_prepend_last_as: Option<u8>,
+}
+
+impl PeerTemplate {
+    pub fn inherit(&self, group: &PeerTemplate) -> PeerTemplate {
+        let mut afi_safi = self.afi_safi.clone();
+        for v in group.afi_safi.0.iter() {
+            if !afi_safi.has(v) {
+                afi_safi.push(v.clone());
+            }
+        }
+        PeerTemplate {
+            peer_as: self.peer_as.or(group.peer_as),
+            hold_time: self.hold_time.or(group.hold_time),
+            keepalive: self.keepalive.or(group.keepalive),
+            connect_retry: self.connect_retry.or(group.connect_retry),
+            passive: self.passive.or(group.passive),
+            afi_safi,
+            next_hop_self: self.next_hop_self.or(group.next_hop_self),
+            remove_private_as: self.remove_private_as.or(group.remove_private_as),
+            as_override: self.as_override.or(group.as_override),
+            as_path_prepend: self
+                .as_path_prepend
+                .clone()
+                .or_else(|| group.as_path_prepend.clone()),
+            as_path_prepend_last_as: self
+                .as_path_prepend_last_as
+                .or(group.as_path_prepend_last_as),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PeerGroup {
+    pub template: PeerTemplate,
+    // Listen ranges of the dynamic neighbors.
+    pub ranges: BTreeSet<Ipv4Net>,
+}
+
+pub type PeerGroups = BTreeMap<String, PeerGroup>;
+
+#[derive(Debug, PartialEq)]
+pub enum DynamicError {
+    NoRange,
+    NoPeerAs(String),
+    Limit(usize),
+}
+
+impl fmt::Display for DynamicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoRange => write!(f, "not in listen range"),
+            Self::NoPeerAs(name) => write!(f, "peer-group {} has no peer-as", name),
+            Self::Limit(max) => write!(f, "dynamic neighbor limit {} reached", max),
+        }
+    }
+}
+
+// Peer group of the longest listen range which contains the address.
+pub fn peer_group_lookup(groups: &PeerGroups, addr: Ipv4Addr) -> Option<&str> {
+    groups
+        .iter()
+        .flat_map(|(name, group)| group.ranges.iter().map(move |range| (name, range)))
+        .filter(|(_, range)| range.contains(&addr))
+        .max_by_key(|(_, range)| range.prefix_len())
+        .map(|(name, _)| name.as_str())
+}
+
+impl Bgp {
+    // Settings of the neighbor in effect.
+    pub fn peer_template(&self, peer: &Peer) -> PeerTemplate {
+        match peer
+            .peer_group
+            .as_ref()
+            .and_then(|name| self.peer_groups.get(name))
+        {
+            Some(group) => peer.template.inherit(&group.template),
+            None => peer.template.clone(),
+        }
+    }
+
+    // Bring the neighbor in sync with the settings in effect.
+    pub fn peer_config_apply(&mut self, addr: Ipv4Addr) {
+        let Some(peer) = self.peers.get(&addr) else {
+            return;
+        };
+        let config = self.peer_template(peer);
+        let Some(peer) = self.peers.get_mut(&addr) else {
+            return;
+        };
+
+        let peer_as = config.peer_as.unwrap_or(0);
+        if peer.peer_as != peer_as {
+            peer.peer_as = peer_as;
+            peer.peer_type = if peer_as == self.asn {
+                PeerType::Internal
+            } else {
+                PeerType::External
+            };
+            peer.update();
+        }
+
+        // Timers take effect when the session is established next time.
+        peer.config.hold_time = config.hold_time;
+        peer.config.keepalive = config.keepalive;
+        peer.config.connect_retry = config.connect_retry;
+
+        let passive = config.passive.unwrap_or(false) || peer.dynamic.is_some();
+        if peer.config.transport.passive != passive {
+            peer.config.transport.passive = passive;
+            peer.timer.idle_hold_timer = None;
+            peer.state = fsm_init(peer);
+        }
+
+        let mut afi_safi = AfiSafis::default();
+        afi_safi.push(AfiSafi::new(Afi::IP, Safi::Unicast));
+        for v in config.afi_safi.0.into_iter() {
+            if !afi_safi.has(&v) {
+                afi_safi.push(v);
+            }
+        }
+        peer.config.afi_safi = afi_safi;
+
+        // Outbound transforms are applied to the Adj-RIB-Out right away.
+        let as_override = config.as_override.unwrap_or(false);
+        let as_path_prepend = AsPathPrepend {
+            asns: config.as_path_prepend.unwrap_or_default(),
+            last_as: config.as_path_prepend_last_as.unwrap_or(0),
+        };
+        if peer.config.next_hop_self != config.next_hop_self
+            || peer.config.remove_private_as != config.remove_private_as
+            || peer.config.as_override != as_override
+            || peer.config.as_path_prepend != as_path_prepend
+        {
+            peer.config.next_hop_self = config.next_hop_self;
+            peer.config.remove_private_as = config.remove_private_as;
+            peer.config.as_override = as_override;
+            peer.config.as_path_prepend = as_path_prepend;
+            // The outbound config differs from the last sync, so the whole
+            // Loc-RIB is exported.
+            peer_adj_rib_out_update(
+                peer,
+                &self.ptree,
+                &BTreeSet::new(),
+                self.advertise_best_external,
+            );
+        }
+    }
+
+    // Propagate the change of the peer group to the members.
+    pub fn peer_group_apply(&mut self, name: &str) {
+        let members: Vec<Ipv4Addr> = self
+            .peers
+            .values()
+            .filter(|peer| peer.peer_group.as_deref() == Some(name))
+            .map(|peer| peer.address)
+            .collect();
+        for addr in members.into_iter() {
+            self.peer_config_apply(addr);
+        }
+    }
+
+    pub fn dynamic_peer_count(&self) -> usize {
+        self.peers
+            .values()
+            .filter(|peer| peer.dynamic.is_some())
+            .count()
+    }
+
+    // Instantiate the neighbor for the inbound connection. The neighbor is
+    // created in Active state, ready to take the connection.
+    pub fn dynamic_peer_create(&mut self, addr: Ipv4Addr) -> Result<(), DynamicError> {
+        let name = peer_group_lookup(&self.peer_groups, addr).ok_or(DynamicError::NoRange)?;
+        let peer_as = self.peer_groups[name]
+            .template
+            .peer_as
+            .ok_or_else(|| DynamicError::NoPeerAs(name.to_string()))?;
+        if self.dynamic_peer_count() >= self.max_dynamic_peers {
+            return Err(DynamicError::Limit(self.max_dynamic_peers));
+        }
+        let mut peer = Peer::new(
+            addr,
+            self.asn,
+            self.router_id,
+            peer_as,
+            addr,
+            self.tx.clone(),
+        );
+        peer.peer_type = if peer_as == self.asn {
+            PeerType::Internal
+        } else {
+            PeerType::External
+        };
+        peer.peer_group = Some(name.to_string());
+        peer.dynamic = Some(name.to_string());
+        peer.config.transport.passive = true;
+        peer.active = true;
+        peer.state = State::Active;
+        self.peers.insert(addr, peer);
+        self.peer_config_apply(addr);
+        Ok(())
+    }
+
+    pub fn dynamic_peer_delete(&mut self, addr: Ipv4Addr) {
+        if self.peers.remove(&addr).is_some() {
+            let removed = route_clear(&mut self.ptree, &mut self.nexthops, addr);
+            println!("Dynamic neighbor {} removed, {} routes", addr, removed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn bgp() -> Bgp {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.asn = 65000;
+        bgp.router_id = "10.0.0.1".parse().unwrap();
+        let mut group = PeerGroup::default();
+        group.template.peer_as = Some(65001);
+        group.ranges.insert("192.168.0.0/16".parse().unwrap());
+        bgp.peer_groups.insert("leaf".to_string(), group);
+        let mut group = PeerGroup::default();
+        group.template.peer_as = Some(65000);
+        group.ranges.insert("192.168.10.0/24".parse().unwrap());
+        bgp.peer_groups.insert("rr-client".to_string(), group);
+        bgp
+    }
+
+    #[test]
+    fn listen_range() {
+        let mut bgp = bgp();
+        let groups = &bgp.peer_groups;
+        assert_eq!(
+            peer_group_lookup(groups, "192.168.1.1".parse().unwrap()),
+            Some("leaf")
+        );
+        // Longest range wins.
+        assert_eq!(
+            peer_group_lookup(groups, "192.168.10.1".parse().unwrap()),
+            Some("rr-client")
+        );
+
+        // Inside the range.
+        let addr: Ipv4Addr = "192.168.1.1".parse().unwrap();
+        assert_eq!(bgp.dynamic_peer_create(addr), Ok(()));
+        let peer = bgp.peers.get(&addr).unwrap();
+        assert_eq!(peer.dynamic.as_deref(), Some("leaf"));
+        assert_eq!(peer.peer_as, 65001);
+        assert!(matches!(peer.peer_type, PeerType::External));
+        assert!(peer.is_passive());
+        assert_eq!(peer.state, State::Active);
+
+        let addr: Ipv4Addr = "192.168.10.1".parse().unwrap();
+        assert_eq!(bgp.dynamic_peer_create(addr), Ok(()));
+        assert!(matches!(
+            bgp.peers.get(&addr).unwrap().peer_type,
+            PeerType::Internal
+        ));
+
+        // Outside of the ranges.
+        let addr: Ipv4Addr = "172.16.0.1".parse().unwrap();
+        assert_eq!(bgp.dynamic_peer_create(addr), Err(DynamicError::NoRange));
+        assert!(!bgp.peers.contains_key(&addr));
+        assert_eq!(bgp.dynamic_peer_count(), 2);
+
+        bgp.dynamic_peer_delete("192.168.1.1".parse().unwrap());
+        assert_eq!(bgp.dynamic_peer_count(), 1);
+    }
+
+    #[test]
+    fn limit() {
+        let mut bgp = bgp();
+        bgp.max_dynamic_peers = 1;
+        assert_eq!(
+            bgp.dynamic_peer_create("192.168.1.1".parse().unwrap()),
+            Ok(())
+        );
+        assert_eq!(
+            bgp.dynamic_peer_create("192.168.1.2".parse().unwrap()),
+            Err(DynamicError::Limit(1))
+        );
+
+        // Configured neighbors are not counted.
+        let addr: Ipv4Addr = "10.0.0.2".parse().unwrap();
+        let peer = Peer::new(addr, 65000, bgp.router_id, 65002, addr, bgp.tx.clone());
+        bgp.peers.insert(addr, peer);
+        assert_eq!(bgp.dynamic_peer_count(), 1);
+
+        bgp.peer_groups.get_mut("leaf").unwrap().template.peer_as = None;
+        bgp.max_dynamic_peers = 2;
+        assert_eq!(
+            bgp.dynamic_peer_create("192.168.1.2".parse().unwrap()),
+            Err(DynamicError::NoPeerAs("leaf".to_string()))
+        );
+    }
+
+    #[test]
+    fn inherit() {
+        let ipv6 = AfiSafi::new(Afi::IP6, Safi::Unicast);
+        let vpn = AfiSafi::new(Afi::IP, Safi::MplsVpn);
+        let group = PeerTemplate {
+            peer_as: Some(65001),
+            hold_time: Some(30),
+            afi_safi: AfiSafis(vec![ipv6.clone()]),
+            remove_private_as: Some(RemovePrivateAs::All),
+            as_override: Some(true),
+            as_path_prepend: Some(vec![65001, 65001]),
+            ..Default::default()
+        };
+        let own = PeerTemplate {
+            hold_time: Some(90),
+            afi_safi: AfiSafis(vec![vpn.clone(), ipv6.clone()]),
+            as_override: Some(false),
+            as_path_prepend_last_as: Some(2),
+            ..Default::default()
+        };
+        let config = own.inherit(&group);
+        assert_eq!(config.peer_as, Some(65001));
+        assert_eq!(config.hold_time, Some(90));
+        assert_eq!(config.passive, None);
+        assert_eq!(config.afi_safi.0, vec![vpn, ipv6]);
+        assert_eq!(config.remove_private_as, Some(RemovePrivateAs::All));
+        // Disabled on the neighbor.
+        assert_eq!(config.as_override, Some(false));
+        assert_eq!(config.as_path_prepend, Some(vec![65001, 65001]));
+        assert_eq!(config.as_path_prepend_last_as, Some(2));
+    }
+
+    #[tokio::test]
+    async fn member() {
+        let mut bgp = bgp();
+        let addr: Ipv4Addr = "10.0.0.2".parse().unwrap();
+        let peer = Peer::new(addr, 65000, bgp.router_id, 0, addr, bgp.tx.clone());
+        bgp.peers.insert(addr, peer);
+        let group = bgp.peer_groups.get_mut("leaf").unwrap();
+        group.template.hold_time = Some(30);
+        group.template.next_hop_self = Some(NextHopSelf::Enabled);
+
+        bgp.peers.get_mut(&addr).unwrap().peer_group = Some("leaf".to_string());
+        bgp.peer_config_apply(addr);
+        let peer = bgp.peers.get(&addr).unwrap();
+        assert_eq!(peer.peer_as, 65001);
+        assert!(matches!(peer.peer_type, PeerType::External));
+        assert_eq!(peer.config.hold_time, Some(30));
+        assert_eq!(peer.config.next_hop_self, Some(NextHopSelf::Enabled));
+
+        // Overridden on the member.
+        bgp.peers.get_mut(&addr).unwrap().template.hold_time = Some(90);
+        bgp.peer_config_apply(addr);
+        assert_eq!(bgp.peers.get(&addr).unwrap().config.hold_time, Some(90));
+
+        // Changes of the group are propagated unless overridden.
+        let group = bgp.peer_groups.get_mut("leaf").unwrap();
+        group.template.hold_time = Some(15);
+        group.template.peer_as = Some(65010);
+        group.template.passive = Some(true);
+        bgp.peer_group_apply("leaf");
+        let peer = bgp.peers.get(&addr).unwrap();
+        assert_eq!(peer.peer_as, 65010);
+        assert_eq!(peer.config.hold_time, Some(90));
+        assert!(peer.is_passive());
+
+        // Left the group.
+        bgp.peers.get_mut(&addr).unwrap().peer_group = None;
+        bgp.peer_config_apply(addr);
+        let peer = bgp.peers.get(&addr).unwrap();
+        assert_eq!(peer.peer_as, 0);
+        assert_eq!(peer.config.next_hop_self, None);
+        assert!(!peer.is_passive());
+    }
+}
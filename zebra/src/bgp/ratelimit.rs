@@ -0,0 +1,165 @@
+// Per-peer UPDATE rate limiting.
+//
+// A token bucket is attached to the reader and writer task of the peer.
+// UPDATE messages beyond the configured rate are delayed and never dropped:
+// on inbound delaying the read pushes back on the peer through TCP flow
+// control, on outbound the message stays in the writer queue.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const BGP_UPDATE_RATE_BURST: u32 = 100;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    pub inbound: Option<u32>,
+    pub outbound: Option<u32>,
+    pub burst: Option<u32>,
+}
+
+impl RateLimitConfig {
+    pub fn burst(&self) -> u32 {
+        self.burst.unwrap_or(BGP_UPDATE_RATE_BURST)
+    }
+
+    pub fn inbound_bucket(&self) -> Option<TokenBucket> {
+        self.inbound
+            .map(|rate| TokenBucket::new(rate, self.burst(), Instant::now()))
+    }
+
+    pub fn outbound_bucket(&self) -> Option<TokenBucket> {
+        self.outbound
+            .map(|rate| TokenBucket::new(rate, self.burst(), Instant::now()))
+    }
+}
+
+// Counters shared between the peer and its reader/writer task.
+#[derive(Debug, Default)]
+pub struct RateLimitStat {
+    pub in_delayed: AtomicU64,
+    pub out_delayed: AtomicU64,
+}
+
+#[derive(Debug, Default, Serialize, Clone, Copy)]
+pub struct RateLimitCounter {
+    pub in_delayed: u64,
+    pub out_delayed: u64,
+}
+
+impl RateLimitStat {
+    pub fn counter(&self) -> RateLimitCounter {
+        RateLimitCounter {
+            in_delayed: self.in_delayed.load(Ordering::Relaxed),
+            out_delayed: self.out_delayed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub type RateLimitStatRef = Arc<RateLimitStat>;
+
+// Tokens are kept in nanoseconds worth of rate so that the arithmetic is
+// exact.
+const TOKEN: u128 = 1_000_000_000;
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: u32,
+    burst: u32,
+    tokens: u128,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32, burst: u32, now: Instant) -> Self {
+        let rate = rate.max(1);
+        let burst = burst.max(1);
+        Self {
+            rate,
+            burst,
+            tokens: burst as u128 * TOKEN,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_nanos();
+        self.tokens = (self.tokens + elapsed * self.rate as u128).min(self.burst as u128 * TOKEN);
+        self.last = now;
+    }
+
+    // Take a token. When no token is available returns the time to wait
+    // before retrying.
+    pub fn acquire(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        if self.tokens >= TOKEN {
+            self.tokens -= TOKEN;
+            None
+        } else {
+            let wait = (TOKEN - self.tokens).div_ceil(self.rate as u128);
+            Some(Duration::from_nanos(wait as u64))
+        }
+    }
+
+    // Wait until a token is available. Returns true when the caller has been
+    // delayed.
+    pub async fn wait(&mut self) -> bool {
+        let mut delayed = false;
+        while let Some(wait) = self.acquire(Instant::now()) {
+            delayed = true;
+            tokio::time::sleep(wait).await;
+        }
+        delayed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10, 5, now);
+        for _ in 0..5 {
+            assert!(bucket.acquire(now).is_none());
+        }
+        let wait = bucket.acquire(now).unwrap();
+        assert!(wait <= Duration::from_millis(100));
+        assert!(bucket.acquire(now + wait).is_none());
+    }
+
+    #[test]
+    fn flood() {
+        // Flood 1000 updates at once with 100 updates per second limit. All
+        // of the updates must pass and the last one not earlier than the
+        // rate allows.
+        let start = Instant::now();
+        let mut now = start;
+        let mut bucket = TokenBucket::new(100, 10, start);
+        let mut passed = 0;
+        let mut delayed = 0;
+        while passed < 1000 {
+            match bucket.acquire(now) {
+                None => passed += 1,
+                Some(wait) => {
+                    delayed += 1;
+                    now += wait;
+                }
+            }
+        }
+        assert_eq!(passed, 1000);
+        assert_eq!(delayed, 990);
+        assert_eq!(now.duration_since(start), Duration::from_millis(9900));
+    }
+
+    #[tokio::test]
+    async fn wait() {
+        let mut bucket = TokenBucket::new(1000, 1, Instant::now());
+        assert!(!bucket.wait().await);
+        let start = Instant::now();
+        assert!(bucket.wait().await);
+        assert!(start.elapsed() >= Duration::from_micros(500));
+    }
+}
@@ -0,0 +1,215 @@
+use super::packet::{Attribute, Attrs};
+use std::str::FromStr;
+
+// BGP Role and Only to Customer (OTC) attribute, RFC 9234. The role of the
+// local AS is configured per neighbor and advertised in the BGP Role
+// capability. The OTC attribute marks a route which has been sent down to a
+// customer or across to a peer, so that it is not propagated up or across
+// again.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgpRole {
+    Provider = 0,
+    RouteServer = 1,
+    RouteServerClient = 2,
+    Customer = 3,
+    Peer = 4,
+}
+
+impl BgpRole {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Provider),
+            1 => Some(Self::RouteServer),
+            2 => Some(Self::RouteServerClient),
+            3 => Some(Self::Customer),
+            4 => Some(Self::Peer),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Provider => "provider",
+            Self::RouteServer => "rs",
+            Self::RouteServerClient => "rs-client",
+            Self::Customer => "customer",
+            Self::Peer => "peer",
+        }
+    }
+
+    // Role expected on the other side of the session.
+    pub fn remote(&self) -> Self {
+        match self {
+            Self::Provider => Self::Customer,
+            Self::RouteServer => Self::RouteServerClient,
+            Self::RouteServerClient => Self::RouteServer,
+            Self::Customer => Self::Provider,
+            Self::Peer => Self::Peer,
+        }
+    }
+}
+
+impl FromStr for BgpRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "provider" => Ok(Self::Provider),
+            "rs" => Ok(Self::RouteServer),
+            "rs-client" => Ok(Self::RouteServerClient),
+            "customer" => Ok(Self::Customer),
+            "peer" => Ok(Self::Peer),
+            _ => Err(()),
+        }
+    }
+}
+
+// Received role is checked only when it is advertised by the neighbor. A
+// mismatch is an OPEN message error, RFC 9234 4.2.
+pub fn role_match(local: BgpRole, remote: u8) -> bool {
+    BgpRole::from_u8(remote) == Some(local.remote())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OtcAction {
+    // The route is a leak on ingress, or must not be sent on egress.
+    Reject,
+    // OTC of the value is added.
+    Set(u32),
+    Keep,
+}
+
+pub fn attrs_otc(attrs: &Attrs) -> Option<u32> {
+    attrs.iter().find_map(|attr| match attr {
+        Attribute::Otc(v) => Some(v.asn),
+        _ => None,
+    })
+}
+
+// Ingress procedure, RFC 9234 5. A route with OTC from a customer or RS
+// client, or with OTC of another AS from a peer, is a leak. A route from a
+// provider, RS or peer is marked with the neighbor's AS.
+pub fn otc_ingress(role: BgpRole, peer_as: u32, attrs: &Attrs) -> OtcAction {
+    match (role, attrs_otc(attrs)) {
+        (BgpRole::Provider | BgpRole::RouteServer, Some(_)) => OtcAction::Reject,
+        (BgpRole::Peer, Some(otc)) if otc != peer_as => OtcAction::Reject,
+        (BgpRole::Customer | BgpRole::RouteServerClient | BgpRole::Peer, None) => {
+            OtcAction::Set(peer_as)
+        }
+        _ => OtcAction::Keep,
+    }
+}
+
+// Egress procedure, RFC 9234 5. A route with OTC is not sent to a provider,
+// RS or peer. A route sent to a customer, RS client or peer is marked with
+// the local AS.
+pub fn otc_egress(role: BgpRole, local_as: u32, attrs: &Attrs) -> OtcAction {
+    match (role, attrs_otc(attrs)) {
+        (BgpRole::Customer | BgpRole::RouteServerClient | BgpRole::Peer, Some(_)) => {
+            OtcAction::Reject
+        }
+        (BgpRole::Provider | BgpRole::RouteServer | BgpRole::Peer, None) => {
+            OtcAction::Set(local_as)
+        }
+        _ => OtcAction::Keep,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::OtcAttr;
+
+    fn otc(asn: u32) -> Attrs {
+        vec![Attribute::Otc(OtcAttr { asn })]
+    }
+
+    #[test]
+    fn role_pair() {
+        assert!(role_match(BgpRole::Provider, BgpRole::Customer as u8));
+        assert!(role_match(BgpRole::Customer, BgpRole::Provider as u8));
+        assert!(role_match(
+            BgpRole::RouteServer,
+            BgpRole::RouteServerClient as u8
+        ));
+        assert!(role_match(BgpRole::Peer, BgpRole::Peer as u8));
+        assert!(!role_match(BgpRole::Peer, BgpRole::Customer as u8));
+        assert!(!role_match(BgpRole::Provider, BgpRole::Provider as u8));
+        assert!(!role_match(BgpRole::Customer, 5));
+        assert_eq!("rs-client".parse(), Ok(BgpRole::RouteServerClient));
+        assert!("transit".parse::<BgpRole>().is_err());
+    }
+
+    #[test]
+    fn ingress() {
+        // Leaked by the customer.
+        assert_eq!(
+            otc_ingress(BgpRole::Provider, 65001, &otc(65100)),
+            OtcAction::Reject
+        );
+        assert_eq!(
+            otc_ingress(BgpRole::RouteServer, 65001, &otc(65001)),
+            OtcAction::Reject
+        );
+        assert_eq!(
+            otc_ingress(BgpRole::Provider, 65001, &Vec::new()),
+            OtcAction::Keep
+        );
+
+        // Peer may only send the routes it marked itself.
+        assert_eq!(
+            otc_ingress(BgpRole::Peer, 65001, &otc(65100)),
+            OtcAction::Reject
+        );
+        assert_eq!(
+            otc_ingress(BgpRole::Peer, 65001, &otc(65001)),
+            OtcAction::Keep
+        );
+        assert_eq!(
+            otc_ingress(BgpRole::Peer, 65001, &Vec::new()),
+            OtcAction::Set(65001)
+        );
+
+        // From the provider.
+        assert_eq!(
+            otc_ingress(BgpRole::Customer, 65001, &Vec::new()),
+            OtcAction::Set(65001)
+        );
+        assert_eq!(
+            otc_ingress(BgpRole::Customer, 65001, &otc(65100)),
+            OtcAction::Keep
+        );
+    }
+
+    #[test]
+    fn egress() {
+        // Up to the provider or across to the peer.
+        assert_eq!(
+            otc_egress(BgpRole::Customer, 65000, &otc(65100)),
+            OtcAction::Reject
+        );
+        assert_eq!(
+            otc_egress(BgpRole::Peer, 65000, &otc(65100)),
+            OtcAction::Reject
+        );
+        assert_eq!(
+            otc_egress(BgpRole::Customer, 65000, &Vec::new()),
+            OtcAction::Keep
+        );
+
+        // Down to the customer.
+        assert_eq!(
+            otc_egress(BgpRole::Provider, 65000, &Vec::new()),
+            OtcAction::Set(65000)
+        );
+        assert_eq!(
+            otc_egress(BgpRole::Provider, 65000, &otc(65100)),
+            OtcAction::Keep
+        );
+        assert_eq!(
+            otc_egress(BgpRole::Peer, 65000, &Vec::new()),
+            OtcAction::Set(65000)
+        );
+    }
+}
@@ -1,33 +1,753 @@
 use super::{
-    packet::{Attrs, UpdatePacket},
-    peer::{ConfigRef, Peer},
+    adj_rib::LOCAL_PREF_DEFAULT,
+    multipath::{multipath_install, multipath_select, path_key, PathKey},
+    nexthop::NexthopCache,
+    packet::{Attribute, Attrs, MpNlriAttr, OtcAttr, RouteTarget, UpdatePacket},
+    peer::{ConfigRef, Peer, PeerType},
+    role::{otc_ingress, OtcAction},
+    vpn::{vpn_update, VpnTable},
 };
+use crate::rib::api::RibTx;
+use crate::rib::vrf::VpnRoute;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::cmp::Ordering;
 use std::net::Ipv4Addr;
+use std::sync::Arc;
 
-// pub enum RouteFrom {
-//     Peer,
-//     Redist,
-//     Import,
-//     Aggregate,
-//     Static,
-// }
+// Source of the route. Locally originated routes have unspecified `from`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RouteFrom {
+    #[default]
+    Peer,
+    // Network statement.
+    Static,
+    Aggregate,
+}
 
 pub struct Route {
     pub from: Ipv4Addr,
-    pub attrs: Attrs,
+    // BGP identifier of the peer, compared at the end of the route selection.
+    pub router_id: Ipv4Addr,
+    pub kind: RouteFrom,
+    // Attributes of the update. Shared by all of the routes in it and by the
+    // Adj-RIB-Out of the peers the route is advertised to.
+    pub attrs: Arc<Attrs>,
+    // AS path rendered for as-path-set matching. It is rendered once per
+    // update and shared by all of the routes in it.
+    pub aspath: Arc<str>,
+    // IPv4 nexthop tracked by the RIB.
+    pub nexthop: Option<Ipv4Addr>,
     pub ibgp: bool,
     pub selected: bool,
+    // Installed to the RIB together with the selected route.
+    pub multipath: bool,
+    // Best route learned from an external peer while the selected one is
+    // learned from an internal peer.
+    pub best_external: bool,
+    // Marked at BoRR and cleared when re-advertised before EoRR.
+    pub stale: bool,
+    // More specific route of a summary-only aggregate, not advertised.
+    pub suppressed: bool,
+    // IPv6 next hop of IPv4 route, RFC 8950. The RIB tracks only IPv4 next
+    // hops, so the route is kept but never selected.
+    pub unresolvable: bool,
+}
+
+pub fn route_aspath(attrs: &Attrs) -> Arc<str> {
+    for attr in attrs.iter() {
+        if let Attribute::As4Path(as_path) = attr {
+            return as_path.to_string().into();
+        }
+    }
+    "".into()
+}
+
+pub fn route_nexthop(attrs: &Attrs) -> Option<Ipv4Addr> {
+    attrs.iter().find_map(|attr| match attr {
+        Attribute::NextHop(nexthop) => Some(Ipv4Addr::from(nexthop.next_hop)),
+        _ => None,
+    })
+}
+
+impl Route {
+    pub fn valid(&self, nexthops: &NexthopCache) -> bool {
+        if self.unresolvable {
+            return false;
+        }
+        match self.nexthop {
+            Some(nexthop) => nexthops.valid(&nexthop),
+            None => true,
+        }
+    }
+}
+
+// BGP decision process, RFC 4271 9.1.2. Among the routes which nexthop is
+// reachable, the route is preferred by:
+//
+//  1. Higher LOCAL_PREF. LOCAL_PREF of the eBGP route is ignored.
+//  2. Locally originated route.
+//  3. Shorter AS_PATH.
+//  4. Lower ORIGIN.
+//  5. Lower MED, compared only among the routes from the same neighboring AS.
+//  6. eBGP route over iBGP route.
+//  7. Lower IGP metric to the nexthop.
+//  8. Lower BGP identifier of the peer, then lower peer address.
+//
+// MED is not compared between all of the routes, so the winner depends on
+// the order of the comparison as the other implementations without
+// deterministic MED.
+fn route_compare(
+    a: &Route,
+    b: &Route,
+    ka: &PathKey,
+    kb: &PathKey,
+    nexthops: &NexthopCache,
+) -> Ordering {
+    let local_pref = |route: &Route, key: &PathKey| {
+        if route.kind == RouteFrom::Peer && !route.ibgp {
+            LOCAL_PREF_DEFAULT
+        } else {
+            key.local_pref
+        }
+    };
+    let med = if ka.neighbor_as == kb.neighbor_as {
+        ka.med.cmp(&kb.med)
+    } else {
+        Ordering::Equal
+    };
+    let metric = |route: &Route| {
+        route
+            .nexthop
+            .map(|addr| nexthops.metric(&addr))
+            .unwrap_or(0)
+    };
+    local_pref(b, kb)
+        .cmp(&local_pref(a, ka))
+        .then((a.kind == RouteFrom::Peer).cmp(&(b.kind == RouteFrom::Peer)))
+        .then(ka.length.cmp(&kb.length))
+        .then(ka.origin.cmp(&kb.origin))
+        .then(med)
+        .then(a.ibgp.cmp(&b.ibgp))
+        .then(metric(a).cmp(&metric(b)))
+        .then(a.router_id.cmp(&b.router_id))
+        .then(a.from.cmp(&b.from))
+}
+
+// Best of the routes which nexthop is reachable and which satisfy the filter.
+fn route_best(
+    routes: &[Route],
+    keys: &[PathKey],
+    nexthops: &NexthopCache,
+    filter: impl Fn(&Route) -> bool,
+) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for (i, route) in routes.iter().enumerate() {
+        if !route.valid(nexthops) || !filter(route) {
+            continue;
+        }
+        let better = match best {
+            Some(b) => route_compare(route, &routes[b], &keys[i], &keys[b], nexthops).is_lt(),
+            None => true,
+        };
+        if better {
+            best = Some(i);
+        }
+    }
+    best
 }
 
-pub fn route_from_peer(peer: &mut Peer, packet: UpdatePacket, bgp: &mut ConfigRef) {
+// Select the best route. When the selected route is learned from an internal
+// peer, the best route learned from an external peer is tracked as the best
+// external, which is advertised to the internal peers with advertise
+// best-external. Returns true when the selection or the multipath has been
+// changed.
+pub fn route_select(routes: &mut [Route], nexthops: &NexthopCache) -> bool {
+    let prev = routes.iter().position(|route| route.selected);
+    let prev_external = routes.iter().position(|route| route.best_external);
+    let keys: Vec<PathKey> = routes.iter().map(|route| path_key(&route.attrs)).collect();
+    let next = route_best(routes, &keys, nexthops, |_| true);
+    let next_external = next.filter(|i| routes[*i].ibgp).and_then(|_| {
+        route_best(routes, &keys, nexthops, |route| {
+            route.kind == RouteFrom::Peer && !route.ibgp
+        })
+    });
+    for (i, route) in routes.iter_mut().enumerate() {
+        route.selected = Some(i) == next;
+        route.best_external = Some(i) == next_external;
+    }
+    let multipath = multipath_select(routes, nexthops);
+    prev != next || prev_external != next_external || multipath
+}
+
+// Select the routes of the prefix, and update the RIB when the selection has
+// been changed. The prefix is exported to the peers again as the attributes
+// may have been changed without the selection.
+pub fn route_update(prefix: Ipv4Net, routes: &mut [Route], nexthops: &mut NexthopCache) {
+    nexthops.changed.insert(prefix);
+    if route_select(routes, nexthops) {
+        let msg = multipath_install(prefix, routes);
+        nexthops.pending.push(msg);
+    }
+}
+
+// The route replaces the one from the same peer without removing the prefix
+// from the RIB in between.
+fn route_add(bgp: &mut ConfigRef, prefix: Ipv4Net, route: Route) {
+    if let Some(nexthop) = route.nexthop {
+        bgp.nexthops.lock(nexthop);
+    }
+    let routes = bgp.ptree.entry(prefix).or_default();
+    if let Some(index) = routes.iter().position(|r| r.from == route.from) {
+        if let Some(nexthop) = routes.remove(index).nexthop {
+            bgp.nexthops.unlock(nexthop);
+        }
+    }
+    routes.push(route);
+    route_update(prefix, routes, bgp.nexthops);
+}
+
+fn route_remove(bgp: &mut ConfigRef, prefix: Ipv4Net, from: Ipv4Addr) {
+    route_remove_entry(bgp.ptree, bgp.nexthops, prefix, from);
+}
+
+fn route_remove_entry(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    prefix: Ipv4Net,
+    from: Ipv4Addr,
+) {
+    let Some(routes) = ptree.get_mut(&prefix) else {
+        return;
+    };
+    let Some(index) = routes.iter().position(|route| route.from == from) else {
+        return;
+    };
+    let route = routes.remove(index);
+    if let Some(nexthop) = route.nexthop {
+        nexthops.unlock(nexthop);
+    }
+    if routes.is_empty() {
+        ptree.remove(&prefix);
+        nexthops.changed.insert(prefix);
+        if route.selected {
+            nexthops.pending.push(multipath_install(prefix, &[]));
+        }
+    } else {
+        route_update(prefix, routes, nexthops);
+    }
+}
+
+// Enhanced route refresh, RFC 7313. Routes of the peer are marked stale at
+// BoRR. Re-advertised routes replace the stale ones, and the routes still
+// stale at EoRR are removed. Returns the number of removed routes.
+pub fn route_refresh_begin(ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>, from: Ipv4Addr) {
+    for (_, routes) in ptree.iter_mut() {
+        for route in routes.iter_mut().filter(|route| route.from == from) {
+            route.stale = true;
+        }
+    }
+}
+
+pub fn route_refresh_end(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    from: Ipv4Addr,
+) -> usize {
+    let stale: Vec<Ipv4Net> = ptree
+        .iter()
+        .filter(|(_, routes)| routes.iter().any(|route| route.from == from && route.stale))
+        .map(|(prefix, _)| *prefix)
+        .collect();
+    for prefix in stale.iter() {
+        route_remove_entry(ptree, nexthops, *prefix, from);
+    }
+    stale.len()
+}
+
+// Remove all of the routes from the peer. Returns the number of removed
+// routes.
+pub fn route_clear(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    from: Ipv4Addr,
+) -> usize {
+    let prefixes: Vec<Ipv4Net> = ptree
+        .iter()
+        .filter(|(_, routes)| routes.iter().any(|route| route.from == from))
+        .map(|(prefix, _)| *prefix)
+        .collect();
+    for prefix in prefixes.iter() {
+        route_remove_entry(ptree, nexthops, *prefix, from);
+    }
+    prefixes.len()
+}
+
+// Re-run the route selection of all of the routes using the nexthop.
+pub fn route_nexthop_update(
+    ptree: &mut PrefixMap<Ipv4Net, Vec<Route>>,
+    nexthops: &mut NexthopCache,
+    nexthop: Option<Ipv4Addr>,
+) {
+    for (prefix, routes) in ptree.iter_mut() {
+        if nexthop.is_none() || routes.iter().any(|route| route.nexthop == nexthop) {
+            route_update(*prefix, routes, nexthops);
+        }
+    }
+}
+
+pub fn route_targets(attrs: &Attrs) -> Vec<RouteTarget> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr {
+            Attribute::ExtendedCom(ecom) => Some(ecom.route_targets()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+pub fn route_bandwidth(attrs: &Attrs) -> Option<u64> {
+    attrs.iter().find_map(|attr| match attr {
+        Attribute::ExtendedCom(ecom) => ecom.link_bandwidth().map(|lb| lb.bandwidth),
+        _ => None,
+    })
+}
+
+// VPN-IPv4 routes are not selected here. They are kept for the show and
+// passed to the RIB which imports them to the VRFs by route-target.
+fn route_vpn_from_peer(
+    peer: &Peer,
+    vpn: &mut VpnTable,
+    attrs: &Attrs,
+    mp_nlri: &MpNlriAttr,
+    add: bool,
+) -> Vec<RibTx> {
+    let nexthop = mp_nlri.vpnv4_next_hop.unwrap_or(peer.address);
+    let (rts, bandwidth) = if add {
+        (route_targets(attrs), route_bandwidth(attrs))
+    } else {
+        (Vec::new(), None)
+    };
+    mp_nlri
+        .vpnv4_prefix
+        .iter()
+        .map(|nlri| {
+            let route = VpnRoute {
+                rd: nlri.rd,
+                prefix: nlri.prefix,
+                label: nlri.label,
+                nexthop,
+                route_targets: rts.clone(),
+                from: peer.address,
+                bandwidth,
+            };
+            vpn_update(vpn, &route, add);
+            if add {
+                RibTx::VpnRouteAdd(route)
+            } else {
+                RibTx::VpnRouteDel(route)
+            }
+        })
+        .collect()
+}
+
+pub fn route_from_peer(peer: &mut Peer, mut packet: UpdatePacket, bgp: &mut ConfigRef) {
+    for ipv4 in packet.ipv4_withdraw.iter() {
+        route_remove(bgp, *ipv4, peer.address);
+    }
+    // Route leak is treated as withdraw, RFC 9234.
+    let mut leak = false;
+    if let Some(role) = peer.config.role {
+        match otc_ingress(role, peer.peer_as, &packet.attrs) {
+            OtcAction::Reject => leak = true,
+            OtcAction::Set(asn) => packet.attrs.push(Attribute::Otc(OtcAttr { asn })),
+            OtcAction::Keep => {}
+        }
+    }
+    let aspath = route_aspath(&packet.attrs);
+    let nexthop = route_nexthop(&packet.attrs);
+    let ibgp = matches!(peer.peer_type, PeerType::Internal);
+    let attrs = Arc::new(packet.attrs);
     for ipv4 in packet.ipv4_update.iter() {
         let route = Route {
             from: peer.address,
-            attrs: packet.attrs.clone(),
-            ibgp: false,
+            router_id: peer.remote_id,
+            kind: RouteFrom::Peer,
+            attrs: attrs.clone(),
+            aspath: aspath.clone(),
+            nexthop,
+            ibgp,
             selected: false,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
         };
-        bgp.ptree.entry(*ipv4).or_default().push(route);
-        //let node = bgp.ptree.get(&ipv4);
+        if leak {
+            println!("route leak {} from {}", ipv4, peer.address);
+            route_remove(bgp, *ipv4, peer.address);
+        } else {
+            route_add(bgp, *ipv4, route);
+        }
+    }
+    for attr in attrs.iter() {
+        if let Attribute::MpUnreachNlri(mp_nlri) = attr {
+            let msgs = route_vpn_from_peer(peer, bgp.vpn, &attrs, mp_nlri, false);
+            bgp.rib_pending.extend(msgs);
+            for ipv4 in mp_nlri.ipv4_prefix.iter() {
+                route_remove(bgp, *ipv4, peer.address);
+            }
+        }
+        if let Attribute::MpReachNlri(mp_nlri) = attr {
+            let msgs = route_vpn_from_peer(peer, bgp.vpn, &attrs, mp_nlri, true);
+            bgp.rib_pending.extend(msgs);
+            // IPv4-mapped next hop is tracked as IPv4 address.
+            let mapped = mp_nlri.next_hop.and_then(|nhop| nhop.to_ipv4_mapped());
+            for ipv4 in mp_nlri.ipv4_prefix.iter() {
+                let route = Route {
+                    from: peer.address,
+                    router_id: peer.remote_id,
+                    kind: RouteFrom::Peer,
+                    attrs: attrs.clone(),
+                    aspath: aspath.clone(),
+                    nexthop: mapped,
+                    ibgp,
+                    selected: false,
+                    multipath: false,
+                    best_external: false,
+                    stale: false,
+                    suppressed: false,
+                    unresolvable: mapped.is_none(),
+                };
+                if leak {
+                    println!("route leak {} from {}", ipv4, peer.address);
+                    route_remove(bgp, *ipv4, peer.address);
+                } else {
+                    route_add(bgp, *ipv4, route);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{As4PathAttr, As4Segment, LocalPrefAttr, MedAttr, AS_SEQUENCE};
+    use crate::rib::api::{NexthopUpdate, RibTx};
+    use std::collections::BTreeSet;
+
+    fn route(from: &str, nexthop: &str) -> Route {
+        Route {
+            from: from.parse().unwrap(),
+            router_id: from.parse().unwrap(),
+            kind: RouteFrom::Peer,
+            attrs: Arc::new(Vec::new()),
+            aspath: "".into(),
+            nexthop: Some(nexthop.parse().unwrap()),
+            ibgp: false,
+            selected: false,
+            multipath: false,
+            best_external: false,
+            stale: false,
+            suppressed: false,
+            unresolvable: false,
+        }
+    }
+
+    fn resolved(addr: &str, prefix: Option<&str>) -> NexthopUpdate {
+        NexthopUpdate {
+            addr: addr.parse().unwrap(),
+            resolved: prefix.map(|p| p.parse().unwrap()),
+            metric: 0,
+        }
+    }
+
+    fn selected(ptree: &PrefixMap<Ipv4Net, Vec<Route>>, prefix: &Ipv4Net) -> Option<Ipv4Addr> {
+        ptree
+            .get(prefix)?
+            .iter()
+            .find(|route| route.selected)
+            .map(|route| route.from)
+    }
+
+    #[test]
+    fn nexthop_flap() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let prefix: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        nexthops.lock("10.0.1.1".parse().unwrap());
+        assert_eq!(nexthops.pending.len(), 2);
+        ptree.insert(
+            prefix,
+            vec![route("1.1.1.1", "10.0.0.1"), route("2.2.2.2", "10.0.1.1")],
+        );
+
+        // Nothing is selected until the nexthops are resolved.
+        route_nexthop_update(&mut ptree, &mut nexthops, None);
+        assert_eq!(selected(&ptree, &prefix), None);
+
+        assert!(nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/24"))));
+        assert!(nexthops.update(resolved("10.0.1.1", Some("10.0.1.0/24"))));
+        route_nexthop_update(&mut ptree, &mut nexthops, None);
+        assert_eq!(selected(&ptree, &prefix), "1.1.1.1".parse().ok());
+
+        // IGP route under the first nexthop goes away.
+        assert!(nexthops.update(resolved("10.0.0.1", None)));
+        route_nexthop_update(&mut ptree, &mut nexthops, "10.0.0.1".parse().ok());
+        assert_eq!(selected(&ptree, &prefix), "2.2.2.2".parse().ok());
+
+        // Both of them are gone.
+        assert!(nexthops.update(resolved("10.0.1.1", None)));
+        route_nexthop_update(&mut ptree, &mut nexthops, "10.0.1.1".parse().ok());
+        assert_eq!(selected(&ptree, &prefix), None);
+
+        // Restored.
+        assert!(nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/24"))));
+        route_nexthop_update(&mut ptree, &mut nexthops, "10.0.0.1".parse().ok());
+        assert_eq!(selected(&ptree, &prefix), "1.1.1.1".parse().ok());
+
+        // Change of resolving route keeps the nexthop valid.
+        assert!(!nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/16"))));
+    }
+
+    #[test]
+    fn best_external() {
+        let mut nexthops = NexthopCache::default();
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        nexthops.lock("10.0.1.1".parse().unwrap());
+        nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/24")));
+        nexthops.update(resolved("10.0.1.1", Some("10.0.1.0/24")));
+
+        // Internal route wins by LOCAL_PREF.
+        let mut internal = route("1.1.1.1", "10.0.0.1");
+        internal.ibgp = true;
+        internal.attrs = Arc::new(vec![local_pref(200)]);
+        let mut routes = vec![internal, route("2.2.2.2", "10.0.1.1")];
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(routes[0].selected && !routes[0].best_external);
+        assert!(!routes[1].selected && routes[1].best_external);
+        assert!(!route_select(&mut routes, &nexthops));
+
+        // External route is not reachable.
+        nexthops.update(resolved("10.0.1.1", None));
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(routes[0].selected);
+        assert!(!routes[1].best_external);
+
+        // External route is the best.
+        nexthops.update(resolved("10.0.1.1", Some("10.0.1.0/24")));
+        routes[0].attrs = Arc::new(Vec::new());
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(routes[1].selected);
+        assert!(routes.iter().all(|route| !route.best_external));
+    }
+
+    fn local_pref(local_pref: u32) -> Attribute {
+        Attribute::LocalPref(LocalPrefAttr { local_pref })
+    }
+
+    fn with_attrs(from: &str, nexthop: &str, asn: &[u32], med: u32, ibgp: bool) -> Route {
+        let mut route = route(from, nexthop);
+        route.ibgp = ibgp;
+        route.attrs = Arc::new(vec![
+            Attribute::As4Path(As4PathAttr {
+                segments: vec![As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: asn.to_vec(),
+                }],
+            }),
+            Attribute::Med(MedAttr { med }),
+        ]);
+        route
+    }
+
+    fn best(routes: &mut [Route], nexthops: &NexthopCache) -> Ipv4Addr {
+        route_select(routes, nexthops);
+        routes.iter().find(|route| route.selected).unwrap().from
+    }
+
+    #[test]
+    fn decision_process() {
+        let mut nexthops = NexthopCache::default();
+        for addr in ["10.0.0.1", "10.0.0.2"] {
+            nexthops.lock(addr.parse().unwrap());
+            nexthops.update(resolved(addr, Some("10.0.0.0/24")));
+        }
+        let one: Ipv4Addr = "1.1.1.1".parse().unwrap();
+        let two: Ipv4Addr = "2.2.2.2".parse().unwrap();
+
+        // Shorter AS_PATH regardless of the arrival order.
+        let mut routes = vec![
+            with_attrs("1.1.1.1", "10.0.0.1", &[65001, 65100], 0, false),
+            with_attrs("2.2.2.2", "10.0.0.2", &[65002], 0, false),
+        ];
+        assert_eq!(best(&mut routes, &nexthops), two);
+
+        // Higher LOCAL_PREF of iBGP route wins over shorter AS_PATH.
+        let mut attrs = (*routes[0].attrs).clone();
+        attrs.push(local_pref(200));
+        routes[0].attrs = Arc::new(attrs);
+        routes[0].ibgp = true;
+        assert_eq!(best(&mut routes, &nexthops), one);
+
+        // LOCAL_PREF of eBGP route is ignored.
+        routes[0].ibgp = false;
+        assert_eq!(best(&mut routes, &nexthops), two);
+
+        // Lower MED from the same neighboring AS.
+        let mut routes = vec![
+            with_attrs("1.1.1.1", "10.0.0.1", &[65001], 20, false),
+            with_attrs("2.2.2.2", "10.0.0.2", &[65001], 10, false),
+        ];
+        assert_eq!(best(&mut routes, &nexthops), two);
+
+        // MED is not compared between the different neighboring AS.
+        let mut routes = vec![
+            with_attrs("1.1.1.1", "10.0.0.1", &[65001], 20, false),
+            with_attrs("2.2.2.2", "10.0.0.2", &[65002], 10, false),
+        ];
+        assert_eq!(best(&mut routes, &nexthops), one);
+
+        // eBGP over iBGP.
+        let mut routes = vec![
+            with_attrs("1.1.1.1", "10.0.0.1", &[65001], 0, true),
+            with_attrs("2.2.2.2", "10.0.0.2", &[65001], 0, false),
+        ];
+        assert_eq!(best(&mut routes, &nexthops), two);
+
+        // Lower IGP metric.
+        routes[1].ibgp = true;
+        nexthops.update(NexthopUpdate {
+            addr: "10.0.0.1".parse().unwrap(),
+            resolved: "10.0.0.0/24".parse().ok(),
+            metric: 10,
+        });
+        assert_eq!(best(&mut routes, &nexthops), two);
+
+        // Lower BGP identifier.
+        nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/24")));
+        routes[1].router_id = "0.0.0.1".parse().unwrap();
+        assert_eq!(best(&mut routes, &nexthops), two);
+        routes[1].router_id = two;
+        assert_eq!(best(&mut routes, &nexthops), one);
+    }
+
+    #[test]
+    fn unresolvable() {
+        let mut nexthops = NexthopCache::default();
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        nexthops.update(resolved("10.0.0.1", Some("10.0.0.0/24")));
+
+        // Route with IPv6 next hop is never selected over the others.
+        let mut ipv6 = route("1.1.1.1", "10.0.0.1");
+        ipv6.nexthop = None;
+        ipv6.unresolvable = true;
+        let mut routes = vec![ipv6, route("2.2.2.2", "10.0.0.1")];
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(!routes[0].selected && routes[1].selected);
+
+        routes.pop();
+        assert!(route_select(&mut routes, &nexthops));
+        assert!(!routes[0].selected);
+    }
+
+    #[test]
+    fn resolve_via_default() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let prefix: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        ptree.insert(prefix, vec![route("1.1.1.1", "10.0.0.1")]);
+
+        // Resolved only via default route.
+        assert!(!nexthops.update(resolved("10.0.0.1", Some("0.0.0.0/0"))));
+        route_nexthop_update(&mut ptree, &mut nexthops, None);
+        assert_eq!(selected(&ptree, &prefix), None);
+
+        nexthops.resolve_via_default = true;
+        route_nexthop_update(&mut ptree, &mut nexthops, None);
+        assert_eq!(selected(&ptree, &prefix), "1.1.1.1".parse().ok());
+
+        nexthops.resolve_via_default = false;
+        route_nexthop_update(&mut ptree, &mut nexthops, None);
+        assert_eq!(selected(&ptree, &prefix), None);
+    }
+
+    #[test]
+    fn refresh_stale() {
+        let mut nexthops = NexthopCache::default();
+        let mut ptree = PrefixMap::<Ipv4Net, Vec<Route>>::new();
+        let kept: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+        let purged: Ipv4Net = "192.168.1.0/24".parse().unwrap();
+        let peer: Ipv4Addr = "1.1.1.1".parse().unwrap();
+
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        nexthops.lock("10.0.0.1".parse().unwrap());
+        ptree.insert(kept, vec![route("1.1.1.1", "10.0.0.1")]);
+        ptree.insert(
+            purged,
+            vec![route("1.1.1.1", "10.0.0.1"), route("2.2.2.2", "10.0.0.1")],
+        );
+
+        route_refresh_begin(&mut ptree, peer);
+        assert!(ptree.get(&kept).unwrap()[0].stale);
+        assert!(!ptree.get(&purged).unwrap()[1].stale);
+
+        // Re-advertised between BoRR and EoRR.
+        let routes = ptree.get_mut(&kept).unwrap();
+        routes.clear();
+        routes.push(route("1.1.1.1", "10.0.0.1"));
+
+        assert_eq!(route_refresh_end(&mut ptree, &mut nexthops, peer), 1);
+        assert_eq!(ptree.get(&kept).unwrap().len(), 1);
+        let routes = ptree.get(&purged).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].from, "2.2.2.2".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(
+            nexthops
+                .map
+                .get(&"10.0.0.1".parse().unwrap())
+                .unwrap()
+                .refcnt,
+            2
+        );
+
+        // Only the purged prefix is exported again.
+        assert_eq!(nexthops.changed, BTreeSet::from([purged]));
+
+        // Nothing is stale anymore.
+        nexthops.changed.clear();
+        assert_eq!(route_refresh_end(&mut ptree, &mut nexthops, peer), 0);
+        assert!(nexthops.changed.is_empty());
+
+        // Removal of the last route of the prefix is a change too.
+        let other: Ipv4Addr = "2.2.2.2".parse().unwrap();
+        assert_eq!(route_clear(&mut ptree, &mut nexthops, other), 1);
+        assert!(ptree.get(&purged).is_none());
+        assert_eq!(nexthops.changed, BTreeSet::from([purged]));
+    }
+
+    #[test]
+    fn refcnt() {
+        let mut nexthops = NexthopCache::default();
+        let addr: Ipv4Addr = "10.0.0.1".parse().unwrap();
+        nexthops.lock(addr);
+        nexthops.lock(addr);
+        assert_eq!(nexthops.map.get(&addr).unwrap().refcnt, 2);
+        nexthops.unlock(addr);
+        assert!(nexthops.map.contains_key(&addr));
+        nexthops.unlock(addr);
+        assert!(!nexthops.map.contains_key(&addr));
+        assert!(matches!(
+            nexthops.pending.as_slice(),
+            [RibTx::NexthopRegister(_), RibTx::NexthopUnregister(_)]
+        ));
     }
 }
@@ -0,0 +1,98 @@
+use super::handler::Bgp;
+use super::peer::{fsm, Event, State};
+use std::net::Ipv4Addr;
+
+// BGP identifier. The configured identifier takes precedence over the router
+// ID selected by the RIB. The sessions are reset when the identifier
+// changes, since the identifier is exchanged in the OPEN message. The
+// neighbors with the local identifier configured are not affected.
+
+impl Bgp {
+    pub fn router_id_update(&mut self) {
+        let Some(router_id) = self.identifier.or(self.rib_router_id) else {
+            return;
+        };
+        if self.router_id == router_id {
+            return;
+        }
+        self.router_id = router_id;
+        let mut reset = Vec::new();
+        for (addr, peer) in self.peers.iter_mut() {
+            peer.router_id = router_id;
+            if peer.local_identifier.is_none() && peer.state != State::Idle {
+                reset.push(*addr);
+            }
+        }
+        for addr in reset.into_iter() {
+            fsm(self, addr, Event::AdminReset);
+        }
+    }
+
+    pub fn router_id_rib_update(&mut self, router_id: Ipv4Addr) {
+        self.rib_router_id = Some(router_id);
+        self.router_id_update();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::BgpType;
+    use crate::bgp::peer::Peer;
+    use bytes::BytesMut;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    fn established(bgp: &mut Bgp, addr: Ipv4Addr) -> UnboundedReceiver<BytesMut> {
+        let mut peer = Peer::new(addr, bgp.asn, bgp.router_id, 65001, addr, bgp.tx.clone());
+        let (tx, rx) = mpsc::unbounded_channel();
+        peer.packet_tx = Some(tx);
+        peer.state = State::Established;
+        bgp.peers.insert(addr, peer);
+        rx
+    }
+
+    fn v4(s: &str) -> Ipv4Addr {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn rib_router_id() {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.asn = 65000;
+        let mut rx = established(&mut bgp, v4("10.0.0.2"));
+        let mut rx_local = established(&mut bgp, v4("10.0.0.3"));
+        bgp.peers.get_mut(&v4("10.0.0.3")).unwrap().local_identifier = Some(v4("3.3.3.3"));
+
+        // Session is reset with the new identifier.
+        bgp.router_id_rib_update(v4("10.255.0.1"));
+        assert_eq!(bgp.router_id, v4("10.255.0.1"));
+        let msg = rx.try_recv().unwrap();
+        assert_eq!(msg[18], BgpType::Notification as u8);
+        // Cease, Administrative Reset.
+        assert_eq!(&msg[19..21], &[6, 4]);
+        let peer = &bgp.peers[&v4("10.0.0.2")];
+        assert_eq!(peer.router_id, v4("10.255.0.1"));
+        assert_ne!(peer.state, State::Established);
+        // Local identifier of the neighbor is not changed.
+        assert!(rx_local.try_recv().is_err());
+        assert_eq!(bgp.peers[&v4("10.0.0.3")].state, State::Established);
+
+        // Configured identifier takes precedence.
+        let mut rx = established(&mut bgp, v4("10.0.0.4"));
+        bgp.identifier = Some(v4("1.1.1.1"));
+        bgp.router_id_update();
+        assert_eq!(bgp.router_id, v4("1.1.1.1"));
+        assert!(rx.try_recv().is_ok());
+        let mut rx = established(&mut bgp, v4("10.0.0.5"));
+        bgp.router_id_rib_update(v4("10.255.0.2"));
+        assert_eq!(bgp.router_id, v4("1.1.1.1"));
+        assert!(rx.try_recv().is_err());
+
+        // Back to the RIB router ID when the config is removed.
+        bgp.identifier = None;
+        bgp.router_id_update();
+        assert_eq!(bgp.router_id, v4("10.255.0.2"));
+        assert!(rx.try_recv().is_ok());
+    }
+}
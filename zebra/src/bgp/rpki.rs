@@ -0,0 +1,466 @@
+use super::handler::Bgp;
+use super::packet::{is_confed, Attribute, Attrs, AS_SEQUENCE};
+use super::rtr::{rtr_task, RtrEvent, RtrUpdate};
+use super::task::Task;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use prefix_trie::PrefixMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+// RPKI origin validation (RFC 6811). The Validated ROA Payloads (VRPs) are
+// received from the configured caches with the RTR protocol. A route is
+// valid when a VRP covering the prefix has the origin AS of the route and a
+// max length not shorter than the prefix, invalid when the prefix is covered
+// by VRPs but none of them matches, and not found when no VRP covers it. The
+// VRPs of all of the caches are used together.
+//
+// The state is not stored in the routes. It is derived from the current VRPs
+// whenever it is shown or matched, so that a change of the VRPs applies to
+// the routes without re-validating them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vrp {
+    pub prefix: IpNet,
+    pub max_len: u8,
+    pub asn: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RpkiState {
+    Valid,
+    Invalid,
+    NotFound,
+}
+
+impl RpkiState {
+    // Code in the route table, see SHOW_BGP_HEADER.
+    pub fn code(&self) -> char {
+        match self {
+            Self::Valid => 'V',
+            Self::Invalid => 'I',
+            Self::NotFound => 'N',
+        }
+    }
+}
+
+impl fmt::Display for RpkiState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid => write!(f, "valid"),
+            Self::Invalid => write!(f, "invalid"),
+            Self::NotFound => write!(f, "not-found"),
+        }
+    }
+}
+
+impl FromStr for RpkiState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valid" => Ok(Self::Valid),
+            "invalid" => Ok(Self::Invalid),
+            "not-found" | "notfound" => Ok(Self::NotFound),
+            _ => Err(format!("invalid RPKI validation state {}", s)),
+        }
+    }
+}
+
+// Origin AS and max length of the VRPs of a prefix.
+type VrpOrigins = BTreeSet<(u32, u8)>;
+
+#[derive(Debug, Default)]
+pub struct VrpTable {
+    pub v4: PrefixMap<Ipv4Net, VrpOrigins>,
+    pub v6: PrefixMap<Ipv6Net, VrpOrigins>,
+}
+
+impl VrpTable {
+    pub fn announce(&mut self, vrp: &Vrp) {
+        let origin = (vrp.asn, vrp.max_len);
+        match vrp.prefix.trunc() {
+            IpNet::V4(prefix) => self.v4.entry(prefix).or_default().insert(origin),
+            IpNet::V6(prefix) => self.v6.entry(prefix).or_default().insert(origin),
+        };
+    }
+
+    pub fn withdraw(&mut self, vrp: &Vrp) {
+        let origin = (vrp.asn, vrp.max_len);
+        match vrp.prefix.trunc() {
+            IpNet::V4(prefix) => {
+                if let Some(origins) = self.v4.get_mut(&prefix) {
+                    origins.remove(&origin);
+                    if origins.is_empty() {
+                        self.v4.remove(&prefix);
+                    }
+                }
+            }
+            IpNet::V6(prefix) => {
+                if let Some(origins) = self.v6.get_mut(&prefix) {
+                    origins.remove(&origin);
+                    if origins.is_empty() {
+                        self.v6.remove(&prefix);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, update: &RtrUpdate) {
+        if update.reset {
+            *self = Self::default();
+        }
+        for vrp in update.withdraw.iter() {
+            self.withdraw(vrp);
+        }
+        for vrp in update.announce.iter() {
+            self.announce(vrp);
+        }
+    }
+
+    pub fn count(&self) -> (usize, usize) {
+        (
+            self.v4.iter().map(|(_, origins)| origins.len()).sum(),
+            self.v6.iter().map(|(_, origins)| origins.len()).sum(),
+        )
+    }
+
+    pub fn vrps(&self) -> Vec<Vrp> {
+        let mut vrps = Vec::new();
+        let mut push = |prefix: IpNet, origins: &VrpOrigins| {
+            for (asn, max_len) in origins.iter() {
+                vrps.push(Vrp {
+                    prefix,
+                    max_len: *max_len,
+                    asn: *asn,
+                });
+            }
+        };
+        for (prefix, origins) in self.v4.iter() {
+            push(IpNet::V4(*prefix), origins);
+        }
+        for (prefix, origins) in self.v6.iter() {
+            push(IpNet::V6(*prefix), origins);
+        }
+        vrps
+    }
+
+    // VRPs covering the prefix, from the most specific one.
+    fn covering(&self, prefix: &IpNet) -> Vec<&VrpOrigins> {
+        let mut covering = Vec::new();
+        match prefix.trunc() {
+            IpNet::V4(prefix) => {
+                let mut key = Some(prefix);
+                while let Some((net, origins)) = key.and_then(|net| self.v4.get_lpm(&net)) {
+                    covering.push(origins);
+                    key = net.supernet();
+                }
+            }
+            IpNet::V6(prefix) => {
+                let mut key = Some(prefix);
+                while let Some((net, origins)) = key.and_then(|net| self.v6.get_lpm(&net)) {
+                    covering.push(origins);
+                    key = net.supernet();
+                }
+            }
+        }
+        covering
+    }
+
+    // Origin of None is a route whose AS path ends with AS_SET, which never
+    // matches a VRP. Neither does AS 0 (RFC 6483).
+    pub fn validate(&self, prefix: &IpNet, origin: Option<u32>) -> RpkiState {
+        let covering = self.covering(prefix);
+        if covering.is_empty() {
+            return RpkiState::NotFound;
+        }
+        let len = prefix.prefix_len();
+        let matched = covering
+            .iter()
+            .flat_map(|origins| origins.iter())
+            .any(|(asn, max_len)| *asn != 0 && Some(*asn) == origin && len <= *max_len);
+        if matched {
+            RpkiState::Valid
+        } else {
+            RpkiState::Invalid
+        }
+    }
+}
+
+// Origin AS of the route, the last AS of the AS path. A route originated by
+// the local AS or within the confederation has the local AS as its origin.
+pub fn route_origin_as(attrs: &Attrs, local_as: u32) -> Option<u32> {
+    let as_path = attrs.iter().find_map(|attr| match attr {
+        Attribute::As4Path(as_path) => Some(as_path),
+        _ => None,
+    });
+    let last = as_path.and_then(|as_path| {
+        as_path
+            .segments
+            .iter()
+            .rev()
+            .find(|seg| !is_confed(seg.typ) && !seg.asn.is_empty())
+    });
+    match last {
+        None => Some(local_as),
+        Some(seg) if seg.typ == AS_SEQUENCE => seg.asn.last().cloned(),
+        Some(_) => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct RpkiCache {
+    pub port: u16,
+    pub task: Option<Task<()>>,
+    pub connected: bool,
+    pub session: Option<u16>,
+    pub serial: u32,
+    pub updates: u64,
+    pub error: Option<String>,
+    pub vrps: VrpTable,
+}
+
+impl RpkiCache {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            task: None,
+            connected: false,
+            session: None,
+            serial: 0,
+            updates: 0,
+            error: None,
+            vrps: VrpTable::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rpki {
+    pub caches: BTreeMap<IpAddr, RpkiCache>,
+}
+
+impl Rpki {
+    pub fn is_enabled(&self) -> bool {
+        !self.caches.is_empty()
+    }
+
+    pub fn validate(&self, prefix: &IpNet, origin: Option<u32>) -> RpkiState {
+        let mut state = RpkiState::NotFound;
+        for cache in self.caches.values() {
+            match cache.vrps.validate(prefix, origin) {
+                RpkiState::Valid => return RpkiState::Valid,
+                RpkiState::Invalid => state = RpkiState::Invalid,
+                RpkiState::NotFound => {}
+            }
+        }
+        state
+    }
+
+    pub fn route_state(&self, prefix: &Ipv4Net, attrs: &Attrs, local_as: u32) -> RpkiState {
+        self.validate(&IpNet::V4(*prefix), route_origin_as(attrs, local_as))
+    }
+
+    // Match condition of the validation state for the route policy.
+    pub fn matches(
+        &self,
+        prefix: &Ipv4Net,
+        attrs: &Attrs,
+        local_as: u32,
+        state: RpkiState,
+    ) -> bool {
+        self.route_state(prefix, attrs, local_as) == state
+    }
+}
+
+impl Bgp {
+    // Connect to the caches which have been added or whose port has been
+    // changed. Removing a cache drops its VRPs.
+    pub fn rpki_update(&mut self) {
+        for (addr, cache) in self.rpki.caches.iter_mut() {
+            if cache.task.is_none() {
+                let sockaddr = SocketAddr::new(*addr, cache.port);
+                cache.task = Some(rtr_task(sockaddr, self.tx.clone()));
+            }
+        }
+    }
+
+    pub fn rpki_event(&mut self, addr: IpAddr, event: RtrEvent) {
+        let Some(cache) = self.rpki.caches.get_mut(&addr) else {
+            return;
+        };
+        match event {
+            RtrEvent::Connected => {
+                cache.connected = true;
+                cache.error = None;
+            }
+            RtrEvent::Update(update) => {
+                cache.vrps.update(&update);
+                cache.session = Some(update.session);
+                cache.serial = update.serial;
+                cache.updates += 1;
+            }
+            RtrEvent::Down(err) => {
+                println!("RPKI cache {}: {}", addr, err);
+                // VRPs are kept until the session is reset by the cache.
+                cache.connected = false;
+                cache.error = Some(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{As4PathAttr, As4Segment, AS_CONFED_SEQUENCE, AS_SET};
+
+    fn vrp(prefix: &str, max_len: u8, asn: u32) -> Vrp {
+        Vrp {
+            prefix: prefix.parse().unwrap(),
+            max_len,
+            asn,
+        }
+    }
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    fn table(vrps: &[Vrp]) -> VrpTable {
+        let mut table = VrpTable::default();
+        for vrp in vrps.iter() {
+            table.announce(vrp);
+        }
+        table
+    }
+
+    fn attrs(segments: &[(u8, &[u32])]) -> Attrs {
+        let segments = segments
+            .iter()
+            .map(|(typ, asn)| As4Segment {
+                typ: *typ,
+                asn: asn.to_vec(),
+            })
+            .collect();
+        vec![Attribute::As4Path(As4PathAttr { segments })]
+    }
+
+    #[test]
+    fn validate() {
+        let table = table(&[
+            vrp("192.0.2.0/24", 24, 65001),
+            vrp("10.0.0.0/8", 16, 65002),
+            vrp("10.1.0.0/16", 24, 65003),
+            vrp("2001:db8::/32", 48, 65004),
+            vrp("198.51.100.0/24", 24, 0),
+        ]);
+        let state = |prefix, origin| table.validate(&net(prefix), origin);
+
+        assert_eq!(state("192.0.2.0/24", Some(65001)), RpkiState::Valid);
+        // Longer than the max length.
+        assert_eq!(state("192.0.2.0/25", Some(65001)), RpkiState::Invalid);
+        // Another origin.
+        assert_eq!(state("192.0.2.0/24", Some(65009)), RpkiState::Invalid);
+        assert_eq!(state("192.0.2.0/24", None), RpkiState::Invalid);
+        // Not covered, a shorter prefix is not covered by the VRP either.
+        assert_eq!(state("203.0.113.0/24", Some(65001)), RpkiState::NotFound);
+        assert_eq!(state("192.0.0.0/16", Some(65001)), RpkiState::NotFound);
+
+        // Any of the covering VRPs matches.
+        assert_eq!(state("10.2.0.0/16", Some(65002)), RpkiState::Valid);
+        assert_eq!(state("10.1.2.0/24", Some(65003)), RpkiState::Valid);
+        assert_eq!(state("10.1.0.0/16", Some(65002)), RpkiState::Valid);
+        assert_eq!(state("10.1.2.0/24", Some(65002)), RpkiState::Invalid);
+
+        assert_eq!(state("2001:db8:1::/48", Some(65004)), RpkiState::Valid);
+        assert_eq!(state("2001:db8:1::/64", Some(65004)), RpkiState::Invalid);
+        assert_eq!(state("2001:db9::/32", Some(65004)), RpkiState::NotFound);
+
+        // AS 0 VRP makes the prefix invalid whatever the origin is.
+        assert_eq!(state("198.51.100.0/24", Some(0)), RpkiState::Invalid);
+        assert_eq!(state("198.51.100.0/24", Some(65001)), RpkiState::Invalid);
+    }
+
+    #[test]
+    fn update() {
+        let mut table = table(&[vrp("192.0.2.0/24", 24, 65001)]);
+        let mut update = RtrUpdate {
+            announce: vec![vrp("192.0.2.0/24", 24, 65002), vrp("2001:db8::/32", 32, 1)],
+            withdraw: vec![vrp("192.0.2.0/24", 24, 65001)],
+            ..Default::default()
+        };
+        table.update(&update);
+        assert_eq!(table.count(), (1, 1));
+        let prefix = net("192.0.2.0/24");
+        assert_eq!(table.validate(&prefix, Some(65001)), RpkiState::Invalid);
+        assert_eq!(table.validate(&prefix, Some(65002)), RpkiState::Valid);
+
+        // Withdrawing the last VRP of the prefix removes it.
+        update.announce.clear();
+        update.withdraw = vec![vrp("192.0.2.0/24", 24, 65002)];
+        table.update(&update);
+        assert_eq!(table.validate(&prefix, Some(65002)), RpkiState::NotFound);
+
+        // Reset replaces the VRPs.
+        update.reset = true;
+        update.withdraw.clear();
+        update.announce = vec![vrp("10.0.0.0/8", 8, 65003)];
+        table.update(&update);
+        assert_eq!(table.vrps(), vec![vrp("10.0.0.0/8", 8, 65003)]);
+    }
+
+    #[test]
+    fn origin_as() {
+        assert_eq!(
+            route_origin_as(&attrs(&[(AS_SEQUENCE, &[65001, 65002])]), 100),
+            Some(65002)
+        );
+        // Locally originated.
+        assert_eq!(route_origin_as(&attrs(&[]), 100), Some(100));
+        assert_eq!(route_origin_as(&Vec::new(), 100), Some(100));
+        assert_eq!(
+            route_origin_as(&attrs(&[(AS_CONFED_SEQUENCE, &[64512])]), 100),
+            Some(100)
+        );
+        assert_eq!(
+            route_origin_as(
+                &attrs(&[(AS_SEQUENCE, &[65001]), (AS_CONFED_SEQUENCE, &[64512])]),
+                100
+            ),
+            Some(65001)
+        );
+        // Aggregated with AS_SET.
+        assert_eq!(
+            route_origin_as(
+                &attrs(&[(AS_SEQUENCE, &[65001]), (AS_SET, &[65002, 65003])]),
+                100
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn caches() {
+        let mut rpki = Rpki::default();
+        let prefix: Ipv4Net = "192.0.2.0/24".parse().unwrap();
+        let path = attrs(&[(AS_SEQUENCE, &[65001])]);
+        assert!(rpki.matches(&prefix, &path, 100, RpkiState::NotFound));
+
+        let mut first = RpkiCache::new(323);
+        first.vrps.announce(&vrp("192.0.2.0/24", 24, 65009));
+        rpki.caches.insert("10.0.0.1".parse().unwrap(), first);
+        assert!(rpki.matches(&prefix, &path, 100, RpkiState::Invalid));
+
+        // VRP of another cache validates the route.
+        let mut second = RpkiCache::new(323);
+        second.vrps.announce(&vrp("192.0.0.0/16", 24, 65001));
+        rpki.caches.insert("10.0.0.2".parse().unwrap(), second);
+        assert_eq!(rpki.route_state(&prefix, &path, 100), RpkiState::Valid);
+        assert!(rpki.matches(&prefix, &path, 100, "valid".parse().unwrap()));
+    }
+}
@@ -0,0 +1,557 @@
+use super::handler::Message;
+use super::rpki::Vrp;
+use super::task::Task;
+use bytes::BytesMut;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::UnboundedSender;
+
+// RPKI to Router protocol client (RFC 8210). The client connects to the
+// cache, asks for the whole set of the Validated ROA Payloads (VRPs) with a
+// Reset Query, and then keeps it up to date with a Serial Query on the Serial
+// Notify of the cache or when the refresh interval has passed. The VRPs
+// received between the Cache Response and the End of Data are sent to the
+// event loop as a single update. Router Key PDUs are ignored.
+
+pub const RTR_PORT: u16 = 323;
+pub const RTR_VERSION: u8 = 1;
+pub const RTR_REFRESH: Duration = Duration::from_secs(3600);
+pub const RTR_RETRY: Duration = Duration::from_secs(30);
+
+const RTR_HEADER_LEN: usize = 8;
+const RTR_PDU_MAX: usize = 65535;
+
+const PDU_SERIAL_NOTIFY: u8 = 0;
+const PDU_SERIAL_QUERY: u8 = 1;
+const PDU_RESET_QUERY: u8 = 2;
+const PDU_CACHE_RESPONSE: u8 = 3;
+const PDU_IPV4_PREFIX: u8 = 4;
+const PDU_IPV6_PREFIX: u8 = 6;
+const PDU_END_OF_DATA: u8 = 7;
+const PDU_CACHE_RESET: u8 = 8;
+const PDU_ROUTER_KEY: u8 = 9;
+const PDU_ERROR_REPORT: u8 = 10;
+
+const FLAG_ANNOUNCE: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RtrPdu {
+    SerialNotify {
+        session: u16,
+        serial: u32,
+    },
+    SerialQuery {
+        session: u16,
+        serial: u32,
+    },
+    ResetQuery,
+    CacheResponse {
+        session: u16,
+    },
+    Prefix {
+        announce: bool,
+        vrp: Vrp,
+    },
+    // Intervals are carried from version 1.
+    EndOfData {
+        session: u16,
+        serial: u32,
+        refresh: Option<u32>,
+    },
+    CacheReset,
+    RouterKey,
+    ErrorReport {
+        code: u16,
+        text: String,
+    },
+}
+
+fn be16(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn pdu_prefix(body: &[u8], ipv6: bool) -> Result<RtrPdu, String> {
+    let addr_len = if ipv6 { 16 } else { 4 };
+    if body.len() != 4 + addr_len + 4 {
+        return Err(String::from("invalid prefix PDU length"));
+    }
+    let (plen, max_len) = (body[1], body[2]);
+    let addr = &body[4..4 + addr_len];
+    let prefix = if ipv6 {
+        let octets: [u8; 16] = addr.try_into().unwrap();
+        Ipv6Net::new(Ipv6Addr::from(octets), plen).map(IpNet::V6)
+    } else {
+        Ipv4Net::new(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]), plen).map(IpNet::V4)
+    };
+    let prefix = prefix.map_err(|_| format!("invalid prefix length {}", plen))?;
+    if max_len < plen || max_len > prefix.max_prefix_len() {
+        return Err(format!("invalid max length {}", max_len));
+    }
+    Ok(RtrPdu::Prefix {
+        announce: body[0] & FLAG_ANNOUNCE != 0,
+        vrp: Vrp {
+            prefix: prefix.trunc(),
+            max_len,
+            asn: be32(&body[4 + addr_len..]),
+        },
+    })
+}
+
+// Decode a PDU from the buffer. None is returned until the whole PDU has
+// been received.
+pub fn pdu_parse(buf: &mut BytesMut) -> Result<Option<RtrPdu>, String> {
+    if buf.len() < RTR_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = be32(&buf[4..8]) as usize;
+    if !(RTR_HEADER_LEN..=RTR_PDU_MAX).contains(&len) {
+        return Err(format!("invalid PDU length {}", len));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let pdu = buf.split_to(len);
+    let (typ, session, body) = (pdu[1], be16(&pdu[2..4]), &pdu[8..]);
+    let pdu = match typ {
+        PDU_SERIAL_NOTIFY | PDU_SERIAL_QUERY if body.len() == 4 => {
+            let serial = be32(body);
+            if typ == PDU_SERIAL_NOTIFY {
+                RtrPdu::SerialNotify { session, serial }
+            } else {
+                RtrPdu::SerialQuery { session, serial }
+            }
+        }
+        PDU_RESET_QUERY => RtrPdu::ResetQuery,
+        PDU_CACHE_RESPONSE => RtrPdu::CacheResponse { session },
+        PDU_IPV4_PREFIX => pdu_prefix(body, false)?,
+        PDU_IPV6_PREFIX => pdu_prefix(body, true)?,
+        PDU_END_OF_DATA if body.len() == 4 || body.len() == 16 => RtrPdu::EndOfData {
+            session,
+            serial: be32(body),
+            refresh: (body.len() == 16).then(|| be32(&body[4..])),
+        },
+        PDU_CACHE_RESET => RtrPdu::CacheReset,
+        PDU_ROUTER_KEY => RtrPdu::RouterKey,
+        PDU_ERROR_REPORT => {
+            // Encapsulated PDU is skipped.
+            let text = body
+                .get(..4)
+                .map(|b| 4 + be32(b) as usize)
+                .and_then(|pos| Some((pos + 4, be32(body.get(pos..pos + 4)?) as usize)))
+                .and_then(|(pos, len)| body.get(pos..pos + len))
+                .map(|text| String::from_utf8_lossy(text).to_string())
+                .unwrap_or_default();
+            RtrPdu::ErrorReport {
+                code: session,
+                text,
+            }
+        }
+        _ => return Err(format!("unexpected PDU type {}", typ)),
+    };
+    Ok(Some(pdu))
+}
+
+impl RtrPdu {
+    pub fn encode(&self, version: u8) -> Vec<u8> {
+        let (typ, session, body): (u8, u16, Vec<u8>) = match self {
+            Self::SerialNotify { session, serial } => {
+                (PDU_SERIAL_NOTIFY, *session, serial.to_be_bytes().to_vec())
+            }
+            Self::SerialQuery { session, serial } => {
+                (PDU_SERIAL_QUERY, *session, serial.to_be_bytes().to_vec())
+            }
+            Self::ResetQuery => (PDU_RESET_QUERY, 0, Vec::new()),
+            Self::CacheResponse { session } => (PDU_CACHE_RESPONSE, *session, Vec::new()),
+            Self::Prefix { announce, vrp } => {
+                let flags = if *announce { FLAG_ANNOUNCE } else { 0 };
+                let mut body = vec![flags, vrp.prefix.prefix_len(), vrp.max_len, 0];
+                let typ = match vrp.prefix.addr() {
+                    IpAddr::V4(addr) => {
+                        body.extend_from_slice(&addr.octets());
+                        PDU_IPV4_PREFIX
+                    }
+                    IpAddr::V6(addr) => {
+                        body.extend_from_slice(&addr.octets());
+                        PDU_IPV6_PREFIX
+                    }
+                };
+                body.extend_from_slice(&vrp.asn.to_be_bytes());
+                (typ, 0, body)
+            }
+            Self::EndOfData {
+                session,
+                serial,
+                refresh,
+            } => {
+                let mut body = serial.to_be_bytes().to_vec();
+                if let Some(refresh) = refresh {
+                    body.extend_from_slice(&refresh.to_be_bytes());
+                    body.extend_from_slice(&600u32.to_be_bytes());
+                    body.extend_from_slice(&7200u32.to_be_bytes());
+                }
+                (PDU_END_OF_DATA, *session, body)
+            }
+            Self::CacheReset => (PDU_CACHE_RESET, 0, Vec::new()),
+            Self::RouterKey => (PDU_ROUTER_KEY, 0, Vec::new()),
+            Self::ErrorReport { code, text } => {
+                let mut body = 0u32.to_be_bytes().to_vec();
+                body.extend_from_slice(&(text.len() as u32).to_be_bytes());
+                body.extend_from_slice(text.as_bytes());
+                (PDU_ERROR_REPORT, *code, body)
+            }
+        };
+        let mut pdu = vec![version, typ];
+        pdu.extend_from_slice(&session.to_be_bytes());
+        pdu.extend_from_slice(&((RTR_HEADER_LEN + body.len()) as u32).to_be_bytes());
+        pdu.extend_from_slice(&body);
+        pdu
+    }
+}
+
+// VRPs received in response to a query. A reset update replaces all of the
+// VRPs of the cache.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RtrUpdate {
+    pub reset: bool,
+    pub session: u16,
+    pub serial: u32,
+    pub announce: Vec<Vrp>,
+    pub withdraw: Vec<Vrp>,
+}
+
+#[derive(Debug)]
+pub enum RtrEvent {
+    Connected,
+    Update(RtrUpdate),
+    Down(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RtrAction {
+    Send(RtrPdu),
+    Update(RtrUpdate),
+}
+
+// State of the session with the cache.
+#[derive(Debug, Default)]
+pub struct RtrSession {
+    pub session: Option<u16>,
+    pub serial: u32,
+    pub refresh: Option<Duration>,
+    // Response of the outstanding query is being received.
+    pending: Option<RtrUpdate>,
+    reset: bool,
+}
+
+impl RtrSession {
+    // Serial Query when the session has been established, otherwise Reset
+    // Query.
+    pub fn query(&mut self) -> RtrPdu {
+        match self.session {
+            Some(session) => {
+                self.reset = false;
+                RtrPdu::SerialQuery {
+                    session,
+                    serial: self.serial,
+                }
+            }
+            None => {
+                self.reset = true;
+                RtrPdu::ResetQuery
+            }
+        }
+    }
+
+    pub fn process(&mut self, pdu: RtrPdu) -> Result<Option<RtrAction>, String> {
+        match pdu {
+            RtrPdu::SerialNotify { session, serial } => {
+                if self.pending.is_some() || Some(session) != self.session || serial == self.serial
+                {
+                    return Ok(None);
+                }
+                Ok(Some(RtrAction::Send(self.query())))
+            }
+            RtrPdu::CacheResponse { session } => {
+                if !self.reset && Some(session) != self.session {
+                    return Err(format!("session {} has been changed", session));
+                }
+                self.pending = Some(RtrUpdate {
+                    reset: self.reset,
+                    session,
+                    ..Default::default()
+                });
+                Ok(None)
+            }
+            RtrPdu::Prefix { announce, vrp } => {
+                let pending = self
+                    .pending
+                    .as_mut()
+                    .ok_or_else(|| String::from("prefix PDU without cache response"))?;
+                if announce {
+                    pending.announce.push(vrp);
+                } else {
+                    pending.withdraw.push(vrp);
+                }
+                Ok(None)
+            }
+            RtrPdu::EndOfData {
+                session,
+                serial,
+                refresh,
+            } => {
+                let mut update = self
+                    .pending
+                    .take()
+                    .ok_or_else(|| String::from("end of data without cache response"))?;
+                if session != update.session {
+                    return Err(format!("session {} has been changed", session));
+                }
+                self.session = Some(session);
+                self.serial = serial;
+                if let Some(refresh) = refresh {
+                    self.refresh = Some(Duration::from_secs(refresh.into()));
+                }
+                update.serial = serial;
+                Ok(Some(RtrAction::Update(update)))
+            }
+            // The cache has no data for the serial, start over.
+            RtrPdu::CacheReset => {
+                self.session = None;
+                self.pending = None;
+                Ok(Some(RtrAction::Send(self.query())))
+            }
+            RtrPdu::RouterKey => Ok(None),
+            RtrPdu::ErrorReport { code, text } => Err(format!("error {}: {}", code, text)),
+            RtrPdu::SerialQuery { .. } | RtrPdu::ResetQuery => {
+                Err(String::from("unexpected query from the cache"))
+            }
+        }
+    }
+
+    pub fn refresh(&self) -> Duration {
+        self.refresh.unwrap_or(RTR_REFRESH)
+    }
+}
+
+async fn rtr_session(addr: SocketAddr, tx: &UnboundedSender<Message>) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(|err| err.to_string())?;
+    let _ = tx.send(Message::Rtr(addr.ip(), RtrEvent::Connected));
+    let mut session = RtrSession::default();
+    let query = session.query().encode(RTR_VERSION);
+    stream
+        .write_all(&query)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut buf = BytesMut::with_capacity(RTR_PDU_MAX);
+    loop {
+        let refresh = tokio::time::sleep(session.refresh());
+        tokio::select! {
+            read = stream.read_buf(&mut buf) => {
+                match read {
+                    Ok(0) => return Err(String::from("connection closed")),
+                    Ok(_) => {}
+                    Err(err) => return Err(err.to_string()),
+                }
+            }
+            _ = refresh => {
+                let query = session.query().encode(RTR_VERSION);
+                stream.write_all(&query).await.map_err(|err| err.to_string())?;
+                continue;
+            }
+        }
+        while let Some(pdu) = pdu_parse(&mut buf)? {
+            match session.process(pdu)? {
+                Some(RtrAction::Send(pdu)) => {
+                    let pdu = pdu.encode(RTR_VERSION);
+                    stream
+                        .write_all(&pdu)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                }
+                Some(RtrAction::Update(update)) => {
+                    let _ = tx.send(Message::Rtr(addr.ip(), RtrEvent::Update(update)));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+// The session is established again after the retry interval when it has
+// failed. The VRPs are reset by the first update of the new session.
+pub fn rtr_task(addr: SocketAddr, tx: UnboundedSender<Message>) -> Task<()> {
+    Task::spawn(async move {
+        loop {
+            if let Err(err) = rtr_session(addr, &tx).await {
+                let _ = tx.send(Message::Rtr(addr.ip(), RtrEvent::Down(err)));
+            }
+            tokio::time::sleep(RTR_RETRY).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vrp(prefix: &str, max_len: u8, asn: u32) -> Vrp {
+        Vrp {
+            prefix: prefix.parse().unwrap(),
+            max_len,
+            asn,
+        }
+    }
+
+    fn parse(pdus: &[RtrPdu]) -> Vec<RtrPdu> {
+        let mut buf = BytesMut::new();
+        for pdu in pdus.iter() {
+            buf.extend_from_slice(&pdu.encode(RTR_VERSION));
+        }
+        let mut parsed = Vec::new();
+        while let Some(pdu) = pdu_parse(&mut buf).unwrap() {
+            parsed.push(pdu);
+        }
+        assert!(buf.is_empty());
+        parsed
+    }
+
+    #[test]
+    fn codec() {
+        let pdus = vec![
+            RtrPdu::SerialNotify {
+                session: 7,
+                serial: 10,
+            },
+            RtrPdu::CacheResponse { session: 7 },
+            RtrPdu::Prefix {
+                announce: true,
+                vrp: vrp("192.0.2.0/24", 24, 65001),
+            },
+            RtrPdu::Prefix {
+                announce: false,
+                vrp: vrp("2001:db8::/32", 48, 65002),
+            },
+            RtrPdu::EndOfData {
+                session: 7,
+                serial: 11,
+                refresh: Some(900),
+            },
+            RtrPdu::ErrorReport {
+                code: 2,
+                text: "No Data Available".to_string(),
+            },
+        ];
+        assert_eq!(parse(&pdus), pdus);
+        assert_eq!(RtrPdu::ResetQuery.encode(1), vec![1, 2, 0, 0, 0, 0, 0, 8]);
+
+        // Partial PDU is kept in the buffer.
+        let pdu = RtrPdu::CacheResponse { session: 1 }.encode(RTR_VERSION);
+        let mut buf = BytesMut::from(&pdu[..5]);
+        assert_eq!(pdu_parse(&mut buf), Ok(None));
+        buf.extend_from_slice(&pdu[5..]);
+        assert_eq!(
+            pdu_parse(&mut buf),
+            Ok(Some(RtrPdu::CacheResponse { session: 1 }))
+        );
+
+        // Max length shorter than the prefix.
+        let mut pdu = RtrPdu::Prefix {
+            announce: true,
+            vrp: vrp("192.0.2.0/24", 24, 65001),
+        }
+        .encode(RTR_VERSION);
+        pdu[10] = 16;
+        assert!(pdu_parse(&mut BytesMut::from(&pdu[..])).is_err());
+    }
+
+    #[test]
+    fn session() {
+        let mut session = RtrSession::default();
+        assert_eq!(session.query(), RtrPdu::ResetQuery);
+
+        let announce = |prefix, max_len, asn| RtrPdu::Prefix {
+            announce: true,
+            vrp: vrp(prefix, max_len, asn),
+        };
+        assert_eq!(
+            session.process(RtrPdu::CacheResponse { session: 7 }),
+            Ok(None)
+        );
+        session
+            .process(announce("192.0.2.0/24", 24, 65001))
+            .unwrap();
+        let action = session
+            .process(RtrPdu::EndOfData {
+                session: 7,
+                serial: 1,
+                refresh: Some(900),
+            })
+            .unwrap();
+        let Some(RtrAction::Update(update)) = action else {
+            panic!("no update");
+        };
+        assert!(update.reset);
+        assert_eq!(update.announce, vec![vrp("192.0.2.0/24", 24, 65001)]);
+        assert_eq!(session.refresh(), Duration::from_secs(900));
+
+        // Notified of the new serial.
+        assert_eq!(
+            session.process(RtrPdu::SerialNotify {
+                session: 7,
+                serial: 1
+            }),
+            Ok(None)
+        );
+        assert_eq!(
+            session.process(RtrPdu::SerialNotify {
+                session: 7,
+                serial: 2
+            }),
+            Ok(Some(RtrAction::Send(RtrPdu::SerialQuery {
+                session: 7,
+                serial: 1
+            })))
+        );
+        session
+            .process(RtrPdu::CacheResponse { session: 7 })
+            .unwrap();
+        session
+            .process(RtrPdu::Prefix {
+                announce: false,
+                vrp: vrp("192.0.2.0/24", 24, 65001),
+            })
+            .unwrap();
+        let action = session.process(RtrPdu::EndOfData {
+            session: 7,
+            serial: 2,
+            refresh: None,
+        });
+        let Ok(Some(RtrAction::Update(update))) = action else {
+            panic!("no update");
+        };
+        assert!(!update.reset);
+        assert_eq!(update.withdraw.len(), 1);
+
+        // Session of the cache has been changed.
+        session.query();
+        assert!(session
+            .process(RtrPdu::CacheResponse { session: 8 })
+            .is_err());
+
+        // Cache has no data for the serial.
+        assert_eq!(
+            session.process(RtrPdu::CacheReset),
+            Ok(Some(RtrAction::Send(RtrPdu::ResetQuery)))
+        );
+        assert!(session.process(announce("10.0.0.0/8", 8, 1)).is_err());
+    }
+}
@@ -1,66 +1,223 @@
+use super::adj_rib::AdjOut;
 use super::handler::{Bgp, ShowCallback};
-use super::packet::BgpType;
+use super::packet::{Attribute, Attrs, BgpType, RouteDistinguisher};
 use super::peer::{Peer, PeerCounter, PeerParam};
-use crate::config::Args;
+use super::ratelimit::RateLimitCounter;
+use super::route::{Route, RouteFrom};
+use super::rpki::RpkiState;
+use super::transform::transforms_str;
+use crate::config::{output, Args, Render, Uptime};
+use ipnet::{IpNet, Ipv4Net};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Write;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::Instant;
 
-fn show_peer_summary(buf: &mut String, peer: &Peer) {
-    let mut sent: u64 = 0;
-    let mut rcvd: u64 = 0;
-    for counter in peer.counter.iter() {
-        sent += counter.sent;
-        rcvd += counter.rcvd;
+#[derive(Serialize, Debug)]
+struct Origination {
+    kind: &'static str,
+    prefix: String,
+    summary_only: bool,
+    as_set: bool,
+    active: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct ListenerSummary {
+    address: SocketAddr,
+    listening: bool,
+    accepted: u64,
+    bind_failures: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct NeighborSummary<'a> {
+    address: Ipv4Addr,
+    remote_as: u32,
+    dynamic: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    state: &'a str,
+    uptime: Uptime,
+    msg_rcvd: u64,
+    msg_sent: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct Summary<'a> {
+    router_id: Option<Ipv4Addr>,
+    local_as: Option<u32>,
+    originations: Vec<Origination>,
+    listeners: Vec<ListenerSummary>,
+    neighbors: Vec<NeighborSummary<'a>>,
+    dynamic_neighbors: usize,
+    max_dynamic_neighbors: usize,
+}
+
+fn origination_active(bgp: &Bgp, prefix: &Ipv4Net, kind: RouteFrom) -> bool {
+    bgp.ptree
+        .get(prefix)
+        .is_some_and(|routes| routes.iter().any(|route| route.kind == kind))
+}
+
+fn neighbor_summary(peer: &Peer) -> NeighborSummary {
+    NeighborSummary {
+        address: peer.address,
+        remote_as: peer.peer_as,
+        dynamic: peer.dynamic.is_some(),
+        description: peer.config.description.as_deref(),
+        state: peer.state_str(),
+        uptime: Uptime::since(peer.instant),
+        msg_rcvd: peer.counter.iter().map(|counter| counter.rcvd).sum(),
+        msg_sent: peer.counter.iter().map(|counter| counter.sent).sum(),
     }
-    writeln!(
-        buf,
-        "{:16} {:11} {:8} {:8}",
-        peer.address, peer.peer_as, rcvd, sent,
-    )
-    .unwrap();
 }
 
-fn show_bgp_instance(bgp: &Bgp) -> String {
-    let mut buf = String::new();
-    let asn = if bgp.asn == 0 {
-        "Not Configured".to_string()
-    } else {
-        bgp.asn.to_string()
-    };
-    let identifier = if bgp.router_id.is_unspecified() {
-        "Not Configured".to_string()
-    } else {
-        bgp.router_id.to_string()
-    };
-    writeln!(
-        buf,
-        "BGP router identifier {}, local AS number {}",
-        identifier, asn
-    )
-    .unwrap();
-    writeln!(buf).unwrap();
-
-    if bgp.peers.is_empty() {
-        writeln!(buf, "No neighbor has been configured").unwrap();
-    } else {
+fn summary(bgp: &Bgp) -> Summary {
+    let mut originations = Vec::new();
+    for (prefix, network) in bgp.networks.iter() {
+        originations.push(Origination {
+            kind: "network",
+            prefix: prefix.to_string(),
+            summary_only: false,
+            as_set: false,
+            active: origination_active(bgp, prefix, RouteFrom::Static),
+        });
+    }
+    for (prefix, aggregate) in bgp.aggregates.iter() {
+        originations.push(Origination {
+            kind: "aggregate",
+            prefix: prefix.to_string(),
+            summary_only: aggregate.summary_only,
+            as_set: aggregate.as_set,
+            active: origination_active(bgp, prefix, RouteFrom::Aggregate),
+        });
+    }
+    let listeners = bgp
+        .listeners
+        .iter()
+        .map(|(addr, listener)| ListenerSummary {
+            address: *addr,
+            listening: listener.fd.is_some(),
+            accepted: listener.accepted,
+            bind_failures: listener.bind_failures,
+            error: listener.error.clone(),
+        })
+        .collect();
+    Summary {
+        router_id: (!bgp.router_id.is_unspecified()).then_some(bgp.router_id),
+        local_as: (bgp.asn != 0).then_some(bgp.asn),
+        originations,
+        listeners,
+        neighbors: bgp.peers.values().map(neighbor_summary).collect(),
+        dynamic_neighbors: bgp.dynamic_peer_count(),
+        max_dynamic_neighbors: bgp.max_dynamic_peers,
+    }
+}
+
+impl Render for Summary<'_> {
+    fn render(&self, buf: &mut String) {
+        let not_configured = || "Not Configured".to_string();
+        writeln!(
+            buf,
+            "BGP router identifier {}, local AS number {}",
+            self.router_id
+                .map_or_else(not_configured, |v| v.to_string()),
+            self.local_as.map_or_else(not_configured, |v| v.to_string()),
+        )
+        .unwrap();
+        writeln!(buf).unwrap();
+
+        for origination in self.originations.iter() {
+            if origination.kind == "network" {
+                write!(buf, "Network {}", origination.prefix).unwrap();
+            } else {
+                write!(buf, "Aggregate {}", origination.prefix).unwrap();
+            }
+            if origination.summary_only {
+                write!(buf, " summary-only").unwrap();
+            }
+            if origination.as_set {
+                write!(buf, " as-set").unwrap();
+            }
+            let status = if origination.active {
+                "active"
+            } else {
+                "inactive"
+            };
+            writeln!(buf, ": {}", status).unwrap();
+        }
+        if !self.originations.is_empty() {
+            writeln!(buf).unwrap();
+        }
+
+        for listener in self.listeners.iter() {
+            let status = if listener.listening {
+                "listening"
+            } else {
+                "down"
+            };
+            write!(
+                buf,
+                "Listen {}: {}, {} accepted",
+                listener.address, status, listener.accepted
+            )
+            .unwrap();
+            if listener.bind_failures > 0 {
+                write!(buf, ", {} bind failures", listener.bind_failures).unwrap();
+            }
+            if let Some(error) = listener.error.as_ref() {
+                write!(buf, " ({})", error).unwrap();
+            }
+            writeln!(buf).unwrap();
+        }
+        if !self.listeners.is_empty() {
+            writeln!(buf).unwrap();
+        }
+
+        if self.neighbors.is_empty() {
+            writeln!(buf, "No neighbor has been configured").unwrap();
+            return;
+        }
         writeln!(
             buf,
             "Neighbor                  AS  MsgRcvd  MsgSent   TblVer  InQ OutQ Up/Down  State/PfxRcd"
         )
         .unwrap();
-        for (_, peer) in bgp.peers.iter() {
-            show_peer_summary(&mut buf, peer);
+        for n in self.neighbors.iter() {
+            // Dynamic neighbor is marked with '*'.
+            let address = if n.dynamic {
+                format!("*{}", n.address)
+            } else {
+                n.address.to_string()
+            };
+            writeln!(
+                buf,
+                "{:16} {:11} {:8} {:8} {:8} {:4} {:4} {:8} {}",
+                address, n.remote_as, n.msg_rcvd, n.msg_sent, 0, 0, 0, n.uptime.text, n.state,
+            )
+            .unwrap();
+        }
+        if self.dynamic_neighbors > 0 {
+            writeln!(
+                buf,
+                "\n* - dynamic neighbor, {} of {} dynamic neighbors",
+                self.dynamic_neighbors, self.max_dynamic_neighbors
+            )
+            .unwrap();
         }
     }
+}
 
-    buf
+fn show_bgp_summary(bgp: &Bgp, _args: Args, json: bool) -> String {
+    output(&summary(bgp), json)
 }
 
 static SHOW_BGP_HEADER: &str = r#"Status codes:  s suppressed, d damped, h history, u unsorted,
-               * valid, > best, = multipath,
+               * valid, > best, = multipath, x best-external,
                i internal, r RIB-failure, S Stale, R Removed
 Nexthop codes: @NNN nexthop's vrf id, < announce-nh-self
 Origin codes:  i - IGP, e - EGP, ? - incomplete
@@ -69,23 +226,145 @@ RPKI validation codes: V valid, I invalid, N Not found
      Network          Next Hop            Metric LocPrf Weight Path
 "#;
 
-fn show_bgp_route(bgp: &Bgp) -> String {
-    let mut buf = String::new();
+#[derive(Default)]
+struct AttrSummary {
+    next_hop: Option<Ipv4Addr>,
+    med: Option<u32>,
+    local_pref: Option<u32>,
+    as_path: String,
+    origin: &'static str,
+    community: Option<String>,
+}
 
-    buf.push_str(SHOW_BGP_HEADER);
+fn attr_summary(attrs: &Attrs) -> AttrSummary {
+    let mut summary = AttrSummary {
+        origin: "?",
+        ..Default::default()
+    };
+    for attr in attrs.iter() {
+        match attr {
+            Attribute::NextHop(v) => summary.next_hop = Some(Ipv4Addr::from(v.next_hop)),
+            Attribute::Med(v) => summary.med = Some(v.med),
+            Attribute::LocalPref(v) => summary.local_pref = Some(v.local_pref),
+            Attribute::As4Path(v) => summary.as_path = v.to_string(),
+            Attribute::Community(v) => summary.community = Some(v.to_string()),
+            Attribute::Origin(v) => {
+                summary.origin = match v.origin {
+                    0 => "i",
+                    1 => "e",
+                    _ => "?",
+                }
+            }
+            _ => {}
+        }
+    }
+    summary
+}
 
-    for (key, _value) in bgp.ptree.iter() {
-        writeln!(buf, "{}", key).unwrap();
+fn opt_string<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[derive(Serialize, Debug)]
+struct BgpRoute {
+    prefix: Ipv4Net,
+    // Locally originated routes are shown with nexthop 0.0.0.0.
+    next_hop: Option<Ipv4Addr>,
+    med: Option<u32>,
+    local_pref: Option<u32>,
+    weight: u32,
+    as_path: String,
+    origin: &'static str,
+    // Validation state is shown once a cache is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rpki: Option<RpkiState>,
+    suppressed: bool,
+    selected: bool,
+    multipath: bool,
+    best_external: bool,
+}
+
+fn bgp_route(prefix: &Ipv4Net, route: &Route, rpki: Option<RpkiState>) -> BgpRoute {
+    let summary = attr_summary(&route.attrs);
+    // Locally originated routes have weight 32768.
+    let local = route.kind != RouteFrom::Peer;
+    BgpRoute {
+        prefix: *prefix,
+        next_hop: if local {
+            Some(Ipv4Addr::UNSPECIFIED)
+        } else {
+            summary.next_hop
+        },
+        med: summary.med,
+        local_pref: summary.local_pref,
+        weight: if local { 32768 } else { 0 },
+        as_path: route.aspath.to_string(),
+        origin: summary.origin,
+        rpki,
+        suppressed: route.suppressed,
+        selected: route.selected,
+        multipath: route.multipath,
+        best_external: route.best_external,
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct BgpRoutes(Vec<BgpRoute>);
+
+impl Render for BgpRoutes {
+    fn render(&self, buf: &mut String) {
+        buf.push_str(SHOW_BGP_HEADER);
+        for route in self.0.iter() {
+            let valid = format!(
+                "{}{}{}",
+                route
+                    .rpki
+                    .map(|state| state.code().to_string())
+                    .unwrap_or_default(),
+                if route.suppressed { 's' } else { '*' },
+                if route.selected {
+                    '>'
+                } else if route.multipath {
+                    '='
+                } else if route.best_external {
+                    'x'
+                } else {
+                    ' '
+                }
+            );
+            let path = if route.as_path.is_empty() {
+                route.origin.to_string()
+            } else {
+                format!("{} {}", route.as_path, route.origin)
+            };
+            writeln!(
+                buf,
+                "{} {:16} {:19} {:>6} {:>6} {:>6} {}",
+                valid,
+                route.prefix.to_string(),
+                opt_string(route.next_hop),
+                opt_string(route.med),
+                opt_string(route.local_pref),
+                route.weight,
+                path
+            )
+            .unwrap();
+        }
     }
-    buf
 }
 
-fn show_bgp(bgp: &Bgp, args: Args) -> String {
-    if args.is_empty() {
-        show_bgp_route(bgp)
-    } else {
-        show_bgp_instance(bgp)
+fn show_bgp(bgp: &Bgp, _args: Args, json: bool) -> String {
+    let mut routes = Vec::new();
+    for (key, entries) in bgp.ptree.iter() {
+        for route in entries.iter() {
+            let rpki = bgp
+                .rpki
+                .is_enabled()
+                .then(|| bgp.rpki.route_state(key, &route.attrs, bgp.asn));
+            routes.push(bgp_route(key, route, rpki));
+        }
     }
+    output(&BgpRoutes(routes), json)
 }
 
 #[derive(Serialize, Debug)]
@@ -96,22 +375,17 @@ struct Neighbor<'a> {
     remote_as: u32,
     local_router_id: Ipv4Addr,
     remote_router_id: Ipv4Addr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
     state: &'a str,
-    uptime: String,
+    uptime: Uptime,
     timer: PeerParam,
     timer_sent: PeerParam,
     timer_recv: PeerParam,
-    count: HashMap<&'a str, PeerCounter>,
-}
-
-fn uptime(instant: &Option<Instant>) -> String {
-    if let Some(instant) = instant {
-        let now = Instant::now();
-        let duration = now.duration_since(*instant);
-        format!("{:?}", duration)
-    } else {
-        String::from("never")
-    }
+    count: BTreeMap<&'a str, PeerCounter>,
+    rate_limit: RateLimitCounter,
+    transforms: Vec<String>,
+    ttl: String,
 }
 
 fn fetch(peer: &Peer) -> Neighbor {
@@ -122,12 +396,16 @@ fn fetch(peer: &Peer) -> Neighbor {
         peer_type: peer.peer_type.to_str(),
         local_router_id: peer.router_id.clone(),
         remote_router_id: peer.remote_id.clone(),
-        state: peer.state.to_str(),
-        uptime: uptime(&peer.instant),
+        description: peer.config.description.as_deref(),
+        state: peer.state_str(),
+        uptime: Uptime::since(peer.instant),
         timer: peer.param.clone(),
         timer_sent: peer.param_tx.clone(),
         timer_recv: peer.param_rx.clone(),
-        count: HashMap::default(),
+        count: BTreeMap::default(),
+        rate_limit: peer.rate_stat.counter(),
+        transforms: transforms_str(&peer.config),
+        ttl: peer.ttl_mode().to_string(),
     };
 
     // Timers.
@@ -200,13 +478,21 @@ fn fetch(peer: &Peer) -> Neighbor {
 fn render(neighbor: &Neighbor, out: &mut String) -> anyhow::Result<()> {
     writeln!(
         out,
-        r#"BGP neighbor is {}, remote AS {}, local AS {}, {} link
-  BGP version 4, remote router ID {}, local router ID {}
+        "BGP neighbor is {}, remote AS {}, local AS {}, {} link",
+        neighbor.address, neighbor.remote_as, neighbor.local_as, neighbor.peer_type,
+    )?;
+    if let Some(description) = neighbor.description {
+        writeln!(out, " Description: {}", description)?;
+    }
+    writeln!(
+        out,
+        r#"  BGP version 4, remote router ID {}, local router ID {}
   BGP state = {}, up for {}
   Last read 00:00:00, Last write 00:00:00
   Hold time {} seconds, keepalive {} seconds
   Sent Hold time {} seconds, sent keepalive {} seconds
   Recv Hold time {} seconds, Recieved keepalive {} seconds
+  TTL mode {}
   Message statistics:
                               Sent          Rcvd
     Opens:              {:>10}    {:>10}
@@ -216,21 +502,21 @@ fn render(neighbor: &Neighbor, out: &mut String) -> anyhow::Result<()> {
     Route Refresh:      {:>10}    {:>10}
     Capability:         {:>10}    {:>10}
     Total:              {:>10}    {:>10}
+  Update rate limit delayed:
+    Inbound:            {:>10}
+    Outbound:           {:>10}
 "#,
-        neighbor.address,
-        neighbor.remote_as,
-        neighbor.local_as,
-        neighbor.peer_type,
         neighbor.remote_router_id,
         neighbor.local_router_id,
         neighbor.state,
-        neighbor.uptime,
+        neighbor.uptime.text,
         neighbor.timer.hold_time,
         neighbor.timer.keepalive,
         neighbor.timer_sent.hold_time,
         neighbor.timer_sent.keepalive,
         neighbor.timer_recv.hold_time,
         neighbor.timer_recv.keepalive,
+        neighbor.ttl,
         neighbor.count.get("open").unwrap().sent,
         neighbor.count.get("open").unwrap().rcvd,
         neighbor.count.get("notification").unwrap().sent,
@@ -245,26 +531,642 @@ fn render(neighbor: &Neighbor, out: &mut String) -> anyhow::Result<()> {
         neighbor.count.get("capability").unwrap().rcvd,
         neighbor.count.get("total").unwrap().sent,
         neighbor.count.get("total").unwrap().rcvd,
+        neighbor.rate_limit.in_delayed,
+        neighbor.rate_limit.out_delayed,
     )?;
+    if !neighbor.transforms.is_empty() {
+        writeln!(
+            out,
+            "  Outbound transforms: {}",
+            neighbor.transforms.join(", ")
+        )?;
+    }
     Ok(())
 }
 
-fn show_bgp_neighbor(bgp: &Bgp, args: Args) -> String {
-    let mut out = String::new();
+#[derive(Serialize, Debug)]
+struct Neighbors<'a> {
+    neighbors: Vec<Neighbor<'a>>,
+}
+
+impl Render for Neighbors<'_> {
+    fn render(&self, buf: &mut String) {
+        for neighbor in self.neighbors.iter() {
+            render(neighbor, buf).unwrap();
+        }
+    }
+}
+
+fn show_bgp_neighbor(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let addr = args.v4addr();
+    let neighbors = bgp
+        .peers
+        .values()
+        .filter(|peer| addr.is_none_or(|addr| addr == peer.address))
+        .map(fetch)
+        .collect();
+    output(&Neighbors { neighbors }, json)
+}
+
+fn show_bgp_neighbor_trace(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let mut buf = String::new();
+    if json {
+        buf.push_str("% JSON not supported\n");
+        return buf;
+    }
+    let mut addr: Option<Ipv4Addr> = None;
+    let mut last: Option<usize> = None;
+    while let Some(arg) = args.string() {
+        if let Ok(v) = arg.parse::<Ipv4Addr>() {
+            addr = Some(v);
+        } else if let Ok(v) = arg.parse::<usize>() {
+            last = Some(v);
+        }
+    }
+    if let Some(addr) = addr {
+        if !bgp.peers.contains_key(&addr) {
+            writeln!(buf, "% No such neighbor {}", addr).unwrap();
+            return buf;
+        }
+    }
+    let now = Instant::now();
+    for (_, peer) in bgp.peers.iter() {
+        if addr.is_some_and(|addr| addr != peer.address) {
+            continue;
+        }
+        let trace = &peer.trace;
+        writeln!(
+            buf,
+            "BGP neighbor {} trace, {} of {} records",
+            peer.address,
+            trace.len(),
+            trace.size()
+        )
+        .unwrap();
+        if !trace.config.packets && !trace.config.events {
+            writeln!(buf, "  Tracing is disabled").unwrap();
+        }
+        for record in trace.records(last) {
+            let age = now.duration_since(record.time).as_secs_f64();
+            writeln!(buf, "  {:>12.3}s ago  {}", age, record.event).unwrap();
+        }
+    }
+    buf
+}
+
+static SHOW_ADJ_OUT_HEADER: &str = r#"Status codes:  * advertised, p pending by MRAI
+Origin codes:  i - IGP, e - EGP, ? - incomplete
+
+     Network          Next Hop            Metric LocPrf Weight Path
+"#;
+
+#[derive(Serialize, Debug)]
+struct AdvertisedRoute {
+    prefix: String,
+    next_hop: Option<Ipv4Addr>,
+    med: Option<u32>,
+    local_pref: Option<u32>,
+    as_path: String,
+    origin: &'static str,
+    community: Option<String>,
+    pending: bool,
+}
+
+fn advertised_route(prefix: &Ipv4Net, out: &AdjOut, pending: bool) -> AdvertisedRoute {
+    let summary = attr_summary(&out.attrs());
+    AdvertisedRoute {
+        prefix: prefix.to_string(),
+        next_hop: summary.next_hop,
+        med: summary.med,
+        local_pref: summary.local_pref,
+        as_path: summary.as_path,
+        origin: summary.origin,
+        community: summary.community,
+        pending,
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct AdvertisedRoutes(Vec<AdvertisedRoute>);
+
+impl Render for AdvertisedRoutes {
+    fn render(&self, buf: &mut String) {
+        buf.push_str(SHOW_ADJ_OUT_HEADER);
+        for route in self.0.iter() {
+            let path = if route.as_path.is_empty() {
+                route.origin.to_string()
+            } else {
+                format!("{} {}", route.as_path, route.origin)
+            };
+            writeln!(
+                buf,
+                "{}  {:16} {:19} {:>6} {:>6} {:>6} {}",
+                if route.pending { 'p' } else { '*' },
+                route.prefix,
+                opt_string(route.next_hop),
+                opt_string(route.med),
+                opt_string(route.local_pref),
+                0,
+                path
+            )
+            .unwrap();
+        }
+        writeln!(buf, "\nTotal number of prefixes {}", self.0.len()).unwrap();
+    }
+}
+
+static SHOW_ADJ_OUT_VPN_HEADER: &str = r#"Status codes:  * advertised, p pending by MRAI
+     Network            Next Hop         Label   Route targets
+"#;
+
+#[derive(Serialize, Debug)]
+struct AdvertisedVpnRoute {
+    rd: String,
+    prefix: Ipv4Net,
+    next_hop: Ipv4Addr,
+    label: u32,
+    route_targets: Vec<String>,
+    pending: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct AdvertisedVpnRoutes(Vec<AdvertisedVpnRoute>);
+
+impl Render for AdvertisedVpnRoutes {
+    fn render(&self, buf: &mut String) {
+        buf.push_str(SHOW_ADJ_OUT_VPN_HEADER);
+        let mut rd: Option<&str> = None;
+        for route in self.0.iter() {
+            if rd != Some(route.rd.as_str()) {
+                writeln!(buf, "Route Distinguisher: {}", route.rd).unwrap();
+                rd = Some(route.rd.as_str());
+            }
+            writeln!(
+                buf,
+                "{}    {:18} {:16} {:>7} {}",
+                if route.pending { 'p' } else { '*' },
+                route.prefix.to_string(),
+                route.next_hop,
+                route.label,
+                route.route_targets.join(" ")
+            )
+            .unwrap();
+        }
+        writeln!(buf, "\nTotal number of prefixes {}", self.0.len()).unwrap();
+    }
+}
+
+fn advertised_vpn_routes(peer: &Peer, prefix: Option<Ipv4Net>) -> AdvertisedVpnRoutes {
+    let routes = peer
+        .adj_out
+        .vpn
+        .routes
+        .iter()
+        .filter(|(_, out)| prefix.is_none_or(|prefix| out.route.prefix == prefix))
+        .map(|(key, out)| AdvertisedVpnRoute {
+            rd: out.route.rd.to_string(),
+            prefix: out.route.prefix,
+            next_hop: out.route.nexthop,
+            label: out.route.label,
+            route_targets: out
+                .route
+                .route_targets
+                .iter()
+                .map(|rt| rt.to_string())
+                .collect(),
+            pending: peer.adj_out.vpn.pending.contains(key),
+        })
+        .collect();
+    AdvertisedVpnRoutes(routes)
+}
+
+// Routes in the Adj-RIB-Out of the neighbor with the attributes as sent, of
+// IPv4 unicast unless the address family is given.
+fn show_bgp_advertised_routes(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let mut buf = String::new();
+    let Some(addr) = args.v4addr() else {
+        return buf;
+    };
+    let Some(peer) = bgp.peers.get(&addr) else {
+        writeln!(buf, "% No such neighbor {}", addr).unwrap();
+        return buf;
+    };
+    let mut prefix: Option<Ipv4Net> = None;
+    let mut afi_safi = String::from("ipv4-unicast");
+    while let Some(arg) = args.string() {
+        match arg.parse::<Ipv4Net>() {
+            Ok(v) => prefix = Some(v),
+            Err(_) => afi_safi = arg,
+        }
+    }
+    match afi_safi.as_str() {
+        "ipv4-unicast" => {
+            let routes = peer
+                .adj_out
+                .routes
+                .iter()
+                .filter(|(p, _)| prefix.is_none_or(|prefix| **p == prefix))
+                .map(|(p, out)| advertised_route(p, out, peer.adj_out.pending.contains(p)))
+                .collect();
+            output(&AdvertisedRoutes(routes), json)
+        }
+        "l3vpn-ipv4-unicast" => output(&advertised_vpn_routes(peer, prefix), json),
+        _ => format!("% Address family {} is not supported\n", afi_safi),
+    }
+}
+
+static SHOW_VPN_HEADER: &str = r#"Status codes:  * received, l local VRF route
+     Network            Next Hop         Label   From             Route targets
+"#;
+
+#[derive(Serialize, Debug)]
+struct VpnRouteOut {
+    rd: String,
+    prefix: Ipv4Net,
+    label: u32,
+    // None for the local routes, advertised with the address of the session.
+    next_hop: Option<Ipv4Addr>,
+    from: Option<Ipv4Addr>,
+    route_targets: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct VpnRoutes(Vec<VpnRouteOut>);
+
+impl Render for VpnRoutes {
+    fn render(&self, buf: &mut String) {
+        buf.push_str(SHOW_VPN_HEADER);
+        let mut rd: Option<&str> = None;
+        for route in self.0.iter() {
+            if rd != Some(route.rd.as_str()) {
+                writeln!(buf, "Route Distinguisher: {}", route.rd).unwrap();
+                rd = Some(route.rd.as_str());
+            }
+            writeln!(
+                buf,
+                "{}    {:18} {:16} {:>7} {:16} {}",
+                if route.from.is_some() { '*' } else { 'l' },
+                route.prefix.to_string(),
+                opt_string(route.next_hop),
+                route.label,
+                route
+                    .from
+                    .map_or("local".to_string(), |from| from.to_string()),
+                route.route_targets.join(" ")
+            )
+            .unwrap();
+        }
+        writeln!(buf, "\nTotal number of prefixes {}", self.0.len()).unwrap();
+    }
+}
+
+// VPN-IPv4 routes received from the neighbors and exported from the VRFs,
+// optionally of the RD.
+fn show_bgp_vpnv4(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let rd: Option<RouteDistinguisher> = match args.string() {
+        Some(arg) => match arg.parse() {
+            Ok(rd) => Some(rd),
+            Err(_) => return format!("% Invalid route distinguisher {}\n", arg),
+        },
+        None => None,
+    };
+    let routes = bgp
+        .vpn
+        .values()
+        .filter(|route| rd.is_none_or(|rd| route.rd == rd))
+        .map(|route| {
+            let local = route.from.is_unspecified();
+            VpnRouteOut {
+                rd: route.rd.to_string(),
+                prefix: route.prefix,
+                label: route.label,
+                next_hop: (!local).then_some(route.nexthop),
+                from: (!local).then_some(route.from),
+                route_targets: route
+                    .route_targets
+                    .iter()
+                    .map(|rt| rt.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
+    output(&VpnRoutes(routes), json)
+}
+
+#[derive(Serialize, Debug)]
+struct TrackedNexthop {
+    address: Ipv4Addr,
+    refcnt: usize,
+    valid: bool,
+    resolved: Option<Ipv4Net>,
+    metric: u32,
+}
+
+#[derive(Serialize, Debug)]
+struct NexthopTracking {
+    resolve_via_default: bool,
+    nexthops: Vec<TrackedNexthop>,
+}
+
+impl Render for NexthopTracking {
+    fn render(&self, buf: &mut String) {
+        writeln!(
+            buf,
+            "Resolve via default: {}",
+            if self.resolve_via_default {
+                "on"
+            } else {
+                "off"
+            }
+        )
+        .unwrap();
+        writeln!(
+            buf,
+            "{:16} {:>6} {:8} {:19} {:>6}",
+            "Nexthop", "Refcnt", "State", "Resolved via", "Metric"
+        )
+        .unwrap();
+        for nexthop in self.nexthops.iter() {
+            writeln!(
+                buf,
+                "{:16} {:>6} {:8} {:19} {:>6}",
+                nexthop.address.to_string(),
+                nexthop.refcnt,
+                if nexthop.valid { "valid" } else { "invalid" },
+                nexthop
+                    .resolved
+                    .map_or(String::from("-"), |prefix| prefix.to_string()),
+                nexthop.metric
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn show_bgp_nexthop_tracking(bgp: &Bgp, _args: Args, json: bool) -> String {
+    let nexthops = bgp
+        .nexthops
+        .map
+        .iter()
+        .map(|(addr, nexthop)| TrackedNexthop {
+            address: *addr,
+            refcnt: nexthop.refcnt,
+            valid: nexthop.valid(bgp.nexthops.resolve_via_default),
+            resolved: nexthop.resolved(),
+            metric: nexthop.update.as_ref().map(|u| u.metric).unwrap_or(0),
+        })
+        .collect();
+    let tracking = NexthopTracking {
+        resolve_via_default: bgp.nexthops.resolve_via_default,
+        nexthops,
+    };
+    output(&tracking, json)
+}
 
-    if args.is_empty() {
-        let mut neighbors = Vec::<Neighbor>::new();
-        for (_, peer) in bgp.peers.iter() {
-            neighbors.push(fetch(peer));
+#[derive(Serialize, Debug)]
+struct AsPathSetOut<'a> {
+    name: &'a str,
+    members: Vec<&'a str>,
+}
+
+#[derive(Serialize, Debug)]
+struct AsPathSets<'a>(Vec<AsPathSetOut<'a>>);
+
+impl Render for AsPathSets<'_> {
+    fn render(&self, buf: &mut String) {
+        for set in self.0.iter() {
+            writeln!(buf, "as-path-set {}", set.name).unwrap();
+            for member in set.members.iter() {
+                writeln!(buf, "  {}", member).unwrap();
+            }
         }
-        for neighbor in neighbors.iter() {
-            render(neighbor, &mut out).unwrap();
+    }
+}
+
+fn show_bgp_aspath_set(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let name = args.string();
+    let sets = bgp
+        .aspath_sets
+        .iter()
+        .filter(|(set_name, _)| name.as_ref().is_none_or(|name| name == *set_name))
+        .map(|(set_name, set)| AsPathSetOut {
+            name: set_name,
+            members: set.members.iter().map(|m| m.pattern.as_str()).collect(),
+        })
+        .collect();
+    output(&AsPathSets(sets), json)
+}
+
+#[derive(Serialize, Debug)]
+struct PrefixSetEntryOut {
+    prefix: Ipv4Net,
+    // Empty for the exact match.
+    ranges: Vec<String>,
+    // Compiled form is the prefix lengths matched under the prefix.
+    lengths: Vec<(u8, u8)>,
+    hits: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct PrefixSetOut<'a> {
+    name: &'a str,
+    entries: Vec<PrefixSetEntryOut>,
+}
+
+#[derive(Serialize, Debug)]
+struct PrefixSets<'a>(Vec<PrefixSetOut<'a>>);
+
+impl Render for PrefixSets<'_> {
+    fn render(&self, buf: &mut String) {
+        for set in self.0.iter() {
+            writeln!(
+                buf,
+                "prefix-set {}, {} entries",
+                set.name,
+                set.entries.len()
+            )
+            .unwrap();
+            for entry in set.entries.iter() {
+                let ranges = if entry.ranges.is_empty() {
+                    String::from("exact")
+                } else {
+                    entry.ranges.join(",")
+                };
+                let lengths: Vec<String> = entry
+                    .lengths
+                    .iter()
+                    .map(|(min, max)| format!("/{}-/{}", min, max))
+                    .collect();
+                writeln!(
+                    buf,
+                    "  {:18} {:16} {:16} {:>10} matches",
+                    entry.prefix.to_string(),
+                    ranges,
+                    lengths.join(","),
+                    entry.hits
+                )
+                .unwrap();
+            }
         }
-        // out = serde_json::to_string(&neighbors).unwrap();
-    } else {
-        // Specific neighbor.
     }
-    out
+}
+
+fn show_bgp_prefix_set(bgp: &Bgp, mut args: Args, json: bool) -> String {
+    let name = args.string();
+    let sets = bgp
+        .prefix_sets
+        .iter()
+        .filter(|(set_name, _)| name.as_ref().is_none_or(|name| name == *set_name))
+        .map(|(set_name, set)| PrefixSetOut {
+            name: set_name,
+            entries: set
+                .entries
+                .iter()
+                .map(|(prefix, entry)| PrefixSetEntryOut {
+                    prefix: *prefix,
+                    ranges: entry.ranges.iter().map(|r| r.to_string()).collect(),
+                    lengths: entry.bounds(prefix.prefix_len()),
+                    hits: entry.hits,
+                })
+                .collect(),
+        })
+        .collect();
+    output(&PrefixSets(sets), json)
+}
+
+#[derive(Serialize, Debug)]
+struct RpkiCacheOut {
+    address: IpAddr,
+    port: u16,
+    connected: bool,
+    session: Option<u16>,
+    serial: u32,
+    updates: u64,
+    ipv4_vrps: usize,
+    ipv6_vrps: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct RpkiCaches(Vec<RpkiCacheOut>);
+
+impl Render for RpkiCaches {
+    fn render(&self, buf: &mut String) {
+        for cache in self.0.iter() {
+            let state = if cache.connected {
+                "connected"
+            } else {
+                "not connected"
+            };
+            writeln!(
+                buf,
+                "RPKI cache {} port {}, {}",
+                cache.address, cache.port, state
+            )
+            .unwrap();
+            if let Some(session) = cache.session {
+                writeln!(
+                    buf,
+                    "  Session {}, serial {}, {} updates",
+                    session, cache.serial, cache.updates
+                )
+                .unwrap();
+            }
+            writeln!(
+                buf,
+                "  IPv4 VRPs {}, IPv6 VRPs {}",
+                cache.ipv4_vrps, cache.ipv6_vrps
+            )
+            .unwrap();
+            if let Some(err) = cache.error.as_ref() {
+                writeln!(buf, "  Last error: {}", err).unwrap();
+            }
+        }
+    }
+}
+
+fn show_bgp_rpki(bgp: &Bgp, _args: Args, json: bool) -> String {
+    let caches = bgp
+        .rpki
+        .caches
+        .iter()
+        .map(|(addr, cache)| {
+            let (ipv4_vrps, ipv6_vrps) = cache.vrps.count();
+            RpkiCacheOut {
+                address: *addr,
+                port: cache.port,
+                connected: cache.connected,
+                session: cache.session,
+                serial: cache.serial,
+                updates: cache.updates,
+                ipv4_vrps,
+                ipv6_vrps,
+                error: cache.error.clone(),
+            }
+        })
+        .collect();
+    output(&RpkiCaches(caches), json)
+}
+
+#[derive(Serialize, Debug)]
+struct VrpOut {
+    prefix: IpNet,
+    max_len: u8,
+    origin_as: u32,
+    cache: IpAddr,
+}
+
+#[derive(Serialize, Debug)]
+struct VrpTableOut(Vec<VrpOut>);
+
+impl Render for VrpTableOut {
+    fn render(&self, buf: &mut String) {
+        writeln!(
+            buf,
+            "{:43} {:>6} {:>10} Cache",
+            "Prefix", "MaxLen", "Origin-AS"
+        )
+        .unwrap();
+        for vrp in self.0.iter() {
+            writeln!(
+                buf,
+                "{:43} {:>6} {:>10} {}",
+                vrp.prefix.to_string(),
+                vrp.max_len,
+                vrp.origin_as,
+                vrp.cache
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn show_bgp_rpki_table(bgp: &Bgp, _args: Args, json: bool) -> String {
+    let mut vrps = Vec::new();
+    for (addr, cache) in bgp.rpki.caches.iter() {
+        for vrp in cache.vrps.vrps().iter() {
+            vrps.push(VrpOut {
+                prefix: vrp.prefix,
+                max_len: vrp.max_len,
+                origin_as: vrp.asn,
+                cache: *addr,
+            });
+        }
+    }
+    output(&VrpTableOut(vrps), json)
+}
+
+fn state_bgp_global(bgp: &Bgp) -> serde_json::Value {
+    serde_json::json!({
+        "as": bgp.asn,
+        "router-id": bgp.router_id,
+    })
+}
+
+fn state_bgp_neighbor(bgp: &Bgp) -> serde_json::Value {
+    let neighbors: Vec<Neighbor> = bgp.peers.values().map(fetch).collect();
+    serde_json::to_value(neighbors).unwrap_or_default()
 }
 
 impl Bgp {
@@ -274,7 +1176,358 @@ impl Bgp {
 
     pub fn show_build(&mut self) {
         self.show_add("/show/ip/bgp", show_bgp);
-        self.show_add("/show/ip/bgp/summary", show_bgp);
+        self.show_add("/show/ip/bgp/summary", show_bgp_summary);
         self.show_add("/show/ip/bgp/neighbor", show_bgp_neighbor);
+        self.show_add("/show/ip/bgp/neighbor/trace", show_bgp_neighbor_trace);
+        self.show_add(
+            "/show/ip/bgp/neighbor/advertised-routes",
+            show_bgp_advertised_routes,
+        );
+        self.show_add(
+            "/show/ip/bgp/neighbor/advertised-routes/prefix",
+            show_bgp_advertised_routes,
+        );
+        self.show_add(
+            "/show/ip/bgp/neighbor/advertised-routes/afi-safi",
+            show_bgp_advertised_routes,
+        );
+        self.show_add("/show/ip/bgp/as-path-set", show_bgp_aspath_set);
+        self.show_add("/show/ip/bgp/prefix-set", show_bgp_prefix_set);
+        self.show_add("/show/ip/bgp/rpki", show_bgp_rpki);
+        self.show_add("/show/ip/bgp/rpki/table", show_bgp_rpki_table);
+        self.show_add("/show/ip/bgp/nexthop-tracking", show_bgp_nexthop_tracking);
+        self.show_add("/show/ip/bgp/vpnv4/unicast", show_bgp_vpnv4);
+        self.show_add("/show/ip/bgp/vpnv4/unicast/rd", show_bgp_vpnv4);
+    }
+
+    pub fn state_build(&mut self) {
+        self.state_cb.add("/bgp/global", state_bgp_global);
+        self.state_cb
+            .add("/bgp/neighbors/neighbor", state_bgp_neighbor);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::adj_rib::ExportPeer;
+    use crate::bgp::listen::Listener;
+    use crate::bgp::network::local_route_update;
+    use crate::bgp::packet::{As4PathAttr, OriginAttr, ORIGIN_IGP};
+    use crate::bgp::peer::PeerType;
+    use crate::bgp::peer_group::PeerGroup;
+    use crate::rib::vrf::VpnRoute;
+    use std::collections::VecDeque;
+    use tokio::sync::mpsc;
+
+    fn args() -> Args {
+        Args(VecDeque::new())
+    }
+
+    fn bgp() -> Bgp {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.asn = 65000;
+        bgp.router_id = "10.0.0.1".parse().unwrap();
+        let addr: Ipv4Addr = "10.0.0.2".parse().unwrap();
+        let mut peer = Peer::new(addr, 65000, bgp.router_id, 65001, addr, bgp.tx.clone());
+        peer.peer_type = PeerType::External;
+        bgp.peers.insert(addr, peer);
+        let mut group = PeerGroup::default();
+        group.template.peer_as = Some(65001);
+        group.ranges.insert("192.168.0.0/16".parse().unwrap());
+        bgp.peer_groups.insert("leaf".to_string(), group);
+        bgp.dynamic_peer_create("192.168.1.1".parse().unwrap())
+            .unwrap();
+        bgp
+    }
+
+    #[test]
+    fn summary_output() {
+        let bgp = bgp();
+        assert_eq!(
+            show_bgp_summary(&bgp, args(), false),
+            r#"BGP router identifier 10.0.0.1, local AS number 65000
+
+Neighbor                  AS  MsgRcvd  MsgSent   TblVer  InQ OutQ Up/Down  State/PfxRcd
+10.0.0.2               65001        0        0        0    0    0 never    Idle
+*192.168.1.1           65001        0        0        0    0    0 never    Active
+
+* - dynamic neighbor, 1 of 100 dynamic neighbors
+"#
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&show_bgp_summary(&bgp, args(), true)).unwrap();
+        let never = serde_json::json!({"seconds": null, "text": "never"});
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "router_id": "10.0.0.1",
+                "local_as": 65000,
+                "originations": [],
+                "listeners": [],
+                "neighbors": [{
+                    "address": "10.0.0.2",
+                    "remote_as": 65001,
+                    "dynamic": false,
+                    "state": "Idle",
+                    "uptime": never,
+                    "msg_rcvd": 0,
+                    "msg_sent": 0,
+                }, {
+                    "address": "192.168.1.1",
+                    "remote_as": 65001,
+                    "dynamic": true,
+                    "state": "Active",
+                    "uptime": never,
+                    "msg_rcvd": 0,
+                    "msg_sent": 0,
+                }],
+                "dynamic_neighbors": 1,
+                "max_dynamic_neighbors": 100,
+            })
+        );
+
+        let (rib, _) = mpsc::channel(1);
+        let empty = Bgp::new(rib);
+        assert_eq!(
+            show_bgp_summary(&empty, args(), false),
+            "BGP router identifier Not Configured, local AS number Not Configured\n\nNo neighbor has been configured\n"
+        );
+        let json = show_bgp_summary(&empty, args(), true);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["router_id"], serde_json::Value::Null);
+        assert_eq!(json["neighbors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn summary_listeners() {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        let listener = Listener {
+            fd: Some(10),
+            accepted: 3,
+            ..Default::default()
+        };
+        bgp.listeners
+            .insert("0.0.0.0:179".parse().unwrap(), listener);
+        let listener = Listener {
+            bind_failures: 2,
+            error: Some("Cannot assign requested address".to_string()),
+            ..Default::default()
+        };
+        bgp.listeners
+            .insert("10.0.0.1:1179".parse().unwrap(), listener);
+        assert_eq!(
+            show_bgp_summary(&bgp, args(), false),
+            r#"BGP router identifier Not Configured, local AS number Not Configured
+
+Listen 0.0.0.0:179: listening, 3 accepted
+Listen 10.0.0.1:1179: down, 0 accepted, 2 bind failures (Cannot assign requested address)
+
+No neighbor has been configured
+"#
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&show_bgp_summary(&bgp, args(), true)).unwrap();
+        assert_eq!(
+            json["listeners"],
+            serde_json::json!([{
+                "address": "0.0.0.0:179",
+                "listening": true,
+                "accepted": 3,
+                "bind_failures": 0,
+            }, {
+                "address": "10.0.0.1:1179",
+                "listening": false,
+                "accepted": 0,
+                "bind_failures": 2,
+                "error": "Cannot assign requested address",
+            }])
+        );
+    }
+
+    #[test]
+    fn neighbor_output() {
+        let mut bgp = bgp();
+        bgp.peers.remove(&"192.168.1.1".parse().unwrap());
+        assert_eq!(
+            show_bgp_neighbor(&bgp, args(), false),
+            r#"BGP neighbor is 10.0.0.2, remote AS 65001, local AS 65000, external link
+  BGP version 4, remote router ID 0.0.0.0, local router ID 10.0.0.1
+  BGP state = Idle, up for never
+  Last read 00:00:00, Last write 00:00:00
+  Hold time 0 seconds, keepalive 0 seconds
+  Sent Hold time 0 seconds, sent keepalive 0 seconds
+  Recv Hold time 0 seconds, Recieved keepalive 0 seconds
+  TTL mode single-hop
+  Message statistics:
+                              Sent          Rcvd
+    Opens:                       0             0
+    Notifications:               0             0
+    Updates:                     0             0
+    Keepalives:                  0             0
+    Route Refresh:               0             0
+    Capability:                  0             0
+    Total:                       0             0
+  Update rate limit delayed:
+    Inbound:                     0
+    Outbound:                    0
+
+"#
+        );
+
+        let json = show_bgp_neighbor(&bgp, args(), true);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let neighbor = &json["neighbors"][0];
+        assert_eq!(neighbor["address"], "10.0.0.2");
+        assert_eq!(neighbor["remote_as"], 65001);
+        assert_eq!(neighbor["peer_type"], "external");
+        assert_eq!(
+            neighbor["uptime"],
+            serde_json::json!({"seconds": null, "text": "never"})
+        );
+        assert_eq!(
+            neighbor["count"]["total"],
+            serde_json::json!({"sent": 0, "rcvd": 0})
+        );
+        assert_eq!(neighbor["transforms"], serde_json::json!([]));
+        assert_eq!(neighbor["ttl"], "single-hop");
+        assert!(neighbor.get("description").is_none());
+
+        let peer = bgp.peers.get_mut(&"10.0.0.2".parse().unwrap()).unwrap();
+        peer.config.description = Some("transit A".to_string());
+        let out = show_bgp_neighbor(&bgp, args(), false);
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next(),
+            Some("BGP neighbor is 10.0.0.2, remote AS 65001, local AS 65000, external link")
+        );
+        assert_eq!(lines.next(), Some(" Description: transit A"));
+        let json = show_bgp_neighbor(&bgp, args(), true);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["neighbors"][0]["description"], "transit A");
+    }
+
+    #[test]
+    fn vpnv4_output() {
+        let mut bgp = bgp();
+        let local = VpnRoute {
+            rd: "65000:1".parse().unwrap(),
+            prefix: "10.1.0.0/16".parse().unwrap(),
+            label: 16,
+            nexthop: Ipv4Addr::UNSPECIFIED,
+            route_targets: vec!["100:1".parse().unwrap()],
+            from: Ipv4Addr::UNSPECIFIED,
+            bandwidth: None,
+        };
+        let remote = VpnRoute {
+            rd: "65000:2".parse().unwrap(),
+            prefix: "10.2.0.0/16".parse().unwrap(),
+            label: 1000,
+            nexthop: "192.0.2.2".parse().unwrap(),
+            route_targets: vec!["100:1".parse().unwrap(), "100:2".parse().unwrap()],
+            from: "192.0.2.2".parse().unwrap(),
+            bandwidth: None,
+        };
+        bgp.vpn_rib_update(local, true);
+        bgp.vpn_rib_update(remote, true);
+        assert_eq!(
+            show_bgp_vpnv4(&bgp, args(), false),
+            format!(
+                "{}Route Distinguisher: 65000:1\n{}\nRoute Distinguisher: 65000:2\n{}\n\nTotal number of prefixes 2\n",
+                SHOW_VPN_HEADER,
+                "l    10.1.0.0/16                              16 local            100:1",
+                "*    10.2.0.0/16        192.0.2.2           1000 192.0.2.2        100:1 100:2"
+            )
+        );
+
+        let args = Args(VecDeque::from(["65000:2".to_string()]));
+        let json: serde_json::Value =
+            serde_json::from_str(&show_bgp_vpnv4(&bgp, args, true)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["label"], 1000);
+        assert_eq!(json[0]["from"], "192.0.2.2");
+        assert_eq!(json[0]["route_targets"][1], "100:2");
+    }
+
+    #[test]
+    fn bgp_routes_output() {
+        let mut bgp = bgp();
+        let prefix: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let attrs = vec![
+            Attribute::Origin(OriginAttr { origin: ORIGIN_IGP }),
+            Attribute::As4Path(As4PathAttr {
+                segments: Vec::new(),
+            }),
+        ];
+        local_route_update(
+            &mut bgp.ptree,
+            &mut bgp.nexthops,
+            prefix,
+            RouteFrom::Static,
+            Some(attrs),
+        );
+        bgp.ptree.get_mut(&prefix).unwrap()[0].selected = true;
+
+        let out = show_bgp(&bgp, args(), false);
+        assert!(out.starts_with(SHOW_BGP_HEADER));
+        assert_eq!(
+            out.lines().last(),
+            Some("*> 10.1.0.0/16      0.0.0.0                            32768 i")
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&show_bgp(&bgp, args(), true)).unwrap();
+        assert_eq!(json[0]["prefix"], "10.1.0.0/16");
+        assert_eq!(json[0]["next_hop"], "0.0.0.0");
+        assert_eq!(json[0]["weight"], 32768);
+        assert_eq!(json[0]["selected"], true);
+        assert!(json[0].get("rpki").is_none());
+    }
+
+    #[test]
+    fn advertised_routes_afi_safi() {
+        let mut bgp = bgp();
+        let local = VpnRoute {
+            rd: "65000:1".parse().unwrap(),
+            prefix: "10.1.0.0/16".parse().unwrap(),
+            label: 16,
+            nexthop: Ipv4Addr::UNSPECIFIED,
+            route_targets: vec!["100:1".parse().unwrap()],
+            from: Ipv4Addr::UNSPECIFIED,
+            bandwidth: None,
+        };
+        bgp.vpn_rib_update(local, true);
+        let peer = bgp.peers.get_mut(&"10.0.0.2".parse().unwrap()).unwrap();
+        let export = ExportPeer::new(peer);
+        peer.adj_out.vpn.sync(&export, &bgp.vpn);
+
+        let args = |afi_safi: &str| {
+            Args(VecDeque::from([
+                "10.0.0.2".to_string(),
+                afi_safi.to_string(),
+            ]))
+        };
+        let out = show_bgp_advertised_routes(&bgp, args("l3vpn-ipv4-unicast"), false);
+        assert!(out.starts_with(SHOW_ADJ_OUT_VPN_HEADER));
+        let mut lines = out.lines().skip(2);
+        assert_eq!(lines.next(), Some("Route Distinguisher: 65000:1"));
+        assert!(lines.next().unwrap().starts_with("p    10.1.0.0/16 "));
+        assert!(out.ends_with("Total number of prefixes 1\n"));
+
+        let json = show_bgp_advertised_routes(&bgp, args("l3vpn-ipv4-unicast"), true);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json[0]["label"], 16);
+        assert_eq!(json[0]["pending"], true);
+
+        // Nothing in IPv4 unicast.
+        let out = show_bgp_advertised_routes(&bgp, args("ipv4-unicast"), false);
+        assert!(out.ends_with("Total number of prefixes 0\n"));
+
+        assert_eq!(
+            show_bgp_advertised_routes(&bgp, args("ipv6-unicast"), false),
+            "% Address family ipv6-unicast is not supported\n"
+        );
     }
 }
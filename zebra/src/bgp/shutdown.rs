@@ -0,0 +1,158 @@
+use super::handler::Bgp;
+use super::peer::{fsm, fsm_init, Event, PeerShutdown};
+use std::net::Ipv4Addr;
+
+// Administrative shutdown of the neighbors. A neighbor which is shut down
+// ceases the session with the Administrative Shutdown notification and
+// stays in Idle, refusing the connections from the neighbor, until the
+// shutdown is removed. The global shutdown applies to all of the neighbors
+// for maintenance, and on exit.
+
+impl Bgp {
+    // Apply the change of the shutdown flags of the neighbor.
+    pub fn peer_shutdown_update(&mut self, addr: Ipv4Addr, f: impl FnOnce(&mut PeerShutdown)) {
+        let Some(peer) = self.peers.get_mut(&addr) else {
+            return;
+        };
+        let was_down = peer.shutdown.is_down();
+        f(&mut peer.shutdown);
+        let down = peer.shutdown.is_down();
+        if down && !was_down {
+            fsm(self, addr, Event::AdminShutdown);
+        } else if !down && was_down && peer.active {
+            peer.state = fsm_init(peer);
+        }
+    }
+
+    pub fn shutdown_update(&mut self, shutdown: bool) {
+        self.shutdown = shutdown;
+        let peers: Vec<Ipv4Addr> = self.peers.keys().cloned().collect();
+        for addr in peers.into_iter() {
+            self.peer_shutdown_update(addr, |s| s.global = shutdown);
+        }
+    }
+
+    // Cease all of the sessions with the Administrative Shutdown and stop
+    // listening.
+    pub fn exit(&mut self) {
+        self.shutdown_update(true);
+        self.listeners.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::BgpType;
+    use crate::bgp::peer::{accept, Peer, State};
+    use bytes::BytesMut;
+    use std::net::SocketAddr;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    fn bgp(addr: Ipv4Addr) -> Bgp {
+        let (rib, _) = mpsc::channel(1);
+        let mut bgp = Bgp::new(rib);
+        bgp.asn = 65000;
+        let mut peer = Peer::new(addr, 65000, bgp.router_id, 65001, addr, bgp.tx.clone());
+        peer.config.transport.passive = true;
+        peer.active = true;
+        peer.state = fsm_init(&mut peer);
+        bgp.peers.insert(addr, peer);
+        bgp
+    }
+
+    #[tokio::test]
+    async fn notification() {
+        let addr: Ipv4Addr = "10.0.0.2".parse().unwrap();
+        let mut bgp = bgp(addr);
+        let (tx, mut rx) = mpsc::unbounded_channel::<BytesMut>();
+        let peer = bgp.peers.get_mut(&addr).unwrap();
+        peer.packet_tx = Some(tx);
+        peer.state = State::Established;
+        peer.counter[BgpType::Update as usize].rcvd = 10;
+
+        bgp.peer_shutdown_update(addr, |s| {
+            s.message = Some("maintenance".to_string());
+            s.config = true;
+        });
+        let msg = rx.try_recv().unwrap();
+        // Cease, Administrative Shutdown with the shutdown communication.
+        assert_eq!(msg[18], BgpType::Notification as u8);
+        assert_eq!(&msg[19..21], &[6, 2]);
+        assert_eq!(msg[21] as usize, "maintenance".len());
+        assert_eq!(&msg[22..], b"maintenance");
+
+        let peer = bgp.peers.get(&addr).unwrap();
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(peer.state_str(), "Idle (Admin)");
+        assert!(peer.timer.idle_hold_timer.is_none());
+        assert!(peer.packet_tx.is_none());
+        // Counters are kept.
+        assert_eq!(peer.counter[BgpType::Update as usize].rcvd, 10);
+        assert_eq!(peer.counter[BgpType::Notification as usize].sent, 1);
+
+        // Reconnect is not attempted while shut down.
+        fsm(&mut bgp, addr, Event::Start);
+        assert_eq!(bgp.peers[&addr].state, State::Idle);
+
+        bgp.peer_shutdown_update(addr, |s| s.config = false);
+        assert_eq!(bgp.peers[&addr].state, State::Active);
+        assert_eq!(bgp.peers[&addr].state_str(), "Active");
+    }
+
+    #[test]
+    fn shutdown_data() {
+        use crate::bgp::peer::shutdown_data;
+        assert!(shutdown_data(None).is_empty());
+        assert!(shutdown_data(Some("")).is_empty());
+        let data = shutdown_data(Some("maintenance"));
+        assert_eq!(data[0], 11);
+        // Truncated to 255 octets without splitting a character.
+        let long = "\u{3042}".repeat(100);
+        let data = shutdown_data(Some(&long));
+        assert_eq!(data[0], 255);
+        assert!(std::str::from_utf8(&data[1..]).is_ok());
+    }
+
+    async fn connect(listener: &TcpListener) -> (TcpStream, TcpStream, SocketAddr) {
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, sockaddr) = listener.accept().await.unwrap();
+        (client, stream, sockaddr)
+    }
+
+    #[tokio::test]
+    async fn refuse_inbound() {
+        let addr: Ipv4Addr = "127.0.0.1".parse().unwrap();
+        let mut bgp = bgp(addr);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        bgp.peer_shutdown_update(addr, |s| s.exec = true);
+        let (mut client, stream, sockaddr) = connect(&listener).await;
+        accept(&mut bgp, stream, sockaddr);
+        let peer = &bgp.peers[&addr];
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.packet_tx.is_none());
+        // Connection is closed without OPEN.
+        let mut buf = [0u8; 64];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+
+        // Global shutdown refuses the connection as well.
+        bgp.peer_shutdown_update(addr, |s| s.exec = false);
+        bgp.shutdown_update(true);
+        let (mut client, stream, sockaddr) = connect(&listener).await;
+        accept(&mut bgp, stream, sockaddr);
+        assert_eq!(bgp.peers[&addr].state, State::Idle);
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+
+        // Accepted once the shutdown is removed.
+        bgp.shutdown_update(false);
+        assert_eq!(bgp.peers[&addr].state, State::Active);
+        let (_client, stream, sockaddr) = connect(&listener).await;
+        accept(&mut bgp, stream, sockaddr);
+        assert_eq!(bgp.peers[&addr].state, State::OpenSent);
+    }
+}
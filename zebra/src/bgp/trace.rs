@@ -0,0 +1,221 @@
+use super::peer::State;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Instant;
+
+// Per-peer ring buffer of recent protocol activity. Records are kept in a
+// compact form and formatted only when displayed. Nothing is recorded, and
+// the buffer is not allocated, unless tracing is enabled for the peer.
+
+pub const TRACE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceDir {
+    Send,
+    Recv,
+}
+
+impl fmt::Display for TraceDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Send => write!(f, "send"),
+            Self::Recv => write!(f, "recv"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    Open(TraceDir, u32, u16),
+    Keepalive(TraceDir),
+    Update(TraceDir, u32, u32),
+    Notification(TraceDir, u8, u8),
+    RouteRefresh(TraceDir, u8),
+    State(State, State),
+    Error(&'static str),
+}
+
+impl TraceEvent {
+    fn is_packet(&self) -> bool {
+        matches!(
+            self,
+            Self::Open(..)
+                | Self::Keepalive(_)
+                | Self::Update(..)
+                | Self::Notification(..)
+                | Self::RouteRefresh(..)
+        )
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(dir, asn, hold_time) => {
+                write!(f, "{} OPEN as {} hold time {}", dir, asn, hold_time)
+            }
+            Self::Keepalive(dir) => write!(f, "{} KEEPALIVE", dir),
+            Self::Update(dir, update, withdraw) => {
+                write!(f, "{} UPDATE nlri {} withdraw {}", dir, update, withdraw)
+            }
+            Self::Notification(dir, code, sub_code) => {
+                write!(f, "{} NOTIFICATION code {} subcode {}", dir, code, sub_code)
+            }
+            Self::RouteRefresh(dir, subtype) => {
+                write!(f, "{} ROUTE-REFRESH subtype {}", dir, subtype)
+            }
+            Self::State(from, to) => write!(f, "state {} -> {}", from.to_str(), to.to_str()),
+            Self::Error(err) => write!(f, "error {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    pub time: Instant,
+    pub event: TraceEvent,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TraceConfig {
+    pub packets: bool,
+    pub events: bool,
+    pub size: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct PeerTrace {
+    pub config: TraceConfig,
+    ring: VecDeque<TraceRecord>,
+}
+
+impl PeerTrace {
+    pub fn enabled(&self, event: &TraceEvent) -> bool {
+        if event.is_packet() {
+            self.config.packets
+        } else {
+            self.config.events
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.config.size.unwrap_or(TRACE_SIZE)
+    }
+
+    pub fn record(&mut self, event: TraceEvent) {
+        if !self.enabled(&event) {
+            return;
+        }
+        if self.ring.capacity() == 0 {
+            self.ring.reserve_exact(self.size());
+        }
+        while self.ring.len() >= self.size() {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(TraceRecord {
+            time: Instant::now(),
+            event,
+        });
+    }
+
+    // Apply the config change. The buffer is released when tracing has
+    // been disabled.
+    pub fn update(&mut self) {
+        if !self.config.packets && !self.config.events {
+            self.ring = VecDeque::new();
+            return;
+        }
+        while self.ring.len() > self.size() {
+            self.ring.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+
+    // Latest `last` records, oldest first.
+    pub fn records(&self, last: Option<usize>) -> impl Iterator<Item = &TraceRecord> {
+        let skip = last.map_or(0, |last| self.ring.len().saturating_sub(last));
+        self.ring.iter().skip(skip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_trace(size: usize) -> PeerTrace {
+        let mut trace = PeerTrace::default();
+        trace.config.packets = true;
+        trace.config.events = true;
+        trace.config.size = Some(size);
+        trace
+    }
+
+    #[test]
+    fn isolation() {
+        let mut enabled = new_trace(8);
+        let mut disabled = PeerTrace::default();
+        enabled.record(TraceEvent::Keepalive(TraceDir::Send));
+        disabled.record(TraceEvent::Keepalive(TraceDir::Send));
+        assert_eq!(enabled.len(), 1);
+        assert_eq!(disabled.len(), 0);
+
+        // Packets only.
+        let mut packets = PeerTrace::default();
+        packets.config.packets = true;
+        packets.record(TraceEvent::State(State::Idle, State::Connect));
+        packets.record(TraceEvent::Update(TraceDir::Recv, 2, 0));
+        assert_eq!(packets.len(), 1);
+    }
+
+    #[test]
+    fn eviction() {
+        let mut trace = new_trace(4);
+        for i in 0..10 {
+            trace.record(TraceEvent::Update(TraceDir::Recv, i, 0));
+        }
+        assert_eq!(trace.len(), 4);
+        let events: Vec<TraceEvent> = trace.records(None).map(|r| r.event.clone()).collect();
+        assert_eq!(events[0], TraceEvent::Update(TraceDir::Recv, 6, 0));
+        assert_eq!(events[3], TraceEvent::Update(TraceDir::Recv, 9, 0));
+
+        let last: Vec<&TraceRecord> = trace.records(Some(2)).collect();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[0].event, TraceEvent::Update(TraceDir::Recv, 8, 0));
+        assert_eq!(trace.records(Some(100)).count(), 4);
+
+        // Shrink.
+        trace.config.size = Some(2);
+        trace.update();
+        assert_eq!(trace.len(), 2);
+    }
+
+    #[test]
+    fn disabled_no_allocation() {
+        let mut trace = PeerTrace::default();
+        for _ in 0..100 {
+            trace.record(TraceEvent::Keepalive(TraceDir::Recv));
+            trace.record(TraceEvent::Error("hold timer expired"));
+        }
+        assert_eq!(trace.len(), 0);
+        assert_eq!(trace.capacity(), 0);
+
+        let mut trace = new_trace(4);
+        trace.record(TraceEvent::Keepalive(TraceDir::Recv));
+        assert!(trace.capacity() > 0);
+        trace.config.packets = false;
+        trace.config.events = false;
+        trace.update();
+        assert_eq!(trace.capacity(), 0);
+    }
+}
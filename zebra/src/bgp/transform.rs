@@ -0,0 +1,426 @@
+use super::packet::{is_confed, As4PathAttr, As4Segment, AS_SEQUENCE, AS_SET};
+use super::peer::PeerConfig;
+use std::str::FromStr;
+
+// Outbound transforms of a neighbor, applied to the attributes in the
+// Adj-RIB-Out. next-hop-self sets the nexthop to the local address of the
+// session towards an internal peer. remove-private-as removes or replaces the
+// private AS numbers of the AS path, as-override replaces the AS of the peer
+// with the local AS, and as-path-prepend lengthens the path, all towards an
+// external peer. Confederation segments are not changed.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextHopSelf {
+    Enabled,
+    // Routes learned from internal peers are rewritten as well.
+    Force,
+}
+
+impl NextHopSelf {
+    pub fn applies(&self, ibgp_learned: bool) -> bool {
+        *self == Self::Force || !ibgp_learned
+    }
+}
+
+impl FromStr for NextHopSelf {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enabled" => Ok(Self::Enabled),
+            "force" => Ok(Self::Force),
+            _ => Err(()),
+        }
+    }
+}
+
+// Without all, the path is changed only when it consists of private AS
+// numbers and does not contain the AS of the peer. With all, every private AS
+// number other than the AS of the peer is changed, so that the peer still
+// detects the loop. With replace-as, the private AS numbers are replaced with
+// the local AS instead of being removed, which keeps the path length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovePrivateAs {
+    Enabled,
+    All,
+    ReplaceAs,
+    AllReplaceAs,
+}
+
+impl RemovePrivateAs {
+    fn all(&self) -> bool {
+        matches!(self, Self::All | Self::AllReplaceAs)
+    }
+
+    fn replace(&self) -> bool {
+        matches!(self, Self::ReplaceAs | Self::AllReplaceAs)
+    }
+}
+
+impl FromStr for RemovePrivateAs {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enabled" => Ok(Self::Enabled),
+            "all" => Ok(Self::All),
+            "replace-as" => Ok(Self::ReplaceAs),
+            "all-replace-as" => Ok(Self::AllReplaceAs),
+            _ => Err(()),
+        }
+    }
+}
+
+// Private use AS numbers, RFC 6996.
+pub fn private_as(asn: u32) -> bool {
+    (64512..=65534).contains(&asn) || (4200000000..=4294967294).contains(&asn)
+}
+
+// AS numbers prepended for traffic engineering, in front of the rewritten
+// path and behind the local AS. last-as repeats the leftmost AS of the path,
+// the neighboring AS the route was learned from, and is applied before the
+// AS numbers. The local AS is repeated for a path without the leftmost AS,
+// e.g. of a local route or starting with AS_SET.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AsPathPrepend {
+    pub asns: Vec<u32>,
+    pub last_as: u8,
+}
+
+impl AsPathPrepend {
+    pub fn is_empty(&self) -> bool {
+        self.asns.is_empty() && self.last_as == 0
+    }
+
+    pub fn apply(&self, path: &mut As4PathAttr, local_as: u32) {
+        if self.last_as > 0 {
+            let last_as = path
+                .segments
+                .iter()
+                .find(|seg| !is_confed(seg.typ))
+                .filter(|seg| seg.typ == AS_SEQUENCE)
+                .and_then(|seg| seg.asn.first())
+                .copied()
+                .unwrap_or(local_as);
+            path.prepend(&vec![last_as; self.last_as as usize]);
+        }
+        path.prepend(&self.asns);
+    }
+}
+
+// AS numbers separated by spaces, e.g. "65001 65001".
+pub fn prepend_parse(s: &str) -> Option<Vec<u32>> {
+    let asns: Vec<u32> = s
+        .split_whitespace()
+        .map(|asn| asn.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    if asns.is_empty() || asns.contains(&0) {
+        return None;
+    }
+    Some(asns)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsPathRewrite {
+    pub local_as: u32,
+    pub peer_as: u32,
+    pub remove_private_as: Option<RemovePrivateAs>,
+    pub as_override: bool,
+    pub prepend: AsPathPrepend,
+}
+
+impl AsPathRewrite {
+    pub fn apply(&self, path: &mut As4PathAttr) {
+        if let Some(mode) = self.remove_private_as {
+            self.remove_private(mode, path);
+        }
+        if self.as_override {
+            path_replace(path, |asn| asn == self.peer_as, self.local_as);
+        }
+        self.prepend.apply(path, self.local_as);
+    }
+
+    fn remove_private(&self, mode: RemovePrivateAs, path: &mut As4PathAttr) {
+        if !mode.all() {
+            let only_private = path
+                .segments
+                .iter()
+                .filter(|seg| !is_confed(seg.typ))
+                .flat_map(|seg| seg.asn.iter())
+                .all(|asn| private_as(*asn));
+            if !only_private || path.contains(self.peer_as) {
+                return;
+            }
+        }
+        let private = |asn: u32| private_as(asn) && asn != self.peer_as;
+        if mode.replace() {
+            path_replace(path, private, self.local_as);
+        } else {
+            path_remove(path, private);
+        }
+    }
+}
+
+// AS_SET is an unordered set, so the duplicates made by the replacement are
+// removed from it.
+fn path_replace(path: &mut As4PathAttr, f: impl Fn(u32) -> bool, to: u32) {
+    for seg in path.segments.iter_mut().filter(|seg| !is_confed(seg.typ)) {
+        for asn in seg.asn.iter_mut().filter(|asn| f(**asn)) {
+            *asn = to;
+        }
+        if seg.typ == AS_SET {
+            let mut seen = Vec::new();
+            seg.asn.retain(|asn| {
+                let first = !seen.contains(asn);
+                seen.push(*asn);
+                first
+            });
+        }
+    }
+}
+
+// Segments left empty are dropped, and the AS_SEQUENCE segments which become
+// adjacent are merged.
+fn path_remove(path: &mut As4PathAttr, f: impl Fn(u32) -> bool) {
+    let mut segments: Vec<As4Segment> = Vec::new();
+    for mut seg in std::mem::take(&mut path.segments).into_iter() {
+        if !is_confed(seg.typ) {
+            seg.asn.retain(|asn| !f(*asn));
+            if seg.asn.is_empty() {
+                continue;
+            }
+        }
+        match segments.last_mut() {
+            Some(last) if last.typ == AS_SEQUENCE && seg.typ == AS_SEQUENCE => {
+                last.asn.append(&mut seg.asn);
+            }
+            _ => segments.push(seg),
+        }
+    }
+    path.segments = segments;
+}
+
+// Active transforms for show neighbor.
+pub fn transforms_str(config: &PeerConfig) -> Vec<String> {
+    let mut transforms = Vec::new();
+    match config.next_hop_self {
+        Some(NextHopSelf::Force) => transforms.push("next-hop-self force".to_string()),
+        Some(NextHopSelf::Enabled) => transforms.push("next-hop-self".to_string()),
+        None => {}
+    }
+    if let Some(mode) = config.remove_private_as {
+        let mut s = String::from("remove-private-as");
+        if mode.all() {
+            s.push_str(" all");
+        }
+        if mode.replace() {
+            s.push_str(" replace-as");
+        }
+        transforms.push(s);
+    }
+    if config.as_override {
+        transforms.push("as-override".to_string());
+    }
+    let prepend = &config.as_path_prepend;
+    if !prepend.asns.is_empty() {
+        let asns: Vec<String> = prepend.asns.iter().map(|asn| asn.to_string()).collect();
+        transforms.push(format!("as-path-prepend {}", asns.join(" ")));
+    }
+    if prepend.last_as > 0 {
+        transforms.push(format!("as-path-prepend last-as {}", prepend.last_as));
+    }
+    transforms
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::AS_CONFED_SEQUENCE;
+
+    fn seg(typ: u8, asn: &[u32]) -> As4Segment {
+        As4Segment {
+            typ,
+            asn: asn.to_vec(),
+        }
+    }
+
+    fn rewrite(mode: Option<RemovePrivateAs>, as_override: bool, segs: &[As4Segment]) -> String {
+        let rewrite = AsPathRewrite {
+            local_as: 65000,
+            peer_as: 65002,
+            remove_private_as: mode,
+            as_override,
+            prepend: AsPathPrepend::default(),
+        };
+        let mut path = As4PathAttr {
+            segments: segs.to_vec(),
+        };
+        rewrite.apply(&mut path);
+        path.to_string()
+    }
+
+    #[test]
+    fn private() {
+        assert!(!private_as(64511));
+        assert!(private_as(64512));
+        assert!(private_as(65534));
+        assert!(!private_as(65535));
+        assert!(!private_as(4199999999));
+        assert!(private_as(4200000000));
+        assert!(private_as(4294967294));
+        assert!(!private_as(4294967295));
+    }
+
+    #[test]
+    fn remove_private_as() {
+        let private = [seg(AS_SEQUENCE, &[64512, 4200000001, 65100])];
+        let mixed = [seg(AS_SEQUENCE, &[64512, 65001, 4200000001])];
+        let remove = Some(RemovePrivateAs::Enabled);
+        let all = Some(RemovePrivateAs::All);
+        let replace = Some(RemovePrivateAs::ReplaceAs);
+        let all_replace = Some(RemovePrivateAs::AllReplaceAs);
+
+        // Path of private AS numbers only.
+        assert_eq!(rewrite(remove, false, &private), "");
+        assert_eq!(rewrite(replace, false, &private), "65000 65000 65000");
+
+        // Mixed path is left as is unless all.
+        assert_eq!(rewrite(remove, false, &mixed), "64512 65001 4200000001");
+        assert_eq!(rewrite(replace, false, &mixed), "64512 65001 4200000001");
+        assert_eq!(rewrite(all, false, &mixed), "65001");
+        assert_eq!(rewrite(all_replace, false, &mixed), "65000 65001 65000");
+
+        // AS of the peer is kept for loop detection.
+        let path = [seg(AS_SEQUENCE, &[64512, 65002, 64513])];
+        assert_eq!(rewrite(remove, false, &path), "64512 65002 64513");
+        assert_eq!(rewrite(all, false, &path), "65002");
+        assert_eq!(rewrite(all_replace, false, &path), "65000 65002 65000");
+    }
+
+    #[test]
+    fn as_set() {
+        let path = [
+            seg(AS_SEQUENCE, &[65001]),
+            seg(AS_SET, &[64512, 4200000000]),
+            seg(AS_SEQUENCE, &[65003, 64513]),
+        ];
+        // Emptied AS_SET is dropped and the sequences are merged.
+        assert_eq!(
+            rewrite(Some(RemovePrivateAs::All), false, &path),
+            "65001 65003"
+        );
+        // Replaced members of AS_SET are merged.
+        assert_eq!(
+            rewrite(Some(RemovePrivateAs::AllReplaceAs), false, &path),
+            "65001 {65000} 65003 65000"
+        );
+        let path = [seg(AS_SET, &[64512, 65001, 4200000005])];
+        assert_eq!(rewrite(Some(RemovePrivateAs::All), false, &path), "{65001}");
+        assert_eq!(
+            rewrite(Some(RemovePrivateAs::AllReplaceAs), false, &path),
+            "{65000,65001}"
+        );
+        let path = [seg(AS_SET, &[64512, 4200000005])];
+        assert_eq!(rewrite(Some(RemovePrivateAs::Enabled), false, &path), "");
+    }
+
+    #[test]
+    fn as_override() {
+        let path = [
+            seg(AS_SEQUENCE, &[65001, 65002]),
+            seg(AS_SET, &[65002, 65000, 65003]),
+        ];
+        assert_eq!(rewrite(None, true, &path), "65001 65000 {65000,65003}");
+
+        // Confederation segments are not changed.
+        let path = [
+            seg(AS_CONFED_SEQUENCE, &[64512, 65002]),
+            seg(AS_SEQUENCE, &[65002, 64513]),
+        ];
+        assert_eq!(
+            rewrite(Some(RemovePrivateAs::All), true, &path),
+            "(64512 65002) 65000"
+        );
+    }
+
+    fn prepend(asns: &[u32], last_as: u8, segs: &[As4Segment]) -> String {
+        let prepend = AsPathPrepend {
+            asns: asns.to_vec(),
+            last_as,
+        };
+        let mut path = As4PathAttr {
+            segments: segs.to_vec(),
+        };
+        prepend.apply(&mut path, 65000);
+        path.to_string()
+    }
+
+    #[test]
+    fn as_path_prepend() {
+        let path = [seg(AS_SEQUENCE, &[65001, 65002])];
+        assert_eq!(
+            prepend(&[65000, 65000], 0, &path),
+            "65000 65000 65001 65002"
+        );
+        assert_eq!(
+            prepend(&[4200000001, 65000], 0, &path),
+            "4200000001 65000 65001 65002"
+        );
+        assert_eq!(prepend(&[], 0, &path), "65001 65002");
+
+        // Leftmost AS is repeated, then the AS numbers are prepended.
+        assert_eq!(prepend(&[], 2, &path), "65001 65001 65001 65002");
+        assert_eq!(prepend(&[65000], 1, &path), "65000 65001 65001 65002");
+        let path = [seg(AS_SEQUENCE, &[4200000001])];
+        assert_eq!(
+            prepend(&[], 3, &path),
+            "4200000001 4200000001 4200000001 4200000001"
+        );
+
+        // Local AS without the leftmost AS.
+        assert_eq!(prepend(&[], 2, &[]), "65000 65000");
+        let path = [seg(AS_SET, &[65001, 65002])];
+        assert_eq!(prepend(&[], 1, &path), "65000 {65001,65002}");
+        assert_eq!(prepend(&[65003], 0, &path), "65003 {65001,65002}");
+
+        // Confederation segments are skipped for the leftmost AS.
+        let path = [
+            seg(AS_CONFED_SEQUENCE, &[64512]),
+            seg(AS_SEQUENCE, &[65001]),
+        ];
+        assert_eq!(prepend(&[], 1, &path), "65001 (64512) 65001");
+
+        assert_eq!(
+            prepend_parse("65001  4200000001"),
+            Some(vec![65001, 4200000001])
+        );
+        assert_eq!(prepend_parse(""), None);
+        assert_eq!(prepend_parse("65001 0"), None);
+        assert_eq!(prepend_parse("65001 as"), None);
+    }
+
+    #[test]
+    fn transforms() {
+        let mut config = PeerConfig::default();
+        assert!(transforms_str(&config).is_empty());
+        config.next_hop_self = Some(NextHopSelf::Force);
+        config.remove_private_as = Some(RemovePrivateAs::AllReplaceAs);
+        config.as_override = true;
+        config.as_path_prepend = AsPathPrepend {
+            asns: vec![65000, 65000],
+            last_as: 2,
+        };
+        assert_eq!(
+            transforms_str(&config),
+            vec![
+                "next-hop-self force",
+                "remove-private-as all replace-as",
+                "as-override",
+                "as-path-prepend 65000 65000",
+                "as-path-prepend last-as 2"
+            ]
+        );
+        assert_eq!("all-replace-as".parse(), Ok(RemovePrivateAs::AllReplaceAs));
+        assert!("force".parse::<RemovePrivateAs>().is_err());
+    }
+}
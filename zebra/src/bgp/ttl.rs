@@ -0,0 +1,300 @@
+// TTL of the BGP sessions.
+//
+// A single-hop eBGP session is sent with TTL 1 so that it is established only
+// with a directly connected neighbor. ebgp-multihop raises the TTL for a
+// neighbor which is some hops away. ttl-security is the Generalized TTL
+// Security Mechanism (RFC 5082): packets are sent with TTL 255 and the kernel
+// drops the packets of the session arriving with TTL below 255 - hops + 1
+// with IP_MINTTL or IPV6_MINHOPCOUNT, so that a spoofed packet from far away
+// never reaches BGP. iBGP sessions use the system default TTL.
+//
+// The two options are mutually exclusive.
+
+use std::fmt;
+use std::io;
+use std::os::unix::io::RawFd;
+
+pub const TTL_MAX: u8 = 255;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TtlConfig {
+    pub ttl_security: Option<u8>,
+    pub ebgp_multihop: Option<u8>,
+}
+
+impl TtlConfig {
+    pub fn set_ttl_security(&mut self, hops: Option<u8>) -> Result<(), String> {
+        if hops.is_some() && self.ebgp_multihop.is_some() {
+            return Err(String::from(
+                "ttl-security and ebgp-multihop are mutually exclusive",
+            ));
+        }
+        if hops.is_some_and(|hops| hops == 0 || hops == TTL_MAX) {
+            return Err(String::from("ttl-security hops must be 1..254"));
+        }
+        self.ttl_security = hops;
+        Ok(())
+    }
+
+    pub fn set_ebgp_multihop(&mut self, ttl: Option<u8>) -> Result<(), String> {
+        if ttl.is_some() && self.ttl_security.is_some() {
+            return Err(String::from(
+                "ttl-security and ebgp-multihop are mutually exclusive",
+            ));
+        }
+        if ttl == Some(0) {
+            return Err(String::from("ebgp-multihop TTL must be 1..255"));
+        }
+        self.ebgp_multihop = ttl;
+        Ok(())
+    }
+
+    pub fn mode(&self, ebgp: bool) -> TtlMode {
+        if let Some(hops) = self.ttl_security {
+            return TtlMode::Security(hops);
+        }
+        if !ebgp {
+            return TtlMode::Default;
+        }
+        match self.ebgp_multihop {
+            Some(ttl) => TtlMode::Multihop(ttl),
+            None => TtlMode::SingleHop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TtlMode {
+    Default,
+    SingleHop,
+    Multihop(u8),
+    Security(u8),
+}
+
+impl TtlMode {
+    // Outgoing TTL, None for the system default.
+    pub fn ttl(&self) -> Option<u8> {
+        match self {
+            Self::Default => None,
+            Self::SingleHop => Some(1),
+            Self::Multihop(ttl) => Some(*ttl),
+            Self::Security(_) => Some(TTL_MAX),
+        }
+    }
+
+    // Minimum TTL of the received packets.
+    pub fn min_ttl(&self) -> Option<u8> {
+        match self {
+            Self::Security(hops) => Some(TTL_MAX - hops + 1),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TtlMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::SingleHop => write!(f, "single-hop"),
+            Self::Multihop(ttl) => write!(f, "ebgp-multihop, TTL {}", ttl),
+            Self::Security(hops) => write!(f, "ttl-security, {} hops", hops),
+        }
+    }
+}
+
+// Socket options of the TTL, so that the selection of the options can be
+// tested without a socket.
+pub trait TtlSocket {
+    fn set_ttl(&self, ttl: u8) -> io::Result<()>;
+    fn set_min_ttl(&self, ttl: u8) -> io::Result<()>;
+}
+
+pub fn ttl_apply<S: TtlSocket>(socket: &S, mode: TtlMode) -> io::Result<()> {
+    if let Some(ttl) = mode.ttl() {
+        socket.set_ttl(ttl)?;
+    }
+    if let Some(ttl) = mode.min_ttl() {
+        socket.set_min_ttl(ttl)?;
+    }
+    Ok(())
+}
+
+pub struct SocketFd {
+    pub fd: RawFd,
+    pub ipv6: bool,
+}
+
+// IPV6_MINHOPCOUNT in <linux/in6.h>.
+#[cfg(target_os = "linux")]
+const IPV6_MINHOPCOUNT: libc::c_int = 73;
+
+fn setsockopt_int(fd: RawFd, level: libc::c_int, name: libc::c_int, value: u8) -> io::Result<()> {
+    let value = value as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl TtlSocket for SocketFd {
+    fn set_ttl(&self, ttl: u8) -> io::Result<()> {
+        if self.ipv6 {
+            setsockopt_int(self.fd, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, ttl)
+        } else {
+            setsockopt_int(self.fd, libc::IPPROTO_IP, libc::IP_TTL, ttl)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_min_ttl(&self, ttl: u8) -> io::Result<()> {
+        if self.ipv6 {
+            setsockopt_int(self.fd, libc::IPPROTO_IPV6, IPV6_MINHOPCOUNT, ttl)
+        } else {
+            setsockopt_int(self.fd, libc::IPPROTO_IP, libc::IP_MINTTL, ttl)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_min_ttl(&self, _ttl: u8) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "TTL security is not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MockSocket {
+        ops: RefCell<Vec<(&'static str, u8)>>,
+    }
+
+    impl TtlSocket for MockSocket {
+        fn set_ttl(&self, ttl: u8) -> io::Result<()> {
+            self.ops.borrow_mut().push(("ttl", ttl));
+            Ok(())
+        }
+
+        fn set_min_ttl(&self, ttl: u8) -> io::Result<()> {
+            self.ops.borrow_mut().push(("minttl", ttl));
+            Ok(())
+        }
+    }
+
+    fn applied(mode: TtlMode) -> Vec<(&'static str, u8)> {
+        let socket = MockSocket::default();
+        ttl_apply(&socket, mode).unwrap();
+        socket.ops.take()
+    }
+
+    #[test]
+    fn socket_options() {
+        let mut config = TtlConfig::default();
+        assert_eq!(applied(config.mode(false)), vec![]);
+        assert_eq!(applied(config.mode(true)), vec![("ttl", 1)]);
+
+        config.set_ebgp_multihop(Some(5)).unwrap();
+        assert_eq!(config.mode(true), TtlMode::Multihop(5));
+        assert_eq!(applied(config.mode(true)), vec![("ttl", 5)]);
+        // Multihop is for eBGP only.
+        assert_eq!(config.mode(false), TtlMode::Default);
+
+        config.set_ebgp_multihop(None).unwrap();
+        config.set_ttl_security(Some(1)).unwrap();
+        assert_eq!(
+            applied(config.mode(true)),
+            vec![("ttl", 255), ("minttl", 255)]
+        );
+        config.set_ttl_security(Some(3)).unwrap();
+        assert_eq!(
+            applied(config.mode(false)),
+            vec![("ttl", 255), ("minttl", 253)]
+        );
+        assert_eq!(config.mode(true).to_string(), "ttl-security, 3 hops");
+    }
+
+    #[cfg(target_os = "linux")]
+    fn getsockopt_int(fd: RawFd, name: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::IPPROTO_IP,
+                name,
+                &mut value as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        assert_eq!(ret, 0);
+        value
+    }
+
+    // Options set on the socket, and the connection from beyond the hop
+    // limit dropped by the kernel.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn gtsm_socket() {
+        use socket2::{Domain, Socket, Type};
+        use std::net::TcpListener;
+        use std::os::unix::io::AsRawFd;
+        use std::time::Duration;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = SocketFd {
+            fd: listener.as_raw_fd(),
+            ipv6: false,
+        };
+        ttl_apply(&fd, TtlMode::Multihop(5)).unwrap();
+        assert_eq!(getsockopt_int(fd.fd, libc::IP_TTL), 5);
+
+        ttl_apply(&fd, TtlMode::Security(3)).unwrap();
+        assert_eq!(getsockopt_int(fd.fd, libc::IP_TTL), 255);
+        assert_eq!(getsockopt_int(fd.fd, libc::IP_MINTTL), 253);
+
+        // TTL is not decremented over the loopback, the peer is 256 - TTL
+        // hops away.
+        let addr = listener.local_addr().unwrap().into();
+        let connect = |ttl: u32| {
+            let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+            socket.set_ttl(ttl).unwrap();
+            socket.connect_timeout(&addr, Duration::from_millis(500))
+        };
+        assert!(connect(252).is_err());
+        assert!(connect(253).is_ok());
+    }
+
+    #[test]
+    fn exclusive() {
+        let mut config = TtlConfig::default();
+        config.set_ttl_security(Some(1)).unwrap();
+        assert!(config.set_ebgp_multihop(Some(2)).is_err());
+        assert_eq!(config.ebgp_multihop, None);
+        // Removal is always allowed.
+        config.set_ebgp_multihop(None).unwrap();
+
+        config.set_ttl_security(None).unwrap();
+        config.set_ebgp_multihop(Some(2)).unwrap();
+        assert!(config.set_ttl_security(Some(1)).is_err());
+        assert_eq!(config.mode(true), TtlMode::Multihop(2));
+
+        assert!(config.set_ebgp_multihop(Some(0)).is_err());
+        config.set_ebgp_multihop(None).unwrap();
+        assert!(config.set_ttl_security(Some(0)).is_err());
+        assert!(config.set_ttl_security(Some(255)).is_err());
+        assert_eq!(config, TtlConfig::default());
+    }
+}
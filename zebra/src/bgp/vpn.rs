@@ -0,0 +1,501 @@
+use super::adj_rib::{ExportPeer, LOCAL_PREF_DEFAULT};
+use super::handler::Bgp;
+use super::packet::{
+    As4PathAttr, Attribute, Attrs, ExtendedComAttr, LocalPrefAttr, MpNlriAttr, OriginAttr,
+    RouteDistinguisher, UpdatePacket, Vpnv4Nlri, ORIGIN_INCOMPLETE,
+};
+use super::peer::{Peer, State};
+use super::{Afi, AfiSafi, Safi};
+use crate::rib::api::RibTx;
+use crate::rib::vrf::{VpnKey, VpnRoute};
+use bytes::BytesMut;
+use ipnet::Ipv4Net;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+
+// VPN-IPv4 routes, RFC 4364. Routes received from the peers are kept by the
+// RD, the prefix and the peer, and passed to the RIB which imports them to
+// the VRFs by route-target. Routes of the VRFs exported by the RIB are kept
+// with the unspecified peer and advertised to the peers which negotiated
+// VPN-IPv4, with the local address of the session as the nexthop. Received
+// routes are not advertised again.
+
+pub type VpnTable = BTreeMap<VpnKey, VpnRoute>;
+
+// NLRI to be advertised with the nexthop and the attributes.
+pub type VpnUpdate = (Vpnv4Nlri, Ipv4Addr, Attrs);
+
+pub fn vpnv4() -> AfiSafi {
+    AfiSafi::new(Afi::IP, Safi::MplsVpn)
+}
+
+pub fn vpn_update(vpn: &mut VpnTable, route: &VpnRoute, add: bool) {
+    if add {
+        vpn.insert(route.key(), route.clone());
+    } else {
+        vpn.remove(&route.key());
+    }
+}
+
+// Remove the routes of the peer and withdraw them from the RIB.
+pub fn vpn_clear(vpn: &mut VpnTable, from: Ipv4Addr) -> Vec<RibTx> {
+    let keys: Vec<VpnKey> = vpn.keys().filter(|(_, _, f)| *f == from).copied().collect();
+    keys.iter()
+        .filter_map(|key| vpn.remove(key))
+        .map(RibTx::VpnRouteDel)
+        .collect()
+}
+
+fn vpn_nlri(route: &VpnRoute) -> Vpnv4Nlri {
+    Vpnv4Nlri {
+        label: route.label,
+        rd: route.rd,
+        prefix: route.prefix,
+    }
+}
+
+// Attributes of the exported route other than MP_REACH_NLRI. The
+// route-targets are attached as extended communities.
+pub fn vpn_attrs(peer: &ExportPeer, route: &VpnRoute) -> Attrs {
+    let mut as_path = As4PathAttr {
+        segments: Vec::new(),
+    };
+    let mut attrs = vec![Attribute::Origin(OriginAttr {
+        origin: ORIGIN_INCOMPLETE,
+    })];
+    if peer.ibgp {
+        attrs.push(Attribute::LocalPref(LocalPrefAttr {
+            local_pref: LOCAL_PREF_DEFAULT,
+        }));
+    } else {
+        as_path.prepend(&[peer.local_as]);
+    }
+    attrs.push(Attribute::As4Path(as_path));
+    let ecoms = route.route_targets.iter().map(|rt| (*rt).into()).collect();
+    attrs.push(Attribute::ExtendedCom(ExtendedComAttr(ecoms)));
+    attrs
+}
+
+#[derive(Debug, Clone)]
+pub struct VpnOut {
+    // Route as advertised, with the nexthop of the session.
+    pub route: VpnRoute,
+    pub attrs: Attrs,
+}
+
+// Exported route of the VRF is advertised by the RD and the prefix.
+pub type VpnOutKey = (RouteDistinguisher, Ipv4Net);
+
+// VPN-IPv4 part of the Adj-RIB-Out of the peer.
+#[derive(Debug, Default)]
+pub struct VpnRibOut {
+    pub routes: BTreeMap<VpnOutKey, VpnOut>,
+    // Updated and not yet sent.
+    pub pending: BTreeSet<VpnOutKey>,
+    // Withdrawn and not yet sent.
+    pub withdraw: BTreeMap<VpnOutKey, Vpnv4Nlri>,
+    // Export parameters of the last sync with the whole VPN table.
+    pub export: Option<ExportPeer>,
+}
+
+impl VpnRibOut {
+    pub fn is_pending(&self) -> bool {
+        !self.withdraw.is_empty() || !self.pending.is_empty()
+    }
+
+    pub fn refresh(&mut self) {
+        self.pending = self.routes.keys().copied().collect();
+    }
+
+    // Replace the entry of the key with the exported route, or remove it
+    // when nothing is exported. The attributes are built again when the
+    // export parameters have changed. Returns true when the entry has been
+    // changed.
+    fn update(&mut self, peer: &ExportPeer, key: VpnOutKey, route: Option<&VpnRoute>) -> bool {
+        let Some(route) = route else {
+            let Some(out) = self.routes.remove(&key) else {
+                return false;
+            };
+            self.pending.remove(&key);
+            self.withdraw.insert(key, vpn_nlri(&out.route));
+            return true;
+        };
+        let mut route = route.clone();
+        route.nexthop = peer.local_addr;
+        if self.export.as_ref() == Some(peer)
+            && self.routes.get(&key).is_some_and(|out| out.route == route)
+        {
+            return false;
+        }
+        self.withdraw.remove(&key);
+        self.pending.insert(key);
+        let out = VpnOut {
+            attrs: vpn_attrs(peer, &route),
+            route,
+        };
+        self.routes.insert(key, out);
+        true
+    }
+
+    // Bring the routes in sync with the exported routes. Returns the number
+    // of changed entries.
+    pub fn sync(&mut self, peer: &ExportPeer, vpn: &VpnTable) -> usize {
+        let mut changed = 0;
+        let mut exported = BTreeSet::new();
+        for route in vpn.values().filter(|route| route.from.is_unspecified()) {
+            let key = (route.rd, route.prefix);
+            exported.insert(key);
+            if self.update(peer, key, Some(route)) {
+                changed += 1;
+            }
+        }
+        let removed: Vec<VpnOutKey> = self
+            .routes
+            .keys()
+            .filter(|key| !exported.contains(key))
+            .copied()
+            .collect();
+        for key in removed.into_iter() {
+            if self.update(peer, key, None) {
+                changed += 1;
+            }
+        }
+        self.export = Some(peer.clone());
+        changed
+    }
+
+    // Export the changed routes of the VRFs. The whole VPN table is exported
+    // only when the export parameters differ from the last sync. Returns the
+    // number of changed entries.
+    pub fn sync_changed(
+        &mut self,
+        peer: &ExportPeer,
+        vpn: &VpnTable,
+        keys: &BTreeSet<VpnOutKey>,
+    ) -> usize {
+        if self.export.as_ref() != Some(peer) {
+            return self.sync(peer, vpn);
+        }
+        let mut changed = 0;
+        for (rd, prefix) in keys.iter() {
+            let route = vpn.get(&(*rd, *prefix, Ipv4Addr::UNSPECIFIED));
+            if self.update(peer, (*rd, *prefix), route) {
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    // Take the pending withdrawals and routes with the nexthop and the
+    // attributes to be sent.
+    pub fn flush(&mut self) -> (Vec<Vpnv4Nlri>, Vec<VpnUpdate>) {
+        let withdraw = std::mem::take(&mut self.withdraw).into_values().collect();
+        let updates = std::mem::take(&mut self.pending)
+            .into_iter()
+            .filter_map(|key| {
+                let out = self.routes.get(&key)?;
+                Some((vpn_nlri(&out.route), out.route.nexthop, out.attrs.clone()))
+            })
+            .collect();
+        (withdraw, updates)
+    }
+}
+
+fn mp_nlri(nexthop: Option<Ipv4Addr>, nlri: Vec<Vpnv4Nlri>) -> MpNlriAttr {
+    MpNlriAttr {
+        next_hop: None,
+        prefix: Vec::new(),
+        ipv4_prefix: Vec::new(),
+        vpnv4_next_hop: nexthop,
+        vpnv4_prefix: nlri,
+    }
+}
+
+// Pack the flushed VPN-IPv4 changes into UPDATE messages as the IPv4 unicast
+// ones are. The NLRI is carried in MP_REACH_NLRI and MP_UNREACH_NLRI.
+pub fn vpn_update_pack(
+    withdraw: Vec<Vpnv4Nlri>,
+    updates: Vec<VpnUpdate>,
+    as4: bool,
+    max: usize,
+) -> Vec<UpdatePacket> {
+    let mut packets = Vec::new();
+
+    // MP_UNREACH_NLRI with the extended length, and the AFI and the SAFI.
+    let empty = UpdatePacket::new().encode(as4).len() + 4 + 3;
+    let mut nlri: Vec<Vpnv4Nlri> = Vec::new();
+    let mut len = empty;
+    let unreach = |nlri: Vec<Vpnv4Nlri>| {
+        let mut packet = UpdatePacket::new();
+        packet.attrs = vec![Attribute::MpUnreachNlri(mp_nlri(None, nlri))];
+        packet
+    };
+    for n in withdraw.into_iter() {
+        if !nlri.is_empty() && len + n.encoded_len() > max {
+            packets.push(unreach(std::mem::take(&mut nlri)));
+            len = empty;
+        }
+        len += n.encoded_len();
+        nlri.push(n);
+    }
+    if !nlri.is_empty() {
+        packets.push(unreach(nlri));
+    }
+
+    // Routes grouped by the nexthop and the encoded attributes.
+    let mut groups: Vec<(usize, Ipv4Addr, Attrs, Vec<Vec<Vpnv4Nlri>>)> = Vec::new();
+    let mut index: HashMap<BytesMut, usize> = HashMap::new();
+    for (n, nexthop, attrs) in updates.into_iter() {
+        let mut packet = UpdatePacket::new();
+        packet.attrs = attrs.clone();
+        packet
+            .attrs
+            .push(Attribute::MpReachNlri(mp_nlri(Some(nexthop), Vec::new())));
+        let encoded = packet.encode(as4);
+        let i = match index.get(&encoded) {
+            Some(i) => *i,
+            None => {
+                index.insert(encoded.clone(), groups.len());
+                // One more octet for the extended length of MP_REACH_NLRI.
+                groups.push((encoded.len() + 1, nexthop, attrs, vec![Vec::new()]));
+                groups.len() - 1
+            }
+        };
+        let (empty, _, _, group) = &mut groups[i];
+        let last = group.last_mut().unwrap();
+        let len = *empty + last.iter().map(Vpnv4Nlri::encoded_len).sum::<usize>();
+        if !last.is_empty() && len + n.encoded_len() > max {
+            group.push(Vec::new());
+        }
+        group.last_mut().unwrap().push(n);
+    }
+    for (_, nexthop, attrs, group) in groups.into_iter() {
+        for nlri in group.into_iter() {
+            let mut packet = UpdatePacket::new();
+            packet.attrs = attrs.clone();
+            packet
+                .attrs
+                .push(Attribute::MpReachNlri(mp_nlri(Some(nexthop), nlri)));
+            packets.push(packet);
+        }
+    }
+    packets
+}
+
+// Sync the VPN-IPv4 Adj-RIB-Out of the peer with the changed routes of the
+// VRFs. The changes are sent with the IPv4 unicast ones.
+pub fn peer_vpn_out_update(peer: &mut Peer, vpn: &VpnTable, changed: &BTreeSet<VpnOutKey>) {
+    if peer.state != State::Established || !peer.afi_safi.has(&vpnv4()) {
+        return;
+    }
+    let export = ExportPeer::new(peer);
+    peer.adj_out.vpn.sync_changed(&export, vpn, changed);
+}
+
+impl Bgp {
+    // Routes of the VRFs exported by the RIB.
+    pub fn vpn_rib_update(&mut self, route: VpnRoute, add: bool) {
+        vpn_update(&mut self.vpn, &route, add);
+        if route.from.is_unspecified() {
+            self.vpn_changed.insert((route.rd, route.prefix));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{parse_bgp_packet, BgpPacket, RouteTarget, BGP_PACKET_LEN};
+
+    fn route(rd: &str, prefix: &str, from: &str) -> VpnRoute {
+        VpnRoute {
+            rd: rd.parse().unwrap(),
+            prefix: prefix.parse().unwrap(),
+            label: 16,
+            nexthop: Ipv4Addr::UNSPECIFIED,
+            route_targets: vec!["100:1".parse().unwrap()],
+            from: from.parse().unwrap(),
+            bandwidth: None,
+        }
+    }
+
+    fn peer(peer_as: u32) -> ExportPeer {
+        ExportPeer {
+            address: "192.0.2.2".parse().unwrap(),
+            local_as: 65000,
+            peer_as,
+            ibgp: peer_as == 65000,
+            local_addr: "192.0.2.1".parse().unwrap(),
+            role: None,
+            next_hop_self: None,
+            remove_private_as: None,
+            as_override: false,
+            as_path_prepend: Default::default(),
+            best_external: false,
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> UpdatePacket {
+        let (rest, packet) = parse_bgp_packet(bytes, true).unwrap();
+        assert!(rest.is_empty());
+        let BgpPacket::Update(packet) = packet else {
+            panic!("not an update");
+        };
+        packet
+    }
+
+    #[test]
+    fn clear_peer() {
+        let mut vpn = VpnTable::new();
+        let routes = [
+            route("65000:1", "10.1.0.0/16", "192.0.2.2"),
+            route("65000:2", "10.1.0.0/16", "192.0.2.2"),
+            route("65000:1", "10.1.0.0/16", "192.0.2.3"),
+        ];
+        for r in routes.iter() {
+            vpn_update(&mut vpn, r, true);
+        }
+        let msgs = vpn_clear(&mut vpn, "192.0.2.2".parse().unwrap());
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs
+            .iter()
+            .all(|m| matches!(m, RibTx::VpnRouteDel(r) if r.from == routes[0].from)));
+        assert_eq!(vpn.len(), 1);
+        assert!(vpn_clear(&mut vpn, "192.0.2.2".parse().unwrap()).is_empty());
+
+        // Withdrawn by the key regardless of the attributes.
+        let mut withdraw = routes[2].clone();
+        withdraw.route_targets.clear();
+        vpn_update(&mut vpn, &withdraw, false);
+        assert!(vpn.is_empty());
+    }
+
+    #[test]
+    fn advertise() {
+        let mut vpn = VpnTable::new();
+        vpn_update(&mut vpn, &route("65000:1", "10.1.0.0/16", "0.0.0.0"), true);
+        vpn_update(&mut vpn, &route("65000:1", "10.2.0.0/16", "0.0.0.0"), true);
+        // Received routes are not advertised again.
+        vpn_update(
+            &mut vpn,
+            &route("65000:9", "10.9.0.0/16", "192.0.2.3"),
+            true,
+        );
+
+        let ibgp = peer(65000);
+        let mut out = VpnRibOut::default();
+        assert_eq!(out.sync(&ibgp, &vpn), 2);
+        assert_eq!(out.sync(&ibgp, &vpn), 0);
+        let (withdraw, updates) = out.flush();
+        assert!(withdraw.is_empty());
+        assert_eq!(updates.len(), 2);
+        assert!(!out.is_pending());
+
+        // Both of them in one UPDATE.
+        let packets = vpn_update_pack(withdraw, updates, true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 1);
+        let bytes = packets[0].encode(true);
+        let parsed = parse(&bytes);
+        let mut rts = Vec::new();
+        let mut reach = None;
+        for attr in parsed.attrs.iter() {
+            match attr {
+                Attribute::ExtendedCom(v) => rts.extend(v.route_targets()),
+                Attribute::MpReachNlri(v) => reach = Some(v.clone()),
+                Attribute::Origin(v) => assert_eq!(v.origin, ORIGIN_INCOMPLETE),
+                _ => {}
+            }
+        }
+        assert_eq!(rts, vec!["100:1".parse::<RouteTarget>().unwrap()]);
+        let reach = reach.unwrap();
+        assert_eq!(reach.vpnv4_next_hop, Some(ibgp.local_addr));
+        assert_eq!(reach.vpnv4_prefix.len(), 2);
+        assert_eq!(reach.vpnv4_prefix[0].label, 16);
+        assert_eq!(reach.vpnv4_prefix[0].rd, "65000:1".parse().unwrap());
+
+        // Withdrawn when the RIB removes the export.
+        vpn_update(&mut vpn, &route("65000:1", "10.1.0.0/16", "0.0.0.0"), false);
+        assert_eq!(out.sync(&ibgp, &vpn), 1);
+        let (withdraw, updates) = out.flush();
+        assert!(updates.is_empty());
+        let packets = vpn_update_pack(withdraw, updates, true, BGP_PACKET_LEN);
+        assert_eq!(packets.len(), 1);
+        let bytes = packets[0].encode(true);
+        let parsed = parse(&bytes);
+        let unreach = parsed.attrs.iter().find_map(|attr| match attr {
+            Attribute::MpUnreachNlri(v) => Some(v.clone()),
+            _ => None,
+        });
+        let unreach = unreach.unwrap();
+        assert_eq!(unreach.vpnv4_prefix.len(), 1);
+        assert_eq!(
+            unreach.vpnv4_prefix[0].prefix,
+            "10.1.0.0/16".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn changed_keys() {
+        let mut vpn = VpnTable::new();
+        let r1 = route("65000:1", "10.1.0.0/16", "0.0.0.0");
+        let r2 = route("65000:1", "10.2.0.0/16", "0.0.0.0");
+        vpn_update(&mut vpn, &r1, true);
+        vpn_update(&mut vpn, &r2, true);
+
+        // The first export is the whole table.
+        let ibgp = peer(65000);
+        let mut out = VpnRibOut::default();
+        assert_eq!(out.sync_changed(&ibgp, &vpn, &BTreeSet::new()), 2);
+        out.flush();
+
+        // Only the changed keys are looked at.
+        let mut r3 = r2.clone();
+        r3.label = 17;
+        vpn_update(&mut vpn, &r3, true);
+        vpn_update(&mut vpn, &r1, false);
+        assert_eq!(out.sync_changed(&ibgp, &vpn, &BTreeSet::new()), 0);
+        let keys = BTreeSet::from([(r1.rd, r1.prefix), (r2.rd, r2.prefix)]);
+        assert_eq!(out.sync_changed(&ibgp, &vpn, &keys), 2);
+        assert_eq!(out.pending, BTreeSet::from([(r2.rd, r2.prefix)]));
+        let (withdraw, updates) = out.flush();
+        assert_eq!(withdraw.len(), 1);
+        assert_eq!(updates[0].0.label, 17);
+
+        // Changed export parameters export the whole table again.
+        let ebgp = peer(65001);
+        assert_eq!(out.sync_changed(&ebgp, &vpn, &BTreeSet::new()), 1);
+    }
+
+    #[test]
+    fn pack_max() {
+        let updates: Vec<VpnUpdate> = (0..600u32)
+            .map(|i| {
+                let r = route("65000:1", "10.0.0.0/24", "0.0.0.0");
+                let nlri = Vpnv4Nlri {
+                    label: 16,
+                    rd: r.rd,
+                    prefix: Ipv4Net::new(Ipv4Addr::from(0x0a000000 + (i << 8)), 24).unwrap(),
+                };
+                (
+                    nlri,
+                    "192.0.2.1".parse().unwrap(),
+                    vpn_attrs(&peer(65001), &r),
+                )
+            })
+            .collect();
+        let packets = vpn_update_pack(Vec::new(), updates, true, BGP_PACKET_LEN);
+        assert!(packets.len() > 1);
+        let mut count = 0;
+        for packet in packets.iter() {
+            let bytes = packet.encode(true);
+            assert!(bytes.len() <= BGP_PACKET_LEN);
+            let parsed = parse(&bytes);
+            for attr in parsed.attrs.iter() {
+                if let Attribute::MpReachNlri(v) = attr {
+                    count += v.vpnv4_prefix.len();
+                }
+            }
+        }
+        assert_eq!(count, 600);
+    }
+}
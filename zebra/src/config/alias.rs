@@ -0,0 +1,237 @@
+use super::{Args, ConfigOp};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+// Command aliases. "set system alias exec sib command \"show ip bgp\"" defines
+// sib in exec mode. The first word of the input is replaced with the command
+// of the alias of the mode and the rest of the input is appended, so that
+// "sib summary" runs "show ip bgp summary". The expansion is repeated while
+// the first word is an alias, up to ALIAS_DEPTH times. An alias which comes
+// back to itself is an error. Aliases are expanded before the command is
+// parsed, so that the name of a command of the mode is rejected at commit,
+// and changing them requires ALIAS_PRIVILEGE.
+
+pub const ALIAS_DEPTH: usize = 8;
+
+pub const ALIAS_PRIVILEGE: u32 = 15;
+
+#[derive(Debug, PartialEq)]
+pub enum AliasError {
+    Loop(String),
+    Depth(String),
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loop(name) => write!(f, "alias loop at {}", name),
+            Self::Depth(name) => write!(f, "alias {} nested too deep", name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Aliases {
+    // Command by mode and alias name.
+    map: BTreeMap<(String, String), String>,
+}
+
+impl Aliases {
+    pub fn set(&mut self, mode: &str, name: &str, command: &str) {
+        self.map
+            .insert((mode.to_string(), name.to_string()), command.to_string());
+    }
+
+    pub fn remove(&mut self, mode: &str, name: &str) -> bool {
+        self.map
+            .remove(&(mode.to_string(), name.to_string()))
+            .is_some()
+    }
+
+    fn get(&self, mode: &str, name: &str) -> Option<&String> {
+        self.map.get(&(mode.to_string(), name.to_string()))
+    }
+
+    pub fn names(&self, mode: &str) -> impl Iterator<Item = &String> {
+        self.map
+            .keys()
+            .filter(move |(m, _)| m == mode)
+            .map(|(_, name)| name)
+    }
+
+    pub fn expand(&self, mode: &str, input: &str) -> Result<String, AliasError> {
+        let mut line = input.trim().to_string();
+        let mut seen: Vec<String> = Vec::new();
+        loop {
+            let (first, rest) = match line.split_once(char::is_whitespace) {
+                Some((first, rest)) => (first, rest.trim_start()),
+                None => (line.as_str(), ""),
+            };
+            let Some(command) = self.get(mode, first) else {
+                return Ok(line);
+            };
+            if seen.iter().any(|name| name == first) {
+                return Err(AliasError::Loop(first.to_string()));
+            }
+            if seen.len() >= ALIAS_DEPTH {
+                return Err(AliasError::Depth(seen[0].clone()));
+            }
+            seen.push(first.to_string());
+            line = if rest.is_empty() {
+                command.clone()
+            } else {
+                format!("{} {}", command, rest)
+            };
+        }
+    }
+
+    pub fn show(&self) -> String {
+        let mut buf = String::new();
+        for ((mode, name), command) in self.map.iter() {
+            writeln!(buf, "alias {} {} \"{}\"", mode, name, command).unwrap();
+        }
+        buf
+    }
+}
+
+// Handle "alias", which lists the aliases. Returns None when the input is not
+// an alias command.
+pub fn alias_command(aliases: &Aliases, input: &str) -> Option<String> {
+    let mut words = input.split_whitespace();
+    if words.next() != Some("alias") {
+        return None;
+    }
+    if words.next().is_some() {
+        return Some(String::from(
+            "% Aliases are configured with \"set system alias\"\n",
+        ));
+    }
+    Some(aliases.show())
+}
+
+// Alias of the name would shadow the command of the mode.
+pub fn alias_validate(name: &str, commands: &[String]) -> Result<(), String> {
+    if name == "alias" || commands.iter().any(|command| command == name) {
+        return Err(format!("alias {} collides with the command", name));
+    }
+    Ok(())
+}
+
+pub fn alias_config(aliases: &mut Aliases, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let mode = args.string()?;
+    let name = args.string()?;
+    match (path, op) {
+        ("/system/alias/command", ConfigOp::Set) => {
+            aliases.set(&mode, &name, &args.string()?);
+        }
+        ("/system/alias/command" | "/system/alias", ConfigOp::Delete) => {
+            aliases.remove(&mode, &name);
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(words: &[&str]) -> Args {
+        Args(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn expand() {
+        let mut aliases = Aliases::default();
+        let path = "/system/alias/command";
+        let set = ConfigOp::Set;
+        assert_eq!(
+            alias_config(
+                &mut aliases,
+                path,
+                args(&["exec", "sib", "show ip bgp"]),
+                set.clone()
+            ),
+            Some(())
+        );
+        alias_config(
+            &mut aliases,
+            path,
+            args(&["exec", "sibs", "sib summary"]),
+            set.clone(),
+        );
+        alias_config(&mut aliases, path, args(&["configure", "c", "commit"]), set);
+
+        assert_eq!(aliases.expand("exec", "sib"), Ok("show ip bgp".into()));
+        assert_eq!(
+            aliases.expand("exec", " sib  neighbor 10.0.0.1 "),
+            Ok("show ip bgp neighbor 10.0.0.1".into())
+        );
+        assert_eq!(
+            aliases.expand("exec", "sibs"),
+            Ok("show ip bgp summary".into())
+        );
+        assert_eq!(aliases.expand("exec", "show sib"), Ok("show sib".into()));
+
+        // Scoped by mode.
+        assert_eq!(aliases.expand("configure", "sib"), Ok("sib".into()));
+        assert_eq!(aliases.expand("configure", "c"), Ok("commit".into()));
+        assert_eq!(aliases.expand("exec", "c"), Ok("c".into()));
+        assert_eq!(aliases.names("configure").collect::<Vec<_>>(), vec!["c"]);
+
+        assert_eq!(
+            alias_command(&aliases, "alias"),
+            Some(String::from(
+                "alias configure c \"commit\"\nalias exec sib \"show ip bgp\"\nalias exec sibs \"sib summary\"\n"
+            ))
+        );
+        assert!(alias_command(&aliases, "alias exec x y")
+            .unwrap()
+            .starts_with('%'));
+        assert_eq!(alias_command(&aliases, "show alias"), None);
+
+        // Removed with the list entry.
+        alias_config(
+            &mut aliases,
+            "/system/alias",
+            args(&["exec", "sib"]),
+            ConfigOp::Delete,
+        );
+        assert_eq!(aliases.expand("exec", "sibs"), Ok("sib summary".into()));
+    }
+
+    #[test]
+    fn collision() {
+        let commands = vec![String::from("show"), String::from("configure")];
+        assert!(alias_validate("sib", &commands).is_ok());
+        assert!(alias_validate("show", &commands).is_err());
+        assert!(alias_validate("alias", &commands).is_err());
+    }
+
+    #[test]
+    fn recursion() {
+        let mut aliases = Aliases::default();
+        aliases.set("exec", "a", "b x");
+        aliases.set("exec", "b", "a y");
+        assert_eq!(
+            aliases.expand("exec", "a"),
+            Err(AliasError::Loop("a".into()))
+        );
+        aliases.set("exec", "s", "s -v");
+        assert_eq!(
+            aliases.expand("exec", "s"),
+            Err(AliasError::Loop("s".into()))
+        );
+
+        // Chain of distinct aliases beyond the depth.
+        for i in 0..ALIAS_DEPTH {
+            aliases.set("exec", &format!("n{}", i), &format!("n{}", i + 1));
+        }
+        aliases.set("exec", &format!("n{}", ALIAS_DEPTH), "show version");
+        assert_eq!(
+            aliases.expand("exec", "n0"),
+            Err(AliasError::Depth("n0".into()))
+        );
+        assert_eq!(aliases.expand("exec", "n1"), Ok("show version".into()));
+    }
+}
@@ -1,5 +1,6 @@
+use super::state::StatePath;
 use super::vtysh::CommandPath;
-use super::{Completion, ExecCode};
+use super::{Args, Completion, ExecCode};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::Sender;
 
@@ -16,6 +17,12 @@ pub enum ConfigOp {
     Completion,
 }
 
+// Check of a set command before it is committed, for the errors which the
+// schema does not catch. It is called with the arguments of the command and
+// every set command of the candidate config as the path and the arguments,
+// and returns the error the protocol callback would fail with.
+pub type ConfigValidator = fn(Args, &[(String, Args)]) -> Result<(), String>;
+
 #[derive(Debug)]
 pub struct ConfigRequest {
     pub paths: Vec<CommandPath>,
@@ -43,7 +50,10 @@ impl ConfigChannel {
 #[derive(Debug)]
 pub struct ExecuteRequest {
     pub mode: String,
+    pub privilege: u32,
     pub input: String,
+    // Address of the client, recorded in the commit history.
+    pub client: String,
     pub resp: Sender<ExecuteResponse>,
 }
 
@@ -68,10 +78,18 @@ pub struct CompletionResponse {
 }
 
 impl ExecuteRequest {
-    pub fn new(mode: &str, input: &str, resp: Sender<ExecuteResponse>) -> Self {
+    pub fn new(
+        mode: &str,
+        privilege: u32,
+        input: &str,
+        client: &str,
+        resp: Sender<ExecuteResponse>,
+    ) -> Self {
         Self {
             mode: mode.to_string(),
+            privilege,
             input: input.to_string(),
+            client: client.to_string(),
             resp,
         }
     }
@@ -103,10 +121,18 @@ impl CompletionResponse {
     }
 }
 
+// Latest commands in the history, oldest first.
+#[derive(Debug)]
+pub struct HistoryRequest {
+    pub last: usize,
+    pub resp: Sender<Vec<String>>,
+}
+
 #[derive(Debug)]
 pub enum Message {
     Execute(ExecuteRequest),
     Completion(CompletionRequest),
+    History(HistoryRequest),
 }
 
 #[derive(Debug)]
@@ -127,3 +153,22 @@ pub struct DisplayRequest {
     pub paths: Vec<CommandPath>,
     pub resp: mpsc::Sender<String>,
 }
+
+#[derive(Debug)]
+pub struct StateChannel {
+    pub tx: UnboundedSender<StateRequest>,
+    pub rx: UnboundedReceiver<StateRequest>,
+}
+
+impl StateChannel {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self { tx, rx }
+    }
+}
+
+#[derive(Debug)]
+pub struct StateRequest {
+    pub path: StatePath,
+    pub resp: Sender<Option<serde_json::Value>>,
+}
@@ -1,12 +1,16 @@
+use super::commits::{commit_compare, commit_list_show, commit_show};
+use super::history::history_show;
 use super::manager::ConfigManager;
 use super::util::trim_first_line;
-use super::ExecCode;
+use super::{Args, ExecCode};
 use libyang::Entry;
 use similar::TextDiff;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-type FuncMap = HashMap<String, fn(&ConfigManager) -> (ExecCode, String)>;
+type ExecFunc = fn(&ConfigManager, Args) -> (ExecCode, String);
+
+type FuncMap = HashMap<String, ExecFunc>;
 
 #[derive(Debug)]
 pub struct Mode {
@@ -22,7 +26,7 @@ impl Mode {
         }
     }
 
-    pub fn install_func(&mut self, path: String, f: fn(&ConfigManager) -> (ExecCode, String)) {
+    pub fn install_func(&mut self, path: String, f: ExecFunc) {
         self.fmap.insert(path, f);
     }
 }
@@ -31,8 +35,9 @@ pub fn exec_mode_create(entry: Rc<Entry>) -> Mode {
     let mut mode = Mode::new(entry);
     mode.install_func(String::from("/help"), help);
     mode.install_func(String::from("/show/version"), show_version);
-    mode.install_func(String::from("/show/ip/route"), show_ip_route_prefix);
     mode.install_func(String::from("/configure"), configure);
+    history_install(&mut mode);
+    commit_install(&mut mode);
     mode
 }
 
@@ -50,37 +55,49 @@ pub fn configure_mode_create(entry: Rc<Entry>) -> Mode {
     mode.install_func(String::from("/list"), list);
     mode.install_func(String::from("/load"), load);
     mode.install_func(String::from("/save"), save);
+    history_install(&mut mode);
+    commit_install(&mut mode);
     mode
 }
 
-fn help(_config: &ConfigManager) -> (ExecCode, String) {
+fn history_install(mode: &mut Mode) {
+    mode.install_func(String::from("/show/history"), show_history);
+    mode.install_func(String::from("/show/history/last"), show_history);
+    mode.install_func(String::from("/history/clear"), history_clear);
+}
+
+fn commit_install(mode: &mut Mode) {
+    mode.install_func(String::from("/show/system/commit"), show_system_commit);
+    mode.install_func(
+        String::from("/show/system/commit/compare"),
+        show_system_commit_compare,
+    );
+}
+
+fn help(_config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let output = r#"This is help for openconfigd's `cli' command help.
 cli is based on bash so you can use any shell command in it.
 "#;
     (ExecCode::Show, output.to_string())
 }
 
-fn show_version(_config: &ConfigManager) -> (ExecCode, String) {
+fn show_version(_config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     (ExecCode::Show, String::from("version 0.1"))
 }
 
-fn show_ip_route_prefix(_config: &ConfigManager) -> (ExecCode, String) {
-    (ExecCode::Show, String::from("show ip route prefix"))
-}
-
-fn configure(_config: &ConfigManager) -> (ExecCode, String) {
+fn configure(_config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let cli_command = r#"SuccessExec
 CLI_MODE=configure;CLI_MODE_STR=Configure;CLI_PRIVILEGE=15;_cli_refresh"#;
     (ExecCode::Success, cli_command.to_string())
 }
 
-fn exit(_config: &ConfigManager) -> (ExecCode, String) {
+fn exit(_config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let cli_command = r#"SuccessExec
 CLI_MODE=exec;CLI_PRIVILEGE=1;_cli_refresh"#;
     (ExecCode::Success, cli_command.to_string())
 }
 
-fn show(config: &ConfigManager) -> (ExecCode, String) {
+fn show(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut running = String::new();
     let mut candidate = String::new();
     config.store.running.borrow().format(&mut running);
@@ -97,52 +114,88 @@ fn show(config: &ConfigManager) -> (ExecCode, String) {
     }
 }
 
-fn candidate(config: &ConfigManager) -> (ExecCode, String) {
+fn candidate(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut output = String::new();
     config.store.candidate.borrow().format(&mut output);
     (ExecCode::Show, output)
 }
 
-fn running(config: &ConfigManager) -> (ExecCode, String) {
+fn running(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut output = String::new();
     config.store.running.borrow().format(&mut output);
     (ExecCode::Show, output)
 }
 
-fn json(config: &ConfigManager) -> (ExecCode, String) {
+fn json(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut output = String::new();
     config.store.candidate.borrow().json(&mut output);
     (ExecCode::Show, output)
 }
 
-fn yaml(config: &ConfigManager) -> (ExecCode, String) {
+fn yaml(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut output = String::new();
     config.store.candidate.borrow().yaml(&mut output);
     (ExecCode::Show, output)
 }
 
-fn commit(config: &ConfigManager) -> (ExecCode, String) {
-    config.commit_config();
-    (ExecCode::Show, String::from(""))
+// Nothing is committed when any of the changes fails.
+fn commit(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
+    let Err(errors) = config.commit_config() else {
+        return (ExecCode::Show, String::from(""));
+    };
+    let mut output: String = errors.iter().map(|line| format!("% {}\n", line)).collect();
+    output.push_str("% Commit failed, the candidate config is kept\n");
+    (ExecCode::Show, output)
 }
 
-fn discard(config: &ConfigManager) -> (ExecCode, String) {
+fn discard(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     config.store.discard();
     (ExecCode::Show, String::from(""))
 }
 
-fn load(config: &ConfigManager) -> (ExecCode, String) {
-    config.load_config();
-    (ExecCode::Show, String::from(""))
+fn load(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
+    let output: String = config
+        .load_config()
+        .iter()
+        .map(|line| format!("% {}\n", line))
+        .collect();
+    (ExecCode::Show, output)
 }
 
-fn save(config: &ConfigManager) -> (ExecCode, String) {
+fn save(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     config.save_config();
     (ExecCode::Show, String::from(""))
 }
 
-fn list(config: &ConfigManager) -> (ExecCode, String) {
+fn show_history(config: &ConfigManager, mut args: Args) -> (ExecCode, String) {
+    let last = args.u32().map(|n| n as usize);
+    (ExecCode::Show, history_show(&config.history.borrow(), last))
+}
+
+fn history_clear(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
+    config.history.borrow_mut().clear();
+    (ExecCode::Show, String::from(""))
+}
+
+fn list(config: &ConfigManager, _args: Args) -> (ExecCode, String) {
     let mut output = String::new();
     config.store.candidate.borrow().list(&mut output);
     (ExecCode::Show, output)
 }
+
+fn show_system_commit(config: &ConfigManager, mut args: Args) -> (ExecCode, String) {
+    let commits = config.commits.borrow();
+    let output = match args.u32() {
+        Some(id) => commit_show(&commits, id.into()),
+        None => commit_list_show(&commits),
+    };
+    (ExecCode::Show, output)
+}
+
+fn show_system_commit_compare(config: &ConfigManager, mut args: Args) -> (ExecCode, String) {
+    let (Some(from), Some(to)) = (args.u32(), args.u32()) else {
+        return (ExecCode::Show, String::from("% Invalid commit id\n"));
+    };
+    let output = commit_compare(&config.commits.borrow(), from.into(), to.into());
+    (ExecCode::Show, output)
+}
@@ -0,0 +1,362 @@
+use super::history::time_str;
+use super::util::trim_first_line;
+use similar::TextDiff;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Commit history. Each commit which changes the running config is recorded
+// with the commit id, the time, the client which has sent the commit and the
+// changed config lines. The running config after the commit is saved as the
+// snapshot of the commit, so that the changes of a commit and the difference
+// between any two commits are shown from the snapshots. The index of the
+// history and the snapshots are stored in the commit directory next to the
+// config, and the oldest commit is removed with its snapshot when the history
+// is full.
+
+pub const COMMIT_HISTORY_MAX: usize = 50;
+
+const COMMIT_INDEX: &str = "index";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitEntry {
+    pub id: u64,
+    pub time: u64,
+    pub client: String,
+    // Changed lines of the config with '+' or '-'.
+    pub changes: Vec<String>,
+}
+
+impl CommitEntry {
+    // One commit per line: id, time, client and the changes separated by tab.
+    fn parse(s: &str) -> Option<Self> {
+        let mut fields = s.split('\t');
+        let id = fields.next()?.parse().ok()?;
+        let time = fields.next()?.parse().ok()?;
+        let client = fields.next()?.to_string();
+        let changes = fields.map(|s| s.to_string()).collect();
+        Some(Self {
+            id,
+            time,
+            client,
+            changes,
+        })
+    }
+
+    fn format(&self) -> String {
+        let mut line = format!("{}\t{}\t{}", self.id, self.time, self.client);
+        for change in self.changes.iter() {
+            line.push('\t');
+            line.push_str(change);
+        }
+        line
+    }
+}
+
+#[derive(Debug)]
+pub struct CommitHistory {
+    pub dir: Option<PathBuf>,
+    pub max: usize,
+    pub next_id: u64,
+    pub entries: VecDeque<CommitEntry>,
+}
+
+impl CommitHistory {
+    pub fn new(dir: Option<PathBuf>, max: usize) -> Self {
+        Self {
+            dir,
+            max,
+            next_id: 1,
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        let mut path = self.dir.clone()?;
+        path.push(name);
+        Some(path)
+    }
+
+    fn snapshot_path(&self, id: u64) -> Option<PathBuf> {
+        self.file(&format!("{}.conf", id))
+    }
+
+    pub fn load(&mut self) {
+        let Some(path) = self.file(COMMIT_INDEX) else {
+            return;
+        };
+        let Ok(output) = std::fs::read_to_string(path) else {
+            return;
+        };
+        self.entries = output.lines().filter_map(CommitEntry::parse).collect();
+        self.next_id = self.entries.back().map(|e| e.id + 1).unwrap_or(1);
+        if self.trim() {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let (Some(dir), Some(path)) = (self.dir.as_ref(), self.file(COMMIT_INDEX)) else {
+            return;
+        };
+        let mut output = String::new();
+        for entry in self.entries.iter() {
+            output.push_str(&entry.format());
+            output.push('\n');
+        }
+        let result = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, output));
+        if let Err(err) = result {
+            println!("{}: {}", path.display(), err);
+        }
+    }
+
+    // Remove the oldest commits and their snapshots over the limit.
+    fn trim(&mut self) -> bool {
+        let mut trimmed = false;
+        while self.entries.len() > self.max {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            if let Some(path) = self.snapshot_path(entry.id) {
+                let _ = std::fs::remove_file(path);
+            }
+            trimmed = true;
+        }
+        trimmed
+    }
+
+    pub fn set_max(&mut self, max: usize) {
+        self.max = max;
+        if self.trim() {
+            self.save();
+        }
+    }
+
+    // Record the commit with the running config after it, and return the
+    // commit id.
+    pub fn add(&mut self, client: &str, changes: Vec<String>, snapshot: &str) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let (Some(dir), Some(path)) = (self.dir.as_ref(), self.snapshot_path(id)) {
+            let result = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, snapshot));
+            if let Err(err) = result {
+                println!("{}: {}", path.display(), err);
+            }
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.entries.push_back(CommitEntry {
+            id,
+            time,
+            client: client.replace(['\n', '\t'], " "),
+            changes: changes
+                .into_iter()
+                .map(|line| line.replace('\t', " "))
+                .collect(),
+        });
+        self.trim();
+        self.save();
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&CommitEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn snapshot(&self, id: u64) -> Option<String> {
+        self.get(id)?;
+        std::fs::read_to_string(self.snapshot_path(id)?).ok()
+    }
+}
+
+// Unified diff of the two configs without the hunk header.
+pub fn config_diff(old: &str, new: &str) -> String {
+    let text_diff = TextDiff::from_lines(old, new);
+    let mut binding = text_diff.unified_diff();
+    let mut diff = binding.context_radius(65535).to_string();
+    trim_first_line(&mut diff)
+}
+
+pub fn commit_list_show(commits: &CommitHistory) -> String {
+    let mut buf = String::new();
+    writeln!(
+        buf,
+        "{:>5}  {:19}  {:24} {}",
+        "ID", "Time", "Client", "Changes"
+    )
+    .unwrap();
+    for entry in commits.entries.iter().rev() {
+        writeln!(
+            buf,
+            "{:>5}  {:19}  {:24} {}",
+            entry.id,
+            time_str(entry.time),
+            entry.client,
+            entry.changes.len()
+        )
+        .unwrap();
+    }
+    buf
+}
+
+// Changes of the commit, from the snapshot of the previous commit. When the
+// previous commit has been removed from the history the recorded changes are
+// shown.
+pub fn commit_show(commits: &CommitHistory, id: u64) -> String {
+    let Some(entry) = commits.get(id) else {
+        return format!("% No such commit {}\n", id);
+    };
+    let mut buf = format!(
+        "Commit {} at {} by {}\n",
+        entry.id,
+        time_str(entry.time),
+        entry.client
+    );
+    let Some(current) = commits.snapshot(id) else {
+        return buf + "% Snapshot is not available\n";
+    };
+    let prev = match commits.entries.iter().position(|e| e.id == id) {
+        Some(0) if id > 1 => None,
+        Some(0) => Some(String::new()),
+        Some(index) => commits.snapshot(commits.entries[index - 1].id),
+        None => None,
+    };
+    match prev {
+        Some(prev) => buf.push_str(&config_diff(&prev, &current)),
+        None => {
+            for change in entry.changes.iter() {
+                buf.push_str(change);
+                buf.push('\n');
+            }
+        }
+    }
+    buf
+}
+
+pub fn commit_compare(commits: &CommitHistory, from: u64, to: u64) -> String {
+    let Some(old) = commits.snapshot(from) else {
+        return format!("% No such commit {}\n", from);
+    };
+    let Some(new) = commits.snapshot(to) else {
+        return format!("% No such commit {}\n", to);
+    };
+    config_diff(&old, &new)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("zebra-commit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    const CONFIG1: &str =
+        "routing {\n    bgp {\n        global {\n            as 100;\n        }\n    }\n}\n";
+    const CONFIG2: &str =
+        "routing {\n    bgp {\n        global {\n            as 200;\n        }\n    }\n}\n";
+    const CONFIG3: &str = "routing {\n    bgp {\n        global {\n            as 200;\n            identifier 10.0.0.1;\n        }\n    }\n}\n";
+
+    fn commits(dir: &PathBuf, max: usize) -> CommitHistory {
+        let mut commits = CommitHistory::new(Some(dir.clone()), max);
+        commits.add(
+            "127.0.0.1:50001",
+            vec!["+routing bgp global as 100".to_string()],
+            CONFIG1,
+        );
+        commits.add(
+            "127.0.0.1:50002",
+            vec![
+                "-routing bgp global as 100".to_string(),
+                "+routing bgp global as 200".to_string(),
+            ],
+            CONFIG2,
+        );
+        commits.add(
+            "127.0.0.1:50003",
+            vec!["+routing bgp global identifier 10.0.0.1".to_string()],
+            CONFIG3,
+        );
+        commits
+    }
+
+    #[test]
+    fn sequence() {
+        let dir = temp_dir("sequence");
+        let commits = commits(&dir, COMMIT_HISTORY_MAX);
+        let ids: Vec<u64> = commits.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(commits.get(2).unwrap().client, "127.0.0.1:50002");
+        assert_eq!(commits.snapshot(2).unwrap(), CONFIG2);
+
+        // Ids continue after restart.
+        let mut loaded = CommitHistory::new(Some(dir.clone()), COMMIT_HISTORY_MAX);
+        loaded.load();
+        assert_eq!(loaded.entries, commits.entries);
+        assert_eq!(loaded.add("local", Vec::new(), CONFIG3), 4);
+
+        let list = commit_list_show(&loaded);
+        let ids: Vec<&str> = list
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        assert_eq!(ids, vec!["4", "3", "2", "1"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diff() {
+        let dir = temp_dir("diff");
+        let commits = commits(&dir, COMMIT_HISTORY_MAX);
+
+        let output = commit_show(&commits, 2);
+        assert!(output.starts_with("Commit 2 at "));
+        assert!(output.contains("-            as 100;\n"));
+        assert!(output.contains("+            as 200;\n"));
+        assert!(!output.contains("+            identifier"));
+
+        // First commit is the difference from the empty config.
+        let output = commit_show(&commits, 1);
+        assert!(output.contains("+            as 100;\n"));
+
+        let output = commit_compare(&commits, 1, 3);
+        assert_eq!(output, config_diff(CONFIG1, CONFIG3));
+        assert!(output.contains("-            as 100;\n"));
+        assert!(output.contains("+            identifier 10.0.0.1;\n"));
+        assert!(commit_compare(&commits, 3, 3).is_empty());
+        assert_eq!(commit_compare(&commits, 1, 9), "% No such commit 9\n");
+        assert_eq!(commit_show(&commits, 9), "% No such commit 9\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn eviction() {
+        let dir = temp_dir("eviction");
+        let mut commits = commits(&dir, 2);
+        let ids: Vec<u64> = commits.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(!dir.join("1.conf").exists());
+        assert!(dir.join("2.conf").exists());
+        assert!(commits.snapshot(1).is_none());
+
+        // Previous snapshot is gone, the recorded changes are shown.
+        let output = commit_show(&commits, 2);
+        assert!(output.ends_with("-routing bgp global as 100\n+routing bgp global as 200\n"));
+
+        commits.set_max(1);
+        assert!(!dir.join("2.conf").exists());
+        assert!(dir.join("3.conf").exists());
+        let mut loaded = CommitHistory::new(Some(dir.clone()), COMMIT_HISTORY_MAX);
+        loaded.load();
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.next_id, 4);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
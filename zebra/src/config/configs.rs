@@ -60,6 +60,12 @@ impl Args {
         Some(arg)
     }
 
+    pub fn u8(&mut self) -> Option<u8> {
+        let item = self.0.pop_front()?;
+        let arg: u8 = item.parse().ok()?;
+        Some(arg)
+    }
+
     pub fn u16(&mut self) -> Option<u16> {
         let item = self.0.pop_front()?;
         let arg: u16 = item.parse().ok()?;
@@ -73,6 +79,7 @@ impl Args {
             "ipv4-labeled-unicast" => Some(AfiSafi::new(Afi::IP, Safi::MplsLabel)),
             "ipv6-unicast" => Some(AfiSafi::new(Afi::IP6, Safi::Unicast)),
             "ipv6-labeled-unicast" => Some(AfiSafi::new(Afi::IP6, Safi::MplsLabel)),
+            "l3vpn-ipv4-unicast" => Some(AfiSafi::new(Afi::IP, Safi::MplsVpn)),
             _ => None,
         }
     }
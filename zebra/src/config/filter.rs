@@ -0,0 +1,209 @@
+use regex::Regex;
+use std::fmt;
+
+// Output modifiers of show commands. "show ip route | include ^B" shows the
+// lines matching the regular expression, "exclude" the lines not matching
+// it, and "begin" the lines from the first matching one. The output is
+// streamed from the daemons in chunks which may end in the middle of a
+// line, so the last incomplete line of a chunk is held until the rest of it
+// arrives.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterType {
+    Include,
+    Exclude,
+    Begin,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    Modifier(String),
+    Pattern(String),
+    Regex(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Modifier(s) => write!(f, "unknown output modifier {}", s),
+            Self::Pattern(s) => write!(f, "{} needs a regular expression", s),
+            Self::Regex(s) => write!(f, "invalid regular expression {}", s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OutputFilter {
+    typ: FilterType,
+    regex: Regex,
+    begun: bool,
+    partial: String,
+}
+
+impl OutputFilter {
+    pub fn new(typ: FilterType, pattern: &str) -> Result<Self, FilterError> {
+        let regex = Regex::new(pattern).map_err(|_| FilterError::Regex(pattern.to_string()))?;
+        Ok(Self {
+            typ,
+            regex,
+            begun: false,
+            partial: String::new(),
+        })
+    }
+
+    fn pass(&mut self, line: &str) -> bool {
+        match self.typ {
+            FilterType::Include => self.regex.is_match(line),
+            FilterType::Exclude => !self.regex.is_match(line),
+            FilterType::Begin => {
+                self.begun = self.begun || self.regex.is_match(line);
+                self.begun
+            }
+        }
+    }
+
+    // Filtered complete lines of the chunk.
+    pub fn apply(&mut self, chunk: &str) -> String {
+        self.partial.push_str(chunk);
+        let Some(end) = self.partial.rfind('\n') else {
+            return String::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.partial, rest);
+        let mut output = String::new();
+        for line in lines.split_inclusive('\n') {
+            if self.pass(line.trim_end_matches('\n')) {
+                output.push_str(line);
+            }
+        }
+        output
+    }
+
+    // The last line without the newline at the end of the output.
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.partial);
+        if !line.is_empty() && self.pass(&line) {
+            line
+        } else {
+            String::new()
+        }
+    }
+}
+
+// Split the show command line at the output modifier. The modifier follows
+// " | ", so that "|" in the arguments such as "_(65001|65002)_" is kept in
+// the command. The other commands are not split.
+pub fn filter_split(line: &str) -> Result<(&str, Option<OutputFilter>), FilterError> {
+    let show = line.split_whitespace().next() == Some("show");
+    let Some((command, modifier)) = line.split_once(" | ").filter(|_| show) else {
+        return Ok((line, None));
+    };
+    let modifier = modifier.trim();
+    let (name, pattern) = modifier.split_once(' ').unwrap_or((modifier, ""));
+    let typ = match name {
+        "include" => FilterType::Include,
+        "exclude" => FilterType::Exclude,
+        "begin" => FilterType::Begin,
+        _ => return Err(FilterError::Modifier(name.to_string())),
+    };
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(FilterError::Pattern(name.to_string()));
+    }
+    Ok((command.trim_end(), Some(OutputFilter::new(typ, pattern)?)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUTPUT: &str = "Codes: K - kernel, C - connected, S - static, B - BGP\n\
+                          \n\
+                          C    *> 10.0.0.0/24 is directly connected, eth0\n\
+                          S    *> 10.1.0.0/16 [1/0] via 10.0.0.2, eth0\n\
+                          B    *> 10.2.0.0/16 [20/0] via 10.0.0.3, eth0\n\
+                          B       10.3.0.0/16 [200/0] via 10.0.0.4, eth0\n";
+
+    // Output streamed in chunks which break the lines.
+    fn filtered(line: &str) -> String {
+        let (_, filter) = filter_split(line).unwrap();
+        let mut filter = filter.unwrap();
+        let mut output = String::new();
+        let bytes = OUTPUT.as_bytes();
+        for chunk in bytes.chunks(7) {
+            output.push_str(&filter.apply(std::str::from_utf8(chunk).unwrap()));
+        }
+        output.push_str(&filter.finish());
+        output
+    }
+
+    #[test]
+    fn include() {
+        assert_eq!(
+            filtered("show ip route | include ^B"),
+            "B    *> 10.2.0.0/16 [20/0] via 10.0.0.3, eth0\n\
+             B       10.3.0.0/16 [200/0] via 10.0.0.4, eth0\n"
+        );
+        assert_eq!(filtered("show ip route | include nomatch"), "");
+    }
+
+    #[test]
+    fn exclude() {
+        let output = filtered("show ip route | exclude \\[");
+        assert_eq!(
+            output,
+            "Codes: K - kernel, C - connected, S - static, B - BGP\n\
+             \n\
+             C    *> 10.0.0.0/24 is directly connected, eth0\n"
+        );
+    }
+
+    #[test]
+    fn begin() {
+        let output = filtered("show ip route | begin ^S");
+        assert!(output.starts_with("S    *> 10.1.0.0/16"));
+        // Lines after the first match are all shown.
+        assert_eq!(output.lines().count(), 3);
+
+        // Last line without the newline.
+        let (_, filter) = filter_split("show | begin b").unwrap();
+        let mut filter = filter.unwrap();
+        assert_eq!(filter.apply("a\nb"), "");
+        assert_eq!(filter.finish(), "b");
+    }
+
+    #[test]
+    fn split() {
+        let (command, filter) = filter_split("show ip route").unwrap();
+        assert_eq!(command, "show ip route");
+        assert!(filter.is_none());
+        let (command, filter) = filter_split("show ip route | include 10.0 via").unwrap();
+        assert_eq!(command, "show ip route");
+        assert!(filter.unwrap().regex.is_match("10.0 via"));
+        assert_eq!(
+            filter_split("show ip route | grep x").unwrap_err(),
+            FilterError::Modifier("grep".to_string())
+        );
+        assert_eq!(
+            filter_split("show ip route | include").unwrap_err(),
+            FilterError::Pattern("include".to_string())
+        );
+        assert_eq!(
+            filter_split("show ip route | include (").unwrap_err(),
+            FilterError::Regex("(".to_string())
+        );
+
+        // "|" in the arguments.
+        let line = "show ip bgp regexp _(65001|65002)_";
+        let (command, filter) = filter_split(line).unwrap();
+        assert_eq!(command, line);
+        assert!(filter.is_none());
+        let (command, filter) = filter_split("show ip bgp regexp _(1|2)_ | include a|b").unwrap();
+        assert_eq!(command, "show ip bgp regexp _(1|2)_");
+        assert!(filter.unwrap().regex.is_match("b"));
+
+        // Not a show command.
+        let line = "as-path-set p member _(65001 | 65002)_";
+        assert_eq!(filter_split(line).unwrap().0, line);
+    }
+}
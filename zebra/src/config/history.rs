@@ -0,0 +1,308 @@
+use super::configs::ymatch_enum;
+use super::vtysh::{CommandPath, YangMatch};
+use super::ExecCode;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Command history of the exec requests. Each command is recorded with the
+// time, the privilege of the session and the result code. The history is
+// written to the history file next to the config so that it survives restart.
+// Values of the password and key leaves are replaced before they are stored.
+
+pub const HISTORY_MAX: usize = 1000;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub time: u64,
+    pub privilege: u32,
+    pub code: ExecCode,
+    pub line: String,
+}
+
+impl HistoryEntry {
+    // One entry per line: time, privilege, code and the command separated by
+    // tab.
+    fn parse(s: &str) -> Option<Self> {
+        let mut fields = s.splitn(4, '\t');
+        let time = fields.next()?.parse().ok()?;
+        let privilege = fields.next()?.parse().ok()?;
+        let code = ExecCode::from_i32(fields.next()?.parse().ok()?)?;
+        let line = fields.next()?.to_string();
+        Some(Self {
+            time,
+            privilege,
+            code,
+            line,
+        })
+    }
+
+    fn format(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.time, self.privilege, self.code as i32, self.line
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    pub path: Option<PathBuf>,
+    pub max: usize,
+    pub entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>, max: usize) -> Self {
+        Self {
+            path,
+            max,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn load(&mut self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let Ok(output) = std::fs::read_to_string(path) else {
+            return;
+        };
+        self.entries = output.lines().filter_map(HistoryEntry::parse).collect();
+        self.trim();
+    }
+
+    fn save(&self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let mut output = String::new();
+        for entry in self.entries.iter() {
+            output.push_str(&entry.format());
+            output.push('\n');
+        }
+        if let Err(err) = std::fs::write(path, output) {
+            println!("{}: {}", path.display(), err);
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.max {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn add(&mut self, privilege: u32, code: ExecCode, line: String) {
+        let line = line.replace(['\n', '\t'], " ");
+        if line.trim().is_empty() {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.entries.push_back(HistoryEntry {
+            time,
+            privilege,
+            code,
+            line,
+        });
+        self.trim();
+        self.save();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.save();
+    }
+
+    // Index in the history and the entry of the latest n commands.
+    pub fn last(&self, n: usize) -> impl Iterator<Item = (usize, &HistoryEntry)> {
+        let skip = self.entries.len().saturating_sub(n);
+        self.entries.iter().enumerate().skip(skip)
+    }
+}
+
+fn is_secret(name: &str) -> bool {
+    name == "password"
+        || name == "key"
+        || name == "secret"
+        || name.ends_with("-password")
+        || name.ends_with("-key")
+}
+
+// Command line to be stored. When the input has been parsed the command is
+// rebuilt from the paths, so that abbreviated leaf names are redacted as well.
+// Otherwise the words following the secret leaf names are redacted.
+pub fn history_redact(input: &str, paths: &[CommandPath]) -> String {
+    let is_secret_leaf =
+        |p: &CommandPath| ymatch_enum(p.ymatch) == YangMatch::Leaf && is_secret(&p.name);
+    if paths.iter().any(is_secret_leaf) {
+        let mut secret = false;
+        let words: Vec<&str> = paths
+            .iter()
+            .map(|p| {
+                let word = if secret && ymatch_enum(p.ymatch) == YangMatch::LeafMatched {
+                    REDACTED
+                } else {
+                    p.name.as_str()
+                };
+                secret = is_secret_leaf(p);
+                word
+            })
+            .collect();
+        return words.join(" ");
+    }
+    let mut secret = false;
+    let words: Vec<&str> = input
+        .split_whitespace()
+        .map(|w| {
+            let word = if secret { REDACTED } else { w };
+            secret = is_secret(w);
+            word
+        })
+        .collect();
+    words.join(" ")
+}
+
+// UTC date and time of the seconds since the epoch.
+pub fn time_str(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let secs = time % 86400;
+    // Days to civil date, from Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+pub fn history_show(history: &History, last: Option<usize>) -> String {
+    let mut buf = String::new();
+    let n = last.unwrap_or(history.entries.len());
+    for (index, entry) in history.last(n) {
+        writeln!(
+            buf,
+            "{:>5}  {}  {:>2}  {:<13} {}",
+            index + 1,
+            time_str(entry.time),
+            entry.privilege,
+            entry.code.as_str_name().to_lowercase(),
+            entry.line
+        )
+        .unwrap();
+    }
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(name: &str, ymatch: YangMatch) -> CommandPath {
+        CommandPath {
+            name: name.to_string(),
+            key: String::new(),
+            ymatch: ymatch as i32,
+        }
+    }
+
+    #[test]
+    fn persistence() {
+        let mut file = std::env::temp_dir();
+        file.push(format!("zebra-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+
+        let mut history = History::new(Some(file.clone()), HISTORY_MAX);
+        history.add(1, ExecCode::Show, String::from("show ip bgp"));
+        history.add(15, ExecCode::Nomatch, String::from("set foo\tbar"));
+        history.add(15, ExecCode::Show, String::from("  "));
+
+        let mut loaded = History::new(Some(file.clone()), HISTORY_MAX);
+        loaded.load();
+        assert_eq!(loaded.entries, history.entries);
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.entries[1].line, "set foo bar");
+        assert_eq!(loaded.entries[1].privilege, 15);
+        assert_eq!(loaded.entries[1].code, ExecCode::Nomatch);
+
+        loaded.clear();
+        let mut cleared = History::new(Some(file.clone()), HISTORY_MAX);
+        cleared.load();
+        assert!(cleared.entries.is_empty());
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn redaction() {
+        let paths = vec![
+            path("set", YangMatch::Dir),
+            path("routing", YangMatch::Dir),
+            path("bgp", YangMatch::Dir),
+            path("neighbors", YangMatch::Dir),
+            path("neighbor", YangMatch::Key),
+            path("10.0.0.1", YangMatch::KeyMatched),
+            path("password", YangMatch::Leaf),
+            path("s3cret", YangMatch::LeafMatched),
+        ];
+        let line = history_redact("set r b n n 10.0.0.1 pass s3cret", &paths);
+        assert_eq!(
+            line,
+            "set routing bgp neighbors neighbor 10.0.0.1 password <redacted>"
+        );
+
+        // Key chain name is not a secret.
+        let paths = vec![
+            path("key-chain", YangMatch::Leaf),
+            path("chain1", YangMatch::LeafMatched),
+        ];
+        assert_eq!(
+            history_redact("key-chain chain1", &paths),
+            "key-chain chain1"
+        );
+
+        // Unparsed input.
+        assert_eq!(
+            history_redact("set ospfv2-key abc auth-password xyz as 1", &[]),
+            "set ospfv2-key <redacted> auth-password <redacted> as 1"
+        );
+    }
+
+    #[test]
+    fn bounded() {
+        let mut history = History::new(None, 3);
+        for i in 0..10 {
+            history.add(1, ExecCode::Show, format!("show {}", i));
+        }
+        assert_eq!(history.entries.len(), 3);
+        assert_eq!(history.entries[0].line, "show 7");
+
+        let last: Vec<usize> = history.last(2).map(|(i, _)| i).collect();
+        assert_eq!(last, vec![1, 2]);
+        assert_eq!(history.last(10).count(), 3);
+        assert_eq!(history_show(&history, Some(1)).lines().count(), 1);
+    }
+
+    #[test]
+    fn time_format() {
+        assert_eq!(time_str(0), "1970-01-01 00:00:00");
+        assert_eq!(time_str(951825600), "2000-02-29 12:00:00");
+        assert_eq!(time_str(1792238400), "2026-10-17 12:00:00");
+    }
+}
@@ -1,19 +1,23 @@
-use super::api::{CompletionResponse, ConfigOp, ExecuteResponse, Message};
+use super::alias::{alias_validate, ALIAS_PRIVILEGE};
+use super::api::{CompletionResponse, ConfigOp, ConfigValidator, ExecuteResponse, Message};
 use super::commands::Mode;
 use super::commands::{configure_mode_create, exec_mode_create};
+use super::commits::{CommitHistory, COMMIT_HISTORY_MAX};
 use super::configs::{carbon_copy, delete, set};
 use super::files::load_config_file;
+use super::history::{history_redact, History, HISTORY_MAX};
 use super::parse::parse;
 use super::parse::State;
-use super::paths::{path_trim, paths_str};
+use super::paths::{path_from_command, path_trim};
 use super::util::trim_first_line;
 use super::vtysh::CommandPath;
-use super::{Completion, Config, ConfigRequest, ExecCode};
+use super::yang::{entry_conflicts, entry_merge, yang_user_read};
+use super::{Args, Completion, Config, ConfigRequest, ExecCode};
 use libyang::{to_entry, Entry, YangStore};
 use similar::TextDiff;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedSender};
 use tokio::sync::oneshot;
@@ -42,6 +46,9 @@ impl ConfigStore {
     }
 }
 
+// Client of the config loaded from the file.
+const CLIENT_LOCAL: &str = "local";
+
 pub struct ConfigManager {
     pub yang_path: String,
     pub config_path: PathBuf,
@@ -50,12 +57,21 @@ pub struct ConfigManager {
     pub tx: Sender<Message>,
     pub rx: Receiver<Message>,
     pub cm_clients: HashMap<String, UnboundedSender<ConfigRequest>>,
+    pub validators: HashMap<String, ConfigValidator>,
+    pub history: RefCell<History>,
+    pub commits: RefCell<CommitHistory>,
+    // Client of the request being executed.
+    pub client: RefCell<String>,
 }
 
 impl ConfigManager {
     pub fn new(mut system_path: PathBuf) -> anyhow::Result<Self> {
         let yang_path = system_path.to_string_lossy().to_string();
         system_path.pop();
+        let mut history_path = system_path.clone();
+        history_path.push("zebra.history");
+        let mut commit_path = system_path.clone();
+        commit_path.push("zebra.commit");
         system_path.push("zebra.conf");
 
         let (tx, rx) = mpsc::channel(255);
@@ -67,6 +83,10 @@ impl ConfigManager {
             tx,
             rx,
             cm_clients: HashMap::new(),
+            validators: HashMap::new(),
+            history: RefCell::new(History::new(Some(history_path), HISTORY_MAX)),
+            commits: RefCell::new(CommitHistory::new(Some(commit_path), COMMIT_HISTORY_MAX)),
+            client: RefCell::new(String::from(CLIENT_LOCAL)),
         };
         cm.init()?;
         Ok(cm)
@@ -89,10 +109,49 @@ impl ConfigManager {
         Ok(())
     }
 
+    // Merge the user YANG modules in the directory into the config tree.
+    pub fn yang_user_load(&mut self, dir: &Path) -> anyhow::Result<()> {
+        let modules = yang_user_read(dir, &self.yang_path)?;
+        let Some(mode) = self.modes.get("configure") else {
+            anyhow::bail!("configure mode not found");
+        };
+        let dsts: Vec<Rc<Entry>> = mode
+            .entry
+            .dir
+            .borrow()
+            .iter()
+            .filter(|e| e.name == "set" || e.name == "delete")
+            .cloned()
+            .collect();
+        let mut conflicts = Vec::new();
+        for module in modules.iter() {
+            if let Some(dst) = dsts.first() {
+                entry_conflicts(dst, module, "", &mut conflicts);
+            }
+        }
+        if !conflicts.is_empty() {
+            anyhow::bail!(
+                "{}: conflicts with the built-in schema: {}",
+                dir.display(),
+                conflicts.join(", ")
+            );
+        }
+        for module in modules.iter() {
+            for dst in dsts.iter() {
+                entry_merge(dst, module);
+            }
+        }
+        Ok(())
+    }
+
     pub fn subscribe(&mut self, name: &str, cm_tx: UnboundedSender<ConfigRequest>) {
         self.cm_clients.insert(name.to_owned(), cm_tx);
     }
 
+    pub fn validator_add(&mut self, path: &str, f: ConfigValidator) {
+        self.validators.insert(path.to_owned(), f);
+    }
+
     fn paths(&self, input: String) -> Option<Vec<CommandPath>> {
         let mode = self.modes.get("configure")?;
         let state = State::new();
@@ -113,7 +172,83 @@ impl ConfigManager {
         }
     }
 
-    pub fn commit_config(&self) {
+    // Set commands of the candidate config as the path and the arguments.
+    fn candidate_commands(&self) -> Vec<(String, Args)> {
+        let mut candidate = String::new();
+        self.store.candidate.borrow().list(&mut candidate);
+        candidate
+            .lines()
+            .filter_map(|line| self.paths(line.to_string()))
+            .map(|paths| path_from_command(&paths))
+            .collect()
+    }
+
+    // Name of the alias is checked against the first level commands of the
+    // mode of the alias.
+    fn alias_validate(&self, mut args: Args) -> Result<(), String> {
+        let mode = args.string().unwrap_or_default();
+        let name = args.string().unwrap_or_default();
+        let commands: Vec<String> = self
+            .modes
+            .get(&mode)
+            .map(|mode| {
+                mode.entry
+                    .dir
+                    .borrow()
+                    .iter()
+                    .map(|e| e.name.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        alias_validate(&name, &commands)
+    }
+
+    // Whether the input changes the aliases, i.e. sets or deletes the alias
+    // config or a parent of it.
+    fn alias_change(&self, mode: &Mode, input: &str) -> bool {
+        let (code, _, state) = parse(
+            input,
+            mode.entry.clone(),
+            Some(self.store.candidate.borrow().clone()),
+            State::new(),
+        );
+        let name = if state.set {
+            "set"
+        } else if state.delete {
+            "delete"
+        } else {
+            return false;
+        };
+        if code != ExecCode::Success {
+            return false;
+        }
+        let (path, _) = path_from_command(&path_trim(name, state.paths));
+        path.starts_with("/system/alias") || "/system/alias".starts_with(&path)
+    }
+
+    // The candidate config is listed only when a validator needs it.
+    fn validate(
+        &self,
+        paths: &[CommandPath],
+        commands: &mut Option<Vec<(String, Args)>>,
+    ) -> Result<(), String> {
+        let (path, args) = path_from_command(paths);
+        if path == "/system/alias/command" {
+            return self.alias_validate(args);
+        }
+        let Some(f) = self.validators.get(&path) else {
+            return Ok(());
+        };
+        let commands = commands.get_or_insert_with(|| self.candidate_commands());
+        f(args, commands)
+    }
+
+    // Commit candidate config. Every change is mapped by the schema and
+    // checked by the validators of the protocols before any of them is sent
+    // to the protocol modules. When any of them fails, nothing is sent, the
+    // running and the candidate config are left as they are, and the errors
+    // are returned.
+    pub fn commit_config(&self) -> Result<(), Vec<String>> {
         let mut running = String::new();
         let mut candidate = String::new();
         self.store.running.borrow().list(&mut running);
@@ -126,27 +261,80 @@ impl ConfigManager {
 
         let remove_first_char = |s: &str| -> String { s.chars().skip(1).collect() };
 
-        for line in diff.lines() {
-            if !line.is_empty() {
-                let first_char = line.chars().next().unwrap();
-                let op = if first_char == '+' {
-                    ConfigOp::Set
-                } else {
-                    ConfigOp::Delete
-                };
-                let line = remove_first_char(line);
-                let paths = self.paths(line.clone());
-                if paths.is_none() {
+        let mut commands = None;
+        let mut requests = Vec::new();
+        let mut changes = Vec::new();
+        let mut errors = Vec::new();
+        // Unchanged lines are the context of the diff and are not sent.
+        for line in diff.lines().filter(|line| line.starts_with(['+', '-'])) {
+            changes.push(line.to_string());
+            let op = if line.starts_with('+') {
+                ConfigOp::Set
+            } else {
+                ConfigOp::Delete
+            };
+            let line = remove_first_char(line);
+            let Some(paths) = self.paths(line.clone()) else {
+                errors.push(format!("invalid config: {}", line.trim()));
+                continue;
+            };
+            if op == ConfigOp::Set {
+                if let Err(err) = self.validate(&paths, &mut commands) {
+                    errors.push(format!("{}: {}", line.trim(), err));
                     continue;
                 }
-                let paths = paths.unwrap();
-                for (_, tx) in self.cm_clients.iter() {
-                    tx.send(ConfigRequest::new(paths.clone(), op.clone()))
-                        .unwrap();
-                }
+            }
+            requests.push((paths, op));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for (paths, op) in requests.into_iter() {
+            for (_, tx) in self.cm_clients.iter() {
+                tx.send(ConfigRequest::new(paths.clone(), op.clone()))
+                    .unwrap();
             }
         }
         self.store.commit();
+        if !changes.is_empty() {
+            let mut snapshot = String::new();
+            self.store.running.borrow().format(&mut snapshot);
+            self.commits
+                .borrow_mut()
+                .add(&self.client.borrow(), changes, &snapshot);
+        }
+        Ok(())
+    }
+
+    fn execute_all(&self, mode: &Mode, cmds: &[String]) -> Result<(), Vec<String>> {
+        for cmd in cmds.iter() {
+            let (code, _, _) = self.execute(mode, cmd);
+            if code != ExecCode::Show {
+                return Err(vec![format!("invalid config: {}", cmd)]);
+            }
+        }
+        Ok(())
+    }
+
+    // Apply a batch of set commands atomically. The batch is applied on top
+    // of the running config, so that the uncommitted changes in the
+    // candidate config are neither committed with it nor lost. When any of
+    // the commands is invalid or fails the validation, nothing is applied.
+    pub fn apply_batch(&self, cmds: &[String]) -> Result<(), Vec<String>> {
+        let Some(mode) = self.modes.get("configure") else {
+            return Err(vec![String::from("configure mode not found")]);
+        };
+        let saved = carbon_copy(&self.store.candidate.borrow(), None);
+        self.store.discard();
+        let result = self
+            .execute_all(mode, cmds)
+            .and_then(|_| self.commit_config());
+        self.store.candidate.replace(saved);
+        if result.is_ok() {
+            // The candidate config keeps the batch as committed.
+            let _ = self.execute_all(mode, cmds);
+        }
+        result
     }
 
     fn load_mode(&self, yang: &mut YangStore, mode: &str) -> anyhow::Result<Rc<Entry>> {
@@ -156,17 +344,44 @@ impl ConfigManager {
         Ok(to_entry(yang, module))
     }
 
-    pub fn load_config(&self) {
-        let output = std::fs::read_to_string(&self.config_path);
-        if let Ok(output) = output {
-            let cmds = load_config_file(output);
-            if let Some(mode) = self.modes.get("configure") {
-                for cmd in cmds.iter() {
-                    let _ = self.execute(mode, cmd);
+    // Load the config file. Unlike a batch, an invalid command, e.g. left by
+    // an older version, or one which fails the validation is skipped so that
+    // the rest of the config is applied rather than starting without config.
+    // The skipped commands are reported and returned.
+    pub fn load_config(&self) -> Vec<String> {
+        let Ok(output) = std::fs::read_to_string(&self.config_path) else {
+            return Vec::new();
+        };
+        let Some(mode) = self.modes.get("configure") else {
+            return Vec::new();
+        };
+        let mut errors = Vec::new();
+        for cmd in load_config_file(output).iter() {
+            let (code, _, state) = parse(
+                cmd,
+                mode.entry.clone(),
+                Some(self.store.candidate.borrow().clone()),
+                State::new(),
+            );
+            if state.set && code == ExecCode::Success {
+                let paths = path_trim("set", state.paths);
+                if let Err(err) = self.validate(&paths, &mut None) {
+                    errors.push(format!("invalid config: {}: {}", cmd, err));
+                    continue;
                 }
             }
+            let (code, _, _) = self.execute(mode, cmd);
+            if code != ExecCode::Show {
+                errors.push(format!("invalid config: {}", cmd));
+            }
+        }
+        if let Err(errs) = self.commit_config() {
+            errors.extend(errs);
         }
-        self.commit_config();
+        for err in errors.iter() {
+            println!("{}: {}", self.config_path.display(), err);
+        }
+        errors
     }
 
     pub fn save_config(&self) {
@@ -197,14 +412,14 @@ impl ConfigManager {
             }
             delete(paths, self.store.candidate.borrow().clone());
             (ExecCode::Show, String::from(""), state.paths)
-        } else if state.show && state.paths.len() > 1 {
-            let paths = path_trim("run", state.paths.clone());
-            (ExecCode::RedirectShow, input.to_string(), paths)
         } else {
-            let path = paths_str(&state.paths);
+            let (path, args) = path_from_command(&path_trim("run", state.paths.clone()));
             if let Some(f) = mode.fmap.get(&path) {
-                let (code, input) = f(self);
+                let (code, input) = f(self, args);
                 (code, input, state.paths)
+            } else if state.show && state.paths.len() > 1 {
+                let paths = path_trim("run", state.paths.clone());
+                (ExecCode::RedirectShow, input.to_string(), paths)
             } else {
                 (code, "".to_string(), state.paths)
             }
@@ -246,7 +461,15 @@ impl ConfigManager {
         match m {
             Message::Execute(req) => {
                 let mut resp = ExecuteResponse::new();
+                self.client.replace(req.client.clone());
                 match self.modes.get(&req.mode) {
+                    Some(mode)
+                        if req.privilege < ALIAS_PRIVILEGE
+                            && self.alias_change(mode, &req.input) =>
+                    {
+                        resp.code = ExecCode::Show;
+                        resp.output = format!("% Aliases require privilege {}\n", ALIAS_PRIVILEGE);
+                    }
                     Some(mode) => {
                         (resp.code, resp.output, resp.paths) = self.execute(mode, &req.input);
                     }
@@ -254,6 +477,11 @@ impl ConfigManager {
                         resp.code = ExecCode::Nomatch;
                     }
                 }
+                self.client.replace(String::from(CLIENT_LOCAL));
+                let line = history_redact(&req.input, &resp.paths);
+                self.history
+                    .borrow_mut()
+                    .add(req.privilege, resp.code, line);
                 req.resp.send(resp).unwrap();
             }
             Message::Completion(req) => {
@@ -268,6 +496,15 @@ impl ConfigManager {
                 }
                 req.resp.send(resp).unwrap();
             }
+            Message::History(req) => {
+                let lines = self
+                    .history
+                    .borrow()
+                    .last(req.last)
+                    .map(|(_, entry)| entry.line.clone())
+                    .collect();
+                req.resp.send(lines).unwrap();
+            }
         }
     }
 }
@@ -282,6 +519,8 @@ fn run_from_exec(exec: Rc<Entry>) -> Rc<Entry> {
 }
 
 pub async fn event_loop(mut config: ConfigManager) {
+    config.history.borrow_mut().load();
+    config.commits.borrow_mut().load();
     config.load_config();
     loop {
         tokio::select! {
@@ -296,3 +535,260 @@ fn has_interfaces(input: &str) -> bool {
     input.split_whitespace().any(|s| s == "interfaces")
         | input.split_whitespace().any(|s| s == "neighbors")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn manager() -> ConfigManager {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("yang");
+        let cm = ConfigManager::new(path).unwrap();
+        // Commit history is not written to the source tree.
+        cm.commits
+            .replace(CommitHistory::new(None, COMMIT_HISTORY_MAX));
+        cm
+    }
+
+    fn running(cm: &ConfigManager) -> String {
+        let mut output = String::new();
+        cm.store.running.borrow().list(&mut output);
+        output
+    }
+
+    #[test]
+    fn batch_rollback() {
+        let mut cm = manager();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        cm.subscribe("test", tx);
+
+        let cmds = vec![
+            String::from("set routing bgp global as 100"),
+            String::from("set routing bgp global no-such-leaf 1"),
+        ];
+        assert!(cm.apply_batch(&cmds).is_err());
+        assert!(rx.try_recv().is_err());
+        assert!(running(&cm).is_empty());
+        let mut candidate = String::new();
+        cm.store.candidate.borrow().list(&mut candidate);
+        assert!(candidate.is_empty());
+
+        let cmds = vec![
+            String::from("set routing bgp global as 100"),
+            String::from("set routing bgp global identifier 10.0.0.1"),
+        ];
+        assert!(cm.apply_batch(&cmds).is_ok());
+        let mut count = 0;
+        while let Ok(req) = rx.try_recv() {
+            assert_eq!(req.op, ConfigOp::Set);
+            count += 1;
+        }
+        assert!(count >= 2);
+        assert!(running(&cm).contains("as 100"));
+    }
+
+    #[test]
+    fn load_skips_invalid() {
+        let mut cm = manager();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        cm.subscribe("test", tx);
+
+        let mut file = std::env::temp_dir();
+        file.push(format!("zebra-load-{}.conf", std::process::id()));
+        std::fs::write(
+            &file,
+            "routing {\n  bgp {\n    global {\n      as 100;\n      no-such-leaf 1;\n      identifier 10.0.0.1;\n    }\n  }\n}\n",
+        )
+        .unwrap();
+        cm.config_path = file.clone();
+
+        // The invalid line is reported and the rest is applied.
+        let errors = cm.load_config();
+        let _ = std::fs::remove_file(&file);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("no-such-leaf"));
+        assert!(running(&cm).contains("as 100"));
+        assert!(running(&cm).contains("identifier 10.0.0.1"));
+        assert!(rx.try_recv().is_ok());
+
+        // No config file is not an error.
+        cm.config_path = file;
+        assert!(cm.load_config().is_empty());
+    }
+
+    fn candidate(cm: &ConfigManager) -> String {
+        let mut output = String::new();
+        cm.store.candidate.borrow().list(&mut output);
+        output
+    }
+
+    #[test]
+    fn alias_config() {
+        let mut cm = manager();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        cm.subscribe("test", tx);
+
+        // Name of a command of the mode.
+        let cmds = vec![String::from(
+            "set system alias exec show command \"show ip bgp\"",
+        )];
+        let errors = cm.apply_batch(&cmds).unwrap_err();
+        assert!(errors[0].contains("collides"));
+        assert!(rx.try_recv().is_err());
+
+        let cmds = vec![String::from(
+            "set system alias exec sib command \"show ip bgp\"",
+        )];
+        assert!(cm.apply_batch(&cmds).is_ok());
+        let mut commands = Vec::new();
+        while let Ok(req) = rx.try_recv() {
+            commands.push(path_from_command(&req.paths));
+        }
+        let (_, args) = commands
+            .iter()
+            .find(|(path, _)| path == "/system/alias/command")
+            .unwrap();
+        assert_eq!(args.0, ["exec", "sib", "show ip bgp"]);
+
+        // Privilege is required to change the aliases.
+        let mode = cm.modes.get("configure").unwrap();
+        assert!(cm.alias_change(mode, "delete system alias exec sib"));
+        assert!(cm.alias_change(mode, "delete system"));
+        assert!(!cm.alias_change(mode, "set system hostname r1"));
+    }
+
+    #[test]
+    fn commit_validate() {
+        let mut cm = manager();
+        crate::bgp::config::validator_build(&mut cm);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        cm.subscribe("test", tx);
+        let mode = cm.modes.get("configure").unwrap();
+
+        // Uncommitted change of the operator.
+        cm.execute(mode, "set routing bgp global identifier 10.0.0.1");
+
+        // Bad pattern is rejected with the rest of the batch.
+        let cmds = vec![
+            String::from("set as-path-set A member _65001_"),
+            String::from("set as-path-set A member 65001("),
+        ];
+        let errors = cm.apply_batch(&cmds).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("invalid pattern 65001("), "{:?}", errors);
+        assert!(rx.try_recv().is_err());
+        assert!(running(&cm).is_empty());
+        assert!(candidate(&cm).contains("identifier 10.0.0.1"));
+        assert!(!candidate(&cm).contains("as-path-set"));
+
+        // The batch does not commit the change of the operator, which is
+        // kept in the candidate config.
+        let cmds = vec![String::from("set as-path-set A member _65001_")];
+        assert!(cm.apply_batch(&cmds).is_ok());
+        assert!(running(&cm).contains("_65001_"));
+        assert!(!running(&cm).contains("identifier"));
+        assert!(candidate(&cm).contains("_65001_"));
+        assert!(candidate(&cm).contains("identifier 10.0.0.1"));
+        while rx.try_recv().is_ok() {}
+
+        // Commit of the candidate config is rejected as a whole and both of
+        // the configs are left as they are.
+        cm.execute(
+            mode,
+            "set routing bgp neighbors neighbor 10.0.0.2 transport ebgp-multihop multihop-ttl 2",
+        );
+        cm.execute(
+            mode,
+            "set routing bgp neighbors neighbor 10.0.0.2 transport ttl-security hops 1",
+        );
+        let before = candidate(&cm);
+        let errors = cm.commit_config().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("mutually exclusive"), "{:?}", errors);
+        assert!(rx.try_recv().is_err());
+        assert!(!running(&cm).contains("identifier"));
+        assert_eq!(candidate(&cm), before);
+    }
+
+    fn yang_user_dir(name: &str, module: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("zebra-yang-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("local-ext.yang"), module).unwrap();
+        dir
+    }
+
+    const YANG_LOCAL: &str = r#"module local-ext {
+  yang-version "1";
+  namespace "https://example.com/local-ext";
+  prefix "local";
+
+  import extension {
+    prefix ext;
+  }
+
+  container routing {
+    container site {
+      leaf id {
+        type uint32;
+        ext:help "Site ID";
+      }
+    }
+  }
+}
+"#;
+
+    #[test]
+    fn yang_user_augment() {
+        let mut cm = manager();
+        let cmds = vec![String::from("set routing site id 10")];
+        assert!(cm.apply_batch(&cmds).is_err());
+
+        let dir = yang_user_dir("augment", YANG_LOCAL);
+        cm.yang_user_load(&dir).unwrap();
+        assert!(cm.apply_batch(&cmds).is_ok());
+        assert!(running(&cm).contains("id 10"));
+        // Built-in nodes of the merged container are kept.
+        let cmds = vec![String::from("set routing bgp global as 100")];
+        assert!(cm.apply_batch(&cmds).is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn yang_user_conflict() {
+        let mut cm = manager();
+        let module = YANG_LOCAL.replace(
+            "container site {",
+            "container bgp {\n      leaf global { type string; }",
+        );
+        let dir = yang_user_dir("conflict", &module);
+        let err = cm.yang_user_load(&dir).unwrap_err().to_string();
+        assert!(err.contains("/routing/bgp/global"), "{}", err);
+        // Nothing is merged.
+        let cmds = vec![String::from("set routing bgp id 10")];
+        assert!(cm.apply_batch(&cmds).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_history() {
+        let cm = manager();
+        cm.client.replace(String::from("127.0.0.1:50000"));
+        let cmds = vec![String::from("set routing bgp global as 100")];
+        assert!(cm.apply_batch(&cmds).is_ok());
+        // Commit without changes is not recorded.
+        assert!(cm.apply_batch(&cmds).is_ok());
+        let cmds = vec![String::from("set routing bgp global as 200")];
+        assert!(cm.apply_batch(&cmds).is_ok());
+
+        let commits = cm.commits.borrow();
+        let ids: Vec<u64> = commits.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(commits.entries[0].client, "127.0.0.1:50000");
+        assert!(commits.entries[1]
+            .changes
+            .iter()
+            .any(|line| line.starts_with('+') && line.contains("as 200")));
+    }
+}
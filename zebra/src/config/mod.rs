@@ -22,11 +22,24 @@ mod paths;
 pub use paths::path_from_command;
 
 mod api;
-pub use api::{ConfigChannel, ConfigOp, ConfigRequest, DisplayRequest, ShowChannel};
+pub use api::{
+    ConfigChannel, ConfigOp, ConfigRequest, DisplayRequest, ShowChannel, StateChannel, StateRequest,
+};
 
+mod state;
+pub use state::StateProviders;
+
+mod output;
+pub use output::{output, show_path, Render, Uptime};
+
+mod alias;
 mod commands;
+mod commits;
 mod files;
+mod filter;
+mod history;
 mod ip;
 mod parse;
 mod token;
 mod util;
+mod yang;
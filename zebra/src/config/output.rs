@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+// Output of the show commands. A command builds a typed value which is
+// rendered as text for the CLI, or serialized as JSON when the command ends
+// with "json". The dispatcher strips the trailing "json" from the path, so
+// one callback serves both renderings. JSON field names are snake_case in all
+// of the protocols, lists of neighbors are "neighbors" and time is given as
+// seconds together with the formatted text.
+
+pub trait Render: Serialize {
+    fn render(&self, buf: &mut String);
+}
+
+pub fn output<T: Render>(value: &T, json: bool) -> String {
+    let mut buf = String::new();
+    if json {
+        buf = serde_json::to_string_pretty(value).unwrap_or_default();
+        buf.push('\n');
+    } else {
+        value.render(&mut buf);
+    }
+    buf
+}
+
+// Path of the show callback and whether JSON output is requested.
+pub fn show_path(path: String) -> (String, bool) {
+    match path.strip_suffix("/json") {
+        Some(path) => (path.to_string(), true),
+        None => (path, false),
+    }
+}
+
+const DAY: u64 = 24 * 60 * 60;
+const WEEK: u64 = 7 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Uptime {
+    pub seconds: Option<u64>,
+    pub text: String,
+}
+
+impl Uptime {
+    pub fn since(instant: Option<Instant>) -> Self {
+        Self::from_duration(instant.map(|instant| instant.elapsed()))
+    }
+
+    // Same format as the neighbor uptime of other routing daemons, "never"
+    // when it has not been up.
+    pub fn from_duration(duration: Option<Duration>) -> Self {
+        let Some(duration) = duration else {
+            return Self {
+                seconds: None,
+                text: String::from("never"),
+            };
+        };
+        let secs = duration.as_secs();
+        let (hour, min, sec) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+        let day = secs / DAY;
+        let text = if secs < DAY {
+            format!("{:02}:{:02}:{:02}", hour, min, sec)
+        } else if secs < WEEK {
+            format!("{}d{:02}h{:02}m", day, hour, min)
+        } else if secs < YEAR {
+            format!("{:02}w{}d{:02}h", day / 7, day % 7, hour)
+        } else {
+            let yday = day % 365;
+            format!("{:02}y{:02}w{}d", day / 365, yday / 7, yday % 7)
+        };
+        Self {
+            seconds: Some(secs),
+            text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn uptime(secs: u64) -> String {
+        Uptime::from_duration(Some(Duration::from_secs(secs))).text
+    }
+
+    #[test]
+    fn uptime_format() {
+        assert_eq!(Uptime::from_duration(None).text, "never");
+        assert_eq!(uptime(0), "00:00:00");
+        assert_eq!(uptime(3 * 3600 + 25 * 60 + 7), "03:25:07");
+        assert_eq!(uptime(2 * DAY + 5 * 3600 + 30 * 60), "2d05h30m");
+        assert_eq!(uptime(2 * WEEK + 3 * DAY + 4 * 3600), "02w3d04h");
+        assert_eq!(uptime(YEAR + WEEK + DAY), "01y01w1d");
+
+        let json = serde_json::to_value(Uptime::from_duration(Some(Duration::from_secs(61))));
+        assert_eq!(
+            json.unwrap(),
+            serde_json::json!({"seconds": 61, "text": "00:01:01"})
+        );
+    }
+
+    #[test]
+    fn path() {
+        assert_eq!(
+            show_path("/show/ip/bgp/summary/json".into()),
+            ("/show/ip/bgp/summary".into(), true)
+        );
+        assert_eq!(
+            show_path("/show/ip/bgp/summary".into()),
+            ("/show/ip/bgp/summary".into(), false)
+        );
+    }
+}
@@ -484,7 +484,8 @@ pub fn parse(
     if path.name == "delete" {
         s.delete = true;
     }
-    if path.name == "show" {
+    // Clear commands are redirected to the protocol as show commands.
+    if path.name == "show" || (path.name == "clear" && s.paths.is_empty()) {
         s.show = true;
     }
     s.paths.push(path);
@@ -4,15 +4,6 @@ use super::configs::ymatch_enum;
 use super::vtysh::{CommandPath, YangMatch};
 use super::Args;
 
-pub fn paths_str(paths: &[CommandPath]) -> String {
-    let mut s = String::from("");
-    for path in paths.iter() {
-        s.push('/');
-        s.push_str(&path.name.to_string());
-    }
-    s
-}
-
 #[allow(dead_code)]
 pub fn paths_dump(paths: &[CommandPath]) {
     for path in paths.iter() {
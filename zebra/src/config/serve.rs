@@ -1,29 +1,55 @@
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::mpsc::{Sender, UnboundedSender};
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::Server;
 use tonic::Response;
 
+use super::alias::{alias_command, alias_config, Aliases};
 use super::api::{
-    CompletionRequest, CompletionResponse, DisplayRequest, ExecuteRequest, ExecuteResponse, Message,
+    CompletionRequest, CompletionResponse, ConfigChannel, ConfigRequest, DisplayRequest,
+    ExecuteRequest, ExecuteResponse, HistoryRequest, Message, StateRequest,
 };
+use super::filter::filter_split;
+use super::path_from_command;
+use super::state::{state_merge, StateError, StatePath};
 use super::vtysh::exec_server::{Exec, ExecServer};
 use super::vtysh::show_server::{Show, ShowServer};
+use super::vtysh::state_server::{State, StateServer};
 use super::vtysh::{
-    CommandPath, ExecCode, ExecReply, ExecRequest, ExecType, ShowReply, ShowRequest, YangMatch,
+    CommandPath, ExecCode, ExecReply, ExecRequest, ExecType, GetStateReply, GetStateRequest,
+    ShowReply, ShowRequest, YangMatch,
 };
 
 #[derive(Debug)]
 struct ExecService {
     pub tx: mpsc::Sender<Message>,
+    pub aliases: Arc<Mutex<Aliases>>,
 }
 
 impl ExecService {
-    async fn execute_request(&self, mode: &str, input: &str) -> ExecuteResponse {
+    // Alias command output, or the input with the aliases expanded.
+    fn alias(&self, mode: &str, input: &str) -> Result<String, String> {
+        let aliases = self.aliases.lock().unwrap();
+        if let Some(output) = alias_command(&aliases, input) {
+            return Err(output);
+        }
+        aliases
+            .expand(mode, input)
+            .map_err(|err| format!("% {}\n", err))
+    }
+
+    async fn execute_request(
+        &self,
+        mode: &str,
+        privilege: u32,
+        input: &str,
+        client: &str,
+    ) -> ExecuteResponse {
         let (tx, rx) = oneshot::channel();
-        let req = ExecuteRequest::new(mode, input, tx);
+        let req = ExecuteRequest::new(mode, privilege, input, client, tx);
         self.tx.send(Message::Execute(req)).await.unwrap();
         rx.await.unwrap()
     }
@@ -35,6 +61,13 @@ impl ExecService {
         rx.await.unwrap()
     }
 
+    async fn history_request(&self, last: usize) -> Vec<String> {
+        let (tx, rx) = oneshot::channel();
+        let req = HistoryRequest { last, resp: tx };
+        self.tx.send(Message::History(req)).await.unwrap();
+        rx.await.unwrap()
+    }
+
     fn reply(&self, code: ExecCode, lines: String) -> Result<Response<ExecReply>, tonic::Status> {
         let reply = ExecReply {
             code: code as i32,
@@ -69,16 +102,45 @@ impl Exec for ExecService {
         &self,
         request: tonic::Request<ExecRequest>,
     ) -> std::result::Result<Response<ExecReply>, tonic::Status> {
+        let client = request
+            .remote_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|| String::from("local"));
         let request = request.get_ref();
         match request.r#type {
             x if x == ExecType::Exec as i32 => {
-                let resp = self.execute_request(&request.mode, &request.line).await;
-                let (code, output, paths) = exec_commands(&resp);
+                let line = match self.alias(&request.mode, &request.line) {
+                    Ok(line) => line,
+                    Err(output) => return self.reply(ExecCode::Show, output),
+                };
+                // The output modifier of exec mode is applied to the show
+                // output, the command is executed without it.
+                let (line, filter) = if request.mode == "exec" {
+                    match filter_split(&line) {
+                        Ok(split) => split,
+                        Err(err) => return self.reply(ExecCode::Show, format!("% {}\n", err)),
+                    }
+                } else {
+                    (line.as_str(), None)
+                };
+                let resp = self
+                    .execute_request(&request.mode, request.privilege, line, &client)
+                    .await;
+                let (code, mut output, paths) = exec_commands(&resp);
+                if let (ExecCode::Show, Some(mut filter)) = (code, filter) {
+                    output = filter.apply(&output) + &filter.finish();
+                }
                 self.reply_exec(code, output, paths)
             }
             x if x == ExecType::CompleteFirstCommands as i32 => {
                 let resp = self.completion_request(&request.mode, &request.line).await;
-                self.reply(ExecCode::Success, first_commands(&resp))
+                let mut lines = first_commands(&resp);
+                lines.push_str("alias\n");
+                for name in self.aliases.lock().unwrap().names(&request.mode) {
+                    lines.push_str(name);
+                    lines.push('\n');
+                }
+                self.reply(ExecCode::Success, lines)
             }
             x if x == ExecType::Complete as i32 => {
                 let resp = self.completion_request(&request.mode, &request.line).await;
@@ -90,6 +152,15 @@ impl Exec for ExecService {
                 let resp = self.completion_request(&request.mode, &input).await;
                 self.reply(ExecCode::Success, comp_commands(&resp))
             }
+            x if x == ExecType::History as i32 => {
+                let last = request.line.trim().parse().unwrap_or(usize::MAX);
+                let mut lines = String::new();
+                for line in self.history_request(last).await.iter() {
+                    lines.push_str(line);
+                    lines.push('\n');
+                }
+                self.reply(ExecCode::Success, lines)
+            }
             _ => self.reply(ExecCode::Success, String::from("Success\n")),
         }
     }
@@ -166,6 +237,10 @@ impl Show for ShowService {
         request: tonic::Request<ShowRequest>,
     ) -> std::result::Result<Response<Self::ShowStream>, tonic::Status> {
         let request = request.get_ref();
+        let mut filter = match filter_split(&request.line) {
+            Ok((_, filter)) => filter,
+            Err(err) => return Err(tonic::Status::invalid_argument(err.to_string())),
+        };
         let (bus_tx, mut bus_rx) = mpsc::channel::<String>(4);
         let req = DisplayRequest {
             paths: request.paths.clone(),
@@ -181,22 +256,87 @@ impl Show for ShowService {
 
         let (tx, rx) = mpsc::channel(4);
         tokio::spawn(async move {
-            while let Some(item) = bus_rx.recv().await {
+            while let Some(mut item) = bus_rx.recv().await {
+                if let Some(filter) = filter.as_mut() {
+                    item = filter.apply(&item);
+                    if item.is_empty() {
+                        continue;
+                    }
+                }
                 match tx.send(Ok(ShowReply { str: item })).await {
                     Ok(_) => {}
                     Err(_) => {
-                        break;
+                        return;
                     }
                 }
             }
+            if let Some(mut filter) = filter {
+                let item = filter.finish();
+                if !item.is_empty() {
+                    let _ = tx.send(Ok(ShowReply { str: item })).await;
+                }
+            }
         });
         Ok(Response::new(ReceiverStream::new(rx)))
     }
 }
 
+#[derive(Debug)]
+struct StateService {
+    state_clients: HashMap<String, UnboundedSender<StateRequest>>,
+}
+
+impl StateService {
+    // Ask every daemon for the path and merge the replies.
+    async fn get(&self, path: &StatePath) -> Option<serde_json::Value> {
+        let mut pending = Vec::new();
+        for tx in self.state_clients.values() {
+            let (resp, rx) = oneshot::channel();
+            let req = StateRequest {
+                path: path.clone(),
+                resp,
+            };
+            if tx.send(req).is_ok() {
+                pending.push(rx);
+            }
+        }
+        let mut values = Vec::new();
+        for rx in pending.into_iter() {
+            if let Ok(Some(value)) = rx.await {
+                values.push(value);
+            }
+        }
+        state_merge(values)
+    }
+}
+
+#[tonic::async_trait]
+impl State for StateService {
+    async fn get_state(
+        &self,
+        request: tonic::Request<GetStateRequest>,
+    ) -> std::result::Result<Response<GetStateReply>, tonic::Status> {
+        let request = request.get_ref();
+        let path: StatePath = request
+            .path
+            .parse()
+            .map_err(|err: StateError| tonic::Status::invalid_argument(err.to_string()))?;
+        let Some(value) = self.get(&path).await else {
+            let err = StateError::UnknownPath(request.path.clone());
+            return Err(tonic::Status::not_found(err.to_string()));
+        };
+        let json = serde_json::to_string_pretty(&value)
+            .map_err(|err| tonic::Status::internal(err.to_string()))?;
+        Ok(Response::new(GetStateReply { json }))
+    }
+}
+
 pub struct Cli {
     pub tx: mpsc::Sender<Message>,
     pub show_clients: HashMap<String, UnboundedSender<DisplayRequest>>,
+    pub state_clients: HashMap<String, UnboundedSender<StateRequest>>,
+    // Alias config, subscribed to the config manager.
+    pub alias_cm: ConfigChannel,
 }
 
 impl Cli {
@@ -204,16 +344,37 @@ impl Cli {
         Self {
             tx: config_tx,
             show_clients: HashMap::new(),
+            state_clients: HashMap::new(),
+            alias_cm: ConfigChannel::new(),
         }
     }
 
     pub fn subscribe(&mut self, name: &str, tx: UnboundedSender<DisplayRequest>) {
         self.show_clients.insert(name.to_string(), tx);
     }
+
+    pub fn subscribe_state(&mut self, name: &str, tx: UnboundedSender<StateRequest>) {
+        self.state_clients.insert(name.to_string(), tx);
+    }
+}
+
+// Apply the alias config sent at commit.
+fn alias_serve(aliases: Arc<Mutex<Aliases>>, mut rx: UnboundedReceiver<ConfigRequest>) {
+    tokio::spawn(async move {
+        while let Some(req) = rx.recv().await {
+            let (path, args) = path_from_command(&req.paths);
+            alias_config(&mut aliases.lock().unwrap(), &path, args, req.op);
+        }
+    });
 }
 
 pub fn serve(cli: Cli) {
-    let exec_service = ExecService { tx: cli.tx.clone() };
+    let aliases = Arc::new(Mutex::new(Aliases::default()));
+    alias_serve(aliases.clone(), cli.alias_cm.rx);
+    let exec_service = ExecService {
+        tx: cli.tx.clone(),
+        aliases,
+    };
     let exec_server = ExecServer::new(exec_service);
 
     let mut show_service = ShowService {
@@ -226,12 +387,18 @@ pub fn serve(cli: Cli) {
     }
     let show_server = ShowServer::new(show_service);
 
+    let state_service = StateService {
+        state_clients: cli.state_clients.clone(),
+    };
+    let state_server = StateServer::new(state_service);
+
     let addr = "0.0.0.0:2650".parse().unwrap();
 
     tokio::spawn(async move {
         Server::builder()
             .add_service(exec_server)
             .add_service(show_server)
+            .add_service(state_server)
             .serve(addr)
             .await
     });
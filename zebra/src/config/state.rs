@@ -0,0 +1,390 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+
+// Operational state retrieval. Each daemon registers providers keyed by YANG
+// data paths. A GetState request is sent to every daemon, each answers with
+// the part of the tree it owns and the replies are merged into one document.
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StateElem {
+    pub name: String,
+    pub keys: Vec<(String, String)>,
+}
+
+// Path such as /bgp/neighbors/neighbor[address=10.0.0.1]/state.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatePath {
+    pub elems: Vec<StateElem>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    InvalidPath(String),
+    UnknownPath(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPath(path) => write!(f, "invalid path {}", path),
+            Self::UnknownPath(path) => write!(f, "unknown path {}", path),
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':')
+}
+
+fn parse_elem(s: &str) -> Option<StateElem> {
+    let (name, mut rest) = match s.find('[') {
+        Some(pos) => (&s[..pos], &s[pos..]),
+        None => (s, ""),
+    };
+    if !is_name(name) {
+        return None;
+    }
+    let mut elem = StateElem {
+        name: name.to_string(),
+        keys: Vec::new(),
+    };
+    while !rest.is_empty() {
+        let end = rest.find(']')?;
+        let (key, value) = rest.get(1..end)?.split_once('=')?;
+        if !is_name(key) || value.is_empty() {
+            return None;
+        }
+        elem.keys.push((key.to_string(), value.to_string()));
+        rest = &rest[end + 1..];
+        if !rest.is_empty() && !rest.starts_with('[') {
+            return None;
+        }
+    }
+    Some(elem)
+}
+
+impl FromStr for StatePath {
+    type Err = StateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || StateError::InvalidPath(s.to_string());
+        let body = s.strip_prefix('/').ok_or_else(invalid)?;
+        let mut path = StatePath::default();
+        if body.is_empty() {
+            return Ok(path);
+        }
+        // Key values may contain '/', e.g. prefix[prefix=10.0.0.0/8].
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in body.char_indices() {
+            match c {
+                '[' if depth == 0 => depth = 1,
+                ']' if depth == 1 => depth = 0,
+                '[' | ']' => return Err(invalid()),
+                '/' if depth == 0 => {
+                    path.elems
+                        .push(parse_elem(&body[start..i]).ok_or_else(invalid)?);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if depth != 0 {
+            return Err(invalid());
+        }
+        path.elems
+            .push(parse_elem(&body[start..]).ok_or_else(invalid)?);
+        Ok(path)
+    }
+}
+
+impl fmt::Display for StatePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.elems.is_empty() {
+            return write!(f, "/");
+        }
+        for elem in self.elems.iter() {
+            write!(f, "/{}", elem.name)?;
+            for (key, value) in elem.keys.iter() {
+                write!(f, "[{}={}]", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn key_match(item: &Value, key: &str, value: &str) -> bool {
+    match item.get(key) {
+        Some(Value::String(s)) => s == value,
+        Some(v) => v.to_string() == value,
+        None => false,
+    }
+}
+
+// Select the list entries matching all the keys.
+fn state_filter(value: Value, keys: &[(String, String)]) -> Option<Value> {
+    if keys.is_empty() {
+        return Some(value);
+    }
+    let Value::Array(items) = value else {
+        return None;
+    };
+    let items: Vec<Value> = items
+        .into_iter()
+        .filter(|item| keys.iter().all(|(k, v)| key_match(item, k, v)))
+        .collect();
+    if items.is_empty() {
+        None
+    } else {
+        Some(Value::Array(items))
+    }
+}
+
+fn state_descend(value: Value, elems: &[StateElem]) -> Option<Value> {
+    let Some((elem, rest)) = elems.split_first() else {
+        return Some(value);
+    };
+    let child = match value {
+        Value::Object(mut map) => map.remove(&elem.name)?,
+        Value::Array(items) => {
+            let children: Vec<Value> = items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Value::Object(mut map) => map.remove(&elem.name),
+                    _ => None,
+                })
+                .collect();
+            if children.is_empty() {
+                return None;
+            }
+            Value::Array(children)
+        }
+        _ => return None,
+    };
+    state_descend(state_filter(child, &elem.keys)?, rest)
+}
+
+// Return the part of the provider's value, registered at `at`, which answers
+// the request for `path`. A request for an ancestor of the provider path gets
+// the value wrapped in the intermediate containers.
+pub fn state_select(at: &StatePath, value: Value, path: &StatePath) -> Option<Value> {
+    let common = at.elems.len().min(path.elems.len());
+    if at.elems[..common]
+        .iter()
+        .zip(path.elems[..common].iter())
+        .any(|(a, p)| a.name != p.name)
+    {
+        return None;
+    }
+    // Keys are only resolved at or below the provider's own list.
+    let keyed = common.saturating_sub(1);
+    if path.elems[..keyed].iter().any(|p| !p.keys.is_empty()) {
+        return None;
+    }
+    if path.elems.len() < at.elems.len() {
+        if path.elems.iter().any(|p| !p.keys.is_empty()) {
+            return None;
+        }
+        let mut value = value;
+        for elem in at.elems[path.elems.len()..].iter().rev() {
+            let mut map = Map::new();
+            map.insert(elem.name.clone(), value);
+            value = Value::Object(map);
+        }
+        return Some(value);
+    }
+    let value = match path.elems.get(keyed) {
+        Some(elem) if common > 0 => state_filter(value, &elem.keys)?,
+        _ => value,
+    };
+    state_descend(value, &path.elems[at.elems.len()..])
+}
+
+fn state_merge_value(dst: &mut Value, src: Value) {
+    match (dst, src) {
+        (Value::Object(dst), Value::Object(src)) => {
+            for (key, value) in src.into_iter() {
+                match dst.get_mut(&key) {
+                    Some(entry) => state_merge_value(entry, value),
+                    None => {
+                        dst.insert(key, value);
+                    }
+                }
+            }
+        }
+        (dst, src) => *dst = src,
+    }
+}
+
+// Merge the replies from the daemons. None when nobody knows the path.
+pub fn state_merge(values: Vec<Value>) -> Option<Value> {
+    let mut values = values.into_iter();
+    let mut merged = values.next()?;
+    for value in values {
+        state_merge_value(&mut merged, value);
+    }
+    Some(merged)
+}
+
+pub type StateCallback<T> = fn(&T) -> Value;
+
+pub struct StateProviders<T> {
+    providers: Vec<(StatePath, StateCallback<T>)>,
+}
+
+impl<T> Default for StateProviders<T> {
+    fn default() -> Self {
+        Self {
+            providers: Vec::new(),
+        }
+    }
+}
+
+impl<T> StateProviders<T> {
+    pub fn add(&mut self, path: &str, cb: StateCallback<T>) {
+        let path: StatePath = path.parse().expect("state provider path");
+        self.providers.push((path, cb));
+    }
+
+    pub fn get(&self, obj: &T, path: &StatePath) -> Option<Value> {
+        let values: Vec<Value> = self
+            .providers
+            .iter()
+            .filter(|(at, _)| {
+                at.elems
+                    .iter()
+                    .zip(path.elems.iter())
+                    .all(|(a, p)| a.name == p.name)
+            })
+            .filter_map(|(at, cb)| state_select(at, cb(obj), path))
+            .collect();
+        state_merge(values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn path_parse() {
+        let path: StatePath = "/bgp/neighbors/neighbor[address=10.0.0.1]/state"
+            .parse()
+            .unwrap();
+        assert_eq!(path.elems.len(), 4);
+        assert_eq!(path.elems[2].name, "neighbor");
+        assert_eq!(
+            path.elems[2].keys,
+            vec![("address".to_string(), "10.0.0.1".to_string())]
+        );
+        assert_eq!(
+            path.to_string(),
+            "/bgp/neighbors/neighbor[address=10.0.0.1]/state"
+        );
+
+        let path: StatePath = "/rib/route[prefix=10.0.0.0/8][vrf=red]".parse().unwrap();
+        assert_eq!(path.elems.len(), 2);
+        assert_eq!(path.elems[1].keys.len(), 2);
+        assert_eq!(path.elems[1].keys[0].1, "10.0.0.0/8");
+
+        assert!("/".parse::<StatePath>().unwrap().elems.is_empty());
+        for s in [
+            "",
+            "bgp",
+            "/bgp/",
+            "/bgp//neighbors",
+            "/neighbor[address]",
+            "/neighbor[address=]",
+            "/neighbor[address=1",
+            "/neighbor[a=1]x",
+            "/neighbor[[a=1]]",
+        ] {
+            assert_eq!(
+                s.parse::<StatePath>(),
+                Err(StateError::InvalidPath(s.to_string()))
+            );
+        }
+    }
+
+    fn neighbors() -> Value {
+        json!([
+            {"address": "10.0.0.1", "remote_as": 65001, "state": "Established"},
+            {"address": "10.0.0.2", "remote_as": 65002, "state": "Idle"},
+        ])
+    }
+
+    #[test]
+    fn key_filter() {
+        let at: StatePath = "/bgp/neighbors/neighbor".parse().unwrap();
+        let path = "/bgp/neighbors/neighbor[address=10.0.0.2]".parse().unwrap();
+        let value = state_select(&at, neighbors(), &path).unwrap();
+        assert_eq!(value, json!([neighbors()[1]]));
+
+        let path = "/bgp/neighbors/neighbor[address=10.0.0.1]/state"
+            .parse()
+            .unwrap();
+        let value = state_select(&at, neighbors(), &path).unwrap();
+        assert_eq!(value, json!(["Established"]));
+
+        // Non-string key.
+        let path = "/bgp/neighbors/neighbor[remote_as=65002]/address"
+            .parse()
+            .unwrap();
+        let value = state_select(&at, neighbors(), &path).unwrap();
+        assert_eq!(value, json!(["10.0.0.2"]));
+
+        // Unknown key value and leaf.
+        let path = "/bgp/neighbors/neighbor[address=10.0.0.3]".parse().unwrap();
+        assert_eq!(state_select(&at, neighbors(), &path), None);
+        let path = "/bgp/neighbors/neighbor/uptime".parse().unwrap();
+        assert_eq!(state_select(&at, neighbors(), &path), None);
+
+        // Ancestor is wrapped with the containers.
+        let path = "/bgp".parse().unwrap();
+        let value = state_select(&at, neighbors(), &path).unwrap();
+        assert_eq!(value, json!({"neighbors": {"neighbor": neighbors()}}));
+        let path = "/bgp[asn=1]".parse().unwrap();
+        assert_eq!(state_select(&at, neighbors(), &path), None);
+    }
+
+    fn rib_count(_: &()) -> Value {
+        json!({"total": 3, "fib": 2})
+    }
+
+    fn bgp_global(_: &()) -> Value {
+        json!({"as": 65000})
+    }
+
+    fn bgp_neighbor(_: &()) -> Value {
+        neighbors()
+    }
+
+    #[test]
+    fn multi_daemon() {
+        let mut rib = StateProviders::<()>::default();
+        rib.add("/rib/route-count", rib_count);
+        let mut bgp = StateProviders::<()>::default();
+        bgp.add("/bgp/global", bgp_global);
+        bgp.add("/bgp/neighbors/neighbor", bgp_neighbor);
+
+        let get = |path: &str| {
+            let path: StatePath = path.parse().unwrap();
+            let values = [rib.get(&(), &path), bgp.get(&(), &path)];
+            state_merge(values.into_iter().flatten().collect())
+        };
+        assert_eq!(
+            get("/"),
+            Some(json!({
+                "rib": {"route-count": {"total": 3, "fib": 2}},
+                "bgp": {"global": {"as": 65000}, "neighbors": {"neighbor": neighbors()}},
+            }))
+        );
+        assert_eq!(get("/bgp/global/as"), Some(json!(65000)));
+        assert_eq!(get("/rib/route-count/fib"), Some(json!(2)));
+        assert_eq!(get("/ospf"), None);
+    }
+}
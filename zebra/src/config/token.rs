@@ -20,7 +20,8 @@ pub fn tokenizer(input: String) -> Vec<Token> {
             ch if ch.is_whitespace() => {
                 continue;
             }
-            'a'..='z' | '0'..='9' => {
+            // '*' and '?' are for the interface name patterns.
+            'a'..='z' | '0'..='9' | '*' => {
                 let s: String = iter::once(ch)
                     .chain(from_fn(|| {
                         chars.by_ref().next_if(|c| {
@@ -29,6 +30,8 @@ pub fn tokenizer(input: String) -> Vec<Token> {
                                 || c == &'.'
                                 || c == &'-'
                                 || c == &'/'
+                                || c == &'*'
+                                || c == &'?'
                         })
                     }))
                     .collect();
@@ -85,4 +88,25 @@ routing {
         );
         assert_eq!(tokens.get(11).unwrap(), &Token::LeftBrace);
     }
+
+    #[test]
+    fn test_tokenizer_pattern() {
+        let config: &str = r#"
+interfaces {
+    interface eth* {
+    }
+    interface *.100 {
+    }
+    interface ge-0/0/? {
+    }
+}
+"#;
+        let tokens = tokenizer(config.to_string());
+        assert_eq!(tokens.get(3).unwrap(), &Token::String("eth*".to_string()));
+        assert_eq!(tokens.get(7).unwrap(), &Token::String("*.100".to_string()));
+        assert_eq!(
+            tokens.get(11).unwrap(),
+            &Token::String("ge-0/0/?".to_string())
+        );
+    }
 }
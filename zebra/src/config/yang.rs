@@ -0,0 +1,73 @@
+use libyang::{to_entry, Entry, YangStore};
+use std::path::Path;
+use std::rc::Rc;
+
+// YANG modules of the user loaded in addition to the built-in ones, e.g.
+// local extensions of the config. Like an augment, the top level nodes of
+// the module are merged into the config tree, "set" and "delete" of
+// configure mode, at the same path as they are in the module. The modules
+// may import the built-in modules. A node already in the built-in schema is
+// a conflict unless both of them are containers, or lists with the same
+// keys, which are merged. Nothing is loaded when there is a conflict.
+
+// Module names of the YANG files in the directory, without the revision.
+pub fn yang_user_modules(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut modules = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "yang") {
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                let name = stem.split('@').next().unwrap_or(stem);
+                modules.push(name.to_string());
+            }
+        }
+    }
+    modules.sort();
+    modules.dedup();
+    Ok(modules)
+}
+
+pub fn yang_user_read(dir: &Path, system: &str) -> anyhow::Result<Vec<Rc<Entry>>> {
+    let mut yang = YangStore::new();
+    yang.add_path(&dir.to_string_lossy());
+    yang.add_path(system);
+    let mut entries = Vec::new();
+    for name in yang_user_modules(dir)?.into_iter() {
+        yang.read_with_resolve(&name)
+            .map_err(|err| anyhow::anyhow!("{}: {}", name, err))?;
+        yang.identity_resolve();
+        let Some(module) = yang.find_module(&name) else {
+            anyhow::bail!("{}: module not found in {}", name, dir.display());
+        };
+        entries.push(to_entry(&yang, module));
+    }
+    Ok(entries)
+}
+
+fn entry_mergeable(dst: &Entry, src: &Entry) -> bool {
+    dst.is_directory_entry() && src.is_directory_entry() && dst.key == src.key
+}
+
+// Paths of the nodes of `src` conflicting with the nodes of `dst`.
+pub fn entry_conflicts(dst: &Entry, src: &Entry, path: &str, conflicts: &mut Vec<String>) {
+    for s in src.dir.borrow().iter() {
+        let path = format!("{}/{}", path, s.name);
+        if let Some(d) = dst.dir.borrow().iter().find(|d| d.name == s.name) {
+            if entry_mergeable(d, s) {
+                entry_conflicts(d, s, &path, conflicts);
+            } else {
+                conflicts.push(path);
+            }
+        }
+    }
+}
+
+pub fn entry_merge(dst: &Entry, src: &Entry) {
+    for s in src.dir.borrow().iter() {
+        let d = dst.dir.borrow().iter().find(|d| d.name == s.name).cloned();
+        match d {
+            Some(d) => entry_merge(&d, s),
+            None => dst.dir.borrow_mut().push(s.clone()),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::oneshot;
+
+// Ordered shutdown on SIGTERM or SIGINT. The protocols are stopped first so
+// that they say goodbye to their neighbors, and then the RIB, which flushes
+// or retains the routes in the FIB. Each module is sent the exit request
+// with a channel to report that it is done, and is waited for up to the
+// timeout so that a stuck module does not prevent the exit.
+
+pub const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type ExitTx = Sender<oneshot::Sender<()>>;
+pub type ExitRx = Receiver<oneshot::Sender<()>>;
+
+pub struct ExitChannel {
+    pub tx: ExitTx,
+    pub rx: ExitRx,
+}
+
+impl ExitChannel {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(1);
+        Self { tx, rx }
+    }
+}
+
+// Request the exit of the module and wait for it. Returns false on timeout.
+// The module which is already gone is done.
+pub async fn exit_wait(tx: &ExitTx, timeout: Duration) -> bool {
+    let (done_tx, done_rx) = oneshot::channel();
+    if tx.send(done_tx).await.is_err() {
+        return true;
+    }
+    tokio::time::timeout(timeout, done_rx).await.is_ok()
+}
+
+// Exit the modules in the order. Returns the name of the modules timed out.
+pub async fn exit_sequence(modules: &[(&str, ExitTx)], timeout: Duration) -> Vec<String> {
+    let mut timed_out = Vec::new();
+    for (name, tx) in modules.iter() {
+        if !exit_wait(tx, timeout).await {
+            println!("zebra: {} did not exit in {:?}", name, timeout);
+            timed_out.push(name.to_string());
+        }
+    }
+    timed_out
+}
+
+pub async fn exit_signal() {
+    let Ok(mut term) = signal(SignalKind::terminate()) else {
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Module which records its exit and answers after the delay, or never.
+    fn module(name: &'static str, delay: Option<u64>, log: Arc<Mutex<Vec<&str>>>) -> ExitTx {
+        let ExitChannel { tx, mut rx } = ExitChannel::new();
+        tokio::spawn(async move {
+            let done = rx.recv().await.unwrap();
+            log.lock().unwrap().push(name);
+            let Some(delay) = delay else {
+                // Stuck, the request is held.
+                std::future::pending::<()>().await;
+                return;
+            };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            let _ = done.send(());
+        });
+        tx
+    }
+
+    #[tokio::test]
+    async fn order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let modules = [
+            ("bgp", module("bgp", Some(20), log.clone())),
+            ("rib", module("rib", Some(0), log.clone())),
+        ];
+        let timed_out = exit_sequence(&modules, EXIT_TIMEOUT).await;
+        assert!(timed_out.is_empty());
+        // RIB is requested after BGP is done.
+        assert_eq!(*log.lock().unwrap(), vec!["bgp", "rib"]);
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let modules = [
+            ("bgp", module("bgp", None, log.clone())),
+            ("rib", module("rib", Some(0), log.clone())),
+        ];
+        let start = std::time::Instant::now();
+        let timed_out = exit_sequence(&modules, Duration::from_millis(50)).await;
+        assert_eq!(timed_out, vec!["bgp"]);
+        // Stuck BGP does not keep the RIB from the exit.
+        assert_eq!(*log.lock().unwrap(), vec!["bgp", "rib"]);
+        assert!(start.elapsed() < EXIT_TIMEOUT);
+
+        // Module already gone.
+        let ExitChannel { tx, rx } = ExitChannel::new();
+        drop(rx);
+        assert!(exit_wait(&tx, Duration::from_millis(50)).await);
+    }
+}
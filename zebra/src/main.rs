@@ -7,14 +7,26 @@ mod bgp;
 use bgp::Bgp;
 mod rib;
 use rib::Rib;
+mod exit;
 mod policy;
 use clap::Parser;
+use exit::{exit_sequence, exit_signal, ExitChannel, EXIT_TIMEOUT};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Arg {
     #[arg(short, long, help = "YANG load path", default_value = "")]
     yang_path: String,
+    #[arg(long, help = "YANG modules of the user merged into the config")]
+    yang_user_path: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Number of commits kept in the commit history",
+        default_value_t = 50
+    )]
+    commit_history: usize,
+    #[arg(long, help = "Retain the routes in the FIB on exit")]
+    retain_routes_on_exit: bool,
 }
 
 fn system_path(arg: &Arg) -> PathBuf {
@@ -47,27 +59,49 @@ async fn main() -> anyhow::Result<()> {
     let arg = Arg::parse();
 
     let mut rib = Rib::new()?;
+    rib.retain_routes_default = arg.retain_routes_on_exit;
 
     let bgp = Bgp::new(rib.api.tx.clone());
     rib.subscribe(bgp.redist.tx.clone());
 
     let mut config = ConfigManager::new(system_path(&arg))?;
+    config.commits.borrow_mut().set_max(arg.commit_history);
+    if let Some(dir) = arg.yang_user_path.as_ref() {
+        config.yang_user_load(dir)?;
+    }
     config.subscribe("rib", rib.cm.tx.clone());
     config.subscribe("bgp", bgp.cm.tx.clone());
+    bgp::config::validator_build(&mut config);
 
     let mut cli = Cli::new(config.tx.clone());
+    config.subscribe("alias", cli.alias_cm.tx.clone());
     cli.subscribe("rib", rib.show.tx.clone());
     cli.subscribe("bgp", bgp.show.tx.clone());
+    cli.subscribe_state("rib", rib.state.tx.clone());
+    cli.subscribe_state("bgp", bgp.state.tx.clone());
 
     config::serve(cli);
 
-    bgp::serve(bgp);
+    // Protocols exit first, then the RIB.
+    let bgp_exit = ExitChannel::new();
+    let rib_exit = ExitChannel::new();
+    let exits = [("bgp", bgp_exit.tx), ("rib", rib_exit.tx)];
+
+    bgp::serve(bgp, bgp_exit.rx);
+
+    rib::api_serve(rib.api.tx.clone());
 
-    rib::serve(rib);
+    rib::serve(rib, rib_exit.rx);
 
     println!("zebra: started");
 
-    config::event_loop(config).await;
+    tokio::select! {
+        _ = config::event_loop(config) => {}
+        _ = exit_signal() => {
+            exit_sequence(&exits, EXIT_TIMEOUT).await;
+            println!("zebra: exited");
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,140 @@
+use regex::Regex;
+
+// AS path regular expression set. Patterns are matched against the AS path
+// rendered in the standard form, e.g. "100 200 {300,400} (65001 65002)".
+//
+// As in other BGP implementations '_' matches a delimiter of the rendered
+// path: space, the braces/parentheses of AS_SET and confederation segments,
+// or the beginning/end of the path.
+
+const ASPATH_DELIMITER: &str = "(?:^|[ ,{}()\\[\\]]|$)";
+
+#[derive(Debug)]
+pub struct AsPathMember {
+    pub pattern: String,
+    pub regex: Regex,
+}
+
+#[derive(Debug, Default)]
+pub struct AsPathSet {
+    pub members: Vec<AsPathMember>,
+}
+
+pub fn aspath_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&pattern.replace('_', ASPATH_DELIMITER))
+}
+
+impl AsPathSet {
+    // Compile the pattern and add it to the set. Compile error is returned to
+    // the caller so that it can be reported at commit.
+    pub fn insert(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        if self.members.iter().any(|m| m.pattern == pattern) {
+            return Ok(());
+        }
+        let regex = aspath_regex(pattern)?;
+        self.members.push(AsPathMember {
+            pattern: pattern.to_string(),
+            regex,
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, pattern: &str) {
+        self.members.retain(|m| m.pattern != pattern);
+    }
+
+    // The set matches when any of the patterns matches.
+    pub fn matches(&self, aspath: &str) -> bool {
+        self.members.iter().any(|m| m.regex.is_match(aspath))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bgp::packet::{
+        As4PathAttr, As4Segment, AS_CONFED_SEQUENCE, AS_CONFED_SET, AS_SEQUENCE, AS_SET,
+    };
+
+    fn set(patterns: &[&str]) -> AsPathSet {
+        let mut set = AsPathSet::default();
+        for pattern in patterns.iter() {
+            set.insert(pattern).unwrap();
+        }
+        set
+    }
+
+    #[test]
+    fn anchor() {
+        let origin = set(&["_100$"]);
+        assert!(origin.matches("300 200 100"));
+        assert!(!origin.matches("100 200 300"));
+
+        let neighbor = set(&["^100_"]);
+        assert!(neighbor.matches("100 200 300"));
+        assert!(!neighbor.matches("1100 200 300"));
+
+        let local = set(&["^$"]);
+        assert!(local.matches(""));
+        assert!(!local.matches("100"));
+
+        // Without delimiter the pattern matches a part of AS number.
+        let partial = set(&["100"]);
+        assert!(partial.matches("1000"));
+        let exact = set(&["_100_"]);
+        assert!(!exact.matches("1000 2100"));
+        assert!(exact.matches("1000 100 2100"));
+    }
+
+    #[test]
+    fn as4() {
+        let set = set(&["_4200000000_", "^6553[6-9]_"]);
+        assert!(set.matches("100 4200000000 200"));
+        assert!(!set.matches("100 420000000 200"));
+        assert!(set.matches("65537 100"));
+        assert!(!set.matches("65535 100"));
+    }
+
+    #[test]
+    fn confed_set() {
+        let path = As4PathAttr {
+            segments: vec![
+                As4Segment {
+                    typ: AS_CONFED_SEQUENCE,
+                    asn: vec![65001, 65002],
+                },
+                As4Segment {
+                    typ: AS_CONFED_SET,
+                    asn: vec![65003, 65004],
+                },
+                As4Segment {
+                    typ: AS_SEQUENCE,
+                    asn: vec![100, 4200000000],
+                },
+                As4Segment {
+                    typ: AS_SET,
+                    asn: vec![300, 400],
+                },
+            ],
+        };
+        let rendered = path.to_string();
+        assert_eq!(
+            rendered,
+            "(65001 65002) [65003,65004] 100 4200000000 {300,400}"
+        );
+
+        assert!(set(&["^\\(65001_"]).matches(&rendered));
+        assert!(set(&["_65002\\)"]).matches(&rendered));
+        assert!(set(&["_65004_"]).matches(&rendered));
+        assert!(set(&["_300_"]).matches(&rendered));
+        assert!(set(&["\\{300,400\\}$"]).matches(&rendered));
+        assert!(set(&["_100_4200000000_"]).matches(&rendered));
+    }
+
+    #[test]
+    fn compile_error() {
+        let mut set = AsPathSet::default();
+        assert!(set.insert("_100(").is_err());
+        assert!(set.members.is_empty());
+    }
+}
@@ -1,2 +1,8 @@
+pub mod aspath;
+pub use aspath::*;
+
 pub mod clist;
 pub use clist::*;
+
+pub mod prefix;
+pub use prefix::*;
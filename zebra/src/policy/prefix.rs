@@ -0,0 +1,303 @@
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+// Prefix set. An entry matches a route prefix covered by the entry prefix
+// whose length is within one of the masklength ranges of the entry, e.g.
+// 10.0.0.0/8 with "8..24" is "10.0.0.0/8 le 24" and with "25..32" it is
+// "10.0.0.0/8 ge 25". An entry without a range matches the prefix exactly.
+//
+// Entries are kept in a prefix trie keyed by the entry prefix. A route prefix
+// is looked up with the longest match and then the shorter covering entries,
+// so that the cost of the lookup depends on the prefix length rather than on
+// the size of the set. Entries are added and removed in place.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MaskLengthRange {
+    Exact,
+    OrLonger,
+    Range(u8, u8),
+}
+
+impl MaskLengthRange {
+    // Prefix lengths matched by the range of an entry prefix of the length.
+    pub fn bounds(&self, len: u8) -> (u8, u8) {
+        match self {
+            Self::Exact => (len, len),
+            Self::OrLonger => (len, 32),
+            Self::Range(min, max) => (*min.max(&len), *max),
+        }
+    }
+}
+
+impl FromStr for MaskLengthRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "orlonger" => Ok(Self::OrLonger),
+            _ => {
+                let (min, max) = s
+                    .split_once("..")
+                    .ok_or_else(|| format!("invalid masklength-range {}", s))?;
+                let min: u8 = min.parse().map_err(|_| format!("invalid length {}", min))?;
+                let max: u8 = max.parse().map_err(|_| format!("invalid length {}", max))?;
+                if min > max || max > 32 {
+                    return Err(format!("invalid masklength-range {}", s));
+                }
+                Ok(Self::Range(min, max))
+            }
+        }
+    }
+}
+
+impl fmt::Display for MaskLengthRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact => write!(f, "exact"),
+            Self::OrLonger => write!(f, "orlonger"),
+            Self::Range(min, max) => write!(f, "{}..{}", min, max),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PrefixSetEntry {
+    pub ranges: BTreeSet<MaskLengthRange>,
+    pub hits: u64,
+}
+
+impl PrefixSetEntry {
+    // Compiled form of the ranges, the matched prefix lengths.
+    pub fn bounds(&self, len: u8) -> Vec<(u8, u8)> {
+        if self.ranges.is_empty() {
+            return vec![(len, len)];
+        }
+        self.ranges.iter().map(|range| range.bounds(len)).collect()
+    }
+
+    fn contains(&self, len: u8, route_len: u8) -> bool {
+        self.bounds(len)
+            .iter()
+            .any(|(min, max)| *min <= route_len && route_len <= *max)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PrefixSet {
+    pub entries: PrefixMap<Ipv4Net, PrefixSetEntry>,
+}
+
+impl PrefixSet {
+    pub fn insert(&mut self, prefix: Ipv4Net) {
+        self.entries.entry(prefix.trunc()).or_default();
+    }
+
+    pub fn remove(&mut self, prefix: Ipv4Net) {
+        self.entries.remove(&prefix.trunc());
+    }
+
+    // Range which can never match the entry prefix, e.g. "8..16" of a /24,
+    // is returned as an error so that it can be reported at commit.
+    pub fn range_insert(&mut self, prefix: Ipv4Net, range: MaskLengthRange) -> Result<(), String> {
+        let prefix = prefix.trunc();
+        let (_, max) = range.bounds(prefix.prefix_len());
+        if max < prefix.prefix_len() {
+            return Err(format!("{} is shorter than {}", range, prefix));
+        }
+        self.entries.entry(prefix).or_default().ranges.insert(range);
+        Ok(())
+    }
+
+    pub fn range_remove(&mut self, prefix: Ipv4Net, range: MaskLengthRange) {
+        if let Some(entry) = self.entries.get_mut(&prefix.trunc()) {
+            entry.ranges.remove(&range);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().next().is_none()
+    }
+
+    // Most specific entry which matches the route prefix. Only the entries
+    // covering the prefix are visited.
+    pub fn lookup(&self, prefix: &Ipv4Net) -> Option<Ipv4Net> {
+        let prefix = prefix.trunc();
+        let mut key = Some(prefix);
+        while let Some(net) = key {
+            let (entry_prefix, entry) = self.entries.get_lpm(&net)?;
+            if entry.contains(entry_prefix.prefix_len(), prefix.prefix_len()) {
+                return Some(*entry_prefix);
+            }
+            key = entry_prefix.supernet();
+        }
+        None
+    }
+
+    pub fn matches(&mut self, prefix: &Ipv4Net) -> bool {
+        let Some(entry_prefix) = self.lookup(prefix) else {
+            return false;
+        };
+        if let Some(entry) = self.entries.get_mut(&entry_prefix) {
+            entry.hits += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    fn net(s: &str) -> Ipv4Net {
+        s.parse().unwrap()
+    }
+
+    fn prefix_set(entries: &[(&str, &[&str])]) -> PrefixSet {
+        let mut set = PrefixSet::default();
+        for (prefix, ranges) in entries.iter() {
+            set.insert(net(prefix));
+            for range in ranges.iter() {
+                set.range_insert(net(prefix), range.parse().unwrap())
+                    .unwrap();
+            }
+        }
+        set
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!("exact".parse(), Ok(MaskLengthRange::Exact));
+        assert_eq!("orlonger".parse(), Ok(MaskLengthRange::OrLonger));
+        assert_eq!("21..24".parse(), Ok(MaskLengthRange::Range(21, 24)));
+        assert!("24..21".parse::<MaskLengthRange>().is_err());
+        assert!("24..33".parse::<MaskLengthRange>().is_err());
+        assert!("24".parse::<MaskLengthRange>().is_err());
+        assert_eq!(MaskLengthRange::Range(21, 24).to_string(), "21..24");
+
+        let mut set = PrefixSet::default();
+        assert!(set
+            .range_insert(net("10.0.0.0/24"), MaskLengthRange::Range(8, 16))
+            .is_err());
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn exact() {
+        let mut set = prefix_set(&[("10.0.0.0/8", &[]), ("192.0.2.0/24", &["exact"])]);
+        assert!(set.matches(&net("10.0.0.0/8")));
+        assert!(!set.matches(&net("10.1.0.0/16")));
+        assert!(!set.matches(&net("10.0.0.0/7")));
+        assert!(set.matches(&net("192.0.2.0/24")));
+        assert!(!set.matches(&net("192.0.2.128/25")));
+        // Host bits of the route are ignored.
+        assert!(set.matches(&net("10.1.2.3/8")));
+        assert_eq!(set.entries.get(&net("10.0.0.0/8")).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn le_ge() {
+        // 10.0.0.0/8 le 24 and 172.16.0.0/12 ge 25.
+        let mut set = prefix_set(&[
+            ("10.0.0.0/8", &["8..24"]),
+            ("172.16.0.0/12", &["25..32"]),
+            ("192.168.0.0/16", &["orlonger"]),
+        ]);
+        assert!(set.matches(&net("10.0.0.0/8")));
+        assert!(set.matches(&net("10.1.0.0/16")));
+        assert!(set.matches(&net("10.1.2.0/24")));
+        assert!(!set.matches(&net("10.1.2.0/25")));
+
+        assert!(!set.matches(&net("172.16.0.0/12")));
+        assert!(!set.matches(&net("172.16.1.0/24")));
+        assert!(set.matches(&net("172.16.1.0/25")));
+        assert!(set.matches(&net("172.31.1.1/32")));
+        assert!(!set.matches(&net("172.32.1.0/25")));
+
+        assert!(set.matches(&net("192.168.0.0/16")));
+        assert!(set.matches(&net("192.168.1.1/32")));
+        assert!(!set.matches(&net("192.169.0.0/24")));
+
+        // Ranges are added and removed in place.
+        set.range_insert(net("10.0.0.0/8"), MaskLengthRange::Range(30, 32))
+            .unwrap();
+        assert!(set.matches(&net("10.1.2.0/30")));
+        set.range_remove(net("10.0.0.0/8"), MaskLengthRange::Range(8, 24));
+        assert!(!set.matches(&net("10.1.0.0/16")));
+        // Without any range the entry is exact.
+        set.range_remove(net("10.0.0.0/8"), MaskLengthRange::Range(30, 32));
+        assert!(set.matches(&net("10.0.0.0/8")));
+        assert!(!set.matches(&net("10.1.2.0/30")));
+        set.remove(net("10.0.0.0/8"));
+        assert!(!set.matches(&net("10.0.0.0/8")));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn covering() {
+        // The more specific entry does not match the route, the covering
+        // one does.
+        let set = prefix_set(&[("10.0.0.0/8", &["orlonger"]), ("10.1.0.0/16", &["exact"])]);
+        assert_eq!(set.lookup(&net("10.1.0.0/16")), Some(net("10.1.0.0/16")));
+        assert_eq!(set.lookup(&net("10.1.1.0/24")), Some(net("10.0.0.0/8")));
+
+        // A covered entry never matches the covering route.
+        let set = prefix_set(&[("10.1.0.0/16", &["orlonger"])]);
+        assert_eq!(set.lookup(&net("10.0.0.0/8")), None);
+        assert_eq!(set.lookup(&net("0.0.0.0/0")), None);
+        assert_eq!(set.lookup(&net("10.1.255.0/24")), Some(net("10.1.0.0/16")));
+
+        let set = prefix_set(&[("0.0.0.0/0", &["exact"]), ("0.0.0.0/0", &["8..24"])]);
+        assert_eq!(set.lookup(&net("0.0.0.0/0")), Some(net("0.0.0.0/0")));
+        assert_eq!(set.lookup(&net("10.0.0.0/8")), Some(net("0.0.0.0/0")));
+        assert_eq!(set.lookup(&net("10.0.0.0/25")), None);
+    }
+
+    fn large(count: u32) -> PrefixSet {
+        let mut set = PrefixSet::default();
+        for i in 0..count {
+            let prefix = Ipv4Net::new((0x0a00_0000 + (i << 8)).into(), 24).unwrap();
+            set.insert(prefix);
+        }
+        set
+    }
+
+    // Lookup cost is bounded by the prefix length, a set a hundred times
+    // larger is not a hundred times slower.
+    #[test]
+    fn sublinear() {
+        let lookup = |set: &PrefixSet| {
+            let start = Instant::now();
+            let mut found = 0;
+            for i in 0..100_000u32 {
+                let prefix = Ipv4Net::new((0x0a00_0000 + (i << 8)).into(), 24).unwrap();
+                if set.lookup(&prefix).is_some() {
+                    found += 1;
+                }
+            }
+            (found, start.elapsed())
+        };
+        let small = large(500);
+        let big = large(50_000);
+        assert_eq!(big.len(), 50_000);
+
+        let (found, small_time) = lookup(&small);
+        assert_eq!(found, 500);
+        let (found, big_time) = lookup(&big);
+        assert_eq!(found, 50_000);
+        assert!(
+            big_time < small_time * 20,
+            "{:?} {:?}",
+            big_time,
+            small_time
+        );
+    }
+}
@@ -1,4 +1,11 @@
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use super::entry::RibEntry;
+use super::inject::ApiRoute;
+use super::vrf::VpnRoute;
+use super::watch::RouteWatch;
+use ipnet::Ipv4Net;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
 
 #[derive(Debug)]
 pub struct RibTxChannel {
@@ -15,30 +22,76 @@ impl RibTxChannel {
 
 // Message from protocol module to rib.
 #[allow(dead_code)]
+#[derive(Debug)]
 pub enum RibTx {
-    RouteAdd(),
-    RouteDel(),
-    NexthopResgister(),
-    NexthopUnresgister(),
+    // Route of the protocol with its route type and metric.
+    RouteAdd(Ipv4Net, RibEntry),
+    RouteDel(Ipv4Net, RibEntry),
+    NexthopRegister(Ipv4Addr),
+    NexthopUnregister(Ipv4Addr),
+    ApiRouteAdd(ApiRoute),
+    ApiRouteDel(ApiRoute),
+    ApiOwnerDown {
+        owner: String,
+        persist: bool,
+        sweep: Option<Duration>,
+    },
+    VpnRouteAdd(VpnRoute),
+    VpnRouteDel(VpnRoute),
+    PrefixRegister(Ipv4Net),
+    PrefixUnregister(Ipv4Net),
+    Watch(RouteWatch),
 }
 
+// Channel from rib to protocol module. Unbounded so that the rib never waits
+// for a protocol, which may be waiting for the rib to receive its messages.
 pub struct RibRxChannel {
-    pub tx: Sender<RibRx>,
-    pub rx: Receiver<RibRx>,
+    pub tx: UnboundedSender<RibRx>,
+    pub rx: UnboundedReceiver<RibRx>,
 }
 
 impl RibRxChannel {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel(4);
+        let (tx, rx) = mpsc::unbounded_channel();
         Self { tx, rx }
     }
 }
 
+pub fn rib_notify(redists: &[UnboundedSender<RibRx>], msg: RibRx) {
+    for tx in redists.iter() {
+        let _ = tx.send(msg.clone());
+    }
+}
+
 // Message from rib to protocol module.
 #[allow(dead_code)]
+#[derive(Clone)]
 pub enum RibRx {
     RedistAdd(),
     RedistDel(),
     Link(),
-    Nexthop(),
+    Nexthop(NexthopUpdate),
+    Prefix(PrefixUpdate),
+    // Router ID selected by the RIB.
+    RouterId(Ipv4Addr),
+    // Route of a VRF exported to VPN.
+    VpnRouteAdd(VpnRoute),
+    VpnRouteDel(VpnRoute),
+}
+
+// Resolution result of a registered nexthop. `resolved` is the prefix of the
+// route resolving the nexthop, None when the nexthop is unreachable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NexthopUpdate {
+    pub addr: Ipv4Addr,
+    pub resolved: Option<Ipv4Net>,
+    pub metric: u32,
+}
+
+// Longest selected route covering a registered prefix, the prefix itself
+// included. None when no route covers the prefix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixUpdate {
+    pub prefix: Ipv4Net,
+    pub covering: Option<Ipv4Net>,
 }
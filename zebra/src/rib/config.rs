@@ -1,43 +1,52 @@
 use super::{
-    entry::{RibEntry, RibType},
-    instance::Rib,
+    distance::distance_config, ecmp::ecmp_config, exit::exit_config, instance::Rib,
+    nd_proxy::nd_proxy_config, neigh::neigh_config, ra::ra_config, restart::restart_config,
+    router_id::router_id_config, rule::rule_config, static_route::static_config,
+    stats::stats_config, table::table_config, vrf::vrf_config,
 };
 use crate::config::{Args, ConfigOp};
-use ipnet::Ipv4Net;
-use std::net::{IpAddr, Ipv4Addr};
 
 pub async fn config_dispatch(rib: &mut Rib, path: String, args: Args, op: ConfigOp) {
-    if path == "/routing/static/route" {
-        static_route(rib, args.clone(), op.clone()).await;
+    if path.starts_with("/routing/static/route") {
+        static_config(rib, &path, args.clone(), op.clone()).await;
     }
-    if path == "/routing/static/route/nexthop" {
-        static_route_nexthop(rib, args.clone(), op.clone()).await;
+    if path.starts_with("/routing/static/table") {
+        table_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/routing/distance") {
+        distance_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/routing/maximum-paths") {
+        ecmp_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/routing/retain-routes-on-exit") {
+        exit_config(rib, &path, args.clone(), op.clone());
+    }
+    if path.starts_with("/routing/graceful-restart") {
+        restart_config(rib, &path, args.clone(), op.clone());
+    }
+    if path.starts_with("/interfaces/interface/ipv6/router-advertisements") {
+        ra_config(rib, &path, args.clone(), op.clone());
+    }
+    if path.starts_with("/interfaces/interface/ipv6/nd-proxy") {
+        nd_proxy_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/interfaces/statistics") {
+        stats_config(rib, &path, args.clone(), op.clone());
+    }
+    if path.starts_with("/routing/router-id") {
+        router_id_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/routing/rule") {
+        rule_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/routing/neighbor") {
+        neigh_config(rib, &path, args.clone(), op.clone()).await;
+    }
+    if path.starts_with("/vrf") && vrf_config(rib, &path, args.clone(), op.clone()).is_some() {
+        rib.rule_vrf_update().await;
     }
     // if let Some(f) = self.callbacks.get(&path) {
     //     f(self, args, msg.op);
     // }
 }
-
-async fn static_route(_rib: &mut Rib, args: Args, op: ConfigOp) {
-    if op == ConfigOp::Set && !args.is_empty() {
-        // let asn_str = &args[0];
-        // bgp.asn = asn_str.parse().unwrap();
-    }
-}
-
-async fn static_route_nexthop(rib: &mut Rib, mut args: Args, op: ConfigOp) -> Option<()> {
-    if op == ConfigOp::Set && args.len() > 1 {
-        let dest: Ipv4Net = args.v4net()?;
-        let gateway: Ipv4Addr = args.v4addr()?;
-        //
-        let mut entry = RibEntry::new(RibType::Static);
-        entry.gateway = IpAddr::V4(gateway);
-        // XXX rib.rib.insert(dest, entry);
-
-        rib.fib_handle.route_ipv4_add(dest, gateway).await;
-        // if let Some(handle) = rib.handle.as_ref() {
-        //     route_add(handle.clone(), dest, gateway).await;
-        // }
-    }
-    Some(())
-}
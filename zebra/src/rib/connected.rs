@@ -0,0 +1,257 @@
+use super::entry::{RibEntry, RibType};
+use super::inject::rib_select;
+use super::instance::Rib;
+use super::link::Link;
+use ipnet::{IpNet, Ipv4Net};
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Connected routes of the IPv4 interface addresses. A connected route is
+// installed for each interface with an address in the subnet, and stays while
+// any of the addresses is left, so that removing a secondary address in the
+// same subnet keeps the route. Changes of the addresses are applied once the
+// queued FIB messages have been processed, so that an address moved between
+// interfaces is a nexthop change of the connected route rather than a
+// withdrawal and re-announcement.
+
+// Prefix of the connected route. A /31 is a point-to-point subnet with both
+// addresses usable (RFC 3021) and a /32 is installed as a host route, neither
+// of them has a network or broadcast address.
+pub fn connected_prefix(addr: Ipv4Net) -> Ipv4Net {
+    addr.trunc()
+}
+
+// Number of addresses of each interface contributing to the prefix.
+pub fn connected_refs(links: &BTreeMap<u32, Link>, prefix: Ipv4Net) -> BTreeMap<u32, usize> {
+    let mut refs = BTreeMap::new();
+    for link in links.values() {
+        for addr in link.addr4.iter() {
+            if let IpNet::V4(net) = addr.addr {
+                if connected_prefix(net) == prefix {
+                    *refs.entry(link.index).or_default() += 1;
+                }
+            }
+        }
+    }
+    refs
+}
+
+fn connected_entry(link_index: u32) -> RibEntry {
+    let mut e = RibEntry::new(RibType::Connected);
+    e.link_index = link_index;
+    e.distance = 0;
+    e.selected = true;
+    e.fib = true;
+    e
+}
+
+// Bring the connected entries in sync with the interfaces contributing to the
+// prefix. An entry of an interface which has gone is moved to the interface
+// which has appeared. Returns true when the entries have been changed.
+pub fn connected_sync(entries: &mut Vec<RibEntry>, links: &BTreeSet<u32>) -> bool {
+    let current: BTreeSet<u32> = entries
+        .iter()
+        .filter(|e| e.rtype == RibType::Connected)
+        .map(|e| e.link_index)
+        .collect();
+    let mut added: Vec<u32> = links.difference(&current).cloned().collect();
+    let removed: BTreeSet<u32> = current.difference(links).cloned().collect();
+    if added.is_empty() && removed.is_empty() {
+        return false;
+    }
+    entries.retain_mut(|e| {
+        if e.rtype != RibType::Connected || !removed.contains(&e.link_index) {
+            return true;
+        }
+        match added.pop() {
+            Some(link_index) => {
+                e.link_index = link_index;
+                true
+            }
+            None => false,
+        }
+    });
+    for link_index in added.into_iter() {
+        entries.push(connected_entry(link_index));
+    }
+    true
+}
+
+fn connected_table_sync(
+    rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    prefix: Ipv4Net,
+    links: &BTreeSet<u32>,
+) -> bool {
+    if links.is_empty() && !rib.contains_key(&prefix) {
+        return false;
+    }
+    let entries = rib.entry(prefix).or_default();
+    let changed = connected_sync(entries, links);
+    if entries.is_empty() {
+        rib.remove(&prefix);
+    }
+    changed
+}
+
+impl Rib {
+    // Connected prefix of the address has to be updated.
+    pub fn connected_touch(&mut self, addr: Ipv4Net) {
+        self.connected_dirty.insert(connected_prefix(addr));
+    }
+
+    // Install the connected routes to the global table or to the table of
+    // the VRF which the interface is bound to.
+    pub fn connected_update(&mut self) {
+        let mut vrf_changed = false;
+        for prefix in std::mem::take(&mut self.connected_dirty).into_iter() {
+            let mut tables: BTreeMap<Option<String>, BTreeSet<u32>> = BTreeMap::new();
+            for link_index in connected_refs(&self.links, prefix).into_keys() {
+                let vrf = self
+                    .link_name(link_index)
+                    .and_then(|name| self.vrf_by_link(name));
+                tables.entry(vrf).or_default().insert(link_index);
+            }
+            let links = tables.remove(&None).unwrap_or_default();
+            connected_table_sync(&mut self.rib, prefix, &links);
+            self.watchers.touch(prefix);
+            for (name, vrf) in self.vrfs.iter_mut() {
+                let links = tables.remove(&Some(name.clone())).unwrap_or_default();
+                if connected_table_sync(&mut vrf.rib, prefix, &links) {
+                    if let Some(entries) = vrf.rib.get_mut(&prefix) {
+                        rib_select(entries);
+                    }
+                    vrf_changed = true;
+                }
+            }
+        }
+        if vrf_changed {
+            self.vrf_refresh();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::fib::message::FibLink;
+    use crate::rib::link::{link_addr_del, link_addr_update, LinkAddr};
+
+    fn link(index: u32, name: &str) -> Link {
+        let mut link = FibLink::new();
+        link.index = index;
+        link.name = name.to_string();
+        Link::from(link)
+    }
+
+    fn addr(link_index: u32, addr: &str) -> LinkAddr {
+        LinkAddr {
+            addr: addr.parse().unwrap(),
+            link_index,
+            ..Default::default()
+        }
+    }
+
+    fn net(s: &str) -> Ipv4Net {
+        s.parse().unwrap()
+    }
+
+    fn sync(links: &BTreeMap<u32, Link>, entries: &mut Vec<RibEntry>, prefix: &str) -> bool {
+        let refs = connected_refs(links, net(prefix));
+        connected_sync(entries, &refs.into_keys().collect())
+    }
+
+    fn connected(entries: &[RibEntry]) -> Vec<u32> {
+        entries
+            .iter()
+            .filter(|e| e.rtype == RibType::Connected)
+            .map(|e| e.link_index)
+            .collect()
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(connected_prefix(net("10.0.0.1/24")), net("10.0.0.0/24"));
+        // Both addresses of a /31 belong to the same point-to-point subnet.
+        assert_eq!(connected_prefix(net("10.0.1.0/31")), net("10.0.1.0/31"));
+        assert_eq!(connected_prefix(net("10.0.1.1/31")), net("10.0.1.0/31"));
+        // Host route only.
+        assert_eq!(connected_prefix(net("192.0.2.1/32")), net("192.0.2.1/32"));
+
+        let mut links = BTreeMap::new();
+        let mut eth0 = link(2, "eth0");
+        link_addr_update(&mut eth0, addr(2, "10.0.1.0/31"));
+        link_addr_update(&mut eth0, addr(2, "192.0.2.1/32"));
+        links.insert(2, eth0);
+        assert_eq!(
+            connected_refs(&links, net("10.0.1.0/31")),
+            BTreeMap::from([(2, 1)])
+        );
+        assert_eq!(
+            connected_refs(&links, net("192.0.2.1/32")),
+            BTreeMap::from([(2, 1)])
+        );
+        assert!(connected_refs(&links, net("192.0.2.0/24")).is_empty());
+    }
+
+    #[test]
+    fn secondary() {
+        let mut links = BTreeMap::new();
+        let mut eth0 = link(2, "eth0");
+        link_addr_update(&mut eth0, addr(2, "10.0.0.1/24"));
+        let mut secondary = addr(2, "10.0.0.2/24");
+        secondary.secondary = true;
+        link_addr_update(&mut eth0, secondary.clone());
+        links.insert(2, eth0);
+        assert_eq!(
+            connected_refs(&links, net("10.0.0.0/24")),
+            BTreeMap::from([(2, 2)])
+        );
+
+        let mut entries = Vec::new();
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(connected(&entries), vec![2]);
+
+        // Primary address still covers the subnet.
+        link_addr_del(links.get_mut(&2).unwrap(), secondary);
+        assert!(!sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(connected(&entries), vec![2]);
+
+        link_addr_del(links.get_mut(&2).unwrap(), addr(2, "10.0.0.1/24"));
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn moved() {
+        let mut links = BTreeMap::new();
+        links.insert(2, link(2, "eth0"));
+        links.insert(3, link(3, "eth1"));
+        link_addr_update(links.get_mut(&2).unwrap(), addr(2, "10.0.0.1/24"));
+
+        let mut kernel = RibEntry::new(RibType::Kernel);
+        kernel.distance = 0;
+        let mut entries = vec![kernel.clone()];
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(connected(&entries), vec![2]);
+
+        // Deleted from eth0 and added to eth1 together. The entry is kept
+        // with the new interface.
+        link_addr_del(links.get_mut(&2).unwrap(), addr(2, "10.0.0.1/24"));
+        link_addr_update(links.get_mut(&3).unwrap(), addr(3, "10.0.0.1/24"));
+        let before = entries.len();
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(entries.len(), before);
+        assert_eq!(entries[0], kernel);
+        assert_eq!(connected(&entries), vec![3]);
+        assert!(entries[1].selected && entries[1].fib);
+
+        // Added to eth0 before it is deleted from eth1, the route is never
+        // withdrawn.
+        link_addr_update(links.get_mut(&2).unwrap(), addr(2, "10.0.0.1/24"));
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(connected(&entries), vec![3, 2]);
+        link_addr_del(links.get_mut(&3).unwrap(), addr(3, "10.0.0.1/24"));
+        assert!(sync(&links, &mut entries, "10.0.0.0/24"));
+        assert_eq!(connected(&entries), vec![2]);
+    }
+}
@@ -0,0 +1,273 @@
+use super::entry::{RibEntry, RibSubType, RibType};
+use super::inject::rib_select;
+use super::instance::Rib;
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::BTreeMap;
+
+// Administrative distance of the route types of the protocols. Each route
+// type within a protocol has its own distance, so that the preference of the
+// protocol, e.g. OSPF intra area over inter area over external, is kept in
+// the selection against the routes of the other protocols. The routes of the
+// protocols are installed with their route type and the metric, and the
+// distance is given by the RIB from the table below or the configured one.
+
+const DISTANCES: &[(&str, RibType, RibSubType, u32)] = &[
+    (
+        "ospf-intra-area",
+        RibType::OSPF,
+        RibSubType::NotApplicable,
+        110,
+    ),
+    ("ospf-inter-area", RibType::OSPF, RibSubType::OSPF_IA, 111),
+    (
+        "ospf-external-1",
+        RibType::OSPF,
+        RibSubType::OSPF_External_1,
+        112,
+    ),
+    (
+        "ospf-external-2",
+        RibType::OSPF,
+        RibSubType::OSPF_External_2,
+        113,
+    ),
+    ("ospf-nssa-1", RibType::OSPF, RibSubType::OSPF_NSSA_1, 112),
+    ("ospf-nssa-2", RibType::OSPF, RibSubType::OSPF_NSSA_2, 113),
+    ("isis-level-1", RibType::ISIS, RibSubType::ISIS_Level_1, 115),
+    ("isis-level-2", RibType::ISIS, RibSubType::ISIS_Level_2, 116),
+    (
+        "isis-inter-area",
+        RibType::ISIS,
+        RibSubType::ISIS_Intra_Area,
+        117,
+    ),
+    ("rip", RibType::RIP, RibSubType::NotApplicable, 120),
+];
+
+// Configured distances keyed by the name of the route type.
+pub type Distances = BTreeMap<String, u32>;
+
+fn distance_lookup(rtype: &RibType, rsubtype: &RibSubType) -> Option<(&'static str, u32)> {
+    DISTANCES
+        .iter()
+        .find(|(_, t, s, _)| t == rtype && s == rsubtype)
+        .map(|(name, _, _, distance)| (*name, *distance))
+}
+
+pub fn distance(config: &Distances, rtype: &RibType, rsubtype: &RibSubType) -> Option<u32> {
+    let (name, distance) = distance_lookup(rtype, rsubtype)?;
+    Some(config.get(name).cloned().unwrap_or(distance))
+}
+
+// Add the route of the protocol, replacing the one of the same route type.
+pub fn protocol_route_add(
+    rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    config: &Distances,
+    prefix: Ipv4Net,
+    mut e: RibEntry,
+) {
+    if let Some(distance) = distance(config, &e.rtype, &e.rsubtype) {
+        e.distance = distance;
+    }
+    let entries = rib.entry(prefix).or_default();
+    entries.retain(|x| !(x.rtype == e.rtype && x.rsubtype == e.rsubtype && x.owner == e.owner));
+    entries.push(e);
+    rib_select(entries);
+}
+
+pub fn protocol_route_del(
+    rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    prefix: Ipv4Net,
+    e: &RibEntry,
+) {
+    let Some(entries) = rib.get_mut(&prefix) else {
+        return;
+    };
+    entries.retain(|x| !(x.rtype == e.rtype && x.rsubtype == e.rsubtype && x.owner == e.owner));
+    if entries.is_empty() {
+        rib.remove(&prefix);
+    } else {
+        rib_select(entries);
+    }
+}
+
+// Apply the distances to the routes and select again. Returns true when any
+// of the distances has changed.
+pub fn distance_apply(rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>, config: &Distances) -> bool {
+    let mut changed = false;
+    for (_, entries) in rib.iter_mut() {
+        let mut update = false;
+        for e in entries.iter_mut() {
+            if let Some(distance) = distance(config, &e.rtype, &e.rsubtype) {
+                if e.distance != distance {
+                    e.distance = distance;
+                    update = true;
+                }
+            }
+        }
+        if update {
+            rib_select(entries);
+            changed = true;
+        }
+    }
+    changed
+}
+
+impl Rib {
+    pub async fn protocol_route_add(&mut self, prefix: Ipv4Net, e: RibEntry) {
+        protocol_route_add(&mut self.rib, &self.distances, prefix, e);
+        self.watchers.touch(prefix);
+        self.nexthop_update().await;
+    }
+
+    pub async fn protocol_route_del(&mut self, prefix: Ipv4Net, e: RibEntry) {
+        protocol_route_del(&mut self.rib, prefix, &e);
+        self.watchers.touch(prefix);
+        self.nexthop_update().await;
+    }
+}
+
+pub async fn distance_config(
+    rib: &mut Rib,
+    path: &str,
+    mut args: Args,
+    op: ConfigOp,
+) -> Option<()> {
+    let name = args.string()?;
+    if !DISTANCES.iter().any(|(n, _, _, _)| *n == name) {
+        return None;
+    }
+    match path {
+        "/routing/distance" => {
+            if op == ConfigOp::Set {
+                return Some(());
+            }
+            rib.distances.remove(&name);
+        }
+        "/routing/distance/value" => {
+            if op == ConfigOp::Set {
+                rib.distances.insert(name, args.u8()?.into());
+            } else {
+                rib.distances.remove(&name);
+            }
+        }
+        _ => return None,
+    }
+    if distance_apply(&mut rib.rib, &rib.distances) {
+        rib.watchers.touch_all();
+        rib.nexthop_update().await;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(rtype: RibType, rsubtype: RibSubType, metric: u32) -> RibEntry {
+        let mut e = RibEntry::new(rtype);
+        e.rsubtype = rsubtype;
+        e.metric = metric;
+        e
+    }
+
+    fn selected(rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>, prefix: &Ipv4Net) -> Vec<String> {
+        rib.get(prefix)
+            .unwrap()
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| format!("{}{}", e.rtype.name(), e.rsubtype.string().trim()))
+            .collect()
+    }
+
+    #[test]
+    fn subtype_selection() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut config = Distances::new();
+        let prefix: Ipv4Net = "10.0.0.0/24".parse().unwrap();
+
+        // IS-IS L1 is preferred over L2 even with the higher metric.
+        protocol_route_add(
+            &mut rib,
+            &config,
+            prefix,
+            entry(RibType::ISIS, RibSubType::ISIS_Level_2, 10),
+        );
+        protocol_route_add(
+            &mut rib,
+            &config,
+            prefix,
+            entry(RibType::ISIS, RibSubType::ISIS_Level_1, 20),
+        );
+        assert_eq!(selected(&rib, &prefix), vec!["isisL1"]);
+
+        // OSPF inter area wins over IS-IS.
+        protocol_route_add(
+            &mut rib,
+            &config,
+            prefix,
+            entry(RibType::OSPF, RibSubType::OSPF_IA, 100),
+        );
+        assert_eq!(selected(&rib, &prefix), vec!["ospfIA"]);
+        let distances: Vec<u32> = rib
+            .get(&prefix)
+            .unwrap()
+            .iter()
+            .map(|e| e.distance)
+            .collect();
+        assert_eq!(distances, vec![116, 115, 111]);
+
+        // Same route type is replaced.
+        protocol_route_add(
+            &mut rib,
+            &config,
+            prefix,
+            entry(RibType::OSPF, RibSubType::OSPF_IA, 50),
+        );
+        assert_eq!(rib.get(&prefix).unwrap().len(), 3);
+
+        // Runtime override of the distance selects again.
+        config.insert("ospf-inter-area".to_string(), 200);
+        assert!(distance_apply(&mut rib, &config));
+        assert_eq!(selected(&rib, &prefix), vec!["isisL1"]);
+        assert!(!distance_apply(&mut rib, &config));
+
+        config.insert("isis-level-2".to_string(), 115);
+        assert!(distance_apply(&mut rib, &config));
+        // Same distance, the lower metric wins.
+        assert_eq!(selected(&rib, &prefix), vec!["isisL2"]);
+
+        config.clear();
+        distance_apply(&mut rib, &config);
+        assert_eq!(selected(&rib, &prefix), vec!["ospfIA"]);
+
+        protocol_route_del(
+            &mut rib,
+            prefix,
+            &entry(RibType::OSPF, RibSubType::OSPF_IA, 0),
+        );
+        assert_eq!(selected(&rib, &prefix), vec!["isisL1"]);
+    }
+
+    #[test]
+    fn tie_break() {
+        let config = Distances::new();
+        let prefix: Ipv4Net = "10.0.0.0/24".parse().unwrap();
+        let e1 = entry(RibType::OSPF, RibSubType::OSPF_External_1, 20);
+        let e2 = entry(RibType::OSPF, RibSubType::OSPF_NSSA_1, 20);
+
+        // Same distance and metric, the route type decides regardless of the
+        // order.
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        protocol_route_add(&mut rib, &config, prefix, e1.clone());
+        protocol_route_add(&mut rib, &config, prefix, e2.clone());
+        assert_eq!(selected(&rib, &prefix), vec!["ospfN1"]);
+
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        protocol_route_add(&mut rib, &config, prefix, e2);
+        protocol_route_add(&mut rib, &config, prefix, e1);
+        assert_eq!(selected(&rib, &prefix), vec!["ospfN1"]);
+    }
+}
@@ -0,0 +1,240 @@
+use super::entry::RibType;
+use super::fib::FibBatch;
+use super::instance::Rib;
+use super::nexthop::Nexthop;
+use super::static_route::{static_rib_update, FibOp, StaticRoute};
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use std::collections::BTreeMap;
+
+// Equal-cost multipath. The nexthops of a route are ordered with a stable key,
+// the address and then the interface, labels and SIDs, so that the same
+// nexthops are installed regardless of the order they have been learned in.
+// The first maximum-paths of them are installed to the FIB and the rest are
+// kept as backups, which take over when one of the installed ones is gone.
+//
+// When all of the installed nexthops have the bandwidth, the traffic is
+// shared in proportion to it. The kernel weight is one octet, so the
+// bandwidths are scaled to have the largest one at the maximum weight.
+
+// Only the first nexthop is installed unless configured.
+pub const MAXIMUM_PATHS: u8 = 1;
+
+// Maximum paths keyed by the protocol name.
+pub type MaximumPaths = BTreeMap<String, u8>;
+
+fn nexthop_cmp(a: &Nexthop, b: &Nexthop) -> std::cmp::Ordering {
+    let key = |n: &Nexthop| {
+        (
+            n.ntype.is_discard(),
+            n.nexthop,
+            n.ifname.clone(),
+            n.labels.clone(),
+            n.seg6.clone(),
+        )
+    };
+    key(a).cmp(&key(b))
+}
+
+pub fn ecmp_select(nexthops: &mut [Nexthop], max: u8) {
+    nexthops.sort_by(nexthop_cmp);
+    for (i, nhop) in nexthops.iter_mut().enumerate() {
+        nhop.backup = i >= max as usize;
+    }
+}
+
+// Nexthops installed to the FIB.
+pub fn ecmp_active(nexthops: &[Nexthop]) -> Vec<Nexthop> {
+    nexthops
+        .iter()
+        .filter(|nhop| !nhop.backup)
+        .cloned()
+        .collect()
+}
+
+// Maximum weight of the nexthop in the kernel.
+pub const WEIGHT_MAX: u8 = 255;
+
+// Weights of the nexthops from 1 to WEIGHT_MAX. Equal unless all of them have
+// the bandwidth.
+pub fn ecmp_weights(nexthops: &[Nexthop]) -> Vec<u8> {
+    let equal = vec![1; nexthops.len()];
+    let Some(bandwidths) = nexthops
+        .iter()
+        .map(|nhop| nhop.bandwidth)
+        .collect::<Option<Vec<u64>>>()
+    else {
+        return equal;
+    };
+    let max = bandwidths.iter().cloned().max().unwrap_or(0) as u128;
+    if max == 0 {
+        return equal;
+    }
+    bandwidths
+        .iter()
+        .map(|bw| {
+            let weight = (*bw as u128 * WEIGHT_MAX as u128 + max / 2) / max;
+            weight.clamp(1, WEIGHT_MAX as u128) as u8
+        })
+        .collect()
+}
+
+impl Rib {
+    pub fn maximum_paths(&self, rtype: &RibType) -> u8 {
+        self.maximum_paths
+            .get(rtype.name())
+            .cloned()
+            .unwrap_or(MAXIMUM_PATHS)
+    }
+
+    // Apply the maximum-paths of the protocol to its routes.
+    async fn ecmp_update(&mut self, rtype: RibType) {
+        if rtype == RibType::Static {
+            let max = self.maximum_paths(&rtype);
+            let mut batch = FibBatch::default();
+            let statics: Vec<(Ipv4Net, StaticRoute)> = self
+                .statics
+                .iter()
+                .map(|(prefix, route)| (*prefix, route.clone()))
+                .collect();
+            for (prefix, route) in statics.iter() {
+                let table = self.static_table(route);
+                self.watchers.touch(*prefix);
+                let rib = self.table_mut(table);
+                match static_rib_update(rib, *prefix, Some(route), max) {
+                    Some(FibOp::Install(nhops)) => batch.install(table, *prefix, nhops),
+                    Some(FibOp::Uninstall(nhops)) => batch.uninstall(table, *prefix, nhops),
+                    None => {}
+                }
+            }
+            self.fib_handle.route_batch(batch).await;
+            self.nexthop_update().await;
+            return;
+        }
+        let max = self.maximum_paths(&rtype);
+        if rtype == RibType::API {
+            for owner in self.injected.owners.values_mut() {
+                for route in owner.routes.values_mut() {
+                    ecmp_select(&mut route.nexthops, max);
+                }
+            }
+        }
+        for (_, entries) in self.rib.iter_mut() {
+            for e in entries.iter_mut().filter(|e| e.rtype == rtype) {
+                ecmp_select(&mut e.nexthops, max);
+            }
+        }
+        self.watchers.touch_all();
+        self.nexthop_update().await;
+    }
+}
+
+pub async fn ecmp_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let protocol = args.string()?;
+    let rtype: RibType = protocol.parse().ok()?;
+    match path {
+        "/routing/maximum-paths" => {
+            if op == ConfigOp::Set {
+                return Some(());
+            }
+            rib.maximum_paths.remove(&protocol);
+        }
+        "/routing/maximum-paths/paths" => {
+            if op == ConfigOp::Set {
+                rib.maximum_paths.insert(protocol, args.u8()?);
+            } else {
+                rib.maximum_paths.remove(&protocol);
+            }
+        }
+        _ => return None,
+    }
+    rib.ecmp_update(rtype).await;
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nexthops(addrs: &[&str]) -> Vec<Nexthop> {
+        addrs
+            .iter()
+            .map(|addr| Nexthop::new(addr.parse().unwrap()))
+            .collect()
+    }
+
+    fn active(nexthops: &[Nexthop]) -> Vec<String> {
+        ecmp_active(nexthops)
+            .iter()
+            .map(|nhop| nhop.nexthop.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let addrs = ["10.0.0.3", "10.0.0.1", "10.0.0.4", "10.0.0.2"];
+        let mut first = nexthops(&addrs);
+        ecmp_select(&mut first, 2);
+
+        // Learned in another order.
+        let mut reversed: Vec<&str> = addrs.to_vec();
+        reversed.reverse();
+        let mut second = nexthops(&reversed);
+        ecmp_select(&mut second, 2);
+        assert_eq!(first, second);
+        assert_eq!(active(&first), vec!["10.0.0.1", "10.0.0.2"]);
+
+        // Same address through another interface follows it.
+        let mut nhops = nexthops(&["10.0.0.1", "10.0.0.1"]);
+        nhops[0].ifname = "eth1".to_string();
+        nhops[1].ifname = "eth0".to_string();
+        ecmp_select(&mut nhops, 1);
+        assert_eq!(nhops[0].ifname, "eth0");
+        assert!(nhops[1].backup);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut nhops = nexthops(&["10.0.0.3", "10.0.0.1", "10.0.0.2"]);
+        ecmp_select(&mut nhops, MAXIMUM_PATHS);
+        assert_eq!(active(&nhops), vec!["10.0.0.1"]);
+        // Backups are kept.
+        assert_eq!(nhops.len(), 3);
+        assert!(nhops[1].backup && nhops[2].backup);
+
+        ecmp_select(&mut nhops, 2);
+        assert_eq!(active(&nhops), vec!["10.0.0.1", "10.0.0.2"]);
+
+        // Fewer nexthops than the maximum.
+        ecmp_select(&mut nhops, 8);
+        assert_eq!(active(&nhops).len(), 3);
+
+        // Installed nexthop is gone, the first backup takes over.
+        nhops.remove(0);
+        ecmp_select(&mut nhops, 1);
+        assert_eq!(active(&nhops), vec!["10.0.0.2"]);
+    }
+
+    #[test]
+    fn weights() {
+        let mut nhops = nexthops(&["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(ecmp_weights(&nhops), vec![1, 1]);
+
+        // 1 Gbps and 3 Gbps in bytes per second.
+        nhops[0].bandwidth = Some(125_000_000);
+        // Equal until all of them have the bandwidth.
+        assert_eq!(ecmp_weights(&nhops), vec![1, 1]);
+        nhops[1].bandwidth = Some(375_000_000);
+        let weights = ecmp_weights(&nhops);
+        assert_eq!(weights, vec![85, 255]);
+        assert_eq!(weights[1], weights[0] * 3);
+
+        // Too small to be in proportion is still used.
+        nhops[0].bandwidth = Some(1);
+        assert_eq!(ecmp_weights(&nhops), vec![1, 255]);
+
+        nhops[0].bandwidth = Some(0);
+        nhops[1].bandwidth = Some(0);
+        assert_eq!(ecmp_weights(&nhops), vec![1, 1]);
+    }
+}
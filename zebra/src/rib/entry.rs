@@ -1,7 +1,11 @@
-use super::{nexthop::Nexthop, Rib};
+use super::{
+    nexthop::{Nexthop, NexthopType},
+    Rib,
+};
 use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[allow(non_camel_case_types, dead_code, clippy::upper_case_acronyms)]
 pub enum RibType {
     Kernel,
@@ -11,9 +15,44 @@ pub enum RibType {
     OSPF,
     ISIS,
     BGP,
+    API,
 }
 
-#[derive(Debug, PartialEq)]
+impl RibType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Kernel => "kernel",
+            Self::Connected => "connected",
+            Self::Static => "static",
+            Self::RIP => "rip",
+            Self::OSPF => "ospf",
+            Self::ISIS => "isis",
+            Self::BGP => "bgp",
+            Self::API => "api",
+        }
+    }
+}
+
+impl FromStr for RibType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kernel" => Ok(Self::Kernel),
+            "connected" => Ok(Self::Connected),
+            "static" => Ok(Self::Static),
+            "rip" => Ok(Self::RIP),
+            "ospf" => Ok(Self::OSPF),
+            "isis" => Ok(Self::ISIS),
+            "bgp" => Ok(Self::BGP),
+            "api" => Ok(Self::API),
+            _ => Err(()),
+        }
+    }
+}
+
+// Route type within the protocol. The order breaks the tie of the selection.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[allow(non_camel_case_types, dead_code)]
 pub enum RibSubType {
     NotApplicable,
@@ -27,7 +66,7 @@ pub enum RibSubType {
     ISIS_Intra_Area,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RibEntry {
     pub rtype: RibType,
     pub rsubtype: RibSubType,
@@ -40,6 +79,8 @@ pub struct RibEntry {
     pub nexthops: Vec<Nexthop>,
     pub gateway: IpAddr,
     pub link_index: u32,
+    pub owner: String,
+    pub nexthop_vrf: Option<String>,
 }
 
 impl RibEntry {
@@ -56,6 +97,8 @@ impl RibEntry {
             nexthops: Vec::new(),
             gateway: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
             link_index: 0,
+            owner: String::new(),
+            nexthop_vrf: None,
         }
     }
 
@@ -68,12 +111,33 @@ impl RibEntry {
     }
 
     pub fn gateway(&self, rib: &Rib) -> String {
+        self.gateway_link(rib.link_name(self.link_index).map(|s| s.as_str()))
+    }
+
+    pub fn gateway_link(&self, link_name: Option<&str>) -> String {
+        let gateway = self.gateway_str(link_name);
+        if let Some(vrf) = self.nexthop_vrf.as_ref() {
+            format!("{} (vrf {})", gateway, vrf)
+        } else {
+            gateway
+        }
+    }
+
+    fn gateway_str(&self, link_name: Option<&str>) -> String {
         if self.rtype == RibType::Connected {
-            if let Some(name) = rib.link_name(self.link_index) {
-                format!("directly connected {}", name)
-            } else {
-                "directly connected unknown".to_string()
+            format!("directly connected {}", link_name.unwrap_or("unknown"))
+        } else if let Some(nhop) = self.nexthops.first() {
+            match nhop.ntype {
+                NexthopType::Gateway => {}
+                NexthopType::Null0 => return "directly connected, Null0".to_string(),
+                ntype => return ntype.to_string(),
+            }
+            let mut gateway = format!("via {}", nhop.nexthop);
+            if !nhop.ifname.is_empty() {
+                gateway.push_str(&format!(", {}", nhop.ifname));
             }
+            gateway.push_str(&nhop.encap_str());
+            gateway
         } else {
             format!("via {:?}", &self.gateway)
         }
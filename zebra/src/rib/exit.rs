@@ -0,0 +1,151 @@
+use super::ecmp::ecmp_active;
+use super::entry::{RibEntry, RibType};
+use super::fib::batch::{fib_batch_exec, FIB_BATCH_WINDOW};
+use super::fib::{FibBatch, FibRouteExec};
+use super::instance::Rib;
+use super::nexthop::Nexthop;
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+
+// Routes in the FIB on exit. The routes installed by zebra are removed from
+// the FIB unless they are retained, either by the command line option or by
+// the config. Retained routes keep forwarding across the restart until the
+// new instance replaces them.
+
+// Routes installed to the FIB table by zebra with their nexthops. The kernel
+// and connected routes in the FIB are not ours.
+pub fn fib_routes(
+    table: u32,
+    rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>,
+) -> Vec<(u32, Ipv4Net, Vec<Nexthop>)> {
+    rib.iter()
+        .filter_map(|(prefix, entries)| {
+            let e = entries
+                .iter()
+                .find(|e| e.rtype == RibType::Static && e.fib)?;
+            Some((table, *prefix, ecmp_active(&e.nexthops)))
+        })
+        .collect()
+}
+
+// Remove the routes from the FIB in a batch unless retained. Returns the
+// number of the removed routes.
+pub async fn fib_exit<F: FibRouteExec>(
+    fib: &F,
+    routes: Vec<(u32, Ipv4Net, Vec<Nexthop>)>,
+    retain: bool,
+) -> usize {
+    if retain {
+        return 0;
+    }
+    let mut batch = FibBatch::default();
+    for (table, prefix, nhops) in routes.into_iter() {
+        batch.uninstall(table, prefix, nhops);
+    }
+    let count = batch.len();
+    let errors = fib_batch_exec(fib, batch, FIB_BATCH_WINDOW).await;
+    for err in errors.iter() {
+        println!("rib: {}", err);
+    }
+    count - errors.len()
+}
+
+impl Rib {
+    pub fn retain_routes(&self) -> bool {
+        self.retain_routes_default || self.retain_routes
+    }
+
+    pub async fn exit(&mut self) {
+        let routes = self
+            .table_ids()
+            .into_iter()
+            .filter_map(|id| self.table(id).map(|rib| fib_routes(id, rib)))
+            .flatten()
+            .collect();
+        let retain = self.retain_routes();
+        let count = fib_exit(&self.fib_handle, routes, retain).await;
+        if retain {
+            println!("rib: routes are retained in the FIB");
+        } else {
+            println!("rib: {} routes are removed from the FIB", count);
+        }
+    }
+}
+
+pub fn exit_config(rib: &mut Rib, path: &str, _args: Args, op: ConfigOp) -> Option<()> {
+    if path != "/routing/retain-routes-on-exit" {
+        return None;
+    }
+    rib.retain_routes = op == ConfigOp::Set;
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::static_route::{static_rib_update, FibOp, StaticRoute};
+    use crate::rib::table::RT_TABLE_MAIN;
+    use std::cell::RefCell;
+
+    // FIB which records the removed routes.
+    #[derive(Default)]
+    struct MockFib {
+        deleted: RefCell<Vec<(u32, Ipv4Net)>>,
+    }
+
+    impl FibRouteExec for MockFib {
+        async fn route_exec(&self, table: u32, prefix: Ipv4Net, op: &FibOp) -> Result<(), String> {
+            assert!(matches!(op, FibOp::Uninstall(_)));
+            self.deleted.borrow_mut().push((table, prefix));
+            Ok(())
+        }
+    }
+
+    fn rib() -> PrefixMap<Ipv4Net, Vec<RibEntry>> {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let p1: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let p2: Ipv4Net = "10.2.0.0/16".parse().unwrap();
+        let mut route = StaticRoute::default();
+        route.nexthops.insert("192.168.0.1".parse().unwrap());
+        static_rib_update(&mut rib, p1, Some(&route), 1);
+        static_rib_update(&mut rib, p2, Some(&route), 1);
+
+        // Not installed by zebra.
+        let mut e = RibEntry::new(RibType::Connected);
+        e.selected = true;
+        e.fib = true;
+        rib.insert("10.3.0.0/16".parse().unwrap(), vec![e]);
+        rib
+    }
+
+    #[tokio::test]
+    async fn flush() {
+        let fib = MockFib::default();
+        let routes = fib_routes(RT_TABLE_MAIN, &rib());
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].2.len(), 1);
+        assert_eq!(fib_exit(&fib, routes, false).await, 2);
+        let deleted: Vec<String> = fib
+            .deleted
+            .borrow()
+            .iter()
+            .map(|(_, p)| p.to_string())
+            .collect();
+        assert_eq!(deleted, vec!["10.1.0.0/16", "10.2.0.0/16"]);
+
+        // Routes of the other table are removed from it.
+        let fib = MockFib::default();
+        let routes = fib_routes(100, &rib());
+        assert_eq!(fib_exit(&fib, routes, false).await, 2);
+        assert!(fib.deleted.borrow().iter().all(|(table, _)| *table == 100));
+    }
+
+    #[tokio::test]
+    async fn retain() {
+        let fib = MockFib::default();
+        let routes = fib_routes(RT_TABLE_MAIN, &rib());
+        assert_eq!(fib_exit(&fib, routes, true).await, 0);
+        assert!(fib.deleted.borrow().is_empty());
+    }
+}
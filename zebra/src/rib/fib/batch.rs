@@ -0,0 +1,204 @@
+use crate::rib::nexthop::Nexthop;
+use crate::rib::static_route::FibOp;
+use crate::rib::table::RT_TABLE_MAIN;
+use futures::stream::{self, StreamExt};
+use ipnet::Ipv4Net;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Batched route operations. Bulk changes of the FIB, e.g. flushing the
+// routes on exit, are collected into a batch instead of being programmed one
+// route at a time. Operations on the same prefix are coalesced so that only
+// the last one is sent to the kernel, and the rest of them are sent without
+// waiting for the acknowledgement of each other, up to the window. The
+// entries which the kernel has refused are reported together at the end.
+// Operations are keyed by the kernel routing table and the prefix.
+
+pub const FIB_BATCH_WINDOW: usize = 64;
+
+#[derive(Debug, Default)]
+pub struct FibBatch {
+    ops: BTreeMap<(u32, Ipv4Net), FibOp>,
+}
+
+impl FibBatch {
+    pub fn install(&mut self, table: u32, prefix: Ipv4Net, nhops: Vec<Nexthop>) {
+        self.ops.insert((table, prefix), FibOp::Install(nhops));
+    }
+
+    pub fn uninstall(&mut self, table: u32, prefix: Ipv4Net, nhops: Vec<Nexthop>) {
+        self.ops.insert((table, prefix), FibOp::Uninstall(nhops));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FibBatchError {
+    pub table: u32,
+    pub prefix: Ipv4Net,
+    pub install: bool,
+    pub error: String,
+}
+
+impl fmt::Display for FibBatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.install { "install" } else { "uninstall" };
+        if self.table != RT_TABLE_MAIN {
+            return write!(
+                f,
+                "{} {} table {}: {}",
+                op, self.prefix, self.table, self.error
+            );
+        }
+        write!(f, "{} {}: {}", op, self.prefix, self.error)
+    }
+}
+
+pub trait FibRouteExec {
+    async fn route_exec(&self, table: u32, prefix: Ipv4Net, op: &FibOp) -> Result<(), String>;
+}
+
+// Program the batch with up to `window` operations in flight. Returns the
+// failed entries in the order of the table and the prefix.
+pub async fn fib_batch_exec<F: FibRouteExec>(
+    fib: &F,
+    batch: FibBatch,
+    window: usize,
+) -> Vec<FibBatchError> {
+    let results: Vec<_> = stream::iter(batch.ops.iter())
+        .map(|((table, prefix), op)| async move {
+            let result = fib.route_exec(*table, *prefix, op).await;
+            (*table, *prefix, op, result)
+        })
+        .buffer_unordered(window.max(1))
+        .collect()
+        .await;
+    let mut errors: Vec<FibBatchError> = results
+        .into_iter()
+        .filter_map(|(table, prefix, op, result)| {
+            result.err().map(|error| FibBatchError {
+                table,
+                prefix,
+                install: matches!(op, FibOp::Install(_)),
+                error,
+            })
+        })
+        .collect();
+    errors.sort_by_key(|e| (e.table, e.prefix));
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::net::Ipv4Addr;
+
+    // FIB which refuses the prefixes of the third octet 13, and records the
+    // operations and the number of them in flight.
+    #[derive(Default)]
+    struct MockFib {
+        programmed: RefCell<Vec<Ipv4Net>>,
+        in_flight: Cell<usize>,
+        max_in_flight: Cell<usize>,
+    }
+
+    impl FibRouteExec for MockFib {
+        async fn route_exec(
+            &self,
+            _table: u32,
+            prefix: Ipv4Net,
+            _op: &FibOp,
+        ) -> Result<(), String> {
+            self.in_flight.set(self.in_flight.get() + 1);
+            self.max_in_flight
+                .set(self.max_in_flight.get().max(self.in_flight.get()));
+            tokio::task::yield_now().await;
+            self.in_flight.set(self.in_flight.get() - 1);
+            if prefix.addr().octets()[2] == 13 {
+                return Err("Network is unreachable".to_string());
+            }
+            self.programmed.borrow_mut().push(prefix);
+            Ok(())
+        }
+    }
+
+    fn prefix(i: u32) -> Ipv4Net {
+        Ipv4Net::new(Ipv4Addr::from(0x0a000000 + (i << 8)), 24).unwrap()
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let fib = MockFib::default();
+        let mut batch = FibBatch::default();
+        let nhops = vec![Nexthop::new("192.168.0.1".parse().unwrap())];
+        for i in 0..1000 {
+            batch.install(RT_TABLE_MAIN, prefix(i), nhops.clone());
+        }
+        assert_eq!(batch.len(), 1000);
+
+        let errors = fib_batch_exec(&fib, batch, FIB_BATCH_WINDOW).await;
+        // 10.x.13.0/24 of the 1000 prefixes are refused.
+        let failed: Vec<Ipv4Net> = (0..1000)
+            .map(prefix)
+            .filter(|p| p.addr().octets()[2] == 13)
+            .collect();
+        assert_eq!(failed.len(), 4);
+        assert_eq!(errors.iter().map(|e| e.prefix).collect::<Vec<_>>(), failed);
+        assert!(errors.iter().all(|e| e.install));
+        assert_eq!(
+            errors[0].to_string(),
+            "install 10.0.13.0/24: Network is unreachable"
+        );
+        assert_eq!(fib.programmed.borrow().len(), 996);
+
+        // Operations are not waited for one by one.
+        assert_eq!(fib.max_in_flight.get(), FIB_BATCH_WINDOW);
+    }
+
+    #[tokio::test]
+    async fn coalesce() {
+        let fib = MockFib::default();
+        let mut batch = FibBatch::default();
+        let nhops = vec![Nexthop::new("192.168.0.1".parse().unwrap())];
+        batch.install(RT_TABLE_MAIN, prefix(1), nhops.clone());
+        batch.uninstall(RT_TABLE_MAIN, prefix(1), nhops.clone());
+        batch.install(RT_TABLE_MAIN, prefix(2), nhops.clone());
+        batch.uninstall(RT_TABLE_MAIN, prefix(13), nhops.clone());
+        // Same prefix in another table is not coalesced.
+        batch.install(100, prefix(13), nhops);
+        assert_eq!(batch.len(), 4);
+
+        let errors = fib_batch_exec(&fib, batch, 1).await;
+        assert_eq!(
+            errors,
+            vec![
+                FibBatchError {
+                    table: RT_TABLE_MAIN,
+                    prefix: prefix(13),
+                    install: false,
+                    error: "Network is unreachable".to_string(),
+                },
+                FibBatchError {
+                    table: 100,
+                    prefix: prefix(13),
+                    install: true,
+                    error: "Network is unreachable".to_string(),
+                }
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "install 10.0.13.0/24 table 100: Network is unreachable"
+        );
+        assert_eq!(fib.max_in_flight.get(), 1);
+        assert!(fib.programmed.borrow().contains(&prefix(1)));
+    }
+}
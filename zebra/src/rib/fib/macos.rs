@@ -1,5 +1,11 @@
-use super::message::{FibAddr, FibLink, FibMessage, FibRoute};
+use super::batch::{fib_batch_exec, FibBatch, FibBatchError, FibRouteExec, FIB_BATCH_WINDOW};
+use super::message::{FibAddr, FibLink, FibMessage, FibNeigh, FibRoute, FibRule};
 use crate::rib::link;
+use crate::rib::nexthop::Nexthop;
+use crate::rib::static_route::FibOp;
+use crate::rib::stats::LinkCounters;
+use crate::rib::table::RT_TABLE_MAIN;
+use crate::rib::DadState;
 use anyhow::Result;
 use ioctl_rs::SIOCGIFMTU;
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
@@ -8,7 +14,7 @@ use nix::ifaddrs::getifaddrs;
 use nix::libc::{ioctl, socket, AF_INET, IFNAMSIZ, SOCK_DGRAM};
 use nix::net::if_::if_nametoindex;
 use nix::net::if_::InterfaceFlags;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::CString;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use tokio::sync::mpsc::UnboundedSender;
@@ -23,17 +29,107 @@ impl FibHandle {
         Ok(Self { h })
     }
 
-    pub async fn route_ipv4_add(&self, dest: Ipv4Net, gateway: Ipv4Addr) {
-        let route = Route::new(IpAddr::V4(dest.addr()), dest.prefix_len())
-            .with_gateway(IpAddr::V4(gateway));
-        self.h.add(&route).await.unwrap();
+    // Discard routes point to the loopback which drops the packets.
+    // RTF_BLACKHOLE and RTF_REJECT are not exposed by net_route, so reject
+    // and prohibit are installed as blackhole as well.
+    fn route_ipv4(dest: Ipv4Net, nhop: &Nexthop) -> Route {
+        let gateway = if nhop.ntype.is_discard() {
+            Ipv4Addr::LOCALHOST
+        } else {
+            nhop.nexthop
+        };
+        Route::new(IpAddr::V4(dest.addr()), dest.prefix_len()).with_gateway(IpAddr::V4(gateway))
     }
 
-    #[allow(dead_code)]
-    pub async fn route_ipv4_del(&self, dest: Ipv4Net, gateway: Ipv4Addr) {
-        let route = Route::new(IpAddr::V4(dest.addr()), dest.prefix_len())
-            .with_gateway(IpAddr::V4(gateway));
-        self.h.delete(&route).await.unwrap();
+    // There is no replace in the routing socket, the existing route is
+    // deleted first. Multipath is not supported, only the first nexthop is
+    // installed.
+    async fn route_ipv4_add_exec(&self, dest: Ipv4Net, nhops: &[Nexthop]) -> Result<()> {
+        let Some(nhop) = nhops.first() else {
+            return Ok(());
+        };
+        let route = Self::route_ipv4(dest, nhop);
+        let _ = self.h.delete(&route).await;
+        self.h.add(&route).await?;
+        Ok(())
+    }
+
+    async fn route_ipv4_del_exec(&self, dest: Ipv4Net, nhops: &[Nexthop]) -> Result<()> {
+        let Some(nhop) = nhops.first() else {
+            return Ok(());
+        };
+        let route = Self::route_ipv4(dest, nhop);
+        self.h.delete(&route).await?;
+        Ok(())
+    }
+
+    pub async fn route_ipv4_add(&self, table: u32, dest: Ipv4Net, nhops: &[Nexthop]) {
+        if let Err(err) = self
+            .route_exec(table, dest, &FibOp::Install(nhops.to_vec()))
+            .await
+        {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn route_ipv4_del(&self, table: u32, dest: Ipv4Net, nhops: &[Nexthop]) {
+        if let Err(err) = self
+            .route_exec(table, dest, &FibOp::Uninstall(nhops.to_vec()))
+            .await
+        {
+            println!("Err: {}", err);
+        }
+    }
+
+    // There is only the main table in the routing socket.
+    pub async fn route_table_dump(&self, _table: u32, _tx: UnboundedSender<FibMessage>) {}
+
+    pub async fn route_batch(&self, batch: FibBatch) -> Vec<FibBatchError> {
+        let errors = fib_batch_exec(self, batch, FIB_BATCH_WINDOW).await;
+        for err in errors.iter() {
+            println!("Err: {}", err);
+        }
+        errors
+    }
+
+    // Policy routing rules are not supported by the routing socket.
+    pub async fn rule_add(&self, rule: &FibRule) {
+        println!("Err: ip rule {} is not supported", rule.priority);
+    }
+
+    pub async fn rule_del(&self, _rule: &FibRule) {}
+
+    // Static neighbors are not supported by the routing socket.
+    pub async fn neigh_add(&self, neigh: &FibNeigh) {
+        println!("Err: static neighbor {} is not supported", neigh.addr);
+    }
+
+    pub async fn neigh_del(&self, _neigh: &FibNeigh) {}
+
+    // ND proxy is not supported by the routing socket.
+    pub async fn nd_proxy_add(&self, _link_index: u32, addr: Ipv6Addr) {
+        println!("Err: ND proxy for {} is not supported", addr);
+    }
+
+    pub async fn nd_proxy_del(&self, _link_index: u32, _addr: Ipv6Addr) {}
+
+    pub fn nd_proxy_enable(&self, _name: &str, _enable: bool) {}
+
+    pub fn link_poller(&self) -> LinkPoller {
+        LinkPoller
+    }
+}
+
+impl FibRouteExec for FibHandle {
+    async fn route_exec(&self, table: u32, prefix: Ipv4Net, op: &FibOp) -> Result<(), String> {
+        if table != RT_TABLE_MAIN {
+            return Err(format!("table {} is not supported", table));
+        }
+        let result = match op {
+            FibOp::Install(nhops) => self.route_ipv4_add_exec(prefix, nhops).await,
+            FibOp::Uninstall(nhops) => self.route_ipv4_del_exec(prefix, nhops).await,
+        };
+        result.map_err(|err| err.to_string())
     }
 }
 
@@ -122,6 +218,7 @@ fn os_dump(tx: UnboundedSender<FibMessage>) {
                                 addr: IpNet::V4(ipv4net),
                                 link_index: index,
                                 secondary: false,
+                                dad: DadState::Preferred,
                             };
                             let msg = FibMessage::NewAddr(osaddr);
                             tx.send(msg).unwrap();
@@ -140,6 +237,7 @@ fn os_dump(tx: UnboundedSender<FibMessage>) {
                                 addr: IpNet::V6(ipv6net),
                                 link_index: index,
                                 secondary: false,
+                                dad: DadState::Preferred,
                             };
                             let msg = FibMessage::NewAddr(osaddr);
                             tx.send(msg).unwrap();
@@ -166,6 +264,8 @@ async fn os_route_dump(tx: UnboundedSender<FibMessage>) {
                         let osroute = FibRoute {
                             route: IpNet::V4(v4net),
                             gateway,
+                            table: RT_TABLE_MAIN,
+                            owned: false,
                         };
                         let msg = FibMessage::NewRoute(osroute);
                         tx.send(msg).unwrap();
@@ -176,13 +276,26 @@ async fn os_route_dump(tx: UnboundedSender<FibMessage>) {
     }
 }
 
-pub async fn fib_dump(_handle: &FibHandle, tx: UnboundedSender<FibMessage>) -> std::io::Result<()> {
+pub async fn fib_dump(
+    _handle: &FibHandle,
+    tx: UnboundedSender<FibMessage>,
+    _tables: &BTreeSet<u32>,
+) -> std::io::Result<()> {
     os_dump(tx.clone());
     os_route_dump(tx.clone()).await;
 
     Ok(())
 }
 
+// Interface counters are not polled.
+pub struct LinkPoller;
+
+impl LinkPoller {
+    pub async fn counters(&self) -> Vec<(String, LinkCounters)> {
+        Vec::new()
+    }
+}
+
 pub fn os_traffic_dump() -> impl Fn(&String, &mut String) {
     move |_link_name: &String, _buf: &mut String| {}
 }
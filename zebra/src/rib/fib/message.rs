@@ -1,6 +1,9 @@
-use super::{LinkFlags, LinkType};
-use ipnet::IpNet;
+use super::{DadState, LinkFlags, LinkType};
+use crate::rib::stats::LinkCounters;
+use crate::rib::MacAddr;
+use ipnet::{IpNet, Ipv4Net};
 use std::net::IpAddr;
+use std::time::Instant;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 #[derive(Debug)]
@@ -38,6 +41,7 @@ pub struct FibAddr {
     pub addr: IpNet,
     pub link_index: u32,
     pub secondary: bool,
+    pub dad: DadState,
 }
 
 impl FibAddr {
@@ -53,6 +57,61 @@ impl FibAddr {
 pub struct FibRoute {
     pub route: IpNet,
     pub gateway: IpAddr,
+    // Kernel routing table of the route.
+    pub table: u32,
+    // Installed by zebra, either by this instance or by the previous one.
+    pub owned: bool,
+}
+
+// Policy routing rule as installed to the kernel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FibRule {
+    pub priority: u32,
+    pub from: Option<Ipv4Net>,
+    pub to: Option<Ipv4Net>,
+    pub fwmark: Option<u32>,
+    pub table: u32,
+}
+
+// Reachability state of a neighbor entry, NUD_* of the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighState {
+    Incomplete,
+    Reachable,
+    Stale,
+    Delay,
+    Probe,
+    Failed,
+    Noarp,
+    Permanent,
+    None,
+}
+
+impl NeighState {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Incomplete => "incomplete",
+            Self::Reachable => "reachable",
+            Self::Stale => "stale",
+            Self::Delay => "delay",
+            Self::Probe => "probe",
+            Self::Failed => "failed",
+            Self::Noarp => "noarp",
+            Self::Permanent => "permanent",
+            Self::None => "none",
+        }
+    }
+}
+
+// ARP or NDP neighbor entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FibNeigh {
+    pub addr: IpAddr,
+    pub link_index: u32,
+    pub mac: Option<MacAddr>,
+    pub state: NeighState,
+    // Neighbor is an IPv6 router.
+    pub router: bool,
 }
 
 #[allow(dead_code)]
@@ -63,4 +122,8 @@ pub enum FibMessage {
     DelAddr(FibAddr),
     NewRoute(FibRoute),
     DelRoute(FibRoute),
+    NewNeigh(FibNeigh),
+    DelNeigh(FibNeigh),
+    // Polled interface counters keyed by the interface name.
+    LinkStats(Instant, Vec<(String, LinkCounters)>),
 }
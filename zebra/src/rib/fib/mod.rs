@@ -10,6 +10,8 @@ pub use netlink::route_add;
 pub use netlink::route_del;
 #[cfg(target_os = "linux")]
 pub use netlink::FibHandle;
+#[cfg(target_os = "linux")]
+pub use netlink::LinkPoller;
 
 #[cfg(target_os = "macos")]
 pub mod macos;
@@ -19,8 +21,13 @@ pub use macos::fib_dump;
 pub use macos::os_traffic_dump;
 #[cfg(target_os = "macos")]
 pub use macos::FibHandle;
+#[cfg(target_os = "macos")]
+pub use macos::LinkPoller;
+
+pub mod batch;
+pub use batch::{FibBatch, FibBatchError, FibRouteExec};
 
 pub mod message;
-pub use message::{FibChannel, FibMessage};
+pub use message::{FibChannel, FibMessage, FibNeigh, FibRule, NeighState};
 
-pub use super::{LinkFlags, LinkType};
+pub use super::{DadState, LinkFlags, LinkType};
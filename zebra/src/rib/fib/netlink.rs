@@ -1,25 +1,40 @@
-use super::message::{FibAddr, FibLink, FibMessage, FibRoute};
+use super::batch::{fib_batch_exec, FibBatch, FibBatchError, FibRouteExec, FIB_BATCH_WINDOW};
+use super::message::{FibAddr, FibLink, FibMessage, FibNeigh, FibRoute, FibRule, NeighState};
+use crate::rib::ecmp::ecmp_weights;
 use crate::rib::link;
+use crate::rib::nexthop::{Nexthop, NexthopType};
+use crate::rib::static_route::FibOp;
+use crate::rib::stats::LinkCounters;
+use crate::rib::table::RT_TABLE_MAIN;
+use crate::rib::{DadState, MacAddr};
 use anyhow::Result;
 use futures::stream::{StreamExt, TryStreamExt};
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
-use netlink_packet_route::address::{AddressAttribute, AddressMessage};
-use netlink_packet_route::link::{LinkAttribute, LinkFlag, LinkLayerType, LinkMessage};
+use netlink_packet_route::address::{AddressAttribute, AddressFlag, AddressMessage};
+use netlink_packet_route::link::{
+    LinkAttribute, LinkFlag, LinkLayerType, LinkMessage, Stats, Stats64,
+};
+use netlink_packet_route::neighbour::{
+    NeighbourAddress, NeighbourAttribute, NeighbourFlag, NeighbourMessage, NeighbourState,
+};
 use netlink_packet_route::route::{
-    RouteAddress, RouteAttribute, RouteHeader, RouteMessage, RouteProtocol, RouteScope, RouteType,
+    RouteAddress, RouteAttribute, RouteHeader, RouteMessage, RouteNextHop, RouteProtocol,
+    RouteScope, RouteType,
 };
+use netlink_packet_route::rule::{RuleAction, RuleAttribute, RuleMessage};
 use netlink_packet_route::{AddressFamily, RouteNetlinkMessage};
 use netlink_sys::{AsyncSocket, SocketAddr};
 use rtnetlink::{
     constants::{
         RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_IPV6_ROUTE, RTMGRP_LINK,
+        RTMGRP_NEIGH,
     },
     new_connection, IpVersion,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Write;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use tokio::sync::mpsc::UnboundedSender;
 
 pub struct FibHandle {
@@ -34,7 +49,8 @@ impl FibHandle {
             | RTMGRP_IPV4_ROUTE
             | RTMGRP_IPV6_ROUTE
             | RTMGRP_IPV4_IFADDR
-            | RTMGRP_IPV6_IFADDR;
+            | RTMGRP_IPV6_IFADDR
+            | RTMGRP_NEIGH;
 
         let addr = SocketAddr::new(0, mgroup_flags);
         connection.socket_mut().socket_mut().bind(&addr)?;
@@ -51,7 +67,326 @@ impl FibHandle {
         Ok(Self { handle })
     }
 
-    pub async fn route_ipv4_add(&self, _dest: Ipv4Net, _gateway: Ipv4Addr) {}
+    // The route is added with NLM_F_REPLACE so that changing the nexthops
+    // does not remove the route from the kernel in between.
+    async fn route_ipv4_add_exec(
+        &self,
+        table: u32,
+        dest: Ipv4Net,
+        nhops: &[Nexthop],
+    ) -> Result<()> {
+        if nhops.is_empty() {
+            return Ok(());
+        }
+        let mut req = self.handle.route().add().v4().replace();
+        *req.message_mut() = route_add_message(table, dest, nhops);
+        req.execute().await?;
+        Ok(())
+    }
+
+    // Multipath route is deleted by the prefix. Without the nexthops our
+    // route of the prefix is deleted whatever the type and the nexthops.
+    async fn route_ipv4_del_exec(
+        &self,
+        table: u32,
+        dest: Ipv4Net,
+        nhops: &[Nexthop],
+    ) -> Result<()> {
+        let mut message = RouteDelMessage::new()
+            .destination(dest.addr(), dest.prefix_len())
+            .table(table);
+        match nhops.first() {
+            Some(nhop) => {
+                message = message.kind(route_type(nhop.ntype));
+                if nhops.len() == 1 && !nhop.ntype.is_discard() {
+                    message = message.gateway(nhop.nexthop);
+                }
+            }
+            None => {
+                message = message.kind(RouteType::Unspec);
+            }
+        }
+        self.handle.route().del(message.build()).execute().await?;
+        Ok(())
+    }
+
+    pub async fn route_ipv4_add(&self, table: u32, dest: Ipv4Net, nhops: &[Nexthop]) {
+        if let Err(err) = self.route_ipv4_add_exec(table, dest, nhops).await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn route_ipv4_del(&self, table: u32, dest: Ipv4Net, nhops: &[Nexthop]) {
+        if let Err(err) = self.route_ipv4_del_exec(table, dest, nhops).await {
+            println!("Err: {}", err);
+        }
+    }
+
+    // Import the routes of the table which has come into use.
+    pub async fn route_table_dump(&self, table: u32, tx: UnboundedSender<FibMessage>) {
+        let tables = BTreeSet::from([table]);
+        if let Err(err) = route_dump(self.handle.clone(), tx, IpVersion::V4, &tables).await {
+            println!("Err: {}", err);
+        }
+    }
+
+    // The requests of the batch share the netlink socket, and are sent
+    // without waiting for the acknowledgement of the previous ones.
+    pub async fn route_batch(&self, batch: FibBatch) -> Vec<FibBatchError> {
+        let errors = fib_batch_exec(self, batch, FIB_BATCH_WINDOW).await;
+        for err in errors.iter() {
+            println!("Err: {}", err);
+        }
+        errors
+    }
+
+    pub async fn rule_add(&self, rule: &FibRule) {
+        let mut req = self.handle.rule().add();
+        *req.message_mut() = rule_message(rule);
+        if let Err(err) = req.execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn rule_del(&self, rule: &FibRule) {
+        let req = self.handle.rule().del(rule_message(rule));
+        if let Err(err) = req.execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    // Static neighbor is added as a permanent entry, replacing the learned
+    // one of the address.
+    pub async fn neigh_add(&self, neigh: &FibNeigh) {
+        let mut req = self
+            .handle
+            .neighbours()
+            .add(neigh.link_index, neigh.addr)
+            .state(NeighbourState::Permanent)
+            .replace();
+        if let Some(mac) = neigh.mac {
+            req = req.link_local_address(&mac.octets());
+        }
+        if let Err(err) = req.execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn neigh_del(&self, neigh: &FibNeigh) {
+        let req = self.handle.neighbours().del(neigh_message(neigh));
+        if let Err(err) = req.execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn nd_proxy_add(&self, link_index: u32, addr: Ipv6Addr) {
+        let req = self
+            .handle
+            .neighbours()
+            .add(link_index, IpAddr::V6(addr))
+            .flags(vec![NeighbourFlag::Proxy]);
+        if let Err(err) = req.execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub async fn nd_proxy_del(&self, link_index: u32, addr: Ipv6Addr) {
+        let mut msg = NeighbourMessage::default();
+        msg.header.family = AddressFamily::Inet6;
+        msg.header.ifindex = link_index;
+        msg.header.flags = vec![NeighbourFlag::Proxy];
+        let dst = NeighbourAddress::Inet6(addr);
+        msg.attributes.push(NeighbourAttribute::Destination(dst));
+        if let Err(err) = self.handle.neighbours().del(msg).execute().await {
+            println!("Err: {}", err);
+        }
+    }
+
+    pub fn link_poller(&self) -> LinkPoller {
+        LinkPoller {
+            handle: self.handle.clone(),
+        }
+    }
+
+    // Proxy entries are looked up by the kernel only when proxy_ndp is
+    // enabled on the interface.
+    pub fn nd_proxy_enable(&self, name: &str, enable: bool) {
+        let path = format!("/proc/sys/net/ipv6/conf/{}/proxy_ndp", name);
+        if let Err(err) = std::fs::write(&path, if enable { "1" } else { "0" }) {
+            println!("Err: {}: {}", path, err);
+        }
+    }
+}
+
+impl FibRouteExec for FibHandle {
+    async fn route_exec(&self, table: u32, prefix: Ipv4Net, op: &FibOp) -> Result<(), String> {
+        let result = match op {
+            FibOp::Install(nhops) => self.route_ipv4_add_exec(table, prefix, nhops).await,
+            FibOp::Uninstall(nhops) => self.route_ipv4_del_exec(table, prefix, nhops).await,
+        };
+        result.map_err(|err| err.to_string())
+    }
+}
+
+pub fn neigh_message(neigh: &FibNeigh) -> NeighbourMessage {
+    let mut msg = NeighbourMessage::default();
+    msg.header.ifindex = neigh.link_index;
+    msg.header.state = NeighbourState::Permanent;
+    let addr = match neigh.addr {
+        IpAddr::V4(v4) => {
+            msg.header.family = AddressFamily::Inet;
+            NeighbourAddress::Inet(v4)
+        }
+        IpAddr::V6(v6) => {
+            msg.header.family = AddressFamily::Inet6;
+            NeighbourAddress::Inet6(v6)
+        }
+    };
+    msg.attributes.push(NeighbourAttribute::Destination(addr));
+    msg
+}
+
+fn neigh_state(state: NeighbourState) -> NeighState {
+    match state {
+        NeighbourState::Incomplete => NeighState::Incomplete,
+        NeighbourState::Reachable => NeighState::Reachable,
+        NeighbourState::Stale => NeighState::Stale,
+        NeighbourState::Delay => NeighState::Delay,
+        NeighbourState::Probe => NeighState::Probe,
+        NeighbourState::Failed => NeighState::Failed,
+        NeighbourState::Noarp => NeighState::Noarp,
+        NeighbourState::Permanent => NeighState::Permanent,
+        _ => NeighState::None,
+    }
+}
+
+// Entries without an IP address, e.g. the bridge FDB, and proxy entries are
+// ignored.
+fn neigh_from_msg(msg: NeighbourMessage) -> Option<FibNeigh> {
+    if msg.header.flags.contains(&NeighbourFlag::Proxy) {
+        return None;
+    }
+    let mut addr = None;
+    let mut mac = None;
+    for attr in msg.attributes.into_iter() {
+        match attr {
+            NeighbourAttribute::Destination(NeighbourAddress::Inet(v4)) => {
+                addr = Some(IpAddr::V4(v4));
+            }
+            NeighbourAttribute::Destination(NeighbourAddress::Inet6(v6)) => {
+                addr = Some(IpAddr::V6(v6));
+            }
+            NeighbourAttribute::LinkLocalAddress(lladdr) => {
+                mac = MacAddr::from_slice(&lladdr);
+            }
+            _ => {}
+        }
+    }
+    Some(FibNeigh {
+        addr: addr?,
+        link_index: msg.header.ifindex,
+        mac,
+        state: neigh_state(msg.header.state),
+        router: msg.header.flags.contains(&NeighbourFlag::Router),
+    })
+}
+
+// Table id does not fit in the header beyond 255, it is always carried in
+// the FRA_TABLE attribute as iproute2 does.
+pub fn rule_message(rule: &FibRule) -> RuleMessage {
+    let mut msg = RuleMessage::default();
+    msg.header.family = AddressFamily::Inet;
+    msg.header.action = RuleAction::ToTable;
+    msg.header.table = u8::try_from(rule.table).unwrap_or(RouteHeader::RT_TABLE_UNSPEC);
+    msg.attributes.push(RuleAttribute::Priority(rule.priority));
+    msg.attributes.push(RuleAttribute::Table(rule.table));
+    if let Some(from) = rule.from {
+        msg.header.src_len = from.prefix_len();
+        msg.attributes
+            .push(RuleAttribute::Source(IpAddr::V4(from.network())));
+    }
+    if let Some(to) = rule.to {
+        msg.header.dst_len = to.prefix_len();
+        msg.attributes
+            .push(RuleAttribute::Destination(IpAddr::V4(to.network())));
+    }
+    if let Some(fwmark) = rule.fwmark {
+        msg.attributes.push(RuleAttribute::FwMark(fwmark));
+    }
+    msg
+}
+
+// Table id beyond 255 does not fit in the header either, the header has
+// RT_TABLE_UNSPEC and the id is in the RTA_TABLE attribute.
+fn route_table_set(msg: &mut RouteMessage, table: u32) {
+    msg.header.table = u8::try_from(table).unwrap_or(RouteHeader::RT_TABLE_UNSPEC);
+    msg.attributes.push(RouteAttribute::Table(table));
+}
+
+// Table of the route, RTA_TABLE when present.
+fn route_table(msg: &RouteMessage) -> u32 {
+    msg.attributes
+        .iter()
+        .find_map(|attr| match attr {
+            RouteAttribute::Table(table) => Some(*table),
+            _ => None,
+        })
+        .unwrap_or(msg.header.table as u32)
+}
+
+// Routes of the tables not in use are not imported.
+fn route_dump_filter(msg: &RouteMessage, tables: &BTreeSet<u32>) -> bool {
+    tables.contains(&route_table(msg))
+}
+
+// Routes installed by zebra are marked with the protocol, so that they are
+// told apart from the kernel routes, also those of the previous instance.
+pub const RTPROT_ZEBRA: RouteProtocol = RouteProtocol::Zebra;
+
+// More than one nexthop is installed as a multipath route with the weights
+// of the nexthops.
+pub fn route_add_message(table: u32, dest: Ipv4Net, nhops: &[Nexthop]) -> RouteMessage {
+    let mut msg = RouteMessage::default();
+    msg.header.address_family = AddressFamily::Inet;
+    msg.header.destination_prefix_length = dest.prefix_len();
+    msg.header.protocol = RTPROT_ZEBRA;
+    msg.header.scope = RouteScope::Universe;
+    msg.header.kind = RouteType::Unicast;
+    route_table_set(&mut msg, table);
+    msg.attributes
+        .push(RouteAttribute::Destination(RouteAddress::Inet(dest.addr())));
+    let Some(nhop) = nhops.first() else {
+        return msg;
+    };
+    msg.header.kind = route_type(nhop.ntype);
+    if nhops.len() > 1 {
+        let paths = nhops
+            .iter()
+            .zip(ecmp_weights(nhops))
+            .map(|(nhop, weight)| {
+                let mut path = RouteNextHop::default();
+                // Kernel weight is hops + 1.
+                path.hops = weight - 1;
+                path.attributes
+                    .push(RouteAttribute::Gateway(RouteAddress::Inet(nhop.nexthop)));
+                path
+            })
+            .collect();
+        msg.attributes.push(RouteAttribute::MultiPath(paths));
+    } else if !nhop.ntype.is_discard() {
+        msg.attributes
+            .push(RouteAttribute::Gateway(RouteAddress::Inet(nhop.nexthop)));
+    }
+    msg
+}
+
+fn route_type(ntype: NexthopType) -> RouteType {
+    match ntype {
+        NexthopType::Gateway => RouteType::Unicast,
+        NexthopType::Null0 | NexthopType::Blackhole => RouteType::BlackHole,
+        NexthopType::Reject => RouteType::Unreachable,
+        NexthopType::Prohibit => RouteType::Prohibit,
+    }
 }
 
 fn flags_u32(f: &LinkFlag) -> u32 {
@@ -121,6 +456,12 @@ fn addr_from_msg(msg: AddressMessage) -> FibAddr {
                     }
                 }
             },
+            AddressAttribute::Flags(flags) => {
+                os_addr.dad = DadState::from_flags(
+                    flags.contains(&AddressFlag::Tentative),
+                    flags.contains(&AddressFlag::Dadfailed),
+                );
+            }
             _ => {
                 //
             }
@@ -133,6 +474,8 @@ fn route_from_msg(msg: RouteMessage) -> FibRoute {
     let mut route = FibRoute {
         route: IpNet::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).unwrap(),
         gateway: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        table: route_table(&msg),
+        owned: msg.header.protocol == RTPROT_ZEBRA,
     };
 
     for attr in msg.attributes.into_iter() {
@@ -183,7 +526,7 @@ fn process_msg(msg: NetlinkMessage<RouteNetlinkMessage>, tx: UnboundedSender<Fib
             }
             RouteNetlinkMessage::NewRoute(msg) => {
                 let route = route_from_msg(msg);
-                if !route.gateway.is_unspecified() {
+                if route.owned || !route.gateway.is_unspecified() {
                     let msg = FibMessage::NewRoute(route);
                     tx.send(msg).unwrap();
                 }
@@ -193,6 +536,16 @@ fn process_msg(msg: NetlinkMessage<RouteNetlinkMessage>, tx: UnboundedSender<Fib
                 let msg = FibMessage::DelRoute(route);
                 tx.send(msg).unwrap();
             }
+            RouteNetlinkMessage::NewNeighbour(msg) => {
+                if let Some(neigh) = neigh_from_msg(msg) {
+                    tx.send(FibMessage::NewNeigh(neigh)).unwrap();
+                }
+            }
+            RouteNetlinkMessage::DelNeighbour(msg) => {
+                if let Some(neigh) = neigh_from_msg(msg) {
+                    tx.send(FibMessage::DelNeigh(neigh)).unwrap();
+                }
+            }
             _ => {}
         },
         _ => {}
@@ -223,9 +576,13 @@ async fn route_dump(
     handle: rtnetlink::Handle,
     tx: UnboundedSender<FibMessage>,
     ip_version: IpVersion,
+    tables: &BTreeSet<u32>,
 ) -> Result<()> {
     let mut routes = handle.route().get(ip_version).execute();
     while let Some(msg) = routes.try_next().await? {
+        if !route_dump_filter(&msg, tables) {
+            continue;
+        }
         let route = route_from_msg(msg);
         let msg = FibMessage::NewRoute(route);
         tx.send(msg).unwrap();
@@ -233,6 +590,16 @@ async fn route_dump(
     Ok(())
 }
 
+async fn neigh_dump(handle: rtnetlink::Handle, tx: UnboundedSender<FibMessage>) -> Result<()> {
+    let mut neighbours = handle.neighbours().get().execute();
+    while let Some(msg) = neighbours.try_next().await? {
+        if let Some(neigh) = neigh_from_msg(msg) {
+            tx.send(FibMessage::NewNeigh(neigh)).unwrap();
+        }
+    }
+    Ok(())
+}
+
 pub async fn route_add(handle: rtnetlink::Handle, dest: Ipv4Net, gateway: Ipv4Addr) {
     let result = handle
         .route()
@@ -264,7 +631,7 @@ impl RouteDelMessage {
             message: RouteMessage::default(),
         };
         msg.message.header.table = RouteHeader::RT_TABLE_MAIN;
-        msg.message.header.protocol = RouteProtocol::Static;
+        msg.message.header.protocol = RTPROT_ZEBRA;
         msg.message.header.scope = RouteScope::Universe;
         msg.message.header.kind = RouteType::Unicast;
 
@@ -287,6 +654,16 @@ impl RouteDelMessage {
         self
     }
 
+    pub fn table(mut self, table: u32) -> Self {
+        route_table_set(&mut self.message, table);
+        self
+    }
+
+    pub fn kind(mut self, kind: RouteType) -> Self {
+        self.message.header.kind = kind;
+        self
+    }
+
     pub fn build(self) -> RouteMessage {
         self.message
     }
@@ -312,11 +689,17 @@ pub async fn route_del(handle: rtnetlink::Handle, dest: Ipv4Net, gateway: Ipv4Ad
     }
 }
 
-pub async fn fib_dump(handle: &FibHandle, tx: UnboundedSender<FibMessage>) -> Result<()> {
+// Routes are imported from the tables in use.
+pub async fn fib_dump(
+    handle: &FibHandle,
+    tx: UnboundedSender<FibMessage>,
+    tables: &BTreeSet<u32>,
+) -> Result<()> {
     link_dump(handle.handle.clone(), tx.clone()).await?;
     address_dump(handle.handle.clone(), tx.clone()).await?;
-    route_dump(handle.handle.clone(), tx.clone(), IpVersion::V4).await?;
-    route_dump(handle.handle.clone(), tx.clone(), IpVersion::V6).await?;
+    route_dump(handle.handle.clone(), tx.clone(), IpVersion::V4, tables).await?;
+    route_dump(handle.handle.clone(), tx.clone(), IpVersion::V6, tables).await?;
+    neigh_dump(handle.handle.clone(), tx.clone()).await?;
     Ok(())
 }
 
@@ -474,7 +857,7 @@ pub fn os_traffic_parse(version: i32, line: &str) -> Result<LinkStats, Box<dyn E
     Ok(stats)
 }
 
-pub fn os_traffic_dump() -> impl Fn(&String, &mut String) {
+fn os_traffic_read() -> HashMap<String, LinkStats> {
     let mut stat_map = HashMap::new();
     if let Ok(lines) = read_lines("/proc/net/dev") {
         let mut lines = lines.map_while(Result::ok);
@@ -495,6 +878,99 @@ pub fn os_traffic_dump() -> impl Fn(&String, &mut String) {
             }
         }
     }
+    stat_map
+}
+
+// Counters of the interface in the link message. IFLA_STATS64 is used when
+// present, otherwise the 32-bit IFLA_STATS.
+fn link_counters_from_msg(msg: &LinkMessage) -> Option<(String, LinkCounters)> {
+    let mut name = None;
+    let mut stats64: Option<&Stats64> = None;
+    let mut stats: Option<&Stats> = None;
+    for attr in msg.attributes.iter() {
+        match attr {
+            LinkAttribute::IfName(ifname) => name = Some(ifname.clone()),
+            LinkAttribute::Stats64(s) => stats64 = Some(s),
+            LinkAttribute::Stats(s) => stats = Some(s),
+            _ => {}
+        }
+    }
+    let counters = match (stats64, stats) {
+        (Some(s), _) => LinkCounters {
+            rx_bytes: s.rx_bytes,
+            rx_packets: s.rx_packets,
+            rx_errors: s.rx_errors,
+            rx_dropped: s.rx_dropped,
+            tx_bytes: s.tx_bytes,
+            tx_packets: s.tx_packets,
+            tx_errors: s.tx_errors,
+            tx_dropped: s.tx_dropped,
+            wrap32: false,
+        },
+        (None, Some(s)) => LinkCounters {
+            rx_bytes: s.rx_bytes.into(),
+            rx_packets: s.rx_packets.into(),
+            rx_errors: s.rx_errors.into(),
+            rx_dropped: s.rx_dropped.into(),
+            tx_bytes: s.tx_bytes.into(),
+            tx_packets: s.tx_packets.into(),
+            tx_errors: s.tx_errors.into(),
+            tx_dropped: s.tx_dropped.into(),
+            wrap32: true,
+        },
+        (None, None) => return None,
+    };
+    Some((name?, counters))
+}
+
+// Counters of the interfaces for the statistics polling, taken from the link
+// dump. /proc/net/dev is read when the dump fails.
+pub struct LinkPoller {
+    handle: rtnetlink::Handle,
+}
+
+impl LinkPoller {
+    pub async fn counters(&self) -> Vec<(String, LinkCounters)> {
+        match self.link_counters().await {
+            Ok(counters) => counters,
+            Err(_) => tokio::task::spawn_blocking(os_link_counters)
+                .await
+                .unwrap_or_default(),
+        }
+    }
+
+    async fn link_counters(&self) -> Result<Vec<(String, LinkCounters)>> {
+        let mut links = self.handle.link().get().execute();
+        let mut counters = Vec::new();
+        while let Some(msg) = links.try_next().await? {
+            counters.extend(link_counters_from_msg(&msg));
+        }
+        Ok(counters)
+    }
+}
+
+fn os_link_counters() -> Vec<(String, LinkCounters)> {
+    os_traffic_read()
+        .into_iter()
+        .map(|(name, stats)| {
+            let counters = LinkCounters {
+                rx_bytes: stats.rx_bytes,
+                rx_packets: stats.rx_packets.into(),
+                rx_errors: stats.rx_errors.into(),
+                rx_dropped: stats.rx_dropped.into(),
+                tx_bytes: stats.tx_bytes,
+                tx_packets: stats.tx_packets.into(),
+                tx_errors: stats.tx_errors.into(),
+                tx_dropped: stats.tx_dropped.into(),
+                wrap32: true,
+            };
+            (name, counters)
+        })
+        .collect()
+}
+
+pub fn os_traffic_dump() -> impl Fn(&String, &mut String) {
+    let stat_map = os_traffic_read();
     move |link_name: &String, buf: &mut String| {
         if let Some(stat) = stat_map.get(link_name) {
             writeln!(
@@ -525,3 +1001,202 @@ pub fn os_traffic_dump() -> impl Fn(&String, &mut String) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rule_encode() {
+        let rule = FibRule {
+            priority: 100,
+            from: "10.1.1.0/24".parse().ok(),
+            to: None,
+            fwmark: None,
+            table: 10,
+        };
+        let msg = rule_message(&rule);
+        assert_eq!(msg.header.family, AddressFamily::Inet);
+        assert_eq!(msg.header.src_len, 24);
+        assert_eq!(msg.header.dst_len, 0);
+        assert_eq!(msg.header.table, 10);
+        assert!(msg
+            .attributes
+            .contains(&RuleAttribute::Source("10.1.1.0".parse().unwrap())));
+        assert!(msg.attributes.contains(&RuleAttribute::Table(10)));
+        assert!(msg.attributes.contains(&RuleAttribute::Priority(100)));
+
+        // Table id beyond the header.
+        let rule = FibRule {
+            priority: 200,
+            from: None,
+            to: "192.168.0.0/16".parse().ok(),
+            fwmark: Some(1),
+            table: 1000,
+        };
+        let msg = rule_message(&rule);
+        assert_eq!(msg.header.table, RouteHeader::RT_TABLE_UNSPEC);
+        assert_eq!(msg.header.src_len, 0);
+        assert_eq!(msg.header.dst_len, 16);
+        assert!(msg.attributes.contains(&RuleAttribute::Table(1000)));
+        assert!(msg.attributes.contains(&RuleAttribute::FwMark(1)));
+        assert!(!msg
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, RuleAttribute::Source(_))));
+    }
+
+    #[test]
+    fn route_table_encode() {
+        let dest: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+        let nhops = vec![Nexthop::new("192.168.0.1".parse().unwrap())];
+        let msg = route_add_message(100, dest, &nhops);
+        assert_eq!(msg.header.table, 100);
+        assert!(msg.attributes.contains(&RouteAttribute::Table(100)));
+        assert_eq!(msg.header.destination_prefix_length, 8);
+        assert_eq!(msg.header.kind, RouteType::Unicast);
+        assert!(msg
+            .attributes
+            .contains(&RouteAttribute::Gateway(RouteAddress::Inet(
+                "192.168.0.1".parse().unwrap()
+            ))));
+        assert_eq!(route_table(&msg), 100);
+
+        // Table id beyond the header.
+        let msg = route_add_message(1000, dest, &nhops);
+        assert_eq!(msg.header.table, RouteHeader::RT_TABLE_UNSPEC);
+        assert!(msg.attributes.contains(&RouteAttribute::Table(1000)));
+        assert_eq!(route_table(&msg), 1000);
+
+        let msg = RouteDelMessage::new()
+            .destination(dest.addr(), dest.prefix_len())
+            .table(100)
+            .build();
+        assert_eq!(msg.header.table, 100);
+        assert!(msg.attributes.contains(&RouteAttribute::Table(100)));
+
+        let msg = route_add_message(RT_TABLE_MAIN, dest, &nhops);
+        assert_eq!(msg.header.table, RouteHeader::RT_TABLE_MAIN);
+    }
+
+    #[test]
+    fn route_dump_table() {
+        let dest: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+        let nhops = vec![Nexthop::new("192.168.0.1".parse().unwrap())];
+        let tables = BTreeSet::from([RT_TABLE_MAIN, 100, 1000]);
+        assert!(route_dump_filter(
+            &route_add_message(RT_TABLE_MAIN, dest, &nhops),
+            &tables
+        ));
+        assert!(route_dump_filter(
+            &route_add_message(100, dest, &nhops),
+            &tables
+        ));
+        assert!(route_dump_filter(
+            &route_add_message(1000, dest, &nhops),
+            &tables
+        ));
+        assert!(!route_dump_filter(
+            &route_add_message(200, dest, &nhops),
+            &tables
+        ));
+
+        // Header only, as the kernel sends the routes of the local table.
+        let mut msg = RouteMessage::default();
+        msg.header.table = 255;
+        assert!(!route_dump_filter(&msg, &tables));
+        msg.header.table = RouteHeader::RT_TABLE_MAIN;
+        assert!(route_dump_filter(&msg, &tables));
+
+        let route = route_from_msg(route_add_message(100, dest, &nhops));
+        assert_eq!(route.table, 100);
+        assert_eq!(route.route, IpNet::V4(dest));
+    }
+
+    #[test]
+    fn link_counters_decode() {
+        let mut msg = LinkMessage::default();
+        msg.header.index = 2;
+        msg.attributes
+            .push(LinkAttribute::IfName("eth0".to_string()));
+        msg.attributes.push(LinkAttribute::Stats(Stats {
+            rx_bytes: 1,
+            ..Default::default()
+        }));
+        msg.attributes.push(LinkAttribute::Stats64(Stats64 {
+            rx_packets: 10,
+            tx_packets: 20,
+            rx_bytes: 5_000_000_000,
+            tx_bytes: 2_000,
+            rx_errors: 1,
+            tx_errors: 2,
+            rx_dropped: 3,
+            tx_dropped: 4,
+            ..Default::default()
+        }));
+        let (name, counters) = link_counters_from_msg(&msg).unwrap();
+        assert_eq!(name, "eth0");
+        // 64-bit counters take precedence.
+        assert_eq!(
+            counters,
+            LinkCounters {
+                rx_bytes: 5_000_000_000,
+                rx_packets: 10,
+                rx_errors: 1,
+                rx_dropped: 3,
+                tx_bytes: 2_000,
+                tx_packets: 20,
+                tx_errors: 2,
+                tx_dropped: 4,
+                wrap32: false,
+            }
+        );
+
+        // 32-bit counters only.
+        let mut msg = LinkMessage::default();
+        msg.attributes.push(LinkAttribute::IfName("lo".to_string()));
+        msg.attributes.push(LinkAttribute::Stats(Stats {
+            rx_bytes: 100,
+            tx_packets: 2,
+            ..Default::default()
+        }));
+        let (_, counters) = link_counters_from_msg(&msg).unwrap();
+        assert_eq!(counters.rx_bytes, 100);
+        assert!(counters.wrap32);
+        assert_eq!(counters.tx_packets, 2);
+
+        // Without the counters.
+        let mut msg = LinkMessage::default();
+        msg.attributes.push(LinkAttribute::IfName("lo".to_string()));
+        assert_eq!(link_counters_from_msg(&msg), None);
+    }
+
+    #[test]
+    fn neigh_decode() {
+        let neigh = FibNeigh {
+            addr: "fe80::1".parse().unwrap(),
+            link_index: 3,
+            mac: "02:00:00:00:00:01".parse().ok(),
+            state: NeighState::Permanent,
+            router: false,
+        };
+        let mut msg = neigh_message(&neigh);
+        assert_eq!(msg.header.family, AddressFamily::Inet6);
+        msg.header.state = NeighbourState::Stale;
+        msg.header.flags.push(NeighbourFlag::Router);
+        msg.attributes
+            .push(NeighbourAttribute::LinkLocalAddress(vec![2, 0, 0, 0, 0, 1]));
+        let decoded = neigh_from_msg(msg).unwrap();
+        assert_eq!(decoded.addr, neigh.addr);
+        assert_eq!(decoded.link_index, 3);
+        assert_eq!(decoded.mac, neigh.mac);
+        assert_eq!(decoded.state, NeighState::Stale);
+        assert!(decoded.router);
+
+        // Entry without an IP address.
+        let mut msg = NeighbourMessage::default();
+        msg.attributes
+            .push(NeighbourAttribute::LinkLocalAddress(vec![2, 0, 0, 0, 0, 1]));
+        assert_eq!(neigh_from_msg(msg), None);
+    }
+}
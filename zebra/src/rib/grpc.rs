@@ -0,0 +1,264 @@
+use super::api::RibTx;
+use super::entry::{RibEntry, RibType};
+use super::inject::{ApiRoute, API_DISTANCE};
+use super::nexthop::Nexthop;
+use super::watch::{self, RouteWatch, WatchFilter, WATCH_QUEUE};
+use ipnet::Ipv4Net;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+mod routes {
+    tonic::include_proto!("routes");
+}
+use routes::routes_server::{Routes, RoutesServer};
+use routes::{Route, RouteEvent, RouteEventType, RouteReply, SyncOp, SyncRequest, WatchRequest};
+
+fn api_route(route: &Route) -> Result<ApiRoute, Status> {
+    let prefix: Ipv4Net = route
+        .prefix
+        .parse()
+        .map_err(|_| Status::invalid_argument(format!("invalid prefix {}", route.prefix)))?;
+    if route.owner.is_empty() {
+        return Err(Status::invalid_argument("owner is not specified"));
+    }
+    let mut nexthops = Vec::new();
+    for nhop in route.nexthops.iter() {
+        let addr: Ipv4Addr = nhop
+            .address
+            .parse()
+            .map_err(|_| Status::invalid_argument(format!("invalid nexthop {}", nhop.address)))?;
+        let mut nexthop = Nexthop::new(addr);
+        nexthop.ifname = nhop.interface.clone();
+        nexthop.labels = nhop.labels.clone();
+        nexthops.push(nexthop);
+    }
+    Ok(ApiRoute {
+        prefix: prefix.trunc(),
+        nexthops,
+        metric: route.metric,
+        distance: if route.distance == 0 {
+            API_DISTANCE
+        } else {
+            route.distance
+        },
+        owner: route.owner.clone(),
+    })
+}
+
+fn reply(prefix: &str, result: Result<(), Status>) -> RouteReply {
+    match result {
+        Ok(_) => RouteReply {
+            result: 0,
+            prefix: prefix.to_string(),
+            message: String::new(),
+        },
+        Err(status) => RouteReply {
+            result: -1,
+            prefix: prefix.to_string(),
+            message: status.message().to_string(),
+        },
+    }
+}
+
+fn watch_filter(request: &WatchRequest) -> Result<WatchFilter, Status> {
+    let ipv4 = match request.afi.as_str() {
+        "" | "ipv4" => true,
+        "ipv6" => false,
+        afi => return Err(Status::invalid_argument(format!("invalid afi {}", afi))),
+    };
+    let mut protocols = Vec::new();
+    for protocol in request.protocols.iter() {
+        let rtype: RibType = protocol
+            .parse()
+            .map_err(|_| Status::invalid_argument(format!("invalid protocol {}", protocol)))?;
+        protocols.push(rtype);
+    }
+    Ok(WatchFilter { ipv4, protocols })
+}
+
+fn entry_route(prefix: &Ipv4Net, e: &RibEntry) -> Route {
+    let mut nexthops: Vec<routes::Nexthop> = e
+        .nexthops
+        .iter()
+        .map(|nhop| routes::Nexthop {
+            address: nhop.nexthop.to_string(),
+            interface: nhop.ifname.clone(),
+            labels: nhop.labels.clone(),
+        })
+        .collect();
+    if nexthops.is_empty() && !e.gateway.is_unspecified() {
+        nexthops.push(routes::Nexthop {
+            address: e.gateway.to_string(),
+            interface: String::new(),
+            labels: Vec::new(),
+        });
+    }
+    Route {
+        prefix: prefix.to_string(),
+        nexthops,
+        metric: e.metric,
+        distance: e.distance,
+        owner: e.owner.clone(),
+    }
+}
+
+fn route_event(typ: RouteEventType, route: Option<(Ipv4Net, RibEntry)>) -> RouteEvent {
+    RouteEvent {
+        r#type: typ as i32,
+        protocol: route
+            .as_ref()
+            .map(|(_, e)| e.rtype.name().to_string())
+            .unwrap_or_default(),
+        route: route.map(|(prefix, e)| entry_route(&prefix, &e)),
+    }
+}
+
+// Snapshot is streamed as the routes followed by SnapshotEnd.
+fn route_events(event: watch::RouteEvent) -> Vec<RouteEvent> {
+    match event {
+        watch::RouteEvent::Add(prefix, e) => {
+            vec![route_event(RouteEventType::RouteAdd, Some((prefix, e)))]
+        }
+        watch::RouteEvent::Delete(prefix, e) => {
+            vec![route_event(RouteEventType::RouteDelete, Some((prefix, e)))]
+        }
+        watch::RouteEvent::Change(prefix, e) => {
+            vec![route_event(RouteEventType::RouteChange, Some((prefix, e)))]
+        }
+        watch::RouteEvent::Snapshot(routes) => routes
+            .into_iter()
+            .map(|route| route_event(RouteEventType::RouteAdd, Some(route)))
+            .chain(std::iter::once(route_event(
+                RouteEventType::SnapshotEnd,
+                None,
+            )))
+            .collect(),
+    }
+}
+
+#[derive(Debug)]
+struct RouteService {
+    tx: Sender<RibTx>,
+}
+
+impl RouteService {
+    async fn send(&self, msg: RibTx) -> Result<(), Status> {
+        self.tx
+            .send(msg)
+            .await
+            .map_err(|_| Status::unavailable("rib is not running"))
+    }
+}
+
+#[tonic::async_trait]
+impl Routes for RouteService {
+    async fn add_route(&self, request: Request<Route>) -> Result<Response<RouteReply>, Status> {
+        let route = api_route(request.get_ref())?;
+        self.send(RibTx::ApiRouteAdd(route)).await?;
+        Ok(Response::new(reply(&request.get_ref().prefix, Ok(()))))
+    }
+
+    async fn delete_route(&self, request: Request<Route>) -> Result<Response<RouteReply>, Status> {
+        let route = api_route(request.get_ref())?;
+        self.send(RibTx::ApiRouteDel(route)).await?;
+        Ok(Response::new(reply(&request.get_ref().prefix, Ok(()))))
+    }
+
+    type SyncRoutesStream = ReceiverStream<Result<RouteReply, Status>>;
+
+    async fn sync_routes(
+        &self,
+        request: Request<Streaming<SyncRequest>>,
+    ) -> Result<Response<Self::SyncRoutesStream>, Status> {
+        let mut stream = request.into_inner();
+        let rib = self.tx.clone();
+        let (tx, rx) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            // Owners seen on this stream with their persist flag and sweep
+            // timeout. The latest request of the owner wins.
+            let mut owners: BTreeMap<String, (bool, u32)> = BTreeMap::new();
+
+            while let Ok(Some(req)) = stream.message().await {
+                let Some(route) = req.route.as_ref() else {
+                    continue;
+                };
+                let result = api_route(route);
+                if let Ok(route) = result.as_ref() {
+                    owners.insert(route.owner.clone(), (req.persist, req.sweep_timeout));
+                    let msg = if req.op == SyncOp::Delete as i32 {
+                        RibTx::ApiRouteDel(route.clone())
+                    } else {
+                        RibTx::ApiRouteAdd(route.clone())
+                    };
+                    if rib.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                let _ = tx.send(Ok(reply(&route.prefix, result.map(|_| ())))).await;
+            }
+
+            // Stream has been disconnected.
+            for (owner, (persist, sweep)) in owners.into_iter() {
+                let sweep = if sweep == 0 {
+                    None
+                } else {
+                    Some(Duration::from_secs(sweep as u64))
+                };
+                let _ = rib
+                    .send(RibTx::ApiOwnerDown {
+                        owner,
+                        persist,
+                        sweep,
+                    })
+                    .await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type WatchRoutesStream = ReceiverStream<Result<RouteEvent, Status>>;
+
+    async fn watch_routes(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchRoutesStream>, Status> {
+        let filter = watch_filter(request.get_ref())?;
+        let (watch_tx, mut watch_rx) = mpsc::channel(WATCH_QUEUE);
+        self.send(RibTx::Watch(RouteWatch {
+            filter,
+            tx: watch_tx,
+        }))
+        .await?;
+
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Some(event) = watch_rx.recv().await {
+                for event in route_events(event).into_iter() {
+                    if tx.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+pub fn api_serve(tx: Sender<RibTx>) {
+    let route_server = RoutesServer::new(RouteService { tx });
+
+    let addr = "0.0.0.0:2651".parse().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(route_server)
+            .serve(addr)
+            .await
+    });
+}
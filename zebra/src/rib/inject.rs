@@ -0,0 +1,273 @@
+use super::ecmp::ecmp_select;
+use super::entry::{RibEntry, RibType};
+use super::instance::Rib;
+use super::nexthop::Nexthop;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// Routes injected by external controllers through the gRPC API. Routes are
+// kept per owner tag so that all of the routes of a controller can be
+// withdrawn when its stream is disconnected.
+
+// Distance used when the client does not specify one.
+pub const API_DISTANCE: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiRoute {
+    pub prefix: Ipv4Net,
+    pub nexthops: Vec<Nexthop>,
+    pub metric: u32,
+    pub distance: u32,
+    pub owner: String,
+}
+
+impl ApiRoute {
+    fn entry(&self) -> RibEntry {
+        let mut e = RibEntry::new(RibType::API);
+        e.distance = self.distance;
+        e.metric = self.metric;
+        e.owner = self.owner.clone();
+        if let Some(nhop) = self.nexthops.first() {
+            e.gateway = IpAddr::V4(nhop.nexthop);
+        }
+        e.nexthops = self.nexthops.clone();
+        e
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApiOwner {
+    pub routes: BTreeMap<Ipv4Net, ApiRoute>,
+    pub stale: BTreeSet<Ipv4Net>,
+    pub sweep: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct ApiRoutes {
+    pub owners: BTreeMap<String, ApiOwner>,
+}
+
+// Select the entry with the lowest distance, then the lowest metric. The tie
+// is broken by the protocol, the route type in the protocol and the owner so
+// that the selection does not depend on the order the routes are learned in.
+// The first one wins when they are all the same.
+pub fn rib_select(entries: &mut [RibEntry]) {
+    let key = |e: &RibEntry| (e.distance, e.metric, e.rtype.clone(), e.rsubtype.clone());
+    let best = entries
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| key(a).cmp(&key(b)).then(a.owner.cmp(&b.owner)))
+        .map(|(i, _)| i);
+    for (i, e) in entries.iter_mut().enumerate() {
+        e.selected = Some(i) == best;
+    }
+}
+
+fn rib_entry_add(rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>, route: &ApiRoute) {
+    let entries = rib.entry(route.prefix).or_default();
+    entries.retain(|e| !(e.rtype == RibType::API && e.owner == route.owner));
+    entries.push(route.entry());
+    rib_select(entries);
+}
+
+fn rib_entry_del(rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>, prefix: &Ipv4Net, owner: &str) {
+    if let Some(entries) = rib.get_mut(prefix) {
+        entries.retain(|e| !(e.rtype == RibType::API && e.owner == owner));
+        if entries.is_empty() {
+            rib.remove(prefix);
+        } else {
+            rib_select(entries);
+        }
+    }
+}
+
+impl ApiRoutes {
+    pub fn add(&mut self, rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>, route: ApiRoute) {
+        rib_entry_add(rib, &route);
+        let owner = self.owners.entry(route.owner.clone()).or_default();
+        owner.stale.remove(&route.prefix);
+        owner.routes.insert(route.prefix, route);
+    }
+
+    pub fn delete(
+        &mut self,
+        rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+        prefix: &Ipv4Net,
+        owner: &str,
+    ) -> bool {
+        let Some(o) = self.owners.get_mut(owner) else {
+            return false;
+        };
+        if o.routes.remove(prefix).is_none() {
+            return false;
+        }
+        o.stale.remove(prefix);
+        if o.routes.is_empty() {
+            self.owners.remove(owner);
+        }
+        rib_entry_del(rib, prefix, owner);
+        true
+    }
+
+    // The stream of the owner has been disconnected. Routes are withdrawn
+    // right away unless the owner asked them to persist, in which case they
+    // are marked stale and swept at `sweep` unless re-added before that.
+    pub fn owner_down(
+        &mut self,
+        rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+        owner: &str,
+        persist: bool,
+        sweep: Option<Instant>,
+    ) {
+        if persist {
+            if let Some(o) = self.owners.get_mut(owner) {
+                o.stale = o.routes.keys().cloned().collect();
+                o.sweep = sweep;
+            }
+        } else if let Some(o) = self.owners.remove(owner) {
+            for prefix in o.routes.keys() {
+                rib_entry_del(rib, prefix, owner);
+            }
+        }
+    }
+
+    // Remove stale routes of the owners which sweep time has passed. Returns
+    // the number of removed routes.
+    pub fn sweep(&mut self, rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>, now: Instant) -> usize {
+        let mut count = 0;
+        for (name, o) in self.owners.iter_mut() {
+            if o.sweep.is_some_and(|sweep| sweep <= now) {
+                for prefix in std::mem::take(&mut o.stale).iter() {
+                    o.routes.remove(prefix);
+                    rib_entry_del(rib, prefix, name);
+                    count += 1;
+                }
+                o.sweep = None;
+            }
+        }
+        self.owners.retain(|_, o| !o.routes.is_empty());
+        count
+    }
+}
+
+impl Rib {
+    pub async fn api_route_add(&mut self, mut route: ApiRoute) {
+        ecmp_select(&mut route.nexthops, self.maximum_paths(&RibType::API));
+        self.watchers.touch(route.prefix);
+        self.injected.add(&mut self.rib, route);
+        self.nexthop_update().await;
+    }
+
+    pub async fn api_route_del(&mut self, route: ApiRoute) {
+        if self
+            .injected
+            .delete(&mut self.rib, &route.prefix, &route.owner)
+        {
+            self.watchers.touch(route.prefix);
+            self.nexthop_update().await;
+        }
+    }
+
+    pub async fn api_owner_down(&mut self, owner: String, persist: bool, sweep: Option<Duration>) {
+        let sweep = sweep.map(|sweep| Instant::now() + sweep);
+        self.injected
+            .owner_down(&mut self.rib, &owner, persist, sweep);
+        self.watchers.touch_all();
+        self.nexthop_update().await;
+    }
+
+    pub async fn api_sweep(&mut self) {
+        if self.injected.sweep(&mut self.rib, Instant::now()) > 0 {
+            self.watchers.touch_all();
+            self.nexthop_update().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn route(prefix: &str, owner: &str) -> ApiRoute {
+        ApiRoute {
+            prefix: prefix.parse().unwrap(),
+            nexthops: vec![Nexthop::new("192.168.0.1".parse().unwrap())],
+            metric: 0,
+            distance: API_DISTANCE,
+            owner: owner.to_string(),
+        }
+    }
+
+    fn api_count(rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>, owner: &str) -> usize {
+        rib.iter()
+            .flat_map(|(_, entries)| entries.iter())
+            .filter(|e| e.rtype == RibType::API && e.owner == owner)
+            .count()
+    }
+
+    #[test]
+    fn owner_cleanup() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut api = ApiRoutes::default();
+
+        let mut kernel = RibEntry::new(RibType::Kernel);
+        kernel.selected = true;
+        rib.insert("10.0.0.0/24".parse().unwrap(), vec![kernel]);
+
+        api.add(&mut rib, route("10.0.0.0/24", "a"));
+        api.add(&mut rib, route("10.0.1.0/24", "a"));
+        api.add(&mut rib, route("10.0.1.0/24", "b"));
+        assert_eq!(api_count(&rib, "a"), 2);
+        assert_eq!(api_count(&rib, "b"), 1);
+
+        // Kernel route with lower distance stays selected.
+        let entries = rib.get(&"10.0.0.0/24".parse().unwrap()).unwrap();
+        assert!(entries[0].selected);
+        assert!(!entries[1].selected);
+
+        api.owner_down(&mut rib, "a", false, None);
+        assert_eq!(api_count(&rib, "a"), 0);
+        assert_eq!(api_count(&rib, "b"), 1);
+        assert!(!api.owners.contains_key("a"));
+        assert_eq!(rib.get(&"10.0.0.0/24".parse().unwrap()).unwrap().len(), 1);
+        let entries = rib.get(&"10.0.1.0/24".parse().unwrap()).unwrap();
+        assert!(entries[0].selected);
+
+        assert!(api.delete(&mut rib, &"10.0.1.0/24".parse().unwrap(), "b"));
+        assert!(rib.get(&"10.0.1.0/24".parse().unwrap()).is_none());
+        assert!(api.owners.is_empty());
+    }
+
+    #[test]
+    fn persist_sweep() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut api = ApiRoutes::default();
+
+        api.add(&mut rib, route("10.0.0.0/24", "a"));
+        api.add(&mut rib, route("10.0.1.0/24", "a"));
+
+        let now = Instant::now();
+        let sweep = now + Duration::from_secs(10);
+        api.owner_down(&mut rib, "a", true, Some(sweep));
+        assert_eq!(api_count(&rib, "a"), 2);
+        assert_eq!(api.owners.get("a").unwrap().stale.len(), 2);
+
+        // Owner reconnected and re-added one of the routes.
+        api.add(&mut rib, route("10.0.1.0/24", "a"));
+        assert_eq!(api.sweep(&mut rib, now + Duration::from_secs(5)), 0);
+        assert_eq!(api_count(&rib, "a"), 2);
+
+        assert_eq!(api.sweep(&mut rib, sweep), 1);
+        assert!(rib.get(&"10.0.0.0/24".parse().unwrap()).is_none());
+        assert!(rib.get(&"10.0.1.0/24".parse().unwrap()).is_some());
+        assert!(api.owners.get("a").unwrap().sweep.is_none());
+
+        // Without sweep time stale routes are kept.
+        api.owner_down(&mut rib, "a", true, None);
+        assert_eq!(api.sweep(&mut rib, sweep + Duration::from_secs(3600)), 0);
+        assert_eq!(api_count(&rib, "a"), 1);
+    }
+}
@@ -1,29 +1,89 @@
-use super::api::RibRx;
+use super::api::{NexthopUpdate, PrefixUpdate, RibRx, RibTx};
 use super::config::config_dispatch;
+use super::distance::Distances;
+use super::ecmp::MaximumPaths;
 use super::entry::RibEntry;
 use super::fib::fib_dump;
-use super::fib::{FibChannel, FibHandle, FibMessage};
+use super::fib::{FibChannel, FibHandle, FibMessage, FibNeigh, FibRule};
+use super::inject::ApiRoutes;
+use super::label::LabelPool;
+use super::nd_proxy::NdProxies;
+use super::neigh::{Neighbors, StaticNeighbors};
+use super::ra::RaConfig;
+use super::restart::FibRestart;
+use super::router_id::RouterId;
+use super::rule::IpRules;
+use super::static_route::StaticRoutes;
+use super::stats::STATS_INTERVAL;
+use super::table::Tables;
+use super::vrf::{VpnExports, VpnKey, VpnRoute, Vrf};
+use super::watch::RouteWatchers;
 use super::{Link, RibTxChannel};
-use crate::config::{path_from_command, Args};
+use crate::bgp::task::Task;
+use crate::config::{path_from_command, show_path, Args};
 use crate::config::{ConfigChannel, ConfigOp, ConfigRequest, DisplayRequest, ShowChannel};
+use crate::config::{StateChannel, StateProviders, StateRequest};
+use crate::exit::ExitRx;
 use ipnet::Ipv4Net;
 use prefix_trie::PrefixMap;
-use std::collections::{BTreeMap, HashMap};
-use tokio::sync::mpsc::Sender;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
 // use tracing::warn;
 
-pub type ShowCallback = fn(&Rib, Args) -> String;
+pub type ShowCallback = fn(&Rib, Args, bool) -> String;
 
 pub struct Rib {
     pub api: RibTxChannel,
     pub cm: ConfigChannel,
     pub show: ShowChannel,
     pub show_cb: HashMap<String, ShowCallback>,
+    pub state: StateChannel,
+    pub state_cb: StateProviders<Rib>,
     pub fib: FibChannel,
     pub fib_handle: FibHandle,
-    pub redists: Vec<Sender<RibRx>>,
+    pub redists: Vec<UnboundedSender<RibRx>>,
     pub links: BTreeMap<u32, Link>,
     pub rib: PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    // Kernel routing tables other than the main one in use.
+    pub tables: Tables,
+    // Router Advertisement config by the interface name or pattern, and the
+    // tasks by the interface name.
+    pub ra: BTreeMap<String, RaConfig>,
+    pub ra_tasks: BTreeMap<String, Task<()>>,
+    pub nht: BTreeMap<Ipv4Addr, NexthopUpdate>,
+    pub prefix_watch: BTreeMap<Ipv4Net, PrefixUpdate>,
+    pub injected: ApiRoutes,
+    pub vrfs: BTreeMap<String, Vrf>,
+    pub vpn: BTreeMap<VpnKey, VpnRoute>,
+    // Routes of the VRFs exported to BGP.
+    pub vpn_export: VpnExports,
+    pub labels: LabelPool,
+    pub statics: StaticRoutes,
+    // Default table of the static routes, the main table unless configured.
+    pub static_table: Option<u32>,
+    pub maximum_paths: MaximumPaths,
+    pub distances: Distances,
+    pub rules: IpRules,
+    pub rules_fib: BTreeMap<u32, FibRule>,
+    pub neighbors: Neighbors,
+    pub static_neighbors: StaticNeighbors,
+    pub static_neighbors_fib: BTreeMap<IpAddr, FibNeigh>,
+    pub nd_proxy: NdProxies,
+    pub nd_proxy_fib: BTreeSet<(u32, Ipv6Addr)>,
+    pub connected_dirty: BTreeSet<Ipv4Net>,
+    pub watchers: RouteWatchers,
+    pub stats_interval: Duration,
+    pub stats_task: Option<JoinHandle<()>>,
+    // Routes are retained in the FIB on exit by the command line option or
+    // the config.
+    pub retain_routes_default: bool,
+    pub retain_routes: bool,
+    // Routes of the previous instance are reconciled after the grace period.
+    pub restart: FibRestart,
+    pub router_id: RouterId,
 }
 
 impl Rib {
@@ -35,17 +95,49 @@ impl Rib {
             cm: ConfigChannel::new(),
             show: ShowChannel::new(),
             show_cb: HashMap::new(),
+            state: StateChannel::new(),
+            state_cb: StateProviders::default(),
             fib,
             fib_handle,
             redists: Vec::new(),
             links: BTreeMap::new(),
             rib: prefix_trie::PrefixMap::new(),
+            tables: Tables::new(),
+            ra: BTreeMap::new(),
+            ra_tasks: BTreeMap::new(),
+            nht: BTreeMap::new(),
+            prefix_watch: BTreeMap::new(),
+            injected: ApiRoutes::default(),
+            vrfs: BTreeMap::new(),
+            vpn: BTreeMap::new(),
+            vpn_export: VpnExports::new(),
+            labels: LabelPool::default(),
+            statics: StaticRoutes::new(),
+            static_table: None,
+            maximum_paths: MaximumPaths::new(),
+            distances: Distances::new(),
+            rules: IpRules::new(),
+            rules_fib: BTreeMap::new(),
+            neighbors: Neighbors::new(),
+            static_neighbors: StaticNeighbors::new(),
+            static_neighbors_fib: BTreeMap::new(),
+            nd_proxy: NdProxies::new(),
+            nd_proxy_fib: BTreeSet::new(),
+            connected_dirty: BTreeSet::new(),
+            watchers: RouteWatchers::default(),
+            stats_interval: STATS_INTERVAL,
+            stats_task: None,
+            retain_routes_default: false,
+            retain_routes: false,
+            restart: FibRestart::default(),
+            router_id: RouterId::default(),
         };
         rib.show_build();
+        rib.state_build();
         Ok(rib)
     }
 
-    pub fn subscribe(&mut self, tx: Sender<RibRx>) {
+    pub fn subscribe(&mut self, tx: UnboundedSender<RibRx>) {
         self.redists.push(tx);
     }
 
@@ -69,6 +161,61 @@ impl Rib {
             FibMessage::DelRoute(route) => {
                 self.route_del(route);
             }
+            FibMessage::NewNeigh(neigh) => {
+                self.neigh_add(neigh);
+            }
+            FibMessage::DelNeigh(neigh) => {
+                self.neigh_del(neigh);
+            }
+            FibMessage::LinkStats(time, counters) => {
+                self.link_stats_update(time, counters);
+            }
+        }
+    }
+
+    async fn process_api_msg(&mut self, msg: RibTx) {
+        match msg {
+            RibTx::NexthopRegister(addr) => {
+                self.nexthop_register(addr).await;
+            }
+            RibTx::NexthopUnregister(addr) => {
+                self.nexthop_unregister(addr);
+            }
+            RibTx::PrefixRegister(prefix) => {
+                self.prefix_register(prefix).await;
+            }
+            RibTx::PrefixUnregister(prefix) => {
+                self.prefix_unregister(prefix);
+            }
+            RibTx::Watch(watch) => {
+                self.watchers.subscribe(watch, &self.rib);
+            }
+            RibTx::RouteAdd(prefix, e) => {
+                self.protocol_route_add(prefix, e).await;
+            }
+            RibTx::RouteDel(prefix, e) => {
+                self.protocol_route_del(prefix, e).await;
+            }
+            RibTx::ApiRouteAdd(route) => {
+                self.api_route_add(route).await;
+            }
+            RibTx::ApiRouteDel(route) => {
+                self.api_route_del(route).await;
+            }
+            RibTx::ApiOwnerDown {
+                owner,
+                persist,
+                sweep,
+            } => {
+                self.api_owner_down(owner, persist, sweep).await;
+            }
+            RibTx::VpnRouteAdd(route) => {
+                self.vpn_route_add(route);
+            }
+            RibTx::VpnRouteDel(route) => {
+                self.vpn_route_del(route);
+            }
+            _ => {}
         }
     }
 
@@ -86,34 +233,80 @@ impl Rib {
 
     async fn process_show_msg(&self, msg: DisplayRequest) {
         let (path, args) = path_from_command(&msg.paths);
+        let (path, json) = show_path(path);
         if let Some(f) = self.show_cb.get(&path) {
-            let output = f(self, args);
+            let output = f(self, args, json);
             msg.resp.send(output).await.unwrap();
         }
     }
 
-    pub async fn event_loop(&mut self) {
-        if let Err(_err) = fib_dump(&self.fib_handle, self.fib.tx.clone()).await {
+    fn process_state_msg(&self, msg: StateRequest) {
+        let _ = msg.resp.send(self.state_cb.get(self, &msg.path));
+    }
+
+    pub async fn event_loop(&mut self, mut exit: ExitRx) {
+        let tables = self.tables_configured();
+        if let Err(_err) = fib_dump(&self.fib_handle, self.fib.tx.clone(), &tables).await {
             // warn!("FIB dump error {}", err);
         }
+        self.stats_start();
+        let mut sweep = tokio::time::interval(Duration::from_secs(1));
         loop {
             tokio::select! {
                 Some(msg) = self.fib.rx.recv() => {
-                    self.process_fib_msg(msg);
+                    // Messages queued together, e.g. the deletion and the
+                    // addition of an address moved between interfaces, are
+                    // applied at once.
+                    let mut link = false;
+                    let mut addr = false;
+                    let mut next = Some(msg);
+                    while let Some(msg) = next {
+                        link |= matches!(msg, FibMessage::NewLink(_) | FibMessage::DelLink(_));
+                        addr |= matches!(msg, FibMessage::NewAddr(_) | FibMessage::DelAddr(_));
+                        self.process_fib_msg(msg);
+                        next = self.fib.rx.try_recv().ok();
+                    }
+                    self.connected_update();
+                    if link {
+                        self.neigh_static_sync().await;
+                        self.nd_proxy_sync().await;
+                    }
+                    if link || addr {
+                        self.router_id_update().await;
+                    }
+                    self.nexthop_update().await;
+                    self.vpn_export_update();
+                }
+                Some(msg) = self.api.rx.recv() => {
+                    self.process_api_msg(msg).await;
+                    self.vpn_export_update();
                 }
                 Some(msg) = self.cm.rx.recv() => {
                     self.process_cm_msg(msg).await;
+                    self.vpn_export_update();
                 }
                 Some(msg) = self.show.rx.recv() => {
                     self.process_show_msg(msg).await;
                 }
+                Some(msg) = self.state.rx.recv() => {
+                    self.process_state_msg(msg);
+                }
+                _ = sweep.tick() => {
+                    self.api_sweep().await;
+                    self.restart_sweep().await;
+                }
+                Some(done) = exit.recv() => {
+                    self.exit().await;
+                    let _ = done.send(());
+                    return;
+                }
             }
         }
     }
 }
 
-pub fn serve(mut rib: Rib) {
+pub fn serve(mut rib: Rib, exit: ExitRx) {
     tokio::spawn(async move {
-        rib.event_loop().await;
+        rib.event_loop(exit).await;
     });
 }
@@ -0,0 +1,41 @@
+use std::collections::BTreeSet;
+
+// Dynamic MPLS label allocation. Labels 0 to 15 are reserved, RFC 3032.
+pub const LABEL_MIN: u32 = 16;
+pub const LABEL_MAX: u32 = (1 << 20) - 1;
+
+#[derive(Debug)]
+pub struct LabelPool {
+    next: u32,
+    free: BTreeSet<u32>,
+}
+
+impl Default for LabelPool {
+    fn default() -> Self {
+        Self {
+            next: LABEL_MIN,
+            free: BTreeSet::new(),
+        }
+    }
+}
+
+impl LabelPool {
+    // Released labels are reused lowest first.
+    pub fn alloc(&mut self) -> Option<u32> {
+        if let Some(label) = self.free.pop_first() {
+            return Some(label);
+        }
+        if self.next > LABEL_MAX {
+            return None;
+        }
+        let label = self.next;
+        self.next += 1;
+        Some(label)
+    }
+
+    pub fn release(&mut self, label: u32) {
+        if (LABEL_MIN..self.next).contains(&label) {
+            self.free.insert(label);
+        }
+    }
+}
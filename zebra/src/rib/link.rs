@@ -1,10 +1,10 @@
-use crate::config::Args;
+use crate::config::{output, Args};
 
-use super::entry::{RibEntry, RibType};
 use super::fib::message::{FibAddr, FibLink};
 use super::fib::os_traffic_dump;
+use super::stats::{link_rate_show, LinkStats, LinkStatsOut, LinkStatsTable};
 use super::Rib;
-use ipnet::IpNet;
+use ipnet::{IpNet, Ipv6Net};
 use std::fmt::{self, Write};
 
 #[derive(Debug)]
@@ -18,6 +18,7 @@ pub struct Link {
     pub label: bool,
     pub addr4: Vec<LinkAddr>,
     pub addr6: Vec<LinkAddr>,
+    pub stats: LinkStats,
 }
 
 impl Link {
@@ -32,6 +33,7 @@ impl Link {
             label: false,
             addr4: Vec::new(),
             addr6: Vec::new(),
+            stats: LinkStats::default(),
         }
     }
 
@@ -50,6 +52,51 @@ impl Link {
     pub fn is_up_and_running(&self) -> bool {
         self.is_up() && self.is_running()
     }
+
+    // Global IPv6 prefixes of the interface which have passed DAD.
+    pub fn connected6(&self) -> Vec<Ipv6Net> {
+        self.addr6
+            .iter()
+            .filter(|addr| addr.is_preferred())
+            .filter_map(|addr| match addr.addr {
+                IpNet::V6(v6) if !v6.addr().is_unicast_link_local() => Some(v6),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// Duplicate Address Detection state of an IPv6 address (RFC 4862). The
+// kernel performs DAD and reports the address as tentative until it
+// completes, and flags it when another node on the link uses the same
+// address. Only preferred addresses are used, a duplicate one stays so until
+// it is removed. IPv4 addresses are always preferred.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum DadState {
+    Tentative,
+    #[default]
+    Preferred,
+    Duplicate,
+}
+
+impl DadState {
+    pub fn from_flags(tentative: bool, dadfailed: bool) -> Self {
+        if dadfailed {
+            Self::Duplicate
+        } else if tentative {
+            Self::Tentative
+        } else {
+            Self::Preferred
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Tentative => "tentative",
+            Self::Preferred => "preferred",
+            Self::Duplicate => "duplicate",
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -57,6 +104,7 @@ pub struct LinkAddr {
     pub addr: IpNet,
     pub link_index: u32,
     pub secondary: bool,
+    pub dad: DadState,
 }
 
 impl LinkAddr {
@@ -65,9 +113,14 @@ impl LinkAddr {
             addr: osaddr.addr,
             link_index: osaddr.link_index,
             secondary: osaddr.secondary,
+            dad: osaddr.dad,
         }
     }
 
+    pub fn is_preferred(&self) -> bool {
+        self.dad == DadState::Preferred
+    }
+
     pub fn is_v4(&self) -> bool {
         match self.addr {
             IpNet::V4(_) => true,
@@ -178,15 +231,29 @@ fn link_info_show(link: &Link, buf: &mut String, cb: &impl Fn(&String, &mut Stri
         }
     }
     for addr in link.addr6.iter() {
-        writeln!(buf, "  inet6 {}", addr.addr).unwrap();
+        write!(buf, "  inet6 {}", addr.addr).unwrap();
+        if addr.is_preferred() {
+            writeln!(buf).unwrap();
+        } else {
+            writeln!(buf, " {}", addr.dad.to_str()).unwrap();
+        }
     }
     cb(&link.name, buf);
+    link_rate_show(link, buf);
 }
 
-pub fn link_show(rib: &Rib, mut args: Args) -> String {
+pub fn link_show(rib: &Rib, mut args: Args, json: bool) -> String {
     let cb = os_traffic_dump();
     let mut buf = String::new();
 
+    if json {
+        let links = match args.string() {
+            Some(name) => rib.link_by_name(&name).into_iter().collect(),
+            None => rib.links.values().collect::<Vec<_>>(),
+        };
+        let table = LinkStatsTable(links.into_iter().map(LinkStatsOut::from).collect());
+        return output(&table, true);
+    }
     if args.is_empty() {
         for (_, link) in rib.links.iter() {
             link_info_show(link, &mut buf, &cb);
@@ -202,22 +269,21 @@ pub fn link_show(rib: &Rib, mut args: Args) -> String {
     buf
 }
 
+// Returns None when the address exists and its DAD state has not changed.
 pub fn link_addr_update(link: &mut Link, addr: LinkAddr) -> Option<()> {
-    if addr.is_v4() {
-        for a in link.addr4.iter() {
-            if a.addr == addr.addr {
-                return None;
-            }
-        }
-        link.addr4.push(addr);
+    let addrs = if addr.is_v4() {
+        &mut link.addr4
     } else {
-        for a in link.addr6.iter() {
-            if a.addr == addr.addr {
-                return None;
-            }
+        &mut link.addr6
+    };
+    if let Some(a) = addrs.iter_mut().find(|a| a.addr == addr.addr) {
+        if a.dad == addr.dad {
+            return None;
         }
-        link.addr6.push(addr);
+        a.dad = addr.dad;
+        return Some(());
     }
+    addrs.push(addr);
     Some(())
 }
 
@@ -238,12 +304,21 @@ impl Rib {
     pub fn link_add(&mut self, oslink: FibLink) {
         if !self.links.contains_key(&oslink.index) {
             let link = Link::from(oslink);
+            let index = link.index;
             self.links.insert(link.index, link);
+            self.ra_link_update(index);
         }
     }
 
     pub fn link_delete(&mut self, oslink: FibLink) {
-        self.links.remove(&oslink.index);
+        if let Some(link) = self.links.remove(&oslink.index) {
+            self.ra_tasks.remove(&link.name);
+            for addr in link.addr4.iter() {
+                if let IpNet::V4(net) = addr.addr {
+                    self.connected_touch(net);
+                }
+            }
+        }
     }
 
     pub fn link_name(&self, link_index: u32) -> Option<&String> {
@@ -265,24 +340,90 @@ impl Rib {
 
     pub fn addr_add(&mut self, osaddr: FibAddr) {
         let addr = LinkAddr::from(osaddr);
-        if let Some(link) = self.links.get_mut(&addr.link_index) {
-            if link_addr_update(link, addr.clone()).is_some() {
-                let mut e = RibEntry::new(RibType::Connected);
-                e.link_index = link.index;
-                e.distance = 0;
-                e.selected = true;
-                e.fib = true;
-                if let IpNet::V4(net) = addr.addr {
-                    self.ipv4_add(net, e);
-                }
-            }
+        let Some(link) = self.links.get_mut(&addr.link_index) else {
+            return;
+        };
+        if link_addr_update(link, addr.clone()).is_none() {
+            return;
+        }
+        if addr.dad == DadState::Duplicate {
+            println!("DAD failed for {} on {}", addr.addr, link.name);
+        }
+        match addr.addr {
+            IpNet::V4(net) => self.connected_touch(net),
+            IpNet::V6(_) => self.ra_link_update(addr.link_index),
         }
     }
 
     pub fn addr_del(&mut self, osaddr: FibAddr) {
         let addr = LinkAddr::from(osaddr);
-        if let Some(link) = self.links.get_mut(&addr.link_index) {
-            link_addr_del(link, addr);
+        let link_index = addr.link_index;
+        let prefix = addr.addr;
+        let Some(link) = self.links.get_mut(&link_index) else {
+            return;
+        };
+        if link_addr_del(link, addr).is_none() {
+            return;
         }
+        match prefix {
+            IpNet::V4(net) => self.connected_touch(net),
+            IpNet::V6(_) => self.ra_link_update(link_index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(addr: &str, dad: DadState) -> LinkAddr {
+        LinkAddr {
+            addr: addr.parse().unwrap(),
+            link_index: 2,
+            secondary: false,
+            dad,
+        }
+    }
+
+    #[test]
+    fn dad_transition() {
+        let mut fib = FibLink::new();
+        fib.index = 2;
+        fib.name = "eth0".to_string();
+        let mut link = Link::from(fib);
+
+        assert_eq!(DadState::from_flags(false, false), DadState::Preferred);
+        assert_eq!(DadState::from_flags(true, false), DadState::Tentative);
+        assert_eq!(DadState::from_flags(true, true), DadState::Duplicate);
+
+        // Tentative address is not used until DAD completes.
+        let a = "2001:db8::1/64";
+        assert!(link_addr_update(&mut link, addr(a, DadState::Tentative)).is_some());
+        assert!(link_addr_update(&mut link, addr(a, DadState::Tentative)).is_none());
+        assert!(link.connected6().is_empty());
+
+        assert!(link_addr_update(&mut link, addr(a, DadState::Preferred)).is_some());
+        assert_eq!(link.addr6.len(), 1);
+        assert_eq!(link.connected6(), vec!["2001:db8::1/64".parse().unwrap()]);
+
+        // Duplicate address stays on the link but is not used.
+        let b = "2001:db8:1::1/64";
+        assert!(link_addr_update(&mut link, addr(b, DadState::Tentative)).is_some());
+        assert!(link_addr_update(&mut link, addr(b, DadState::Duplicate)).is_some());
+        assert_eq!(link.addr6.len(), 2);
+        assert_eq!(link.addr6[1].dad, DadState::Duplicate);
+        assert_eq!(link.connected6(), vec!["2001:db8::1/64".parse().unwrap()]);
+
+        // Link local address is not a connected prefix.
+        link_addr_update(&mut link, addr("fe80::1/64", DadState::Preferred));
+        assert_eq!(link.connected6().len(), 1);
+
+        let mut buf = String::new();
+        link_info_show(&link, &mut buf, &|_, _| {});
+        assert!(buf.contains("  inet6 2001:db8::1/64\n"));
+        assert!(buf.contains("  inet6 2001:db8:1::1/64 duplicate\n"));
+
+        assert!(link_addr_del(&mut link, addr(b, DadState::Duplicate)).is_some());
+        assert_eq!(link.addr6.len(), 2);
     }
 }
@@ -0,0 +1,89 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+// Ethernet MAC address, written as six colon separated octets in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacAddr([u8; 6]);
+
+impl MacAddr {
+    // Link layer address of the netlink messages, which may be of another
+    // length for non Ethernet links.
+    pub fn from_slice(octets: &[u8]) -> Option<Self> {
+        Some(Self(octets.try_into().ok()?))
+    }
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let o = &self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            o[0], o[1], o[2], o[3], o[4], o[5]
+        )
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = ();
+
+    // Octets are separated with ':' or '-'.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut octets = [0u8; 6];
+        let mut parts = s.split([':', '-']);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(())?;
+            if part.is_empty() || part.len() > 2 {
+                return Err(());
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| ())?;
+        }
+        if parts.next().is_some() {
+            return Err(());
+        }
+        Ok(Self(octets))
+    }
+}
+
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mac: MacAddr = "52:54:00:AB:cd:0f".parse().unwrap();
+        assert_eq!(mac.octets(), [0x52, 0x54, 0x00, 0xab, 0xcd, 0x0f]);
+        assert_eq!(mac.to_string(), "52:54:00:ab:cd:0f");
+        assert_eq!("52-54-00-ab-cd-0f".parse(), Ok(mac));
+        assert_eq!(
+            "2:54:0:ab:cd:f".parse::<MacAddr>().unwrap().to_string(),
+            "02:54:00:ab:cd:0f"
+        );
+
+        assert!("52:54:00:ab:cd".parse::<MacAddr>().is_err());
+        assert!("52:54:00:ab:cd:0f:01".parse::<MacAddr>().is_err());
+        assert!("52:54:00:ab:cd:xx".parse::<MacAddr>().is_err());
+        assert!("52:54::ab:cd:0f".parse::<MacAddr>().is_err());
+
+        assert_eq!(
+            MacAddr::from_slice(&[1, 2, 3, 4, 5, 6]),
+            "01:02:03:04:05:06".parse().ok()
+        );
+        assert_eq!(MacAddr::from_slice(&[1, 2, 3, 4]), None);
+        assert_eq!(
+            serde_json::to_value(mac).unwrap(),
+            serde_json::json!("52:54:00:ab:cd:0f")
+        );
+    }
+}
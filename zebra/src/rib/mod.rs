@@ -5,16 +5,60 @@ pub mod instance;
 pub use instance::{serve, Rib};
 
 pub mod link;
-pub use link::{Link, LinkFlags, LinkType};
+
+pub mod connected;
+pub use link::{DadState, Link, LinkFlags, LinkType};
+
+pub mod mac_addr;
+pub use mac_addr::MacAddr;
+
+pub mod neigh;
+
+pub mod nd_proxy;
 
 pub mod entry;
 
 pub mod route;
 
+pub mod static_route;
+
+pub mod table;
+
+pub mod ecmp;
+
+pub mod distance;
+
+pub mod exit;
+
+pub mod restart;
+
 pub mod nexthop;
 
 pub mod config;
 
 pub mod show;
 
+pub mod ra;
+
+pub mod resolve;
+
+pub mod inject;
+
+pub mod grpc;
+pub use grpc::api_serve;
+
+pub mod vrf;
+
+pub mod label;
+
+pub mod rule;
+
+pub mod router_id;
+
+pub mod stats;
+
+pub mod template;
+
+pub mod watch;
+
 pub mod fib;
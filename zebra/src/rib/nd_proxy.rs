@@ -0,0 +1,153 @@
+use super::instance::Rib;
+use super::link::Link;
+use super::template::template_lookup;
+use crate::config::{Args, ConfigOp};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv6Addr;
+
+// IPv6 Neighbor Discovery proxy. Neighbor Solicitations for the configured
+// target addresses are answered on the interface with its own link layer
+// address, e.g. for hosts which are reachable through another interface. The
+// targets are programmed as proxy entries of the kernel neighbor table, and
+// proxy_ndp is enabled on the interface while it has entries.
+
+// Target addresses keyed by the interface name or pattern.
+pub type NdProxies = BTreeMap<String, BTreeSet<Ipv6Addr>>;
+
+// Proxy entries of the interfaces which exist, by link index and address.
+pub fn nd_proxy_entries(
+    config: &NdProxies,
+    links: &BTreeMap<u32, Link>,
+) -> BTreeSet<(u32, Ipv6Addr)> {
+    links
+        .values()
+        .filter_map(|link| Some((link.index, template_lookup(config, &link.name)?)))
+        .flat_map(|(index, targets)| targets.iter().map(move |addr| (index, *addr)))
+        .collect()
+}
+
+impl Rib {
+    // Bring the kernel proxy entries in sync with the config and the
+    // interfaces.
+    pub async fn nd_proxy_sync(&mut self) {
+        let next = nd_proxy_entries(&self.nd_proxy, &self.links);
+        if next == self.nd_proxy_fib {
+            return;
+        }
+        let prev_links: BTreeSet<u32> = self.nd_proxy_fib.iter().map(|(i, _)| *i).collect();
+        let next_links: BTreeSet<u32> = next.iter().map(|(i, _)| *i).collect();
+
+        // Entries of a removed link have been flushed by the kernel.
+        for (index, addr) in self.nd_proxy_fib.difference(&next) {
+            if self.links.contains_key(index) {
+                self.fib_handle.nd_proxy_del(*index, *addr).await;
+            }
+        }
+        for index in prev_links.difference(&next_links) {
+            if let Some(link) = self.links.get(index) {
+                self.fib_handle.nd_proxy_enable(&link.name, false);
+            }
+        }
+        for index in next_links.difference(&prev_links) {
+            if let Some(link) = self.links.get(index) {
+                self.fib_handle.nd_proxy_enable(&link.name, true);
+            }
+        }
+        for (index, addr) in next.difference(&self.nd_proxy_fib) {
+            self.fib_handle.nd_proxy_add(*index, *addr).await;
+        }
+        self.nd_proxy_fib = next;
+    }
+}
+
+pub async fn nd_proxy_config(
+    rib: &mut Rib,
+    path: &str,
+    mut args: Args,
+    op: ConfigOp,
+) -> Option<()> {
+    if path != "/interfaces/interface/ipv6/nd-proxy/target" {
+        return None;
+    }
+    let name = args.string()?;
+    let target: Ipv6Addr = args.string()?.parse().ok()?;
+    if op == ConfigOp::Set {
+        rib.nd_proxy.entry(name).or_default().insert(target);
+    } else {
+        let targets = rib.nd_proxy.get_mut(&name)?;
+        targets.remove(&target);
+        if targets.is_empty() {
+            rib.nd_proxy.remove(&name);
+        }
+    }
+    rib.nd_proxy_sync().await;
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::fib::message::FibLink;
+
+    fn link(index: u32, name: &str) -> Link {
+        let mut link = FibLink::new();
+        link.index = index;
+        link.name = name.to_string();
+        Link::from(link)
+    }
+
+    #[test]
+    fn entries() {
+        let mut config = NdProxies::new();
+        let mut links = BTreeMap::new();
+        let target: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let other: Ipv6Addr = "2001:db8::20".parse().unwrap();
+        config.entry("eth0".to_string()).or_default().insert(target);
+        config.entry("eth1".to_string()).or_default().insert(other);
+
+        // Interfaces do not exist yet.
+        assert!(nd_proxy_entries(&config, &links).is_empty());
+
+        links.insert(2, link(2, "eth0"));
+        links.insert(3, link(3, "eth2"));
+        let entries = nd_proxy_entries(&config, &links);
+        assert_eq!(entries, BTreeSet::from([(2, target)]));
+
+        config.get_mut("eth0").unwrap().insert(other);
+        links.insert(4, link(4, "eth1"));
+        let entries = nd_proxy_entries(&config, &links);
+        assert_eq!(
+            entries,
+            BTreeSet::from([(2, target), (2, other), (4, other)])
+        );
+    }
+
+    #[test]
+    fn template() {
+        let mut config = NdProxies::new();
+        let mut links = BTreeMap::new();
+        let target: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let other: Ipv6Addr = "2001:db8::20".parse().unwrap();
+        config.entry("eth*".to_string()).or_default().insert(target);
+        config.entry("eth1".to_string()).or_default().insert(other);
+
+        links.insert(2, link(2, "eth0"));
+        links.insert(3, link(3, "eth1"));
+        links.insert(4, link(4, "eth3"));
+        links.insert(5, link(5, "lo"));
+        let entries = nd_proxy_entries(&config, &links);
+        // Exact name takes precedence over the pattern.
+        assert_eq!(
+            entries,
+            BTreeSet::from([(2, target), (3, other), (4, target)])
+        );
+
+        // New interface picks up the template.
+        links.insert(6, link(6, "eth2"));
+        let entries = nd_proxy_entries(&config, &links);
+        assert_eq!(
+            entries,
+            BTreeSet::from([(2, target), (3, other), (4, target), (6, target)])
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use super::fib::{FibNeigh, NeighState};
+use super::instance::Rib;
+use super::link::Link;
+use super::MacAddr;
+use crate::config::{output, Args, ConfigOp, Render};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+
+// ARP and NDP neighbor table. Entries are learned from the kernel, which
+// notifies every change of the reachability state, so that stale and failed
+// entries are shown as the kernel sees them. A static neighbor is configured
+// with the interface and the MAC address, and programmed as a permanent entry
+// once the interface exists.
+
+#[derive(Debug, Default, Clone)]
+pub struct StaticNeighbor {
+    pub interface: Option<String>,
+    pub mac: Option<MacAddr>,
+}
+
+impl StaticNeighbor {
+    pub fn fib_neigh(&self, addr: IpAddr, links: &BTreeMap<u32, Link>) -> Option<FibNeigh> {
+        let name = self.interface.as_ref()?;
+        let link = links.values().find(|link| &link.name == name)?;
+        Some(FibNeigh {
+            addr,
+            link_index: link.index,
+            mac: Some(self.mac?),
+            state: NeighState::Permanent,
+            router: false,
+        })
+    }
+}
+
+pub type StaticNeighbors = BTreeMap<IpAddr, StaticNeighbor>;
+
+// Kernel neighbor table keyed by the address and the link index.
+pub type Neighbors = BTreeMap<(IpAddr, u32), FibNeigh>;
+
+impl Rib {
+    pub fn neigh_add(&mut self, neigh: FibNeigh) {
+        self.neighbors.insert((neigh.addr, neigh.link_index), neigh);
+    }
+
+    pub fn neigh_del(&mut self, neigh: FibNeigh) {
+        self.neighbors.remove(&(neigh.addr, neigh.link_index));
+    }
+
+    // Bring the kernel entry of the static neighbor in sync with the config.
+    pub async fn neigh_static_update(&mut self, addr: IpAddr) {
+        let next = self
+            .static_neighbors
+            .get(&addr)
+            .and_then(|neigh| neigh.fib_neigh(addr, &self.links));
+        let prev = self.static_neighbors_fib.get(&addr);
+        if prev == next.as_ref() {
+            return;
+        }
+        // Entries of a removed link have been flushed by the kernel.
+        if let Some(prev) = prev.filter(|prev| self.links.contains_key(&prev.link_index)) {
+            self.fib_handle.neigh_del(prev).await;
+        }
+        match next {
+            Some(next) => {
+                self.fib_handle.neigh_add(&next).await;
+                self.static_neighbors_fib.insert(addr, next);
+            }
+            None => {
+                self.static_neighbors_fib.remove(&addr);
+            }
+        }
+    }
+
+    // Interfaces have been added or removed.
+    pub async fn neigh_static_sync(&mut self) {
+        let addrs: Vec<IpAddr> = self.static_neighbors.keys().cloned().collect();
+        for addr in addrs.into_iter() {
+            self.neigh_static_update(addr).await;
+        }
+    }
+}
+
+pub async fn neigh_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let addr: IpAddr = args.string()?.parse().ok()?;
+    let set = op == ConfigOp::Set;
+    if path == "/routing/neighbor" {
+        if set {
+            rib.static_neighbors.entry(addr).or_default();
+        } else {
+            rib.static_neighbors.remove(&addr);
+        }
+        rib.neigh_static_update(addr).await;
+        return Some(());
+    }
+    let neigh = if set {
+        rib.static_neighbors.entry(addr).or_default()
+    } else {
+        rib.static_neighbors.get_mut(&addr)?
+    };
+    match path {
+        "/routing/neighbor/interface" => {
+            neigh.interface = if set { Some(args.string()?) } else { None };
+        }
+        "/routing/neighbor/mac-address" => {
+            neigh.mac = if set {
+                Some(args.string()?.parse().ok()?)
+            } else {
+                None
+            };
+        }
+        _ => return None,
+    }
+    rib.neigh_static_update(addr).await;
+    Some(())
+}
+
+#[derive(Serialize)]
+struct NeighborOut {
+    address: IpAddr,
+    interface: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mac_address: Option<MacAddr>,
+    state: &'static str,
+    #[serde(rename = "static")]
+    is_static: bool,
+    router: bool,
+}
+
+#[derive(Serialize)]
+struct NeighborTable(Vec<NeighborOut>);
+
+impl Render for NeighborTable {
+    fn render(&self, buf: &mut String) {
+        writeln!(
+            buf,
+            "Address                   Interface        MAC address        State       Flags"
+        )
+        .unwrap();
+        for n in self.0.iter() {
+            let mac = n.mac_address.map_or("-".to_string(), |mac| mac.to_string());
+            let mut flags = Vec::new();
+            if n.is_static {
+                flags.push("static");
+            }
+            if n.router {
+                flags.push("router");
+            }
+            let line = format!(
+                "{:25} {:16} {:18} {:11} {}",
+                n.address.to_string(),
+                n.interface,
+                mac,
+                n.state,
+                flags.join(" ")
+            );
+            writeln!(buf, "{}", line.trim_end()).unwrap();
+        }
+    }
+}
+
+// Neighbors of IPv4 or IPv6. An entry is static when it is the one
+// programmed from the config.
+fn neighbor_table(
+    neighbors: &Neighbors,
+    installed: &BTreeMap<IpAddr, FibNeigh>,
+    links: &BTreeMap<u32, Link>,
+    ipv6: bool,
+) -> NeighborTable {
+    let entries = neighbors
+        .values()
+        .filter(|n| n.addr.is_ipv6() == ipv6)
+        .map(|n| NeighborOut {
+            address: n.addr,
+            interface: links
+                .get(&n.link_index)
+                .map_or(n.link_index.to_string(), |link| link.name.clone()),
+            mac_address: n.mac,
+            state: n.state.to_str(),
+            is_static: installed
+                .get(&n.addr)
+                .is_some_and(|fib| fib.link_index == n.link_index),
+            router: n.router,
+        })
+        .collect();
+    NeighborTable(entries)
+}
+
+pub(crate) fn neigh_show(rib: &Rib, _args: Args, json: bool) -> String {
+    let table = neighbor_table(&rib.neighbors, &rib.static_neighbors_fib, &rib.links, false);
+    output(&table, json)
+}
+
+pub(crate) fn neigh6_show(rib: &Rib, _args: Args, json: bool) -> String {
+    let table = neighbor_table(&rib.neighbors, &rib.static_neighbors_fib, &rib.links, true);
+    output(&table, json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::fib::message::FibLink;
+
+    fn link(index: u32, name: &str) -> Link {
+        let mut link = FibLink::new();
+        link.index = index;
+        link.name = name.to_string();
+        Link::from(link)
+    }
+
+    fn learned(addr: &str, mac: Option<&str>, state: NeighState) -> FibNeigh {
+        FibNeigh {
+            addr: addr.parse().unwrap(),
+            link_index: 2,
+            mac: mac.and_then(|mac| mac.parse().ok()),
+            state,
+            router: false,
+        }
+    }
+
+    #[test]
+    fn static_neighbor() {
+        let mut links = BTreeMap::new();
+        let addr: IpAddr = "10.0.0.10".parse().unwrap();
+        let neigh = StaticNeighbor {
+            interface: Some("eth0".to_string()),
+            mac: "02:00:00:00:00:01".parse().ok(),
+        };
+        // Interface does not exist yet.
+        assert_eq!(neigh.fib_neigh(addr, &links), None);
+
+        links.insert(2, link(2, "eth0"));
+        let fib = neigh.fib_neigh(addr, &links).unwrap();
+        assert_eq!(fib.link_index, 2);
+        assert_eq!(fib.state, NeighState::Permanent);
+        assert_eq!(fib.mac, "02:00:00:00:00:01".parse().ok());
+
+        // MAC address is mandatory.
+        let incomplete = StaticNeighbor {
+            mac: None,
+            ..neigh.clone()
+        };
+        assert_eq!(incomplete.fib_neigh(addr, &links), None);
+
+        // Programmed entry is notified back by the kernel together with the
+        // learned ones.
+        let mut installed = BTreeMap::new();
+        installed.insert(addr, fib.clone());
+        let mut neighbors = Neighbors::new();
+        let mut router = learned("fe80::1", Some("02:00:00:00:00:fe"), NeighState::Reachable);
+        router.router = true;
+        for neigh in [
+            fib,
+            learned("10.0.0.2", Some("52:54:00:12:34:56"), NeighState::Reachable),
+            learned("10.0.0.3", Some("52:54:00:12:34:57"), NeighState::Stale),
+            learned("10.0.0.4", None, NeighState::Failed),
+            router,
+        ] {
+            neighbors.insert((neigh.addr, neigh.link_index), neigh);
+        }
+
+        let table = neighbor_table(&neighbors, &installed, &links, false);
+        assert_eq!(
+            output(&table, false),
+            r#"Address                   Interface        MAC address        State       Flags
+10.0.0.2                  eth0             52:54:00:12:34:56  reachable
+10.0.0.3                  eth0             52:54:00:12:34:57  stale
+10.0.0.4                  eth0             -                  failed
+10.0.0.10                 eth0             02:00:00:00:00:01  permanent   static
+"#
+        );
+        let json: serde_json::Value = serde_json::from_str(&output(&table, true)).unwrap();
+        assert_eq!(
+            json[3],
+            serde_json::json!({
+                "address": "10.0.0.10",
+                "interface": "eth0",
+                "mac_address": "02:00:00:00:00:01",
+                "state": "permanent",
+                "static": true,
+                "router": false,
+            })
+        );
+        assert_eq!(json[2].get("mac_address"), None);
+
+        let table = neighbor_table(&neighbors, &installed, &links, true);
+        assert_eq!(
+            output(&table, false),
+            r#"Address                   Interface        MAC address        State       Flags
+fe80::1                   eth0             02:00:00:00:00:fe  reachable   router
+"#
+        );
+    }
+}
@@ -1,7 +1,131 @@
-use std::net::Ipv4Addr;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 
-#[allow(dead_code)]
-#[derive(Debug)]
+// Discard nexthops drop the packets instead of forwarding them. Null0 is a
+// blackhole shown as an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NexthopType {
+    #[default]
+    Gateway,
+    Null0,
+    Blackhole,
+    Reject,
+    Prohibit,
+}
+
+impl NexthopType {
+    pub fn is_discard(&self) -> bool {
+        *self != Self::Gateway
+    }
+}
+
+impl fmt::Display for NexthopType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gateway => write!(f, "gateway"),
+            Self::Null0 => write!(f, "Null0"),
+            Self::Blackhole => write!(f, "blackhole"),
+            Self::Reject => write!(f, "reject"),
+            Self::Prohibit => write!(f, "prohibit"),
+        }
+    }
+}
+
+impl FromStr for NexthopType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Null0" | "null0" => Ok(Self::Null0),
+            "blackhole" => Ok(Self::Blackhole),
+            "reject" => Ok(Self::Reject),
+            "prohibit" => Ok(Self::Prohibit),
+            _ => Err(()),
+        }
+    }
+}
+
+// Behavior of a local SRv6 SID, RFC 8986 4.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Seg6Local {
+    End,
+    EndX(Ipv6Addr),
+    EndDx4(Ipv4Addr),
+    EndDt4(u32),
+    EndDt6(u32),
+}
+
+impl fmt::Display for Seg6Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::End => write!(f, "End"),
+            Self::EndX(nh6) => write!(f, "End.X nh6 {}", nh6),
+            Self::EndDx4(nh4) => write!(f, "End.DX4 nh4 {}", nh4),
+            Self::EndDt4(table) => write!(f, "End.DT4 table {}", table),
+            Self::EndDt6(table) => write!(f, "End.DT6 table {}", table),
+        }
+    }
+}
+
+// Label stack and SRv6 SIDs are kept on the nexthop regardless of the
+// protocol which installed the route. Labels are pushed, outermost first,
+// and the packet is encapsulated with the SID list, first segment first.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Nexthop {
-    nexthop: Ipv4Addr,
+    pub nexthop: Ipv4Addr,
+    pub ifname: String,
+    pub labels: Vec<u32>,
+    pub seg6: Vec<Ipv6Addr>,
+    pub seg6local: Option<Seg6Local>,
+    pub ntype: NexthopType,
+    // Not installed to the FIB beyond the maximum paths.
+    pub backup: bool,
+    // Bandwidth of the path in bytes per second, from the link bandwidth
+    // extended community of BGP. The weight of the nexthop in the FIB is in
+    // proportion to it.
+    pub bandwidth: Option<u64>,
+}
+
+impl Nexthop {
+    pub fn new(nexthop: Ipv4Addr) -> Self {
+        Self {
+            nexthop,
+            ifname: String::new(),
+            labels: Vec::new(),
+            seg6: Vec::new(),
+            seg6local: None,
+            ntype: NexthopType::Gateway,
+            backup: false,
+            bandwidth: None,
+        }
+    }
+
+    pub fn discard(ntype: NexthopType) -> Self {
+        Self {
+            ntype,
+            ..Self::new(Ipv4Addr::UNSPECIFIED)
+        }
+    }
+
+    // Label or SRv6 operation is applied to the packet.
+    pub fn is_encap(&self) -> bool {
+        !self.labels.is_empty() || !self.seg6.is_empty() || self.seg6local.is_some()
+    }
+
+    pub fn encap_str(&self) -> String {
+        let mut encap = String::new();
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self.labels.iter().map(|l| l.to_string()).collect();
+            encap.push_str(&format!(", labels {}", labels.join("/")));
+        }
+        if !self.seg6.is_empty() {
+            let sids: Vec<String> = self.seg6.iter().map(|s| s.to_string()).collect();
+            encap.push_str(&format!(", seg6 [{}]", sids.join(" ")));
+        }
+        if let Some(action) = self.seg6local.as_ref() {
+            encap.push_str(&format!(", seg6local {}", action));
+        }
+        encap
+    }
 }
@@ -0,0 +1,601 @@
+// IPv6 Router Advertisement origination (RFC 4861).
+//
+// Each interface with `send-advertisements true` runs its own task which sends
+// unsolicited Router Advertisements to all-nodes at a random interval between
+// `min-rtr-adv-interval` and `max-rtr-adv-interval` seconds, and answers
+// Router Solicitations received on the interface (RFC 4861 6.2.4, 6.2.6). The
+// answer is delayed randomly and kept MIN_DELAY_BETWEEN_RAS apart from the
+// previous advertisement, so that a burst of solicitations is answered by one
+// advertisement. When no prefix is configured the interface's global IPv6
+// prefixes are advertised.
+
+use super::template::{glob_match, template_lookup};
+use super::Rib;
+use crate::bgp::task::Task;
+use crate::config::{Args, ConfigOp};
+use bytes::{BufMut, BytesMut};
+use ipnet::Ipv6Net;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::net::{Ipv6Addr, SocketAddrV6};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+
+pub const ICMP6_ROUTER_SOLICIT: u8 = 133;
+pub const ICMP6_ROUTER_ADVERT: u8 = 134;
+
+const ND_OPT_PREFIX_INFORMATION: u8 = 3;
+const ND_OPT_MTU: u8 = 5;
+
+const ND_RA_FLAG_MANAGED: u8 = 0x80;
+const ND_RA_FLAG_OTHER: u8 = 0x40;
+const ND_OPT_PI_FLAG_ONLINK: u8 = 0x80;
+const ND_OPT_PI_FLAG_AUTO: u8 = 0x40;
+
+const RA_MAX_INTERVAL: u16 = 600;
+const RA_HOP_LIMIT: u8 = 64;
+const RA_MAX_LIFETIME: u32 = 9000;
+const RA_VALID_LIFETIME: u32 = 2592000;
+const RA_PREFERRED_LIFETIME: u32 = 604800;
+
+// Router constants, RFC 4861 10.
+const MAX_INITIAL_RTR_ADVERT_INTERVAL: Duration = Duration::from_secs(16);
+const MAX_INITIAL_RTR_ADVERTISEMENTS: u32 = 3;
+const MIN_DELAY_BETWEEN_RAS: Duration = Duration::from_secs(3);
+const MAX_RA_DELAY_TIME: Duration = Duration::from_millis(500);
+
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+
+const RA_PATH: &str = "/interfaces/interface/ipv6/router-advertisements";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaPrefix {
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    pub on_link: bool,
+    pub autonomous: bool,
+}
+
+impl Default for RaPrefix {
+    fn default() -> Self {
+        Self {
+            valid_lifetime: RA_VALID_LIFETIME,
+            preferred_lifetime: RA_PREFERRED_LIFETIME,
+            on_link: true,
+            autonomous: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RaConfig {
+    pub send: bool,
+    pub max_interval: u16,
+    pub min_interval: Option<u16>,
+    pub managed: bool,
+    pub other: bool,
+    pub link_mtu: u32,
+    pub reachable_time: u32,
+    pub retrans_timer: u32,
+    pub hop_limit: u8,
+    pub default_lifetime: Option<u16>,
+    pub prefixes: BTreeMap<Ipv6Net, RaPrefix>,
+}
+
+impl Default for RaConfig {
+    fn default() -> Self {
+        Self {
+            send: false,
+            max_interval: RA_MAX_INTERVAL,
+            min_interval: None,
+            managed: false,
+            other: false,
+            link_mtu: 0,
+            reachable_time: 0,
+            retrans_timer: 0,
+            hop_limit: RA_HOP_LIMIT,
+            default_lifetime: None,
+            prefixes: BTreeMap::new(),
+        }
+    }
+}
+
+impl RaConfig {
+    // MinRtrAdvInterval defaults to 0.33 * MaxRtrAdvInterval, or 0.75 *
+    // MaxRtrAdvInterval when it is less than 9 seconds.
+    pub fn min_interval(&self) -> u16 {
+        let max = self.max_interval as u32;
+        let min = match self.min_interval {
+            Some(min) => min as u32,
+            None if max >= 9 => max * 33 / 100,
+            None => max * 3 / 4,
+        };
+        min.min(max) as u16
+    }
+
+    // Router lifetime defaults to three times the advertisement interval.
+    pub fn lifetime(&self) -> u16 {
+        match self.default_lifetime {
+            Some(lifetime) => lifetime,
+            None => (self.max_interval as u32 * 3).min(RA_MAX_LIFETIME) as u16,
+        }
+    }
+
+    pub fn advert(&self, connected: &[Ipv6Net]) -> RouterAdvert {
+        let prefixes = if self.prefixes.is_empty() {
+            connected
+                .iter()
+                .map(|prefix| (prefix.trunc(), RaPrefix::default()))
+                .collect()
+        } else {
+            self.prefixes
+                .iter()
+                .map(|(prefix, p)| (*prefix, p.clone()))
+                .collect()
+        };
+        RouterAdvert {
+            hop_limit: self.hop_limit,
+            managed: self.managed,
+            other: self.other,
+            lifetime: self.lifetime(),
+            reachable_time: self.reachable_time,
+            retrans_timer: self.retrans_timer,
+            mtu: if self.link_mtu != 0 {
+                Some(self.link_mtu)
+            } else {
+                None
+            },
+            prefixes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RouterAdvert {
+    pub hop_limit: u8,
+    pub managed: bool,
+    pub other: bool,
+    pub lifetime: u16,
+    pub reachable_time: u32,
+    pub retrans_timer: u32,
+    pub mtu: Option<u32>,
+    pub prefixes: Vec<(Ipv6Net, RaPrefix)>,
+}
+
+impl From<RouterAdvert> for BytesMut {
+    fn from(ra: RouterAdvert) -> Self {
+        let mut buf = BytesMut::new();
+        buf.put_u8(ICMP6_ROUTER_ADVERT);
+        buf.put_u8(0);
+        // Checksum is filled in by the kernel for ICMPv6 raw sockets.
+        buf.put_u16(0);
+        buf.put_u8(ra.hop_limit);
+        let mut flags = 0u8;
+        if ra.managed {
+            flags |= ND_RA_FLAG_MANAGED;
+        }
+        if ra.other {
+            flags |= ND_RA_FLAG_OTHER;
+        }
+        buf.put_u8(flags);
+        buf.put_u16(ra.lifetime);
+        buf.put_u32(ra.reachable_time);
+        buf.put_u32(ra.retrans_timer);
+
+        if let Some(mtu) = ra.mtu {
+            buf.put_u8(ND_OPT_MTU);
+            buf.put_u8(1);
+            buf.put_u16(0);
+            buf.put_u32(mtu);
+        }
+
+        for (prefix, p) in ra.prefixes.iter() {
+            buf.put_u8(ND_OPT_PREFIX_INFORMATION);
+            buf.put_u8(4);
+            buf.put_u8(prefix.prefix_len());
+            let mut flags = 0u8;
+            if p.on_link {
+                flags |= ND_OPT_PI_FLAG_ONLINK;
+            }
+            if p.autonomous {
+                flags |= ND_OPT_PI_FLAG_AUTO;
+            }
+            buf.put_u8(flags);
+            buf.put_u32(p.valid_lifetime);
+            buf.put_u32(p.preferred_lifetime);
+            buf.put_u32(0);
+            buf.put(&prefix.network().octets()[..]);
+        }
+        buf
+    }
+}
+
+fn ra_socket(ifindex: u32, ifname: &str) -> std::io::Result<Socket> {
+    let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?;
+    #[cfg(target_os = "linux")]
+    socket.bind_device(Some(ifname.as_bytes()))?;
+    #[cfg(not(target_os = "linux"))]
+    let _ = ifname;
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_unicast_hops_v6(255)?;
+    socket.set_multicast_loop_v6(false)?;
+    socket.join_multicast_v6(&ALL_ROUTERS, ifindex)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+// Random duration between `min` and `max`, in milliseconds. The keys of the
+// std hasher are random for each RandomState.
+fn ra_random(min: Duration, max: Duration) -> Duration {
+    let span = max.saturating_sub(min).as_millis() as u64;
+    let random = RandomState::new().build_hasher().finish();
+    min + Duration::from_millis(random % (span + 1))
+}
+
+// Time of the next advertisement.
+#[derive(Debug)]
+pub struct RaSchedule {
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    // Number of advertisements sent.
+    pub sent: u32,
+    pub last: Option<Instant>,
+    pub next: Instant,
+}
+
+impl RaSchedule {
+    // The first advertisement is sent right away.
+    pub fn new(min_interval: Duration, max_interval: Duration, now: Instant) -> Self {
+        Self {
+            min_interval,
+            max_interval,
+            sent: 0,
+            last: None,
+            next: now,
+        }
+    }
+
+    // Schedule the next unsolicited advertisement after one has been sent.
+    // The initial advertisements are sent at a shorter interval.
+    pub fn sent(&mut self, now: Instant) {
+        self.sent += 1;
+        self.last = Some(now);
+        let mut interval = ra_random(self.min_interval, self.max_interval);
+        if self.sent <= MAX_INITIAL_RTR_ADVERTISEMENTS {
+            interval = interval.min(MAX_INITIAL_RTR_ADVERT_INTERVAL);
+        }
+        self.next = now + interval;
+    }
+
+    // Answer a Router Solicitation. The advertisement already scheduled
+    // earlier answers it as well.
+    pub fn solicited(&mut self, now: Instant) {
+        let mut at = now + ra_random(Duration::ZERO, MAX_RA_DELAY_TIME);
+        if let Some(last) = self.last {
+            at = at.max(last + MIN_DELAY_BETWEEN_RAS);
+        }
+        self.next = self.next.min(at);
+    }
+}
+
+fn ra_start(ifindex: u32, ifname: String, config: &RaConfig, packet: BytesMut) -> Task<()> {
+    let min_interval = Duration::from_secs(config.min_interval() as u64);
+    let max_interval = Duration::from_secs(config.max_interval as u64);
+    Task::spawn(async move {
+        let socket = match ra_socket(ifindex, &ifname).and_then(AsyncFd::new) {
+            Ok(socket) => socket,
+            Err(err) => {
+                println!("RA socket for {}: {}", ifname, err);
+                return;
+            }
+        };
+        let dest = SockAddr::from(SocketAddrV6::new(ALL_NODES, 0, 0, ifindex));
+        let mut schedule = RaSchedule::new(min_interval, max_interval, Instant::now());
+        let mut buf = [0u8; 1500];
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(schedule.next.into()) => {
+                    let _ = socket.get_ref().send_to(&packet, &dest);
+                    schedule.sent(Instant::now());
+                }
+                guard = socket.readable() => {
+                    let Ok(mut guard) = guard else {
+                        return;
+                    };
+                    let result = guard.try_io(|inner| {
+                        let mut sock = inner.get_ref();
+                        sock.read(&mut buf)
+                    });
+                    if let Ok(Ok(len)) = result {
+                        if len > 0 && buf[0] == ICMP6_ROUTER_SOLICIT {
+                            schedule.solicited(Instant::now());
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+impl Rib {
+    // (Re)start Router Advertisement on the interface to reflect the latest
+    // configuration and interface addresses.
+    pub fn ra_update(&mut self, name: &str) {
+        self.ra_tasks.remove(name);
+        let Some(config) = template_lookup(&self.ra, name) else {
+            return;
+        };
+        if !config.send {
+            return;
+        }
+        let Some(link) = self.link_by_name(name) else {
+            return;
+        };
+        if link.is_up() {
+            let packet: BytesMut = config.advert(&link.connected6()).into();
+            let task = ra_start(link.index, name.to_string(), config, packet);
+            self.ra_tasks.insert(name.to_string(), task);
+        }
+    }
+
+    pub fn ra_link_update(&mut self, link_index: u32) {
+        if let Some(name) = self.link_name(link_index).cloned() {
+            self.ra_update(&name);
+        }
+    }
+
+    // Interfaces of the name or the pattern of the config.
+    fn ra_config_update(&mut self, pattern: &str) {
+        let names: Vec<String> = self
+            .links
+            .values()
+            .filter(|link| glob_match(pattern, &link.name))
+            .map(|link| link.name.clone())
+            .collect();
+        for name in names.iter() {
+            self.ra_update(name);
+        }
+    }
+}
+
+fn ra_prefix<'a>(config: &'a mut RaConfig, args: &mut Args) -> Option<&'a mut RaPrefix> {
+    let prefix: Ipv6Net = args.v6net()?;
+    Some(config.prefixes.entry(prefix).or_default())
+}
+
+pub fn ra_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let leaf = path.strip_prefix(RA_PATH)?;
+    let set = op == ConfigOp::Set;
+    let config = rib.ra.entry(name.clone()).or_default();
+    let default = RaConfig::default();
+    let prefix_default = RaPrefix::default();
+
+    match leaf {
+        "/send-advertisements" => {
+            config.send = set && args.boolean()?;
+        }
+        "/max-rtr-adv-interval" => {
+            config.max_interval = if set {
+                args.u16()?
+            } else {
+                default.max_interval
+            };
+        }
+        "/min-rtr-adv-interval" => {
+            config.min_interval = if set { Some(args.u16()?) } else { None };
+        }
+        "/managed-flag" => {
+            config.managed = set && args.boolean()?;
+        }
+        "/other-config-flag" => {
+            config.other = set && args.boolean()?;
+        }
+        "/link-mtu" => {
+            config.link_mtu = if set { args.u32()? } else { default.link_mtu };
+        }
+        "/reachable-time" => {
+            config.reachable_time = if set {
+                args.u32()?
+            } else {
+                default.reachable_time
+            };
+        }
+        "/retrans-timer" => {
+            config.retrans_timer = if set {
+                args.u32()?
+            } else {
+                default.retrans_timer
+            };
+        }
+        "/cur-hop-limit" => {
+            config.hop_limit = if set { args.u8()? } else { default.hop_limit };
+        }
+        "/default-lifetime" => {
+            config.default_lifetime = if set { Some(args.u16()?) } else { None };
+        }
+        "/prefix" => {
+            let prefix: Ipv6Net = args.v6net()?;
+            if set {
+                config.prefixes.entry(prefix).or_default();
+            } else {
+                config.prefixes.remove(&prefix);
+            }
+        }
+        "/prefix/valid-lifetime" => {
+            let p = ra_prefix(config, &mut args)?;
+            p.valid_lifetime = if set {
+                args.u32()?
+            } else {
+                prefix_default.valid_lifetime
+            };
+        }
+        "/prefix/preferred-lifetime" => {
+            let p = ra_prefix(config, &mut args)?;
+            p.preferred_lifetime = if set {
+                args.u32()?
+            } else {
+                prefix_default.preferred_lifetime
+            };
+        }
+        "/prefix/on-link-flag" => {
+            let p = ra_prefix(config, &mut args)?;
+            p.on_link = if set {
+                args.boolean()?
+            } else {
+                prefix_default.on_link
+            };
+        }
+        "/prefix/autonomous-flag" => {
+            let p = ra_prefix(config, &mut args)?;
+            p.autonomous = if set {
+                args.boolean()?
+            } else {
+                prefix_default.autonomous
+            };
+        }
+        _ => return None,
+    }
+    rib.ra_config_update(&name);
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn advert_bytes() {
+        let mut config = RaConfig {
+            send: true,
+            managed: true,
+            link_mtu: 1500,
+            ..Default::default()
+        };
+        let prefix: Ipv6Net = "2001:db8:1::/64".parse().unwrap();
+        config.prefixes.insert(prefix, RaPrefix::default());
+
+        let bytes: BytesMut = config.advert(&[]).into();
+        let expect: Vec<u8> = vec![
+            // Router Advertisement header.
+            134, 0, 0, 0, 64, 0x80, 0x07, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, // MTU option.
+            5, 1, 0, 0, 0, 0, 0x05, 0xdc, // Prefix information option.
+            3, 4, 64, 0xc0, 0x00, 0x27, 0x8d, 0x00, 0x00, 0x09, 0x3a, 0x80, 0, 0, 0, 0, 0x20, 0x01,
+            0x0d, 0xb8, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(&bytes[..], &expect[..]);
+    }
+
+    #[test]
+    fn advert_connected_prefixes() {
+        let config = RaConfig::default();
+        let connected: Vec<Ipv6Net> = vec![
+            "2001:db8:1::1/64".parse().unwrap(),
+            "2001:db8:2::1/64".parse().unwrap(),
+        ];
+        let ra = config.advert(&connected);
+        assert_eq!(ra.prefixes.len(), 2);
+        assert_eq!(ra.prefixes[0].0, "2001:db8:1::/64".parse().unwrap());
+        assert_eq!(ra.prefixes[1].0, "2001:db8:2::/64".parse().unwrap());
+        assert_eq!(ra.lifetime, 1800);
+
+        // Configured prefixes take precedence over connected ones.
+        let mut config = RaConfig::default();
+        let prefix: Ipv6Net = "2001:db8:ffff::/64".parse().unwrap();
+        config.prefixes.insert(
+            prefix,
+            RaPrefix {
+                autonomous: false,
+                ..Default::default()
+            },
+        );
+        let bytes: BytesMut = config.advert(&connected).into();
+        assert_eq!(bytes.len(), 16 + 32);
+        // Only the on-link flag is set.
+        assert_eq!(bytes[16 + 3], 0x80);
+    }
+
+    #[test]
+    fn lifetime() {
+        let config = RaConfig {
+            max_interval: 4000,
+            ..Default::default()
+        };
+        assert_eq!(config.lifetime(), 9000);
+        let config = RaConfig {
+            default_lifetime: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(config.lifetime(), 0);
+    }
+
+    #[test]
+    fn min_interval() {
+        let mut config = RaConfig::default();
+        assert_eq!(config.min_interval(), 198);
+        config.max_interval = 8;
+        assert_eq!(config.min_interval(), 6);
+        config.min_interval = Some(3);
+        assert_eq!(config.min_interval(), 3);
+    }
+
+    #[test]
+    fn schedule_interval() {
+        let min = Duration::from_secs(200);
+        let max = Duration::from_secs(600);
+        let now = Instant::now();
+        let mut schedule = RaSchedule::new(min, max, now);
+        assert_eq!(schedule.next, now);
+
+        // Initial advertisements are at most 16 seconds apart.
+        for _ in 0..MAX_INITIAL_RTR_ADVERTISEMENTS {
+            schedule.sent(now);
+            assert!(schedule.next <= now + MAX_INITIAL_RTR_ADVERT_INTERVAL);
+        }
+
+        // Then randomized between the min and the max.
+        let mut intervals = Vec::new();
+        for _ in 0..20 {
+            schedule.sent(now);
+            let interval = schedule.next - now;
+            assert!(interval >= min && interval <= max);
+            intervals.push(interval);
+        }
+        assert!(intervals.iter().any(|interval| *interval != intervals[0]));
+    }
+
+    #[test]
+    fn schedule_solicited() {
+        let now = Instant::now();
+        let mut schedule = RaSchedule::new(Duration::from_secs(200), Duration::from_secs(600), now);
+        for _ in 0..MAX_INITIAL_RTR_ADVERTISEMENTS {
+            schedule.sent(now);
+        }
+        schedule.sent(now);
+
+        // Burst of solicitations right after an advertisement is answered by
+        // one advertisement at least MIN_DELAY_BETWEEN_RAS later.
+        let rs = now + Duration::from_secs(1);
+        for _ in 0..10 {
+            schedule.solicited(rs);
+        }
+        assert!(schedule.next >= now + MIN_DELAY_BETWEEN_RAS);
+        assert!(schedule.next <= now + MIN_DELAY_BETWEEN_RAS + MAX_RA_DELAY_TIME);
+
+        // Later solicitation is delayed by MAX_RA_DELAY_TIME at most.
+        schedule.sent(schedule.next);
+        let last = schedule.last.unwrap();
+        let rs = last + Duration::from_secs(10);
+        schedule.solicited(rs);
+        assert!(schedule.next >= rs && schedule.next <= rs + MAX_RA_DELAY_TIME);
+
+        // Advertisement scheduled earlier is kept.
+        let next = schedule.next;
+        schedule.solicited(rs + Duration::from_secs(1));
+        assert_eq!(schedule.next, next);
+    }
+}
@@ -0,0 +1,181 @@
+use super::api::{rib_notify, NexthopUpdate, PrefixUpdate, RibRx};
+use super::entry::RibEntry;
+use super::instance::Rib;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::net::Ipv4Addr;
+
+// Resolve the nexthop with the longest prefix match among the selected
+// routes. Default route is returned as well, it is up to the protocol
+// whether the nexthop may be resolved via default route.
+pub fn rib_resolve(
+    rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    addr: Ipv4Addr,
+) -> Option<(Ipv4Net, u32)> {
+    for plen in (0..=32).rev() {
+        let prefix = Ipv4Net::new(addr, plen).ok()?.trunc();
+        if let Some(entries) = rib.get(&prefix) {
+            if let Some(e) = entries.iter().find(|e| e.selected) {
+                return Some((prefix, e.metric));
+            }
+        }
+    }
+    None
+}
+
+// Longest selected route which covers the prefix, the prefix itself included.
+pub fn rib_covering(rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>, prefix: Ipv4Net) -> Option<Ipv4Net> {
+    for plen in (0..=prefix.prefix_len()).rev() {
+        let covering = Ipv4Net::new(prefix.addr(), plen).ok()?.trunc();
+        if let Some(entries) = rib.get(&covering) {
+            if entries.iter().any(|e| e.selected) {
+                return Some(covering);
+            }
+        }
+    }
+    None
+}
+
+impl Rib {
+    fn nexthop_resolve(&self, addr: Ipv4Addr) -> NexthopUpdate {
+        let resolved = rib_resolve(&self.rib, addr);
+        NexthopUpdate {
+            addr,
+            resolved: resolved.map(|(prefix, _)| prefix),
+            metric: resolved.map(|(_, metric)| metric).unwrap_or(0),
+        }
+    }
+
+    fn nexthop_notify(&self, update: NexthopUpdate) {
+        rib_notify(&self.redists, RibRx::Nexthop(update));
+    }
+
+    fn prefix_notify(&self, update: PrefixUpdate) {
+        rib_notify(&self.redists, RibRx::Prefix(update));
+    }
+
+    fn prefix_resolve(&self, prefix: Ipv4Net) -> PrefixUpdate {
+        PrefixUpdate {
+            prefix,
+            covering: rib_covering(&self.rib, prefix),
+        }
+    }
+
+    // Prefix tracking for the routes originated by the protocols while the
+    // prefix exists in the RIB, e.g. BGP network statement.
+    pub async fn prefix_register(&mut self, prefix: Ipv4Net) {
+        let update = self.prefix_resolve(prefix);
+        self.prefix_watch.insert(prefix, update.clone());
+        self.prefix_notify(update);
+    }
+
+    pub fn prefix_unregister(&mut self, prefix: Ipv4Net) {
+        self.prefix_watch.remove(&prefix);
+    }
+
+    pub async fn nexthop_register(&mut self, addr: Ipv4Addr) {
+        let update = self.nexthop_resolve(addr);
+        self.nht.insert(addr, update.clone());
+        self.nexthop_notify(update);
+    }
+
+    pub fn nexthop_unregister(&mut self, addr: Ipv4Addr) {
+        self.nht.remove(&addr);
+    }
+
+    // Re-resolve registered nexthops after the routing table has been
+    // changed and notify the ones which resolution has been changed. Route
+    // watchers are notified of the changes as well.
+    pub async fn nexthop_update(&mut self) {
+        let mut updates = Vec::new();
+        for (addr, prev) in self.nht.iter() {
+            let update = self.nexthop_resolve(*addr);
+            if update != *prev {
+                updates.push(update);
+            }
+        }
+        for update in updates.into_iter() {
+            self.nht.insert(update.addr, update.clone());
+            self.nexthop_notify(update);
+        }
+
+        // Registered prefixes are re-evaluated at the same time.
+        let mut updates = Vec::new();
+        for (prefix, prev) in self.prefix_watch.iter() {
+            let update = self.prefix_resolve(*prefix);
+            if update != *prev {
+                updates.push(update);
+            }
+        }
+        for update in updates.into_iter() {
+            self.prefix_watch.insert(update.prefix, update.clone());
+            self.prefix_notify(update);
+        }
+
+        self.watchers.update(&self.rib);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::entry::RibType;
+
+    fn entry(metric: u32) -> RibEntry {
+        let mut e = RibEntry::new(RibType::Kernel);
+        e.selected = true;
+        e.metric = metric;
+        e
+    }
+
+    #[test]
+    fn resolve() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let addr: Ipv4Addr = "10.0.1.1".parse().unwrap();
+        assert_eq!(rib_resolve(&rib, addr), None);
+
+        rib.insert("0.0.0.0/0".parse().unwrap(), vec![entry(0)]);
+        assert_eq!(
+            rib_resolve(&rib, addr),
+            Some(("0.0.0.0/0".parse().unwrap(), 0))
+        );
+
+        rib.insert("10.0.0.0/16".parse().unwrap(), vec![entry(20)]);
+        assert_eq!(
+            rib_resolve(&rib, addr),
+            Some(("10.0.0.0/16".parse().unwrap(), 20))
+        );
+
+        // Route without selected entry is skipped.
+        let mut e = entry(10);
+        e.selected = false;
+        rib.insert("10.0.1.0/24".parse().unwrap(), vec![e]);
+        assert_eq!(
+            rib_resolve(&rib, addr),
+            Some(("10.0.0.0/16".parse().unwrap(), 20))
+        );
+
+        rib.remove(&"10.0.0.0/16".parse().unwrap());
+        assert_eq!(
+            rib_resolve(&rib, addr),
+            Some(("0.0.0.0/0".parse().unwrap(), 0))
+        );
+    }
+
+    #[test]
+    fn covering() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+        assert_eq!(rib_covering(&rib, prefix), None);
+
+        // More specific route does not cover the prefix.
+        rib.insert("10.0.1.0/24".parse().unwrap(), vec![entry(0)]);
+        assert_eq!(rib_covering(&rib, prefix), None);
+
+        rib.insert("10.0.0.0/8".parse().unwrap(), vec![entry(0)]);
+        assert_eq!(rib_covering(&rib, prefix), "10.0.0.0/8".parse().ok());
+
+        rib.insert(prefix, vec![entry(0)]);
+        assert_eq!(rib_covering(&rib, prefix), Some(prefix));
+    }
+}
@@ -0,0 +1,188 @@
+use super::exit::fib_routes;
+use super::fib::batch::{fib_batch_exec, FIB_BATCH_WINDOW};
+use super::fib::{FibBatch, FibRouteExec};
+use super::instance::Rib;
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+// FIB graceful restart. Routes installed by zebra are marked with our
+// protocol id in the kernel, so that the routes left in the FIB by the
+// previous instance, e.g. retained on exit, are recognized in the dump at the
+// start. They keep forwarding during the grace period while the routes are
+// computed again. Routes installed again replace them in place, and the rest
+// of them are removed from the FIB at the end of the grace period.
+
+pub const GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct FibRestart {
+    pub started: Instant,
+    pub grace: Duration,
+    // Routes of the previous instance by the table and the prefix, None once
+    // reconciled.
+    pub stale: Option<BTreeSet<(u32, Ipv4Net)>>,
+}
+
+impl Default for FibRestart {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            grace: GRACE_PERIOD,
+            stale: Some(BTreeSet::new()),
+        }
+    }
+}
+
+impl FibRestart {
+    pub fn expired(&self, now: Instant) -> bool {
+        self.stale.is_some() && self.started + self.grace <= now
+    }
+}
+
+// Remove the stale routes not installed again. Returns the number of the
+// removed routes.
+pub async fn fib_reconcile<F: FibRouteExec>(
+    fib: &F,
+    stale: BTreeSet<(u32, Ipv4Net)>,
+    installed: &BTreeSet<(u32, Ipv4Net)>,
+) -> usize {
+    let mut batch = FibBatch::default();
+    for (table, prefix) in stale.difference(installed) {
+        // Removed by the prefix regardless of the nexthops.
+        batch.uninstall(*table, *prefix, Vec::new());
+    }
+    let count = batch.len();
+    let errors = fib_batch_exec(fib, batch, FIB_BATCH_WINDOW).await;
+    for err in errors.iter() {
+        println!("rib: {}", err);
+    }
+    count - errors.len()
+}
+
+impl Rib {
+    // Route of the previous instance found in the FIB. Ignored once
+    // reconciled, it is our own route then.
+    pub fn restart_stale_add(&mut self, table: u32, prefix: Ipv4Net) {
+        if let Some(stale) = self.restart.stale.as_mut() {
+            stale.insert((table, prefix));
+        }
+    }
+
+    pub fn restart_stale_del(&mut self, table: u32, prefix: Ipv4Net) {
+        if let Some(stale) = self.restart.stale.as_mut() {
+            stale.remove(&(table, prefix));
+        }
+    }
+
+    pub async fn restart_sweep(&mut self) {
+        if !self.restart.expired(Instant::now()) {
+            return;
+        }
+        let stale = self.restart.stale.take().unwrap_or_default();
+        let installed: BTreeSet<(u32, Ipv4Net)> = self
+            .table_ids()
+            .into_iter()
+            .filter_map(|id| self.table(id).map(|rib| fib_routes(id, rib)))
+            .flatten()
+            .map(|(table, prefix, _)| (table, prefix))
+            .collect();
+        let count = fib_reconcile(&self.fib_handle, stale, &installed).await;
+        if count > 0 {
+            println!("rib: {} stale routes are removed from the FIB", count);
+        }
+    }
+}
+
+pub fn restart_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    if path != "/routing/graceful-restart/grace-period" {
+        return None;
+    }
+    rib.restart.grace = if op == ConfigOp::Set {
+        Duration::from_secs(args.u16()?.into())
+    } else {
+        GRACE_PERIOD
+    };
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::entry::RibEntry;
+    use crate::rib::static_route::{static_rib_update, FibOp, StaticRoute};
+    use crate::rib::table::RT_TABLE_MAIN;
+    use prefix_trie::PrefixMap;
+    use std::cell::RefCell;
+
+    // FIB which records the removed routes.
+    #[derive(Default)]
+    struct MockFib {
+        deleted: RefCell<Vec<(u32, Ipv4Net)>>,
+    }
+
+    impl FibRouteExec for MockFib {
+        async fn route_exec(&self, table: u32, prefix: Ipv4Net, op: &FibOp) -> Result<(), String> {
+            assert_eq!(op, &FibOp::Uninstall(Vec::new()));
+            self.deleted.borrow_mut().push((table, prefix));
+            Ok(())
+        }
+    }
+
+    fn net(s: &str) -> Ipv4Net {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reconcile() {
+        // Routes of the previous instance in the dump.
+        let stale = BTreeSet::from([
+            (RT_TABLE_MAIN, net("10.1.0.0/16")),
+            (RT_TABLE_MAIN, net("10.2.0.0/16")),
+            (100, net("10.1.0.0/16")),
+            (100, net("10.3.0.0/16")),
+        ]);
+
+        // 10.1.0.0/16 of the main table and 10.3.0.0/16 of table 100 are
+        // installed again after the restart.
+        let mut route = StaticRoute::default();
+        route.nexthops.insert("192.168.0.1".parse().unwrap());
+        let mut main = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        static_rib_update(&mut main, net("10.1.0.0/16"), Some(&route), 1);
+        let mut table = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        static_rib_update(&mut table, net("10.3.0.0/16"), Some(&route), 1);
+        let installed: BTreeSet<(u32, Ipv4Net)> = fib_routes(RT_TABLE_MAIN, &main)
+            .into_iter()
+            .chain(fib_routes(100, &table))
+            .map(|(table, prefix, _)| (table, prefix))
+            .collect();
+
+        let fib = MockFib::default();
+        assert_eq!(fib_reconcile(&fib, stale, &installed).await, 2);
+        assert_eq!(
+            *fib.deleted.borrow(),
+            vec![
+                (100, net("10.1.0.0/16")),
+                (RT_TABLE_MAIN, net("10.2.0.0/16"))
+            ]
+        );
+    }
+
+    #[test]
+    fn grace_period() {
+        let mut restart = FibRestart::default();
+        let now = restart.started;
+        assert!(!restart.expired(now));
+        assert!(!restart.expired(now + Duration::from_secs(59)));
+        assert!(restart.expired(now + GRACE_PERIOD));
+
+        restart.grace = Duration::from_secs(300);
+        assert!(!restart.expired(now + GRACE_PERIOD));
+        assert!(restart.expired(now + Duration::from_secs(300)));
+
+        // Reconciled once.
+        restart.stale = None;
+        assert!(!restart.expired(now + Duration::from_secs(300)));
+    }
+}
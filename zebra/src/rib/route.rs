@@ -6,6 +6,7 @@ use ipnet::{IpNet, Ipv4Net};
 // Route.
 impl Rib {
     pub fn ipv4_add(&mut self, dest: Ipv4Net, e: RibEntry) {
+        self.watchers.touch(dest);
         if let Some(n) = self.rib.get_mut(&dest) {
             n.push(e);
         } else {
@@ -13,24 +14,46 @@ impl Rib {
         }
     }
 
+    // Kernel routes of the tables not in use are ignored. Our own routes are
+    // not kernel routes, those of the previous instance are kept track of
+    // until reconciled.
     pub fn route_add(&mut self, r: FibRoute) {
         if let IpNet::V4(v4) = r.route {
+            if r.owned {
+                self.restart_stale_add(r.table, v4);
+                return;
+            }
             let mut e = RibEntry::new(RibType::Kernel);
             e.distance = 0;
             e.selected = true;
             e.fib = true;
             e.gateway = r.gateway;
-            if !e.gateway.is_unspecified() {
-                self.ipv4_add(v4, e);
+            if e.gateway.is_unspecified() {
+                return;
+            }
+            if let Some(table) = self.table_import(r.table) {
+                table.entry(v4).or_default().push(e);
             }
+            self.watchers.touch(v4);
         }
     }
 
     pub fn route_del(&mut self, r: FibRoute) {
         if let IpNet::V4(v4) = r.route {
-            if let Some(_ribs) = self.rib.get(&v4) {
-                //
+            if r.owned {
+                self.restart_stale_del(r.table, v4);
+                return;
+            }
+            let Some(table) = self.table_import(r.table) else {
+                return;
+            };
+            if let Some(ribs) = table.get_mut(&v4) {
+                ribs.retain(|e| !(e.rtype == RibType::Kernel && e.gateway == r.gateway));
+                if ribs.is_empty() {
+                    table.remove(&v4);
+                }
             }
+            self.watchers.touch(v4);
         }
     }
 }
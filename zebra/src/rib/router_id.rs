@@ -0,0 +1,318 @@
+use super::api::{rib_notify, RibRx};
+use super::instance::Rib;
+use super::link::{Link, LinkType, IFF_LOOPBACK};
+use crate::config::{output, Args, ConfigOp, Render};
+use ipnet::IpNet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+use tokio::sync::mpsc::UnboundedSender;
+
+// Router ID shared by the protocols. The configured router ID takes
+// precedence. Otherwise the highest address of the loopback interfaces is
+// selected, then the highest address of the other interfaces. The selected
+// ID is sticky: it is kept while its address exists on an interface, even
+// when a higher address is added, so that the sessions are not reset by the
+// change of an unrelated address. The protocols are notified when the ID
+// changes.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RouterIdSource {
+    #[default]
+    None,
+    Config,
+    Loopback,
+    Interface,
+}
+
+impl RouterIdSource {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Config => "config",
+            Self::Loopback => "loopback",
+            Self::Interface => "interface",
+        }
+    }
+}
+
+// Candidate addresses with the source they are selected by.
+pub type RouterIdCandidates = BTreeMap<Ipv4Addr, RouterIdSource>;
+
+#[derive(Debug, Default)]
+pub struct RouterId {
+    pub config: Option<Ipv4Addr>,
+    pub id: Option<Ipv4Addr>,
+    pub source: RouterIdSource,
+}
+
+impl RouterId {
+    // Select the router ID from the candidates. Returns the new ID when it
+    // has changed.
+    pub fn update(&mut self, candidates: &RouterIdCandidates) -> Option<Ipv4Addr> {
+        let (id, source) = if let Some(config) = self.config {
+            (config, RouterIdSource::Config)
+        } else if let Some(current) = self.id.and_then(|id| candidates.get_key_value(&id)) {
+            // Current ID still exists.
+            (*current.0, *current.1)
+        } else {
+            let select = |source| {
+                candidates
+                    .iter()
+                    .rev()
+                    .find(|(_, s)| **s == source)
+                    .map(|(addr, _)| *addr)
+            };
+            if let Some(addr) = select(RouterIdSource::Loopback) {
+                (addr, RouterIdSource::Loopback)
+            } else if let Some(addr) = select(RouterIdSource::Interface) {
+                (addr, RouterIdSource::Interface)
+            } else {
+                // No address to select, the current ID is kept.
+                return None;
+            }
+        };
+        let prev = self.id.replace(id);
+        self.source = source;
+        if prev == Some(id) {
+            None
+        } else {
+            Some(id)
+        }
+    }
+}
+
+// Loopback addresses, 127.0.0.0/8, and link local addresses are not
+// candidates.
+pub fn router_id_candidates<'a>(links: impl Iterator<Item = &'a Link>) -> RouterIdCandidates {
+    let mut candidates = RouterIdCandidates::new();
+    for link in links {
+        let source = if link.link_type == LinkType::Loopback
+            || (link.flags.0 & IFF_LOOPBACK) == IFF_LOOPBACK
+        {
+            RouterIdSource::Loopback
+        } else {
+            RouterIdSource::Interface
+        };
+        for addr in link.addr4.iter() {
+            let IpNet::V4(net) = addr.addr else {
+                continue;
+            };
+            let addr = net.addr();
+            if addr.is_loopback() || addr.is_link_local() || addr.is_unspecified() {
+                continue;
+            }
+            candidates.insert(addr, source);
+        }
+    }
+    candidates
+}
+
+pub fn router_id_notify(redists: &[UnboundedSender<RibRx>], id: Ipv4Addr) {
+    rib_notify(redists, RibRx::RouterId(id));
+}
+
+impl Rib {
+    pub async fn router_id_update(&mut self) {
+        let candidates = router_id_candidates(self.links.values());
+        if let Some(id) = self.router_id.update(&candidates) {
+            println!("rib: router-id {} ({})", id, self.router_id.source.to_str());
+            router_id_notify(&self.redists, id);
+        }
+    }
+}
+
+pub async fn router_id_config(
+    rib: &mut Rib,
+    path: &str,
+    mut args: Args,
+    op: ConfigOp,
+) -> Option<()> {
+    if path != "/routing/router-id" {
+        return None;
+    }
+    rib.router_id.config = if op == ConfigOp::Set {
+        Some(args.v4addr()?)
+    } else {
+        None
+    };
+    // The ID is selected again from the interfaces when the config is
+    // removed.
+    if rib.router_id.config.is_none() {
+        rib.router_id.id = None;
+    }
+    rib.router_id_update().await;
+    Some(())
+}
+
+#[derive(Serialize, Debug)]
+struct RouterIdOut {
+    router_id: Option<Ipv4Addr>,
+    source: RouterIdSource,
+    candidates: Vec<RouterIdCandidate>,
+}
+
+#[derive(Serialize, Debug)]
+struct RouterIdCandidate {
+    address: Ipv4Addr,
+    source: RouterIdSource,
+}
+
+impl Render for RouterIdOut {
+    fn render(&self, buf: &mut String) {
+        match self.router_id {
+            Some(id) => writeln!(buf, "Router ID: {} ({})", id, self.source.to_str()).unwrap(),
+            None => writeln!(buf, "Router ID: none").unwrap(),
+        }
+        if !self.candidates.is_empty() {
+            writeln!(buf, "Candidates:").unwrap();
+        }
+        for c in self.candidates.iter() {
+            writeln!(buf, "  {:<16} {}", c.address.to_string(), c.source.to_str()).unwrap();
+        }
+    }
+}
+
+pub fn router_id_show(rib: &Rib, _args: Args, json: bool) -> String {
+    let candidates = router_id_candidates(rib.links.values())
+        .into_iter()
+        .rev()
+        .map(|(address, source)| RouterIdCandidate { address, source })
+        .collect();
+    let out = RouterIdOut {
+        router_id: rib.router_id.id,
+        source: rib.router_id.source,
+        candidates,
+    };
+    output(&out, json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::fib::message::FibLink;
+    use crate::rib::link::LinkAddr;
+    use crate::rib::DadState;
+    use tokio::sync::mpsc;
+
+    fn link(index: u32, link_type: LinkType, addrs: &[&str]) -> Link {
+        let mut fib = FibLink::new();
+        fib.index = index;
+        fib.link_type = link_type;
+        let mut link = Link::from(fib);
+        for addr in addrs.iter() {
+            link.addr4.push(LinkAddr {
+                addr: addr.parse().unwrap(),
+                link_index: index,
+                secondary: false,
+                dad: DadState::Preferred,
+            });
+        }
+        link
+    }
+
+    fn v4(s: &str) -> Ipv4Addr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        let lo = link(1, LinkType::Loopback, &["127.0.0.1/8", "10.255.0.1/32"]);
+        let eth = link(2, LinkType::Ethernet, &["192.168.0.1/24", "169.254.0.1/16"]);
+        let candidates = router_id_candidates([&lo, &eth].into_iter());
+        assert_eq!(candidates.len(), 2);
+
+        // Loopback address over the higher interface address.
+        let mut router_id = RouterId::default();
+        assert_eq!(router_id.update(&candidates), Some(v4("10.255.0.1")));
+        assert_eq!(router_id.source, RouterIdSource::Loopback);
+
+        // Interface address without the loopback.
+        let mut router_id = RouterId::default();
+        let candidates = router_id_candidates([&eth].into_iter());
+        assert_eq!(router_id.update(&candidates), Some(v4("192.168.0.1")));
+        assert_eq!(router_id.source, RouterIdSource::Interface);
+
+        // Config over the addresses.
+        router_id.config = Some(v4("1.1.1.1"));
+        assert_eq!(router_id.update(&candidates), Some(v4("1.1.1.1")));
+        assert_eq!(router_id.source, RouterIdSource::Config);
+        assert_eq!(router_id.update(&candidates), None);
+
+        // Nothing to select.
+        let mut router_id = RouterId::default();
+        assert_eq!(router_id.update(&RouterIdCandidates::new()), None);
+        assert_eq!(router_id.id, None);
+    }
+
+    #[test]
+    fn sticky() {
+        let mut router_id = RouterId::default();
+        let eth = link(2, LinkType::Ethernet, &["10.0.0.1/24"]);
+        let candidates = router_id_candidates([&eth].into_iter());
+        assert_eq!(router_id.update(&candidates), Some(v4("10.0.0.1")));
+
+        // Higher address, on a loopback too, does not replace the ID.
+        let lo = link(1, LinkType::Loopback, &["10.255.0.1/32"]);
+        let eth = link(2, LinkType::Ethernet, &["10.0.0.1/24", "10.0.1.1/24"]);
+        let candidates = router_id_candidates([&lo, &eth].into_iter());
+        assert_eq!(router_id.update(&candidates), None);
+        assert_eq!(router_id.id, Some(v4("10.0.0.1")));
+
+        // Changed once the address of the ID disappears.
+        let eth = link(2, LinkType::Ethernet, &["10.0.1.1/24"]);
+        let candidates = router_id_candidates([&lo, &eth].into_iter());
+        assert_eq!(router_id.update(&candidates), Some(v4("10.255.0.1")));
+        assert_eq!(router_id.source, RouterIdSource::Loopback);
+
+        // Kept when no address is left.
+        assert_eq!(router_id.update(&RouterIdCandidates::new()), None);
+        assert_eq!(router_id.id, Some(v4("10.255.0.1")));
+    }
+
+    #[test]
+    fn notify() {
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let (tx2, mut rx2) = mpsc::unbounded_channel();
+        router_id_notify(&[tx1, tx2], v4("10.0.0.1"));
+        for rx in [&mut rx1, &mut rx2] {
+            match rx.try_recv() {
+                Ok(RibRx::RouterId(id)) => assert_eq!(id, v4("10.0.0.1")),
+                _ => panic!("router-id is not notified"),
+            }
+            assert!(rx.try_recv().is_err());
+        }
+    }
+
+    #[test]
+    fn show() {
+        let lo = link(1, LinkType::Loopback, &["10.255.0.1/32"]);
+        let eth = link(2, LinkType::Ethernet, &["192.168.0.1/24"]);
+        let candidates = router_id_candidates([&lo, &eth].into_iter());
+        let mut router_id = RouterId::default();
+        router_id.update(&candidates);
+        let out = RouterIdOut {
+            router_id: router_id.id,
+            source: router_id.source,
+            candidates: candidates
+                .into_iter()
+                .rev()
+                .map(|(address, source)| RouterIdCandidate { address, source })
+                .collect(),
+        };
+        assert_eq!(
+            output(&out, false),
+            "Router ID: 10.255.0.1 (loopback)
+Candidates:
+  192.168.0.1      interface
+  10.255.0.1       loopback
+"
+        );
+        let json: serde_json::Value = serde_json::from_str(&output(&out, true)).unwrap();
+        assert_eq!(json["router_id"], "10.255.0.1");
+        assert_eq!(json["source"], "loopback");
+        assert_eq!(json["candidates"][0]["source"], "interface");
+    }
+}
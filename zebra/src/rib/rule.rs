@@ -0,0 +1,209 @@
+use super::fib::FibRule;
+use super::instance::Rib;
+use super::vrf::Vrf;
+use crate::config::{output, Args, ConfigOp, Render};
+use ipnet::Ipv4Net;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// Policy routing rules. A rule selects the routing table to look up by the
+// source and destination prefixes and the firewall mark of the packet. Rules
+// are keyed by the priority and evaluated by the kernel in ascending order.
+// The table is given by its id or by the name of the VRF, in which case the
+// table id of the VRF is used. A rule is installed once the table is known.
+
+#[derive(Debug, Default, Clone)]
+pub struct IpRule {
+    pub from: Option<Ipv4Net>,
+    pub to: Option<Ipv4Net>,
+    pub fwmark: Option<u32>,
+    pub table: Option<u32>,
+    pub vrf: Option<String>,
+}
+
+impl IpRule {
+    pub fn fib_rule(&self, priority: u32, vrfs: &BTreeMap<String, Vrf>) -> Option<FibRule> {
+        let table = match self.vrf.as_ref() {
+            Some(name) => vrfs.get(name)?.table_id?,
+            None => self.table?,
+        };
+        Some(FibRule {
+            priority,
+            from: self.from,
+            to: self.to,
+            fwmark: self.fwmark,
+            table,
+        })
+    }
+}
+
+pub type IpRules = BTreeMap<u32, IpRule>;
+
+impl Rib {
+    // Bring the kernel rule of the priority in sync with the config. A
+    // changed rule is deleted before the new one is added, since the kernel
+    // allows more than one rule with the same priority.
+    pub async fn rule_update(&mut self, priority: u32) {
+        let next = self
+            .rules
+            .get(&priority)
+            .and_then(|rule| rule.fib_rule(priority, &self.vrfs));
+        let prev = self.rules_fib.get(&priority);
+        if prev == next.as_ref() {
+            return;
+        }
+        if let Some(prev) = prev {
+            self.fib_handle.rule_del(prev).await;
+        }
+        match next {
+            Some(next) => {
+                self.fib_handle.rule_add(&next).await;
+                self.rules_fib.insert(priority, next);
+            }
+            None => {
+                self.rules_fib.remove(&priority);
+            }
+        }
+    }
+
+    // Table id of a VRF has been changed.
+    pub async fn rule_vrf_update(&mut self) {
+        let priorities: Vec<u32> = self
+            .rules
+            .iter()
+            .filter(|(_, rule)| rule.vrf.is_some())
+            .map(|(priority, _)| *priority)
+            .collect();
+        for priority in priorities.into_iter() {
+            self.rule_update(priority).await;
+        }
+    }
+}
+
+pub async fn rule_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let priority = args.u32()?;
+    let set = op == ConfigOp::Set;
+    if path == "/routing/rule" {
+        if set {
+            rib.rules.entry(priority).or_default();
+        } else {
+            rib.rules.remove(&priority);
+        }
+        rib.rule_update(priority).await;
+        return Some(());
+    }
+    let rule = if set {
+        rib.rules.entry(priority).or_default()
+    } else {
+        rib.rules.get_mut(&priority)?
+    };
+    match path {
+        "/routing/rule/from" => {
+            rule.from = if set { Some(args.v4net()?) } else { None };
+        }
+        "/routing/rule/to" => {
+            rule.to = if set { Some(args.v4net()?) } else { None };
+        }
+        "/routing/rule/fwmark" => {
+            rule.fwmark = if set { Some(args.u32()?) } else { None };
+        }
+        "/routing/rule/table" => {
+            rule.table = if set { Some(args.u32()?) } else { None };
+        }
+        "/routing/rule/vrf" => {
+            rule.vrf = if set { Some(args.string()?) } else { None };
+        }
+        _ => return None,
+    }
+    rib.rule_update(priority).await;
+    Some(())
+}
+
+#[derive(Serialize)]
+struct RuleOut<'a> {
+    priority: u32,
+    from: Option<Ipv4Net>,
+    to: Option<Ipv4Net>,
+    fwmark: Option<u32>,
+    // Table looked up, None while the rule is inactive.
+    table: Option<u32>,
+    vrf: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct RuleTable<'a>(Vec<RuleOut<'a>>);
+
+impl Render for RuleTable<'_> {
+    fn render(&self, buf: &mut String) {
+        for rule in self.0.iter() {
+            write!(buf, "{}:\tfrom ", rule.priority).unwrap();
+            match rule.from {
+                Some(from) => write!(buf, "{}", from).unwrap(),
+                None => buf.push_str("all"),
+            }
+            if let Some(to) = rule.to {
+                write!(buf, " to {}", to).unwrap();
+            }
+            if let Some(fwmark) = rule.fwmark {
+                write!(buf, " fwmark {:#x}", fwmark).unwrap();
+            }
+            match (rule.table, rule.vrf) {
+                (Some(table), Some(vrf)) => write!(buf, " lookup {} vrf {}", table, vrf).unwrap(),
+                (Some(table), None) => write!(buf, " lookup {}", table).unwrap(),
+                (None, Some(vrf)) => write!(buf, " vrf {} (inactive)", vrf).unwrap(),
+                (None, None) => buf.push_str(" (inactive)"),
+            }
+            buf.push('\n');
+        }
+    }
+}
+
+pub(crate) fn rule_show(rib: &Rib, _args: Args, json: bool) -> String {
+    let rules = rib
+        .rules
+        .iter()
+        .map(|(priority, rule)| RuleOut {
+            priority: *priority,
+            from: rule.from,
+            to: rule.to,
+            fwmark: rule.fwmark,
+            table: rib.rules_fib.get(priority).map(|fib| fib.table),
+            vrf: rule.vrf.as_deref(),
+        })
+        .collect();
+    output(&RuleTable(rules), json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table_resolve() {
+        let mut vrfs = BTreeMap::<String, Vrf>::new();
+        let mut rule = IpRule {
+            from: "10.1.0.0/16".parse().ok(),
+            fwmark: Some(3),
+            ..Default::default()
+        };
+        // No table.
+        assert_eq!(rule.fib_rule(100, &vrfs), None);
+
+        rule.table = Some(10);
+        let fib = rule.fib_rule(100, &vrfs).unwrap();
+        assert_eq!(fib.priority, 100);
+        assert_eq!(fib.table, 10);
+        assert_eq!(fib.from, "10.1.0.0/16".parse().ok());
+        assert_eq!(fib.fwmark, Some(3));
+
+        // VRF takes precedence over the table id, and the rule is inactive
+        // until the VRF has a table.
+        rule.vrf = Some("red".to_string());
+        assert_eq!(rule.fib_rule(100, &vrfs), None);
+        vrfs.insert("red".to_string(), Vrf::default());
+        assert_eq!(rule.fib_rule(100, &vrfs), None);
+        vrfs.get_mut("red").unwrap().table_id = Some(1001);
+        assert_eq!(rule.fib_rule(100, &vrfs).unwrap().table, 1001);
+    }
+}
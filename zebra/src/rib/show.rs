@@ -1,11 +1,20 @@
-use crate::config::Args;
+use crate::config::{output, Args, Render};
 
 use super::{
-    entry::{RibSubType, RibType},
+    entry::{RibEntry, RibSubType, RibType},
     instance::ShowCallback,
     link::link_show,
+    neigh::{neigh6_show, neigh_show},
+    router_id::router_id_show,
+    rule::rule_show,
+    stats::link_stats_show,
     Rib,
 };
+use crate::bgp::packet::RouteTarget;
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use serde::Serialize;
+use std::collections::BTreeSet;
 use std::fmt::Write;
 
 impl RibType {
@@ -18,6 +27,7 @@ impl RibType {
             Self::OSPF => 'O',
             Self::RIP => 'R',
             Self::ISIS => 'i',
+            Self::API => 'A',
         }
     }
 }
@@ -42,31 +52,202 @@ static SHOW_IPV4_HEADER: &str = r#"Codes: K - kernel, C - connected, S - static,
        O - OSPF, IA - OSPF inter area, N1/N2 - OSPF NSSA external type 1/2
        E1/E2 - OSPF external type 1/2
        i - IS-IS, L1/L2 - IS-IS level-1/2, ia - IS-IS inter area
+       A - API
        > - selected route, * - FIB route, S - Stale route
 
 "#;
 
-pub(crate) fn rib_show(rib: &Rib, _args: Args) -> String {
-    let mut buf = String::new();
+fn route_line(prefix: &Ipv4Net, e: &RibEntry, link_name: Option<&str>) -> String {
+    format!(
+        "{} {} {} {:?}{} {}",
+        e.rtype.string(),
+        e.rsubtype.string(),
+        e.selected(),
+        prefix,
+        e.distance(),
+        e.gateway_link(link_name),
+    )
+}
+
+// Route with label or SRv6 operation on any of the nexthops.
+fn route_is_encap(e: &RibEntry) -> bool {
+    e.nexthops.iter().any(|nhop| nhop.is_encap())
+}
 
-    buf.push_str(SHOW_IPV4_HEADER);
+#[derive(Serialize)]
+struct NexthopOut {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    interface: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discard: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    seg6: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seg6local: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    backup: bool,
+}
+
+#[derive(Serialize)]
+struct RouteOut {
+    // Line of the text rendering.
+    #[serde(skip)]
+    line: String,
+    prefix: String,
+    protocol: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtype: Option<String>,
+    selected: bool,
+    fib: bool,
+    distance: u32,
+    metric: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interface: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vrf: Option<String>,
+    nexthops: Vec<NexthopOut>,
+}
 
-    for (prefix, entry) in rib.rib.iter() {
+#[derive(Serialize)]
+struct Routes(Vec<RouteOut>);
+
+impl Render for Routes {
+    fn render(&self, buf: &mut String) {
+        buf.push_str(SHOW_IPV4_HEADER);
+        for route in self.0.iter() {
+            writeln!(buf, "{}", route.line).unwrap();
+        }
+    }
+}
+
+fn route_out(prefix: &Ipv4Net, e: &RibEntry, link_name: Option<&str>) -> RouteOut {
+    let nexthops = e
+        .nexthops
+        .iter()
+        .map(|nhop| NexthopOut {
+            address: (!nhop.ntype.is_discard()).then(|| nhop.nexthop.to_string()),
+            interface: nhop.ifname.clone(),
+            discard: nhop.ntype.is_discard().then(|| nhop.ntype.to_string()),
+            labels: nhop.labels.clone(),
+            seg6: nhop.seg6.iter().map(|sid| sid.to_string()).collect(),
+            seg6local: nhop.seg6local.as_ref().map(|action| action.to_string()),
+            backup: nhop.backup,
+        })
+        .collect();
+    RouteOut {
+        line: route_line(prefix, e, link_name),
+        prefix: prefix.to_string(),
+        protocol: e.rtype.name(),
+        subtype: (e.rsubtype != RibSubType::NotApplicable).then(|| e.rsubtype.string()),
+        selected: e.selected,
+        fib: e.fib,
+        distance: e.distance,
+        metric: e.metric,
+        interface: link_name
+            .filter(|_| e.rtype == RibType::Connected)
+            .map(|s| s.to_string()),
+        vrf: e.nexthop_vrf.clone(),
+        nexthops,
+    }
+}
+
+// Routes of the table, with the labels or SRv6 operations only.
+fn rib_table(rib: &Rib, table: &PrefixMap<Ipv4Net, Vec<RibEntry>>, labels: bool) -> Routes {
+    let mut routes = Vec::new();
+    for (prefix, entry) in table.iter() {
         for e in entry.iter() {
+            if labels && !route_is_encap(e) {
+                continue;
+            }
+            let link_name = rib.link_name(e.link_index).map(|s| s.as_str());
+            routes.push(route_out(prefix, e, link_name));
+        }
+    }
+    Routes(routes)
+}
+
+pub(crate) fn rib_show(rib: &Rib, _args: Args, json: bool) -> String {
+    output(&rib_table(rib, &rib.rib, false), json)
+}
+
+fn rib_show_labels(rib: &Rib, _args: Args, json: bool) -> String {
+    output(&rib_table(rib, &rib.rib, true), json)
+}
+
+fn rib_show_table(rib: &Rib, mut args: Args, json: bool) -> String {
+    let Some(id) = args.u32() else {
+        return "% Table id is required\n".to_string();
+    };
+    match rib.table(id) {
+        Some(table) => output(&rib_table(rib, table, false), json),
+        None => format!("% Table {} is not in use\n", id),
+    }
+}
+
+fn route_targets(rts: &BTreeSet<RouteTarget>) -> String {
+    let rts: Vec<String> = rts.iter().map(|rt| rt.to_string()).collect();
+    rts.join(" ")
+}
+
+fn vrf_show(rib: &Rib, mut args: Args, json: bool) -> String {
+    let mut buf = String::new();
+    let Some(name) = args.string() else {
+        for (name, vrf) in rib.vrfs.iter() {
+            let interfaces: Vec<&str> = vrf.interfaces.iter().map(|s| s.as_str()).collect();
+            writeln!(buf, "VRF {}", name).unwrap();
+            writeln!(buf, "  Interfaces: {}", interfaces.join(" ")).unwrap();
+            if let Some(rd) = vrf.rd {
+                writeln!(buf, "  RD: {}", rd).unwrap();
+            }
+            if let Some(label) = vrf.label {
+                writeln!(buf, "  Label: {}", label).unwrap();
+            }
+            if let Some(table_id) = vrf.table_id {
+                writeln!(buf, "  Table: {}", table_id).unwrap();
+            }
+            writeln!(
+                buf,
+                "  Import route-targets: {}",
+                route_targets(&vrf.import)
+            )
+            .unwrap();
             writeln!(
                 buf,
-                "{} {} {} {:?}{} {}",
-                e.rtype.string(),
-                e.rsubtype.string(),
-                e.selected(),
-                prefix,
-                e.distance(),
-                e.gateway(rib),
+                "  Export route-targets: {}",
+                route_targets(&vrf.export)
             )
             .unwrap();
         }
+        return buf;
+    };
+    match rib.vrfs.get(&name) {
+        Some(vrf) => output(&rib_table(rib, &vrf.rib, false), json),
+        None => format!("% VRF {} not found\n", name),
+    }
+}
+
+// Number of routes per protocol in the main table.
+fn state_route_count(rib: &Rib) -> serde_json::Value {
+    let mut count = serde_json::Map::new();
+    let mut total = 0;
+    let mut fib = 0;
+    for (_, entries) in rib.rib.iter() {
+        for e in entries.iter() {
+            let n = count.entry(e.rtype.name()).or_insert(serde_json::json!(0));
+            *n = serde_json::json!(n.as_u64().unwrap_or(0) + 1);
+            total += 1;
+            if e.fib {
+                fib += 1;
+            }
+        }
     }
-    buf
+    count.insert("total".to_string(), serde_json::json!(total));
+    count.insert("fib".to_string(), serde_json::json!(fib));
+    serde_json::Value::Object(count)
 }
 
 impl Rib {
@@ -76,6 +257,98 @@ impl Rib {
 
     pub fn show_build(&mut self) {
         self.show_add("/show/interfaces", link_show);
+        self.show_add("/show/interface/statistics", link_stats_show);
         self.show_add("/show/ip/route", rib_show);
+        self.show_add("/show/ip/route/labels", rib_show_labels);
+        self.show_add("/show/ip/route/table", rib_show_table);
+        self.show_add("/show/ip/vrf", vrf_show);
+        self.show_add("/show/ip/rule", rule_show);
+        self.show_add("/show/ip/neighbor", neigh_show);
+        self.show_add("/show/ipv6/neighbor", neigh6_show);
+        self.show_add("/show/router-id", router_id_show);
+    }
+
+    pub fn state_build(&mut self) {
+        self.state_cb.add("/rib/route-count", state_route_count);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::nexthop::{Nexthop, Seg6Local};
+    use std::net::IpAddr;
+
+    fn bgp_route(nhop: Nexthop) -> RibEntry {
+        let mut e = RibEntry::new(RibType::BGP);
+        e.distance = 200;
+        e.selected = true;
+        e.fib = true;
+        e.gateway = IpAddr::V4(nhop.nexthop);
+        e.nexthops.push(nhop);
+        e
+    }
+
+    #[test]
+    fn label_stack() {
+        let prefix: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let mut nhop = Nexthop::new("192.168.0.2".parse().unwrap());
+        nhop.ifname = "eth0".to_string();
+        nhop.labels = vec![16001, 24005];
+        let e = bgp_route(nhop);
+        assert!(route_is_encap(&e));
+        assert_eq!(
+            route_line(&prefix, &e, None),
+            "B    *> 10.1.0.0/16 [200/0] via 192.168.0.2, eth0, labels 16001/24005"
+        );
+        let json = serde_json::to_value(route_out(&prefix, &e, None)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "prefix": "10.1.0.0/16",
+                "protocol": "bgp",
+                "selected": true,
+                "fib": true,
+                "distance": 200,
+                "metric": 0,
+                "nexthops": [{
+                    "address": "192.168.0.2",
+                    "interface": "eth0",
+                    "labels": [16001, 24005],
+                }],
+            })
+        );
+
+        let plain = bgp_route(Nexthop::new("192.168.0.2".parse().unwrap()));
+        assert!(!route_is_encap(&plain));
+    }
+
+    #[test]
+    fn srv6_encap() {
+        let prefix: Ipv4Net = "10.2.0.0/16".parse().unwrap();
+        let mut nhop = Nexthop::new("192.168.0.2".parse().unwrap());
+        nhop.seg6 = vec![
+            "fc00:0:1::1".parse().unwrap(),
+            "fc00:0:2::d4".parse().unwrap(),
+        ];
+        let e = bgp_route(nhop);
+        assert!(route_is_encap(&e));
+        assert_eq!(
+            route_line(&prefix, &e, None),
+            "B    *> 10.2.0.0/16 [200/0] via 192.168.0.2, seg6 [fc00:0:1::1 fc00:0:2::d4]"
+        );
+        let json = serde_json::to_value(route_out(&prefix, &e, None)).unwrap();
+        assert_eq!(
+            json["nexthops"][0]["seg6"],
+            serde_json::json!(["fc00:0:1::1", "fc00:0:2::d4"])
+        );
+        assert!(json["nexthops"][0].get("labels").is_none());
+
+        let mut nhop = Nexthop::new("192.168.0.2".parse().unwrap());
+        nhop.seg6local = Some(Seg6Local::EndDt4(100));
+        let e = bgp_route(nhop);
+        assert!(route_line(&prefix, &e, None).ends_with(", seg6local End.DT4 table 100"));
+        let json = serde_json::to_value(route_out(&prefix, &e, None)).unwrap();
+        assert_eq!(json["nexthops"][0]["seg6local"], "End.DT4 table 100");
     }
 }
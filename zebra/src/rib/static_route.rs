@@ -0,0 +1,307 @@
+use super::ecmp::{ecmp_active, ecmp_select};
+use super::entry::{RibEntry, RibType};
+use super::inject::rib_select;
+use super::instance::Rib;
+use super::nexthop::{Nexthop, NexthopType};
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr};
+
+// Static routes. A route either forwards to the gateway nexthops or discards
+// the packets. Discard takes precedence when both are configured. Up to the
+// maximum paths of the gateway nexthops of the selected static route are
+// installed to the FIB. The route is installed to its kernel routing table,
+// and moved between the tables when the table is changed.
+
+pub const STATIC_DISTANCE: u32 = 1;
+
+#[derive(Debug, Default, Clone)]
+pub struct StaticRoute {
+    pub nexthops: BTreeSet<Ipv4Addr>,
+    pub discard: Option<NexthopType>,
+    pub distance: Option<u8>,
+    pub metric: Option<u32>,
+    pub table: Option<u32>,
+}
+
+impl StaticRoute {
+    pub fn entry(&self, max: u8) -> Option<RibEntry> {
+        let mut nexthops: Vec<Nexthop> = match self.discard {
+            Some(ntype) => vec![Nexthop::discard(ntype)],
+            None => self
+                .nexthops
+                .iter()
+                .map(|addr| Nexthop::new(*addr))
+                .collect(),
+        };
+        ecmp_select(&mut nexthops, max);
+        let first = nexthops.first()?;
+        let mut e = RibEntry::new(RibType::Static);
+        e.distance = self.distance.map_or(STATIC_DISTANCE, u32::from);
+        e.metric = self.metric.unwrap_or(0);
+        e.gateway = IpAddr::V4(first.nexthop);
+        e.nexthops = nexthops;
+        Some(e)
+    }
+}
+
+pub type StaticRoutes = BTreeMap<Ipv4Net, StaticRoute>;
+
+// Kernel operation to move the FIB from the previously installed nexthops to
+// the new ones. Install replaces the existing kernel route, so changing the
+// nexthops or the discard type does not delete the route in between.
+#[derive(Debug, PartialEq)]
+pub enum FibOp {
+    Install(Vec<Nexthop>),
+    Uninstall(Vec<Nexthop>),
+}
+
+pub fn static_fib_op(prev: &[Nexthop], next: &[Nexthop]) -> Option<FibOp> {
+    if prev == next {
+        return None;
+    }
+    if next.is_empty() {
+        return Some(FibOp::Uninstall(prev.to_vec()));
+    }
+    Some(FibOp::Install(next.to_vec()))
+}
+
+fn static_installed(rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>, prefix: &Ipv4Net) -> Vec<Nexthop> {
+    rib.get(prefix)
+        .and_then(|entries| entries.iter().find(|e| e.rtype == RibType::Static && e.fib))
+        .map(|e| ecmp_active(&e.nexthops))
+        .unwrap_or_default()
+}
+
+// Replace the static entry of the prefix with the configured one and re-run
+// the selection. Returns the kernel operation required.
+pub fn static_rib_update(
+    rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    prefix: Ipv4Net,
+    route: Option<&StaticRoute>,
+    max: u8,
+) -> Option<FibOp> {
+    let prev = static_installed(rib, &prefix);
+    let entries = rib.entry(prefix).or_default();
+    entries.retain(|e| e.rtype != RibType::Static);
+    if let Some(e) = route.and_then(|route| route.entry(max)) {
+        entries.push(e);
+    }
+    if entries.is_empty() {
+        rib.remove(&prefix);
+    } else {
+        rib_select(entries);
+        for e in entries.iter_mut() {
+            if e.rtype == RibType::Static {
+                e.fib = e.selected;
+            }
+        }
+    }
+    let next = static_installed(rib, &prefix);
+    static_fib_op(&prev, &next)
+}
+
+impl Rib {
+    pub async fn static_update(&mut self, prefix: Ipv4Net) {
+        let max = self.maximum_paths(&RibType::Static);
+        let route = self.statics.get(&prefix).cloned();
+        let id = route.as_ref().map(|route| self.static_table(route));
+        if let Some(id) = id {
+            self.table_add(id).await;
+        }
+        // The route is removed from the other tables.
+        for table in self.table_ids() {
+            let route = route.as_ref().filter(|_| id == Some(table));
+            let op = static_rib_update(self.table_mut(table), prefix, route, max);
+            match op {
+                Some(FibOp::Install(nhops)) => {
+                    self.fib_handle.route_ipv4_add(table, prefix, &nhops).await
+                }
+                Some(FibOp::Uninstall(nhops)) => {
+                    self.fib_handle.route_ipv4_del(table, prefix, &nhops).await
+                }
+                None => {}
+            }
+        }
+        self.table_sweep();
+        self.watchers.touch(prefix);
+        self.nexthop_update().await;
+    }
+}
+
+pub async fn static_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let prefix: Ipv4Net = args.v4net()?;
+    let set = op == ConfigOp::Set;
+    if path == "/routing/static/route" {
+        if set {
+            rib.statics.entry(prefix).or_default();
+        } else {
+            rib.statics.remove(&prefix);
+        }
+        rib.static_update(prefix).await;
+        return Some(());
+    }
+    let route = if set {
+        rib.statics.entry(prefix).or_default()
+    } else {
+        rib.statics.get_mut(&prefix)?
+    };
+    match path {
+        "/routing/static/route/nexthop" => {
+            let addr = args.v4addr()?;
+            if set {
+                route.nexthops.insert(addr);
+            } else {
+                route.nexthops.remove(&addr);
+            }
+        }
+        "/routing/static/route/discard" => {
+            route.discard = if set {
+                Some(args.string()?.parse().ok()?)
+            } else {
+                None
+            };
+        }
+        "/routing/static/route/distance" => {
+            route.distance = if set { Some(args.u8()?) } else { None };
+        }
+        "/routing/static/route/metric" => {
+            route.metric = if set { Some(args.u32()?) } else { None };
+        }
+        "/routing/static/route/table" => {
+            route.table = if set { Some(args.u32()?) } else { None };
+        }
+        _ => return None,
+    }
+    rib.static_update(prefix).await;
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::resolve::rib_resolve;
+
+    fn discard(ntype: NexthopType) -> StaticRoute {
+        StaticRoute {
+            discard: Some(ntype),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn discard_type_change() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+
+        let op = static_rib_update(&mut rib, prefix, Some(&discard(NexthopType::Blackhole)), 1);
+        assert_eq!(
+            op,
+            Some(FibOp::Install(vec![Nexthop::discard(
+                NexthopType::Blackhole
+            )]))
+        );
+        let entries = rib.get(&prefix).unwrap();
+        assert!(entries[0].selected && entries[0].fib);
+
+        // Changing the type replaces the kernel route without uninstall.
+        let op = static_rib_update(&mut rib, prefix, Some(&discard(NexthopType::Reject)), 1);
+        assert_eq!(
+            op,
+            Some(FibOp::Install(vec![Nexthop::discard(NexthopType::Reject)]))
+        );
+        assert_eq!(rib.get(&prefix).unwrap().len(), 1);
+
+        // No change.
+        let op = static_rib_update(&mut rib, prefix, Some(&discard(NexthopType::Reject)), 1);
+        assert_eq!(op, None);
+
+        // Discard takes precedence over the gateway.
+        let mut route = discard(NexthopType::Reject);
+        route.nexthops.insert("192.168.0.1".parse().unwrap());
+        assert_eq!(static_rib_update(&mut rib, prefix, Some(&route), 1), None);
+
+        route.discard = None;
+        let op = static_rib_update(&mut rib, prefix, Some(&route), 1);
+        assert_eq!(
+            op,
+            Some(FibOp::Install(vec![Nexthop::new(
+                "192.168.0.1".parse().unwrap()
+            )]))
+        );
+
+        let op = static_rib_update(&mut rib, prefix, None, 1);
+        assert_eq!(
+            op,
+            Some(FibOp::Uninstall(vec![Nexthop::new(
+                "192.168.0.1".parse().unwrap()
+            )]))
+        );
+        assert!(rib.get(&prefix).is_none());
+    }
+
+    #[test]
+    fn multipath() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+        let nhop = |addr: &str| Nexthop::new(addr.parse().unwrap());
+        let mut route = StaticRoute::default();
+        for addr in ["192.168.0.3", "192.168.0.1", "192.168.0.2"] {
+            route.nexthops.insert(addr.parse().unwrap());
+        }
+
+        let op = static_rib_update(&mut rib, prefix, Some(&route), 2);
+        assert_eq!(
+            op,
+            Some(FibOp::Install(vec![
+                nhop("192.168.0.1"),
+                nhop("192.168.0.2")
+            ]))
+        );
+        let entries = rib.get(&prefix).unwrap();
+        assert_eq!(entries[0].nexthops.len(), 3);
+        assert!(entries[0].nexthops[2].backup);
+        assert_eq!(static_rib_update(&mut rib, prefix, Some(&route), 2), None);
+
+        // Installed nexthop is removed and the backup takes over.
+        route.nexthops.remove(&"192.168.0.1".parse().unwrap());
+        let op = static_rib_update(&mut rib, prefix, Some(&route), 2);
+        assert_eq!(
+            op,
+            Some(FibOp::Install(vec![
+                nhop("192.168.0.2"),
+                nhop("192.168.0.3")
+            ]))
+        );
+
+        let op = static_rib_update(&mut rib, prefix, Some(&route), 1);
+        assert_eq!(op, Some(FibOp::Install(vec![nhop("192.168.0.2")])));
+    }
+
+    #[test]
+    fn discard_selection() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+        let mut kernel = RibEntry::new(RibType::Kernel);
+        kernel.selected = true;
+        kernel.fib = true;
+        rib.insert(prefix, vec![kernel]);
+
+        // Kernel route with lower distance wins, nothing is installed.
+        let op = static_rib_update(&mut rib, prefix, Some(&discard(NexthopType::Null0)), 1);
+        assert_eq!(op, None);
+        let entries = rib.get(&prefix).unwrap();
+        assert!(entries[0].selected);
+        assert!(!entries[1].selected && !entries[1].fib);
+
+        // Blackhole is a resolution endpoint for the nexthops under it.
+        let prefix: Ipv4Net = "172.16.0.0/12".parse().unwrap();
+        static_rib_update(&mut rib, prefix, Some(&discard(NexthopType::Blackhole)), 1);
+        assert_eq!(
+            rib_resolve(&rib, "172.16.1.1".parse().unwrap()),
+            Some((prefix, 0))
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use super::fib::message::FibMessage;
+use super::fib::LinkPoller;
+use super::instance::Rib;
+use super::link::Link;
+use crate::config::{output, Args, ConfigOp, Render};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+// Interface statistics. The counters of the interfaces are polled by a task
+// off the event loop, from the 64-bit counters of the kernel link dump, and
+// sent over the FIB channel. Each interface keeps the samples of the last
+// five minutes, from which the 30 second and 5 minute rates are computed.
+// The rate is the sum of the differences between the consecutive samples, so
+// that a 32-bit counter which has wrapped between two polls is still counted
+// correctly. A 64-bit counter which has gone down has been reset.
+
+pub const STATS_INTERVAL: Duration = Duration::from_secs(10);
+pub const RATE_SHORT: Duration = Duration::from_secs(30);
+pub const RATE_LONG: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct LinkCounters {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+    // Taken from a 32-bit source, IFLA_STATS or /proc/net/dev.
+    #[serde(skip)]
+    pub wrap32: bool,
+}
+
+// Increase of the counter between two samples. A 32-bit counter which has
+// gone down from a value within 32 bits has wrapped, otherwise the counter
+// has been reset and counts from zero.
+pub fn counter_delta(prev: u64, cur: u64, wrap32: bool) -> u64 {
+    if cur >= prev {
+        cur - prev
+    } else if wrap32 && prev <= u32::MAX as u64 {
+        (u32::MAX as u64 - prev) + cur + 1
+    } else {
+        cur
+    }
+}
+
+impl LinkCounters {
+    fn delta(&self, prev: &LinkCounters) -> LinkCounters {
+        let wrap32 = self.wrap32 && prev.wrap32;
+        let delta = |prev, cur| counter_delta(prev, cur, wrap32);
+        LinkCounters {
+            rx_bytes: delta(prev.rx_bytes, self.rx_bytes),
+            rx_packets: delta(prev.rx_packets, self.rx_packets),
+            rx_errors: delta(prev.rx_errors, self.rx_errors),
+            rx_dropped: delta(prev.rx_dropped, self.rx_dropped),
+            tx_bytes: delta(prev.tx_bytes, self.tx_bytes),
+            tx_packets: delta(prev.tx_packets, self.tx_packets),
+            tx_errors: delta(prev.tx_errors, self.tx_errors),
+            tx_dropped: delta(prev.tx_dropped, self.tx_dropped),
+            wrap32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct LinkRate {
+    pub rx_bps: u64,
+    pub rx_pps: u64,
+    pub tx_bps: u64,
+    pub tx_pps: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkStats {
+    samples: VecDeque<(Instant, LinkCounters)>,
+}
+
+impl LinkStats {
+    pub fn push(&mut self, time: Instant, counters: LinkCounters) {
+        if self.samples.back().is_some_and(|(last, _)| time <= *last) {
+            return;
+        }
+        self.samples.push_back((time, counters));
+        // One sample at or before the start of the longest window is kept.
+        while self.samples.len() > 2 && self.samples[1].0 + RATE_LONG <= time {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn counters(&self) -> Option<&LinkCounters> {
+        self.samples.back().map(|(_, counters)| counters)
+    }
+
+    // Rate over the window. The samples since the start of the window are
+    // used, or all of them when the history is shorter than the window.
+    pub fn rate(&self, window: Duration) -> Option<LinkRate> {
+        let (now, _) = self.samples.back()?;
+        let start = self
+            .samples
+            .iter()
+            .rposition(|(time, _)| *time + window <= *now)
+            .unwrap_or(0);
+        let elapsed = now.duration_since(self.samples[start].0).as_secs_f64();
+        if elapsed == 0.0 {
+            return None;
+        }
+        let (mut bytes, mut packets) = ([0u64; 2], [0u64; 2]);
+        let samples: Vec<_> = self.samples.iter().skip(start).collect();
+        for pair in samples.windows(2) {
+            let delta = pair[1].1.delta(&pair[0].1);
+            bytes[0] += delta.rx_bytes;
+            bytes[1] += delta.tx_bytes;
+            packets[0] += delta.rx_packets;
+            packets[1] += delta.tx_packets;
+        }
+        let per_sec = |count: u64| (count as f64 / elapsed).round() as u64;
+        Some(LinkRate {
+            rx_bps: per_sec(bytes[0] * 8),
+            rx_pps: per_sec(packets[0]),
+            tx_bps: per_sec(bytes[1] * 8),
+            tx_pps: per_sec(packets[1]),
+        })
+    }
+}
+
+// Counters are dumped from the kernel by the task so that a slow dump never
+// holds the event loop.
+pub fn stats_task(
+    interval: Duration,
+    tx: UnboundedSender<FibMessage>,
+    poller: LinkPoller,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let counters = poller.counters().await;
+            if tx
+                .send(FibMessage::LinkStats(Instant::now(), counters))
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+}
+
+impl Rib {
+    // Restart the polling with the configured interval.
+    pub fn stats_start(&mut self) {
+        if let Some(task) = self.stats_task.take() {
+            task.abort();
+        }
+        let poller = self.fib_handle.link_poller();
+        self.stats_task = Some(stats_task(self.stats_interval, self.fib.tx.clone(), poller));
+    }
+
+    pub fn link_stats_update(&mut self, time: Instant, counters: Vec<(String, LinkCounters)>) {
+        for (name, counters) in counters.into_iter() {
+            if let Some(link) = self.links.values_mut().find(|link| link.name == name) {
+                link.stats.push(time, counters);
+            }
+        }
+    }
+}
+
+pub fn stats_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    if path != "/interfaces/statistics/poll-interval" {
+        return None;
+    }
+    rib.stats_interval = if op == ConfigOp::Set {
+        Duration::from_secs(args.u16()?.into())
+    } else {
+        STATS_INTERVAL
+    };
+    if rib.stats_task.is_some() {
+        rib.stats_start();
+    }
+    Some(())
+}
+
+#[derive(Serialize)]
+pub struct LinkStatsOut {
+    pub name: String,
+    pub index: u32,
+    pub counters: Option<LinkCounters>,
+    pub rate_30s: Option<LinkRate>,
+    pub rate_5m: Option<LinkRate>,
+}
+
+impl LinkStatsOut {
+    pub fn from(link: &Link) -> Self {
+        Self {
+            name: link.name.clone(),
+            index: link.index,
+            counters: link.stats.counters().cloned(),
+            rate_30s: link.stats.rate(RATE_SHORT),
+            rate_5m: link.stats.rate(RATE_LONG),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct LinkStatsTable(pub Vec<LinkStatsOut>);
+
+impl Render for LinkStatsTable {
+    fn render(&self, buf: &mut String) {
+        writeln!(
+            buf,
+            "{:16} {:>10} {:>12} {:>12} {:>12} {:>10} {:>10}",
+            "Interface", "Input bps", "Input pps", "Output bps", "Output pps", "Errors", "Drops"
+        )
+        .unwrap();
+        for link in self.0.iter() {
+            let rate = link.rate_30s.unwrap_or_default();
+            let counters = link.counters.unwrap_or_default();
+            writeln!(
+                buf,
+                "{:16} {:>10} {:>12} {:>12} {:>12} {:>10} {:>10}",
+                link.name,
+                rate.rx_bps,
+                rate.rx_pps,
+                rate.tx_bps,
+                rate.tx_pps,
+                counters.rx_errors + counters.tx_errors,
+                counters.rx_dropped + counters.tx_dropped
+            )
+            .unwrap();
+        }
+    }
+}
+
+// Rates of the interface in "show interfaces".
+pub fn link_rate_show(link: &Link, buf: &mut String) {
+    for (label, window) in [("30 second", RATE_SHORT), ("5 minute", RATE_LONG)] {
+        let Some(rate) = link.stats.rate(window) else {
+            continue;
+        };
+        writeln!(
+            buf,
+            "  {} input rate {} bits/sec, {} packets/sec",
+            label, rate.rx_bps, rate.rx_pps
+        )
+        .unwrap();
+        writeln!(
+            buf,
+            "  {} output rate {} bits/sec, {} packets/sec",
+            label, rate.tx_bps, rate.tx_pps
+        )
+        .unwrap();
+    }
+}
+
+pub fn link_stats_show(rib: &Rib, _args: Args, json: bool) -> String {
+    let table = LinkStatsTable(rib.links.values().map(LinkStatsOut::from).collect());
+    output(&table, json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counters(rx_bytes: u64, rx_packets: u64, wrap32: bool) -> LinkCounters {
+        LinkCounters {
+            rx_bytes,
+            rx_packets,
+            tx_bytes: rx_bytes / 2,
+            tx_packets: rx_packets / 2,
+            wrap32,
+            ..Default::default()
+        }
+    }
+
+    // Samples every 10 seconds from the start, of 32-bit counters or not.
+    fn stats_from(samples: &[(u64, u64)], wrap32: bool) -> LinkStats {
+        let start = Instant::now();
+        let mut stats = LinkStats::default();
+        for (i, (bytes, packets)) in samples.iter().enumerate() {
+            let time = start + STATS_INTERVAL * i as u32;
+            stats.push(time, counters(*bytes, *packets, wrap32));
+        }
+        stats
+    }
+
+    fn stats(samples: &[(u64, u64)]) -> LinkStats {
+        stats_from(samples, false)
+    }
+
+    #[test]
+    fn delta() {
+        assert_eq!(counter_delta(100, 250, false), 150);
+        // 32-bit counter has wrapped.
+        assert_eq!(counter_delta(u32::MAX as u64 - 9, 5, true), 15);
+        // 64-bit counter within 32 bits has been reset.
+        assert_eq!(counter_delta(u32::MAX as u64 - 9, 5, false), 5);
+        assert_eq!(counter_delta(1 << 40, 5, false), 5);
+        assert_eq!(counter_delta(1 << 40, 5, true), 5);
+    }
+
+    #[test]
+    fn rate() {
+        // 1000 bytes and 10 packets per second.
+        let stats = stats(&[(0, 0), (10_000, 100), (20_000, 200), (30_000, 300)]);
+        let rate = stats.rate(RATE_SHORT).unwrap();
+        assert_eq!(rate.rx_bps, 8000);
+        assert_eq!(rate.rx_pps, 10);
+        assert_eq!(rate.tx_bps, 4000);
+        assert_eq!(rate.tx_pps, 5);
+        // Shorter history than the window.
+        assert_eq!(stats.rate(RATE_LONG), Some(rate));
+
+        let stats = stats(&[(0, 0)]);
+        assert_eq!(stats.rate(RATE_SHORT), None);
+    }
+
+    #[test]
+    fn wrap() {
+        // rx_bytes of a 32-bit counter wraps between the second and the
+        // third sample, still 1000 bytes per second.
+        let base = u32::MAX as u64 - 14_999;
+        let samples = [(base, 0), (base + 10_000, 100), (5_000, 200), (15_000, 300)];
+        let stats = stats_from(&samples, true);
+        let rate = stats.rate(RATE_SHORT).unwrap();
+        assert_eq!(rate.rx_bps, 8000);
+        assert_eq!(rate.rx_pps, 10);
+    }
+
+    #[test]
+    fn reset() {
+        // 64-bit counters are reset between the second and the third sample,
+        // e.g. by a driver reload. Only the increase since the reset is
+        // counted, not a 32-bit wrap of about 4 GB.
+        let base = u32::MAX as u64 - 14_999;
+        let samples = [(base, 0), (base + 10_000, 100), (5_000, 200), (15_000, 300)];
+        let stats = stats_from(&samples, false);
+        // (10000 + 5000 + 10000) bytes in 30 seconds.
+        assert_eq!(stats.rate(RATE_SHORT).unwrap().rx_bps, 6667);
+    }
+
+    #[test]
+    fn window() {
+        // 100 bytes per second for five minutes and 1000 for the last 30
+        // seconds.
+        let mut samples = Vec::new();
+        let mut bytes = 0;
+        for i in 0..=33 {
+            samples.push((bytes, 0));
+            bytes += if i < 30 { 1_000 } else { 10_000 };
+        }
+        let stats = stats(&samples);
+        assert_eq!(stats.rate(RATE_SHORT).unwrap().rx_bps, 8000);
+        // (27 * 1000 + 3 * 10000) bytes in 300 seconds.
+        assert_eq!(stats.rate(RATE_LONG).unwrap().rx_bps, 1520);
+        // History is bounded by the longest window.
+        assert_eq!(stats.samples.len(), 31);
+    }
+}
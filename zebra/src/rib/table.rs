@@ -0,0 +1,104 @@
+use super::entry::RibEntry;
+use super::instance::Rib;
+use super::static_route::StaticRoute;
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Kernel routing tables. Routes are installed to the main table unless the
+// static route, or the static routes as a whole, are configured with another
+// table, e.g. a management table looked up by the policy routing rules.
+// Routes of each table are selected on their own. Kernel routes are imported
+// from the main table and from the tables in use, the other tables are
+// ignored. A table is dumped from the kernel when it comes into use.
+
+pub const RT_TABLE_MAIN: u32 = 254;
+
+pub type RibTable = PrefixMap<Ipv4Net, Vec<RibEntry>>;
+
+// Tables other than the main one keyed by the table id.
+pub type Tables = BTreeMap<u32, RibTable>;
+
+impl Rib {
+    pub fn static_table(&self, route: &StaticRoute) -> u32 {
+        route.table.or(self.static_table).unwrap_or(RT_TABLE_MAIN)
+    }
+
+    pub fn table(&self, id: u32) -> Option<&RibTable> {
+        if id == RT_TABLE_MAIN {
+            Some(&self.rib)
+        } else {
+            self.tables.get(&id)
+        }
+    }
+
+    pub fn table_mut(&mut self, id: u32) -> &mut RibTable {
+        if id == RT_TABLE_MAIN {
+            &mut self.rib
+        } else {
+            self.tables.entry(id).or_default()
+        }
+    }
+
+    // Main table followed by the other tables in use.
+    pub fn table_ids(&self) -> Vec<u32> {
+        std::iter::once(RT_TABLE_MAIN)
+            .chain(self.tables.keys().cloned())
+            .collect()
+    }
+
+    // Tables referred to by the config, including the main table.
+    pub fn tables_configured(&self) -> BTreeSet<u32> {
+        let mut tables: BTreeSet<u32> = self
+            .statics
+            .values()
+            .map(|route| self.static_table(route))
+            .collect();
+        tables.insert(RT_TABLE_MAIN);
+        tables
+    }
+
+    // Import the kernel routes of the table when it comes into use.
+    pub async fn table_add(&mut self, id: u32) {
+        if id == RT_TABLE_MAIN || self.tables.contains_key(&id) {
+            return;
+        }
+        self.tables.insert(id, RibTable::new());
+        self.fib_handle
+            .route_table_dump(id, self.fib.tx.clone())
+            .await;
+    }
+
+    // Drop the tables no longer in use. Our routes have been removed from
+    // them by then.
+    pub fn table_sweep(&mut self) {
+        let configured = self.tables_configured();
+        self.tables.retain(|id, _| configured.contains(id));
+    }
+
+    // Table of the kernel route, None when the table is not in use.
+    pub fn table_import(&mut self, id: u32) -> Option<&mut RibTable> {
+        if id == RT_TABLE_MAIN {
+            Some(&mut self.rib)
+        } else {
+            self.tables.get_mut(&id)
+        }
+    }
+}
+
+pub async fn table_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    if path != "/routing/static/table" {
+        return None;
+    }
+    rib.static_table = if op == ConfigOp::Set {
+        Some(args.u32()?)
+    } else {
+        None
+    };
+    let prefixes: Vec<Ipv4Net> = rib.statics.keys().cloned().collect();
+    for prefix in prefixes.into_iter() {
+        rib.static_update(prefix).await;
+    }
+    Some(())
+}
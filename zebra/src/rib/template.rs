@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+// Interface templates. The name of the interface in the config may be a glob
+// pattern, e.g. "eth*", where '*' matches any string and '?' matches any one
+// character. The config of the pattern applies to all of the interfaces
+// whose name matches it, including the ones which appear later. The config
+// of the exact name takes precedence over the patterns, and of the patterns
+// the longest one, as the most specific, is used.
+
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last '*' and the name position it is matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                let Some((sp, sn)) = star else {
+                    return false;
+                };
+                p = sp + 1;
+                n = sn + 1;
+                star = Some((sp, sn + 1));
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Config of the interface from the config keyed by the interface name or the
+// pattern.
+pub fn template_lookup<'a, T>(config: &'a BTreeMap<String, T>, name: &str) -> Option<&'a T> {
+    if let Some(value) = config.get(name) {
+        return Some(value);
+    }
+    config
+        .iter()
+        .filter(|(pattern, _)| is_glob(pattern) && glob_match(pattern, name))
+        .max_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then(b.cmp(a)))
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("eth*", "eth0"));
+        assert!(glob_match("eth*", "eth"));
+        assert!(glob_match("eth?", "eth1"));
+        assert!(!glob_match("eth?", "eth10"));
+        assert!(glob_match("*.100", "eth0.100"));
+        assert!(!glob_match("*.100", "eth0.1001"));
+        assert!(glob_match("e*h*1", "eth0.1"));
+        assert!(!glob_match("eth*", "veth0"));
+        assert!(glob_match("eth0", "eth0"));
+        assert!(!is_glob("eth0"));
+    }
+
+    #[test]
+    fn lookup() {
+        let mut config = BTreeMap::new();
+        config.insert("eth*".to_string(), 1);
+        config.insert("eth1*".to_string(), 2);
+        config.insert("eth10".to_string(), 3);
+        config.insert("e?h2".to_string(), 4);
+
+        assert_eq!(template_lookup(&config, "eth0"), Some(&1));
+        assert_eq!(template_lookup(&config, "eth10"), Some(&3));
+        assert_eq!(template_lookup(&config, "eth11"), Some(&2));
+        assert_eq!(template_lookup(&config, "eth1"), Some(&2));
+        // Same length, the first one in the order.
+        assert_eq!(template_lookup(&config, "eth2"), Some(&4));
+        assert_eq!(template_lookup(&config, "lo"), None);
+    }
+}
@@ -0,0 +1,604 @@
+use super::api::{rib_notify, RibRx};
+use super::entry::{RibEntry, RibType};
+use super::inject::rib_select;
+use super::instance::Rib;
+use super::label::LabelPool;
+use super::nexthop::Nexthop;
+use crate::bgp::packet::{RouteDistinguisher, RouteTarget};
+use crate::config::{Args, ConfigOp};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::sync::mpsc::UnboundedSender;
+
+// VRF routing tables. Connected routes of the interfaces bound to a VRF are
+// kept in the VRF's table instead of the global one. Routes are leaked
+// between VRFs by route-target: the selected route of a VRF is imported to
+// every other VRF whose import route-targets intersect with the export
+// route-targets of the VRF. The leaked route keeps its nexthop, which is
+// resolved in the source VRF.
+//
+// VPN-IPv4 routes received by BGP are imported to the VRFs in the same way.
+// The RD is stripped and the route is installed with the VPN label pushed
+// on the nexthop, which is resolved in the global table.
+//
+// The selected routes of a VRF with the RD are exported to BGP, which
+// advertises them as VPN-IPv4 routes with the RD, the label of the VRF and
+// the export route-targets. One label is allocated per VRF. Routes imported
+// from VPN or leaked from another VRF are not exported.
+
+// Distance of imported VPN routes, same as iBGP.
+pub const VPN_DISTANCE: u32 = 200;
+
+#[derive(Debug, Default)]
+pub struct Vrf {
+    pub rd: Option<RouteDistinguisher>,
+    pub interfaces: BTreeSet<String>,
+    pub import: BTreeSet<RouteTarget>,
+    pub export: BTreeSet<RouteTarget>,
+    pub rib: PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    // Label allocated for the routes advertised from the VRF.
+    pub label: Option<u32>,
+    // Kernel routing table of the VRF, looked up by the policy routing
+    // rules.
+    pub table_id: Option<u32>,
+}
+
+impl Vrf {
+    pub fn new(labels: &mut LabelPool) -> Self {
+        Self {
+            label: labels.alloc(),
+            ..Default::default()
+        }
+    }
+
+    pub fn route_add(&mut self, prefix: Ipv4Net, e: RibEntry) {
+        let entries = self.rib.entry(prefix).or_default();
+        entries.push(e);
+        rib_select(entries);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpnRoute {
+    pub rd: RouteDistinguisher,
+    pub prefix: Ipv4Net,
+    pub label: u32,
+    pub nexthop: Ipv4Addr,
+    pub route_targets: Vec<RouteTarget>,
+    pub from: Ipv4Addr,
+    // Link bandwidth in bytes per second.
+    pub bandwidth: Option<u64>,
+}
+
+impl VpnRoute {
+    pub fn key(&self) -> VpnKey {
+        (self.rd, self.prefix, self.from)
+    }
+
+    fn entry(&self) -> RibEntry {
+        let mut e = RibEntry::new(RibType::BGP);
+        e.distance = VPN_DISTANCE;
+        e.gateway = IpAddr::V4(self.nexthop);
+        let mut nhop = Nexthop::new(self.nexthop);
+        nhop.labels.push(self.label);
+        nhop.bandwidth = self.bandwidth;
+        e.nexthops.push(nhop);
+        e
+    }
+}
+
+pub type VpnKey = (RouteDistinguisher, Ipv4Net, Ipv4Addr);
+
+// Routes exported from the VRFs by the RD and the prefix.
+pub type VpnExports = BTreeMap<(RouteDistinguisher, Ipv4Net), VpnRoute>;
+
+// Remove the entries matching `f` from the table and return them.
+pub fn table_take(
+    rib: &mut PrefixMap<Ipv4Net, Vec<RibEntry>>,
+    f: impl Fn(&RibEntry) -> bool,
+) -> Vec<(Ipv4Net, RibEntry)> {
+    let mut taken = Vec::new();
+    let mut empty = Vec::new();
+    for (prefix, entries) in rib.iter_mut() {
+        if !entries.iter().any(&f) {
+            continue;
+        }
+        let (take, keep): (Vec<RibEntry>, Vec<RibEntry>) = entries.drain(..).partition(&f);
+        *entries = keep;
+        taken.extend(take.into_iter().map(|e| (*prefix, e)));
+        if entries.is_empty() {
+            empty.push(*prefix);
+        } else {
+            rib_select(entries);
+        }
+    }
+    for prefix in empty.iter() {
+        rib.remove(prefix);
+    }
+    taken
+}
+
+// Recompute VPN routes imported to the VRFs.
+pub fn vrf_vpn_import(vrfs: &mut BTreeMap<String, Vrf>, vpn: &BTreeMap<VpnKey, VpnRoute>) {
+    for vrf in vrfs.values_mut() {
+        table_take(&mut vrf.rib, |e| e.rtype == RibType::BGP);
+        for route in vpn.values() {
+            if route.route_targets.iter().any(|rt| vrf.import.contains(rt)) {
+                vrf.route_add(route.prefix, route.entry());
+            }
+        }
+    }
+}
+
+// VPN routes exported from the VRFs. The nexthop and the peer are
+// unspecified, BGP advertises them with the local address of the session.
+pub fn vrf_vpn_export(vrfs: &BTreeMap<String, Vrf>) -> VpnExports {
+    let mut exports = VpnExports::new();
+    for vrf in vrfs.values() {
+        let (Some(rd), Some(label)) = (vrf.rd, vrf.label) else {
+            continue;
+        };
+        if vrf.export.is_empty() {
+            continue;
+        }
+        for (prefix, entries) in vrf.rib.iter() {
+            let Some(e) = entries.iter().find(|e| e.selected) else {
+                continue;
+            };
+            if e.rtype == RibType::BGP || e.nexthop_vrf.is_some() {
+                continue;
+            }
+            let route = VpnRoute {
+                rd,
+                prefix: *prefix,
+                label,
+                nexthop: Ipv4Addr::UNSPECIFIED,
+                route_targets: vrf.export.iter().copied().collect(),
+                from: Ipv4Addr::UNSPECIFIED,
+                bandwidth: None,
+            };
+            exports.insert((rd, *prefix), route);
+        }
+    }
+    exports
+}
+
+// Messages to BGP for the changes of the exported routes.
+pub fn vpn_export_diff(prev: &VpnExports, next: &VpnExports) -> Vec<RibRx> {
+    let mut msgs = Vec::new();
+    for (key, route) in prev.iter() {
+        if !next.contains_key(key) {
+            msgs.push(RibRx::VpnRouteDel(route.clone()));
+        }
+    }
+    for (key, route) in next.iter() {
+        if prev.get(key) != Some(route) {
+            msgs.push(RibRx::VpnRouteAdd(route.clone()));
+        }
+    }
+    msgs
+}
+
+// Queued without waiting for the protocols as the nexthop tracking, since the
+// changes are notified after every event of the RIB.
+pub fn vpn_export_notify(redists: &[UnboundedSender<RibRx>], msgs: Vec<RibRx>) {
+    for msg in msgs.into_iter() {
+        rib_notify(redists, msg);
+    }
+}
+
+// Recompute leaked routes of all of the VRFs. Leaked routes are not leaked
+// again.
+pub fn vrf_leak(vrfs: &mut BTreeMap<String, Vrf>) {
+    for vrf in vrfs.values_mut() {
+        table_take(&mut vrf.rib, |e| e.nexthop_vrf.is_some());
+    }
+    let mut leaks = Vec::new();
+    for (src_name, src) in vrfs.iter() {
+        for (dst_name, dst) in vrfs.iter() {
+            if src_name == dst_name || src.export.is_disjoint(&dst.import) {
+                continue;
+            }
+            for (prefix, entries) in src.rib.iter() {
+                if let Some(e) = entries.iter().find(|e| e.selected) {
+                    let mut e = e.clone();
+                    e.selected = false;
+                    e.fib = false;
+                    e.nexthop_vrf = Some(src_name.clone());
+                    leaks.push((dst_name.clone(), *prefix, e));
+                }
+            }
+        }
+    }
+    for (dst_name, prefix, e) in leaks.into_iter() {
+        if let Some(dst) = vrfs.get_mut(&dst_name) {
+            dst.route_add(prefix, e);
+        }
+    }
+}
+
+impl Rib {
+    // Create the VRF on demand with its label.
+    pub fn vrf_get(&mut self, name: &str) -> &mut Vrf {
+        let vrf = self
+            .vrfs
+            .entry(name.to_string())
+            .or_insert_with(|| Vrf::new(&mut self.labels));
+        if vrf.label.is_none() {
+            vrf.label = self.labels.alloc();
+        }
+        vrf
+    }
+
+    pub fn vrf_refresh(&mut self) {
+        vrf_vpn_import(&mut self.vrfs, &self.vpn);
+        vrf_leak(&mut self.vrfs);
+    }
+
+    // Notify BGP of the changes of the routes exported from the VRFs.
+    pub fn vpn_export_update(&mut self) {
+        let exports = vrf_vpn_export(&self.vrfs);
+        let msgs = vpn_export_diff(&self.vpn_export, &exports);
+        self.vpn_export = exports;
+        vpn_export_notify(&self.redists, msgs);
+    }
+
+    pub fn vpn_route_add(&mut self, route: VpnRoute) {
+        self.vpn.insert(route.key(), route);
+        self.vrf_refresh();
+    }
+
+    pub fn vpn_route_del(&mut self, route: VpnRoute) {
+        if self.vpn.remove(&route.key()).is_some() {
+            self.vrf_refresh();
+        }
+    }
+
+    pub fn vrf_by_link(&self, ifname: &str) -> Option<String> {
+        self.vrfs
+            .iter()
+            .find(|(_, vrf)| vrf.interfaces.contains(ifname))
+            .map(|(name, _)| name.clone())
+    }
+
+    // Move connected routes of the interface between the global table and
+    // the VRF table.
+    pub fn vrf_link_bind(&mut self, name: &str, ifname: &str, bind: bool) {
+        let Some(link) = self.link_by_name(ifname) else {
+            return;
+        };
+        let index = link.index;
+        let connected = move |e: &RibEntry| e.rtype == RibType::Connected && e.link_index == index;
+        if bind {
+            let moved = table_take(&mut self.rib, connected);
+            for (prefix, _) in moved.iter() {
+                self.watchers.touch(*prefix);
+            }
+            if let Some(vrf) = self.vrfs.get_mut(name) {
+                for (prefix, e) in moved.into_iter() {
+                    vrf.route_add(prefix, e);
+                }
+            }
+        } else if let Some(vrf) = self.vrfs.get_mut(name) {
+            let moved = table_take(&mut vrf.rib, connected);
+            for (prefix, e) in moved.into_iter() {
+                self.ipv4_add(prefix, e);
+            }
+        }
+    }
+
+    pub fn vrf_delete(&mut self, name: &str) {
+        let interfaces = self
+            .vrfs
+            .get(name)
+            .map(|vrf| vrf.interfaces.clone())
+            .unwrap_or_default();
+        for ifname in interfaces.iter() {
+            self.vrf_link_bind(name, ifname, false);
+        }
+        if let Some(label) = self.vrfs.remove(name).and_then(|vrf| vrf.label) {
+            self.labels.release(label);
+        }
+    }
+}
+
+pub fn vrf_config(rib: &mut Rib, path: &str, mut args: Args, op: ConfigOp) -> Option<()> {
+    let name = args.string()?;
+    let set = op == ConfigOp::Set;
+    match path {
+        "/vrf" => {
+            if set {
+                rib.vrf_get(&name);
+            } else {
+                rib.vrf_delete(&name);
+            }
+        }
+        "/vrf/interface" => {
+            let ifname = args.string()?;
+            if set {
+                let vrf = rib.vrf_get(&name);
+                vrf.interfaces.insert(ifname.clone());
+                rib.vrf_link_bind(&name, &ifname, true);
+            } else {
+                rib.vrf_link_bind(&name, &ifname, false);
+                if let Some(vrf) = rib.vrfs.get_mut(&name) {
+                    vrf.interfaces.remove(&ifname);
+                }
+            }
+        }
+        "/vrf/rd" => {
+            if set {
+                rib.vrf_get(&name).rd = Some(args.string()?.parse().ok()?);
+            } else if let Some(vrf) = rib.vrfs.get_mut(&name) {
+                vrf.rd = None;
+            }
+        }
+        "/vrf/table-id" => {
+            if set {
+                rib.vrf_get(&name).table_id = Some(args.u32()?);
+            } else if let Some(vrf) = rib.vrfs.get_mut(&name) {
+                vrf.table_id = None;
+            }
+        }
+        "/vrf/route-target/import" | "/vrf/route-target/export" => {
+            let rt: RouteTarget = args.string()?.parse().ok()?;
+            let vrf = rib.vrf_get(&name);
+            let rts = if path.ends_with("/import") {
+                &mut vrf.import
+            } else {
+                &mut vrf.export
+            };
+            if set {
+                rts.insert(rt);
+            } else {
+                rts.remove(&rt);
+            }
+        }
+        _ => return None,
+    }
+    rib.vrf_refresh();
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn static_route(gateway: Ipv4Addr) -> RibEntry {
+        let mut e = RibEntry::new(RibType::Static);
+        e.distance = 1;
+        e.gateway = IpAddr::V4(gateway);
+        e
+    }
+
+    #[test]
+    fn leak_by_route_target() {
+        let rt: RouteTarget = "100:1".parse().unwrap();
+        let mut vrfs = BTreeMap::<String, Vrf>::new();
+        let mut red = Vrf::default();
+        red.export.insert(rt);
+        let mut blue = Vrf::default();
+        blue.import.insert(rt);
+        let green = Vrf::default();
+
+        let prefix: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let gateway = Ipv4Addr::new(192, 168, 1, 1);
+        red.route_add(prefix, static_route(gateway));
+        vrfs.insert("red".to_string(), red);
+        vrfs.insert("blue".to_string(), blue);
+        vrfs.insert("green".to_string(), green);
+
+        vrf_leak(&mut vrfs);
+        let entries = vrfs.get("blue").unwrap().rib.get(&prefix).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].selected);
+        assert_eq!(entries[0].nexthop_vrf.as_deref(), Some("red"));
+        assert_eq!(entries[0].gateway, IpAddr::V4(gateway));
+        assert!(vrfs.get("green").unwrap().rib.get(&prefix).is_none());
+        assert_eq!(vrfs.get("red").unwrap().rib.get(&prefix).unwrap().len(), 1);
+
+        // Recomputation does not duplicate leaked routes.
+        vrf_leak(&mut vrfs);
+        assert_eq!(vrfs.get("blue").unwrap().rib.get(&prefix).unwrap().len(), 1);
+
+        // Local route is preferred over the leaked one.
+        let local = Ipv4Addr::new(192, 168, 2, 1);
+        vrfs.get_mut("blue")
+            .unwrap()
+            .route_add(prefix, static_route(local));
+        vrf_leak(&mut vrfs);
+        let entries = vrfs.get("blue").unwrap().rib.get(&prefix).unwrap();
+        assert_eq!(entries.len(), 2);
+        let selected = entries.iter().find(|e| e.selected).unwrap();
+        assert_eq!(selected.gateway, IpAddr::V4(local));
+
+        // Withdrawn when the export route-target is removed.
+        vrfs.get_mut("red").unwrap().export.clear();
+        vrf_leak(&mut vrfs);
+        let entries = vrfs.get("blue").unwrap().rib.get(&prefix).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].nexthop_vrf.is_none());
+    }
+
+    #[test]
+    fn vpn_import() {
+        let rt: RouteTarget = "100:1".parse().unwrap();
+        let mut vrfs = BTreeMap::<String, Vrf>::new();
+        let mut red = Vrf::default();
+        red.import.insert(rt);
+        vrfs.insert("red".to_string(), red);
+        vrfs.insert("blue".to_string(), Vrf::default());
+
+        let prefix: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let nexthop = Ipv4Addr::new(192, 168, 0, 2);
+        let route = VpnRoute {
+            rd: "65000:1".parse().unwrap(),
+            prefix,
+            label: 1000,
+            nexthop,
+            route_targets: vec![rt],
+            from: Ipv4Addr::new(192, 168, 0, 2),
+            bandwidth: None,
+        };
+        let mut vpn = BTreeMap::new();
+        vpn.insert(route.key(), route.clone());
+
+        vrf_vpn_import(&mut vrfs, &vpn);
+        let entries = vrfs.get("red").unwrap().rib.get(&prefix).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].selected);
+        assert_eq!(entries[0].rtype, RibType::BGP);
+        assert_eq!(entries[0].gateway, IpAddr::V4(nexthop));
+        assert_eq!(entries[0].nexthops[0].labels, vec![1000]);
+        assert!(vrfs.get("blue").unwrap().rib.get(&prefix).is_none());
+
+        // Same prefix with another RD is imported as another path.
+        let mut other = route.clone();
+        other.rd = "65000:2".parse().unwrap();
+        vpn.insert(other.key(), other);
+        vrf_vpn_import(&mut vrfs, &vpn);
+        assert_eq!(vrfs.get("red").unwrap().rib.get(&prefix).unwrap().len(), 2);
+
+        // Withdrawn when the import route-target is removed.
+        vrfs.get_mut("red").unwrap().import.clear();
+        vrf_vpn_import(&mut vrfs, &vpn);
+        assert!(vrfs.get("red").unwrap().rib.get(&prefix).is_none());
+    }
+
+    #[test]
+    fn label_per_vrf() {
+        let mut labels = LabelPool::default();
+        let red = Vrf::new(&mut labels);
+        let blue = Vrf::new(&mut labels);
+        assert!(red.label.is_some() && blue.label.is_some());
+        assert_ne!(red.label, blue.label);
+
+        // Label of the deleted VRF is reused.
+        labels.release(red.label.unwrap());
+        assert_eq!(Vrf::new(&mut labels).label, red.label);
+    }
+
+    #[test]
+    fn vpn_export() {
+        let rt: RouteTarget = "100:1".parse().unwrap();
+        let mut labels = LabelPool::default();
+        let mut vrfs = BTreeMap::<String, Vrf>::new();
+        for name in ["red", "blue", "green"] {
+            let mut vrf = Vrf::new(&mut labels);
+            vrf.export.insert(rt);
+            vrfs.insert(name.to_string(), vrf);
+        }
+        vrfs.get_mut("red").unwrap().rd = "65000:1".parse().ok();
+        vrfs.get_mut("blue").unwrap().rd = "65000:2".parse().ok();
+
+        let local: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+        let gateway = Ipv4Addr::new(192, 168, 1, 1);
+        for vrf in vrfs.values_mut() {
+            vrf.route_add(local, static_route(gateway));
+        }
+        // Imported from VPN and leaked routes are not exported again.
+        let vpn: Ipv4Net = "10.2.0.0/16".parse().unwrap();
+        let route = VpnRoute {
+            rd: "65000:9".parse().unwrap(),
+            prefix: vpn,
+            label: 1000,
+            nexthop: Ipv4Addr::new(192, 168, 0, 2),
+            route_targets: vec![rt],
+            from: Ipv4Addr::new(192, 168, 0, 2),
+            bandwidth: None,
+        };
+        let red = vrfs.get_mut("red").unwrap();
+        red.route_add(vpn, route.entry());
+        red.import.insert(rt);
+        vrf_leak(&mut vrfs);
+
+        // No RD, no export from green.
+        let exports = vrf_vpn_export(&vrfs);
+        assert_eq!(exports.len(), 2);
+        let red = &exports[&("65000:1".parse().unwrap(), local)];
+        let blue = &exports[&("65000:2".parse().unwrap(), local)];
+        assert_eq!(Some(red.label), vrfs["red"].label);
+        assert_eq!(Some(blue.label), vrfs["blue"].label);
+        assert_eq!(red.route_targets, vec![rt]);
+        assert!(red.nexthop.is_unspecified());
+
+        let msgs = vpn_export_diff(&VpnExports::new(), &exports);
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().all(|m| matches!(m, RibRx::VpnRouteAdd(_))));
+        assert!(vpn_export_diff(&exports, &exports).is_empty());
+
+        // Withdrawn with the route of the VRF.
+        let prev = exports;
+        table_take(&mut vrfs.get_mut("blue").unwrap().rib, |_| true);
+        let exports = vrf_vpn_export(&vrfs);
+        let msgs = vpn_export_diff(&prev, &exports);
+        assert!(matches!(
+            msgs.as_slice(),
+            [RibRx::VpnRouteDel(route)] if route.rd == "65000:2".parse().unwrap()
+        ));
+
+        // Changed route-targets are advertised again.
+        let prev = exports;
+        vrfs.get_mut("red")
+            .unwrap()
+            .export
+            .insert("100:2".parse().unwrap());
+        let exports = vrf_vpn_export(&vrfs);
+        let msgs = vpn_export_diff(&prev, &exports);
+        assert!(matches!(
+            msgs.as_slice(),
+            [RibRx::VpnRouteAdd(route)] if route.route_targets.len() == 2
+        ));
+    }
+
+    #[test]
+    fn vpn_export_burst() {
+        let rt: RouteTarget = "100:1".parse().unwrap();
+        let mut labels = LabelPool::default();
+        let mut vrf = Vrf::new(&mut labels);
+        vrf.export.insert(rt);
+        vrf.rd = "65000:1".parse().ok();
+        let gateway = Ipv4Addr::new(192, 168, 1, 1);
+        let count = 100;
+        for i in 0..count {
+            let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 + (i << 8)), 24).unwrap();
+            vrf.route_add(prefix, static_route(gateway));
+        }
+        let mut vrfs = BTreeMap::new();
+        vrfs.insert("red".to_string(), vrf);
+
+        // Protocol which does not receive meanwhile gets all of the changes.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let exports = vrf_vpn_export(&vrfs);
+        vpn_export_notify(&[tx], vpn_export_diff(&VpnExports::new(), &exports));
+        let mut added = 0;
+        while let Ok(RibRx::VpnRouteAdd(_)) = rx.try_recv() {
+            added += 1;
+        }
+        assert_eq!(added, count);
+    }
+
+    #[test]
+    fn take() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut connected = RibEntry::new(RibType::Connected);
+        connected.link_index = 2;
+        let prefix: Ipv4Net = "10.0.0.0/24".parse().unwrap();
+        rib.insert(
+            prefix,
+            vec![connected, static_route(Ipv4Addr::new(10, 0, 0, 254))],
+        );
+
+        let taken = table_take(&mut rib, |e| e.rtype == RibType::Connected);
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, prefix);
+        let entries = rib.get(&prefix).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].selected);
+
+        table_take(&mut rib, |_| true);
+        assert!(rib.get(&prefix).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use super::entry::{RibEntry, RibType};
+use ipnet::Ipv4Net;
+use prefix_trie::PrefixMap;
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::sync::mpsc::Sender;
+
+// Route change notification. The places which change the routes of the main
+// table mark the prefix with `touch`, and only the marked prefixes are
+// compared with `view`, the selected entries seen by the watchers, after the
+// RIB has been updated. A new watcher receives the current routes in one
+// Snapshot, then the changes. Up to WATCH_QUEUE events are queued for each
+// watcher. A watcher which falls behind is disconnected, as is the one which
+// receiver has been dropped.
+
+pub const WATCH_QUEUE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteEvent {
+    Add(Ipv4Net, RibEntry),
+    Delete(Ipv4Net, RibEntry),
+    Change(Ipv4Net, RibEntry),
+    Snapshot(Vec<(Ipv4Net, RibEntry)>),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct WatchFilter {
+    // False when only IPv6 routes are requested.
+    pub ipv4: bool,
+    // Empty matches all of the protocols.
+    pub protocols: Vec<RibType>,
+}
+
+impl WatchFilter {
+    fn matches(&self, e: &RibEntry) -> bool {
+        self.ipv4 && (self.protocols.is_empty() || self.protocols.contains(&e.rtype))
+    }
+}
+
+#[derive(Debug)]
+pub struct RouteWatch {
+    pub filter: WatchFilter,
+    pub tx: Sender<RouteEvent>,
+}
+
+impl RouteWatch {
+    // Event of the change seen through the filter. Returns false when the
+    // watcher has gone or its queue is full.
+    fn notify(&self, prefix: &Ipv4Net, prev: Option<&RibEntry>, next: Option<&RibEntry>) -> bool {
+        let prev = prev.filter(|e| self.filter.matches(e));
+        let next = next.filter(|e| self.filter.matches(e));
+        let event = match (prev, next) {
+            (None, Some(next)) => RouteEvent::Add(*prefix, next.clone()),
+            (Some(prev), None) => RouteEvent::Delete(*prefix, prev.clone()),
+            (Some(_), Some(next)) => RouteEvent::Change(*prefix, next.clone()),
+            (None, None) => return true,
+        };
+        self.tx.try_send(event).is_ok()
+    }
+}
+
+fn rib_selected(rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>, prefix: &Ipv4Net) -> Option<RibEntry> {
+    rib.get(prefix)?.iter().find(|e| e.selected).cloned()
+}
+
+#[derive(Debug, Default)]
+pub struct RouteWatchers {
+    watchers: Vec<RouteWatch>,
+    view: BTreeMap<Ipv4Net, RibEntry>,
+    // Prefixes changed since the last update.
+    changed: BTreeSet<Ipv4Net>,
+    // All of the prefixes are compared at the next update.
+    changed_all: bool,
+}
+
+impl RouteWatchers {
+    pub fn subscribe(&mut self, watch: RouteWatch, rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>) {
+        if self.watchers.is_empty() {
+            self.view = rib
+                .iter()
+                .filter_map(|(prefix, entries)| {
+                    let e = entries.iter().find(|e| e.selected)?;
+                    Some((*prefix, e.clone()))
+                })
+                .collect();
+            self.changed.clear();
+            self.changed_all = false;
+        }
+        let routes = self
+            .view
+            .iter()
+            .filter(|(_, e)| watch.filter.matches(e))
+            .map(|(prefix, e)| (*prefix, e.clone()))
+            .collect();
+        if watch.tx.try_send(RouteEvent::Snapshot(routes)).is_ok() {
+            self.watchers.push(watch);
+        }
+    }
+
+    // The selected route of the prefix may have been changed.
+    pub fn touch(&mut self, prefix: Ipv4Net) {
+        if !self.watchers.is_empty() {
+            self.changed.insert(prefix);
+        }
+    }
+
+    // Routes have been changed as a whole, e.g. by the distance config.
+    pub fn touch_all(&mut self) {
+        if !self.watchers.is_empty() {
+            self.changed_all = true;
+        }
+    }
+
+    // Notify the changes of the marked prefixes.
+    pub fn update(&mut self, rib: &PrefixMap<Ipv4Net, Vec<RibEntry>>) {
+        let mut changed = std::mem::take(&mut self.changed);
+        if std::mem::take(&mut self.changed_all) {
+            changed.extend(self.view.keys().copied());
+            changed.extend(rib.iter().map(|(prefix, _)| *prefix));
+        }
+        if self.watchers.is_empty() {
+            return;
+        }
+        for prefix in changed.iter() {
+            let next = rib_selected(rib, prefix);
+            let prev = self.view.get(prefix);
+            if prev == next.as_ref() {
+                continue;
+            }
+            self.watchers
+                .retain(|watch| watch.notify(prefix, prev, next.as_ref()));
+            match next {
+                Some(next) => self.view.insert(*prefix, next),
+                None => self.view.remove(prefix),
+            };
+        }
+        if self.watchers.is_empty() {
+            self.view.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rib::static_route::{static_rib_update, StaticRoute};
+    use tokio::sync::mpsc::{self, Receiver};
+
+    fn watch(protocols: Vec<RibType>) -> (RouteWatch, Receiver<RouteEvent>) {
+        let (tx, rx) = mpsc::channel(WATCH_QUEUE);
+        let filter = WatchFilter {
+            ipv4: true,
+            protocols,
+        };
+        (RouteWatch { filter, tx }, rx)
+    }
+
+    fn kernel() -> RibEntry {
+        let mut e = RibEntry::new(RibType::Kernel);
+        e.selected = true;
+        e
+    }
+
+    fn static_route(nexthop: &str) -> StaticRoute {
+        let mut route = StaticRoute::default();
+        route.nexthops.insert(nexthop.parse().unwrap());
+        route
+    }
+
+    #[test]
+    fn snapshot_and_delta() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut watchers = RouteWatchers::default();
+        let kernel_prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+        let prefix: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+        rib.insert(kernel_prefix, vec![kernel()]);
+
+        let (w, mut rx) = watch(Vec::new());
+        watchers.subscribe(w, &rib);
+        assert_eq!(
+            rx.try_recv(),
+            Ok(RouteEvent::Snapshot(vec![(kernel_prefix, kernel())]))
+        );
+
+        // Install a route.
+        static_rib_update(&mut rib, prefix, Some(&static_route("10.0.0.1")), 1);
+        watchers.update(&rib);
+        assert!(rx.try_recv().is_err());
+        watchers.touch(prefix);
+        watchers.update(&rib);
+        let Ok(RouteEvent::Add(p, e)) = rx.try_recv() else {
+            panic!("no add event");
+        };
+        assert_eq!(p, prefix);
+        assert_eq!(e.rtype, RibType::Static);
+        assert!(rx.try_recv().is_err());
+
+        // Nexthop change.
+        static_rib_update(&mut rib, prefix, Some(&static_route("10.0.0.2")), 1);
+        watchers.touch(prefix);
+        watchers.update(&rib);
+        let Ok(RouteEvent::Change(p, e)) = rx.try_recv() else {
+            panic!("no change event");
+        };
+        assert_eq!(p, prefix);
+        assert_eq!(
+            e.nexthops[0].nexthop,
+            "10.0.0.2".parse::<std::net::Ipv4Addr>().unwrap()
+        );
+
+        static_rib_update(&mut rib, prefix, None, 1);
+        watchers.touch_all();
+        watchers.update(&rib);
+        assert!(matches!(rx.try_recv(), Ok(RouteEvent::Delete(p, _)) if p == prefix));
+        assert!(rx.try_recv().is_err());
+
+        // Watcher has gone.
+        drop(rx);
+        static_rib_update(&mut rib, prefix, Some(&static_route("10.0.0.1")), 1);
+        watchers.touch(prefix);
+        watchers.update(&rib);
+        assert!(watchers.watchers.is_empty());
+        assert!(watchers.view.is_empty());
+    }
+
+    #[test]
+    fn protocol_filter() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut watchers = RouteWatchers::default();
+        let prefix: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+        rib.insert("10.0.0.0/8".parse().unwrap(), vec![kernel()]);
+
+        let (w, mut rx) = watch(vec![RibType::Static]);
+        watchers.subscribe(w, &rib);
+        assert_eq!(rx.try_recv(), Ok(RouteEvent::Snapshot(Vec::new())));
+
+        static_rib_update(&mut rib, prefix, Some(&static_route("10.0.0.1")), 1);
+        watchers.touch(prefix);
+        watchers.update(&rib);
+        assert!(matches!(rx.try_recv(), Ok(RouteEvent::Add(p, _)) if p == prefix));
+
+        // Kernel route with lower distance takes over, the static route is
+        // no longer seen through the filter.
+        rib.get_mut(&prefix).unwrap().insert(0, kernel());
+        static_rib_update(&mut rib, prefix, Some(&static_route("10.0.0.1")), 1);
+        watchers.touch(prefix);
+        watchers.update(&rib);
+        assert!(matches!(rx.try_recv(), Ok(RouteEvent::Delete(p, _)) if p == prefix));
+
+        // IPv6 only watcher receives nothing.
+        let (mut w, mut rx) = watch(Vec::new());
+        w.filter.ipv4 = false;
+        watchers.subscribe(w, &rib);
+        assert_eq!(rx.try_recv(), Ok(RouteEvent::Snapshot(Vec::new())));
+    }
+
+    #[test]
+    fn lagging_watcher() {
+        let mut rib = PrefixMap::<Ipv4Net, Vec<RibEntry>>::new();
+        let mut watchers = RouteWatchers::default();
+        let (tx, mut rx) = mpsc::channel(2);
+        let w = RouteWatch {
+            filter: WatchFilter {
+                ipv4: true,
+                protocols: Vec::new(),
+            },
+            tx,
+        };
+        watchers.subscribe(w, &rib);
+        assert_eq!(rx.try_recv(), Ok(RouteEvent::Snapshot(Vec::new())));
+
+        // The third event overflows the queue.
+        for i in 0..3u8 {
+            let prefix = Ipv4Net::new([10, i, 0, 0].into(), 16).unwrap();
+            rib.insert(prefix, vec![kernel()]);
+            watchers.touch(prefix);
+            watchers.update(&rib);
+        }
+        assert!(watchers.watchers.is_empty());
+        assert!(watchers.view.is_empty());
+        assert!(matches!(rx.try_recv(), Ok(RouteEvent::Add(..))));
+        assert!(matches!(rx.try_recv(), Ok(RouteEvent::Add(..))));
+        assert!(rx.try_recv().is_err());
+    }
+}